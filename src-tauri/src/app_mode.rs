@@ -0,0 +1,128 @@
+use crate::crypto::CryptoManager;
+use crate::database::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+const APP_MODE_SETTING: &str = "app_mode";
+
+pub const MODE_NORMAL: &str = "normal";
+pub const MODE_FROZEN: &str = "frozen";
+
+/// What the frontend shows as a banner when the app is not in normal operation.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AppMode {
+    pub mode: String,
+    pub reason: Option<String>,
+    pub changed_by: Option<i64>,
+    pub changed_at: Option<DateTime<Utc>>,
+}
+
+impl Default for AppMode {
+    fn default() -> Self {
+        Self {
+            mode: MODE_NORMAL.to_string(),
+            reason: None,
+            changed_by: None,
+            changed_at: None,
+        }
+    }
+}
+
+pub async fn load(db: &Database) -> Result<AppMode> {
+    Ok(db
+        .get_setting(APP_MODE_SETTING)
+        .await?
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default())
+}
+
+/// Re-applies a freeze after a restart; call right after opening the database.
+pub async fn restore(db: &mut Database) -> Result<AppMode> {
+    let mode = load(db).await?;
+    if mode.mode == MODE_FROZEN {
+        db.freeze_writes();
+    }
+    Ok(mode)
+}
+
+/// Makes the whole database read-only and stops sync, e.g. while a data
+/// incident is investigated. Only an admin may freeze, and only once an app
+/// password exists, since lifting the freeze requires it.
+pub async fn freeze(db: &mut Database, crypto: &CryptoManager, reason: &str, admin_id: i64) -> Result<AppMode> {
+    db.require_admin(admin_id).await?;
+    if reason.trim().is_empty() {
+        return Err(anyhow::anyhow!("A reason is required to freeze processing"));
+    }
+    if !crypto.has_app_password()? {
+        return Err(anyhow::anyhow!("Set an app password first; it is needed to lift the freeze"));
+    }
+    if db.is_frozen() {
+        return Err(anyhow::anyhow!("Processing is already frozen"));
+    }
+
+    let mode = AppMode {
+        mode: MODE_FROZEN.to_string(),
+        reason: Some(reason.trim().to_string()),
+        changed_by: Some(admin_id),
+        changed_at: Some(Utc::now()),
+    };
+    // Stored before freezing, so the freeze survives a restart
+    db.set_setting(APP_MODE_SETTING, &serde_json::to_string(&mode)?).await?;
+    db.freeze_writes();
+    Ok(mode)
+}
+
+pub async fn unfreeze(db: &mut Database, crypto: &CryptoManager, password: &str, user_id: i64) -> Result<AppMode> {
+    if !db.is_frozen() {
+        return Err(anyhow::anyhow!("Processing is not frozen"));
+    }
+    crypto.unlock_with_password(password)?;
+
+    let mode = AppMode {
+        changed_by: Some(user_id),
+        changed_at: Some(Utc::now()),
+        ..Default::default()
+    };
+    db.thaw_writes();
+    db.set_setting(APP_MODE_SETTING, &serde_json::to_string(&mode)?).await?;
+    Ok(mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_frozen_database_rejects_writes_across_restarts() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let crypto = Arc::new(CryptoManager::new().unwrap());
+        let mut db = Database::new(&db_path, crypto.clone()).await.unwrap();
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+
+        let mode = AppMode {
+            mode: MODE_FROZEN.to_string(),
+            reason: Some("Notebook verloren".to_string()),
+            changed_by: Some(1),
+            changed_at: Some(Utc::now()),
+        };
+        db.set_setting(APP_MODE_SETTING, &serde_json::to_string(&mode).unwrap()).await.unwrap();
+        restore(&mut db).await.unwrap();
+
+        assert!(db.is_frozen());
+        assert!(db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.is_err());
+        assert!(db.create_changeset_file(30).await.is_err());
+        assert_eq!(db.get_classes().await.unwrap().len(), 1);
+
+        // Still frozen after reopening
+        drop(db);
+        let mut db = Database::new(&db_path, crypto).await.unwrap();
+        assert_eq!(restore(&mut db).await.unwrap().reason.as_deref(), Some("Notebook verloren"));
+        assert!(db.is_frozen());
+
+        db.thaw_writes();
+        db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+    }
+}
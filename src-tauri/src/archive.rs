@@ -0,0 +1,438 @@
+use crate::crypto::CryptoManager;
+use crate::database::Database;
+use crate::export_protection::{self, ExportProtection};
+use crate::gdpr::{AccessHistory, GdprManager, StudentExport};
+use crate::pdf::{self, Font, ReportWriter};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Bump when the files of the bundle or their columns change.
+pub const ARCHIVE_FORMAT_VERSION: u32 = 4;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ArchiveFile {
+    pub name: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+}
+
+/// Lists every file of the bundle with its hash, so the archive can tell
+/// years later whether anything was changed or lost.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ArchiveManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    pub student_id: i64,
+    #[serde(default)]
+    pub student_reference: String, // Since format version 3
+    pub student_name: String,
+    pub class_name: String,
+    pub created_at: DateTime<Utc>,
+    pub observations: usize,
+    pub report_format: String,
+    pub files: Vec<ArchiveFile>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ArchiveBundle {
+    pub directory: String,
+    pub manifest: ArchiveManifest,
+    pub erasure_request: Option<crate::ErasureRequest>, // Set when the live records are to be erased, once approved
+    pub post_export: Option<crate::export_hook::HookRun>,
+}
+
+/// A CSV field quoted as RFC 4180 has it. Unlike the GDPR exports, archived
+/// text keeps its commas and line breaks; metric series use it as well.
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let mut row = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(",");
+    row.push_str("\r\n");
+    row
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+/// Reference codes of the student and their observations, see `reference_codes`.
+struct References {
+    student: String,
+    observations: BTreeMap<i64, String>,
+}
+
+impl References {
+    fn observation(&self, id: i64) -> String {
+        self.observations.get(&id).cloned().unwrap_or_else(|| id.to_string())
+    }
+}
+
+fn render_observations_csv(export: &StudentExport, users: &BTreeMap<i64, String>, references: &References) -> String {
+    let mut csv = csv_row(
+        &[
+            "observation_id",
+            "reference",
+            "created_at",
+            "updated_at",
+            "author_id",
+            "reported_by",
+            "category",
+            "subject",
+            "tags",
+            "text",
+        ]
+        .map(String::from),
+    );
+    for observation in &export.observations {
+        csv.push_str(&csv_row(&[
+            observation.id.to_string(),
+            references.observation(observation.id),
+            timestamp(observation.created_at),
+            timestamp(observation.updated_at),
+            observation.author_id.to_string(),
+            observation.reporter(users).unwrap_or_default(),
+            observation.category.clone(),
+            observation.subject.clone().unwrap_or_default(),
+            observation.tags.clone(),
+            observation.text.clone(),
+        ]));
+    }
+    csv
+}
+
+fn render_student_csv(export: &StudentExport, class_name: &str, references: &References) -> String {
+    let student = &export.student;
+    let mut csv = csv_row(
+        &[
+            "student_id",
+            "reference",
+            "first_name",
+            "last_name",
+            "class",
+            "status",
+            "created_at",
+            "date_of_birth",
+            "external_id",
+            "notes",
+        ]
+        .map(String::from),
+    );
+    csv.push_str(&csv_row(&[
+        student.id.to_string(),
+        references.student.clone(),
+        student.first_name.clone(),
+        student.last_name.clone(),
+        class_name.to_string(),
+        student.status.clone(),
+        timestamp(student.created_at),
+        student.date_of_birth.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+        student.external_id.clone().unwrap_or_default(),
+        student.notes.clone().unwrap_or_default(),
+    ]));
+    csv.push_str("\r\n");
+    csv.push_str(&csv_row(&["guardian_id", "name", "contact", "relationship", "consent_reference"].map(String::from)));
+    for guardian in &export.guardians {
+        csv.push_str(&csv_row(&[
+            guardian.id.to_string(),
+            guardian.name.clone(),
+            guardian.contact.clone().unwrap_or_default(),
+            guardian.relationship.clone(),
+            guardian.consent_reference.clone().unwrap_or_default(),
+        ]));
+    }
+    csv
+}
+
+fn render_report(
+    export: &StudentExport,
+    class_name: &str,
+    users: &BTreeMap<i64, String>,
+    references: &References,
+    access_history: Option<&AccessHistory>,
+    created_at: DateTime<Utc>,
+) -> Vec<u8> {
+    let student = &export.student;
+    let name = format!("{}, {}", student.last_name, student.first_name);
+    let mut report = ReportWriter::new();
+    report.line(50.0, 16.0, Font::Bold, "Archivbericht Schülerbeobachtung");
+    report.line(50.0, 11.0, Font::Regular, &format!("{} · {} · Klasse {}", name, references.student, class_name));
+    report.line(
+        50.0,
+        9.0,
+        Font::Regular,
+        &format!(
+            "Aufgenommen {} · Status {} · archiviert am {}",
+            student.created_at.format("%d.%m.%Y"),
+            student.status,
+            created_at.format("%d.%m.%Y %H:%M UTC")
+        ),
+    );
+
+    if !export.guardians.is_empty() {
+        report.gap();
+        report.line(50.0, 12.0, Font::Bold, "Erziehungsberechtigte");
+        for guardian in &export.guardians {
+            let contact = guardian.contact.as_deref().map(|c| format!(", {}", c)).unwrap_or_default();
+            report.line(60.0, 10.0, Font::Regular, &format!("{} ({}){}", guardian.name, guardian.relationship, contact));
+        }
+    }
+
+    report.gap();
+    report.line(50.0, 12.0, Font::Bold, &format!("Beobachtungen ({})", export.observations.len()));
+    let mut observations: Vec<_> = export.observations.iter().collect();
+    observations.sort_by_key(|o| (o.created_at, o.id));
+    for observation in observations {
+        report.gap();
+        let subject = observation.subject.as_deref().map(|s| format!(" · {}", s)).unwrap_or_default();
+        let reporter = observation.reporter(users).map(|name| format!(" · berichtet von {}", name)).unwrap_or_default();
+        report.line(
+            50.0,
+            9.0,
+            Font::Bold,
+            &format!(
+                "{} · {}{} · {}{}",
+                observation.created_at.format("%d.%m.%Y"),
+                observation.category,
+                subject,
+                references.observation(observation.id),
+                reporter
+            ),
+        );
+        for line in pdf::wrap_text(&observation.text, 90) {
+            report.line(60.0, 10.0, Font::Regular, &line);
+        }
+        if let (Some(cosigner), Some(cosigned_at)) = (observation.cosigned_by, observation.cosigned_at) {
+            let user_name = |id: i64| users.get(&id).cloned().unwrap_or_else(|| format!("Benutzer {}", id));
+            report.line(
+                60.0,
+                8.0,
+                Font::Regular,
+                &format!(
+                    "Verfasst von {}, gegengezeichnet von {} am {}",
+                    user_name(observation.author_id),
+                    user_name(cosigner),
+                    cosigned_at.format("%d.%m.%Y")
+                ),
+            );
+        }
+    }
+
+    if !export.rubric_scores.is_empty() {
+        report.gap();
+        report.line(50.0, 12.0, Font::Bold, "Bewertungsraster");
+        for score in &export.rubric_scores {
+            let line = format!(
+                "{} · {}: {} · Stufe {} ({})",
+                references.observation(score.observation_id),
+                score.rubric,
+                score.criterion,
+                score.level,
+                score.level_description
+            );
+            for line in pdf::wrap_text(&line, 90) {
+                report.line(60.0, 10.0, Font::Regular, &line);
+            }
+        }
+    }
+
+    if let Some(history) = access_history {
+        history.write_to(&mut report);
+    }
+
+    pdf::render_for_archive(&format!("Archivbericht {}", name), &report.finish())
+}
+
+fn describe_file(name: &str, data: &[u8]) -> ArchiveFile {
+    ArchiveFile {
+        name: name.to_string(),
+        sha256: format!("{:x}", Sha256::digest(data)),
+        size_bytes: data.len() as u64,
+    }
+}
+
+/// Writes the records of a student into a new directory below `directory`:
+/// a report in `report.pdf` (see `pdf::render_for_archive` for how close it
+/// gets to PDF/A), the raw records as CSV and a manifest with the hashes.
+/// Each file is encrypted with `protection` like other exports; the archive
+/// then needs the passphrase or key to read them, so plain bundles stay
+/// possible where encrypted exports are not required. The hashes are of the
+/// files as written. Attachments are not part of the bundle. With
+/// `access_history` the report ends with who accessed the records, see
+/// `GdprManager::get_access_history`.
+pub async fn export_archive_bundle(
+    db: &Database,
+    gdpr: &GdprManager,
+    crypto: &CryptoManager,
+    student_id: i64,
+    directory: &Path,
+    access_history: Option<&AccessHistory>,
+    protection: Option<&ExportProtection>,
+) -> Result<ArchiveBundle> {
+    if !directory.is_dir() {
+        return Err(anyhow::anyhow!("{} is not a directory", directory.display()));
+    }
+    let export = gdpr.export_student_data(db, student_id).await?;
+    let class_name = db
+        .get_class(export.student.class_id)
+        .await?
+        .map(|c| c.name)
+        .unwrap_or_default();
+    let users: BTreeMap<i64, String> = db.get_users().await?.into_iter().map(|u| (u.id, u.name)).collect();
+    let observation_ids: Vec<i64> = export.observations.iter().map(|o| o.id).collect();
+    let references = References {
+        student: crate::reference_codes::code_for(db, "student", student_id).await?,
+        observations: crate::reference_codes::codes_for(db, "observation", &observation_ids).await?,
+    };
+    let created_at = Utc::now();
+
+    let rendered = [
+        ("report.pdf", render_report(&export, &class_name, &users, &references, access_history, created_at)),
+        ("student.csv", render_student_csv(&export, &class_name, &references).into_bytes()),
+        ("observations.csv", render_observations_csv(&export, &users, &references).into_bytes()),
+        ("metrics.csv", crate::reports::render_metric_series_csv(&export.metrics).into_bytes()),
+    ];
+    let mut contents = Vec::with_capacity(rendered.len() + 1);
+    let mut files = Vec::with_capacity(rendered.len());
+    for (name, data) in rendered {
+        let data = export_protection::protect(db, crypto, data, protection).await?;
+        files.push(describe_file(name, &data));
+        contents.push((name, data));
+    }
+    let manifest = ArchiveManifest {
+        format_version: ARCHIVE_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        student_id,
+        student_reference: references.student.clone(),
+        student_name: format!("{} {}", export.student.first_name, export.student.last_name),
+        class_name: class_name.clone(),
+        created_at,
+        observations: export.observations.len(),
+        report_format: "PDF 1.4 with XMP metadata, standard fonts not embedded".to_string(),
+        files,
+    };
+    let manifest_data = serde_json::to_vec_pretty(&manifest)?;
+    contents.push((MANIFEST_FILE, export_protection::protect(db, crypto, manifest_data, protection).await?));
+
+    let bundle_dir = directory.join(format!(
+        "archiv_{}_{}",
+        crate::export_naming::sanitize(&references.student),
+        created_at.format("%Y-%m-%d")
+    ));
+    std::fs::create_dir(&bundle_dir)
+        .with_context(|| format!("Failed to create {}, it may exist already", bundle_dir.display()))?;
+    let written = contents.iter().try_for_each(|(name, data)| {
+        crate::export_target::write_synced(&bundle_dir.join(name).to_string_lossy(), data).map(|_| ())
+    });
+    // An incomplete bundle must not be mistaken for an archive
+    if let Err(e) = written {
+        let _ = std::fs::remove_dir_all(&bundle_dir);
+        return Err(e);
+    }
+
+    Ok(ArchiveBundle {
+        directory: bundle_dir.to_string_lossy().into_owned(),
+        manifest,
+        erasure_request: None,
+        post_export: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_archive_bundle_hashes_match_the_files() {
+        let (db, crypto, temp_dir) = crate::fixtures::test_db_with_crypto().await;
+        let class = db.create_class("10b".to_string(), "2024/25".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let observation = db
+            .create_observation(student.id, 1, "Sozial".to_string(), "Hilft, \"gern\"\nund oft".to_string(), vec![])
+            .await
+            .unwrap();
+        let rubric = db
+            .create_rubric(&crate::database::RubricInput {
+                name: "Lesen".to_string(),
+                description: None,
+                criteria: vec![crate::database::RubricCriterionInput {
+                    id: None,
+                    name: "Flüssigkeit".to_string(),
+                    levels: vec!["stockend".to_string(), "flüssig".to_string()],
+                }],
+            })
+            .await
+            .unwrap();
+        let score = crate::database::RubricScore { criterion_id: rubric.criteria[0].id, level: 2 };
+        db.set_observation_rubric_scores(observation.id, &[score]).await.unwrap();
+        let colleague = db.create_user("Frau Weber".to_string(), "teacher".to_string()).await.unwrap();
+        db.cosign_observation(observation.id, colleague.id).await.unwrap();
+
+        let target = temp_dir.path().join("archiv");
+        std::fs::create_dir(&target).unwrap();
+        let bundle = export_archive_bundle(&db, &GdprManager::new(), &crypto, student.id, &target, None, None)
+            .await
+            .unwrap();
+        assert_eq!(bundle.manifest.observations, 1);
+        assert_eq!(bundle.manifest.files.len(), 4);
+
+        let directory = Path::new(&bundle.directory);
+        for file in &bundle.manifest.files {
+            let data = std::fs::read(directory.join(&file.name)).unwrap();
+            assert_eq!(format!("{:x}", Sha256::digest(&data)), file.sha256);
+        }
+        let manifest: ArchiveManifest =
+            serde_json::from_slice(&std::fs::read(directory.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(manifest.student_name, "Max Mustermann");
+        assert_eq!(manifest.student_reference, format!("ST-{}-0001", Utc::now().format("%Y")));
+
+        let csv = std::fs::read_to_string(directory.join("observations.csv")).unwrap();
+        assert!(csv.ends_with(",Sozial,,[],\"Hilft, \"\"gern\"\"\nund oft\"\r\n"));
+        let report = std::fs::read(directory.join("report.pdf")).unwrap();
+        assert!(report.starts_with(b"%PDF-1.4"));
+        let report = String::from_utf8_lossy(&report);
+        assert!(report.contains("gegengezeichnet von Frau Weber am"));
+        assert!(report.contains("Lesen: Fl"));
+
+        // A second bundle on the same day does not overwrite the first
+        assert!(export_archive_bundle(&db, &GdprManager::new(), &crypto, student.id, &target, None, None)
+            .await
+            .is_err());
+
+        // Encrypted, every file needs the passphrase, the hashes are of what was written
+        let protected_target = temp_dir.path().join("verschluesselt");
+        std::fs::create_dir(&protected_target).unwrap();
+        let protection = ExportProtection { passphrase: Some("Archiv 2024".to_string()), recipient_key: None };
+        let bundle = export_archive_bundle(
+            &db,
+            &GdprManager::new(),
+            &crypto,
+            student.id,
+            &protected_target,
+            None,
+            Some(&protection),
+        )
+        .await
+        .unwrap();
+        let directory = Path::new(&bundle.directory);
+        for file in &bundle.manifest.files {
+            let data = std::fs::read(directory.join(&file.name)).unwrap();
+            assert!(export_protection::is_protected(&data), "{}", file.name);
+            assert_eq!(format!("{:x}", Sha256::digest(&data)), file.sha256);
+        }
+        let manifest = std::fs::read(directory.join(MANIFEST_FILE)).unwrap();
+        let manifest = export_protection::open(&crypto, &manifest, Some("Archiv 2024")).unwrap();
+        let manifest: ArchiveManifest = serde_json::from_slice(&manifest).unwrap();
+        assert_eq!(manifest.student_name, "Max Mustermann");
+    }
+}
@@ -0,0 +1,294 @@
+use crate::database::{changeset_checksum, Database};
+use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+/// Thumbnails sent instead of payloads use this edge length.
+pub const TRANSFER_THUMBNAIL_SIZE: u32 = 256;
+
+const REQUEST_FORMAT: &str = "attachment_request_v1";
+const TRANSFER_FORMAT: &str = "attachment_transfer_v1";
+
+/// How much of the attachments a changeset carries. Attachments left out are
+/// still listed, so the other device knows what it can request later.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AttachmentPolicy {
+    pub mode: String, // "exclude", "thumbnails" or "full"
+    #[serde(default = "default_max_attachment_bytes")]
+    pub max_attachment_bytes: u64,
+    #[serde(default = "default_max_total_bytes")]
+    pub max_total_bytes: u64,
+}
+
+fn default_max_attachment_bytes() -> u64 {
+    5 * 1024 * 1024
+}
+
+fn default_max_total_bytes() -> u64 {
+    20 * 1024 * 1024
+}
+
+impl Default for AttachmentPolicy {
+    fn default() -> Self {
+        Self {
+            mode: "exclude".to_string(),
+            max_attachment_bytes: default_max_attachment_bytes(),
+            max_total_bytes: default_max_total_bytes(),
+        }
+    }
+}
+
+impl AttachmentPolicy {
+    pub fn validate(&self) -> Result<()> {
+        match self.mode.as_str() {
+            "exclude" | "thumbnails" | "full" => Ok(()),
+            other => Err(anyhow::anyhow!("Unknown attachment policy '{}'", other)),
+        }
+    }
+}
+
+/// An attachment as carried between devices. Attachments are identified by
+/// observation and content hash, since attachment ids differ per device.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SyncedAttachment {
+    pub observation_id: i64,
+    pub filename: String,
+    pub content_type: String,
+    pub file_hash: String,
+    pub size_bytes: i64,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub data: Option<String>, // Base64 payload
+    #[serde(default)]
+    pub thumbnail: Option<String>, // Base64 JPEG
+    #[serde(default)]
+    pub omitted: Option<String>, // Why the payload was left out
+}
+
+impl SyncedAttachment {
+    pub fn payload(&self) -> Result<Option<Vec<u8>>> {
+        self.data
+            .as_deref()
+            .map(|data| BASE64_STANDARD.decode(data).context("Invalid attachment payload"))
+            .transpose()
+    }
+
+    pub fn thumbnail_bytes(&self) -> Result<Option<Vec<u8>>> {
+        self.thumbnail
+            .as_deref()
+            .map(|data| BASE64_STANDARD.decode(data).context("Invalid attachment thumbnail"))
+            .transpose()
+    }
+}
+
+/// Attachments of the given observations according to `policy`. Payloads
+/// over the per-file cap or beyond the total cap are listed as omitted.
+/// Observations that stay on the device are skipped, as in changesets, so
+/// a request naming one gets nothing.
+pub async fn collect(db: &Database, observation_ids: &[i64], policy: &AttachmentPolicy) -> Result<Vec<SyncedAttachment>> {
+    policy.validate()?;
+    let mut remaining = policy.max_total_bytes;
+    let mut entries = Vec::new();
+
+    for &observation_id in observation_ids {
+        if !db.get_observation(observation_id).await?.is_some_and(|o| o.leaves_device()) {
+            continue;
+        }
+        // Rows only known from another device have nothing to send
+        for attachment in db.get_attachments(observation_id).await?.into_iter().filter(|a| a.storage != "remote") {
+            let mut entry = SyncedAttachment {
+                observation_id,
+                filename: attachment.filename.clone(),
+                content_type: attachment.content_type.clone(),
+                file_hash: attachment.file_hash.clone(),
+                size_bytes: attachment.size_bytes,
+                created_at: attachment.created_at,
+                data: None,
+                thumbnail: None,
+                omitted: None,
+            };
+            let has_thumbnail = crate::media::is_image(&attachment.content_type)
+                || attachment.content_type == crate::media::STROKES_CONTENT_TYPE;
+            let size = attachment.size_bytes.max(0) as u64;
+
+            match policy.mode.as_str() {
+                "full" if size > policy.max_attachment_bytes => entry.omitted = Some("size_cap".to_string()),
+                "full" if size > remaining => entry.omitted = Some("total_cap".to_string()),
+                "full" => {
+                    remaining -= size;
+                    entry.data = Some(BASE64_STANDARD.encode(db.get_attachment_data(attachment.id).await?));
+                }
+                "thumbnails" if has_thumbnail => {
+                    let thumbnail = db.get_attachment_thumbnail(attachment.id, TRANSFER_THUMBNAIL_SIZE).await?;
+                    if thumbnail.len() as u64 > remaining {
+                        entry.omitted = Some("total_cap".to_string());
+                    } else {
+                        remaining -= thumbnail.len() as u64;
+                        entry.thumbnail = Some(BASE64_STANDARD.encode(thumbnail));
+                        entry.omitted = Some("thumbnail_only".to_string());
+                    }
+                }
+                _ => entry.omitted = Some("excluded".to_string()),
+            }
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Merges received attachments; returns how many payloads arrived.
+pub async fn merge(db: &Database, entries: &[SyncedAttachment]) -> Result<u64> {
+    let mut received = 0;
+    for entry in entries {
+        let payload = entry.payload()?;
+        let thumbnail = entry.thumbnail_bytes()?;
+        if db.merge_synced_attachment(entry, payload.as_deref(), thumbnail.as_deref()).await? && payload.is_some() {
+            received += 1;
+        }
+    }
+    Ok(received)
+}
+
+fn wrap(data: Value) -> Vec<u8> {
+    json!({ "checksum": changeset_checksum(&data), "data": data })
+        .to_string()
+        .into_bytes()
+}
+
+fn unwrap_file(bytes: &[u8], format: &str) -> Result<Value> {
+    let parsed: Value = serde_json::from_slice(bytes).context("Invalid attachment file format")?;
+    let data = parsed.get("data").context("Missing data section in attachment file")?;
+    if parsed.get("checksum").and_then(|c| c.as_str()) != Some(changeset_checksum(data).as_str()) {
+        return Err(anyhow::anyhow!("Checksum verification failed"));
+    }
+    if data.get("format").and_then(|f| f.as_str()) != Some(format) {
+        return Err(anyhow::anyhow!("File is not an {} file", format));
+    }
+    Ok(data.clone())
+}
+
+/// Lists the attachments this device only knows by their manifest entry.
+pub async fn build_request(db: &Database, device_id: &str) -> Result<Vec<u8>> {
+    let wanted: Vec<Value> = db
+        .get_remote_attachments()
+        .await?
+        .into_iter()
+        .map(|a| json!({ "observation_id": a.observation_id, "file_hash": a.file_hash }))
+        .collect();
+
+    Ok(wrap(json!({
+        "format": REQUEST_FORMAT,
+        "device_id": device_id,
+        "timestamp": Utc::now(),
+        "attachments": wanted,
+    })))
+}
+
+/// Answers a request with the payloads this device has, within the caps of
+/// `policy`. What does not fit stays requested for the next round.
+pub async fn export_missing(
+    db: &Database,
+    device_id: &str,
+    request: &[u8],
+    policy: &AttachmentPolicy,
+) -> Result<(Vec<u8>, usize)> {
+    db.ensure_not_frozen()?;
+    let request = unwrap_file(request, REQUEST_FORMAT)?;
+    let wanted: Vec<(i64, String)> = request
+        .get("attachments")
+        .and_then(|a| a.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| Some((item.get("observation_id")?.as_i64()?, item.get("file_hash")?.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut observation_ids: Vec<i64> = wanted.iter().map(|(id, _)| *id).collect();
+    observation_ids.sort_unstable();
+    observation_ids.dedup();
+    let full = AttachmentPolicy {
+        mode: "full".to_string(),
+        ..policy.clone()
+    };
+    let entries: Vec<SyncedAttachment> = collect(db, &observation_ids, &full)
+        .await?
+        .into_iter()
+        .filter(|e| e.data.is_some() && wanted.iter().any(|(id, hash)| *id == e.observation_id && *hash == e.file_hash))
+        .collect();
+    let count = entries.len();
+
+    Ok((
+        wrap(json!({
+            "format": TRANSFER_FORMAT,
+            "device_id": device_id,
+            "timestamp": Utc::now(),
+            "attachments": entries,
+        })),
+        count,
+    ))
+}
+
+pub async fn apply_transfer(db: &Database, transfer: &[u8]) -> Result<u64> {
+    db.ensure_not_frozen()?;
+    let transfer = unwrap_file(transfer, TRANSFER_FORMAT)?;
+    let entries: Vec<SyncedAttachment> = transfer
+        .get("attachments")
+        .and_then(|a| serde_json::from_value(a.clone()).ok())
+        .context("Invalid attachments in transfer file")?;
+    merge(db, &entries).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_omitted_attachments_can_be_requested() {
+        let (notebook, _notebook_dir) = crate::fixtures::test_db().await;
+        let (computer, _computer_dir) = crate::fixtures::test_db().await;
+
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let observation = notebook.create_observation(student.id, 1, "Sozial".to_string(), "Plakat".to_string(), vec![]).await.unwrap();
+        notebook.store_attachment(observation.id, "plakat.txt", "text/plain", b"Plakat zum Thema Wald").await.unwrap();
+        notebook.store_attachment(observation.id, "gross.bin", "application/octet-stream", &[7u8; 2048]).await.unwrap();
+        let backup = json!({ "data": { "classes": [&class], "students": [&student], "observations": [&observation] }});
+        computer
+            .import_full_backup(backup.to_string().as_bytes(), &crate::operations::CancellationToken::new())
+            .await
+            .unwrap();
+
+        let policy = AttachmentPolicy {
+            mode: "full".to_string(),
+            max_attachment_bytes: 1024,
+            ..Default::default()
+        };
+        let entries = collect(&notebook, &[observation.id], &policy).await.unwrap();
+        assert_eq!(entries.iter().filter(|e| e.data.is_some()).count(), 1);
+        assert_eq!(entries.iter().find(|e| e.filename == "gross.bin").unwrap().omitted.as_deref(), Some("size_cap"));
+        assert!(AttachmentPolicy { mode: "alles".to_string(), ..Default::default() }.validate().is_err());
+
+        assert_eq!(merge(&computer, &entries).await.unwrap(), 1);
+        let remote = computer.get_remote_attachments().await.unwrap();
+        assert_eq!(remote.len(), 1);
+        assert!(computer.get_attachment_data(remote[0].id).await.is_err());
+
+        // The follow-up round transfers only what the computer lacks
+        let request = build_request(&computer, "computer").await.unwrap();
+        let (transfer, count) = export_missing(&notebook, "notebook", &request, &AttachmentPolicy::default()).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(apply_transfer(&computer, &transfer).await.unwrap(), 1);
+        assert!(computer.get_remote_attachments().await.unwrap().is_empty());
+        assert_eq!(computer.get_attachment_data(remote[0].id).await.unwrap(), vec![7u8; 2048]);
+        assert!(apply_transfer(&computer, &request).await.is_err());
+
+        // Nor does a request get what stays on the device
+        notebook.set_observation_local_only(observation.id, true).await.unwrap();
+        assert!(collect(&notebook, &[observation.id], &policy).await.unwrap().is_empty());
+        let (_, count) = export_missing(&notebook, "notebook", &request, &AttachmentPolicy::default()).await.unwrap();
+        assert_eq!(count, 0);
+    }
+}
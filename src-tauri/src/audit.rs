@@ -2,10 +2,11 @@ use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde_json::Value;
 use sqlx::{sqlite::SqlitePool, Pool, Row, Sqlite};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct AuditLogger {
     pool: Pool<Sqlite>,
+    db_path: PathBuf,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -19,6 +20,137 @@ pub struct AuditEntry {
     pub details: Option<Value>,
     pub ip_address: Option<String>,
     pub user_agent: Option<String>,
+    /// Order of writing, independent of the system clock.
+    pub sequence: i64,
+    /// Set when the timestamp is doubtful because the clock was changed.
+    pub clock_note: Option<String>,
+}
+
+/// An audit entry queued for `log_actions_batch`.
+#[derive(Debug, Clone)]
+pub struct PendingAuditEntry {
+    pub action: String,
+    pub object_type: String,
+    pub object_id: i64,
+    pub user_id: i64,
+    pub details: Option<String>,
+}
+
+/// Compact form of an entry for scans over the whole log; `details` is kept
+/// as stored, since most details are plain text rather than JSON.
+#[derive(Debug, sqlx::FromRow)]
+pub struct AuditTrace {
+    pub id: i64,
+    pub action: String,
+    pub object_type: String,
+    pub object_id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub details: Option<String>,
+}
+
+/// An entry concerning a data subject, see `get_entries_about`. Like
+/// `AuditTrace`, `details` is kept as stored.
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct SubjectEntry {
+    pub action: String,
+    pub object_type: String,
+    pub object_id: i64,
+    pub user_id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub details: Option<String>,
+    pub clock_note: Option<String>,
+}
+
+/// A file that data was exported to or imported from, as recorded in the log.
+#[derive(Debug, serde::Serialize)]
+pub struct TransferDestination {
+    pub action: String,
+    pub object_type: String,
+    pub destination: String,
+    pub uses: i64,
+    pub last_used: DateTime<Utc>,
+}
+
+/// Clock differences below this are drift, not a jump.
+const CLOCK_TOLERANCE_MINUTES: i64 = 10;
+/// A log without entries for longer than this suggests the clock was set forward.
+const CLOCK_MAX_GAP_DAYS: i64 = 400;
+/// How long a detected jump is reported by the security check.
+const CLOCK_WARNING_DAYS: i64 = 30;
+
+/// A jump of the system clock found on startup.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClockJump {
+    pub direction: String, // "backward" or "forward"
+    pub last_entry_at: DateTime<Utc>,
+    pub detected_at: DateTime<Utc>,
+    pub annotated_entries: u64,
+}
+
+/// Reporting to the supervisory authority (Art. 33 GDPR): "pending" until
+/// decided, then "notified" or "not_required" because no risk is likely.
+pub const NOTIFICATION_STATUSES: [&str; 3] = ["pending", "notified", "not_required"];
+
+/// A personal data breach documented per Art. 33 (5) GDPR. Kept in the audit
+/// database, so incidents can be recorded while processing is frozen.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Incident {
+    pub id: i64,
+    pub title: String,
+    pub description: String,
+    pub occurred_at: Option<DateTime<Utc>>,
+    pub discovered_at: DateTime<Utc>,
+    pub affected_student_ids: String, // JSON list, references into the main database
+    pub measures: String,
+    pub notification_status: String,
+    pub notified_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub recorded_by: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Input of `record_incident` and `update_incident`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct IncidentInput {
+    pub title: String,
+    pub description: String,
+    pub occurred_at: Option<DateTime<Utc>>,
+    pub discovered_at: DateTime<Utc>,
+    #[serde(default)]
+    pub affected_student_ids: Vec<i64>,
+    #[serde(default)]
+    pub measures: String,
+    pub notification_status: String,
+    pub notified_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+impl IncidentInput {
+    fn validate(&self) -> Result<String> {
+        if self.title.trim().is_empty() || self.description.trim().is_empty() {
+            return Err(anyhow::anyhow!("Incident title and description are required"));
+        }
+        if !NOTIFICATION_STATUSES.contains(&self.notification_status.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Notification status must be one of: {}",
+                NOTIFICATION_STATUSES.join(", ")
+            ));
+        }
+        if self.notification_status == "notified" && self.notified_at.is_none() {
+            return Err(anyhow::anyhow!("Date of notification is required"));
+        }
+        Ok(serde_json::to_string(&self.affected_student_ids)?)
+    }
+}
+
+/// Force and override operations are exceptional processing and have to be
+/// documented; returns the trimmed justification for the audit details.
+pub fn require_justification(justification: Option<&str>) -> Result<String> {
+    match justification.map(str::trim) {
+        Some(justification) if !justification.is_empty() => Ok(justification.to_string()),
+        _ => Err(anyhow::anyhow!("A justification is required for this operation")),
+    }
 }
 
 impl AuditLogger {
@@ -35,11 +167,27 @@ impl AuditLogger {
             .await
             .context("Failed to connect to audit database")?;
 
-        let logger = Self { pool };
+        let logger = Self {
+            pool,
+            db_path: db_path.as_ref().to_path_buf(),
+        };
         logger.migrate().await?;
         Ok(logger)
     }
 
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    pub async fn write_copy(&self, path: &Path) -> Result<()> {
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await
+            .context("Failed to copy audit database")?;
+        Ok(())
+    }
+
     async fn migrate(&self) -> Result<()> {
         sqlx::query(
             r#"
@@ -59,6 +207,41 @@ impl AuditLogger {
         .execute(&self.pool)
         .await?;
 
+        // Clock-independent order, clock annotations and transfer destinations for older logs
+        for (column, definition) in [("sequence", "INTEGER"), ("clock_note", "TEXT"), ("destination", "TEXT")] {
+            let exists = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM pragma_table_info('audit_log') WHERE name = ?",
+            )
+            .bind(column)
+            .fetch_one(&self.pool)
+            .await?;
+            if exists == 0 {
+                println!("Adding {} column to audit_log table", column);
+                sqlx::query(&format!("ALTER TABLE audit_log ADD COLUMN {} {}", column, definition))
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        sqlx::query("UPDATE audit_log SET sequence = id WHERE sequence IS NULL")
+            .execute(&self.pool)
+            .await?;
+
+        // Every entry gets the next number, however it was written
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS audit_log_sequence
+            AFTER INSERT ON audit_log
+            WHEN NEW.sequence IS NULL
+            BEGIN
+                UPDATE audit_log
+                SET sequence = (SELECT COALESCE(MAX(sequence), 0) + 1 FROM audit_log)
+                WHERE id = NEW.id;
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         // Create indexes for better query performance
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_audit_timestamp ON audit_log(timestamp)",
@@ -66,6 +249,12 @@ impl AuditLogger {
         .execute(&self.pool)
         .await?;
 
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_audit_sequence ON audit_log(sequence)",
+        )
+        .execute(&self.pool)
+        .await?;
+
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_audit_action ON audit_log(action)",
         )
@@ -78,6 +267,28 @@ impl AuditLogger {
         .execute(&self.pool)
         .await?;
 
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS incidents (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                title TEXT NOT NULL,
+                description TEXT NOT NULL,
+                occurred_at DATETIME,
+                discovered_at DATETIME NOT NULL,
+                affected_student_ids TEXT NOT NULL DEFAULT '[]',
+                measures TEXT NOT NULL DEFAULT '',
+                notification_status TEXT NOT NULL DEFAULT 'pending',
+                notified_at DATETIME,
+                notes TEXT,
+                recorded_by INTEGER NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         Ok(())
     }
 
@@ -107,6 +318,63 @@ impl AuditLogger {
         Ok(())
     }
 
+    /// An export or import with the file or directory the data went to or
+    /// came from, kept apart from the free-text details so the data-flow
+    /// report can list it, see `get_transfer_destinations`.
+    pub async fn log_transfer(
+        &self,
+        action: &str,
+        object_type: &str,
+        object_id: i64,
+        user_id: i64,
+        details: Option<&str>,
+        destination: &str,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (action, object_type, object_id, user_id, details, destination)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(action)
+        .bind(object_type)
+        .bind(object_id)
+        .bind(user_id)
+        .bind(details)
+        .bind(destination)
+        .execute(&self.pool)
+        .await
+        .context("Failed to log audit entry")?;
+
+        Ok(())
+    }
+
+    /// Writes several entries in one transaction.
+    pub async fn log_actions_batch(&self, entries: &[PendingAuditEntry]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for entry in entries {
+            sqlx::query(
+                r#"
+                INSERT INTO audit_log (action, object_type, object_id, user_id, details)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&entry.action)
+            .bind(&entry.object_type)
+            .bind(entry.object_id)
+            .bind(entry.user_id)
+            .bind(&entry.details)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to log audit entry")?;
+        }
+
+        tx.commit().await.context("Failed to log audit entries")?;
+
+        Ok(())
+    }
+
     pub async fn log_action_with_context(
         &self,
         action: &str,
@@ -148,9 +416,9 @@ impl AuditLogger {
         let entries = sqlx::query_as::<_, AuditEntry>(
             r#"
             SELECT id, action, object_type, object_id, user_id, timestamp, 
-                   details, ip_address, user_agent
+                   details, ip_address, user_agent, sequence, clock_note
             FROM audit_log 
-            ORDER BY timestamp DESC 
+            ORDER BY sequence DESC
             LIMIT ? OFFSET ?
             "#,
         )
@@ -171,10 +439,10 @@ impl AuditLogger {
         let entries = sqlx::query_as::<_, AuditEntry>(
             r#"
             SELECT id, action, object_type, object_id, user_id, timestamp, 
-                   details, ip_address, user_agent
+                   details, ip_address, user_agent, sequence, clock_note
             FROM audit_log 
             WHERE object_type = ? AND object_id = ?
-            ORDER BY timestamp DESC
+            ORDER BY sequence DESC
             "#,
         )
         .bind(object_type)
@@ -196,10 +464,10 @@ impl AuditLogger {
         let entries = sqlx::query_as::<_, AuditEntry>(
             r#"
             SELECT id, action, object_type, object_id, user_id, timestamp, 
-                   details, ip_address, user_agent
+                   details, ip_address, user_agent, sequence, clock_note
             FROM audit_log 
             WHERE user_id = ?
-            ORDER BY timestamp DESC 
+            ORDER BY sequence DESC
             LIMIT ?
             "#,
         )
@@ -222,10 +490,10 @@ impl AuditLogger {
         let entries = sqlx::query_as::<_, AuditEntry>(
             r#"
             SELECT id, action, object_type, object_id, user_id, timestamp, 
-                   details, ip_address, user_agent
+                   details, ip_address, user_agent, sequence, clock_note
             FROM audit_log 
             WHERE action = ?
-            ORDER BY timestamp DESC 
+            ORDER BY sequence DESC
             LIMIT ?
             "#,
         )
@@ -248,10 +516,10 @@ impl AuditLogger {
         let entries = sqlx::query_as::<_, AuditEntry>(
             r#"
             SELECT id, action, object_type, object_id, user_id, timestamp, 
-                   details, ip_address, user_agent
+                   details, ip_address, user_agent, sequence, clock_note
             FROM audit_log 
             WHERE timestamp >= ?
-            ORDER BY timestamp DESC 
+            ORDER BY sequence DESC
             LIMIT ?
             "#,
         )
@@ -264,6 +532,173 @@ impl AuditLogger {
         Ok(entries)
     }
 
+    /// All entries in the order they were written.
+    pub async fn get_traces(&self) -> Result<Vec<AuditTrace>> {
+        let traces = sqlx::query_as::<_, AuditTrace>(
+            "SELECT id, action, object_type, object_id, timestamp, details FROM audit_log ORDER BY sequence ASC",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to read audit log")?;
+
+        Ok(traces)
+    }
+
+    /// Entries about any of `objects` (object type and ids) written between
+    /// `from` and `to` (exclusive), in the order they were written.
+    pub async fn get_entries_about(
+        &self,
+        objects: &[(&str, Vec<i64>)],
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SubjectEntry>> {
+        let objects: Vec<&(&str, Vec<i64>)> = objects.iter().filter(|(_, ids)| !ids.is_empty()).collect();
+        if objects.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conditions = objects
+            .iter()
+            .map(|(_, ids)| format!("(object_type = ? AND object_id IN ({}))", vec!["?"; ids.len()].join(", ")))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let sql = format!(
+            r#"
+            SELECT action, object_type, object_id, user_id, timestamp, details, clock_note
+            FROM audit_log
+            WHERE ({}) AND (? IS NULL OR datetime(timestamp) >= datetime(?)) AND (? IS NULL OR datetime(timestamp) < datetime(?))
+            ORDER BY sequence ASC
+            "#,
+            conditions
+        );
+
+        let mut query = sqlx::query_as::<_, SubjectEntry>(&sql);
+        for (object_type, ids) in &objects {
+            query = query.bind(*object_type);
+            for id in ids {
+                query = query.bind(*id);
+            }
+        }
+        let entries = query
+            .bind(from)
+            .bind(from)
+            .bind(to)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch audit entries about the data subject")?;
+
+        Ok(entries)
+    }
+
+    /// Distinct files used by exports, imports and conversions, most recent first.
+    /// Entries written before destinations were recorded apart have none and
+    /// are left out; their details mix paths with counts and notes.
+    pub async fn get_transfer_destinations(&self) -> Result<Vec<TransferDestination>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT action, object_type, destination, COUNT(*) AS uses, MAX(timestamp) AS last_used
+            FROM audit_log
+            WHERE destination IS NOT NULL
+            GROUP BY action, object_type, destination
+            ORDER BY last_used DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch transfer destinations")?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(TransferDestination {
+                    action: row.try_get("action")?,
+                    object_type: row.try_get("object_type")?,
+                    destination: row.try_get("destination")?,
+                    uses: row.try_get("uses")?,
+                    last_used: row.try_get("last_used")?,
+                })
+            })
+            .collect()
+    }
+
+    // Incident log
+    pub async fn record_incident(&self, input: &IncidentInput, user_id: i64) -> Result<Incident> {
+        let affected = input.validate()?;
+
+        let incident = sqlx::query_as::<_, Incident>(
+            r#"
+            INSERT INTO incidents
+                (title, description, occurred_at, discovered_at, affected_student_ids, measures,
+                 notification_status, notified_at, notes, recorded_by)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(input.title.trim())
+        .bind(input.description.trim())
+        .bind(input.occurred_at)
+        .bind(input.discovered_at)
+        .bind(&affected)
+        .bind(input.measures.trim())
+        .bind(&input.notification_status)
+        .bind(input.notified_at)
+        .bind(&input.notes)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to record incident")?;
+
+        Ok(incident)
+    }
+
+    pub async fn update_incident(&self, incident_id: i64, input: &IncidentInput) -> Result<Incident> {
+        let affected = input.validate()?;
+
+        let incident = sqlx::query_as::<_, Incident>(
+            r#"
+            UPDATE incidents
+            SET title = ?, description = ?, occurred_at = ?, discovered_at = ?, affected_student_ids = ?,
+                measures = ?, notification_status = ?, notified_at = ?, notes = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(input.title.trim())
+        .bind(input.description.trim())
+        .bind(input.occurred_at)
+        .bind(input.discovered_at)
+        .bind(&affected)
+        .bind(input.measures.trim())
+        .bind(&input.notification_status)
+        .bind(input.notified_at)
+        .bind(&input.notes)
+        .bind(incident_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to update incident")?
+        .context("Incident not found")?;
+
+        Ok(incident)
+    }
+
+    pub async fn get_incident(&self, incident_id: i64) -> Result<Option<Incident>> {
+        let incident = sqlx::query_as::<_, Incident>("SELECT * FROM incidents WHERE id = ?")
+            .bind(incident_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch incident")?;
+
+        Ok(incident)
+    }
+
+    pub async fn get_incidents(&self) -> Result<Vec<Incident>> {
+        let incidents = sqlx::query_as::<_, Incident>("SELECT * FROM incidents ORDER BY discovered_at DESC, id DESC")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch incidents")?;
+
+        Ok(incidents)
+    }
+
     pub async fn count_entries(&self) -> Result<i64> {
         let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM audit_log")
             .fetch_one(&self.pool)
@@ -274,6 +709,10 @@ impl AuditLogger {
     }
 
     pub async fn cleanup_old_entries(&self, retention_days: i32) -> Result<i64> {
+        // A clock set forward would delete entries that are still to be kept
+        if let Some(warning) = self.clock_warning().await? {
+            return Err(anyhow::anyhow!("Audit cleanup postponed: {}", warning));
+        }
         let cutoff_date = Utc::now() - chrono::Duration::days(retention_days as i64);
 
         let result = sqlx::query(
@@ -289,12 +728,14 @@ impl AuditLogger {
 
     // Verify audit log integrity - audit logs should be immutable
     pub async fn verify_integrity(&self) -> Result<bool> {
-        // Check that timestamps are monotonic (no backdating)
+        // Check that timestamps follow the order of writing (no backdating);
+        // entries annotated after a clock jump are explained
         let count = sqlx::query_scalar::<_, i64>(
             r#"
             SELECT COUNT(*) FROM audit_log a1 
-            JOIN audit_log a2 ON a1.id < a2.id 
-            WHERE a1.timestamp > a2.timestamp
+            JOIN audit_log a2 ON a1.sequence < a2.sequence 
+            WHERE datetime(a1.timestamp) > datetime(a2.timestamp)
+              AND a1.clock_note IS NULL
             "#,
         )
         .fetch_one(&self.pool)
@@ -304,6 +745,137 @@ impl AuditLogger {
         Ok(count == 0)
     }
 
+    /// Checks the newest `limit` entries for gaps in the sequence, i.e.
+    /// deleted entries, and for timestamps going back without a clock note.
+    /// Cheap enough to run periodically, unlike `verify_integrity`. Removing
+    /// the very newest entries leaves no gap and is not detected.
+    pub async fn verify_tail(&self, limit: i64) -> Result<Vec<String>> {
+        let entries = sqlx::query_as::<_, (i64, DateTime<Utc>, Option<String>)>(
+            "SELECT sequence, timestamp, clock_note FROM audit_log ORDER BY sequence DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to read the newest audit entries")?;
+
+        let mut problems = Vec::new();
+        // Newest first, so each pair is (later, earlier)
+        for pair in entries.windows(2) {
+            let ((later, later_at, _), (earlier, earlier_at, earlier_note)) = (&pair[0], &pair[1]);
+            if later - earlier > 1 {
+                problems.push(format!("Audit entries {} to {} are missing", earlier + 1, later - 1));
+            }
+            if earlier_at > later_at && earlier_note.is_none() {
+                problems.push(format!(
+                    "Audit entry {} is dated after entry {}, which was written later",
+                    earlier, later
+                ));
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Latest timestamp not already explained by a clock jump.
+    async fn latest_trusted_timestamp(&self) -> Result<Option<DateTime<Utc>>> {
+        let latest = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            "SELECT MAX(datetime(timestamp)) FROM audit_log WHERE clock_note IS NULL",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to read latest audit timestamp")?;
+
+        Ok(latest)
+    }
+
+    /// Compares the clock with the log on startup. A clock set back leaves
+    /// entries in the future, which get annotated; a clock set far forward
+    /// shows as a long gap. Either way the jump itself is logged.
+    pub async fn check_clock(&self) -> Result<Option<ClockJump>> {
+        self.check_clock_at(Utc::now()).await
+    }
+
+    async fn check_clock_at(&self, now: DateTime<Utc>) -> Result<Option<ClockJump>> {
+        let Some(last_entry_at) = self.latest_trusted_timestamp().await? else {
+            return Ok(None);
+        };
+
+        let (direction, annotated_entries) = if last_entry_at > now + chrono::Duration::minutes(CLOCK_TOLERANCE_MINUTES) {
+            let note = format!(
+                "Written before the clock was set back to {}; order by sequence",
+                now.to_rfc3339()
+            );
+            let result = sqlx::query(
+                "UPDATE audit_log SET clock_note = ? WHERE clock_note IS NULL AND datetime(timestamp) > datetime(?)",
+            )
+            .bind(&note)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .context("Failed to annotate audit entries")?;
+            ("backward", result.rows_affected())
+        } else if last_entry_at + chrono::Duration::days(CLOCK_MAX_GAP_DAYS) < now {
+            ("forward", 0)
+        } else {
+            return Ok(None);
+        };
+
+        let details = format!(
+            "clock set {}: last entry at {}, clock at {}, {} entries annotated",
+            direction,
+            last_entry_at.to_rfc3339(),
+            now.to_rfc3339(),
+            annotated_entries
+        );
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (action, object_type, object_id, user_id, timestamp, details)
+            VALUES ('clock_jump', 'app', 0, 0, ?, ?)
+            "#,
+        )
+        .bind(now.format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(&details)
+        .execute(&self.pool)
+        .await
+        .context("Failed to log clock jump")?;
+
+        Ok(Some(ClockJump {
+            direction: direction.to_string(),
+            last_entry_at,
+            detected_at: now,
+            annotated_entries,
+        }))
+    }
+
+    /// Why the clock cannot be trusted right now, if it cannot.
+    pub async fn clock_warning(&self) -> Result<Option<String>> {
+        let now = Utc::now();
+        if let Some(latest) = self.latest_trusted_timestamp().await? {
+            if latest > now + chrono::Duration::minutes(CLOCK_TOLERANCE_MINUTES) {
+                return Ok(Some(format!(
+                    "The system clock is behind the audit log (last entry at {}); check date and time",
+                    latest.to_rfc3339()
+                )));
+            }
+        }
+
+        let jump = sqlx::query_as::<_, (DateTime<Utc>, Option<String>)>(
+            "SELECT timestamp, details FROM audit_log WHERE action = 'clock_jump' ORDER BY sequence DESC LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to read clock jumps")?;
+
+        Ok(jump
+            .filter(|(detected_at, _)| *detected_at > now - chrono::Duration::days(CLOCK_WARNING_DAYS))
+            .map(|(detected_at, details)| {
+                format!(
+                    "The system clock appears to have been changed (detected {}: {}); audit timestamps may be wrong",
+                    detected_at.to_rfc3339(),
+                    details.unwrap_or_default()
+                )
+            }))
+    }
+
     // Get statistics for GDPR compliance reporting
     pub async fn get_statistics(&self) -> Result<AuditStatistics> {
         let total_entries = self.count_entries().await?;
@@ -344,6 +916,31 @@ impl AuditLogger {
         })
     }
 
+    /// An entry dated `at` instead of now, for tests that need entries
+    /// further apart than the second precision of the timestamps.
+    #[cfg(test)]
+    pub async fn log_action_at(
+        &self,
+        action: &str,
+        object_type: &str,
+        object_id: i64,
+        user_id: i64,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO audit_log (action, object_type, object_id, user_id, timestamp) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(action)
+        .bind(object_type)
+        .bind(object_id)
+        .bind(user_id)
+        .bind(at.format("%Y-%m-%d %H:%M:%S").to_string())
+        .execute(&self.pool)
+        .await
+        .context("Failed to log audit entry")?;
+        Ok(())
+    }
+
     #[cfg(test)]
     pub async fn clear_all_entries(&self) -> Result<()> {
         sqlx::query("DELETE FROM audit_log")
@@ -380,6 +977,8 @@ impl sqlx::FromRow<'_, sqlx::sqlite::SqliteRow> for AuditEntry {
             details,
             ip_address: row.try_get("ip_address")?,
             user_agent: row.try_get("user_agent")?,
+            sequence: row.try_get("sequence")?,
+            clock_note: row.try_get("clock_note")?,
         })
     }
 }
@@ -396,6 +995,13 @@ mod tests {
         (logger, temp_dir)
     }
 
+    #[test]
+    fn test_require_justification() {
+        assert!(require_justification(None).is_err());
+        assert!(require_justification(Some("  ")).is_err());
+        assert_eq!(require_justification(Some(" Doppelt angelegt ")).unwrap(), "Doppelt angelegt");
+    }
+
     #[tokio::test]
     async fn test_create_audit_logger() {
         let (logger, _temp_dir) = create_test_audit_logger().await;
@@ -403,6 +1009,24 @@ mod tests {
         assert_eq!(count, 0);
     }
 
+    #[tokio::test]
+    async fn test_log_actions_batch() {
+        let (logger, _temp_dir) = create_test_audit_logger().await;
+
+        let entries: Vec<PendingAuditEntry> = (1..=3)
+            .map(|id| PendingAuditEntry {
+                action: "create".to_string(),
+                object_type: "observation".to_string(),
+                object_id: id,
+                user_id: 1,
+                details: None,
+            })
+            .collect();
+        logger.log_actions_batch(&entries).await.unwrap();
+
+        assert_eq!(logger.count_entries().await.unwrap(), 3);
+    }
+
     #[tokio::test]
     async fn test_log_simple_action() {
         let (logger, _temp_dir) = create_test_audit_logger().await;
@@ -615,6 +1239,60 @@ mod tests {
         assert!(!integrity_after_tampering);
     }
 
+    #[tokio::test]
+    async fn test_clock_jumps_are_detected_and_annotated() {
+        let (logger, _temp_dir) = create_test_audit_logger().await;
+        assert!(logger.check_clock().await.unwrap().is_none());
+
+        logger.log_action("create", "student", 1, 1, None).await.unwrap();
+        assert!(logger.check_clock().await.unwrap().is_none());
+        assert!(logger.clock_warning().await.unwrap().is_none());
+
+        // Written while the clock ran two days ahead
+        let ahead = (Utc::now() + chrono::Duration::days(2)).format("%Y-%m-%d %H:%M:%S").to_string();
+        sqlx::query("INSERT INTO audit_log (action, object_type, object_id, user_id, timestamp) VALUES ('update', 'student', 1, 1, ?)")
+            .bind(&ahead)
+            .execute(&logger.pool)
+            .await
+            .unwrap();
+        logger.log_action("update", "student", 1, 1, None).await.unwrap();
+        assert!(!logger.verify_integrity().await.unwrap());
+        assert!(logger.clock_warning().await.unwrap().unwrap().contains("behind"));
+
+        let jump = logger.check_clock().await.unwrap().unwrap();
+        assert_eq!(jump.direction, "backward");
+        assert_eq!(jump.annotated_entries, 1);
+        assert!(logger.verify_integrity().await.unwrap());
+        assert!(logger.check_clock().await.unwrap().is_none());
+
+        let entries = logger.get_entries(None, None).await.unwrap();
+        assert_eq!(entries[0].action, "clock_jump");
+        let sequences: Vec<i64> = entries.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![4, 3, 2, 1]);
+        assert!(entries[2].clock_note.is_some());
+        assert!(entries[1].clock_note.is_none());
+
+        assert!(logger.clock_warning().await.unwrap().unwrap().contains("changed"));
+        assert!(logger.cleanup_old_entries(0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_long_gap_counts_as_forward_jump() {
+        let (logger, _temp_dir) = create_test_audit_logger().await;
+
+        let long_ago = (Utc::now() - chrono::Duration::days(800)).format("%Y-%m-%d %H:%M:%S").to_string();
+        sqlx::query("INSERT INTO audit_log (action, object_type, object_id, user_id, timestamp) VALUES ('create', 'student', 1, 1, ?)")
+            .bind(&long_ago)
+            .execute(&logger.pool)
+            .await
+            .unwrap();
+
+        let jump = logger.check_clock().await.unwrap().unwrap();
+        assert_eq!(jump.direction, "forward");
+        assert_eq!(jump.annotated_entries, 0);
+        assert!(logger.check_clock().await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn test_audit_statistics() {
         let (logger, _temp_dir) = create_test_audit_logger().await;
@@ -0,0 +1,184 @@
+use crate::manifest::{ExportManifest, TableHasher, TableSummary};
+use crate::{Class, Observation, Student};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use tokio::sync::mpsc::Sender;
+
+/// One entry of a full backup's `data` section.
+#[derive(Debug)]
+pub enum BackupRecord {
+    Class(Class),
+    Student(Student),
+    Observation(Observation),
+    DictionaryWord(String),
+}
+
+/// What was found in a streamed backup besides the records themselves.
+pub struct BackupStreamSummary {
+    pub manifest: Option<ExportManifest>,
+    /// Count and hash of every section this version imports, as found in the file.
+    pub tables: BTreeMap<String, TableSummary>,
+}
+
+#[derive(Clone, Copy)]
+enum RecordKind {
+    Class,
+    Student,
+    Observation,
+    DictionaryWord,
+}
+
+/// Parses a full backup from `reader` and hands every record to `sink` as soon
+/// as it is complete, so only one record is held in memory at a time. Records
+/// that do not match the current model are skipped, unknown sections ignored.
+/// Blocks while the channel is full; run it on a blocking thread.
+pub fn read_backup_records<R: Read>(
+    reader: R,
+    sink: &Sender<BackupRecord>,
+) -> serde_json::Result<BackupStreamSummary> {
+    let mut hashers: BTreeMap<&'static str, TableHasher> = RecordKind::ALL
+        .iter()
+        .map(|kind| (kind.table(), TableHasher::default()))
+        .collect();
+
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let manifest = BackupSeed { sink, hashers: &mut hashers }.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+
+    Ok(BackupStreamSummary {
+        manifest,
+        tables: hashers
+            .into_iter()
+            .map(|(table, hasher)| (table.to_string(), hasher.finish()))
+            .collect(),
+    })
+}
+
+impl RecordKind {
+    const ALL: [RecordKind; 4] = [
+        RecordKind::Class,
+        RecordKind::Student,
+        RecordKind::Observation,
+        RecordKind::DictionaryWord,
+    ];
+
+    fn table(self) -> &'static str {
+        match self {
+            RecordKind::Class => "classes",
+            RecordKind::Student => "students",
+            RecordKind::Observation => "observations",
+            RecordKind::DictionaryWord => "dictionary",
+        }
+    }
+}
+
+struct BackupSeed<'a, 'h> {
+    sink: &'a Sender<BackupRecord>,
+    hashers: &'h mut BTreeMap<&'static str, TableHasher>,
+}
+
+impl<'de> DeserializeSeed<'de> for BackupSeed<'_, '_> {
+    type Value = Option<ExportManifest>;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for BackupSeed<'_, '_> {
+    type Value = Option<ExportManifest>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a full backup object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut manifest = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "data" => map.next_value_seed(DataSeed { sink: self.sink, hashers: &mut *self.hashers })?,
+                "manifest" => manifest = Some(map.next_value::<ExportManifest>()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+struct DataSeed<'a, 'h> {
+    sink: &'a Sender<BackupRecord>,
+    hashers: &'h mut BTreeMap<&'static str, TableHasher>,
+}
+
+impl<'de> DeserializeSeed<'de> for DataSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DataSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a backup data section")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            let Some(kind) = RecordKind::ALL.into_iter().find(|kind| kind.table() == key) else {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            };
+            let hasher = self.hashers.get_mut(kind.table()).expect("hasher for every record kind");
+            map.next_value_seed(RecordsSeed { sink: self.sink, kind, hasher })?;
+        }
+        Ok(())
+    }
+}
+
+struct RecordsSeed<'a, 'h> {
+    sink: &'a Sender<BackupRecord>,
+    kind: RecordKind,
+    hasher: &'h mut TableHasher,
+}
+
+impl<'de> DeserializeSeed<'de> for RecordsSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RecordsSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of backup records")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(value) = seq.next_element::<serde_json::Value>()? {
+            self.hasher.add(&value);
+            let record = match self.kind {
+                RecordKind::Class => serde_json::from_value(value).ok().map(BackupRecord::Class),
+                RecordKind::Student => serde_json::from_value(value).ok().map(BackupRecord::Student),
+                RecordKind::Observation => serde_json::from_value(value).ok().map(BackupRecord::Observation),
+                RecordKind::DictionaryWord => serde_json::from_value(value).ok().map(BackupRecord::DictionaryWord),
+            };
+            if let Some(record) = record {
+                // The receiver is gone once the import failed or was cancelled
+                self.sink
+                    .blocking_send(record)
+                    .map_err(|_| de::Error::custom("import stopped"))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use crate::database::Database;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+pub const PACK_FORMAT: &str = "category_pack";
+pub const PACK_VERSION: u32 = 1;
+
+/// A school's category taxonomy with its templates and quick phrases. Only
+/// names, colors and texts are carried, never students, observations, device
+/// ids or how often a phrase was used.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CategoryPack {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub categories: Vec<PackCategory>,
+    #[serde(default)]
+    pub templates: Vec<PackTemplate>,
+    #[serde(default)]
+    pub quick_phrases: Vec<PackQuickPhrase>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PackCategory {
+    pub name: String,
+    pub color: String,
+    pub background_color: String,
+    pub text_color: String,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub sort_order: i32,
+    #[serde(default = "default_active")]
+    pub is_active: bool,
+    /// Locale to name, see `localization`.
+    #[serde(default)]
+    pub translations: BTreeMap<String, String>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PackTemplate {
+    pub name: String,
+    pub category: String,
+    pub text: String,
+    #[serde(default)]
+    pub sort_order: i32,
+    #[serde(default)]
+    pub translations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PackQuickPhrase {
+    pub category: String,
+    pub text: String,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct CategoryPackImport {
+    pub categories_created: usize,
+    pub categories_updated: usize,
+    pub templates_created: usize,
+    pub templates_updated: usize,
+    pub quick_phrases_created: usize, // Known phrases are left as they are
+}
+
+pub async fn build_pack(db: &Database) -> Result<CategoryPack> {
+    let categories = db
+        .get_all_categories()
+        .await?
+        .into_iter()
+        .map(|c| PackCategory {
+            name: c.name,
+            color: c.color,
+            background_color: c.background_color,
+            text_color: c.text_color,
+            icon: c.icon,
+            sort_order: c.sort_order,
+            is_active: c.is_active,
+            translations: serde_json::from_str(&c.translations).unwrap_or_default(),
+        })
+        .collect();
+    let templates = db
+        .get_observation_templates()
+        .await?
+        .into_iter()
+        .map(|t| PackTemplate {
+            name: t.name,
+            category: t.category,
+            text: t.text,
+            sort_order: t.sort_order,
+            translations: serde_json::from_str(&t.translations).unwrap_or_default(),
+        })
+        .collect();
+    let quick_phrases = db
+        .get_quick_phrases(None)
+        .await?
+        .into_iter()
+        .map(|p| PackQuickPhrase {
+            category: p.category,
+            text: p.text,
+        })
+        .collect();
+
+    Ok(CategoryPack {
+        format: PACK_FORMAT.to_string(),
+        version: PACK_VERSION,
+        exported_at: Utc::now(),
+        categories,
+        templates,
+        quick_phrases,
+    })
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Reads and checks a pack; unknown fields such as ids are ignored.
+pub fn parse_pack(data: &[u8]) -> Result<CategoryPack> {
+    let pack: CategoryPack = serde_json::from_slice(data).context("File is not a category pack")?;
+    if pack.format != PACK_FORMAT {
+        return Err(anyhow::anyhow!("File is not a category pack"));
+    }
+    if pack.version > PACK_VERSION {
+        return Err(anyhow::anyhow!(
+            "Category pack version {} is newer than supported ({})",
+            pack.version,
+            PACK_VERSION
+        ));
+    }
+    for category in &pack.categories {
+        if category.name.trim().is_empty() {
+            return Err(anyhow::anyhow!("Category pack contains a category without a name"));
+        }
+        for color in [&category.color, &category.background_color, &category.text_color] {
+            if !is_hex_color(color) {
+                return Err(anyhow::anyhow!("Invalid color '{}' for category '{}'", color, category.name));
+            }
+        }
+    }
+    if let Some(template) = pack.templates.iter().find(|t| t.name.trim().is_empty() || t.text.trim().is_empty()) {
+        return Err(anyhow::anyhow!("Template '{}' has no name or text", template.name));
+    }
+    if pack.quick_phrases.iter().any(|p| p.category.trim().is_empty() || p.text.trim().is_empty()) {
+        return Err(anyhow::anyhow!("Category pack contains a quick phrase without category or text"));
+    }
+    Ok(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pack_round_trip_merges_by_name() {
+        let (source, _source_dir) = crate::fixtures::test_db().await;
+        let (target, _target_dir) = crate::fixtures::test_db().await;
+
+        source
+            .create_category("Medienkompetenz".to_string(), "#10B981".to_string(), "#ECFDF5".to_string(), "#064E3B".to_string(), Some("laptop".to_string()))
+            .await
+            .unwrap();
+        source
+            .create_observation_template("Hausaufgaben".to_string(), "Medienkompetenz".to_string(), "Hausaufgaben fehlen".to_string())
+            .await
+            .unwrap();
+        let phrase = source.create_quick_phrase("Medienkompetenz", "recherchiert sicher").await.unwrap();
+        source.record_quick_phrase_use(phrase.id).await.unwrap();
+        let class = source.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        source.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let data = serde_json::to_vec(&build_pack(&source).await.unwrap()).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains("Mustermann"));
+        let pack = parse_pack(&data).unwrap();
+
+        let existing = target.get_all_categories().await.unwrap().len();
+        let report = target.merge_category_pack(&pack).await.unwrap();
+        assert_eq!(report.categories_created, 1);
+        assert_eq!(report.categories_updated, existing);
+        assert_eq!(report.templates_created, 1);
+        assert_eq!(report.quick_phrases_created, 1);
+        assert_eq!(target.get_quick_phrases(Some("Medienkompetenz")).await.unwrap()[0].usage_count, 0);
+
+        let imported = target.get_all_categories().await.unwrap();
+        let media = imported.iter().find(|c| c.name == "Medienkompetenz").unwrap();
+        assert_eq!(media.icon.as_deref(), Some("laptop"));
+
+        // Importing again only updates
+        let report = target.merge_category_pack(&pack).await.unwrap();
+        assert_eq!(report.categories_created + report.templates_created + report.quick_phrases_created, 0);
+        assert!(parse_pack(br#"{"format":"full_export"}"#).is_err());
+    }
+}
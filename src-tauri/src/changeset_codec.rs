@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::io::Read;
+
+/// Compressed changesets start with this header, followed by a zstd frame of
+/// the JSON. Plain JSON files start with `{` and are read as before.
+const MAGIC: &[u8; 4] = b"SBCZ";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// A damaged or hostile file must not expand without bound.
+const MAX_DECOMPRESSED_BYTES: u64 = 512 * 1024 * 1024;
+
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn compress(json: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(HEADER_LEN + json.len() / 4);
+    output.extend_from_slice(MAGIC);
+    output.push(VERSION);
+    zstd::stream::copy_encode(json, &mut output, COMPRESSION_LEVEL).context("Failed to compress changeset")?;
+    Ok(output)
+}
+
+/// Returns the JSON of a changeset file, whether it was compressed or not.
+pub fn decode(data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    if !is_compressed(data) {
+        return Ok(Cow::Borrowed(data));
+    }
+    let version = data.get(MAGIC.len()).copied().context("Truncated changeset file")?;
+    if version > VERSION {
+        return Err(anyhow::anyhow!(
+            "Changeset compression version {} is newer than supported ({})",
+            version,
+            VERSION
+        ));
+    }
+
+    let decoder = zstd::stream::Decoder::new(&data[HEADER_LEN..]).context("Invalid compressed changeset")?;
+    let mut json = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_BYTES + 1)
+        .read_to_end(&mut json)
+        .context("Invalid compressed changeset")?;
+    if json.len() as u64 > MAX_DECOMPRESSED_BYTES {
+        return Err(anyhow::anyhow!("Compressed changeset expands beyond the supported size"));
+    }
+    Ok(Cow::Owned(json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_plain_passthrough() {
+        let json = serde_json::json!({ "observations": vec!["Arbeitet konzentriert mit"; 500] }).to_string();
+
+        let compressed = compress(json.as_bytes()).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < json.len() / 10);
+        assert_eq!(decode(&compressed).unwrap().as_ref(), json.as_bytes());
+
+        assert!(matches!(decode(json.as_bytes()).unwrap(), Cow::Borrowed(_)));
+
+        let mut future = compressed.clone();
+        future[MAGIC.len()] = VERSION + 1;
+        assert!(decode(&future).is_err());
+        assert!(decode(&compressed[..HEADER_LEN + 3]).is_err());
+    }
+}
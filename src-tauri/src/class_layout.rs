@@ -0,0 +1,111 @@
+use anyhow::Result;
+use serde_json::Value;
+
+/// Plans are small; anything larger is most likely an embedded photo.
+const MAX_LAYOUT_BYTES: usize = 256 * 1024;
+
+/// Checks a layout before it is stored. The app does not prescribe the plan
+/// beyond one rule: seats and groups refer to students by `student_id` or
+/// `student_ids`, e.g. `{"seats": [{"row": 1, "column": 2, "student_id": 7}],
+/// "groups": [{"name": "Tisch 1", "student_ids": [7, 9]}]}`.
+pub fn validate(layout: &str) -> Result<Value> {
+    if layout.len() > MAX_LAYOUT_BYTES {
+        return Err(anyhow::anyhow!(
+            "Class layout is too large ({} KB, at most {} KB)",
+            layout.len() / 1024,
+            MAX_LAYOUT_BYTES / 1024
+        ));
+    }
+    let value: Value = serde_json::from_str(layout).map_err(|e| anyhow::anyhow!("Invalid class layout: {}", e))?;
+    if !value.is_object() {
+        return Err(anyhow::anyhow!("Class layout must be a JSON object"));
+    }
+    Ok(value)
+}
+
+fn names_student(object: &serde_json::Map<String, Value>, student_id: i64) -> bool {
+    object.get("student_id").and_then(|id| id.as_i64()) == Some(student_id)
+        || object
+            .get("student_ids")
+            .and_then(|ids| ids.as_array())
+            .is_some_and(|ids| ids.iter().any(|id| id.as_i64() == Some(student_id)))
+}
+
+/// The seats and groups referring to one student, for their GDPR export.
+/// Classmates in the same group are left out.
+pub fn positions_of(layout: &Value, student_id: i64) -> Vec<Value> {
+    let mut positions = Vec::new();
+    collect_positions(layout, student_id, &mut positions);
+    positions
+}
+
+fn collect_positions(value: &Value, student_id: i64, positions: &mut Vec<Value>) {
+    match value {
+        Value::Object(object) if names_student(object, student_id) => {
+            let mut position = object.clone();
+            if position.contains_key("student_ids") {
+                position.insert("student_ids".to_string(), Value::from(vec![student_id]));
+            }
+            positions.push(Value::Object(position));
+        }
+        Value::Object(object) => object.values().for_each(|v| collect_positions(v, student_id, positions)),
+        Value::Array(items) => items.iter().for_each(|v| collect_positions(v, student_id, positions)),
+        _ => {}
+    }
+}
+
+/// Removes every reference to an erased student: their seats become empty
+/// and they leave their groups. Returns whether anything changed.
+pub fn remove_student(layout: &mut Value, student_id: i64) -> bool {
+    match layout {
+        Value::Object(object) => {
+            let mut changed = false;
+            if object.get("student_id").and_then(|id| id.as_i64()) == Some(student_id) {
+                object.insert("student_id".to_string(), Value::Null);
+                changed = true;
+            }
+            if let Some(Value::Array(ids)) = object.get_mut("student_ids") {
+                let before = ids.len();
+                ids.retain(|id| id.as_i64() != Some(student_id));
+                changed |= ids.len() != before;
+            }
+            for value in object.values_mut() {
+                changed |= remove_student(value, student_id);
+            }
+            changed
+        }
+        Value::Array(items) => items.iter_mut().fold(false, |changed, v| remove_student(v, student_id) || changed),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_student_positions_leave_classmates_out() {
+        assert!(validate("[1, 2]").is_err());
+        assert!(validate("{\"seats\": ").is_err());
+        let mut layout = validate(
+            r#"{"seats": [{"row": 1, "column": 2, "student_id": 7}, {"row": 1, "column": 3, "student_id": 9}],
+                "groups": [{"name": "Tisch 1", "student_ids": [7, 9]}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            positions_of(&layout, 7),
+            vec![
+                json!({"name": "Tisch 1", "student_ids": [7]}),
+                json!({"row": 1, "column": 2, "student_id": 7}),
+            ]
+        );
+        assert!(positions_of(&layout, 11).is_empty());
+
+        assert!(remove_student(&mut layout, 7));
+        assert!(!remove_student(&mut layout, 7));
+        assert_eq!(layout["seats"][0], json!({"row": 1, "column": 2, "student_id": null}));
+        assert_eq!(layout["groups"][0]["student_ids"], json!([9]));
+    }
+}
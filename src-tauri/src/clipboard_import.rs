@@ -0,0 +1,219 @@
+use crate::{Category, Student};
+
+/// More than a class writes in a term; a larger clipboard holds something else.
+pub const MAX_CLIPBOARD_ROWS: usize = 1000;
+
+/// One line copied from a spreadsheet, with the students its name column
+/// may mean. Nothing is stored as an observation before the user confirmed
+/// the match, see `Database::promote_clipboard_rows`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ClipboardRow {
+    pub line: usize, // 1-based, as the spreadsheet shows it
+    pub name: String,
+    pub category: String,         // A category of this device, the default when none matched
+    pub category_given: String,   // As copied
+    pub category_known: bool,     // False when `category_given` named no category
+    pub text: String,
+    pub proposed_student_id: Option<i64>, // Set when exactly one student matches best
+    pub candidate_student_ids: Vec<i64>,  // Best match first
+}
+
+/// Removes the quotes a spreadsheet puts around cells with separators or quotes.
+fn unquote(cell: &str) -> String {
+    let cell = cell.trim();
+    match cell.strip_prefix('"').and_then(|c| c.strip_suffix('"')) {
+        Some(inner) => inner.replace("\"\"", "\"").trim().to_string(),
+        None => cell.to_string(),
+    }
+}
+
+/// Splits copied lines into name, category and text. Spreadsheets copy
+/// cells separated by tabs; lines without a tab are split at semicolons as
+/// in "Name;Kategorie;Text". Separators after the third column belong to the
+/// text. Lines with two columns have no category, a header line is skipped.
+pub fn parse_rows(content: &str) -> Vec<(usize, String, String, String)> {
+    let mut rows = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let separator = if line.contains('\t') { '\t' } else { ';' };
+        let cells: Vec<&str> = line.splitn(3, separator).collect();
+        let (name, category, text) = match cells.as_slice() {
+            [name, category, text] => (unquote(name), unquote(category), unquote(text)),
+            [name, text] => (unquote(name), String::new(), unquote(text)),
+            _ => (String::new(), String::new(), unquote(line)),
+        };
+        let header = [&name, &category, &text].map(|c| c.to_lowercase());
+        if rows.is_empty() && header[0] == "name" && ["text", "beobachtung", "notiz"].contains(&header[2].as_str()) {
+            continue;
+        }
+        rows.push((index + 1, name, category, text));
+    }
+    rows
+}
+
+fn words(name: &str) -> Vec<String> {
+    name.split(|c: char| c.is_whitespace() || c == ',' || c == '.')
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// How well one word of the name column fits one word of a student's name:
+/// 3 when equal, 2 for a typo in a longer word ("Musterman"), 1 for an initial ("M.").
+fn word_score(given: &str, name: &str) -> usize {
+    if given == name {
+        3
+    } else if given.chars().count() >= 4 && edit_distance(given, name) <= 1 {
+        2
+    } else if given.chars().count() == 1 && name.starts_with(given) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Every word of the name column has to fit a different word of the
+/// student's name, in any order, so "Mustermann, Max" and "Max M." match
+/// Max Mustermann. 0 when some word fits nothing.
+pub fn name_score(given: &str, student: &Student) -> usize {
+    let mut names = words(&format!("{} {}", student.first_name, student.last_name));
+    let mut score = 0;
+    for word in words(given) {
+        let best = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (index, word_score(&word, name)))
+            .max_by_key(|(_, score)| *score)
+            .filter(|(_, score)| *score > 0);
+        let Some((index, points)) = best else {
+            return 0;
+        };
+        names.remove(index);
+        score += points;
+    }
+    score
+}
+
+/// The category of this device a copied label names, by name or by its
+/// name in the current locale, ignoring case.
+fn find_category<'a>(given: &str, categories: &'a [Category]) -> Option<&'a Category> {
+    let given = given.trim().to_lowercase();
+    categories.iter().filter(|c| c.is_active).find(|c| {
+        c.name.to_lowercase() == given || c.display_name.as_ref().is_some_and(|d| d.to_lowercase() == given)
+    })
+}
+
+/// Matches the parsed lines to the students of the class and the
+/// categories of this device.
+pub fn prepare_rows(
+    content: &str,
+    students: &[Student],
+    categories: &[Category],
+    default_category: &str,
+) -> anyhow::Result<Vec<ClipboardRow>> {
+    let rows = parse_rows(content);
+    if rows.is_empty() {
+        return Err(anyhow::anyhow!("The clipboard holds no lines to import"));
+    }
+    if rows.len() > MAX_CLIPBOARD_ROWS {
+        return Err(anyhow::anyhow!(
+            "The clipboard holds {} lines, at most {} are imported at once",
+            rows.len(),
+            MAX_CLIPBOARD_ROWS
+        ));
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|(line, name, category_given, text)| {
+            let mut scored: Vec<(i64, usize)> = students
+                .iter()
+                .map(|s| (s.id, name_score(&name, s)))
+                .filter(|(_, score)| *score > 0)
+                .collect();
+            scored.sort_by(|a, b| b.1.cmp(&a.1));
+            let proposed_student_id = match scored.as_slice() {
+                [(id, _)] => Some(*id),
+                [(id, best), (_, second), ..] if best > second => Some(*id),
+                _ => None,
+            };
+            let known = find_category(&category_given, categories);
+            let category = known.map(|c| c.name.clone()).unwrap_or_else(|| default_category.to_string());
+            ClipboardRow {
+                line,
+                name,
+                category,
+                category_known: known.is_some() || category_given.is_empty(),
+                category_given,
+                text,
+                proposed_student_id,
+                candidate_student_ids: scored.into_iter().map(|(id, _)| id).collect(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spreadsheet_lines_are_matched_to_students() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let schmidt = db.create_student(class.id, "Max".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Albrecht".to_string(), None).await.unwrap();
+        let students = db.get_students_by_class(class.id).await.unwrap();
+        let categories = db.get_categories().await.unwrap();
+        let known = categories.iter().find(|c| c.is_active).unwrap().name.clone();
+
+        let content = format!(
+            "Name;Kategorie;Text\n\
+             Mustermann, Max;{};Hilft beim Aufräumen; ohne Aufforderung\n\
+             Max Musterman\tunbekannt\t\"Sagt \"\"danke\"\"\"\n\
+             \n\
+             Anna A.;Liest flüssig vor\n\
+             Max;{};Störte\n\
+             Erika;{};Fehlt",
+            known.to_uppercase(),
+            known,
+            known
+        );
+        let rows = prepare_rows(&content, &students, &categories, "Sonstiges").unwrap();
+        assert_eq!(rows.iter().map(|r| r.line).collect::<Vec<_>>(), vec![2, 3, 5, 6, 7]);
+
+        assert_eq!(rows[0].proposed_student_id, Some(max.id));
+        assert_eq!((rows[0].category.as_str(), rows[0].text.as_str()), (known.as_str(), "Hilft beim Aufräumen; ohne Aufforderung"));
+        // A typo in the name, a tab-separated line and an unknown category
+        assert_eq!(rows[1].proposed_student_id, Some(max.id));
+        assert!(rows[0].category_known && !rows[1].category_known);
+        assert_eq!((rows[1].category.as_str(), rows[1].category_given.as_str()), ("Sonstiges", "unbekannt"));
+        assert_eq!(rows[1].text, "Sagt \"danke\"");
+        assert_eq!(rows[2].proposed_student_id, Some(anna.id));
+        assert_eq!(rows[2].category, "Sonstiges");
+        // Two students named Max, and nobody named Erika
+        assert_eq!(rows[3].proposed_student_id, None);
+        assert_eq!(rows[3].candidate_student_ids.len(), 2);
+        assert!(rows[3].candidate_student_ids.contains(&schmidt.id));
+        assert!(rows[4].candidate_student_ids.is_empty());
+
+        assert!(prepare_rows(" \n", &students, &categories, "Sonstiges").is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::AppState;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+const COMPANION_API_SETTING: &str = "companion_api_enabled";
+/// The user the current token was issued for; the companion tool acts as them.
+const COMPANION_API_USER_SETTING: &str = "companion_api_user";
+
+/// Requests are single JSON lines; anything longer is not a valid request.
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(windows)]
+pub const PIPE_NAME: &str = r"\\.\pipe\schuelerbeobachtung-companion";
+
+#[derive(Debug, serde::Serialize)]
+pub struct CompanionApiStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub endpoint: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Request {
+    token: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CreateObservationParams {
+    student_id: i64,
+    category: String,
+    text: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Enabled in the settings and not switched off by its feature flag.
+pub async fn is_enabled(db: &crate::database::Database) -> Result<bool> {
+    Ok(db.get_setting(COMPANION_API_SETTING).await?.as_deref() == Some("true")
+        && crate::feature_flags::is_enabled(db, "companion_api").await?)
+}
+
+/// Enabling issues the API to `user_id`, whose name then stands on what the
+/// companion tool creates.
+pub async fn set_enabled(db: &crate::database::Database, enabled: bool, user_id: i64) -> Result<()> {
+    if enabled {
+        db.get_user(user_id).await?.context("User not found")?;
+        db.set_setting(COMPANION_API_USER_SETTING, &user_id.to_string()).await?;
+    }
+    db.set_setting(COMPANION_API_SETTING, if enabled { "true" } else { "false" })
+        .await
+}
+
+/// The user the companion API was issued to, as long as they still exist.
+async fn token_user(db: &crate::database::Database) -> Result<i64> {
+    let user_id = db
+        .get_setting(COMPANION_API_USER_SETTING)
+        .await?
+        .and_then(|value| value.parse::<i64>().ok())
+        .context("The companion API is not issued to a user")?;
+    db.get_user(user_id)
+        .await?
+        .context("The user the companion API was issued to no longer exists")?;
+    Ok(user_id)
+}
+
+pub fn endpoint(app_data_dir: &Path) -> String {
+    #[cfg(windows)]
+    {
+        let _ = app_data_dir;
+        PIPE_NAME.to_string()
+    }
+    #[cfg(not(windows))]
+    {
+        socket_path(app_data_dir).to_string_lossy().to_string()
+    }
+}
+
+#[cfg(not(windows))]
+fn socket_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("companion.sock")
+}
+
+pub fn status(app_data_dir: &Path, enabled: bool) -> CompanionApiStatus {
+    CompanionApiStatus {
+        enabled,
+        running: RUNNING.load(Ordering::SeqCst),
+        endpoint: endpoint(app_data_dir),
+    }
+}
+
+/// The small set of operations a school-approved companion tool may use.
+/// Everything it does is audited like the same action in the app.
+async fn dispatch(state: &AppState, method: &str, params: Value) -> Result<Value> {
+    match method {
+        "list_classes" => {
+            let db = state.db.lock().await;
+            Ok(serde_json::to_value(db.get_classes().await?)?)
+        }
+        "create_observation" => {
+            let params: CreateObservationParams =
+                serde_json::from_value(params).context("Invalid parameters for create_observation")?;
+            let db = state.db.lock().await;
+            let author_id = token_user(&db).await?;
+            db.get_student(params.student_id).await?.context("Student not found")?;
+            let observation = db
+                .create_observation(params.student_id, author_id, params.category, params.text, params.tags)
+                .await?;
+            drop(db);
+            state
+                .audit
+                .log_action("create", "observation", observation.id, author_id, Some("companion_api"))
+                .await?;
+            Ok(serde_json::to_value(observation)?)
+        }
+        other => Err(anyhow::anyhow!("Unknown method '{}'", other)),
+    }
+}
+
+async fn handle_request(state: &AppState, line: &str) -> Value {
+    let response = async {
+        let request: Request = serde_json::from_str(line).context("Request is not valid JSON")?;
+        // Checked on every request, so a revoked token or a disabled API takes effect at once
+        if !is_enabled(&*state.db.lock().await).await? {
+            return Err(anyhow::anyhow!("Companion API is disabled"));
+        }
+        if !state.crypto.verify_api_token(&request.token)? {
+            let _ = state
+                .audit
+                .log_action("companion_auth_failed", "app", 0, 0, Some(&request.method))
+                .await;
+            return Err(anyhow::anyhow!("Invalid token"));
+        }
+        dispatch(state, &request.method, request.params).await
+    }
+    .await;
+
+    match response {
+        Ok(result) => json!({ "ok": true, "result": result }),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    }
+}
+
+async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin>(state: AppState, stream: S) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader).take(MAX_REQUEST_BYTES as u64);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) if !line.ends_with('\n') && line.len() >= MAX_REQUEST_BYTES => {
+                let _ = writer.write_all(b"{\"ok\":false,\"error\":\"Request too large\"}\n").await;
+                break;
+            }
+            Ok(_) => {}
+        }
+        reader.set_limit(MAX_REQUEST_BYTES as u64);
+
+        let mut response = handle_request(&state, line.trim()).await.to_string();
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Listens on a socket only the current user can open; no network port is used.
+#[cfg(unix)]
+async fn listen(state: AppState, app_data_dir: PathBuf) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = socket_path(&app_data_dir);
+    // A socket left by a crash would block binding
+    let _ = std::fs::remove_file(&path);
+    let listener = tokio::net::UnixListener::bind(&path).context("Failed to open companion socket")?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        if !is_enabled(&*state.db.lock().await).await? {
+            break;
+        }
+        tokio::spawn(serve_connection(state.clone(), stream));
+    }
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+/// Remote clients are rejected, so the pipe is only reachable on this machine.
+#[cfg(windows)]
+async fn listen(state: AppState, _app_data_dir: PathBuf) -> Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .reject_remote_clients(true)
+        .create(PIPE_NAME)
+        .context("Failed to open companion pipe")?;
+    loop {
+        server.connect().await?;
+        let connected = server;
+        server = ServerOptions::new().reject_remote_clients(true).create(PIPE_NAME)?;
+        if !is_enabled(&*state.db.lock().await).await? {
+            break;
+        }
+        tokio::spawn(serve_connection(state.clone(), connected));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+async fn listen(_state: AppState, _app_data_dir: PathBuf) -> Result<()> {
+    Err(anyhow::anyhow!("The companion API is not supported on this platform"))
+}
+
+/// Starts the listener unless it already runs. It stops with the next
+/// connection after the API was disabled.
+pub fn start(state: AppState, app_data_dir: PathBuf) {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = listen(state, app_data_dir).await {
+            eprintln!("Companion API stopped: {}", e);
+        }
+        RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditLogger;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_dispatch_and_disabled_api() {
+        let (db, crypto, temp_dir) = crate::fixtures::test_db_with_crypto().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let teacher = db.create_user("Frau Weber".to_string(), "teacher".to_string()).await.unwrap();
+        let state = AppState {
+            db: Arc::new(Mutex::new(db)),
+            crypto,
+            audit: Arc::new(AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap()),
+            gdpr: Arc::new(crate::gdpr::GdprManager::new()),
+            reports: Arc::new(crate::reports::ReportGenerator::new()),
+            operations: Arc::new(crate::operations::OperationRegistry::new()),
+        };
+
+        let response = handle_request(&state, r#"{"token":"x","method":"list_classes"}"#).await;
+        assert_eq!(response["error"], "Companion API is disabled");
+        // Switched on, but its feature flag is off for this profile
+        {
+            let db = state.db.lock().await;
+            assert!(set_enabled(&db, true, 999).await.is_err());
+            set_enabled(&db, true, teacher.id).await.unwrap();
+            assert!(is_enabled(&db).await.unwrap());
+            db.set_setting("feature_flags", r#"{"companion_api": false}"#).await.unwrap();
+        }
+        let response = handle_request(&state, r#"{"token":"x","method":"list_classes"}"#).await;
+        assert_eq!(response["error"], "Companion API is disabled");
+        assert_eq!(handle_request(&state, "kein json").await["ok"], false);
+
+        let classes = dispatch(&state, "list_classes", Value::Null).await.unwrap();
+        assert_eq!(classes[0]["name"], "5a");
+        let observation = dispatch(
+            &state,
+            "create_observation",
+            json!({ "student_id": student.id, "category": "Leistung", "text": "Note 2 in der Probe" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(observation["student_id"], student.id);
+        assert_eq!(observation["author_id"], teacher.id);
+        assert!(dispatch(&state, "delete_student", Value::Null).await.is_err());
+        assert!(dispatch(&state, "create_observation", json!({ "student_id": 999, "category": "x", "text": "y" }))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::audit::AuditLogger;
+use crate::database::Database;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// Usage history rather than configuration; recorded with every export.
+const UNAUDITED_SETTINGS: [&str; 1] = ["recent_transfer_locations"];
+
+/// One configuration value before and after a change.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ConfigChange {
+    pub setting: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Taken before a command touches settings; `finish` audits whatever it
+/// changed, so no command has to know which keys it writes.
+pub struct SettingsChange {
+    before: BTreeMap<String, String>,
+}
+
+pub async fn begin(db: &Database) -> Result<SettingsChange> {
+    Ok(SettingsChange {
+        before: db.get_all_settings().await?,
+    })
+}
+
+impl SettingsChange {
+    pub async fn finish(self, db: &Database, audit: &AuditLogger, user_id: i64) -> Result<Vec<ConfigChange>> {
+        let changes = diff(&self.before, &db.get_all_settings().await?);
+        log_changes(audit, &changes, user_id).await?;
+        Ok(changes)
+    }
+}
+
+/// For configuration kept outside the settings table, such as the device
+/// name or the database location.
+pub async fn log_change(
+    audit: &AuditLogger,
+    setting: &str,
+    before: Option<String>,
+    after: Option<String>,
+    user_id: i64,
+) -> Result<()> {
+    if before == after {
+        return Ok(());
+    }
+    let change = ConfigChange {
+        setting: setting.to_string(),
+        before,
+        after,
+    };
+    log_changes(audit, &[change], user_id).await
+}
+
+async fn log_changes(audit: &AuditLogger, changes: &[ConfigChange], user_id: i64) -> Result<()> {
+    for change in changes {
+        audit
+            .log_action("configure", "setting", 0, user_id, Some(&serde_json::to_string(change)?))
+            .await?;
+    }
+    Ok(())
+}
+
+pub fn diff(before: &BTreeMap<String, String>, after: &BTreeMap<String, String>) -> Vec<ConfigChange> {
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter(|key| !UNAUDITED_SETTINGS.contains(&key.as_str()))
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| ConfigChange {
+            setting: key.clone(),
+            before: before.get(key).cloned(),
+            after: after.get(key).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_settings_changes_are_audited_with_values() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+        db.set_setting("ui_locale", "de-DE").await.unwrap();
+
+        let change = begin(&db).await.unwrap();
+        db.set_setting("ui_locale", "en-US").await.unwrap();
+        db.set_setting("xapi_export_enabled", "true").await.unwrap();
+        db.set_setting("recent_transfer_locations", "[]").await.unwrap();
+        let changes = change.finish(&db, &audit, 1).await.unwrap();
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            changes[0],
+            ConfigChange {
+                setting: "ui_locale".to_string(),
+                before: Some("de-DE".to_string()),
+                after: Some("en-US".to_string()),
+            }
+        );
+        assert_eq!(changes[1].before, None);
+
+        let entries = audit.get_entries_by_action("configure", None).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        let details = entries.iter().find_map(|e| e.details.clone().filter(|d| d["setting"] == "ui_locale")).unwrap();
+        assert_eq!(details["before"], "de-DE");
+        assert_eq!(details["after"], "en-US");
+
+        // Nothing changed, nothing logged
+        log_change(&audit, "device_name", Some("Notebook".to_string()), Some("Notebook".to_string()), 1).await.unwrap();
+        assert!(begin(&db).await.unwrap().finish(&db, &audit, 1).await.unwrap().is_empty());
+        assert_eq!(audit.get_entries_by_action("configure", None).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::audit::{AuditLogger, AuditTrace};
+use crate::database::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+
+/// Entity types the audit log records object by object.
+const TRACKED_TYPES: [&str; 4] = ["class", "student", "observation", "guardian"];
+
+/// Actions that remove the object they name.
+const DELETING_ACTIONS: [&str; 3] = ["delete", "approve_erasure", "execute_erasure"];
+
+/// Imports and restores add and remove rows without an entry per object, so
+/// rows touched after one cannot be checked against the log.
+const BULK_ACTIONS: [&str; 2] = ["import", "restore"];
+
+#[derive(Debug, serde::Serialize)]
+pub struct ConsistencyAnomaly {
+    pub kind: String, // "missing_after_create", "present_after_delete" or "created_without_audit"
+    pub object_type: String,
+    pub object_id: i64,
+    pub audit_entry_id: Option<i64>,
+    pub message: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ConsistencyReport {
+    pub checked_at: DateTime<Utc>,
+    pub entries_checked: usize,
+    pub objects_checked: usize,
+    pub audit_timestamps_monotonic: bool,
+    pub anomalies: Vec<ConsistencyAnomaly>,
+}
+
+/// Objects whose deletion also removes objects of `entity_type`.
+fn parent_types(entity_type: &str) -> &'static [&'static str] {
+    match entity_type {
+        "student" => &["class"],
+        "observation" | "guardian" => &["student", "class"],
+        _ => &[],
+    }
+}
+
+fn is_deleting(trace: &AuditTrace) -> bool {
+    // Soft deletions keep the row
+    DELETING_ACTIONS.contains(&trace.action.as_str())
+        && !trace.details.as_deref().is_some_and(|details| details.starts_with("soft_delete"))
+}
+
+/// Cross-checks the audit log against the current data. Anomalies are
+/// hints, e.g. for a lost write after a crash or an edited database, not
+/// proof: the log may have been cleaned up by its retention period.
+pub async fn run_consistency_audit(db: &Database, audit: &AuditLogger) -> Result<ConsistencyReport> {
+    let traces = audit.get_traces().await?;
+    let oldest_entry = traces.first().map(|t| t.timestamp);
+    let bulk_after = |at: DateTime<Utc>| {
+        traces
+            .iter()
+            .any(|t| BULK_ACTIONS.contains(&t.action.as_str()) && t.timestamp >= at)
+    };
+
+    let mut anomalies = Vec::new();
+    let mut objects_checked = 0;
+
+    for entity_type in TRACKED_TYPES {
+        let rows: HashMap<i64, DateTime<Utc>> = db.get_entity_rows(entity_type).await?.into_iter().collect();
+        objects_checked += rows.len();
+
+        let mut history: BTreeMap<i64, Vec<&AuditTrace>> = BTreeMap::new();
+        for trace in traces.iter().filter(|t| t.object_type == entity_type && t.object_id > 0) {
+            history.entry(trace.object_id).or_default().push(trace);
+        }
+        let parent_deleted_after = |at: DateTime<Utc>| {
+            traces.iter().any(|t| {
+                parent_types(entity_type).contains(&t.object_type.as_str()) && is_deleting(t) && t.timestamp >= at
+            })
+        };
+
+        for (&object_id, entries) in &history {
+            let created = entries.iter().find(|t| t.action == "create");
+            let deleted = entries.iter().rev().find(|t| is_deleting(t));
+            let exists = rows.contains_key(&object_id);
+
+            match (created, deleted) {
+                (Some(created), None) if !exists && !bulk_after(created.timestamp) && !parent_deleted_after(created.timestamp) => {
+                    anomalies.push(ConsistencyAnomaly {
+                        kind: "missing_after_create".to_string(),
+                        object_type: entity_type.to_string(),
+                        object_id,
+                        audit_entry_id: Some(created.id),
+                        message: format!(
+                            "{} {} was created on {} but no longer exists and no deletion was logged",
+                            entity_type,
+                            object_id,
+                            created.timestamp.format("%Y-%m-%d %H:%M")
+                        ),
+                    });
+                }
+                (_, Some(deleted)) if exists && !bulk_after(deleted.timestamp) => {
+                    anomalies.push(ConsistencyAnomaly {
+                        kind: "present_after_delete".to_string(),
+                        object_type: entity_type.to_string(),
+                        object_id,
+                        audit_entry_id: Some(deleted.id),
+                        message: format!(
+                            "{} {} was deleted on {} but still exists",
+                            entity_type,
+                            object_id,
+                            deleted.timestamp.format("%Y-%m-%d %H:%M")
+                        ),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        for (&object_id, &created_at) in &rows {
+            let logged = history
+                .get(&object_id)
+                .is_some_and(|entries| entries.iter().any(|t| t.action == "create"));
+            // Rows older than the log, e.g. after its retention cleanup, are not checked
+            if logged || oldest_entry.map_or(true, |oldest| created_at < oldest) || bulk_after(created_at) {
+                continue;
+            }
+            anomalies.push(ConsistencyAnomaly {
+                kind: "created_without_audit".to_string(),
+                object_type: entity_type.to_string(),
+                object_id,
+                audit_entry_id: None,
+                message: format!(
+                    "{} {} exists since {} but its creation was never logged",
+                    entity_type,
+                    object_id,
+                    created_at.format("%Y-%m-%d %H:%M")
+                ),
+            });
+        }
+    }
+
+    Ok(ConsistencyReport {
+        checked_at: Utc::now(),
+        entries_checked: traces.len(),
+        objects_checked,
+        audit_timestamps_monotonic: audit.verify_integrity().await?,
+        anomalies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reports_unlogged_changes() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+        // Row timestamps have second precision, so the log starts a minute before the rows
+        audit
+            .log_action_at("login", "app", 0, 1, Utc::now() - chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        audit.log_action("create", "class", class.id, 1, None).await.unwrap();
+        let kept = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        audit.log_action("create", "student", kept.id, 1, None).await.unwrap();
+        let soft = db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        audit.log_action("create", "student", soft.id, 1, None).await.unwrap();
+        db.delete_student(soft.id, false).await.unwrap();
+        audit.log_action("delete", "student", soft.id, 1, Some("soft_delete")).await.unwrap();
+        assert!(run_consistency_audit(&db, &audit).await.unwrap().anomalies.is_empty());
+
+        // A deletion that never reached the log, and a row the log does not know
+        db.delete_student(kept.id, true).await.unwrap();
+        let unlogged = db.create_student(class.id, "Tom".to_string(), "Weber".to_string(), None).await.unwrap();
+
+        let report = run_consistency_audit(&db, &audit).await.unwrap();
+        let kinds: Vec<(&str, i64)> = report.anomalies.iter().map(|a| (a.kind.as_str(), a.object_id)).collect();
+        assert!(kinds.contains(&("missing_after_create", kept.id)));
+        assert!(kinds.contains(&("created_without_audit", unlogged.id)));
+        assert_eq!(kinds.len(), 2);
+
+        // An import afterwards makes both unverifiable instead of suspicious
+        audit.log_action("import", "changeset_file", 0, 1, None).await.unwrap();
+        assert!(run_consistency_audit(&db, &audit).await.unwrap().anomalies.is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::database::changeset_checksum;
+use crate::manifest::{self, ExportManifest};
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    FullExport,
+    Changeset,
+}
+
+impl ExportFormat {
+    pub fn parse(format: &str) -> Result<Self> {
+        match format {
+            "full_export" => Ok(Self::FullExport),
+            "changeset" | "changeset_file_v1" => Ok(Self::Changeset),
+            other => Err(anyhow::anyhow!(
+                "Unknown export format '{}', expected 'full_export' or 'changeset'",
+                other
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FullExport => "full_export",
+            Self::Changeset => "changeset",
+        }
+    }
+
+    /// The extension files of this format are written with.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::FullExport => "json",
+            Self::Changeset => "sbchange",
+        }
+    }
+}
+
+/// Result of `convert_export`: the new file and what did not survive the conversion.
+pub struct Conversion {
+    pub output: Vec<u8>,
+    pub summary: String,
+}
+
+/// Changesets are wrapped as `{checksum, data}`, full exports carry their
+/// format at the top level.
+pub fn detect_format(parsed: &Value) -> Result<ExportFormat> {
+    if parsed.get("checksum").is_some() {
+        return Ok(ExportFormat::Changeset);
+    }
+    match parsed.get("format").and_then(|f| f.as_str()) {
+        Some("full_export") => Ok(ExportFormat::FullExport),
+        Some(other) => Err(anyhow::anyhow!("Unsupported export format '{}'", other)),
+        None => Err(anyhow::anyhow!("File is neither a full export nor a changeset")),
+    }
+}
+
+/// Converts between full exports and changesets without a database. Only
+/// observations exist in both formats: students and classes of a full export
+/// are left out of the changeset, so the receiving device must know them
+/// already; deletions and class transfers of a changeset have no place in a
+/// full export.
+pub fn convert_export(input: &[u8], target: ExportFormat) -> Result<Conversion> {
+    let parsed: Value = serde_json::from_slice(input).context("Invalid export file format")?;
+    let source = detect_format(&parsed)?;
+    if source == target {
+        return Err(anyhow::anyhow!("File already is a {}", target.name()));
+    }
+
+    match source {
+        ExportFormat::FullExport => full_export_to_changeset(&parsed),
+        ExportFormat::Changeset => changeset_to_full_export(&parsed),
+    }
+}
+
+fn full_export_to_changeset(parsed: &Value) -> Result<Conversion> {
+    let data = parsed.get("data").context("Missing data section in full export")?;
+    manifest::verify_optional(parsed.get("manifest"), data)?;
+
+    let observations = records(data, "observations");
+    let logical_clock = observations
+        .iter()
+        .filter_map(|o| o.get("logical_clock").and_then(|c| c.as_i64()))
+        .max()
+        .unwrap_or(0);
+    let exported_at = parsed
+        .get("timestamp")
+        .or_else(|| parsed.get("exported_at"))
+        .cloned()
+        .unwrap_or_else(|| json!(chrono::Utc::now()));
+
+    let changes = json!({
+        "observations": observations,
+        "tombstones": []
+    });
+    let changeset = json!({
+        "format": "changeset_file_v1",
+        "version": "1.0",
+        // A conversion is a new operation; importing it twice is still detected
+        "operation_id": uuid::Uuid::new_v4().to_string(),
+        "timestamp": exported_at,
+        "device_id": Value::Null,
+        "logical_clock": logical_clock,
+        "days_back": Value::Null,
+        "converted_from": "full_export",
+        "manifest": ExportManifest::describe(&changes),
+        "changes": changes
+    });
+
+    let skipped_students = records(data, "students").len();
+    let skipped_classes = records(data, "classes").len();
+    let output = json!({
+        "checksum": changeset_checksum(&changeset),
+        "data": changeset
+    });
+
+    Ok(Conversion {
+        output: output.to_string().into_bytes(),
+        summary: format!(
+            "{} observations converted to a changeset; {} students and {} classes are not part of changesets and must already exist on the receiving device",
+            observations.len(),
+            skipped_students,
+            skipped_classes
+        ),
+    })
+}
+
+fn changeset_to_full_export(parsed: &Value) -> Result<Conversion> {
+    let stored_checksum = parsed
+        .get("checksum")
+        .and_then(|c| c.as_str())
+        .context("Missing checksum in changeset file")?;
+    let changeset = parsed.get("data").context("Missing data section in changeset file")?;
+    if changeset_checksum(changeset) != stored_checksum {
+        return Err(anyhow::anyhow!("Checksum verification failed"));
+    }
+
+    let changes = changeset.get("changes").cloned().unwrap_or_else(|| json!({}));
+    manifest::verify_optional(changeset.get("manifest"), &changes)?;
+
+    let observations = records(&changes, "observations");
+    let dropped_deletions = records(&changes, "tombstones").len();
+    let dropped_transfers = records(&changes, "transfers").len();
+
+    let data = json!({
+        "students": [],
+        "classes": [],
+        "observations": observations
+    });
+    let export = json!({
+        "format": "full_export",
+        "version": "1.0",
+        "timestamp": changeset.get("timestamp").cloned().unwrap_or_else(|| json!(chrono::Utc::now())),
+        "export_scope": {
+            "days_back": changeset.get("days_back"),
+            "total_students": 0,
+            "total_classes": 0,
+            "total_observations": observations.len()
+        },
+        "converted_from": "changeset",
+        "manifest": ExportManifest::describe(&data),
+        "data": data
+    });
+
+    let mut summary = format!("{} observations converted to a full export", observations.len());
+    if dropped_deletions > 0 {
+        summary.push_str(&format!(
+            "; {} deletions cannot be represented in a full export and were dropped",
+            dropped_deletions
+        ));
+    }
+    if dropped_transfers > 0 {
+        summary.push_str(&format!(
+            "; {} class transfers cannot be represented in a full export and were dropped",
+            dropped_transfers
+        ));
+    }
+
+    Ok(Conversion {
+        output: export.to_string().into_bytes(),
+        summary,
+    })
+}
+
+fn records<'a>(section: &'a Value, key: &str) -> &'a [Value] {
+    section
+        .get(key)
+        .and_then(|r| r.as_array())
+        .map(|r| r.as_slice())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_full_export_converts_to_importable_changeset() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let now = chrono::Utc::now();
+        let data = json!({
+            "students": [student],
+            "classes": [class],
+            "observations": [{
+                "id": 4711, "student_id": student.id, "author_id": 1, "category": "Sozial", "text": "Hilft gern",
+                "tags": "[]", "created_at": now, "updated_at": now, "source_device_id": "other", "logical_clock": 3
+            }]
+        });
+        let full_export = json!({
+            "format": "full_export",
+            "timestamp": now,
+            "manifest": ExportManifest::describe(&data),
+            "data": data
+        });
+
+        let conversion = convert_export(full_export.to_string().as_bytes(), ExportFormat::Changeset).unwrap();
+        assert!(conversion.summary.contains("1 students and 1 classes"));
+
+        db.apply_changeset_file(&conversion.output).await.unwrap();
+        assert_eq!(db.search_observations(None, Some(student.id), None).await.unwrap().len(), 1);
+
+        // And back again
+        let back = convert_export(&conversion.output, ExportFormat::FullExport).unwrap();
+        let parsed: Value = serde_json::from_slice(&back.output).unwrap();
+        assert_eq!(parsed["data"]["observations"][0]["id"], 4711);
+        assert!(convert_export(&back.output, ExportFormat::FullExport).is_err());
+    }
+}
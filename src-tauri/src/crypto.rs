@@ -1,7 +1,40 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
 use directories::ProjectDirs;
-use std::{collections::HashMap, fs, path::PathBuf};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fs, path::PathBuf, sync::RwLock};
 use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Rounds of salted SHA-256 for the app password, to slow down guessing.
+const PASSWORD_HASH_ROUNDS: u32 = 100_000;
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Domain separation for keys derived for sealed data.
+const SEAL_CONTEXT: &[u8] = b"schuelerbeobachtung:sealed:v1";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+/// Marks files and staged imports encrypted with the local data key; data
+/// without it was written before and is read as it is.
+const LOCAL_DATA_MARKER: &[u8] = b"SBLOCAL1";
+
+/// Wrong app passwords allowed before unlocking is locked; each further
+/// failure doubles the lockout, up to a day.
+const FREE_UNLOCK_ATTEMPTS: u32 = 3;
+const UNLOCK_LOCKOUT_SECONDS: i64 = 30;
+const MAX_UNLOCK_LOCKOUT_SECONDS: i64 = 24 * 60 * 60;
+
+/// Failed recovery attempts before recovery is locked for a while.
+const MAX_RECOVERY_ATTEMPTS: u32 = 5;
+const RECOVERY_LOCKOUT_MINUTES: i64 = 60;
+/// Without 0/O and 1/I, so a printed code is typed back correctly.
+const RECOVERY_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const RECOVERY_CODE_LEN: usize = 16;
+/// Check characters at the end catch typos before they count as attempts.
+const RECOVERY_CHECK_LEN: usize = 4;
 
 // Encryption disabled - using plaintext storage
 // Original encryption dependencies commented out:
@@ -12,15 +45,28 @@ use uuid::Uuid;
 // use keyring::Entry;
 // use base64::prelude::*;
 
+/// Set when the app data root was moved, see `data_root`.
+static DATA_DIR_OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Keeps the secrets file in `dir` instead of the per-user data directory.
+/// Call before creating the `CryptoManager`.
+pub fn set_data_dir(dir: PathBuf) {
+    *DATA_DIR_OVERRIDE.write().unwrap_or_else(|e| e.into_inner()) = Some(dir);
+}
+
 fn data_dir() -> Result<PathBuf> {
-    let proj = ProjectDirs::from("", "", "schuelerbeobachtung")
-        .context("Failed to determine data directory")?;
-    let dir = proj.data_dir().to_path_buf();
+    let dir = match DATA_DIR_OVERRIDE.read().unwrap_or_else(|e| e.into_inner()).clone() {
+        Some(dir) => dir,
+        None => ProjectDirs::from("", "", "schuelerbeobachtung")
+            .context("Failed to determine data directory")?
+            .data_dir()
+            .to_path_buf(),
+    };
     fs::create_dir_all(&dir).ok();
     Ok(dir)
 }
 
-fn secrets_file() -> Result<PathBuf> {
+pub fn secrets_file() -> Result<PathBuf> {
     Ok(data_dir()?.join("secure.json"))
 }
 
@@ -55,6 +101,13 @@ fn secret_set(name: &str, value: &str) -> Result<()> {
     save_secrets(&map)
 }
 
+/// Failed unlock attempts so far and, while locked, until when.
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct UnlockStatus {
+    pub failed_attempts: u32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
 pub struct CryptoManager {
     device_id: String,
 }
@@ -91,14 +144,38 @@ impl CryptoManager {
         Ok(ciphertext.to_string())
     }
 
+    /// Encrypts data this device keeps outside the database, such as
+    /// attachment files, thumbnails and staged imports, with a key that never
+    /// leaves the device.
     pub fn encrypt_bytes(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
-        // Return bytes as-is (encryption disabled)
-        Ok(plaintext.to_vec())
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        let ciphertext = local_cipher()?
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt data"))?;
+
+        let mut encrypted = Vec::with_capacity(LOCAL_DATA_MARKER.len() + NONCE_LEN + ciphertext.len());
+        encrypted.extend_from_slice(LOCAL_DATA_MARKER);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        Ok(encrypted)
     }
 
     pub fn decrypt_bytes(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
-        // Return bytes as-is (assuming they're plaintext)
-        Ok(ciphertext.to_vec())
+        let Some(rest) = ciphertext.strip_prefix(LOCAL_DATA_MARKER) else {
+            return Ok(ciphertext.to_vec());
+        };
+        if rest.len() < NONCE_LEN {
+            return Err(anyhow::anyhow!("Encrypted data is truncated"));
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        local_cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Encrypted data is damaged or from another device"))
+    }
+
+    /// Whether `data` was written by `encrypt_bytes`.
+    pub fn is_encrypted(data: &[u8]) -> bool {
+        data.starts_with(LOCAL_DATA_MARKER)
     }
 
     // Generate a simple "hash" for integrity checking (not cryptographically secure)
@@ -146,6 +223,229 @@ impl CryptoManager {
         status
     }
 
+    // App password, needed for security-relevant actions such as lifting a freeze
+    pub fn has_app_password(&self) -> Result<bool> {
+        Ok(secret_get("app_password")?.is_some())
+    }
+
+    /// An existing password can only be replaced by someone who knows it.
+    /// Returns a new recovery code, to be shown once and printed.
+    pub fn set_app_password(&self, new_password: &str, current_password: Option<&str>) -> Result<String> {
+        if self.has_app_password()? {
+            self.unlock_with_password(current_password.unwrap_or(""))?;
+        }
+        store_app_password(new_password)?;
+        self.issue_recovery_code()
+    }
+
+    /// False if no app password has been set.
+    pub fn verify_app_password(&self, password: &str) -> Result<bool> {
+        let Some(stored) = secret_get("app_password")? else {
+            return Ok(false);
+        };
+        verify_stored_hash(&stored, password).context("Stored app password is corrupt")
+    }
+
+    /// Checks the app password where it unlocks something, with a failure
+    /// counter that survives restarts. The entered password is never stored.
+    pub fn unlock_with_password(&self, password: &str) -> Result<()> {
+        let (failures, locked_until) = attempts("unlock_attempts")?;
+        if let Some(until) = locked_until.filter(|until| *until > Utc::now()) {
+            return Err(anyhow::anyhow!(
+                "Too many wrong passwords; locked until {}",
+                until.to_rfc3339()
+            ));
+        }
+
+        if self.verify_app_password(password)? {
+            set_attempts("unlock_attempts", 0, None)?;
+            return Ok(());
+        }
+
+        let failures = failures + 1;
+        let locked_until = unlock_lockout(failures).map(|lockout| Utc::now() + lockout);
+        set_attempts("unlock_attempts", failures, locked_until)?;
+        Err(match locked_until {
+            Some(until) => anyhow::anyhow!(
+                "App password is incorrect ({} failed attempts); locked until {}",
+                failures,
+                until.to_rfc3339()
+            ),
+            None => anyhow::anyhow!(
+                "App password is incorrect; {} attempts left before a lockout",
+                FREE_UNLOCK_ATTEMPTS - failures
+            ),
+        })
+    }
+
+    pub fn unlock_status(&self) -> Result<UnlockStatus> {
+        let (failed_attempts, locked_until) = attempts("unlock_attempts")?;
+        Ok(UnlockStatus {
+            failed_attempts,
+            locked_until: locked_until.filter(|until| *until > Utc::now()),
+        })
+    }
+
+    pub fn has_recovery_code(&self) -> Result<bool> {
+        Ok(secret_get("recovery_code")?.is_some())
+    }
+
+    /// Replaces any earlier recovery code. Only its hash is kept.
+    fn issue_recovery_code(&self) -> Result<String> {
+        let body: String = (0..RECOVERY_CODE_LEN)
+            .map(|_| RECOVERY_ALPHABET[usize::from(rand::random::<u8>()) % RECOVERY_ALPHABET.len()] as char)
+            .collect();
+        let salt = hex(&rand::random::<[u8; SALT_LEN]>());
+        let hash = hash_password(&body, &salt, PASSWORD_HASH_ROUNDS);
+        secret_set("recovery_code", &format!("{}${}${}", PASSWORD_HASH_ROUNDS, salt, hash))?;
+        set_attempts("recovery_attempts", 0, None)?;
+        Ok(format_recovery_code(&body))
+    }
+
+    /// Sets a new app password for someone who lost theirs. The code is used
+    /// up; the one replacing it is returned.
+    pub fn recover_with_code(&self, code: &str, new_password: &str) -> Result<String> {
+        let (failures, locked_until) = attempts("recovery_attempts")?;
+        if let Some(until) = locked_until.filter(|until| *until > Utc::now()) {
+            return Err(anyhow::anyhow!(
+                "Too many failed attempts; recovery is locked until {}",
+                until.to_rfc3339()
+            ));
+        }
+        let stored = secret_get("recovery_code")?.context("No recovery code has been issued")?;
+        let body = parse_recovery_code(code)?;
+
+        if !verify_stored_hash(&stored, &body).context("Stored recovery code is corrupt")? {
+            let failures = failures + 1;
+            let locked_until = (failures >= MAX_RECOVERY_ATTEMPTS)
+                .then(|| Utc::now() + Duration::minutes(RECOVERY_LOCKOUT_MINUTES));
+            set_attempts("recovery_attempts", failures, locked_until)?;
+            return Err(match locked_until {
+                Some(until) => anyhow::anyhow!(
+                    "Recovery code is incorrect; recovery is locked until {}",
+                    until.to_rfc3339()
+                ),
+                None => anyhow::anyhow!(
+                    "Recovery code is incorrect; {} attempts left",
+                    MAX_RECOVERY_ATTEMPTS - failures
+                ),
+            });
+        }
+
+        store_app_password(new_password)?;
+        // The lost password may have locked unlocking
+        set_attempts("unlock_attempts", 0, None)?;
+        self.issue_recovery_code()
+    }
+
+    /// New token for the companion API; only its hash is kept, so it is shown once.
+    pub fn rotate_api_token(&self) -> Result<String> {
+        let token = hex(&rand::random::<[u8; 32]>());
+        secret_set("companion_api_token", &hex(&Sha256::digest(token.as_bytes())))?;
+        Ok(token)
+    }
+
+    pub fn verify_api_token(&self, token: &str) -> Result<bool> {
+        let Some(stored) = secret_get("companion_api_token")? else {
+            return Ok(false);
+        };
+        Ok(constant_time_eq(&hex(&Sha256::digest(token.as_bytes())), &stored))
+    }
+
+    /// Stable id for `kind`/`id` that cannot be traced back without the key,
+    /// which never leaves this device.
+    pub fn pseudonymous_id(&self, kind: &str, id: i64) -> Result<String> {
+        let key = match secret_get("pseudonym_key")? {
+            Some(key) => key,
+            None => {
+                let key = hex(&rand::random::<[u8; 32]>());
+                secret_set("pseudonym_key", &key)?;
+                key
+            }
+        };
+        let digest = Sha256::new()
+            .chain_update(key.as_bytes())
+            .chain_update(format!("{}:{}", kind, id).as_bytes())
+            .finalize();
+        Ok(hex(&digest[..16]))
+    }
+
+    /// HMAC-SHA256 of `message` under a key that never leaves this device,
+    /// e.g. to tell later whether the database was edited by another program.
+    pub fn integrity_signature(&self, message: &[u8]) -> Result<String> {
+        let key = match secret_get("integrity_key")? {
+            Some(key) => key,
+            None => {
+                let key = hex(&rand::random::<[u8; 32]>());
+                secret_set("integrity_key", &key)?;
+                key
+            }
+        };
+        Ok(hex(&hmac_sha256(key.as_bytes(), message)))
+    }
+
+    /// The X25519 key other devices seal data for, e.g. a handover package.
+    pub fn device_public_key(&self) -> Result<String> {
+        Ok(hex(PublicKey::from(&device_secret()?).as_bytes()))
+    }
+
+    /// Encrypts `plaintext` so only the device holding `recipient_key` can
+    /// read it, as the data leaves the device.
+    pub fn seal_for(&self, recipient_key: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let recipient = PublicKey::from(parse_key(recipient_key)?);
+        let ephemeral = StaticSecret::from(rand::random::<[u8; KEY_LEN]>());
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let cipher = seal_cipher(ephemeral.diffie_hellman(&recipient).as_bytes(), &ephemeral_public, &recipient);
+
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow::anyhow!("Failed to seal data"))?;
+
+        let mut sealed = Vec::with_capacity(KEY_LEN + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(ephemeral_public.as_bytes());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn open_sealed(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        open_sealed_with(&device_secret()?, sealed)
+    }
+
+    /// Encrypts `plaintext` with a key derived from `passphrase`, for
+    /// exports whose recipient has no device key.
+    pub fn encrypt_with_passphrase(&self, passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+        if passphrase.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(anyhow::anyhow!(
+                "The passphrase must have at least {} characters",
+                MIN_PASSWORD_LENGTH
+            ));
+        }
+        let salt = rand::random::<[u8; SALT_LEN]>();
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        let ciphertext = ChaCha20Poly1305::new(&passphrase_key(passphrase, &salt).into())
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt data"))?;
+
+        let mut encrypted = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+        encrypted.extend_from_slice(&salt);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        Ok(encrypted)
+    }
+
+    pub fn decrypt_with_passphrase(&self, passphrase: &str, encrypted: &[u8]) -> Result<Vec<u8>> {
+        if encrypted.len() < SALT_LEN + NONCE_LEN {
+            return Err(anyhow::anyhow!("Encrypted data is truncated"));
+        }
+        let (salt, rest) = encrypted.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        ChaCha20Poly1305::new(&passphrase_key(passphrase, salt).into())
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Wrong passphrase or damaged data"))
+    }
+
     // Device configuration methods
     pub fn get_device_config(&self) -> Result<HashMap<String, String>> {
         let mut config = HashMap::new();
@@ -226,6 +526,222 @@ impl CryptoManager {
     }
 }
 
+fn store_app_password(password: &str) -> Result<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(anyhow::anyhow!(
+            "App password must have at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    let salt = hex(&rand::random::<[u8; SALT_LEN]>());
+    let hash = hash_password(password, &salt, PASSWORD_HASH_ROUNDS);
+    secret_set("app_password", &format!("{}${}${}", PASSWORD_HASH_ROUNDS, salt, hash))
+}
+
+/// Checks `candidate` against a `rounds$salt$hash` entry.
+fn verify_stored_hash(stored: &str, candidate: &str) -> Result<bool> {
+    let mut parts = stored.splitn(3, '$');
+    let (Some(rounds), Some(salt), Some(hash)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(anyhow::anyhow!("Missing hash parts"));
+    };
+    let rounds: u32 = rounds.parse().context("Invalid number of rounds")?;
+    Ok(constant_time_eq(&hash_password(candidate, salt, rounds), hash))
+}
+
+fn recovery_check(body: &str) -> String {
+    Sha256::digest(body.as_bytes())[..RECOVERY_CHECK_LEN]
+        .iter()
+        .map(|b| RECOVERY_ALPHABET[usize::from(*b) % RECOVERY_ALPHABET.len()] as char)
+        .collect()
+}
+
+/// Groups of four, e.g. `K7QM-2XPA-…`, ending in the check characters.
+fn format_recovery_code(body: &str) -> String {
+    let full = format!("{}{}", body, recovery_check(body));
+    full.as_bytes()
+        .chunks(4)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Accepts the code as typed from paper; returns it without the check characters.
+fn parse_recovery_code(code: &str) -> Result<String> {
+    let code: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if code.len() != RECOVERY_CODE_LEN + RECOVERY_CHECK_LEN || !code.bytes().all(|b| RECOVERY_ALPHABET.contains(&b)) {
+        return Err(anyhow::anyhow!("This is not a recovery code; check for typos"));
+    }
+    let (body, check) = code.split_at(RECOVERY_CODE_LEN);
+    if recovery_check(body) != check {
+        return Err(anyhow::anyhow!("The recovery code contains a typo"));
+    }
+    Ok(body.to_string())
+}
+
+/// Failed attempts and, after too many, until when the check is locked.
+fn attempts(name: &str) -> Result<(u32, Option<DateTime<Utc>>)> {
+    let stored = secret_get(name)?.unwrap_or_default();
+    let (failures, locked_until) = stored.split_once('$').unwrap_or(("0", ""));
+    Ok((
+        failures.parse().unwrap_or(0),
+        DateTime::parse_from_rfc3339(locked_until).ok().map(|until| until.with_timezone(&Utc)),
+    ))
+}
+
+fn set_attempts(name: &str, failures: u32, locked_until: Option<DateTime<Utc>>) -> Result<()> {
+    secret_set(
+        name,
+        &format!("{}${}", failures, locked_until.map(|u| u.to_rfc3339()).unwrap_or_default()),
+    )
+}
+
+fn unlock_lockout(failures: u32) -> Option<Duration> {
+    let exponent = failures.checked_sub(FREE_UNLOCK_ATTEMPTS)?;
+    let seconds = UNLOCK_LOCKOUT_SECONDS.saturating_mul(1i64 << exponent.min(32));
+    Some(Duration::seconds(seconds.min(MAX_UNLOCK_LOCKOUT_SECONDS)))
+}
+
+fn hash_password(password: &str, salt: &str, rounds: u32) -> String {
+    let mut digest = Sha256::new()
+        .chain_update(salt.as_bytes())
+        .chain_update(password.as_bytes())
+        .finalize();
+    for _ in 1..rounds {
+        digest = Sha256::new().chain_update(digest).chain_update(salt.as_bytes()).finalize();
+    }
+    hex(&digest)
+}
+
+/// Compares without an early exit, so timing does not reveal matching prefixes.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_key(key: &str) -> Result<[u8; KEY_LEN]> {
+    let key = key.trim();
+    if key.len() != KEY_LEN * 2 || !key.is_ascii() {
+        return Err(anyhow::anyhow!("A device key has {} hex digits", KEY_LEN * 2));
+    }
+    let mut bytes = [0u8; KEY_LEN];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&key[i * 2..i * 2 + 2], 16).context("Invalid device key")?;
+    }
+    Ok(bytes)
+}
+
+/// A key pair held outside the app, e.g. by the school for escrow backups:
+/// (public key, private key) in hex.
+pub fn generate_key_pair() -> (String, String) {
+    let secret = StaticSecret::from(rand::random::<[u8; KEY_LEN]>());
+    (hex(PublicKey::from(&secret).as_bytes()), hex(secret.as_bytes()))
+}
+
+pub fn public_key_of(private_key: &str) -> Result<String> {
+    Ok(hex(PublicKey::from(&StaticSecret::from(parse_key(private_key)?)).as_bytes()))
+}
+
+/// Short form of a public key for audit entries and for comparing by eye.
+pub fn key_fingerprint(public_key: &str) -> Result<String> {
+    Ok(hex(&Sha256::digest(parse_key(public_key)?)[..8]))
+}
+
+/// Opens data sealed for a key pair that is not the device key.
+pub fn open_sealed_with_key(private_key: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+    open_sealed_with(&StaticSecret::from(parse_key(private_key)?), sealed)
+}
+
+fn open_sealed_with(secret: &StaticSecret, sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < KEY_LEN + NONCE_LEN {
+        return Err(anyhow::anyhow!("Sealed data is truncated"));
+    }
+    let (ephemeral_public, rest) = sealed.split_at(KEY_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let ephemeral_public = PublicKey::from(<[u8; KEY_LEN]>::try_from(ephemeral_public)?);
+
+    let cipher = seal_cipher(
+        secret.diffie_hellman(&ephemeral_public).as_bytes(),
+        &ephemeral_public,
+        &PublicKey::from(secret),
+    );
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Sealed data is damaged or addressed to another key"))
+}
+
+/// Created on first use; never leaves this device.
+fn device_secret() -> Result<StaticSecret> {
+    let key = match secret_get("device_key")? {
+        Some(key) => key,
+        None => {
+            let key = hex(&rand::random::<[u8; KEY_LEN]>());
+            secret_set("device_key", &key)?;
+            key
+        }
+    };
+    Ok(StaticSecret::from(parse_key(&key)?))
+}
+
+/// Created on first use like the device key, and moved with the data root.
+fn local_cipher() -> Result<ChaCha20Poly1305> {
+    let key = match secret_get("local_data_key")? {
+        Some(key) => key,
+        None => {
+            let key = hex(&rand::random::<[u8; KEY_LEN]>());
+            secret_set("local_data_key", &key)?;
+            key
+        }
+    };
+    Ok(ChaCha20Poly1305::new(&parse_key(&key)?.into()))
+}
+
+/// Salted and iterated like the app password, to slow down guessing.
+fn passphrase_key(passphrase: &str, salt: &[u8]) -> [u8; KEY_LEN] {
+    let mut digest = Sha256::new()
+        .chain_update(SEAL_CONTEXT)
+        .chain_update(salt)
+        .chain_update(passphrase.as_bytes())
+        .finalize();
+    for _ in 1..PASSWORD_HASH_ROUNDS {
+        digest = Sha256::new().chain_update(digest).chain_update(salt).finalize();
+    }
+    let mut key = [0u8; KEY_LEN];
+    key.copy_from_slice(&digest);
+    key
+}
+
+/// HMAC as in RFC 2104; a prefixed key alone would allow length extension.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_LEN: usize = 64;
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let inner_key: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let outer_key: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    let inner = Sha256::new().chain_update(&inner_key).chain_update(message).finalize();
+    Sha256::new().chain_update(&outer_key).chain_update(inner).finalize().into()
+}
+
+fn seal_cipher(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> ChaCha20Poly1305 {
+    let key = Sha256::new()
+        .chain_update(SEAL_CONTEXT)
+        .chain_update(shared)
+        .chain_update(ephemeral.as_bytes())
+        .chain_update(recipient.as_bytes())
+        .finalize();
+    ChaCha20Poly1305::new(&key)
+}
+
 impl Default for CryptoManager {
     fn default() -> Self {
         Self::new().expect("Failed to create CryptoManager")
@@ -285,6 +801,101 @@ mod tests {
         assert_eq!(decrypted, plaintext);
     }
 
+    #[test]
+    fn test_hmac_matches_rfc_4231() {
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(hex(&mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        // Keys longer than a block are hashed first
+        let mac = hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First");
+        assert_eq!(hex(&mac), "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+    }
+
+    #[test]
+    fn test_app_password() {
+        let _temp_dir = setup_test_env();
+
+        let crypto = CryptoManager::new().unwrap();
+        assert!(!crypto.verify_app_password("").unwrap());
+        assert!(crypto.set_app_password("kurz", None).is_err());
+
+        crypto.set_app_password("Klassenbuch1", None).unwrap();
+        assert!(crypto.has_app_password().unwrap());
+        assert!(crypto.verify_app_password("Klassenbuch1").unwrap());
+        assert!(!crypto.verify_app_password("klassenbuch1").unwrap());
+
+        // Replacing needs the current password
+        assert!(crypto.set_app_password("Zeugnis2024", None).is_err());
+        crypto.set_app_password("Zeugnis2024", Some("Klassenbuch1")).unwrap();
+        assert!(crypto.verify_app_password("Zeugnis2024").unwrap());
+    }
+
+    #[test]
+    fn test_unlock_backs_off_exponentially() {
+        let _temp_dir = setup_test_env();
+
+        let crypto = CryptoManager::new().unwrap();
+        crypto.set_app_password("Klassenbuch1", None).unwrap();
+        for _ in 1..FREE_UNLOCK_ATTEMPTS {
+            assert!(crypto.unlock_with_password("falsch").unwrap_err().to_string().contains("left"));
+        }
+        crypto.unlock_with_password("Klassenbuch1").unwrap();
+        assert_eq!(crypto.unlock_status().unwrap(), UnlockStatus { failed_attempts: 0, locked_until: None });
+
+        for _ in 0..FREE_UNLOCK_ATTEMPTS {
+            assert!(crypto.unlock_with_password("falsch").is_err());
+        }
+        // Locked: even the right password is refused, and the lock persists
+        let err = crypto.unlock_with_password("Klassenbuch1").unwrap_err();
+        assert!(err.to_string().contains("locked until"));
+        let status = CryptoManager::new().unwrap().unlock_status().unwrap();
+        assert_eq!(status.failed_attempts, FREE_UNLOCK_ATTEMPTS);
+        assert!(status.locked_until.is_some());
+
+        assert_eq!(unlock_lockout(FREE_UNLOCK_ATTEMPTS - 1), None);
+        assert_eq!(unlock_lockout(FREE_UNLOCK_ATTEMPTS), Some(Duration::seconds(30)));
+        assert_eq!(unlock_lockout(FREE_UNLOCK_ATTEMPTS + 2), Some(Duration::seconds(120)));
+        assert_eq!(unlock_lockout(100), Some(Duration::seconds(MAX_UNLOCK_LOCKOUT_SECONDS)));
+    }
+
+    #[test]
+    fn test_recovery_code() {
+        let _temp_dir = setup_test_env();
+
+        let crypto = CryptoManager::new().unwrap();
+        assert!(crypto.recover_with_code("AAAA-AAAA-AAAA-AAAA-AAAA", "Vergessen1").is_err());
+        let code = crypto.set_app_password("Klassenbuch1", None).unwrap();
+        assert!(crypto.has_recovery_code().unwrap());
+        assert_eq!(code.len(), 24);
+
+        // A typo is caught by the check characters and costs no attempt
+        let typo = format!("{}{}", if code.starts_with('A') { 'B' } else { 'A' }, &code[1..]);
+        assert!(crypto.recover_with_code(&typo, "Vergessen1").unwrap_err().to_string().contains("typo"));
+        assert_eq!(attempts("recovery_attempts").unwrap().0, 0);
+
+        // As typed from paper
+        let new_code = crypto.recover_with_code(&code.to_lowercase().replace('-', " "), "Vergessen1").unwrap();
+        assert!(crypto.verify_app_password("Vergessen1").unwrap());
+        assert_ne!(new_code, code);
+        // Used up
+        assert!(crypto.recover_with_code(&code, "Nochmal123").is_err());
+        assert_eq!(attempts("recovery_attempts").unwrap().0, 1);
+    }
+
+    #[test]
+    fn test_recovery_locks_after_failed_attempts() {
+        let _temp_dir = setup_test_env();
+
+        let crypto = CryptoManager::new().unwrap();
+        let code = crypto.set_app_password("Klassenbuch1", None).unwrap();
+        let wrong = format_recovery_code("ABCDEFGHJKLMNPQR");
+        for _ in 0..MAX_RECOVERY_ATTEMPTS {
+            assert!(crypto.recover_with_code(&wrong, "Vergessen1").is_err());
+        }
+        let err = crypto.recover_with_code(&code, "Vergessen1").unwrap_err();
+        assert!(err.to_string().contains("locked"));
+        assert!(crypto.verify_app_password("Klassenbuch1").unwrap());
+    }
+
     #[test]
     fn test_encrypt_decrypt_bytes() {
         let _temp_dir = setup_test_env();
@@ -293,10 +904,45 @@ mod tests {
         let original_data = b"Binary data for testing";
 
         let encrypted = crypto.encrypt_bytes(original_data).unwrap();
-        assert_eq!(encrypted, original_data);
+        assert!(CryptoManager::is_encrypted(&encrypted));
+        assert!(!encrypted.windows(original_data.len()).any(|w| w == original_data));
 
         let decrypted = crypto.decrypt_bytes(&encrypted).unwrap();
         assert_eq!(decrypted, original_data);
+
+        // Written before the data was encrypted
+        assert_eq!(crypto.decrypt_bytes(original_data).unwrap(), original_data);
+        let mut damaged = encrypted.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(crypto.decrypt_bytes(&damaged).is_err());
+    }
+
+    #[test]
+    fn test_sealed_data_opens_only_for_recipient() {
+        let _temp_dir = setup_test_env();
+
+        let crypto = CryptoManager::new().unwrap();
+        let own_key = crypto.device_public_key().unwrap();
+        assert_eq!(own_key, crypto.device_public_key().unwrap());
+
+        let sealed = crypto.seal_for(&own_key, b"Klasse 5a").unwrap();
+        assert!(!sealed.windows(9).any(|w| w == b"Klasse 5a"));
+        assert_eq!(crypto.open_sealed(&sealed).unwrap(), b"Klasse 5a");
+
+        let other_key = hex(&[9u8; KEY_LEN]);
+        assert!(crypto.open_sealed(&crypto.seal_for(&other_key, b"Klasse 5a").unwrap()).is_err());
+        assert!(crypto.seal_for("abc", b"Klasse 5a").is_err());
+    }
+
+    #[test]
+    fn test_passphrase_encryption() {
+        let _temp_dir = setup_test_env();
+
+        let crypto = CryptoManager::new().unwrap();
+        let encrypted = crypto.encrypt_with_passphrase("Schulhof2024", b"Klasse 5a").unwrap();
+        assert_eq!(crypto.decrypt_with_passphrase("Schulhof2024", &encrypted).unwrap(), b"Klasse 5a");
+        assert!(crypto.decrypt_with_passphrase("Schulhof2025", &encrypted).is_err());
+        assert!(crypto.encrypt_with_passphrase("kurz", b"Klasse 5a").is_err());
     }
 
     #[test]
@@ -0,0 +1,249 @@
+use crate::audit::AuditLogger;
+use crate::database::Database;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "config.json";
+
+/// `config.json` always stays in the platform app data directory, since it is
+/// what points to a relocated root.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct AppConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_data_root: Option<String>,
+    /// Files left behind by a relocation, removed on the next start once
+    /// nothing holds them open.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_cleanup: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DataPaths {
+    pub root: PathBuf,
+    pub database: PathBuf,
+    pub audit: PathBuf,
+    /// `None` while the secrets stay in the per-user data directory.
+    pub secrets: Option<PathBuf>,
+}
+
+pub fn load_config(app_data_dir: &Path) -> AppConfig {
+    std::fs::read_to_string(app_data_dir.join(CONFIG_FILE))
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_config(app_data_dir: &Path, config: &AppConfig) -> Result<()> {
+    std::fs::create_dir_all(app_data_dir).context("Failed to create config directory")?;
+    std::fs::write(app_data_dir.join(CONFIG_FILE), serde_json::to_string_pretty(config)?)
+        .context("Failed to save configuration")
+}
+
+pub fn resolve(app_data_dir: &Path, config: &AppConfig) -> DataPaths {
+    let root = config
+        .app_data_root
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| app_data_dir.to_path_buf());
+    DataPaths {
+        database: config
+            .database_path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| root.join("observations.db")),
+        audit: root.join("audit.db"),
+        secrets: config.app_data_root.as_ref().map(|_| root.join("secure.json")),
+        root,
+    }
+}
+
+/// Removes what a relocation left behind. Runs before the databases are opened.
+pub fn finish_pending_cleanup(app_data_dir: &Path) -> Result<()> {
+    let mut config = load_config(app_data_dir);
+    if config.pending_cleanup.is_empty() {
+        return Ok(());
+    }
+
+    let paths = resolve(app_data_dir, &config);
+    let mut remaining = Vec::new();
+    for path in config.pending_cleanup.drain(..) {
+        let path = PathBuf::from(path);
+        // Never remove what the current configuration uses
+        if path == paths.database || path == paths.audit || Some(&path) == paths.secrets.as_ref() {
+            continue;
+        }
+        let removed = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match removed {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                eprintln!("Failed to remove {}: {}", path.display(), e);
+                remaining.push(path.to_string_lossy().to_string());
+            }
+        }
+    }
+    config.pending_cleanup = remaining;
+    save_config(app_data_dir, &config)
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn with_sqlite_companions(path: &Path) -> Vec<PathBuf> {
+    ["", "-wal", "-shm"]
+        .iter()
+        .map(|suffix| {
+            let mut file = path.as_os_str().to_os_string();
+            file.push(suffix);
+            PathBuf::from(file)
+        })
+        .collect()
+}
+
+/// Copies the database with its attachments and snapshots, the audit log and
+/// the secrets to `new_root` and points the configuration there. The old files
+/// are still open, so they are only removed on the next start; the app has to
+/// restart for the new root to take effect.
+pub async fn relocate(
+    app_data_dir: &Path,
+    db: &Database,
+    audit: &AuditLogger,
+    current_secrets: &Path,
+    new_root: &Path,
+) -> Result<DataPaths> {
+    if !new_root.is_absolute() {
+        return Err(anyhow::anyhow!("The data directory must be an absolute path"));
+    }
+    if !new_root.is_dir() {
+        return Err(anyhow::anyhow!("The specified directory does not exist"));
+    }
+
+    let mut config = load_config(app_data_dir);
+    let current = resolve(app_data_dir, &config);
+    config.app_data_root = Some(new_root.to_string_lossy().to_string());
+    config.database_path = None;
+    let target = resolve(app_data_dir, &config);
+    let target_secrets = target.secrets.clone().context("Relocated root has no secrets path")?;
+
+    if new_root == current.root {
+        return Err(anyhow::anyhow!("Data is already stored in this directory"));
+    }
+    for path in [&target.database, &target.audit, &target_secrets] {
+        if path.exists() {
+            return Err(anyhow::anyhow!(
+                "{} already exists; choose an empty directory",
+                path.display()
+            ));
+        }
+    }
+
+    let moved_dirs = [
+        (db.attachments_dir(), target.root.join("attachments")),
+        (db.thumbnails_dir(), target.root.join("thumbnails")),
+        (db.snapshots_dir(), target.root.join("snapshots")),
+    ];
+    let copied = async {
+        db.write_copy(&target.database).await?;
+        audit.write_copy(&target.audit).await?;
+        if current_secrets.exists() {
+            std::fs::copy(current_secrets, &target_secrets).context("Failed to copy secrets")?;
+        }
+        for (from, to) in &moved_dirs {
+            if from.is_dir() {
+                copy_dir_all(from, to).with_context(|| format!("Failed to copy {}", from.display()))?;
+            }
+        }
+        anyhow::Ok(())
+    }
+    .await;
+
+    // A half-finished copy is removed again, the old location stays in use
+    if let Err(e) = copied {
+        for path in [&target.database, &target.audit, &target_secrets] {
+            let _ = std::fs::remove_file(path);
+        }
+        for (_, to) in &moved_dirs {
+            let _ = std::fs::remove_dir_all(to);
+        }
+        return Err(e);
+    }
+
+    config.pending_cleanup = with_sqlite_companions(&current.database)
+        .into_iter()
+        .chain(with_sqlite_companions(&current.audit))
+        .chain(std::iter::once(current_secrets.to_path_buf()))
+        .chain(moved_dirs.iter().map(|(from, _)| from.clone()))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    save_config(app_data_dir, &config)?;
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::CryptoManager;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_relocate_moves_all_data_together() {
+        let temp_dir = TempDir::new().unwrap();
+        let app_data_dir = temp_dir.path().join("app");
+        let new_root = temp_dir.path().join("encrypted");
+        std::fs::create_dir_all(&new_root).unwrap();
+
+        let paths = resolve(&app_data_dir, &load_config(&app_data_dir));
+        let crypto = Arc::new(CryptoManager::new().unwrap());
+        let db = Database::new(&paths.database, crypto.clone()).await.unwrap();
+        let audit = AuditLogger::new(&paths.audit).await.unwrap();
+        let secrets = app_data_dir.join("secure.json");
+        std::fs::write(&secrets, "{}").unwrap();
+
+        db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        db.create_snapshot("vorher").await.unwrap();
+        audit.log_action("create", "class", 1, 1, None).await.unwrap();
+        std::fs::create_dir_all(db.thumbnails_dir()).unwrap();
+        std::fs::write(db.thumbnails_dir().join("cached-256.jpg"), b"thumbnail").unwrap();
+
+        assert!(relocate(&app_data_dir, &db, &audit, &secrets, Path::new("relative")).await.is_err());
+        let target = relocate(&app_data_dir, &db, &audit, &secrets, &new_root).await.unwrap();
+        assert!(relocate(&app_data_dir, &db, &audit, &secrets, &new_root).await.is_err());
+
+        // The next start opens the new root and removes the old files
+        drop(db);
+        drop(audit);
+        finish_pending_cleanup(&app_data_dir).unwrap();
+        let config = load_config(&app_data_dir);
+        assert!(config.pending_cleanup.is_empty());
+        let paths = resolve(&app_data_dir, &config);
+        assert_eq!(paths.database, target.database);
+        assert!(!app_data_dir.join("observations.db").exists());
+        assert!(!secrets.exists());
+        assert!(new_root.join("secure.json").exists());
+        assert!(new_root.join("thumbnails").join("cached-256.jpg").exists());
+        assert!(!app_data_dir.join("thumbnails").exists());
+
+        let db = Database::new(&paths.database, crypto).await.unwrap();
+        assert_eq!(db.get_classes().await.unwrap().len(), 1);
+        assert_eq!(db.list_snapshots().await.unwrap().len(), 1);
+    }
+}
@@ -1,15 +1,897 @@
+use crate::attachment_sync::{AttachmentPolicy, SyncedAttachment};
+use crate::backup_stream::BackupRecord;
 use crate::crypto::CryptoManager;
-use crate::{Class, Observation, Student};
+use crate::manifest::{self, ExportManifest};
+use crate::operations::CancellationToken;
+use crate::{
+    AnonymizationTerm, Attachment, Class, ClassAgreement, ClassLayout, DeviceSyncInfo, ErasureRequest, Guardian, Job, Observation, ObservationSession,
+    ReportArtifact, Rubric, Student, StudentGoal, StudentTransfer, SyncHistoryEntry, SyncStatus, User,
+};
 use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
 // use chrono::Utc; // Temporarily unused
 use sha2::{Digest, Sha256};
-use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite};
-use std::path::Path;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+/// Imported timestamps further ahead of the local clock than this are reported
+/// as clock skew.
+const MAX_CLOCK_SKEW_MINUTES: i64 = 60;
+
+/// Scope of a changeset export when the user does not choose one.
+pub const DEFAULT_CHANGESET_DAYS_BACK: u32 = 30;
+
+const ATTACHMENT_STORAGE_SETTING: &str = "attachment_storage";
+
+/// Days after creation from which observations are read-only; 0 or unset disables the lock.
+const OBSERVATION_LOCK_SETTING: &str = "observation_lock_days";
+
+/// How long an admin unlock of a read-only observation stays valid.
+const OBSERVATION_UNLOCK_HOURS: i64 = 24;
+
+pub const USER_ROLES: [&str; 2] = ["teacher", "admin"];
+pub const GOAL_STATUSES: [&str; 3] = ["active", "achieved", "dropped"];
+/// Statuses `update_student` can set; "deleted" is only set by the erasure.
+pub const STUDENT_STATUSES: [&str; 2] = ["active", "inactive"];
+
+const MAX_CONNECTIONS: u32 = 5;
+
+/// Staged changesets with their observations counted by classification.
+const QUARANTINED_IMPORTS_QUERY: &str = r#"
+    SELECT q.id, q.kind, q.file_path, q.operation_id, q.source_device_id, q.problems, q.created_at,
+           COUNT(CASE WHEN o.status = 'new' THEN 1 END) AS new_observations,
+           COUNT(CASE WHEN o.status = 'updated' THEN 1 END) AS updated_observations,
+           COUNT(CASE WHEN o.status = 'unchanged' THEN 1 END) AS unchanged_observations,
+           COUNT(CASE WHEN o.status = 'duplicate' THEN 1 END) AS duplicate_observations,
+           COUNT(CASE WHEN o.status = 'invalid' THEN 1 END) AS invalid_observations
+    FROM quarantined_imports q
+    LEFT JOIN quarantined_observations o ON o.import_id = q.id
+"#;
+
+/// Sessions with the number of observations grouped under them that the
+/// viewer may see; binds `VISIBILITY_PRIVATE` and the viewer first.
+const OBSERVATION_SESSIONS_QUERY: &str = r#"
+    SELECT os.id, os.class_id, os.title, os.subject, os.started_at, os.ended_at,
+           COUNT(o.id) AS observation_count
+    FROM observation_sessions os
+    LEFT JOIN observations o ON o.session_id = os.id AND (o.visibility != ? OR o.author_id = ?)
+"#;
+
+/// Finished jobs kept in the history.
+const MAX_JOB_HISTORY: i64 = 200;
+
+/// Backup records parsed ahead of the import and inserted per batch.
+const IMPORT_CHUNK_SIZE: usize = 500;
+
+/// Connections reserved for exports and reports, see `Database::reader`.
+const MAX_READ_CONNECTIONS: u32 = 2;
+
+/// How long SQLite itself waits for a lock before reporting SQLITE_BUSY.
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Attempts for write transactions that still hit SQLITE_BUSY, e.g. when a
+/// read transaction cannot be upgraded because another connection committed.
+const BUSY_RETRY_ATTEMPTS: u32 = 4;
+
+/// Tables and columns covered by `critical_tables_digest`: the records a
+/// change outside the app would matter for.
+const INTEGRITY_COLUMNS: [(&str, &[&str]); 7] = [
+    ("classes", &["id", "name", "school_year"]),
+    ("students", &["id", "class_id", "first_name", "last_name", "status"]),
+    ("guardians", &["id", "student_id", "name", "contact", "relationship", "consent_reference"]),
+    (
+        "observations",
+        &[
+            "id", "student_id", "author_id", "category", "text", "tags", "created_at", "updated_at", "visibility", "subject",
+            "cosigned_by", "cosigned_at",
+        ],
+    ),
+    ("users", &["id", "name", "role"]),
+    ("erasure_requests", &["id", "student_id", "requested_by", "reason", "status", "decided_by", "execute_at"]),
+    ("class_agreements", &["id", "class_id", "kind", "collected_on", "valid_until"]),
+];
+
 pub struct Database {
     pool: Pool<Sqlite>,
+    read_pool: Pool<Sqlite>,
+    /// The write pool while processing is frozen; `pool` is the read pool then.
+    frozen_pool: Option<Pool<Sqlite>>,
     crypto: Arc<CryptoManager>,
+    db_path: PathBuf,
+    busy_retries: Arc<AtomicU64>,
+    busy_errors: Arc<AtomicU64>,
+}
+
+/// See `Database::job_progress_recorder`.
+pub struct JobProgressRecorder {
+    pool: Pool<Sqlite>,
+    job_id: i64,
+}
+
+impl JobProgressRecorder {
+    /// `progress` is between 0 and 1; finished jobs keep theirs.
+    pub async fn record(&self, progress: f64) -> Result<()> {
+        sqlx::query("UPDATE jobs SET progress = ? WHERE id = ? AND status = 'running'")
+            .bind(progress.clamp(0.0, 1.0))
+            .bind(self.job_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to update job progress")?;
+        Ok(())
+    }
+}
+
+/// Counts for the sidebar; observations are counted if created after `since`
+/// and visible to the viewer.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct SidebarBadges {
+    pub new_by_class: BTreeMap<i64, i64>,
+    pub new_by_student: BTreeMap<i64, i64>,
+    pub pending_sync_changes: i64,
+    pub open_erasure_requests: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DatabaseHealth {
+    pub pool_size: u32,
+    pub idle_connections: usize,
+    pub max_connections: u32,
+    pub read_pool_size: u32,
+    pub read_idle_connections: usize,
+    pub busy_timeout_ms: u64,
+    pub busy_retries: u64, // Write transactions retried after SQLITE_BUSY
+    pub busy_errors: u64,  // Write transactions that stayed busy on every attempt
+    pub journal_mode: String,
+    pub wal_bytes: u64,
+}
+
+/// True for SQLITE_BUSY and SQLITE_LOCKED, including their extended codes.
+fn is_busy_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| match cause.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(db_error)) => db_error
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| matches!(code & 0xff, 5 | 6)),
+        Some(sqlx::Error::PoolTimedOut) => true,
+        _ => false,
+    })
+}
+
+/// Point-in-time copy of the database file, stored next to it in `snapshots/`.
+/// Metadata lives in a sidecar JSON file so it survives rolling the database back.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub label: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ChangesetImportReport {
+    pub operation_id: String,
+    pub already_applied_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub imported: i64,
+    pub updated: i64,
+    pub skipped: i64,
+    pub deletions_applied: i64,
+    pub transfers_applied: i64,
+    pub guardians_applied: i64,
+    pub students_updated: i64, // Date of birth, notes or external id
+    pub layouts_applied: i64,
+    pub retractions_received: i64,
+    pub attachments_received: i64,
+    pub pending_hard_deletions: Vec<String>,
+    pub warnings: Vec<String>,
+    pub trusted_timestamp: Option<crate::trusted_timestamp::TrustedTimestamp>,
+}
+
+/// Deletion record carried in changesets so removals propagate between devices.
+/// Devices number their records independently, so the receiving device finds
+/// the record by its uuid; `entity_id` is the sender's id.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Tombstone {
+    pub entity_type: String,   // "class", "student", "observation" or "guardian"
+    pub entity_id: i64,
+    #[serde(default)]
+    pub entity_uuid: Option<String>, // None in changesets written before uuids
+    pub deletion_type: String, // "soft" or "hard"
+    pub deleted_at: chrono::DateTime<chrono::Utc>,
+    pub logical_clock: i64,
+    pub device_id: String,
+}
+
+/// An observation that was corrected or deleted after a changeset had already
+/// carried it to another device. The next changeset carries the retraction.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct Retraction {
+    pub observation_id: i64,
+    pub student_id: i64,
+    pub label: String,       // Category and date, never the text
+    pub reason: String,      // "corrected" or "deleted"
+    pub exported_in: String, // JSON list of the changeset operation ids
+    pub retracted_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub carried_in: Option<String>,
+}
+
+/// Provenance of a class absorbed from another teacher's handover package.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct HandoverImport {
+    pub id: i64,
+    pub class_id: i64,
+    pub class_name: String,
+    pub source_device_id: String,
+    pub package_hash: String,
+    pub package_created_at: chrono::DateTime<chrono::Utc>,
+    pub students: i64,
+    pub observations: i64,
+    pub attachments: i64,
+    pub imported_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// An observation that names another student of the same class.
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct MentionWarning {
+    pub observation_id: i64,
+    pub student_id: i64,
+    pub class_id: i64,
+    pub mentioned_student_id: i64,
+    pub mentioned_name: String,
+    pub matched_text: String,
+    pub text: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub detected_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A changeset assembled but not yet recorded as exported.
+struct ChangesetBuild {
+    data: Vec<u8>,
+    operation_id: String,
+    observation_ids: Vec<i64>,
+    summary: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ChangesetSizeEstimate {
+    pub days_back: u32,
+    pub observations: usize,
+    pub plain_bytes: usize,
+    pub compressed_bytes: usize,
+}
+
+/// Private observations are shown to their author only: left out of search,
+/// reports, handover packages and xAPI exports for everyone else. Changesets
+/// still carry them, as the author's other devices need them, and a GDPR
+/// access request covers them like any other data.
+pub const VISIBILITY_PRIVATE: &str = "private";
+pub const VISIBILITY_TEAM: &str = "team";
+pub const OBSERVATION_VISIBILITIES: [&str; 2] = [VISIBILITY_TEAM, VISIBILITY_PRIVATE];
+
+fn validate_visibility(visibility: &str) -> Result<()> {
+    if !OBSERVATION_VISIBILITIES.contains(&visibility) {
+        return Err(anyhow::anyhow!(
+            "Visibility must be one of: {}",
+            OBSERVATION_VISIBILITIES.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// One entry of `create_observations_batch`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct NewObservation {
+    pub student_id: i64,
+    pub category: String,
+    pub text: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Only set for notes imported from elsewhere; new observations are dated now.
+    #[serde(default)]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub local_only: bool,
+    /// The author's default applies when not given.
+    #[serde(default)]
+    pub visibility: Option<String>,
+    /// One of the managed subjects; the running session's subject applies when not given.
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Set when the author writes down what a colleague observed: the
+    /// colleague's user id, or a name for someone without an account. The
+    /// author stays the one who recorded it.
+    #[serde(default)]
+    pub reported_by_user_id: Option<i64>,
+    #[serde(default)]
+    pub reported_by: Option<String>,
+}
+
+/// Changes of `update_observation`; fields not given keep their value.
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct ObservationEdit {
+    pub text: Option<String>,
+    pub category: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+/// Changes of `update_student`; fields not given keep their value, an
+/// empty string clears an optional field.
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct StudentEdit {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub status: Option<String>,
+    pub class_id: Option<i64>,
+    pub date_of_birth: Option<String>, // YYYY-MM-DD
+    pub notes: Option<String>,
+    pub external_id: Option<String>,
+}
+
+/// Optional fields of a new student, see `create_student_with_details`.
+#[derive(Debug, Default, Clone, PartialEq, serde::Deserialize)]
+pub struct StudentDetails {
+    pub date_of_birth: Option<chrono::NaiveDate>,
+    pub notes: Option<String>,
+    pub external_id: Option<String>,
+}
+
+/// Guardian fields that can be marked sensitive and thereby kept out of sync.
+pub const GUARDIAN_FIELDS: [&str; 4] = ["name", "contact", "relationship", "consent_reference"];
+
+/// Input of `create_guardian` and `update_guardian`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct GuardianInput {
+    pub name: String,
+    pub contact: Option<String>,
+    pub relationship: String,
+    pub consent_reference: Option<String>,
+    #[serde(default)]
+    pub sensitive_fields: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StudentSort {
+    #[default]
+    Name,
+    LastObservation, // Most recently observed first, never observed last
+}
+
+/// Sorting and filtering for `list_students`, done in SQL so large schools
+/// need not sort in the webview. Deleted students are never listed.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct StudentListOptions {
+    #[serde(default)]
+    pub sort: StudentSort,
+    /// Lists the students class by class, sorted within each class
+    #[serde(default)]
+    pub group_by_class: bool,
+    pub status: Option<String>,
+}
+
+/// What to take from an older full backup in `restore_from_backup`.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct RestoreSelection {
+    /// Classes to restore together with their students and observations
+    #[serde(default)]
+    pub class_ids: Vec<i64>,
+    /// Also restore students that were deleted locally since the backup was made
+    #[serde(default)]
+    pub deleted_students: bool,
+    /// Replace local records that differ from the backup instead of keeping them
+    #[serde(default)]
+    pub overwrite_conflicts: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RestoreItem {
+    pub entity_type: String,
+    pub entity_id: i64,
+    pub label: String,
+    pub action: String, // "create", "undelete", "overwrite", "conflict", "unchanged" or "skipped"
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RestoreReport {
+    pub applied: bool,
+    pub items: Vec<RestoreItem>,
+}
+
+impl RestoreReport {
+    fn push(&mut self, entity_type: &str, entity_id: i64, label: String, action: &str) {
+        self.items.push(RestoreItem {
+            entity_type: entity_type.to_string(),
+            entity_id,
+            label,
+            action: action.to_string(),
+        });
+    }
+
+    pub fn count(&self, action: &str) -> usize {
+        self.items.iter().filter(|item| item.action == action).count()
+    }
+}
+
+impl std::fmt::Display for RestoreReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} created, {} undeleted, {} overwritten, {} conflicts kept local, {} unchanged, {} skipped",
+            if self.applied { "Restored" } else { "Restore preview" },
+            self.count("create"),
+            self.count("undelete"),
+            self.count("overwrite"),
+            self.count("conflict"),
+            self.count("unchanged"),
+            self.count("skipped")
+        )
+    }
+}
+
+/// Entity types carried in changesets and tombstones, with their tables.
+const SYNCED_ENTITY_TABLES: [(&str, &str); 4] = [
+    ("class", "classes"),
+    ("student", "students"),
+    ("observation", "observations"),
+    ("guardian", "guardians"),
+];
+
+fn entity_table(entity_type: &str) -> Option<&'static str> {
+    SYNCED_ENTITY_TABLES
+        .iter()
+        .find(|(t, _)| *t == entity_type)
+        .map(|(_, table)| *table)
+}
+
+/// Identifies a record on every device it is copied to. Records from files
+/// that predate uuids get a new one.
+fn record_uuid(existing: Option<&str>) -> String {
+    existing
+        .filter(|uuid| !uuid.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Checksum over the `data` section of a changeset file.
+pub fn changeset_checksum(data: &serde_json::Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Checks the checksum and manifest of a decoded changeset file and returns
+/// its `data` section together with the checksum and the trusted timestamp,
+/// if the file carries one. The timestamp is checked against the TSA
+/// certificate of the settings, see `trusted_timestamp::verify`.
+fn verify_changeset(changeset_data: &[u8]) -> Result<(serde_json::Value, String, Option<serde_json::Value>)> {
+    let content = std::str::from_utf8(changeset_data).context("Invalid changeset file encoding")?;
+
+    let mut parsed: serde_json::Value = serde_json::from_str(content)
+        .context("Invalid changeset file format")?;
+
+    // Verify checksum
+    let stored_checksum = parsed.get("checksum")
+        .and_then(|c| c.as_str())
+        .context("Missing checksum in changeset file")?
+        .to_string();
+
+    let data_section = parsed.get_mut("data")
+        .map(serde_json::Value::take)
+        .context("Missing data section in changeset file")?;
+
+    let calculated_checksum = changeset_checksum(&data_section);
+
+    if stored_checksum != calculated_checksum {
+        return Err(anyhow::anyhow!("Checksum verification failed"));
+    }
+
+    if let Some(changes) = data_section.get("changes") {
+        manifest::verify_optional(data_section.get("manifest"), changes)?;
+    }
+    let trusted_timestamp = parsed.get_mut("trusted_timestamp").map(serde_json::Value::take);
+
+    Ok((data_section, calculated_checksum, trusted_timestamp))
+}
+
+/// Files exported before operation ids existed are identified by their checksum.
+fn changeset_operation_id(data_section: &serde_json::Value, checksum: &str) -> String {
+    data_section
+        .get("operation_id")
+        .and_then(|o| o.as_str())
+        .map(|o| o.to_string())
+        .unwrap_or_else(|| format!("legacy:{}", checksum))
+}
+
+/// A measured value taken with an observation.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct ObservationMetric {
+    pub metric: String,
+    pub value: f64,
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// Checks metrics before they are stored: named, once each, with a finite value.
+pub fn validate_metrics(metrics: &[ObservationMetric]) -> Result<()> {
+    let mut names = std::collections::HashSet::new();
+    for metric in metrics {
+        let name = metric.metric.trim();
+        if name.is_empty() || !names.insert(name) {
+            return Err(anyhow::anyhow!("Metric names must be given and unique: {:?}", metric.metric));
+        }
+        if !metric.value.is_finite() {
+            return Err(anyhow::anyhow!("Metric {} needs a numeric value", name));
+        }
+    }
+    Ok(())
+}
+
+/// One value of a student's metric over time, dated by its observation.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct MetricPoint {
+    pub observation_id: i64,
+    pub metric: String,
+    pub value: f64,
+    pub unit: Option<String>,
+    pub observed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A scanned note or copied line as confirmed in the review, see
+/// `promote_scanned_notes` and `promote_clipboard_rows`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ConfirmedScan {
+    pub index: usize, // Position in `get_scanned_notes` or `get_clipboard_rows`
+    pub student_id: i64,
+    pub category: String,
+    #[serde(default)]
+    pub text: Option<String>, // Corrected text, if the recognition needed it
+}
+
+/// How often a competency was documented for a student. Competencies
+/// without observations are listed too, with `observations` 0.
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct CompetencyCoverage {
+    pub competency_id: i64,
+    pub area: String,
+    pub name: String,
+    pub observations: i64,
+    pub last_observed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct CompetencyLink {
+    pub observation_id: i64,
+    pub competency_id: i64,
+    pub area: String,
+    pub name: String,
+}
+
+/// Highest level a rubric criterion can have.
+pub const MAX_RUBRIC_LEVELS: usize = 4;
+
+/// What a class agreement can permit; photo exports of a class need "photo".
+pub const CLASS_AGREEMENT_KINDS: [&str; 4] = ["photo", "audio", "video", "publication"];
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ClassAgreementInput {
+    pub kind: String,
+    pub collected_on: chrono::NaiveDate,
+    #[serde(default)]
+    pub valid_until: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AnonymizationTermInput {
+    pub term: String,
+    #[serde(default)]
+    pub student_id: Option<i64>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// A rubric as created or edited. Each criterion lists the descriptions of
+/// its levels, starting with level 1. Criteria keep their `id` when edited.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RubricInput {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub criteria: Vec<RubricCriterionInput>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RubricCriterionInput {
+    #[serde(default)]
+    pub id: Option<i64>,
+    pub name: String,
+    pub levels: Vec<String>,
+}
+
+impl RubricInput {
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow::anyhow!("Rubric name is required"));
+        }
+        if self.criteria.is_empty() {
+            return Err(anyhow::anyhow!("A rubric needs at least one criterion"));
+        }
+        let mut names = std::collections::HashSet::new();
+        for criterion in &self.criteria {
+            let name = criterion.name.trim();
+            if name.is_empty() || !names.insert(name.to_lowercase()) {
+                return Err(anyhow::anyhow!("Criterion names must be given and unique: {:?}", criterion.name));
+            }
+            if criterion.levels.is_empty() || criterion.levels.len() > MAX_RUBRIC_LEVELS {
+                return Err(anyhow::anyhow!(
+                    "Criterion {} needs between 1 and {} levels",
+                    name,
+                    MAX_RUBRIC_LEVELS
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RubricDetails {
+    #[serde(flatten)]
+    pub rubric: Rubric,
+    pub criteria: Vec<RubricCriterion>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RubricCriterion {
+    pub id: i64,
+    pub name: String,
+    pub levels: Vec<RubricLevel>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct RubricLevel {
+    pub level: i64,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct RubricScore {
+    pub criterion_id: i64,
+    pub level: i64,
+}
+
+/// A score with the names it was given under, for exports that have to be
+/// readable without the rubric.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct NamedRubricScore {
+    pub observation_id: i64,
+    pub rubric: String,
+    pub criterion: String,
+    pub level: i64,
+    pub level_description: String,
+}
+
+/// Which scores `get_rubric_aggregates` counts; unset fields do not filter.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct RubricAggregateFilter {
+    pub student_id: Option<i64>,
+    pub class_id: Option<i64>,
+    pub session_id: Option<i64>,
+    pub subject: Option<String>,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>, // Exclusive
+}
+
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
+pub struct RubricAggregate {
+    pub rubric_id: i64,
+    pub rubric_name: String,
+    pub criterion_id: i64,
+    pub criterion_name: String,
+    pub scores: i64,
+    pub average_level: f64,
+    pub lowest_level: i64,
+    pub highest_level: i64,
+}
+
+/// Counts over the observations of one session as a viewer sees them.
+#[derive(Debug, serde::Serialize)]
+pub struct SessionStatistics {
+    pub session: ObservationSession,
+    pub observations: i64,
+    pub students_in_class: i64,
+    pub by_category: BTreeMap<String, i64>,
+    pub by_subject: BTreeMap<String, i64>, // Observations without a subject are left out
+    pub by_student: BTreeMap<i64, i64>,    // Students without observations are left out
+    pub rubrics: Vec<RubricAggregate>,
+}
+
+/// Categories observed for fewer students are left out of class
+/// comparisons; with two students a count tells too much about each.
+pub const MIN_STUDENTS_PER_CELL: i64 = 3;
+
+/// One category of one class. Both are None when fewer than
+/// `MIN_STUDENTS_PER_CELL` students were observed in it, or to keep such a
+/// category from being worked out of the total, see `compare_class_statistics`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CategoryShare {
+    pub observations: Option<i64>,
+    pub percent: Option<f64>, // Of all observations of the class
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ClassCategoryStatistics {
+    pub class_id: i64,
+    pub class_name: String,
+    pub school_year: String,
+    pub students: i64, // Students not deleted, observed or not
+    pub observations: Option<i64>, // None when it would tell the only hidden category
+    pub by_category: BTreeMap<String, CategoryShare>,
+}
+
+/// Category distributions of several classes, e.g. a 5a of two school
+/// years. Counts only, nothing about single students.
+#[derive(Debug, serde::Serialize)]
+pub struct ClassStatisticsComparison {
+    pub categories: Vec<String>, // Every category of any of the classes
+    pub classes: Vec<ClassCategoryStatistics>,
+    pub min_students_per_cell: i64,
+}
+
+/// What `merge_categories` moved from the source to the target category.
+#[derive(Debug, serde::Serialize)]
+pub struct CategoryMerge {
+    pub source: String,
+    pub target: String,
+    pub observations: u64,
+    pub skipped_observations: Vec<i64>, // Co-signed or read-only, still counted under the target
+    pub templates: u64,
+    pub quick_phrases: u64,
+}
+
+/// A changeset staged for review instead of being applied; see
+/// `quarantine_changeset`. The counts classify its observations. Scanned
+/// paper notes (`kind` "scanned_notes") and lines copied from a spreadsheet
+/// ("clipboard_observations") are staged the same way, with notes no
+/// student could be matched to counted as invalid.
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct QuarantinedImport {
+    pub id: i64,
+    pub kind: String, // "changeset", "scanned_notes" or "clipboard_observations"
+    pub file_path: String,
+    pub operation_id: String,
+    pub source_device_id: Option<String>,
+    pub new_observations: i64,
+    pub updated_observations: i64,
+    pub unchanged_observations: i64, // This device already has this or a later version
+    pub duplicate_observations: i64, // Same student, category, text and time under another id
+    pub invalid_observations: i64,   // Refer to students unknown on this device
+    pub problems: String,            // JSON list of findings that need attention
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Default)]
+struct ImportCounts {
+    classes: u64,
+    students: u64,
+    observations: u64,
+    dictionary_words: u64,
+}
+
+fn validate_dictionary_word(word: &str) -> Result<&str> {
+    let word = word.trim();
+    if word.is_empty() || word.chars().count() > 64 || word.contains(char::is_whitespace) {
+        return Err(anyhow::anyhow!("Dictionary entries must be single words of up to 64 characters"));
+    }
+    Ok(word)
+}
+
+fn backup_records<T: serde::de::DeserializeOwned>(data: &serde_json::Value, key: &str) -> Vec<T> {
+    data.get(key)
+        .and_then(|records| records.as_array())
+        .map(|records| {
+            records
+                .iter()
+                .filter_map(|record| serde_json::from_value::<T>(record.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl std::fmt::Display for ChangesetImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(applied_at) = self.already_applied_at {
+            return write!(
+                f,
+                "Changeset {} was already applied on {} - nothing imported",
+                self.operation_id,
+                applied_at.format("%Y-%m-%d %H:%M:%S UTC")
+            );
+        }
+        write!(
+            f,
+            "Successfully imported {} observations ({} updated, {} unchanged)",
+            self.imported, self.updated, self.skipped
+        )?;
+        if self.deletions_applied > 0 {
+            write!(f, ", {} deletions applied", self.deletions_applied)?;
+        }
+        if self.transfers_applied > 0 {
+            write!(f, ", {} class transfers applied", self.transfers_applied)?;
+        }
+        if self.guardians_applied > 0 {
+            write!(f, ", {} guardians updated", self.guardians_applied)?;
+        }
+        if self.students_updated > 0 {
+            write!(f, ", {} student records updated", self.students_updated)?;
+        }
+        if self.layouts_applied > 0 {
+            write!(f, ", {} class layouts updated", self.layouts_applied)?;
+        }
+        if self.retractions_received > 0 {
+            write!(f, ", {} retractions received", self.retractions_received)?;
+        }
+        if self.attachments_received > 0 {
+            write!(f, ", {} attachments received", self.attachments_received)?;
+        }
+        if !self.pending_hard_deletions.is_empty() {
+            write!(
+                f,
+                "\n{} permanent deletions need confirmation ({}); import again with confirmation to apply them",
+                self.pending_hard_deletions.len(),
+                self.pending_hard_deletions.join(", ")
+            )?;
+        }
+        if let Some(timestamp) = &self.trusted_timestamp {
+            write!(
+                f,
+                "\nTrusted timestamp from {}: exported no later than {}",
+                timestamp.tsa_url,
+                timestamp.gen_time.format("%Y-%m-%d %H:%M:%S UTC")
+            )?;
+        }
+        for warning in &self.warnings {
+            write!(f, "\nWarning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Snapshots are kept next to the database file, see `create_snapshot`.
+pub fn snapshots_dir_for(db_path: &Path) -> PathBuf {
+    db_path.parent().unwrap_or_else(|| Path::new(".")).join("snapshots")
+}
+
+/// Newest first. Works without an open database, e.g. in maintenance mode.
+pub async fn list_snapshots_in(snapshots_dir: &Path) -> Result<Vec<SnapshotInfo>> {
+    if !snapshots_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+    let mut entries = tokio::fs::read_dir(snapshots_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let Ok(metadata) = tokio::fs::read(&path).await else { continue };
+        if let Ok(info) = serde_json::from_slice::<SnapshotInfo>(&metadata) {
+            if snapshots_dir.join(format!("{}.db", info.id)).exists() {
+                snapshots.push(info);
+            }
+        }
+    }
+
+    snapshots.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(snapshots)
 }
 
 impl Database {
@@ -21,24 +903,179 @@ impl Database {
                 .context("Failed to create database directory")?;
         }
 
-        let db_url = format!("sqlite:{}?mode=rwc", db_path.as_ref().display());
+        let db_path = db_path.as_ref().to_path_buf();
+        let pool = Self::connect(&db_path).await?;
+
+        let mut db = Self {
+            read_pool: pool.clone(),
+            pool,
+            frozen_pool: None,
+            crypto,
+            db_path,
+            busy_retries: Arc::new(AtomicU64::new(0)),
+            busy_errors: Arc::new(AtomicU64::new(0)),
+        };
+        db.migrate().await?;
+
+        // Read-only connections need the schema and WAL file to exist already
+        db.read_pool = Self::connect_read_only(&db.db_path).await?;
+        Ok(db)
+    }
+
+    async fn connect(db_path: &Path) -> Result<Pool<Sqlite>> {
+        // Set per connection, so every pooled connection gets WAL, foreign keys
+        // and the busy timeout, not just the first one
+        let options = SqliteConnectOptions::new()
+            .filename(db_path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .foreign_keys(true)
+            .busy_timeout(BUSY_TIMEOUT);
 
         let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(&db_url)
+            .max_connections(MAX_CONNECTIONS)
+            .acquire_timeout(BUSY_TIMEOUT * 2)
+            .connect_with(options)
             .await
             .context("Failed to connect to database")?;
 
-        // Enable SQLite session extension for changeset functionality
-        sqlx::query("PRAGMA journal_mode=WAL")
-            .execute(&pool)
-            .await?;
+        Ok(pool)
+    }
 
-        sqlx::query("PRAGMA foreign_keys=ON").execute(&pool).await?;
+    async fn connect_read_only(db_path: &Path) -> Result<Pool<Sqlite>> {
+        let options = SqliteConnectOptions::new()
+            .filename(db_path)
+            .read_only(true)
+            .busy_timeout(BUSY_TIMEOUT);
 
-        let db = Self { pool, crypto };
-        db.migrate().await?;
-        Ok(db)
+        SqlitePoolOptions::new()
+            .max_connections(MAX_READ_CONNECTIONS)
+            .acquire_timeout(BUSY_TIMEOUT * 2)
+            .connect_with(options)
+            .await
+            .context("Failed to open read-only database connection")
+    }
+
+    /// Closes all connections, e.g. before the file is moved or removed.
+    pub async fn close(&self) {
+        self.pool.close().await;
+        self.read_pool.close().await;
+        if let Some(writer) = &self.frozen_pool {
+            writer.close().await;
+        }
+    }
+
+    /// A handle on the read-only pool for exports, statistics and reports.
+    /// It does not need the application's database lock, and in WAL mode its
+    /// long-running reads neither block nor wait for interactive writes.
+    /// Writes through the handle fail.
+    pub fn reader(&self) -> Database {
+        Database {
+            pool: self.read_pool.clone(),
+            read_pool: self.read_pool.clone(),
+            frozen_pool: None,
+            crypto: self.crypto.clone(),
+            db_path: self.db_path.clone(),
+            busy_retries: self.busy_retries.clone(),
+            busy_errors: self.busy_errors.clone(),
+        }
+    }
+
+    /// Sends every query to the read-only pool, so all writes fail until
+    /// `thaw_writes`. Used while processing is frozen after a suspected incident.
+    pub fn freeze_writes(&mut self) {
+        if self.frozen_pool.is_none() {
+            let writer = std::mem::replace(&mut self.pool, self.read_pool.clone());
+            self.frozen_pool = Some(writer);
+        }
+    }
+
+    pub fn thaw_writes(&mut self) {
+        if let Some(writer) = self.frozen_pool.take() {
+            self.pool = writer;
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen_pool.is_some()
+    }
+
+    /// For operations that would bypass the read-only pool, e.g. by writing
+    /// files or exchanging data with other devices.
+    pub fn ensure_not_frozen(&self) -> Result<()> {
+        if self.is_frozen() {
+            return Err(anyhow::anyhow!(
+                "Processing is frozen because of a suspected data incident; no changes or sync are possible until it is lifted"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs an atomic write operation again when it fails with SQLITE_BUSY.
+    /// Only use this for operations that roll back completely on error.
+    async fn retry_on_busy<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(e) if is_busy_error(&e) => {
+                    if attempt >= BUSY_RETRY_ATTEMPTS {
+                        self.busy_errors.fetch_add(1, Ordering::Relaxed);
+                        return Err(e.context("Database is busy, please try again"));
+                    }
+                    self.busy_retries.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(std::time::Duration::from_millis(50 * 2u64.pow(attempt))).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn get_database_health(&self) -> Result<DatabaseHealth> {
+        let journal_mode = sqlx::query_scalar::<_, String>("PRAGMA journal_mode")
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to read journal mode")?;
+
+        let mut wal_path = self.db_path.clone().into_os_string();
+        wal_path.push("-wal");
+
+        Ok(DatabaseHealth {
+            pool_size: self.pool.size(),
+            idle_connections: self.pool.num_idle(),
+            max_connections: MAX_CONNECTIONS,
+            read_pool_size: self.read_pool.size(),
+            read_idle_connections: self.read_pool.num_idle(),
+            busy_timeout_ms: BUSY_TIMEOUT.as_millis() as u64,
+            busy_retries: self.busy_retries.load(Ordering::Relaxed),
+            busy_errors: self.busy_errors.load(Ordering::Relaxed),
+            journal_mode,
+            wal_bytes: std::fs::metadata(wal_path).map(|m| m.len()).unwrap_or(0),
+        })
+    }
+
+    /// SHA-256 over the rows of `INTEGRITY_COLUMNS`, in id order. Columns the
+    /// app derives itself, like the observation language, are left out, so
+    /// recomputing them on startup does not look like an edit.
+    pub async fn critical_tables_digest(&self) -> Result<String> {
+        let mut hasher = Sha256::new();
+        for (table, columns) in INTEGRITY_COLUMNS {
+            let row = columns.iter().map(|c| format!("quote({})", c)).collect::<Vec<_>>().join(" || '|' || ");
+            let rows = sqlx::query_scalar::<_, String>(&format!("SELECT {} FROM {} ORDER BY id", row, table))
+                .fetch_all(&self.pool)
+                .await
+                .with_context(|| format!("Failed to read {} for the integrity digest", table))?;
+            hasher.update(format!("{}:{}\n", table, rows.len()).as_bytes());
+            for row in rows {
+                hasher.update(row.as_bytes());
+                hasher.update(b"\n");
+            }
+        }
+        Ok(format!("{:x}", hasher.finalize()))
     }
 
     async fn migrate(&self) -> Result<()> {
@@ -115,6 +1152,7 @@ impl Database {
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
                 updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
                 source_device_id TEXT NOT NULL,
+                logical_clock INTEGER NOT NULL DEFAULT 0,
                 FOREIGN KEY (student_id) REFERENCES students (id)
             )
             "#,
@@ -131,6 +1169,8 @@ impl Database {
                 content_type TEXT NOT NULL,
                 file_data BLOB NOT NULL,
                 file_hash TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL DEFAULT 0,
+                storage TEXT NOT NULL DEFAULT 'database',
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
                 FOREIGN KEY (observation_id) REFERENCES observations (id)
             )
@@ -153,977 +1193,10422 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
-        // Create indexes for better performance
+        // Per-device Lamport counter used to order changes independently of wall clocks
         sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_observations_student ON observations(student_id)",
+            r#"
+            CREATE TABLE IF NOT EXISTS sync_clock (
+                device_id TEXT PRIMARY KEY,
+                counter INTEGER NOT NULL DEFAULT 0
+            )
+            "#,
         )
         .execute(&self.pool)
         .await?;
 
+        // Local create/update/delete history of synced entities; delete rows are
+        // the tombstones carried in changesets
         sqlx::query(
-            "CREATE INDEX IF NOT EXISTS idx_observations_created ON observations(created_at)",
+            r#"
+            CREATE TABLE IF NOT EXISTS change_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                entity_type TEXT NOT NULL,
+                entity_id INTEGER NOT NULL,
+                operation TEXT NOT NULL,
+                deletion_type TEXT,
+                logical_clock INTEGER NOT NULL,
+                device_id TEXT NOT NULL,
+                changed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
         )
         .execute(&self.pool)
         .await?;
 
-        // Migrate existing tables to add source_device_id columns if they don't exist
-        self.add_missing_columns().await?;
-        
-        // Seed default categories if none exist
-        self.seed_default_categories().await?;
-
-        Ok(())
-    }
-
-    async fn add_missing_columns(&self) -> Result<()> {
-        let device_id = self.crypto.get_device_id();
-
-        // Check and add source_device_id to classes table
-        let classes_has_column = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM pragma_table_info('classes') WHERE name = 'source_device_id'",
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_change_log_entity ON change_log(entity_type, entity_id)",
         )
-        .fetch_one(&self.pool)
-        .await
-        .unwrap_or(0);
+        .execute(&self.pool)
+        .await?;
 
-        if classes_has_column == 0 {
-            println!("Adding source_device_id column to classes table...");
-            sqlx::query("ALTER TABLE classes ADD COLUMN source_device_id TEXT NOT NULL DEFAULT ''")
-                .execute(&self.pool)
-                .await?;
+        // One row per exported or applied changeset operation
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sync_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                operation_id TEXT NOT NULL,
+                direction TEXT NOT NULL,
+                peer_device_id TEXT,
+                summary TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (operation_id, direction)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-            // Update existing records with current device ID
-            sqlx::query("UPDATE classes SET source_device_id = ? WHERE source_device_id = ''")
-                .bind(&device_id)
-                .execute(&self.pool)
-                .await?;
-        }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS report_artifacts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                class_id INTEGER NOT NULL,
+                report_type TEXT NOT NULL,
+                period_start TEXT NOT NULL,
+                period_end TEXT NOT NULL,
+                format TEXT NOT NULL,
+                content BLOB NOT NULL,
+                content_hash TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                source_device_id TEXT NOT NULL DEFAULT '',
+                FOREIGN KEY (class_id) REFERENCES classes (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        // Check and add source_device_id to students table
-        let students_has_column = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM pragma_table_info('students') WHERE name = 'source_device_id'",
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS app_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
         )
-        .fetch_one(&self.pool)
-        .await
-        .unwrap_or(0);
+        .execute(&self.pool)
+        .await?;
 
-        if students_has_column == 0 {
-            println!("Adding source_device_id column to students table...");
-            sqlx::query(
-                "ALTER TABLE students ADD COLUMN source_device_id TEXT NOT NULL DEFAULT ''",
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS custom_dictionary (
+                word TEXT PRIMARY KEY COLLATE NOCASE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                role TEXT NOT NULL DEFAULT 'teacher' CHECK (role IN ('teacher', 'admin')),
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
             )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Everything recorded so far was done as user 1, so single-user installs keep full rights
+        sqlx::query("INSERT OR IGNORE INTO users (id, name, role) VALUES (1, 'Administrator', 'admin')")
             .execute(&self.pool)
             .await?;
 
-            // Update existing records with current device ID
-            sqlx::query("UPDATE students SET source_device_id = ? WHERE source_device_id = ''")
-                .bind(&device_id)
-                .execute(&self.pool)
-                .await?;
-        }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS observation_unlocks (
+                observation_id INTEGER PRIMARY KEY,
+                unlocked_by INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                unlocked_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                expires_at DATETIME NOT NULL,
+                FOREIGN KEY (observation_id) REFERENCES observations (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        Ok(())
-    }
+        // No foreign key: the request documents the erasure after the student is gone
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS erasure_requests (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                student_id INTEGER NOT NULL,
+                requested_by INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'executed', 'rejected')),
+                decided_by INTEGER,
+                requested_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                decided_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    // Legacy migration support for encrypted -> plaintext transition
-    async fn check_schema_migration_needed(&self) -> Result<bool> {
-        // Check if we have the old encrypted text column
-        let has_encrypted_column = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='observations' AND sql LIKE '%text_encrypted%'",
+        // Transfers are identified across devices by (source_device_id, logical_clock)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS student_class_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                student_id INTEGER NOT NULL,
+                from_class_id INTEGER NOT NULL,
+                to_class_id INTEGER NOT NULL,
+                transferred_at DATETIME NOT NULL,
+                keep_history BOOLEAN NOT NULL DEFAULT 1,
+                recorded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                source_device_id TEXT NOT NULL,
+                logical_clock INTEGER NOT NULL,
+                UNIQUE (source_device_id, logical_clock),
+                FOREIGN KEY (student_id) REFERENCES students (id) ON DELETE CASCADE
+            )
+            "#,
         )
-        .fetch_one(&self.pool)
-        .await
-        .unwrap_or(0);
+        .execute(&self.pool)
+        .await?;
 
-        Ok(has_encrypted_column > 0)
-    }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS guardians (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                student_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                contact TEXT,
+                relationship TEXT NOT NULL,
+                consent_reference TEXT,
+                sensitive_fields TEXT NOT NULL DEFAULT '[]',
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                source_device_id TEXT NOT NULL,
+                logical_clock INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY (student_id) REFERENCES students (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    async fn migrate_encrypted_to_plaintext(&self) -> Result<()> {
-        // This is a placeholder for encrypted->plaintext migration
-        // In practice, you would decrypt the data and move it to the plaintext columns
-        println!("Note: Encrypted data migration not implemented - starting with fresh database");
-        Ok(())
-    }
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_guardians_student ON guardians(student_id)")
+            .execute(&self.pool)
+            .await?;
 
-    // Class operations
-    pub async fn create_class(&self, name: String, school_year: String) -> Result<Class> {
-        let device_id = self.crypto.get_device_id();
+        // Seating plan and groups of a class, JSON as the frontend defines it
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS class_layouts (
+                class_id INTEGER PRIMARY KEY,
+                layout TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                source_device_id TEXT NOT NULL,
+                logical_clock INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY (class_id) REFERENCES classes (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        let class = sqlx::query_as::<_, Class>(
+        // Subjects observations can be filed under, managed like categories
+        sqlx::query(
             r#"
-            INSERT INTO classes (name, school_year, source_device_id)
-            VALUES (?, ?, ?)
-            RETURNING *
+            CREATE TABLE IF NOT EXISTS subjects (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                sort_order INTEGER NOT NULL DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
             "#,
         )
-        .bind(&name)
-        .bind(&school_year)
-        .bind(&device_id)
-        .fetch_one(&self.pool)
-        .await
-        .context("Failed to create class")?;
+        .execute(&self.pool)
+        .await?;
 
-        Ok(class)
-    }
+        // Rubrics: criteria with up to four levels each, scored per observation
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rubrics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                description TEXT,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn get_classes(&self) -> Result<Vec<Class>> {
-        let classes = sqlx::query_as::<_, Class>("SELECT * FROM classes ORDER BY name")
-            .fetch_all(&self.pool)
-            .await
-            .context("Failed to fetch classes")?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rubric_criteria (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                rubric_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                sort_order INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY (rubric_id) REFERENCES rubrics (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        Ok(classes)
-    }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rubric_levels (
+                criterion_id INTEGER NOT NULL,
+                level INTEGER NOT NULL CHECK (level BETWEEN 1 AND 4),
+                description TEXT NOT NULL,
+                PRIMARY KEY (criterion_id, level),
+                FOREIGN KEY (criterion_id) REFERENCES rubric_criteria (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn delete_class(&self, class_id: i64, force_delete: bool) -> Result<()> {
-        if force_delete {
-            // Hard delete: remove class and all related data
-            sqlx::query("DELETE FROM observations WHERE student_id IN (SELECT id FROM students WHERE class_id = ?)")
-                .bind(class_id)
-                .execute(&self.pool)
-                .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS observation_rubric_scores (
+                observation_id INTEGER NOT NULL,
+                criterion_id INTEGER NOT NULL,
+                level INTEGER NOT NULL,
+                PRIMARY KEY (observation_id, criterion_id),
+                FOREIGN KEY (observation_id) REFERENCES observations (id) ON DELETE CASCADE,
+                FOREIGN KEY (criterion_id) REFERENCES rubric_criteria (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-            sqlx::query("DELETE FROM students WHERE class_id = ?")
-                .bind(class_id)
-                .execute(&self.pool)
-                .await?;
+        // Competencies observations can document, e.g. the KMK areas
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS competencies (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                area TEXT NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                sort_order INTEGER NOT NULL DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (area, name)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-            sqlx::query("DELETE FROM classes WHERE id = ?")
-                .bind(class_id)
-                .execute(&self.pool)
-                .await?;
-        } else {
-            // Soft delete: check if class has students
-            let student_count = sqlx::query_scalar::<_, i64>(
-                "SELECT COUNT(*) FROM students WHERE class_id = ? AND status != 'deleted'",
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS observation_competencies (
+                observation_id INTEGER NOT NULL,
+                competency_id INTEGER NOT NULL,
+                PRIMARY KEY (observation_id, competency_id),
+                FOREIGN KEY (observation_id) REFERENCES observations (id) ON DELETE CASCADE,
+                FOREIGN KEY (competency_id) REFERENCES competencies (id)
             )
-            .bind(class_id)
-            .fetch_one(&self.pool)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Measured values taken with an observation, e.g. words read per minute
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS observation_metrics (
+                observation_id INTEGER NOT NULL,
+                metric TEXT NOT NULL,
+                value REAL NOT NULL,
+                unit TEXT,
+                PRIMARY KEY (observation_id, metric),
+                FOREIGN KEY (observation_id) REFERENCES observations (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_observation_metrics_metric ON observation_metrics(metric)")
+            .execute(&self.pool)
             .await?;
 
-            if student_count > 0 {
-                return Err(anyhow::anyhow!(
-                    "Cannot delete class with active students. Use force delete or remove students first."
-                ));
-            }
+        // Permissions collected per class, see `set_class_agreement`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS class_agreements (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                class_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                collected_on DATE NOT NULL,
+                valid_until DATE,
+                reference TEXT,
+                notes TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (class_id, kind),
+                FOREIGN KEY (class_id) REFERENCES classes (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Identifying terms besides the student names, see `redaction::anonymization_dictionary`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS anonymization_terms (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                term TEXT NOT NULL,
+                student_id INTEGER,
+                note TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (student_id) REFERENCES students (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS student_goals (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                student_id INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'active',
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (student_id) REFERENCES students (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Kept when a record is deleted, so its number is not given out again
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS reference_codes (
+                entity_type TEXT NOT NULL,
+                entity_id INTEGER NOT NULL,
+                year INTEGER NOT NULL,
+                number INTEGER NOT NULL,
+                code TEXT NOT NULL UNIQUE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (entity_type, entity_id),
+                UNIQUE (entity_type, year, number)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Lessons observed as a whole, see `start_observation_session`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS observation_sessions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                class_id INTEGER NOT NULL,
+                title TEXT,
+                subject TEXT,
+                started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                ended_at DATETIME,
+                FOREIGN KEY (class_id) REFERENCES classes (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Changesets staged for review, see `quarantine_changeset`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS quarantined_imports (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL DEFAULT 'changeset',
+                file_path TEXT NOT NULL,
+                operation_id TEXT NOT NULL,
+                source_device_id TEXT,
+                payload BLOB NOT NULL,
+                problems TEXT NOT NULL DEFAULT '[]',
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS quarantined_observations (
+                import_id INTEGER NOT NULL,
+                observation_id INTEGER NOT NULL,
+                student_id INTEGER NOT NULL,
+                category TEXT NOT NULL,
+                text TEXT NOT NULL,
+                created_at DATETIME NOT NULL,
+                source_device_id TEXT NOT NULL,
+                logical_clock INTEGER NOT NULL,
+                status TEXT NOT NULL DEFAULT 'new',
+                FOREIGN KEY (import_id) REFERENCES quarantined_imports (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // History of long-running commands, see `create_job`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS jobs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                job_type TEXT NOT NULL,
+                params TEXT NOT NULL DEFAULT '{}',
+                status TEXT NOT NULL DEFAULT 'running',
+                progress REAL NOT NULL DEFAULT 0,
+                operation_id TEXT,
+                result_path TEXT,
+                error TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                finished_at DATETIME
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Classmates named in an observation, found by the mention analyzer
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mention_warnings (
+                observation_id INTEGER NOT NULL,
+                mentioned_student_id INTEGER NOT NULL,
+                matched_text TEXT NOT NULL,
+                detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (observation_id, mentioned_student_id),
+                FOREIGN KEY (observation_id) REFERENCES observations (id) ON DELETE CASCADE,
+                FOREIGN KEY (mentioned_student_id) REFERENCES students (id) ON DELETE CASCADE
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS observation_templates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                category TEXT NOT NULL,
+                text TEXT NOT NULL,
+                sort_order INTEGER NOT NULL DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                translations TEXT NOT NULL DEFAULT '{}'
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Short phrases inserted with one tap; usage_count stays on this device
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS quick_phrases (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                category TEXT NOT NULL,
+                text TEXT NOT NULL,
+                usage_count INTEGER NOT NULL DEFAULT 0,
+                last_used_at DATETIME,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (category, text)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Which changeset carried which observation, so later corrections can be retracted
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS observation_exports (
+                observation_id INTEGER NOT NULL,
+                operation_id TEXT NOT NULL,
+                exported_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (observation_id, operation_id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS retractions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                observation_id INTEGER NOT NULL,
+                student_id INTEGER NOT NULL,
+                label TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                exported_in TEXT NOT NULL,
+                retracted_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                carried_in TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS handover_imports (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                class_id INTEGER NOT NULL,
+                class_name TEXT NOT NULL,
+                source_device_id TEXT NOT NULL,
+                package_hash TEXT NOT NULL UNIQUE,
+                package_created_at DATETIME NOT NULL,
+                students INTEGER NOT NULL,
+                observations INTEGER NOT NULL,
+                attachments INTEGER NOT NULL,
+                imported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create indexes for better performance
+        // (student_id, created_at) serves per-student lookups and their date ordering,
+        // which makes the former single-column index redundant
+        sqlx::query("DROP INDEX IF EXISTS idx_observations_student")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_observations_student_created ON observations(student_id, created_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_observations_category ON observations(category, created_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_students_class_status ON students(class_id, status)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_observations_created ON observations(created_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Migrate existing tables to add source_device_id columns if they don't exist
+        self.add_missing_columns().await?;
+        
+        // Seed default categories if none exist
+        self.seed_default_categories().await?;
+        self.seed_default_subjects().await?;
+        self.seed_default_competencies().await?;
+        self.refresh_observation_languages().await?;
+        self.seal_staged_imports().await?;
+
+        Ok(())
+    }
+
+    async fn add_missing_columns(&self) -> Result<()> {
+        let device_id = self.crypto.get_device_id();
+
+        // Check and add source_device_id to classes table
+        let classes_has_column = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('classes') WHERE name = 'source_device_id'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if classes_has_column == 0 {
+            println!("Adding source_device_id column to classes table...");
+            sqlx::query("ALTER TABLE classes ADD COLUMN source_device_id TEXT NOT NULL DEFAULT ''")
+                .execute(&self.pool)
+                .await?;
+
+            // Update existing records with current device ID
+            sqlx::query("UPDATE classes SET source_device_id = ? WHERE source_device_id = ''")
+                .bind(&device_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add source_device_id to students table
+        let students_has_column = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('students') WHERE name = 'source_device_id'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if students_has_column == 0 {
+            println!("Adding source_device_id column to students table...");
+            sqlx::query(
+                "ALTER TABLE students ADD COLUMN source_device_id TEXT NOT NULL DEFAULT ''",
+            )
+            .execute(&self.pool)
+            .await?;
+
+            // Update existing records with current device ID
+            sqlx::query("UPDATE students SET source_device_id = ? WHERE source_device_id = ''")
+                .bind(&device_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add logical_clock to observations table
+        let observations_has_clock = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'logical_clock'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_clock == 0 {
+            println!("Adding logical_clock column to observations table...");
+            sqlx::query(
+                "ALTER TABLE observations ADD COLUMN logical_clock INTEGER NOT NULL DEFAULT 0",
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        // Check and add storage metadata to attachments table
+        let attachments_has_storage = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('attachments') WHERE name = 'storage'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if attachments_has_storage == 0 {
+            println!("Adding storage columns to attachments table...");
+            sqlx::query("ALTER TABLE attachments ADD COLUMN size_bytes INTEGER NOT NULL DEFAULT 0")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("ALTER TABLE attachments ADD COLUMN storage TEXT NOT NULL DEFAULT 'database'")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("UPDATE attachments SET size_bytes = length(file_data)")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add scheduling columns to erasure_requests table
+        let erasure_has_schedule = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('erasure_requests') WHERE name = 'execute_at'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if erasure_has_schedule == 0 {
+            println!("Adding scheduling columns to erasure_requests table...");
+            sqlx::query("ALTER TABLE erasure_requests ADD COLUMN execute_at DATETIME")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("ALTER TABLE erasure_requests ADD COLUMN notified_at DATETIME")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Scheduled erasures need a second admin like immediate ones
+        let erasure_has_approval = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('erasure_requests') WHERE name = 'approved_by'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if erasure_has_approval == 0 {
+            println!("Adding approval columns to erasure_requests table...");
+            sqlx::query("ALTER TABLE erasure_requests ADD COLUMN approved_by INTEGER")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("ALTER TABLE erasure_requests ADD COLUMN approved_at DATETIME")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add confirmed_until to sync_state table
+        let sync_state_has_confirmed = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('sync_state') WHERE name = 'confirmed_until'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if sync_state_has_confirmed == 0 {
+            println!("Adding confirmed_until column to sync_state table...");
+            sqlx::query("ALTER TABLE sync_state ADD COLUMN confirmed_until DATETIME")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add local_only to observations table
+        let observations_has_local_only = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'local_only'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_local_only == 0 {
+            println!("Adding local_only column to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN local_only BOOLEAN NOT NULL DEFAULT 0")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add visibility to observations table
+        let observations_has_visibility = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'visibility'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_visibility == 0 {
+            println!("Adding visibility column to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN visibility TEXT NOT NULL DEFAULT 'team'")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add subject to observations table
+        let observations_has_subject = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'subject'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_subject == 0 {
+            println!("Adding subject column to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN subject TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_observations_subject ON observations(subject, created_at)")
+            .execute(&self.pool)
+            .await?;
+
+        // Check and add session_id to observations table
+        let observations_has_session = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'session_id'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_session == 0 {
+            println!("Adding session_id column to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN session_id INTEGER")
+                .execute(&self.pool)
+                .await?;
+        }
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_observations_session ON observations(session_id)")
+            .execute(&self.pool)
+            .await?;
+
+        // Check and add co-signature columns to observations table
+        let observations_has_cosigned_by = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'cosigned_by'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_cosigned_by == 0 {
+            println!("Adding co-signature columns to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN cosigned_by INTEGER")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("ALTER TABLE observations ADD COLUMN cosigned_at DATETIME")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add the reporting colleague to observations table
+        let observations_has_reported_by = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'reported_by'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_reported_by == 0 {
+            println!("Adding reported_by columns to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN reported_by_user_id INTEGER")
+                .execute(&self.pool)
+                .await?;
+            sqlx::query("ALTER TABLE observations ADD COLUMN reported_by TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let observations_has_pinned = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'pinned'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_pinned == 0 {
+            println!("Adding pinned column to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let students_has_left_at = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('students') WHERE name = 'left_at'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if students_has_left_at == 0 {
+            println!("Adding left_at column to students table...");
+            sqlx::query("ALTER TABLE students ADD COLUMN left_at DATE")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        for (column, definition) in [("date_of_birth", "DATE"), ("notes", "TEXT"), ("external_id", "TEXT")] {
+            let students_has_column = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM pragma_table_info('students') WHERE name = ?",
+            )
+            .bind(column)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or(0);
+
+            if students_has_column == 0 {
+                println!("Adding {} column to students table...", column);
+                sqlx::query(&format!("ALTER TABLE students ADD COLUMN {} {}", column, definition))
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+
+        // Check and add icon to categories table
+        let categories_has_icon = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('categories') WHERE name = 'icon'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if categories_has_icon == 0 {
+            println!("Adding icon column to categories table...");
+            sqlx::query("ALTER TABLE categories ADD COLUMN icon TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Check and add translations to categories table
+        let categories_has_translations = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('categories') WHERE name = 'translations'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if categories_has_translations == 0 {
+            println!("Adding translations column to categories table...");
+            sqlx::query("ALTER TABLE categories ADD COLUMN translations TEXT NOT NULL DEFAULT '{}'")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let categories_has_merged_into = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('categories') WHERE name = 'merged_into'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if categories_has_merged_into == 0 {
+            println!("Adding merged_into column to categories table...");
+            sqlx::query("ALTER TABLE categories ADD COLUMN merged_into INTEGER")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let templates_has_translations = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observation_templates') WHERE name = 'translations'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if templates_has_translations == 0 {
+            println!("Adding translations column to observation_templates table...");
+            sqlx::query("ALTER TABLE observation_templates ADD COLUMN translations TEXT NOT NULL DEFAULT '{}'")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let quarantine_has_kind = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('quarantined_imports') WHERE name = 'kind'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if quarantine_has_kind == 0 {
+            println!("Adding kind column to quarantined_imports table...");
+            sqlx::query("ALTER TABLE quarantined_imports ADD COLUMN kind TEXT NOT NULL DEFAULT 'changeset'")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let observations_has_language = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'language'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_language == 0 {
+            println!("Adding language column to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN language TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_observations_language ON observations(language, created_at)")
+            .execute(&self.pool)
+            .await?;
+        // Whatever path changes a text, its language has to be detected again,
+        // see `refresh_observation_languages`
+        sqlx::query(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS observations_language_outdated
+            AFTER UPDATE OF text ON observations
+            WHEN NEW.text IS NOT OLD.text
+            BEGIN
+                UPDATE observations SET language = NULL WHERE id = NEW.id;
+            END
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Anonymized observations are not anonymized again, see `anonymize_observation`
+        let observations_has_anonymized = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('observations') WHERE name = 'anonymized_at'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        if observations_has_anonymized == 0 {
+            println!("Adding anonymized_at column to observations table...");
+            sqlx::query("ALTER TABLE observations ADD COLUMN anonymized_at DATETIME")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Integer ids are assigned by each device; changesets name records by uuid
+        let change_log_has_uuid = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('change_log') WHERE name = 'entity_uuid'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+        if change_log_has_uuid == 0 {
+            println!("Adding entity_uuid column to change_log table...");
+            sqlx::query("ALTER TABLE change_log ADD COLUMN entity_uuid TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+        for (entity_type, table) in SYNCED_ENTITY_TABLES {
+            let has_uuid = sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = 'uuid'",
+                table
+            ))
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or(0);
+
+            if has_uuid == 0 {
+                println!("Adding uuid column to {} table...", table);
+                sqlx::query(&format!("ALTER TABLE {} ADD COLUMN uuid TEXT", table))
+                    .execute(&self.pool)
+                    .await?;
+            }
+            sqlx::query(&format!("UPDATE {} SET uuid = lower(hex(randomblob(16))) WHERE uuid IS NULL", table))
+                .execute(&self.pool)
+                .await?;
+            sqlx::query(&format!("CREATE UNIQUE INDEX IF NOT EXISTS idx_{}_uuid ON {}(uuid)", table, table))
+                .execute(&self.pool)
+                .await?;
+
+            // Changes logged before uuids existed, for records still present
+            if change_log_has_uuid == 0 {
+                sqlx::query(&format!(
+                    "UPDATE change_log SET entity_uuid = (SELECT uuid FROM {} WHERE id = change_log.entity_id) WHERE entity_type = ?",
+                    table
+                ))
+                .bind(entity_type)
+                .execute(&self.pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Legacy migration support for encrypted -> plaintext transition
+    async fn check_schema_migration_needed(&self) -> Result<bool> {
+        // Check if we have the old encrypted text column
+        let has_encrypted_column = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='observations' AND sql LIKE '%text_encrypted%'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or(0);
+
+        Ok(has_encrypted_column > 0)
+    }
+
+    async fn migrate_encrypted_to_plaintext(&self) -> Result<()> {
+        // This is a placeholder for encrypted->plaintext migration
+        // In practice, you would decrypt the data and move it to the plaintext columns
+        println!("Note: Encrypted data migration not implemented - starting with fresh database");
+        Ok(())
+    }
+
+    // Class operations
+    pub async fn create_class(&self, name: String, school_year: String) -> Result<Class> {
+        let device_id = self.crypto.get_device_id();
+
+        let class = sqlx::query_as::<_, Class>(
+            r#"
+            INSERT INTO classes (name, school_year, source_device_id, uuid)
+            VALUES (?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(&name)
+        .bind(&school_year)
+        .bind(&device_id)
+        .bind(record_uuid(None))
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create class")?;
+
+        self.record_change("class", class.id, "create", None).await?;
+
+        Ok(class)
+    }
+
+    pub async fn get_class(&self, class_id: i64) -> Result<Option<Class>> {
+        let class = sqlx::query_as::<_, Class>("SELECT * FROM classes WHERE id = ?")
+            .bind(class_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch class")?;
+
+        Ok(class)
+    }
+
+    pub async fn get_classes(&self) -> Result<Vec<Class>> {
+        let classes = sqlx::query_as::<_, Class>("SELECT * FROM classes ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch classes")?;
+
+        Ok(classes)
+    }
+
+    pub async fn delete_class(&self, class_id: i64, force_delete: bool) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let uuid = Self::entity_uuid_on(&mut conn, "class", class_id).await?;
+        drop(conn);
+
+        if force_delete {
+            // Hard delete: remove class and all related data
+            let mut conn = self.pool.acquire().await?;
+            Self::record_retractions_on(
+                &mut conn,
+                "o.student_id IN (SELECT id FROM students WHERE class_id = ?)",
+                class_id,
+                "deleted",
+            )
+            .await?;
+            drop(conn);
+
+            sqlx::query("DELETE FROM attachments WHERE observation_id IN (SELECT o.id FROM observations o JOIN students s ON s.id = o.student_id WHERE s.class_id = ?)")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM observations WHERE student_id IN (SELECT id FROM students WHERE class_id = ?)")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM guardians WHERE student_id IN (SELECT id FROM students WHERE class_id = ?)")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM students WHERE class_id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM report_artifacts WHERE class_id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM class_layouts WHERE class_id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM observation_sessions WHERE class_id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM classes WHERE id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+        } else {
+            // Soft delete: check if class has students
+            let student_count = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM students WHERE class_id = ? AND status != 'deleted'",
+            )
+            .bind(class_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+            if student_count > 0 {
+                return Err(anyhow::anyhow!(
+                    "Cannot delete class with active students. Use force delete or remove students first."
+                ));
+            }
+
+            sqlx::query("DELETE FROM report_artifacts WHERE class_id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM class_layouts WHERE class_id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM observation_sessions WHERE class_id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM classes WHERE id = ?")
+                .bind(class_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Either way the class row is gone
+        self.record_deletion("class", class_id, uuid, "hard").await?;
+        self.prune_attachment_files().await?;
+
+        Ok(())
+    }
+
+    // Student operations
+    pub async fn create_student(
+        &self,
+        class_id: i64,
+        first_name: String,
+        last_name: String,
+        status: Option<String>,
+    ) -> Result<Student> {
+        self.create_student_with_details(class_id, first_name, last_name, status, StudentDetails::default())
+            .await
+    }
+
+    pub async fn create_student_with_details(
+        &self,
+        class_id: i64,
+        first_name: String,
+        last_name: String,
+        status: Option<String>,
+        details: StudentDetails,
+    ) -> Result<Student> {
+        let device_id = self.crypto.get_device_id();
+        let status = status.unwrap_or_else(|| "active".to_string());
+        let details = self.check_student_details(None, details).await?;
+
+        let student = sqlx::query_as::<_, Student>(
+            r#"
+            INSERT INTO students (class_id, first_name, last_name, status, source_device_id, date_of_birth, notes, external_id, uuid)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(class_id)
+        .bind(&first_name)
+        .bind(&last_name)
+        .bind(&status)
+        .bind(&device_id)
+        .bind(details.date_of_birth)
+        .bind(&details.notes)
+        .bind(&details.external_id)
+        .bind(record_uuid(None))
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create student")?;
+
+        self.record_change("student", student.id, "create", None).await?;
+
+        Ok(student)
+    }
+
+    /// Also returns soft-deleted students.
+    /// Trims the details and checks them. An external id identifies one
+    /// student among those not deleted, so imports can match on it.
+    async fn check_student_details(&self, student_id: Option<i64>, details: StudentDetails) -> Result<StudentDetails> {
+        let trimmed = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let details = StudentDetails {
+            date_of_birth: details.date_of_birth,
+            notes: trimmed(details.notes),
+            external_id: trimmed(details.external_id),
+        };
+        if details.date_of_birth.is_some_and(|date| date > chrono::Utc::now().date_naive()) {
+            return Err(anyhow::anyhow!("The date of birth must not be in the future"));
+        }
+        if let Some(external_id) = &details.external_id {
+            let taken = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM students WHERE external_id = ? AND status != 'deleted' AND id != ?",
+            )
+            .bind(external_id)
+            .bind(student_id.unwrap_or(0))
+            .fetch_one(&self.pool)
+            .await?;
+            if taken > 0 {
+                return Err(anyhow::anyhow!("Another student already has the external id {}", external_id));
+            }
+        }
+        Ok(details)
+    }
+
+    pub async fn get_student(&self, student_id: i64) -> Result<Option<Student>> {
+        let student = sqlx::query_as::<_, Student>("SELECT * FROM students WHERE id = ?")
+            .bind(student_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch student")?;
+
+        Ok(student)
+    }
+
+    pub async fn get_students(&self) -> Result<Vec<Student>> {
+        let students = sqlx::query_as::<_, Student>(
+            "SELECT * FROM students WHERE status != 'deleted' ORDER BY last_name, first_name",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch students")?;
+
+        Ok(students)
+    }
+
+    /// Like `get_students`, with `last_observation_at` from the observations
+    /// the viewer may see.
+    pub async fn list_students(&self, options: &StudentListOptions, viewer_id: i64) -> Result<Vec<Student>> {
+        let order = match (options.group_by_class, options.sort) {
+            (false, StudentSort::Name) => "s.last_name, s.first_name",
+            (false, StudentSort::LastObservation) => {
+                "last_observation_at IS NULL, last_observation_at DESC, s.last_name, s.first_name"
+            }
+            (true, StudentSort::Name) => "c.name, s.class_id, s.last_name, s.first_name",
+            (true, StudentSort::LastObservation) => {
+                "c.name, s.class_id, last_observation_at IS NULL, last_observation_at DESC, s.last_name, s.first_name"
+            }
+        };
+        let sql = format!(
+            r#"
+            SELECT s.*, MAX(datetime(o.created_at)) AS last_observation_at
+            FROM students s
+            LEFT JOIN classes c ON c.id = s.class_id
+            LEFT JOIN observations o ON o.student_id = s.id AND (o.visibility = 'team' OR o.author_id = ?)
+            WHERE s.status != 'deleted' AND (? IS NULL OR s.status = ?)
+            GROUP BY s.id
+            ORDER BY {}
+            "#,
+            order
+        );
+
+        let students = sqlx::query_as::<_, Student>(&sql)
+            .bind(viewer_id)
+            .bind(&options.status)
+            .bind(&options.status)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to list students")?;
+
+        Ok(students)
+    }
+
+    pub async fn get_students_by_class(&self, class_id: i64) -> Result<Vec<Student>> {
+        let students = sqlx::query_as::<_, Student>(
+            "SELECT * FROM students WHERE class_id = ? AND status != 'deleted' ORDER BY last_name, first_name",
+        )
+        .bind(class_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch students for class")?;
+
+        Ok(students)
+    }
+
+    /// Every student of the class, including soft-deleted ones.
+    pub async fn get_student_ids_in_class(&self, class_id: i64) -> Result<Vec<i64>> {
+        let ids = sqlx::query_scalar::<_, i64>("SELECT id FROM students WHERE class_id = ? ORDER BY id")
+            .bind(class_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch students for class")?;
+
+        Ok(ids)
+    }
+
+    /// Records the last school day of a student who leaves, e.g. mid-year
+    /// after a move. From that day on the student is inactive (see
+    /// `deactivate_students_who_left`, run by the scheduler for later dates)
+    /// and the retention period of their data runs; nothing is deleted.
+    pub async fn mark_student_left(&self, student_id: i64, left_at: chrono::NaiveDate) -> Result<Student> {
+        let student = self.get_student(student_id).await?.context("Student not found")?;
+        if student.status == "deleted" {
+            return Err(anyhow::anyhow!("Student {} was deleted", student_id));
+        }
+        if left_at < student.created_at.date_naive() {
+            return Err(anyhow::anyhow!("A student cannot leave before they were added"));
+        }
+
+        sqlx::query("UPDATE students SET left_at = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(left_at)
+            .bind(student_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to record that the student left")?;
+        self.record_change("student", student_id, "update", None).await?;
+        self.deactivate_students_who_left(chrono::Utc::now().date_naive()).await?;
+
+        self.get_student(student_id).await?.context("Student not found")
+    }
+
+    /// Students whose retention countdown may have started: those with a
+    /// leaving date and those no longer active, soft-deleted ones included.
+    pub async fn get_students_no_longer_attending(&self) -> Result<Vec<Student>> {
+        let students = sqlx::query_as::<_, Student>(
+            "SELECT * FROM students WHERE left_at IS NOT NULL OR status != 'active' ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch students who left")?;
+
+        Ok(students)
+    }
+
+    /// Makes active students inactive once their `left_at` has come.
+    pub async fn deactivate_students_who_left(&self, today: chrono::NaiveDate) -> Result<Vec<Student>> {
+        let students = sqlx::query_as::<_, Student>(
+            r#"
+            UPDATE students SET status = 'inactive', updated_at = CURRENT_TIMESTAMP
+            WHERE status = 'active' AND left_at IS NOT NULL AND left_at <= ?
+            RETURNING *
+            "#,
+        )
+        .bind(today)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to deactivate students who left")?;
+
+        for student in &students {
+            self.record_change("student", student.id, "update", None).await?;
+        }
+        Ok(students)
+    }
+
+    /// Corrects the record of a student (GDPR Art. 16) and returns it before
+    /// and after. A changed `class_id` fixes a wrong entry without a transfer;
+    /// moves during the year go through `transfer_student`. Making a student
+    /// who left active again clears `left_at`, or the scheduler would
+    /// deactivate them again.
+    pub async fn update_student(&self, student_id: i64, edit: StudentEdit) -> Result<(Student, Student)> {
+        let before = self.get_student(student_id).await?.context("Student not found")?;
+        if before.status == "deleted" {
+            return Err(anyhow::anyhow!("Student {} was deleted", student_id));
+        }
+        let first_name = edit.first_name.map(|n| n.trim().to_string()).unwrap_or_else(|| before.first_name.clone());
+        let last_name = edit.last_name.map(|n| n.trim().to_string()).unwrap_or_else(|| before.last_name.clone());
+        if first_name.is_empty() || last_name.is_empty() {
+            return Err(anyhow::anyhow!("First and last name must not be empty"));
+        }
+        let status = edit.status.unwrap_or_else(|| before.status.clone());
+        if !STUDENT_STATUSES.contains(&status.as_str()) {
+            return Err(anyhow::anyhow!("Unknown student status: {}", status));
+        }
+        let class_id = edit.class_id.unwrap_or(before.class_id);
+        if class_id != before.class_id && self.get_class(class_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Class not found"));
+        }
+        let today = chrono::Utc::now().date_naive();
+        let left_at = before.left_at.filter(|left_at| status != "active" || *left_at > today);
+        let date_of_birth = match edit.date_of_birth.as_deref().map(str::trim) {
+            None => before.date_of_birth,
+            Some("") => None,
+            Some(date) => Some(
+                chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .with_context(|| format!("Invalid date of birth: {}", date))?,
+            ),
+        };
+        let details = StudentDetails {
+            date_of_birth,
+            notes: edit.notes.or_else(|| before.notes.clone()),
+            external_id: edit.external_id.or_else(|| before.external_id.clone()),
+        };
+        let details = self.check_student_details(Some(student_id), details).await?;
+
+        if first_name == before.first_name
+            && last_name == before.last_name
+            && status == before.status
+            && class_id == before.class_id
+            && left_at == before.left_at
+            && details.date_of_birth == before.date_of_birth
+            && details.notes == before.notes
+            && details.external_id == before.external_id
+        {
+            return Ok((before.clone(), before));
+        }
+
+        let after = sqlx::query_as::<_, Student>(
+            r#"
+            UPDATE students
+            SET first_name = ?, last_name = ?, status = ?, class_id = ?, left_at = ?,
+                date_of_birth = ?, notes = ?, external_id = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&first_name)
+        .bind(&last_name)
+        .bind(&status)
+        .bind(class_id)
+        .bind(left_at)
+        .bind(details.date_of_birth)
+        .bind(&details.notes)
+        .bind(&details.external_id)
+        .bind(student_id)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to update student")?;
+        self.record_change("student", student_id, "update", None).await?;
+
+        Ok((before, after))
+    }
+
+    /// Moves a student to another class as of `transfer_date`. With `keep_history`
+    /// earlier observations stay attributed to the old class in reports,
+    /// otherwise they move along with the student.
+    pub async fn transfer_student(
+        &self,
+        student_id: i64,
+        new_class_id: i64,
+        transfer_date: chrono::DateTime<chrono::Utc>,
+        keep_history: bool,
+    ) -> Result<StudentTransfer> {
+        let student = self
+            .get_student(student_id)
+            .await?
+            .filter(|s| s.status != "deleted")
+            .context("Student not found")?;
+        if self.get_class(new_class_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Class not found"));
+        }
+        if student.class_id == new_class_id {
+            return Err(anyhow::anyhow!("Student is already in this class"));
+        }
+        if transfer_date > chrono::Utc::now() {
+            return Err(anyhow::anyhow!("Transfer date must not be in the future"));
+        }
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+        let transfer = sqlx::query_as::<_, StudentTransfer>(
+            r#"
+            INSERT INTO student_class_history
+                (student_id, from_class_id, to_class_id, transferred_at, keep_history, source_device_id, logical_clock)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(student_id)
+        .bind(student.class_id)
+        .bind(new_class_id)
+        .bind(transfer_date)
+        .bind(keep_history)
+        .bind(&device_id)
+        .bind(logical_clock)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to record transfer")?;
+
+        sqlx::query("UPDATE students SET class_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(new_class_id)
+            .bind(student_id)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to transfer student")?;
+
+        Self::record_change_on(&mut tx, &device_id, "student", student_id, "update", None).await?;
+        tx.commit().await?;
+
+        Ok(transfer)
+    }
+
+    /// Records a transfer received from another device. The student ends up in
+    /// the class of their latest transfer, whichever order files arrive in.
+    /// Returns false if the transfer was known already or its student or class
+    /// does not exist here.
+    async fn apply_transfer_on(conn: &mut SqliteConnection, transfer: &StudentTransfer) -> Result<bool> {
+        let known = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT (SELECT COUNT(*) FROM students WHERE id = ?) + (SELECT COUNT(*) FROM classes WHERE id = ?)
+            "#,
+        )
+        .bind(transfer.student_id)
+        .bind(transfer.to_class_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        if known < 2 {
+            return Ok(false);
+        }
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO student_class_history
+                (student_id, from_class_id, to_class_id, transferred_at, keep_history, recorded_at, source_device_id, logical_clock)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(transfer.student_id)
+        .bind(transfer.from_class_id)
+        .bind(transfer.to_class_id)
+        .bind(transfer.transferred_at)
+        .bind(transfer.keep_history)
+        .bind(transfer.recorded_at)
+        .bind(&transfer.source_device_id)
+        .bind(transfer.logical_clock)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to record transfer")?;
+        if inserted.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE students SET class_id = (
+                SELECT to_class_id FROM student_class_history WHERE student_id = ?
+                ORDER BY transferred_at DESC, logical_clock DESC, source_device_id DESC LIMIT 1
+            ), updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(transfer.student_id)
+        .bind(transfer.student_id)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to transfer student")?;
+
+        Ok(true)
+    }
+
+    pub async fn get_student_transfers(&self, student_id: i64) -> Result<Vec<StudentTransfer>> {
+        let transfers = sqlx::query_as::<_, StudentTransfer>(
+            "SELECT * FROM student_class_history WHERE student_id = ? ORDER BY transferred_at, logical_clock",
+        )
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch student transfers")?;
+
+        Ok(transfers)
+    }
+
+    pub async fn get_transfers_since(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<StudentTransfer>> {
+        let transfers = sqlx::query_as::<_, StudentTransfer>(
+            "SELECT * FROM student_class_history WHERE datetime(recorded_at) >= datetime(?) ORDER BY logical_clock",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch transfers")?;
+
+        Ok(transfers)
+    }
+
+    // Guardian operations
+    fn validate_guardian(input: &GuardianInput) -> Result<String> {
+        if input.name.trim().is_empty() || input.relationship.trim().is_empty() {
+            return Err(anyhow::anyhow!("Guardian name and relationship are required"));
+        }
+        if let Some(field) = input
+            .sensitive_fields
+            .iter()
+            .find(|field| !GUARDIAN_FIELDS.contains(&field.as_str()))
+        {
+            return Err(anyhow::anyhow!(
+                "Unknown guardian field '{}', expected one of: {}",
+                field,
+                GUARDIAN_FIELDS.join(", ")
+            ));
+        }
+        Ok(serde_json::to_string(&input.sensitive_fields)?)
+    }
+
+    pub async fn create_guardian(&self, student_id: i64, input: GuardianInput) -> Result<Guardian> {
+        let sensitive_fields = Self::validate_guardian(&input)?;
+        if self.get_student(student_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Student not found"));
+        }
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+
+        let guardian = sqlx::query_as::<_, Guardian>(
+            r#"
+            INSERT INTO guardians
+                (student_id, name, contact, relationship, consent_reference, sensitive_fields, source_device_id, logical_clock, uuid)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(student_id)
+        .bind(input.name.trim())
+        .bind(&input.contact)
+        .bind(input.relationship.trim())
+        .bind(&input.consent_reference)
+        .bind(&sensitive_fields)
+        .bind(&device_id)
+        .bind(logical_clock)
+        .bind(record_uuid(None))
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to create guardian")?;
+
+        Self::record_change_on(&mut tx, &device_id, "guardian", guardian.id, "create", None).await?;
+        tx.commit().await?;
+
+        Ok(guardian)
+    }
+
+    pub async fn update_guardian(&self, guardian_id: i64, input: GuardianInput) -> Result<Guardian> {
+        let sensitive_fields = Self::validate_guardian(&input)?;
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+
+        let guardian = sqlx::query_as::<_, Guardian>(
+            r#"
+            UPDATE guardians
+            SET name = ?, contact = ?, relationship = ?, consent_reference = ?, sensitive_fields = ?,
+                updated_at = CURRENT_TIMESTAMP, source_device_id = ?, logical_clock = ?
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(input.name.trim())
+        .bind(&input.contact)
+        .bind(input.relationship.trim())
+        .bind(&input.consent_reference)
+        .bind(&sensitive_fields)
+        .bind(&device_id)
+        .bind(logical_clock)
+        .bind(guardian_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .context("Failed to update guardian")?
+        .context("Guardian not found")?;
+
+        Self::record_change_on(&mut tx, &device_id, "guardian", guardian_id, "update", None).await?;
+        tx.commit().await?;
+
+        Ok(guardian)
+    }
+
+    pub async fn delete_guardian(&self, guardian_id: i64) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let uuid = Self::entity_uuid_on(&mut conn, "guardian", guardian_id).await?;
+        drop(conn);
+
+        let result = sqlx::query("DELETE FROM guardians WHERE id = ?")
+            .bind(guardian_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete guardian")?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Guardian not found"));
+        }
+
+        self.record_deletion("guardian", guardian_id, uuid, "hard").await
+    }
+
+    pub async fn get_guardians(&self, student_id: i64) -> Result<Vec<Guardian>> {
+        let guardians = sqlx::query_as::<_, Guardian>(
+            "SELECT * FROM guardians WHERE student_id = ? ORDER BY name, id",
+        )
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch guardians")?;
+
+        Ok(guardians)
+    }
+
+    /// Date of birth, notes and external id of the students changed since
+    /// `since`. The students themselves come with backups and handovers; a
+    /// changeset only carries these fields of students the other device has.
+    async fn get_student_details_for_sync(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<serde_json::Value>> {
+        let students = sqlx::query_as::<_, Student>(
+            "SELECT * FROM students WHERE status != 'deleted' AND datetime(updated_at) >= datetime(?) ORDER BY id",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch students for sync")?;
+
+        Ok(students
+            .into_iter()
+            .map(|student| {
+                serde_json::json!({
+                    "id": student.id,
+                    "uuid": student.uuid,
+                    "date_of_birth": student.date_of_birth,
+                    "notes": student.notes,
+                    "external_id": student.external_id,
+                    "updated_at": student.updated_at,
+                })
+            })
+            .collect())
+    }
+
+    /// Merges the details of a student from a changeset unless the local
+    /// record changed later. Students the device does not have are skipped;
+    /// they are found by uuid, the sender's id may name another student here.
+    async fn apply_student_details_on(conn: &mut SqliteConnection, record: &serde_json::Value) -> Result<bool> {
+        let (Some(uuid), Some(updated_at)) = (
+            record.get("uuid").and_then(|v| v.as_str()),
+            record
+                .get("updated_at")
+                .and_then(|t| serde_json::from_value::<chrono::DateTime<chrono::Utc>>(t.clone()).ok()),
+        ) else {
+            return Ok(false);
+        };
+        let Some(local) = sqlx::query_as::<_, Student>("SELECT * FROM students WHERE uuid = ? AND status != 'deleted'")
+            .bind(uuid)
+            .fetch_optional(&mut *conn)
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        let text = |key: &str| record.get(key).and_then(|v| v.as_str()).map(|v| v.to_string());
+        let date_of_birth = record
+            .get("date_of_birth")
+            .and_then(|d| serde_json::from_value::<Option<chrono::NaiveDate>>(d.clone()).ok())
+            .flatten();
+        let (notes, external_id) = (text("notes"), text("external_id"));
+        if updated_at < local.updated_at
+            || (date_of_birth == local.date_of_birth && notes == local.notes && external_id == local.external_id)
+        {
+            return Ok(false);
+        }
+
+        sqlx::query("UPDATE students SET date_of_birth = ?, notes = ?, external_id = ?, updated_at = ? WHERE id = ?")
+            .bind(date_of_birth)
+            .bind(&notes)
+            .bind(&external_id)
+            .bind(updated_at)
+            .bind(local.id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(true)
+    }
+
+    /// Guardians changed since `since` as they go into a changeset: sensitive
+    /// fields are removed, so they never leave this device.
+    async fn get_guardians_for_sync(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<serde_json::Value>> {
+        let guardians = sqlx::query_as::<_, Guardian>(
+            "SELECT * FROM guardians WHERE datetime(updated_at) >= datetime(?) ORDER BY logical_clock",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch guardians for sync")?;
+
+        let mut records = Vec::with_capacity(guardians.len());
+        for guardian in guardians {
+            let sensitive: Vec<String> = serde_json::from_str(&guardian.sensitive_fields).unwrap_or_default();
+            // The receiving device knows the student by its uuid, not by our id
+            let student_uuid = sqlx::query_scalar::<_, Option<String>>("SELECT uuid FROM students WHERE id = ?")
+                .bind(guardian.student_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .flatten();
+            let mut record = serde_json::to_value(&guardian)?;
+            if let Some(fields) = record.as_object_mut() {
+                for field in &sensitive {
+                    fields.remove(field);
+                }
+                fields.insert("student_uuid".to_string(), serde_json::json!(student_uuid));
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Merges a guardian from a changeset; the higher (logical_clock, device)
+    /// pair wins. Fields the other device withheld keep their local value.
+    /// Guardian and student are matched by uuid, as the ids of the sending
+    /// device may name other records here.
+    async fn apply_guardian_on(conn: &mut SqliteConnection, record: &serde_json::Value) -> Result<bool> {
+        let int = |key: &str| record.get(key).and_then(|v| v.as_i64());
+        let text = |key: &str| record.get(key).and_then(|v| v.as_str()).map(|v| v.to_string());
+        let (Some(uuid), Some(student_uuid), Some(logical_clock), Some(device_id)) =
+            (text("uuid"), text("student_uuid"), int("logical_clock"), text("source_device_id"))
+        else {
+            return Ok(false);
+        };
+
+        let Some(student_id) = sqlx::query_scalar::<_, i64>("SELECT id FROM students WHERE uuid = ?")
+            .bind(&student_uuid)
+            .fetch_optional(&mut *conn)
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        let deleted_clock = sqlx::query_scalar::<_, Option<i64>>(
+            "SELECT MAX(logical_clock) FROM change_log WHERE entity_type = 'guardian' AND entity_uuid = ? AND operation = 'delete'",
+        )
+        .bind(&uuid)
+        .fetch_one(&mut *conn)
+        .await?;
+        if deleted_clock.is_some_and(|clock| clock >= logical_clock) {
+            return Ok(false);
+        }
+
+        let existing = sqlx::query_as::<_, (i64, i64, String)>(
+            "SELECT id, logical_clock, source_device_id FROM guardians WHERE uuid = ?",
+        )
+        .bind(&uuid)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if let Some((_, local_clock, local_device)) = &existing {
+            if (logical_clock, &device_id) <= (*local_clock, local_device) {
+                return Ok(false);
+            }
+        }
+
+        let updated_at = record
+            .get("updated_at")
+            .and_then(|t| serde_json::from_value::<chrono::DateTime<chrono::Utc>>(t.clone()).ok())
+            .unwrap_or_else(chrono::Utc::now);
+        match existing {
+            Some((id, _, _)) => {
+                sqlx::query(
+                    r#"
+                    UPDATE guardians SET
+                        student_id = ?,
+                        name = COALESCE(?, name),
+                        contact = CASE WHEN ? THEN ? ELSE contact END,
+                        relationship = COALESCE(?, relationship),
+                        consent_reference = CASE WHEN ? THEN ? ELSE consent_reference END,
+                        sensitive_fields = COALESCE(?, '[]'),
+                        updated_at = ?,
+                        source_device_id = ?,
+                        logical_clock = ?
+                    WHERE id = ?
+                    "#,
+                )
+                .bind(student_id)
+                .bind(text("name"))
+                .bind(record.get("contact").is_some())
+                .bind(text("contact"))
+                .bind(text("relationship"))
+                .bind(record.get("consent_reference").is_some())
+                .bind(text("consent_reference"))
+                .bind(text("sensitive_fields"))
+                .bind(updated_at)
+                .bind(&device_id)
+                .bind(logical_clock)
+                .bind(id)
+                .execute(&mut *conn)
+                .await
+                .context("Failed to merge guardian")?;
+            }
+            None => {
+                // Withheld fields are absent from the record; new rows get empty placeholders
+                sqlx::query(
+                    r#"
+                    INSERT INTO guardians
+                        (student_id, name, contact, relationship, consent_reference, sensitive_fields, updated_at, source_device_id, logical_clock, uuid)
+                    VALUES (?, COALESCE(?, ''), ?, COALESCE(?, ''), ?, COALESCE(?, '[]'), ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(student_id)
+                .bind(text("name"))
+                .bind(text("contact"))
+                .bind(text("relationship"))
+                .bind(text("consent_reference"))
+                .bind(text("sensitive_fields"))
+                .bind(updated_at)
+                .bind(&device_id)
+                .bind(logical_clock)
+                .bind(&uuid)
+                .execute(&mut *conn)
+                .await
+                .context("Failed to merge guardian")?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub async fn get_class_layout(&self, class_id: i64) -> Result<Option<ClassLayout>> {
+        let layout = sqlx::query_as::<_, ClassLayout>("SELECT * FROM class_layouts WHERE class_id = ?")
+            .bind(class_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch class layout")?;
+
+        Ok(layout)
+    }
+
+    /// Records or replaces the agreement of `input.kind` for a class.
+    pub async fn set_class_agreement(&self, class_id: i64, input: &ClassAgreementInput) -> Result<ClassAgreement> {
+        if !CLASS_AGREEMENT_KINDS.contains(&input.kind.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Unknown agreement kind {:?}, expected one of {}",
+                input.kind,
+                CLASS_AGREEMENT_KINDS.join(", ")
+            ));
+        }
+        if input.valid_until.is_some_and(|until| until < input.collected_on) {
+            return Err(anyhow::anyhow!("The agreement cannot end before it was collected"));
+        }
+        if self.get_class(class_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Class not found"));
+        }
+        let trimmed = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(String::from);
+
+        let agreement = sqlx::query_as::<_, ClassAgreement>(
+            r#"
+            INSERT INTO class_agreements (class_id, kind, collected_on, valid_until, reference, notes)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(class_id, kind) DO UPDATE SET
+                collected_on = excluded.collected_on,
+                valid_until = excluded.valid_until,
+                reference = excluded.reference,
+                notes = excluded.notes,
+                updated_at = CURRENT_TIMESTAMP
+            RETURNING *
+            "#,
+        )
+        .bind(class_id)
+        .bind(&input.kind)
+        .bind(input.collected_on)
+        .bind(input.valid_until)
+        .bind(trimmed(&input.reference))
+        .bind(trimmed(&input.notes))
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to store class agreement")?;
+
+        Ok(agreement)
+    }
+
+    /// Agreements of one class, or of all classes.
+    pub async fn get_class_agreements(&self, class_id: Option<i64>) -> Result<Vec<ClassAgreement>> {
+        let agreements = sqlx::query_as::<_, ClassAgreement>(
+            "SELECT * FROM class_agreements WHERE ? IS NULL OR class_id = ? ORDER BY class_id, kind",
+        )
+        .bind(class_id)
+        .bind(class_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch class agreements")?;
+
+        Ok(agreements)
+    }
+
+    /// The agreement of `kind` for a class if it is in force on `date`.
+    pub async fn get_class_agreement_in_force(
+        &self,
+        class_id: i64,
+        kind: &str,
+        date: chrono::NaiveDate,
+    ) -> Result<Option<ClassAgreement>> {
+        let agreement = sqlx::query_as::<_, ClassAgreement>(
+            r#"
+            SELECT * FROM class_agreements
+            WHERE class_id = ? AND kind = ? AND collected_on <= ? AND (valid_until IS NULL OR valid_until >= ?)
+            "#,
+        )
+        .bind(class_id)
+        .bind(kind)
+        .bind(date)
+        .bind(date)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch class agreement")?;
+
+        Ok(agreement)
+    }
+
+    /// Fails unless the class has an agreement of `kind` in force today.
+    pub async fn require_class_agreement(&self, class_id: i64, kind: &str) -> Result<ClassAgreement> {
+        let today = chrono::Local::now().date_naive();
+        match self.get_class_agreement_in_force(class_id, kind, today).await? {
+            Some(agreement) => Ok(agreement),
+            None => {
+                let class = self.get_class(class_id).await?.context("Class not found")?;
+                Err(anyhow::anyhow!("No {} agreement in force for class {}", kind, class.name))
+            }
+        }
+    }
+
+    pub async fn delete_class_agreement(&self, class_id: i64, kind: &str) -> Result<()> {
+        let result = sqlx::query("DELETE FROM class_agreements WHERE class_id = ? AND kind = ?")
+            .bind(class_id)
+            .bind(kind)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete class agreement")?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Class agreement not found"));
+        }
+        Ok(())
+    }
+
+    /// The trimmed term, once it is long enough to be matched and not in the
+    /// dictionary yet for the same student.
+    async fn validate_anonymization_term(&self, input: &AnonymizationTermInput, id: Option<i64>) -> Result<String> {
+        let term = input.term.trim();
+        // The redaction engine skips single characters, they would match everywhere
+        if term.chars().count() < 2 {
+            return Err(anyhow::anyhow!("Dictionary terms need at least two characters"));
+        }
+        if let Some(student_id) = input.student_id {
+            if self.get_student(student_id).await?.is_none() {
+                return Err(anyhow::anyhow!("Student not found"));
+            }
+        }
+        let duplicate = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM anonymization_terms
+            WHERE term = ? COLLATE NOCASE AND student_id IS ? AND (? IS NULL OR id != ?)
+            "#,
+        )
+        .bind(term)
+        .bind(input.student_id)
+        .bind(id)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to check the anonymization dictionary")?;
+        if duplicate > 0 {
+            return Err(anyhow::anyhow!("'{}' is already in the anonymization dictionary", term));
+        }
+        Ok(term.to_string())
+    }
+
+    pub async fn create_anonymization_term(&self, input: &AnonymizationTermInput) -> Result<AnonymizationTerm> {
+        let term = self.validate_anonymization_term(input, None).await?;
+        let stored = sqlx::query_as::<_, AnonymizationTerm>(
+            "INSERT INTO anonymization_terms (term, student_id, note) VALUES (?, ?, ?) RETURNING *",
+        )
+        .bind(term)
+        .bind(input.student_id)
+        .bind(input.note.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to store anonymization term")?;
+
+        Ok(stored)
+    }
+
+    pub async fn update_anonymization_term(&self, id: i64, input: &AnonymizationTermInput) -> Result<AnonymizationTerm> {
+        let term = self.validate_anonymization_term(input, Some(id)).await?;
+        let stored = sqlx::query_as::<_, AnonymizationTerm>(
+            r#"
+            UPDATE anonymization_terms SET term = ?, student_id = ?, note = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(term)
+        .bind(input.student_id)
+        .bind(input.note.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to update anonymization term")?;
+
+        stored.context("Anonymization term not found")
+    }
+
+    pub async fn get_anonymization_terms(&self) -> Result<Vec<AnonymizationTerm>> {
+        let terms = sqlx::query_as::<_, AnonymizationTerm>(
+            "SELECT * FROM anonymization_terms ORDER BY term COLLATE NOCASE, id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch anonymization terms")?;
+
+        Ok(terms)
+    }
+
+    pub async fn delete_anonymization_term(&self, id: i64) -> Result<()> {
+        let result = sqlx::query("DELETE FROM anonymization_terms WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete anonymization term")?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Anonymization term not found"));
+        }
+        Ok(())
+    }
+
+    pub async fn create_student_goal(&self, student_id: i64, text: &str) -> Result<StudentGoal> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(anyhow::anyhow!("A goal needs a text"));
+        }
+        let student = self.get_student(student_id).await?.context("Student not found")?;
+        if student.status == "deleted" {
+            return Err(anyhow::anyhow!("Student {} was deleted", student_id));
+        }
+
+        let goal = sqlx::query_as::<_, StudentGoal>(
+            "INSERT INTO student_goals (student_id, text) VALUES (?, ?) RETURNING *",
+        )
+        .bind(student_id)
+        .bind(text)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create goal")?;
+
+        Ok(goal)
+    }
+
+    /// Active goals first, then the newest.
+    pub async fn get_student_goals(&self, student_id: i64) -> Result<Vec<StudentGoal>> {
+        let goals = sqlx::query_as::<_, StudentGoal>(
+            "SELECT * FROM student_goals WHERE student_id = ? ORDER BY status != 'active', created_at DESC, id DESC",
+        )
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch goals")?;
+
+        Ok(goals)
+    }
+
+    /// Active goals of the class's active students.
+    pub async fn get_active_class_goals(&self, class_id: i64) -> Result<Vec<StudentGoal>> {
+        let goals = sqlx::query_as::<_, StudentGoal>(
+            r#"
+            SELECT g.* FROM student_goals g
+            JOIN students s ON s.id = g.student_id
+            WHERE s.class_id = ? AND s.status = 'active' AND g.status = 'active'
+            ORDER BY g.created_at, g.id
+            "#,
+        )
+        .bind(class_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch goals")?;
+
+        Ok(goals)
+    }
+
+    pub async fn set_student_goal_status(&self, goal_id: i64, status: &str) -> Result<StudentGoal> {
+        if !GOAL_STATUSES.contains(&status) {
+            return Err(anyhow::anyhow!("Status must be one of: {}", GOAL_STATUSES.join(", ")));
+        }
+        let goal = sqlx::query_as::<_, StudentGoal>(
+            "UPDATE student_goals SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? RETURNING *",
+        )
+        .bind(status)
+        .bind(goal_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to update goal")?;
+
+        goal.context("Goal not found")
+    }
+
+    /// Image attachments of the class's observations, oldest first. Photos
+    /// whose payload is still on another device are left out.
+    pub async fn get_class_photos(&self, class_id: i64) -> Result<Vec<(Attachment, Observation)>> {
+        let attachments = sqlx::query_as::<_, Attachment>(
+            r#"
+            SELECT a.id, a.observation_id, a.filename, a.content_type, a.file_hash, a.size_bytes, a.storage, a.created_at
+            FROM attachments a
+            JOIN observations o ON o.id = a.observation_id
+            JOIN students s ON s.id = o.student_id
+            WHERE s.class_id = ? AND a.content_type LIKE 'image/%' AND a.storage != 'remote'
+            ORDER BY o.created_at, a.id
+            "#,
+        )
+        .bind(class_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch class photos")?;
+
+        let mut photos = Vec::with_capacity(attachments.len());
+        for attachment in attachments {
+            let observation = self
+                .get_observation(attachment.observation_id)
+                .await?
+                .context("Observation not found")?;
+            photos.push((attachment, observation));
+        }
+        Ok(photos)
+    }
+
+    /// Classes with photos attached to observations, for the compliance report.
+    pub async fn get_class_ids_with_photos(&self) -> Result<Vec<i64>> {
+        let class_ids = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT DISTINCT s.class_id FROM attachments a
+            JOIN observations o ON o.id = a.observation_id
+            JOIN students s ON s.id = o.student_id
+            WHERE a.content_type LIKE 'image/%'
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch classes with photos")?;
+
+        Ok(class_ids)
+    }
+
+    pub async fn set_class_layout(&self, class_id: i64, layout: &str) -> Result<ClassLayout> {
+        let layout = crate::class_layout::validate(layout)?.to_string();
+        let class_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM classes WHERE id = ?")
+            .bind(class_id)
+            .fetch_one(&self.pool)
+            .await?;
+        if class_exists == 0 {
+            return Err(anyhow::anyhow!("Class not found"));
+        }
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+
+        let stored = sqlx::query_as::<_, ClassLayout>(
+            r#"
+            INSERT INTO class_layouts (class_id, layout, source_device_id, logical_clock)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(class_id) DO UPDATE SET
+                layout = excluded.layout,
+                updated_at = CURRENT_TIMESTAMP,
+                source_device_id = excluded.source_device_id,
+                logical_clock = excluded.logical_clock
+            RETURNING *
+            "#,
+        )
+        .bind(class_id)
+        .bind(&layout)
+        .bind(&device_id)
+        .bind(logical_clock)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to store class layout")?;
+
+        Self::record_change_on(&mut tx, &device_id, "class_layout", class_id, "update", None).await?;
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn get_class_layouts_since(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<ClassLayout>> {
+        let layouts = sqlx::query_as::<_, ClassLayout>(
+            "SELECT * FROM class_layouts WHERE datetime(updated_at) >= datetime(?) ORDER BY logical_clock",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch class layouts for sync")?;
+
+        Ok(layouts)
+    }
+
+    /// Merges a layout from a changeset; as with guardians, the higher
+    /// (logical_clock, device) pair wins.
+    async fn apply_class_layout_on(conn: &mut SqliteConnection, layout: &ClassLayout) -> Result<bool> {
+        if crate::class_layout::validate(&layout.layout).is_err() {
+            return Ok(false);
+        }
+        let class_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM classes WHERE id = ?")
+            .bind(layout.class_id)
+            .fetch_one(&mut *conn)
+            .await?;
+        if class_exists == 0 {
+            return Ok(false);
+        }
+
+        let existing = sqlx::query_as::<_, (i64, String)>(
+            "SELECT logical_clock, source_device_id FROM class_layouts WHERE class_id = ?",
+        )
+        .bind(layout.class_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if let Some((local_clock, local_device)) = existing {
+            if (layout.logical_clock, &layout.source_device_id) <= (local_clock, &local_device) {
+                return Ok(false);
+            }
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO class_layouts (class_id, layout, updated_at, source_device_id, logical_clock)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(class_id) DO UPDATE SET
+                layout = excluded.layout,
+                updated_at = excluded.updated_at,
+                source_device_id = excluded.source_device_id,
+                logical_clock = excluded.logical_clock
+            "#,
+        )
+        .bind(layout.class_id)
+        .bind(&layout.layout)
+        .bind(layout.updated_at)
+        .bind(&layout.source_device_id)
+        .bind(layout.logical_clock)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to merge class layout")?;
+
+        Ok(true)
+    }
+
+    /// Erased students must not linger in a seating plan, including that of a
+    /// class they have left. Each device does this itself when it applies the
+    /// deletion, so it is not recorded as a change.
+    async fn remove_student_from_layouts_on(conn: &mut SqliteConnection, student_id: i64) -> Result<()> {
+        let layouts = sqlx::query_as::<_, (i64, String)>("SELECT class_id, layout FROM class_layouts")
+            .fetch_all(&mut *conn)
+            .await?;
+        for (class_id, layout) in layouts {
+            let Ok(mut layout) = serde_json::from_str::<serde_json::Value>(&layout) else {
+                continue;
+            };
+            if crate::class_layout::remove_student(&mut layout, student_id) {
+                sqlx::query("UPDATE class_layouts SET layout = ? WHERE class_id = ?")
+                    .bind(layout.to_string())
+                    .bind(class_id)
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn delete_student(&self, student_id: i64, force_delete: bool) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let uuid = Self::entity_uuid_on(&mut conn, "student", student_id).await?;
+        drop(conn);
+
+        if force_delete {
+            // Hard delete: remove student and all observations
+            let mut conn = self.pool.acquire().await?;
+            Self::record_retractions_on(&mut conn, "o.student_id = ?", student_id, "deleted").await?;
+            drop(conn);
+
+            // Stored class reports embed the student's observations, so they go too
+            sqlx::query("DELETE FROM report_artifacts WHERE class_id = (SELECT class_id FROM students WHERE id = ?)")
+                .bind(student_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM attachments WHERE observation_id IN (SELECT id FROM observations WHERE student_id = ?)")
+                .bind(student_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM observations WHERE student_id = ?")
+                .bind(student_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM guardians WHERE student_id = ?")
+                .bind(student_id)
+                .execute(&self.pool)
+                .await?;
+
+            let mut conn = self.pool.acquire().await?;
+            Self::remove_student_from_layouts_on(&mut conn, student_id).await?;
+            drop(conn);
+            self.purge_staged_imports_of_student(student_id).await?;
+
+            sqlx::query("DELETE FROM students WHERE id = ?")
+                .bind(student_id)
+                .execute(&self.pool)
+                .await?;
+        } else {
+            // Soft delete: mark as deleted
+            sqlx::query("UPDATE students SET status = 'deleted', updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(student_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let deletion_type = if force_delete { "hard" } else { "soft" };
+        self.record_deletion("student", student_id, uuid, deletion_type).await?;
+        self.prune_attachment_files().await?;
+
+        Ok(())
+    }
+
+    // Observation operations
+    pub async fn create_observation(
+        &self,
+        student_id: i64,
+        author_id: i64,
+        category: String,
+        text: String,
+        tags: Vec<String>,
+    ) -> Result<Observation> {
+        // Observation, clock and change log commit together, which also makes
+        // the insert safe to retry when the database is busy
+        let entry = NewObservation {
+            student_id,
+            category,
+            text,
+            tags,
+            created_at: None,
+            local_only: false,
+            visibility: None,
+            subject: None,
+            reported_by_user_id: None,
+            reported_by: None,
+        };
+        self.create_observations_batch(author_id, vec![entry])
+            .await?
+            .pop()
+            .context("Failed to create observation")
+    }
+
+    /// Creates several observations in a single transaction. Rapid entry during
+    /// a lesson then costs one commit instead of one per observation, and
+    /// either all entries are stored or none.
+    pub async fn create_observations_batch(
+        &self,
+        author_id: i64,
+        entries: Vec<NewObservation>,
+    ) -> Result<Vec<Observation>> {
+        self.retry_on_busy(|| self.insert_observations(author_id, &entries))
+            .await
+    }
+
+    async fn insert_observations(
+        &self,
+        author_id: i64,
+        entries: &[NewObservation],
+    ) -> Result<Vec<Observation>> {
+        let device_id = self.crypto.get_device_id();
+        let default_visibility = self.get_default_visibility(author_id).await?;
+        for visibility in entries.iter().filter_map(|e| e.visibility.as_deref()) {
+            validate_visibility(visibility)?;
+        }
+        for subject in entries.iter().filter_map(|e| e.subject.as_deref()) {
+            self.ensure_subject_exists(subject).await?;
+        }
+        for entry in entries {
+            self.validate_reported_by(author_id, entry).await?;
+        }
+        let mut tx = self.pool.begin().await?;
+        let mut observations = Vec::with_capacity(entries.len());
+
+        for entry in entries {
+            let tags_json = serde_json::to_string(&entry.tags).unwrap_or_else(|_| "[]".to_string());
+            let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+
+            let observation = sqlx::query_as::<_, Observation>(
+                r#"
+                INSERT INTO observations (student_id, author_id, category, text, language, tags, created_at, source_device_id, logical_clock, local_only, visibility, session_id, subject, reported_by_user_id, reported_by, uuid)
+                VALUES (?, ?, ?, ?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP), ?, ?, ?, ?,
+                        (SELECT os.id FROM observation_sessions os JOIN students s ON s.class_id = os.class_id
+                         WHERE os.ended_at IS NULL AND s.id = ?),
+                        COALESCE(?, (SELECT os.subject FROM observation_sessions os JOIN students s ON s.class_id = os.class_id
+                                     WHERE os.ended_at IS NULL AND s.id = ?)),
+                        ?, ?, ?)
+                RETURNING *
+                "#,
+            )
+            .bind(entry.student_id)
+            .bind(author_id)
+            .bind(&entry.category)
+            .bind(&entry.text)
+            .bind(crate::language::detect(&entry.text))
+            .bind(&tags_json)
+            .bind(entry.created_at)
+            .bind(&device_id)
+            .bind(logical_clock)
+            .bind(entry.local_only)
+            .bind(entry.visibility.as_deref().unwrap_or(&default_visibility))
+            .bind(entry.student_id)
+            .bind(&entry.subject)
+            .bind(entry.student_id)
+            .bind(entry.reported_by_user_id)
+            .bind(entry.reported_by.as_deref().map(str::trim))
+            .bind(record_uuid(None))
+            .fetch_one(&mut *tx)
+            .await
+            .context("Failed to create observation")?;
+
+            Self::record_change_on(&mut tx, &device_id, "observation", observation.id, "create", None).await?;
+            observations.push(observation);
+        }
+
+        tx.commit().await?;
+
+        Ok(observations)
+    }
+
+    /// Either a colleague's account or a name, and never the author themselves.
+    async fn validate_reported_by(&self, author_id: i64, entry: &NewObservation) -> Result<()> {
+        let name = entry.reported_by.as_deref().map(str::trim);
+        match (entry.reported_by_user_id, name) {
+            (Some(_), Some(_)) => Err(anyhow::anyhow!("Give either the reporting user or a name, not both")),
+            (None, Some("")) => Err(anyhow::anyhow!("The name of the reporting person must not be empty")),
+            (Some(user_id), None) if user_id == author_id => {
+                Err(anyhow::anyhow!("The author cannot be the reporting colleague as well"))
+            }
+            (Some(user_id), None) => {
+                self.get_user(user_id).await?.context("Reporting user not found")?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Observation session operations
+    /// Observations created for students of the class are grouped under the
+    /// session until it ends. One session runs at a time on a device.
+    pub async fn start_observation_session(
+        &self,
+        class_id: i64,
+        title: Option<&str>,
+        subject: Option<&str>,
+        viewer_id: i64,
+    ) -> Result<ObservationSession> {
+        if let Some(subject) = subject {
+            self.ensure_subject_exists(subject).await?;
+        }
+        if let Some(running) = self.get_active_observation_session(viewer_id).await? {
+            return Err(anyhow::anyhow!(
+                "Observation session {} is still running; end it first",
+                running.title.unwrap_or_else(|| running.id.to_string())
+            ));
+        }
+        let session_id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO observation_sessions (class_id, title, subject) VALUES (?, ?, ?) RETURNING id",
+        )
+        .bind(class_id)
+        .bind(title.map(str::trim).filter(|t| !t.is_empty()))
+        .bind(subject)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to start observation session")?;
+
+        self.get_observation_session(session_id, viewer_id)
+            .await?
+            .context("Observation session not found")
+    }
+
+    pub async fn end_observation_session(&self, viewer_id: i64) -> Result<ObservationSession> {
+        let session_id = sqlx::query_scalar::<_, i64>(
+            "UPDATE observation_sessions SET ended_at = CURRENT_TIMESTAMP WHERE ended_at IS NULL RETURNING id",
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to end observation session")?
+        .context("No observation session is running")?;
+
+        self.get_observation_session(session_id, viewer_id)
+            .await?
+            .context("Observation session not found")
+    }
+
+    pub async fn get_active_observation_session(&self, viewer_id: i64) -> Result<Option<ObservationSession>> {
+        let session = sqlx::query_as::<_, ObservationSession>(&format!(
+            "{} WHERE os.ended_at IS NULL GROUP BY os.id",
+            OBSERVATION_SESSIONS_QUERY
+        ))
+        .bind(VISIBILITY_PRIVATE)
+        .bind(viewer_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch observation session")?;
+
+        Ok(session)
+    }
+
+    pub async fn get_observation_session(&self, session_id: i64, viewer_id: i64) -> Result<Option<ObservationSession>> {
+        let session = sqlx::query_as::<_, ObservationSession>(&format!(
+            "{} WHERE os.id = ? GROUP BY os.id",
+            OBSERVATION_SESSIONS_QUERY
+        ))
+        .bind(VISIBILITY_PRIVATE)
+        .bind(viewer_id)
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch observation session")?;
+
+        Ok(session)
+    }
+
+    /// Newest first.
+    pub async fn get_observation_sessions(&self, class_id: Option<i64>, viewer_id: i64) -> Result<Vec<ObservationSession>> {
+        let sessions = sqlx::query_as::<_, ObservationSession>(&format!(
+            "{} WHERE ? IS NULL OR os.class_id = ? GROUP BY os.id ORDER BY os.started_at DESC, os.id DESC",
+            OBSERVATION_SESSIONS_QUERY
+        ))
+        .bind(VISIBILITY_PRIVATE)
+        .bind(viewer_id)
+        .bind(class_id)
+        .bind(class_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch observation sessions")?;
+
+        Ok(sessions)
+    }
+
+    /// The session's observations `viewer_id` may see, in the order they were made.
+    pub async fn get_session_observations(&self, session_id: i64, viewer_id: i64) -> Result<Vec<Observation>> {
+        let observations = sqlx::query_as::<_, Observation>(
+            r#"
+            SELECT * FROM observations
+            WHERE session_id = ? AND (visibility = 'team' OR author_id = ?)
+            ORDER BY datetime(created_at), id
+            "#,
+        )
+        .bind(session_id)
+        .bind(viewer_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch session observations")?;
+
+        Ok(observations)
+    }
+
+    /// With `subject`, only the observations of that subject are counted.
+    pub async fn get_session_statistics(
+        &self,
+        session_id: i64,
+        viewer_id: i64,
+        subject: Option<&str>,
+    ) -> Result<SessionStatistics> {
+        let session = self
+            .get_observation_session(session_id, viewer_id)
+            .await?
+            .context("Observation session not found")?;
+        let mut observations = self.get_session_observations(session_id, viewer_id).await?;
+        if let Some(subject) = subject {
+            observations.retain(|o| o.subject.as_deref() == Some(subject));
+        }
+        let students_in_class = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM students WHERE class_id = ? AND status != 'deleted'",
+        )
+        .bind(session.class_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let merges = self.get_category_merges().await?;
+        let mut by_category = BTreeMap::new();
+        let mut by_subject = BTreeMap::new();
+        let mut by_student = BTreeMap::new();
+        for observation in &observations {
+            let category = merges.get(&observation.category).unwrap_or(&observation.category);
+            *by_category.entry(category.clone()).or_default() += 1;
+            if let Some(subject) = &observation.subject {
+                *by_subject.entry(subject.clone()).or_default() += 1;
+            }
+            *by_student.entry(observation.student_id).or_default() += 1;
+        }
+        let rubric_filter = RubricAggregateFilter {
+            session_id: Some(session_id),
+            subject: subject.map(str::to_string),
+            ..Default::default()
+        };
+        let rubrics = self.get_rubric_aggregates(&rubric_filter, viewer_id).await?;
+        Ok(SessionStatistics {
+            session,
+            observations: observations.len() as i64,
+            students_in_class,
+            by_category,
+            by_subject,
+            by_student,
+            rubrics,
+        })
+    }
+
+    /// Observations per category for each class, side by side, as the
+    /// viewer can see them. Merged categories count under their target.
+    pub async fn compare_class_statistics(&self, class_ids: &[i64], viewer_id: i64) -> Result<ClassStatisticsComparison> {
+        if class_ids.is_empty() {
+            return Err(anyhow::anyhow!("Choose at least one class to compare"));
+        }
+        let mut classes = Vec::with_capacity(class_ids.len());
+        for &class_id in class_ids {
+            let class = self.get_class(class_id).await?.context("Class not found")?;
+            let students = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM students WHERE class_id = ? AND status != 'deleted'",
+            )
+            .bind(class_id)
+            .fetch_one(&self.pool)
+            .await?;
+            let counts = sqlx::query_as::<_, (String, i64, i64)>(
+                r#"
+                SELECT COALESCE(t.name, o.category) AS name, COUNT(*), COUNT(DISTINCT o.student_id)
+                FROM observations o
+                JOIN students s ON s.id = o.student_id
+                LEFT JOIN categories c ON c.name = o.category
+                LEFT JOIN categories t ON t.id = c.merged_into
+                WHERE s.class_id = ? AND s.status != 'deleted' AND (o.visibility != ? OR o.author_id = ?)
+                GROUP BY name
+                "#,
+            )
+            .bind(class_id)
+            .bind(VISIBILITY_PRIVATE)
+            .bind(viewer_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to count observations per category")?;
+
+            let observations: i64 = counts.iter().map(|(_, count, _)| count).sum();
+            let mut hidden: Vec<String> = counts
+                .iter()
+                .filter(|(_, _, students)| *students < MIN_STUDENTS_PER_CELL)
+                .map(|(category, _, _)| category.clone())
+                .collect();
+            // The total minus the shown counts would give a single hidden
+            // category away, so the smallest shown one is hidden with it
+            if hidden.len() == 1 {
+                if let Some((category, _, _)) = counts
+                    .iter()
+                    .filter(|(category, _, _)| !hidden.contains(category))
+                    .min_by_key(|(category, count, _)| (*count, category.clone()))
+                {
+                    hidden.push(category.clone());
+                }
+            }
+            let by_category = counts
+                .into_iter()
+                .map(|(category, count, _)| {
+                    let share = if hidden.contains(&category) {
+                        CategoryShare { observations: None, percent: None }
+                    } else {
+                        CategoryShare {
+                            observations: Some(count),
+                            percent: Some((count as f64 * 1000.0 / observations as f64).round() / 10.0),
+                        }
+                    };
+                    (category, share)
+                })
+                .collect();
+            classes.push(ClassCategoryStatistics {
+                class_id,
+                class_name: class.name,
+                school_year: class.school_year,
+                students,
+                observations: (hidden.len() != 1).then_some(observations),
+                by_category,
+            });
+        }
+
+        let mut categories: Vec<String> = classes.iter().flat_map(|c| c.by_category.keys().cloned()).collect();
+        categories.sort();
+        categories.dedup();
+        // Side by side, every class has every category; not observed is no secret
+        for class in &mut classes {
+            for category in &categories {
+                class.by_category.entry(category.clone()).or_insert(CategoryShare {
+                    observations: Some(0),
+                    percent: Some(0.0),
+                });
+            }
+        }
+        Ok(ClassStatisticsComparison {
+            categories,
+            classes,
+            min_students_per_cell: MIN_STUDENTS_PER_CELL,
+        })
+    }
+
+    /// What new observations of `user_id` get unless chosen otherwise.
+    pub async fn get_default_visibility(&self, user_id: i64) -> Result<String> {
+        Ok(self
+            .get_setting(&format!("observation_visibility_default.{}", user_id))
+            .await?
+            .unwrap_or_else(|| VISIBILITY_TEAM.to_string()))
+    }
+
+    pub async fn set_default_visibility(&self, user_id: i64, visibility: &str) -> Result<()> {
+        validate_visibility(visibility)?;
+        self.get_user(user_id).await?.context("User not found")?;
+        self.set_setting(&format!("observation_visibility_default.{}", user_id), visibility)
+            .await
+    }
+
+    /// Only the author decides who sees an observation.
+    pub async fn set_observation_visibility(&self, observation_id: i64, user_id: i64, visibility: &str) -> Result<()> {
+        validate_visibility(visibility)?;
+        let observation = self.get_observation(observation_id).await?.context("Observation not found")?;
+        if observation.author_id != user_id {
+            return Err(anyhow::anyhow!("Only the author can change who sees an observation"));
+        }
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+        sqlx::query(
+            "UPDATE observations SET visibility = ?, logical_clock = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(visibility)
+        .bind(logical_clock)
+        .bind(observation_id)
+        .execute(&mut *tx)
+        .await?;
+        Self::record_change_on(&mut tx, &device_id, "observation", observation_id, "update", None).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Pins are this device's choice of what a substitute should know; they
+    /// change neither the observation nor its sync state.
+    pub async fn set_observation_pinned(&self, observation_id: i64, pinned: bool) -> Result<()> {
+        let updated = sqlx::query("UPDATE observations SET pinned = ? WHERE id = ?")
+            .bind(pinned)
+            .bind(observation_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if updated == 0 {
+            return Err(anyhow::anyhow!("Observation not found"));
+        }
+        Ok(())
+    }
+
+    /// Pinned observations of the class's active students, newest first.
+    pub async fn get_pinned_observations(&self, class_id: i64) -> Result<Vec<Observation>> {
+        let observations = sqlx::query_as::<_, Observation>(
+            r#"
+            SELECT o.* FROM observations o
+            JOIN students s ON s.id = o.student_id
+            WHERE s.class_id = ? AND s.status = 'active' AND o.pinned = 1
+            ORDER BY o.created_at DESC, o.id DESC
+            "#,
+        )
+        .bind(class_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch pinned observations")?;
+
+        Ok(observations)
+    }
+
+    /// Clearing the flag bumps the clock, so peers take the observation as new.
+    pub async fn set_observation_local_only(&self, observation_id: i64, local_only: bool) -> Result<()> {
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+
+        let updated = sqlx::query(
+            "UPDATE observations SET local_only = ?, logical_clock = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(local_only)
+        .bind(logical_clock)
+        .bind(observation_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(anyhow::anyhow!("Observation not found"));
+        }
+
+        Self::record_change_on(&mut tx, &device_id, "observation", observation_id, "update", None).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// A second teacher confirms a sensitive entry, e.g. a safeguarding note.
+    /// The observation is immutable afterwards.
+    pub async fn cosign_observation(&self, observation_id: i64, cosigner_id: i64) -> Result<Observation> {
+        let observation = self
+            .get_observation(observation_id)
+            .await?
+            .context("Observation not found")?;
+        if self.get_user(cosigner_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Unknown user {}", cosigner_id));
+        }
+        if observation.cosigned_at.is_some() {
+            return Err(anyhow::anyhow!("Observation {} is already co-signed", observation_id));
+        }
+        if observation.author_id == cosigner_id {
+            return Err(anyhow::anyhow!("An observation must be co-signed by a second teacher"));
+        }
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+
+        sqlx::query(
+            r#"
+            UPDATE observations
+            SET cosigned_by = ?, cosigned_at = CURRENT_TIMESTAMP, logical_clock = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND cosigned_at IS NULL
+            "#,
+        )
+        .bind(cosigner_id)
+        .bind(logical_clock)
+        .bind(observation_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to co-sign observation")?;
+
+        Self::record_change_on(&mut tx, &device_id, "observation", observation_id, "update", None).await?;
+        tx.commit().await?;
+
+        self.get_observation(observation_id)
+            .await?
+            .context("Observation not found")
+    }
+
+    pub async fn get_observation(&self, observation_id: i64) -> Result<Option<Observation>> {
+        let observation = sqlx::query_as::<_, Observation>(
+            "SELECT * FROM observations WHERE id = ?",
+        )
+        .bind(observation_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch observation")?;
+
+        Ok(observation)
+    }
+
+    pub async fn search_observations(
+        &self,
+        query: Option<String>,
+        student_id: Option<i64>,
+        category: Option<String>,
+    ) -> Result<Vec<Observation>> {
+        self.search_observations_in_subject(query, student_id, category, None)
+            .await
+    }
+
+    pub async fn search_observations_in_subject(
+        &self,
+        query: Option<String>,
+        student_id: Option<i64>,
+        category: Option<String>,
+        subject: Option<String>,
+    ) -> Result<Vec<Observation>> {
+        self.search_observations_in_language(query, student_id, category, subject, None)
+            .await
+    }
+
+    /// `language` matches the detected language, see `language::detect`.
+    /// Observations pending detection only match after
+    /// `refresh_observation_languages`.
+    pub async fn search_observations_in_language(
+        &self,
+        query: Option<String>,
+        student_id: Option<i64>,
+        category: Option<String>,
+        subject: Option<String>,
+        language: Option<String>,
+    ) -> Result<Vec<Observation>> {
+        // Only the filters given become part of the statement: sqlx keeps each
+        // filter combination prepared on the connection, and every shape can
+        // use its own index
+        let conditions: Vec<&str> = [
+            (query.is_some(), "text LIKE ?"),
+            (student_id.is_some(), "student_id = ?"),
+            (category.is_some(), "category = ?"),
+            (subject.is_some(), "subject = ?"),
+            (language.is_some(), "language = ?"),
+        ]
+        .into_iter()
+        .filter_map(|(given, condition)| given.then_some(condition))
+        .collect();
+        let sql = if conditions.is_empty() {
+            "SELECT * FROM observations ORDER BY created_at DESC".to_string()
+        } else {
+            format!("SELECT * FROM observations WHERE {} ORDER BY created_at DESC", conditions.join(" AND "))
+        };
+
+        let mut query_builder = sqlx::query_as::<_, Observation>(&sql);
+
+        if let Some(q) = query {
+            query_builder = query_builder.bind(format!("%{}%", q));
+        }
+        if let Some(sid) = student_id {
+            query_builder = query_builder.bind(sid);
+        }
+        if let Some(cat) = category {
+            query_builder = query_builder.bind(cat);
+        }
+        if let Some(subject) = subject {
+            query_builder = query_builder.bind(subject);
+        }
+        if let Some(language) = language {
+            query_builder = query_builder.bind(language);
+        }
+
+        let observations = query_builder
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to search observations")?;
+
+        Ok(observations)
+    }
+
+    /// Detects the language of observations that came in by sync, import or
+    /// an edit since the last detection. Returns how many were updated.
+    pub async fn refresh_observation_languages(&self) -> Result<u64> {
+        let pending = sqlx::query_as::<_, (i64, String)>("SELECT id, text FROM observations WHERE language IS NULL")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch observations without language")?;
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        // Derived data: neither the sync clock nor updated_at changes
+        let mut tx = self.pool.begin().await?;
+        for (observation_id, text) in &pending {
+            sqlx::query("UPDATE observations SET language = ? WHERE id = ?")
+                .bind(crate::language::detect(text))
+                .bind(observation_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(pending.len() as u64)
+    }
+
+    /// For when the detection got it wrong. Kept until the text is edited.
+    pub async fn set_observation_language(&self, observation_id: i64, language: &str) -> Result<Observation> {
+        if !crate::language::is_known(language) {
+            return Err(anyhow::anyhow!("Unknown language {:?}", language));
+        }
+        let result = sqlx::query("UPDATE observations SET language = ? WHERE id = ?")
+            .bind(language)
+            .bind(observation_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to set observation language")?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Observation not found"));
+        }
+        self.get_observation(observation_id)
+            .await?
+            .context("Observation not found")
+    }
+
+    /// One statement for all badges, so the sidebar can refresh them often.
+    /// Pending changes use the same watermark as `get_sync_status`.
+    pub async fn get_sidebar_badges(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        viewer_id: i64,
+    ) -> Result<SidebarBadges> {
+        let rows = sqlx::query_as::<_, (String, i64, i64, i64)>(
+            r#"
+            WITH watermark AS (
+                SELECT COALESCE(
+                    (SELECT MAX(confirmed_until) FROM sync_state),
+                    (SELECT MAX(created_at) FROM sync_history WHERE direction = 'export')
+                ) AS at
+            )
+            SELECT 'student', s.id, s.class_id, COUNT(*)
+            FROM observations o JOIN students s ON s.id = o.student_id
+            WHERE datetime(o.created_at) > datetime(?)
+              AND s.status != 'deleted'
+              AND (o.visibility = 'team' OR o.author_id = ?)
+            GROUP BY s.id
+            UNION ALL
+            SELECT 'pending_sync', 0, 0, COUNT(*) FROM change_log, watermark
+            WHERE watermark.at IS NULL OR datetime(changed_at) > datetime(watermark.at)
+            UNION ALL
+            SELECT 'erasure_requests', 0, 0, COUNT(*) FROM erasure_requests WHERE status = 'pending'
+            "#,
+        )
+        .bind(since)
+        .bind(viewer_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to count sidebar badges")?;
+
+        let mut badges = SidebarBadges::default();
+        for (kind, student_id, class_id, count) in rows {
+            match kind.as_str() {
+                "student" => {
+                    badges.new_by_student.insert(student_id, count);
+                    *badges.new_by_class.entry(class_id).or_default() += count;
+                }
+                "pending_sync" => badges.pending_sync_changes = count,
+                _ => badges.open_erasure_requests = count,
+            }
+        }
+        Ok(badges)
+    }
+
+    /// Observations created before `cutoff` that were not anonymized yet.
+    pub async fn get_observations_to_anonymize(&self, cutoff: chrono::DateTime<chrono::Utc>) -> Result<Vec<Observation>> {
+        let observations = sqlx::query_as::<_, Observation>(
+            "SELECT * FROM observations WHERE datetime(created_at) < datetime(?) AND anonymized_at IS NULL ORDER BY id",
+        )
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch observations to anonymize")?;
+
+        Ok(observations)
+    }
+
+    /// Stores the anonymized text of an observation, `None` when it had
+    /// nothing identifying, and marks it as anonymized either way. The
+    /// retention policy demands it, so locks and co-signatures do not apply.
+    pub async fn anonymize_observation(&self, observation_id: i64, text: Option<&str>) -> Result<()> {
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        if let Some(text) = text {
+            Self::record_retractions_on(&mut tx, "o.id = ?", observation_id, "corrected").await?;
+            sqlx::query("UPDATE observations SET text = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(text)
+                .bind(observation_id)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to anonymize observation")?;
+            Self::record_change_on(&mut tx, &device_id, "observation", observation_id, "update", None).await?;
+        }
+        sqlx::query("UPDATE observations SET anonymized_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(observation_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn get_observations_since(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<Observation>> {
+        let observations = sqlx::query_as::<_, Observation>(
+            "SELECT * FROM observations WHERE created_at >= ? ORDER BY created_at DESC",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch observations since timestamp")?;
+
+        Ok(observations)
+    }
+
+    pub async fn get_class_observations_between(
+        &self,
+        class_id: i64,
+        from: chrono::DateTime<chrono::Utc>,
+        to: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<Observation>> {
+        let observations = sqlx::query_as::<_, Observation>(
+            r#"
+            SELECT o.* FROM observations o
+            JOIN students s ON s.id = o.student_id
+            WHERE COALESCE(
+                    -- The class left by the first kept transfer after the observation
+                    (SELECT h.from_class_id FROM student_class_history h
+                     WHERE h.student_id = o.student_id AND h.keep_history
+                       AND datetime(h.transferred_at) > datetime(o.created_at)
+                     ORDER BY h.transferred_at, h.logical_clock LIMIT 1),
+                    s.class_id
+                  ) = ?
+              AND datetime(o.created_at) >= datetime(?)
+              AND datetime(o.created_at) < datetime(?)
+            ORDER BY o.created_at ASC
+            "#,
+        )
+        .bind(class_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch class observations for period")?;
+
+        Ok(observations)
+    }
+
+    /// Changes text, category or tags of an observation; only its author may.
+    /// Returns the observation before and after the change. Copies already
+    /// carried to other devices are retracted as corrected.
+    pub async fn update_observation(
+        &self,
+        observation_id: i64,
+        author_id: i64,
+        edit: ObservationEdit,
+    ) -> Result<(Observation, Observation)> {
+        let before = self.get_observation(observation_id).await?.context("Observation not found")?;
+        if before.author_id != author_id {
+            return Err(anyhow::anyhow!("Permission denied: You can only edit your own observations"));
+        }
+        self.ensure_observation_editable(observation_id).await?;
+
+        let text = edit.text.unwrap_or_else(|| before.text.clone());
+        if text.trim().is_empty() {
+            return Err(anyhow::anyhow!("The text of an observation must not be empty"));
+        }
+        let category = edit.category.unwrap_or_else(|| before.category.clone());
+        if category != before.category {
+            let merged_into = sqlx::query_scalar::<_, String>(
+                "SELECT t.name FROM categories c JOIN categories t ON t.id = c.merged_into WHERE c.name = ?",
+            )
+            .bind(&category)
+            .fetch_optional(&self.pool)
+            .await?;
+            if let Some(target) = merged_into {
+                return Err(anyhow::anyhow!("Category {} was merged into {}", category, target));
+            }
+        }
+        let tags = match edit.tags {
+            Some(tags) => serde_json::to_string(&tags)?,
+            None => before.tags.clone(),
+        };
+        if text == before.text && category == before.category && tags == before.tags {
+            return Ok((before.clone(), before));
+        }
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        Self::record_retractions_on(&mut tx, "o.id = ?", observation_id, "corrected").await?;
+        let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+        let after = sqlx::query_as::<_, Observation>(
+            r#"
+            UPDATE observations SET text = ?, language = ?, category = ?, tags = ?, logical_clock = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            RETURNING *
+            "#,
+        )
+        .bind(&text)
+        .bind(crate::language::detect(&text))
+        .bind(&category)
+        .bind(&tags)
+        .bind(logical_clock)
+        .bind(observation_id)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to update observation")?;
+        Self::record_change_on(&mut tx, &device_id, "observation", observation_id, "update", None).await?;
+        tx.commit().await?;
+
+        Ok((before, after))
+    }
+
+    pub async fn delete_observation(
+        &self,
+        observation_id: i64,
+        author_id: i64,
+        force_delete: bool,
+    ) -> Result<()> {
+        self.ensure_observation_editable(observation_id).await?;
+
+        let mut conn = self.pool.acquire().await?;
+        let uuid = Self::entity_uuid_on(&mut conn, "observation", observation_id).await?;
+        drop(conn);
+
+        if force_delete {
+            // Hard delete: remove completely
+            let mut conn = self.pool.acquire().await?;
+            Self::record_retractions_on(&mut conn, "o.id = ?", observation_id, "deleted").await?;
+            drop(conn);
+
+            sqlx::query("DELETE FROM attachments WHERE observation_id = ?")
+                .bind(observation_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM observations WHERE id = ?")
+                .bind(observation_id)
+                .execute(&self.pool)
+                .await?;
+        } else {
+            // Check if user is the author
+            let author_check = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM observations WHERE id = ? AND author_id = ?",
+            )
+            .bind(observation_id)
+            .bind(author_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+            if author_check == 0 {
+                return Err(anyhow::anyhow!(
+                    "Permission denied: You can only delete your own observations"
+                ));
+            }
+
+            let mut conn = self.pool.acquire().await?;
+            Self::record_retractions_on(&mut conn, "o.id = ?", observation_id, "deleted").await?;
+            drop(conn);
+
+            sqlx::query("DELETE FROM attachments WHERE observation_id = ?")
+                .bind(observation_id)
+                .execute(&self.pool)
+                .await?;
+
+            sqlx::query("DELETE FROM observations WHERE id = ? AND author_id = ?")
+                .bind(observation_id)
+                .bind(author_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        self.record_deletion("observation", observation_id, uuid, "hard").await?;
+        self.prune_attachment_files().await?;
+
+        Ok(())
+    }
+
+    // Logical clock operations
+    pub async fn next_logical_clock(&self) -> Result<i64> {
+        let mut conn = self.pool.acquire().await?;
+        Self::next_logical_clock_on(&mut conn, &self.crypto.get_device_id()).await
+    }
+
+    async fn next_logical_clock_on(conn: &mut SqliteConnection, device_id: &str) -> Result<i64> {
+        let counter = sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO sync_clock (device_id, counter) VALUES (?, 1)
+            ON CONFLICT(device_id) DO UPDATE SET counter = counter + 1
+            RETURNING counter
+            "#,
+        )
+        .bind(device_id)
+        .fetch_one(&mut *conn)
+        .await
+        .context("Failed to advance logical clock")?;
+
+        Ok(counter)
+    }
+
+    pub async fn current_logical_clock(&self) -> Result<i64> {
+        let device_id = self.crypto.get_device_id();
+
+        let counter = sqlx::query_scalar::<_, i64>("SELECT counter FROM sync_clock WHERE device_id = ?")
+            .bind(&device_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to read logical clock")?;
+
+        Ok(counter.unwrap_or(0))
+    }
+
+    async fn record_change(
+        &self,
+        entity_type: &str,
+        entity_id: i64,
+        operation: &str,
+        deletion_type: Option<&str>,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let device_id = self.crypto.get_device_id();
+        Self::record_change_on(&mut conn, &device_id, entity_type, entity_id, operation, deletion_type).await
+    }
+
+    /// Records the deletion of a record whose row may already be gone, with
+    /// the uuid the caller read before deleting it.
+    async fn record_deletion(
+        &self,
+        entity_type: &str,
+        entity_id: i64,
+        entity_uuid: Option<String>,
+        deletion_type: &str,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let device_id = self.crypto.get_device_id();
+        let entity_uuid = match entity_uuid {
+            Some(uuid) => Some(uuid),
+            None => Self::entity_uuid_on(&mut conn, entity_type, entity_id).await?,
+        };
+        Self::insert_change_on(&mut conn, &device_id, entity_type, entity_id, entity_uuid.as_deref(), "delete", Some(deletion_type))
+            .await
+    }
+
+    /// The uuid of a synced record, from its row or, once the row is gone,
+    /// from the changes logged for it.
+    async fn entity_uuid_on(conn: &mut SqliteConnection, entity_type: &str, entity_id: i64) -> Result<Option<String>> {
+        let Some(table) = entity_table(entity_type) else {
+            return Ok(None);
+        };
+        let uuid = sqlx::query_scalar::<_, Option<String>>(&format!("SELECT uuid FROM {} WHERE id = ?", table))
+            .bind(entity_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .flatten();
+        if uuid.is_some() {
+            return Ok(uuid);
+        }
+        Ok(sqlx::query_scalar::<_, Option<String>>(
+            "SELECT entity_uuid FROM change_log WHERE entity_type = ? AND entity_id = ? AND entity_uuid IS NOT NULL ORDER BY logical_clock DESC LIMIT 1",
+        )
+        .bind(entity_type)
+        .bind(entity_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .flatten())
+    }
+
+    /// Same as `record_change`, on a caller-provided connection or transaction.
+    async fn record_change_on(
+        conn: &mut SqliteConnection,
+        device_id: &str,
+        entity_type: &str,
+        entity_id: i64,
+        operation: &str,
+        deletion_type: Option<&str>,
+    ) -> Result<()> {
+        let entity_uuid = Self::entity_uuid_on(&mut *conn, entity_type, entity_id).await?;
+        Self::insert_change_on(conn, device_id, entity_type, entity_id, entity_uuid.as_deref(), operation, deletion_type)
+            .await
+    }
+
+    async fn insert_change_on(
+        conn: &mut SqliteConnection,
+        device_id: &str,
+        entity_type: &str,
+        entity_id: i64,
+        entity_uuid: Option<&str>,
+        operation: &str,
+        deletion_type: Option<&str>,
+    ) -> Result<()> {
+        let logical_clock = Self::next_logical_clock_on(&mut *conn, device_id).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO change_log (entity_type, entity_id, entity_uuid, operation, deletion_type, logical_clock, device_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(entity_type)
+        .bind(entity_id)
+        .bind(entity_uuid)
+        .bind(operation)
+        .bind(deletion_type)
+        .bind(logical_clock)
+        .bind(device_id)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to record change")?;
+
+        Ok(())
+    }
+
+    pub async fn get_tombstones_since(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<Tombstone>> {
+        let tombstones = sqlx::query_as::<_, Tombstone>(
+            r#"
+            SELECT entity_type, entity_id, entity_uuid, COALESCE(deletion_type, 'hard') AS deletion_type,
+                   changed_at AS deleted_at, logical_clock, device_id
+            FROM change_log
+            WHERE operation = 'delete' AND datetime(changed_at) >= datetime(?)
+              AND NOT EXISTS (
+                  SELECT 1 FROM change_log later
+                  WHERE later.entity_type = change_log.entity_type
+                    AND later.entity_id = change_log.entity_id
+                    AND later.operation != 'delete'
+                    AND later.logical_clock > change_log.logical_clock
+              )
+            ORDER BY logical_clock ASC
+            "#,
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch tombstones")?;
+
+        Ok(tombstones)
+    }
+
+    /// Records a retraction for every observation matching `scope` (a condition
+    /// on `o` with one parameter) that a changeset already carried away. Must run
+    /// before the change, while the rows still hold what was exported.
+    async fn record_retractions_on(
+        conn: &mut SqliteConnection,
+        scope: &str,
+        id: i64,
+        reason: &str,
+    ) -> Result<u64> {
+        let result = sqlx::query(&format!(
+            r#"
+            INSERT INTO retractions (observation_id, student_id, label, reason, exported_in)
+            SELECT o.id, o.student_id, o.category || ' (' || date(o.created_at) || ')', ?, json_group_array(e.operation_id)
+            FROM observations o
+            JOIN observation_exports e ON e.observation_id = o.id
+            WHERE {}
+              AND NOT EXISTS (
+                  SELECT 1 FROM retractions r
+                  WHERE r.observation_id = o.id AND r.reason = ? AND r.carried_in IS NULL
+              )
+            GROUP BY o.id
+            "#,
+            scope
+        ))
+        .bind(reason)
+        .bind(id)
+        .bind(reason)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to record retraction")?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Data that may still exist on other devices in its exported form.
+    /// `pending_only` limits the list to retractions no changeset carried yet.
+    pub async fn get_retractions(&self, pending_only: bool) -> Result<Vec<Retraction>> {
+        let retractions = sqlx::query_as::<_, Retraction>(
+            "SELECT * FROM retractions WHERE NOT ? OR carried_in IS NULL ORDER BY retracted_at DESC, id DESC",
+        )
+        .bind(pending_only)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch retractions")?;
+
+        Ok(retractions)
+    }
+
+    // Handover operations
+    /// Adds a handed-over class as new rows, since ids of the sending device
+    /// mean nothing here. Returns the provenance record and the new id of
+    /// each observation, keyed by its id on the sending device.
+    pub async fn absorb_handover(
+        &self,
+        contents: &crate::handover::HandoverContents,
+        sender_device_id: &str,
+        package_hash: &str,
+        package_created_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(HandoverImport, HashMap<i64, i64>)> {
+        self.ensure_not_frozen()?;
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+
+        let already_imported = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM handover_imports WHERE package_hash = ?")
+            .bind(package_hash)
+            .fetch_one(&mut *tx)
+            .await?;
+        if already_imported > 0 {
+            return Err(anyhow::anyhow!("This handover package was already imported"));
+        }
+
+        let class_id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO classes (name, school_year, created_at, source_device_id, uuid) VALUES (?, ?, ?, ?, ?) RETURNING id",
+        )
+        .bind(&contents.class.name)
+        .bind(&contents.class.school_year)
+        .bind(contents.class.created_at)
+        .bind(&contents.class.source_device_id)
+        // A handover makes copies, which are new records here
+        .bind(record_uuid(None))
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to create handed-over class")?;
+        Self::record_change_on(&mut tx, &device_id, "class", class_id, "create", None).await?;
+
+        let mut student_ids = HashMap::new();
+        for student in &contents.students {
+            let student_id = sqlx::query_scalar::<_, i64>(
+                r#"
+                INSERT INTO students (class_id, first_name, last_name, status, created_at, source_device_id, date_of_birth, notes, external_id, uuid)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                RETURNING id
+                "#,
+            )
+            .bind(class_id)
+            .bind(&student.first_name)
+            .bind(&student.last_name)
+            .bind(&student.status)
+            .bind(student.created_at)
+            .bind(&student.source_device_id)
+            .bind(student.date_of_birth)
+            .bind(&student.notes)
+            .bind(&student.external_id)
+            .bind(record_uuid(None))
+            .fetch_one(&mut *tx)
+            .await
+            .context("Failed to create handed-over student")?;
+            Self::record_change_on(&mut tx, &device_id, "student", student_id, "create", None).await?;
+            student_ids.insert(student.id, student_id);
+        }
+
+        let mut observation_ids = HashMap::new();
+        for obs in &contents.observations {
+            let Some(&student_id) = student_ids.get(&obs.student_id) else {
+                continue;
+            };
+            let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+            let observation_id = sqlx::query_scalar::<_, i64>(
+                r#"
+                INSERT INTO observations (student_id, author_id, category, text, tags, created_at, source_device_id, logical_clock, cosigned_by, cosigned_at, uuid)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                RETURNING id
+                "#,
+            )
+            .bind(student_id)
+            .bind(obs.author_id)
+            .bind(&obs.category)
+            .bind(&obs.text)
+            .bind(&obs.tags)
+            .bind(obs.created_at)
+            .bind(&obs.source_device_id)
+            .bind(logical_clock)
+            .bind(obs.cosigned_by)
+            .bind(obs.cosigned_at)
+            .bind(record_uuid(None))
+            .fetch_one(&mut *tx)
+            .await
+            .context("Failed to create handed-over observation")?;
+            Self::record_change_on(&mut tx, &device_id, "observation", observation_id, "create", None).await?;
+            observation_ids.insert(obs.id, observation_id);
+        }
+
+        let record = sqlx::query_as::<_, HandoverImport>(
+            r#"
+            INSERT INTO handover_imports (class_id, class_name, source_device_id, package_hash, package_created_at, students, observations, attachments)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#,
+        )
+        .bind(class_id)
+        .bind(&contents.class.name)
+        .bind(sender_device_id)
+        .bind(package_hash)
+        .bind(package_created_at)
+        .bind(student_ids.len() as i64)
+        .bind(observation_ids.len() as i64)
+        .bind(contents.attachments.iter().filter(|a| a.data.is_some()).count() as i64)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to record handover provenance")?;
+
+        tx.commit().await?;
+        Ok((record, observation_ids))
+    }
+
+    pub async fn get_handover_imports(&self) -> Result<Vec<HandoverImport>> {
+        let imports = sqlx::query_as::<_, HandoverImport>("SELECT * FROM handover_imports ORDER BY imported_at DESC, id DESC")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch handover imports")?;
+
+        Ok(imports)
+    }
+
+    // Sync and changeset operations
+    pub async fn get_pending_changesets(&self, _operation: &str) -> Result<Vec<u8>> {
+        // Placeholder for changeset export functionality
+        // In a real implementation, this would track changes and serialize them
+        let changeset = serde_json::json!({
+            "format": "changeset_v1",
+            "timestamp": chrono::Utc::now(),
+            "changes": []
+        });
+
+        Ok(changeset.to_string().into_bytes())
+    }
+
+    pub async fn apply_changeset(&self, _changeset: &[u8], _operation: &str) -> Result<()> {
+        // Placeholder for changeset import functionality
+        // In a real implementation, this would parse and apply changes
+        Ok(())
+    }
+
+    /// Expected file size of a changeset with the given scope, computed
+    /// without recording an export.
+    pub async fn estimate_changeset_size(&self, days_back: u32, policy: &AttachmentPolicy) -> Result<ChangesetSizeEstimate> {
+        let build = self.build_changeset(days_back, policy).await?;
+        Ok(ChangesetSizeEstimate {
+            days_back,
+            observations: build.observation_ids.len(),
+            plain_bytes: build.data.len(),
+            compressed_bytes: crate::changeset_codec::compress(&build.data)?.len(),
+        })
+    }
+
+    pub async fn create_changeset_file(&self, days_back: u32) -> Result<Vec<u8>> {
+        self.create_changeset_file_with_policy(days_back, &AttachmentPolicy::default())
+            .await
+    }
+
+    pub async fn create_changeset_file_with_policy(&self, days_back: u32, policy: &AttachmentPolicy) -> Result<Vec<u8>> {
+        self.ensure_not_frozen()?;
+        let build = self.build_changeset(days_back, policy).await?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO sync_history (operation_id, direction, summary) VALUES (?, 'export', ?)",
+        )
+        .bind(&build.operation_id)
+        .bind(&build.summary)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to record changeset export")?;
+
+        for observation_id in &build.observation_ids {
+            sqlx::query("INSERT OR IGNORE INTO observation_exports (observation_id, operation_id) VALUES (?, ?)")
+                .bind(observation_id)
+                .bind(&build.operation_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("UPDATE retractions SET carried_in = ? WHERE carried_in IS NULL")
+            .bind(&build.operation_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(build.data)
+    }
+
+    async fn build_changeset(&self, days_back: u32, policy: &AttachmentPolicy) -> Result<ChangesetBuild> {
+        let device_id = self.crypto.get_device_id();
+        let cutoff_date = chrono::Utc::now() - chrono::Duration::days(days_back as i64);
+
+        let mut recent_observations = self.get_observations_since(cutoff_date).await?;
+        // Whatever the caller asked for, see `Observation::leaves_device`
+        recent_observations.retain(Observation::leaves_device);
+        let tombstones = self.get_tombstones_since(cutoff_date).await?;
+        let transfers = self.get_transfers_since(cutoff_date).await?;
+        let guardians = self.get_guardians_for_sync(cutoff_date).await?;
+        let student_details = self.get_student_details_for_sync(cutoff_date).await?;
+        let class_layouts = self.get_class_layouts_since(cutoff_date).await?;
+        // Carried once, independent of the date range
+        let retractions = self.get_retractions(true).await?;
+        let observation_ids: Vec<i64> = recent_observations.iter().map(|o| o.id).collect();
+        let attachments = crate::attachment_sync::collect(self, &observation_ids, policy).await?;
+        let mut student_ids: Vec<i64> = recent_observations
+            .iter()
+            .map(|o| o.student_id)
+            .chain(student_details.iter().filter_map(|s| s.get("id").and_then(|id| id.as_i64())))
+            .collect();
+        student_ids.sort_unstable();
+        student_ids.dedup();
+        let reference_codes = self.get_reference_codes_for_sync(&observation_ids, &student_ids).await?;
+        
+        let logical_clock = self.current_logical_clock().await?;
+        let operation_id = uuid::Uuid::new_v4().to_string();
+
+        let changes = serde_json::json!({
+            "observations": recent_observations,
+            "tombstones": tombstones,
+            "transfers": transfers,
+            "guardians": guardians,
+            "student_details": student_details,
+            "class_layouts": class_layouts,
+            "retractions": retractions,
+            "attachments": attachments,
+            "reference_codes": reference_codes
+        });
+        let changeset = serde_json::json!({
+            "format": "changeset_file_v1",
+            "version": "1.0",
+            "operation_id": operation_id,
+            "timestamp": chrono::Utc::now(),
+            "device_id": device_id,
+            "logical_clock": logical_clock,
+            "days_back": days_back,
+            "manifest": ExportManifest::describe(&changes),
+            "changes": changes
+        });
+
+        // Calculate checksum for integrity
+        let final_changeset = serde_json::json!({
+            "checksum": changeset_checksum(&changeset),
+            "data": changeset
+        });
+
+        Ok(ChangesetBuild {
+            data: final_changeset.to_string().into_bytes(),
+            summary: format!(
+                "{} observations, {} deletions, {} transfers, {} retractions, last {} days",
+                recent_observations.len(),
+                tombstones.len(),
+                transfers.len(),
+                retractions.len(),
+                days_back
+            ),
+            operation_id,
+            observation_ids,
+        })
+    }
+
+    pub async fn apply_changeset_file(&self, changeset_data: &[u8]) -> Result<String> {
+        self.apply_changeset_file_with_options(changeset_data, false, &CancellationToken::new())
+            .await
+    }
+
+    /// Hard deletions received from another device are only executed when
+    /// `confirm_hard_deletions` is set; otherwise they are listed in the result
+    /// and the changeset stays importable so it can be applied again once confirmed.
+    pub async fn apply_changeset_file_with_options(
+        &self,
+        changeset_data: &[u8],
+        confirm_hard_deletions: bool,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        self.ensure_not_frozen()?;
+        let changeset_data = crate::changeset_codec::decode(changeset_data)?;
+        let (data_section, calculated_checksum, trusted_timestamp) = verify_changeset(&changeset_data)?;
+        let operation_id = changeset_operation_id(&data_section, &calculated_checksum);
+        let source_device_id = data_section
+            .get("device_id")
+            .and_then(|d| d.as_str())
+            .map(|d| d.to_string());
+
+        let mut report = ChangesetImportReport {
+            operation_id: operation_id.clone(),
+            ..Default::default()
+        };
+        if let Some(timestamp) = trusted_timestamp {
+            match crate::trusted_timestamp::configured_certificate(self).await? {
+                Some(certificate) => {
+                    report.trusted_timestamp =
+                        Some(crate::trusted_timestamp::verify(&timestamp, &calculated_checksum, &certificate)?);
+                }
+                None => report.warnings.push(
+                    "The file carries a trusted timestamp, but no TSA certificate is configured to check it".to_string(),
+                ),
+            }
+        }
+
+        let applied_at = sqlx::query_scalar::<_, chrono::DateTime<chrono::Utc>>(
+            "SELECT created_at FROM sync_history WHERE operation_id = ? AND direction = 'import'",
+        )
+        .bind(&operation_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(applied_at) = applied_at {
+            report.already_applied_at = Some(applied_at);
+            return Ok(report.to_string());
+        }
+
+        // Extract observations and merge them
+        let observations_data = data_section.get("changes")
+            .and_then(|c| c.get("observations"))
+            .and_then(|o| o.as_array())
+            .context("Invalid observations data in changeset")?;
+
+        let now = chrono::Utc::now();
+
+        if let Some(exported_at) = data_section
+            .get("timestamp")
+            .and_then(|t| serde_json::from_value::<chrono::DateTime<chrono::Utc>>(t.clone()).ok())
+        {
+            if exported_at - now > chrono::Duration::minutes(MAX_CLOCK_SKEW_MINUTES) {
+                report.warnings.push(format!(
+                    "Changeset was exported at {} which is in the future of this device's clock ({}); one of the clocks is wrong",
+                    exported_at.to_rfc3339(),
+                    now.to_rfc3339()
+                ));
+            }
+        }
+
+        let mut incoming: Vec<Observation> = observations_data
+            .iter()
+            .filter_map(|obs_value| serde_json::from_value::<Observation>(obs_value.clone()).ok())
+            .collect();
+
+        // Apply in logical order so that later changes win regardless of wall clock time
+        incoming.sort_by(|a, b| {
+            (a.logical_clock, &a.source_device_id).cmp(&(b.logical_clock, &b.source_device_id))
+        });
+
+        let mut max_remote_clock = data_section
+            .get("logical_clock")
+            .and_then(|c| c.as_i64())
+            .unwrap_or(0);
+        let mut skewed_entries = 0;
+
+        // Changes and the applied-operation record commit together, so a replay
+        // after a crash either sees nothing applied or the finished operation
+        let mut tx = self.pool.begin().await?;
+
+        let total = incoming.len();
+        for (index, obs) in incoming.into_iter().enumerate() {
+            cancel.check()?;
+            cancel.report_progress(index, total);
+            max_remote_clock = max_remote_clock.max(obs.logical_clock);
+
+            if obs.updated_at - now > chrono::Duration::minutes(MAX_CLOCK_SKEW_MINUTES) {
+                skewed_entries += 1;
+            }
+
+            // Never resurrect an observation this device deleted after the incoming version
+            let deleted_clock = sqlx::query_scalar::<_, Option<i64>>(
+                "SELECT MAX(logical_clock) FROM change_log WHERE entity_type = 'observation' AND entity_id = ? AND operation = 'delete'",
+            )
+            .bind(obs.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if deleted_clock.is_some_and(|clock| clock >= obs.logical_clock) {
+                report.skipped += 1;
+                continue;
+            }
+
+            // Check if observation already exists
+            let existing = sqlx::query_as::<_, (i64, String, Option<chrono::DateTime<chrono::Utc>>)>(
+                "SELECT logical_clock, source_device_id, cosigned_at FROM observations WHERE id = ?",
+            )
+            .bind(obs.id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            match existing {
+                None => {
+                    // The same observation under another id here is not a new one
+                    let uuid = record_uuid(obs.uuid.as_deref());
+                    let known_uuid = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM observations WHERE uuid = ?")
+                        .bind(&uuid)
+                        .fetch_one(&mut *tx)
+                        .await?;
+                    if known_uuid > 0 {
+                        report.skipped += 1;
+                        continue;
+                    }
+
+                    // Insert new observation (preserving original ID and timestamps)
+                    sqlx::query(
+                        r#"
+                        INSERT INTO observations (id, student_id, author_id, category, text, tags, created_at, updated_at, source_device_id, logical_clock, cosigned_by, cosigned_at, visibility, subject, reported_by_user_id, reported_by, uuid)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        "#,
+                    )
+                    .bind(obs.id)
+                    .bind(obs.student_id)
+                    .bind(obs.author_id)
+                    .bind(obs.category)
+                    .bind(obs.text)
+                    .bind(obs.tags)
+                    .bind(obs.created_at)
+                    .bind(obs.updated_at)
+                    .bind(obs.source_device_id)
+                    .bind(obs.logical_clock)
+                    .bind(obs.cosigned_by)
+                    .bind(obs.cosigned_at)
+                    .bind(obs.visibility)
+                    .bind(obs.subject)
+                    .bind(obs.reported_by_user_id)
+                    .bind(obs.reported_by)
+                    .bind(uuid)
+                    .execute(&mut *tx)
+                    .await?;
+
+                    report.imported += 1;
+                }
+                Some((local_clock, local_device, local_cosigned_at)) => {
+                    // Conflict: the higher (logical_clock, device_id) pair wins,
+                    // but a co-signed version is never replaced
+                    if local_cosigned_at.is_none()
+                        && (obs.logical_clock, &obs.source_device_id) > (local_clock, &local_device)
+                    {
+                        sqlx::query(
+                            r#"
+                            UPDATE observations
+                            SET category = ?, text = ?, tags = ?, updated_at = ?, source_device_id = ?, logical_clock = ?,
+                                cosigned_by = ?, cosigned_at = ?, visibility = ?, subject = ?, reported_by_user_id = ?, reported_by = ?
+                            WHERE id = ?
+                            "#,
+                        )
+                        .bind(obs.category)
+                        .bind(obs.text)
+                        .bind(obs.tags)
+                        .bind(obs.updated_at)
+                        .bind(obs.source_device_id)
+                        .bind(obs.logical_clock)
+                        .bind(obs.cosigned_by)
+                        .bind(obs.cosigned_at)
+                        .bind(obs.visibility)
+                        .bind(obs.subject)
+                        .bind(obs.reported_by_user_id)
+                        .bind(obs.reported_by)
+                        .bind(obs.id)
+                        .execute(&mut *tx)
+                        .await?;
+
+                        report.updated += 1;
+                    } else {
+                        report.skipped += 1;
+                    }
+                }
+            }
+        }
+
+        // Copies this device passed on further have to be retracted as well, so
+        // the retraction is relayed before a tombstone in the same file removes the row
+        let retractions: Vec<Retraction> = data_section
+            .get("changes")
+            .and_then(|c| c.get("retractions"))
+            .and_then(|r| r.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|r| serde_json::from_value::<Retraction>(r.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        for retraction in &retractions {
+            cancel.check()?;
+            Self::record_retractions_on(&mut tx, "o.id = ?", retraction.observation_id, &retraction.reason).await?;
+            report.warnings.push(format!(
+                "Observation {} ({}) was {} on the sending device after it had been exported; copies made from it should be corrected",
+                retraction.observation_id, retraction.label, retraction.reason
+            ));
+        }
+        report.retractions_received = retractions.len() as i64;
+
+        // Merged before the tombstones, so a deletion in the same file wins
+        let guardians = data_section
+            .get("changes")
+            .and_then(|c| c.get("guardians"))
+            .and_then(|g| g.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for guardian in &guardians {
+            cancel.check()?;
+            if let Some(clock) = guardian.get("logical_clock").and_then(|c| c.as_i64()) {
+                max_remote_clock = max_remote_clock.max(clock);
+            }
+            if Self::apply_guardian_on(&mut tx, guardian).await? {
+                report.guardians_applied += 1;
+            }
+        }
+
+        let student_details = data_section
+            .get("changes")
+            .and_then(|c| c.get("student_details"))
+            .and_then(|s| s.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for record in &student_details {
+            cancel.check()?;
+            if Self::apply_student_details_on(&mut tx, record).await? {
+                report.students_updated += 1;
+            }
+        }
+
+        // After the observations, so codes of records new here find them
+        let reference_codes = data_section
+            .get("changes")
+            .and_then(|c| c.get("reference_codes"))
+            .and_then(|r| r.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for record in &reference_codes {
+            cancel.check()?;
+            if let Some(warning) = Self::apply_reference_code_on(&mut tx, record).await? {
+                report.warnings.push(warning);
+            }
+        }
+
+        let layouts: Vec<ClassLayout> = data_section
+            .get("changes")
+            .and_then(|c| c.get("class_layouts"))
+            .and_then(|l| l.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|l| serde_json::from_value::<ClassLayout>(l.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        for layout in &layouts {
+            cancel.check()?;
+            max_remote_clock = max_remote_clock.max(layout.logical_clock);
+            if Self::apply_class_layout_on(&mut tx, layout).await? {
+                report.layouts_applied += 1;
+            }
+        }
+
+        let mut tombstones: Vec<Tombstone> = data_section
+            .get("changes")
+            .and_then(|c| c.get("tombstones"))
+            .and_then(|t| t.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|t| serde_json::from_value::<Tombstone>(t.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        tombstones.sort_by_key(|t| t.logical_clock);
+
+        for tombstone in tombstones {
+            cancel.check()?;
+            max_remote_clock = max_remote_clock.max(tombstone.logical_clock);
+
+            let Some(table) = entity_table(&tombstone.entity_type) else {
+                continue;
+            };
+
+            // The sender's id may name an unrelated record here
+            let Some(entity_uuid) = tombstone.entity_uuid.as_deref() else {
+                report.warnings.push(format!(
+                    "Deletion of {} {} skipped: written by a version without record uuids",
+                    tombstone.entity_type, tombstone.entity_id
+                ));
+                continue;
+            };
+            let local_id = sqlx::query_scalar::<_, i64>(&format!("SELECT id FROM {} WHERE uuid = ?", table))
+                .bind(entity_uuid)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let Some(local_id) = local_id else {
+                continue;
+            };
+
+            if tombstone.entity_type == "student" && tombstone.deletion_type == "soft" {
+                let result = sqlx::query(
+                    "UPDATE students SET status = 'deleted', updated_at = CURRENT_TIMESTAMP WHERE id = ? AND status != 'deleted'",
+                )
+                .bind(local_id)
+                .execute(&mut *tx)
+                .await?;
+                report.deletions_applied += result.rows_affected() as i64;
+                continue;
+            }
+
+            if !confirm_hard_deletions {
+                report.pending_hard_deletions.push(format!("{} {}", tombstone.entity_type, local_id));
+                continue;
+            }
+
+            if tombstone.entity_type == "student" {
+                Self::remove_student_from_layouts_on(&mut tx, local_id).await?;
+            }
+            let statements: &[&str] = match tombstone.entity_type.as_str() {
+                "class" => &[
+                    "DELETE FROM attachments WHERE observation_id IN (SELECT o.id FROM observations o JOIN students s ON s.id = o.student_id WHERE s.class_id = ?)",
+                    "DELETE FROM observations WHERE student_id IN (SELECT id FROM students WHERE class_id = ?)",
+                    "DELETE FROM guardians WHERE student_id IN (SELECT id FROM students WHERE class_id = ?)",
+                    "DELETE FROM students WHERE class_id = ?",
+                    "DELETE FROM report_artifacts WHERE class_id = ?",
+                    "DELETE FROM class_layouts WHERE class_id = ?",
+                    "DELETE FROM observation_sessions WHERE class_id = ?",
+                    "DELETE FROM classes WHERE id = ?",
+                ],
+                "student" => &[
+                    "DELETE FROM report_artifacts WHERE class_id = (SELECT class_id FROM students WHERE id = ?)",
+                    "DELETE FROM attachments WHERE observation_id IN (SELECT id FROM observations WHERE student_id = ?)",
+                    "DELETE FROM observations WHERE student_id = ?",
+                    "DELETE FROM guardians WHERE student_id = ?",
+                    "DELETE FROM students WHERE id = ?",
+                ],
+                "guardian" => &["DELETE FROM guardians WHERE id = ?"],
+                _ => &[
+                    "DELETE FROM attachments WHERE observation_id = ?",
+                    "DELETE FROM observations WHERE id = ?",
+                ],
+            };
+            for statement in statements {
+                sqlx::query(statement).bind(local_id).execute(&mut *tx).await?;
+            }
+            report.deletions_applied += 1;
+        }
+
+        let mut transfers: Vec<StudentTransfer> = data_section
+            .get("changes")
+            .and_then(|c| c.get("transfers"))
+            .and_then(|t| t.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|t| serde_json::from_value::<StudentTransfer>(t.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        transfers.sort_by(|a, b| (a.logical_clock, &a.source_device_id).cmp(&(b.logical_clock, &b.source_device_id)));
+
+        for transfer in transfers {
+            cancel.check()?;
+            max_remote_clock = max_remote_clock.max(transfer.logical_clock);
+            if Self::apply_transfer_on(&mut tx, &transfer).await? {
+                report.transfers_applied += 1;
+            }
+        }
+
+        if skewed_entries > 0 {
+            report.warnings.push(format!(
+                "{} imported entries carry timestamps more than {} minutes ahead of local time; conflicts were resolved by logical clock instead",
+                skewed_entries, MAX_CLOCK_SKEW_MINUTES
+            ));
+        }
+
+        cancel.check()?;
+
+        // Lamport merge: local changes made after this import must sort after it
+        sqlx::query(
+            r#"
+            INSERT INTO sync_clock (device_id, counter) VALUES (?, ?)
+            ON CONFLICT(device_id) DO UPDATE SET counter = MAX(counter, excluded.counter)
+            "#,
+        )
+        .bind(self.crypto.get_device_id())
+        .bind(max_remote_clock)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to merge logical clock")?;
+
+        // Held-back hard deletions keep the operation open for a confirmed re-import
+        if report.pending_hard_deletions.is_empty() {
+            sqlx::query(
+                "INSERT INTO sync_history (operation_id, direction, peer_device_id, summary) VALUES (?, 'import', ?, ?)",
+            )
+            .bind(&operation_id)
+            .bind(&source_device_id)
+            .bind(format!(
+                "{} imported, {} updated, {} unchanged, {} deletions",
+                report.imported, report.updated, report.skipped, report.deletions_applied
+            ))
+            .execute(&mut *tx)
+            .await
+            .context("Failed to record applied operation")?;
+        }
+
+        tx.commit().await?;
+
+        // Payloads live outside the transaction, so attachments are merged
+        // once the observations they belong to are committed
+        let attachments: Vec<SyncedAttachment> = data_section
+            .get("changes")
+            .and_then(|c| c.get("attachments"))
+            .and_then(|a| serde_json::from_value(a.clone()).ok())
+            .unwrap_or_default();
+        report.attachments_received = crate::attachment_sync::merge(self, &attachments).await? as i64;
+        let missing = attachments.iter().filter(|a| a.data.is_none()).count();
+        if missing > 0 {
+            report.warnings.push(format!(
+                "{} attachments were left out of the changeset; request them from the other device",
+                missing
+            ));
+        }
+
+        if report.deletions_applied > 0 {
+            self.prune_attachment_files().await?;
+        }
+
+        Ok(report.to_string())
+    }
+
+    /// Computes what an export with the given scope would contain without
+    /// creating a changeset, plus the last sync activity per known device.
+    pub async fn get_sync_status(&self, days_back: u32) -> Result<SyncStatus> {
+        let device_id = self.crypto.get_device_id();
+        let cutoff_date = chrono::Utc::now() - chrono::Duration::days(days_back as i64);
+
+        let scope_counts = sqlx::query_as::<_, (String, i64)>(
+            r#"
+            SELECT operation, COUNT(*) FROM change_log
+            WHERE datetime(changed_at) >= datetime(?)
+            GROUP BY operation
+            "#,
+        )
+        .bind(cutoff_date)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to count changes in scope")?;
+
+        let count_for = |operation: &str| {
+            scope_counts
+                .iter()
+                .find(|(op, _)| op == operation)
+                .map(|(_, count)| *count as u32)
+                .unwrap_or(0)
+        };
+
+        let last_export = sqlx::query_scalar::<_, Option<chrono::DateTime<chrono::Utc>>>(
+            "SELECT MAX(created_at) FROM sync_history WHERE direction = 'export'",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to read last export")?;
+
+        let confirmed = sqlx::query_as::<_, (String, chrono::DateTime<chrono::Utc>)>(
+            "SELECT peer_id, confirmed_until FROM sync_state WHERE confirmed_until IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to read sync receipts")?;
+
+        // Once a peer sent a receipt, exports it has not confirmed still count as pending
+        let watermark = confirmed.iter().map(|(_, until)| *until).max().or(last_export);
+        let pending_changes = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM change_log WHERE ? IS NULL OR datetime(changed_at) > datetime(?)",
+        )
+        .bind(watermark)
+        .bind(watermark)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to count pending changes")?;
+
+        let imports = sqlx::query_as::<_, (String, chrono::DateTime<chrono::Utc>)>(
+            r#"
+            SELECT peer_device_id, MAX(created_at) FROM sync_history
+            WHERE direction = 'import' AND peer_device_id IS NOT NULL
+            GROUP BY peer_device_id
+            ORDER BY MAX(created_at) DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to read import history")?;
+
+        let confirmed_for = |peer: &str| confirmed.iter().find(|(id, _)| id == peer).map(|(_, until)| *until);
+        let mut devices = vec![DeviceSyncInfo {
+            device_id: device_id.clone(),
+            is_local: true,
+            last_export,
+            last_import: None,
+            confirmed_until: None,
+        }];
+        devices.extend(
+            imports
+                .iter()
+                .filter(|(peer, _)| *peer != device_id)
+                .map(|(peer, last_import)| DeviceSyncInfo {
+                    device_id: peer.clone(),
+                    is_local: false,
+                    last_export: None,
+                    last_import: Some(*last_import),
+                    confirmed_until: confirmed_for(peer),
+                }),
+        );
+        // Peers that only ever sent receipts
+        devices.extend(
+            confirmed
+                .iter()
+                .filter(|(peer, _)| *peer != device_id && !imports.iter().any(|(id, _)| id == peer))
+                .map(|(peer, until)| DeviceSyncInfo {
+                    device_id: peer.clone(),
+                    is_local: false,
+                    last_export: None,
+                    last_import: None,
+                    confirmed_until: Some(*until),
+                }),
+        );
+
+        let last_sync = devices
+            .iter()
+            .flat_map(|d| [d.last_export, d.last_import])
+            .flatten()
+            .max();
+
+        Ok(SyncStatus {
+            peer_connected: false, // Always false for file-based sync
+            last_sync,
+            pending_changes: pending_changes as u32,
+            scope_days: days_back,
+            scope_creations: count_for("create"),
+            scope_updates: count_for("update"),
+            scope_deletions: count_for("delete"),
+            devices,
+        })
+    }
+
+    /// Receipt for the exporting device listing the changesets applied here,
+    /// optionally only those that came from `peer_device_id`.
+    pub async fn create_sync_receipt(&self, peer_device_id: Option<&str>) -> Result<Vec<u8>> {
+        let operation_ids = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT operation_id FROM sync_history
+            WHERE direction = 'import' AND (? IS NULL OR peer_device_id = ?)
+            ORDER BY created_at ASC, id ASC
+            "#,
+        )
+        .bind(peer_device_id)
+        .bind(peer_device_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to read applied operations")?;
+
+        let receipt = serde_json::json!({
+            "format": "sync_receipt_v1",
+            "device_id": self.crypto.get_device_id(),
+            "timestamp": chrono::Utc::now(),
+            "operation_ids": operation_ids
+        });
+        let final_receipt = serde_json::json!({
+            "checksum": changeset_checksum(&receipt),
+            "data": receipt
+        });
+
+        Ok(final_receipt.to_string().into_bytes())
+    }
+
+    /// Moves the peer's "confirmed synced up to" watermark to the newest export
+    /// of this device the receipt lists. Operations of other devices are ignored.
+    pub async fn apply_sync_receipt(&self, receipt_data: &[u8]) -> Result<String> {
+        self.ensure_not_frozen()?;
+        let parsed: serde_json::Value = serde_json::from_slice(receipt_data).context("Invalid receipt file format")?;
+        let data = parsed.get("data").context("Missing data section in receipt file")?;
+        if parsed.get("checksum").and_then(|c| c.as_str()) != Some(changeset_checksum(data).as_str()) {
+            return Err(anyhow::anyhow!("Checksum verification failed"));
+        }
+        if data.get("format").and_then(|f| f.as_str()) != Some("sync_receipt_v1") {
+            return Err(anyhow::anyhow!("File is not a sync receipt"));
+        }
+        let peer_device_id = data
+            .get("device_id")
+            .and_then(|d| d.as_str())
+            .context("Missing device id in receipt file")?;
+        if peer_device_id == self.crypto.get_device_id() {
+            return Err(anyhow::anyhow!("The receipt was created on this device"));
+        }
+        let operation_ids: Vec<String> = data
+            .get("operation_ids")
+            .and_then(|o| serde_json::from_value(o.clone()).ok())
+            .context("Invalid operation ids in receipt file")?;
+
+        let mut confirmed = Vec::new();
+        for operation_id in &operation_ids {
+            if let Some(exported_at) = sqlx::query_scalar::<_, chrono::DateTime<chrono::Utc>>(
+                "SELECT created_at FROM sync_history WHERE operation_id = ? AND direction = 'export'",
+            )
+            .bind(operation_id)
+            .fetch_optional(&self.pool)
+            .await?
+            {
+                confirmed.push(exported_at);
+            }
+        }
+        let Some(confirmed_until) = confirmed.iter().max().copied() else {
+            return Ok(format!("Receipt from {} confirms none of this device's exports", peer_device_id));
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO sync_state (peer_id, last_pull, confirmed_until) VALUES (?, CURRENT_TIMESTAMP, ?)
+            ON CONFLICT(peer_id) DO UPDATE SET
+                last_pull = CURRENT_TIMESTAMP,
+                confirmed_until = CASE
+                    WHEN confirmed_until IS NULL OR datetime(excluded.confirmed_until) > datetime(confirmed_until)
+                    THEN excluded.confirmed_until ELSE confirmed_until END
+            "#,
+        )
+        .bind(peer_device_id)
+        .bind(confirmed_until)
+        .execute(&self.pool)
+        .await
+        .context("Failed to record sync receipt")?;
+
+        Ok(format!(
+            "{} of this device's exports confirmed by {}, synced up to {}",
+            confirmed.len(),
+            peer_device_id,
+            confirmed_until.format("%Y-%m-%d %H:%M:%S UTC")
+        ))
+    }
+
+    /// Stages a changeset instead of applying it. Its observations are
+    /// compared with the live tables, so the user can see what promoting it
+    /// would change; nothing is applied until `promote_quarantined_import`.
+    pub async fn quarantine_changeset(&self, file_path: &str, changeset_data: &[u8]) -> Result<QuarantinedImport> {
+        let changeset_data = crate::changeset_codec::decode(changeset_data)?;
+        let (data_section, checksum, _) = verify_changeset(&changeset_data)?;
+        let operation_id = changeset_operation_id(&data_section, &checksum);
+        let changes = data_section.get("changes");
+        let observations: Vec<Observation> = changes
+            .and_then(|c| c.get("observations"))
+            .and_then(|o| o.as_array())
+            .context("Invalid observations data in changeset")?
+            .iter()
+            .filter_map(|o| serde_json::from_value(o.clone()).ok())
+            .collect();
+
+        let mut tx = self.pool.begin().await?;
+        let import_id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO quarantined_imports (file_path, operation_id, source_device_id, payload) VALUES (?, ?, ?, ?) RETURNING id",
+        )
+        .bind(file_path)
+        .bind(&operation_id)
+        .bind(data_section.get("device_id").and_then(|d| d.as_str()))
+        .bind(self.crypto.encrypt_bytes(&changeset_data)?)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to stage changeset")?;
+
+        for obs in &observations {
+            sqlx::query(
+                r#"
+                INSERT INTO quarantined_observations
+                    (import_id, observation_id, student_id, category, text, created_at, source_device_id, logical_clock)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(import_id)
+            .bind(obs.id)
+            .bind(obs.student_id)
+            .bind(&obs.category)
+            .bind(self.seal_staged_text(&obs.text)?)
+            .bind(obs.created_at)
+            .bind(&obs.source_device_id)
+            .bind(obs.logical_clock)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // Classified in the order the import would decide, see `apply_changeset_file_with_options`
+        let classifications = [
+            ("invalid", "student_id NOT IN (SELECT id FROM students)"),
+            (
+                "unchanged",
+                r#"EXISTS (
+                    SELECT 1 FROM observations o
+                    WHERE o.id = q.observation_id
+                      AND (o.cosigned_at IS NOT NULL
+                           OR o.logical_clock > q.logical_clock
+                           OR (o.logical_clock = q.logical_clock AND o.source_device_id >= q.source_device_id))
+                ) OR EXISTS (
+                    SELECT 1 FROM change_log c
+                    WHERE c.entity_type = 'observation' AND c.entity_id = q.observation_id
+                      AND c.operation = 'delete' AND c.logical_clock >= q.logical_clock
+                )"#,
+            ),
+            ("updated", "observation_id IN (SELECT id FROM observations)"),
+        ];
+        for (status, condition) in classifications {
+            sqlx::query(&format!(
+                "UPDATE quarantined_observations AS q SET status = ? WHERE import_id = ? AND status = 'new' AND ({})",
+                condition
+            ))
+            .bind(status)
+            .bind(import_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        // The staged texts are encrypted, so duplicates are found with the texts in hand
+        for obs in &observations {
+            let duplicate = sqlx::query_scalar::<_, i64>(
+                r#"
+                SELECT COUNT(*) FROM observations
+                WHERE student_id = ? AND category = ? AND text = ? AND datetime(created_at) = datetime(?)
+                "#,
+            )
+            .bind(obs.student_id)
+            .bind(&obs.category)
+            .bind(&obs.text)
+            .bind(obs.created_at)
+            .fetch_one(&mut *tx)
+            .await?;
+            if duplicate > 0 {
+                sqlx::query(
+                    "UPDATE quarantined_observations SET status = 'duplicate' WHERE import_id = ? AND observation_id = ? AND status = 'new'",
+                )
+                .bind(import_id)
+                .bind(obs.id)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+
+        let import = self
+            .get_quarantined_import(import_id)
+            .await?
+            .context("Staged changeset not found")?;
+
+        let mut problems = Vec::new();
+        if let Some(applied_at) = sqlx::query_scalar::<_, chrono::DateTime<chrono::Utc>>(
+            "SELECT created_at FROM sync_history WHERE operation_id = ? AND direction = 'import'",
+        )
+        .bind(&operation_id)
+        .fetch_optional(&self.pool)
+        .await?
+        {
+            problems.push(format!(
+                "This changeset was already applied on {}",
+                applied_at.format("%Y-%m-%d %H:%M:%S UTC")
+            ));
+        }
+        if import.invalid_observations > 0 {
+            problems.push(format!(
+                "{} observations refer to students unknown on this device",
+                import.invalid_observations
+            ));
+        }
+        if import.duplicate_observations > 0 {
+            problems.push(format!(
+                "{} observations look like entries this device already has under another id",
+                import.duplicate_observations
+            ));
+        }
+        let hard_deletions = changes
+            .and_then(|c| c.get("tombstones"))
+            .and_then(|t| t.as_array())
+            .map(|items| items.iter().filter(|t| t.get("deletion_type").and_then(|d| d.as_str()) == Some("hard")).count())
+            .unwrap_or(0);
+        if hard_deletions > 0 {
+            problems.push(format!("{} permanent deletions need confirmation when promoted", hard_deletions));
+        }
+
+        sqlx::query("UPDATE quarantined_imports SET problems = ? WHERE id = ?")
+            .bind(serde_json::to_string(&problems)?)
+            .bind(import_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(QuarantinedImport {
+            problems: serde_json::to_string(&problems)?,
+            ..import
+        })
+    }
+
+    pub async fn list_quarantined_imports(&self) -> Result<Vec<QuarantinedImport>> {
+        let imports = sqlx::query_as::<_, QuarantinedImport>(&format!(
+            "{} GROUP BY q.id ORDER BY q.id DESC",
+            QUARANTINED_IMPORTS_QUERY
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch staged changesets")?;
+
+        Ok(imports)
+    }
+
+    pub async fn get_quarantined_import(&self, import_id: i64) -> Result<Option<QuarantinedImport>> {
+        let import = sqlx::query_as::<_, QuarantinedImport>(&format!(
+            "{} WHERE q.id = ? GROUP BY q.id",
+            QUARANTINED_IMPORTS_QUERY
+        ))
+        .bind(import_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch staged changeset")?;
+
+        Ok(import)
+    }
+
+    /// Applies a staged changeset as a regular import and removes it from the
+    /// quarantine. If the import fails, the changeset stays staged.
+    pub async fn promote_quarantined_import(
+        &self,
+        import_id: i64,
+        confirm_hard_deletions: bool,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        let (kind, payload) =
+            sqlx::query_as::<_, (String, Vec<u8>)>("SELECT kind, payload FROM quarantined_imports WHERE id = ?")
+                .bind(import_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .context("Staged changeset not found")?;
+        if kind != "changeset" {
+            return Err(anyhow::anyhow!("Import {} holds staged notes, which are promoted with confirmed students", import_id));
+        }
+        let changeset_data = self.crypto.decrypt_bytes(&payload)?;
+
+        let result = self
+            .apply_changeset_file_with_options(&changeset_data, confirm_hard_deletions, cancel)
+            .await?;
+        self.discard_quarantined_import(import_id).await?;
+        Ok(result)
+    }
+
+    /// Stages recognized paper notes for review. Like changesets, the notes
+    /// are kept encrypted until they are promoted or discarded.
+    pub async fn quarantine_scanned_notes(
+        &self,
+        label: &str,
+        notes: &[crate::ocr::ScannedNote],
+        default_category: &str,
+    ) -> Result<QuarantinedImport> {
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        let import_id = sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO quarantined_imports (kind, file_path, operation_id, source_device_id, payload)
+            VALUES ('scanned_notes', ?, ?, ?, ?) RETURNING id
+            "#,
+        )
+        .bind(label)
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&device_id)
+        .bind(self.crypto.encrypt_bytes(&serde_json::to_vec(notes)?)?)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to stage scanned notes")?;
+
+        for (index, note) in notes.iter().enumerate() {
+            let created_at = note
+                .date
+                .and_then(|d| d.and_hms_opt(12, 0, 0))
+                .map(|d| d.and_utc())
+                .unwrap_or_else(chrono::Utc::now);
+            sqlx::query(
+                r#"
+                INSERT INTO quarantined_observations
+                    (import_id, observation_id, student_id, category, text, created_at, source_device_id, logical_clock, status)
+                VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?)
+                "#,
+            )
+            .bind(import_id)
+            .bind(index as i64)
+            .bind(note.proposed_student_id.unwrap_or(0))
+            .bind(default_category)
+            .bind(self.seal_staged_text(&note.text)?)
+            .bind(created_at)
+            .bind(&device_id)
+            .bind(if note.proposed_student_id.is_some() { "new" } else { "invalid" })
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        let mut problems = Vec::new();
+        let unmatched = notes.iter().filter(|n| n.proposed_student_id.is_none()).count();
+        if unmatched > 0 {
+            problems.push(format!("{} notes could not be matched to one student and need a choice", unmatched));
+        }
+        let empty = notes.iter().filter(|n| n.text.trim().is_empty()).count();
+        if empty > 0 {
+            problems.push(format!("No text was recognized in {} scans", empty));
+        }
+        sqlx::query("UPDATE quarantined_imports SET problems = ? WHERE id = ?")
+            .bind(serde_json::to_string(&problems)?)
+            .bind(import_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.get_quarantined_import(import_id)
+            .await?
+            .context("Staged scanned notes not found")
+    }
+
+    /// The staged notes with their proposed students, for the review.
+    pub async fn get_scanned_notes(&self, import_id: i64) -> Result<Vec<crate::ocr::ScannedNote>> {
+        let payload = sqlx::query_scalar::<_, Vec<u8>>(
+            "SELECT payload FROM quarantined_imports WHERE id = ? AND kind = 'scanned_notes'",
+        )
+        .bind(import_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .context("Staged scanned notes not found")?;
+        Ok(serde_json::from_slice(&self.crypto.decrypt_bytes(&payload)?)?)
+    }
+
+    /// Creates observations for the confirmed notes and removes the import
+    /// from the quarantine; notes left out are dropped with it.
+    pub async fn promote_scanned_notes(
+        &self,
+        import_id: i64,
+        author_id: i64,
+        confirmed: &[ConfirmedScan],
+    ) -> Result<Vec<Observation>> {
+        let notes = self.get_scanned_notes(import_id).await?;
+        let mut new_observations = Vec::with_capacity(confirmed.len());
+        for scan in confirmed {
+            let note = notes
+                .get(scan.index)
+                .with_context(|| format!("Scanned note {} not found", scan.index))?;
+            let text = scan.text.clone().unwrap_or_else(|| note.text.clone());
+            if text.trim().is_empty() {
+                return Err(anyhow::anyhow!("Scanned note {} has no text", scan.index));
+            }
+            new_observations.push(NewObservation {
+                student_id: scan.student_id,
+                category: scan.category.clone(),
+                text,
+                tags: vec!["papiernotiz".to_string()],
+                created_at: note.date.and_then(|d| d.and_hms_opt(12, 0, 0)).map(|d| d.and_utc()),
+                local_only: false,
+                visibility: None,
+                subject: None,
+                reported_by_user_id: None,
+                reported_by: None,
+            });
+        }
+
+        let created = self.create_observations_batch(author_id, new_observations).await?;
+        self.discard_quarantined_import(import_id).await?;
+        Ok(created)
+    }
+
+    /// Stages lines copied from a spreadsheet for review, like scanned notes.
+    pub async fn quarantine_clipboard_rows(
+        &self,
+        rows: &[crate::clipboard_import::ClipboardRow],
+    ) -> Result<QuarantinedImport> {
+        let device_id = self.crypto.get_device_id();
+        let now = chrono::Utc::now();
+        let mut tx = self.pool.begin().await?;
+        let import_id = sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO quarantined_imports (kind, file_path, operation_id, source_device_id, payload)
+            VALUES ('clipboard_observations', ?, ?, ?, ?) RETURNING id
+            "#,
+        )
+        .bind(format!("{} Zeilen aus der Zwischenablage", rows.len()))
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&device_id)
+        .bind(self.crypto.encrypt_bytes(&serde_json::to_vec(rows)?)?)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to stage copied lines")?;
+
+        for (index, row) in rows.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO quarantined_observations
+                    (import_id, observation_id, student_id, category, text, created_at, source_device_id, logical_clock, status)
+                VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?)
+                "#,
+            )
+            .bind(import_id)
+            .bind(index as i64)
+            .bind(row.proposed_student_id.unwrap_or(0))
+            .bind(&row.category)
+            .bind(self.seal_staged_text(&row.text)?)
+            .bind(now)
+            .bind(&device_id)
+            .bind(if row.proposed_student_id.is_some() { "new" } else { "invalid" })
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        let mut problems = Vec::new();
+        let unmatched: Vec<String> = rows
+            .iter()
+            .filter(|r| r.proposed_student_id.is_none())
+            .map(|r| r.line.to_string())
+            .collect();
+        if !unmatched.is_empty() {
+            problems.push(format!(
+                "Lines {} could not be matched to one student and need a choice",
+                unmatched.join(", ")
+            ));
+        }
+        let unknown: Vec<String> = rows
+            .iter()
+            .filter(|r| !r.category_known)
+            .map(|r| r.line.to_string())
+            .collect();
+        if !unknown.is_empty() {
+            problems.push(format!("Lines {} name an unknown category, the default is proposed", unknown.join(", ")));
+        }
+        let empty = rows.iter().filter(|r| r.text.trim().is_empty()).count();
+        if empty > 0 {
+            problems.push(format!("{} lines have no text", empty));
+        }
+        sqlx::query("UPDATE quarantined_imports SET problems = ? WHERE id = ?")
+            .bind(serde_json::to_string(&problems)?)
+            .bind(import_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.get_quarantined_import(import_id)
+            .await?
+            .context("Staged lines not found")
+    }
+
+    /// The staged lines with their proposed students, for the preview.
+    pub async fn get_clipboard_rows(&self, import_id: i64) -> Result<Vec<crate::clipboard_import::ClipboardRow>> {
+        let payload = sqlx::query_scalar::<_, Vec<u8>>(
+            "SELECT payload FROM quarantined_imports WHERE id = ? AND kind = 'clipboard_observations'",
+        )
+        .bind(import_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .context("Staged lines not found")?;
+        Ok(serde_json::from_slice(&self.crypto.decrypt_bytes(&payload)?)?)
+    }
+
+    /// Creates observations for the confirmed lines and removes the import
+    /// from the quarantine; lines left out are dropped with it. A line goes
+    /// to one of the students its name column matched, never to anyone else.
+    pub async fn promote_clipboard_rows(
+        &self,
+        import_id: i64,
+        author_id: i64,
+        confirmed: &[ConfirmedScan],
+    ) -> Result<Vec<Observation>> {
+        let rows = self.get_clipboard_rows(import_id).await?;
+        let mut new_observations = Vec::with_capacity(confirmed.len());
+        for entry in confirmed {
+            let row = rows
+                .get(entry.index)
+                .with_context(|| format!("Copied line {} not found", entry.index))?;
+            if !row.candidate_student_ids.contains(&entry.student_id) {
+                return Err(anyhow::anyhow!(
+                    "Line {} names \"{}\", which does not match student {}",
+                    row.line,
+                    row.name,
+                    entry.student_id
+                ));
+            }
+            let text = entry.text.clone().unwrap_or_else(|| row.text.clone());
+            if text.trim().is_empty() {
+                return Err(anyhow::anyhow!("Line {} has no text", row.line));
+            }
+            new_observations.push(NewObservation {
+                student_id: entry.student_id,
+                category: entry.category.clone(),
+                text,
+                tags: vec!["zwischenablage".to_string()],
+                created_at: None,
+                local_only: false,
+                visibility: None,
+                subject: None,
+                reported_by_user_id: None,
+                reported_by: None,
+            });
+        }
+
+        let created = self.create_observations_batch(author_id, new_observations).await?;
+        self.discard_quarantined_import(import_id).await?;
+        Ok(created)
+    }
+
+    /// Staged texts are kept encrypted like the payload they come with.
+    fn seal_staged_text(&self, text: &str) -> Result<String> {
+        Ok(BASE64_STANDARD.encode(self.crypto.encrypt_bytes(text.as_bytes())?))
+    }
+
+    /// Encrypts what was staged before staged imports were encrypted.
+    async fn seal_staged_imports(&self) -> Result<()> {
+        let payloads = sqlx::query_as::<_, (i64, Vec<u8>)>("SELECT id, payload FROM quarantined_imports")
+            .fetch_all(&self.pool)
+            .await?;
+        for (id, payload) in payloads.into_iter().filter(|(_, p)| !CryptoManager::is_encrypted(p)) {
+            sqlx::query("UPDATE quarantined_imports SET payload = ? WHERE id = ?")
+                .bind(self.crypto.encrypt_bytes(&payload)?)
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        let texts = sqlx::query_as::<_, (i64, String)>("SELECT rowid, text FROM quarantined_observations")
+            .fetch_all(&self.pool)
+            .await?;
+        for (rowid, text) in texts {
+            let sealed = BASE64_STANDARD.decode(&text).is_ok_and(|t| CryptoManager::is_encrypted(&t));
+            if !sealed {
+                sqlx::query("UPDATE quarantined_observations SET text = ? WHERE rowid = ?")
+                    .bind(self.seal_staged_text(&text)?)
+                    .bind(rowid)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the staged imports that hold anything about the student: its
+    /// observations, its record, or notes and lines that may mean it. Part of
+    /// every path that deletes the student's data. Returns how many went.
+    pub async fn purge_staged_imports_of_student(&self, student_id: i64) -> Result<u64> {
+        let mut import_ids: Vec<i64> =
+            sqlx::query_scalar("SELECT DISTINCT import_id FROM quarantined_observations WHERE student_id = ?")
+                .bind(student_id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let payloads = sqlx::query_as::<_, (i64, String, Vec<u8>)>("SELECT id, kind, payload FROM quarantined_imports")
+            .fetch_all(&self.pool)
+            .await?;
+        for (id, kind, payload) in payloads {
+            if import_ids.contains(&id) {
+                continue;
+            }
+            let Ok(payload) = serde_json::from_slice::<serde_json::Value>(&self.crypto.decrypt_bytes(&payload)?) else {
+                continue;
+            };
+            let mentions = match kind.as_str() {
+                // Besides observations, changesets carry the details and guardians of students
+                "changeset" => {
+                    let changes = payload.get("data").and_then(|d| d.get("changes"));
+                    let mentions_in = |section: &str, field: &str| {
+                        changes
+                            .and_then(|c| c.get(section))
+                            .and_then(|s| s.as_array())
+                            .is_some_and(|items| items.iter().any(|i| i.get(field).and_then(|v| v.as_i64()) == Some(student_id)))
+                    };
+                    mentions_in("student_details", "id") || mentions_in("guardians", "student_id")
+                }
+                // Scanned notes and copied lines list every student they may mean
+                _ => payload.as_array().is_some_and(|entries| {
+                    entries.iter().any(|e| {
+                        e.get("candidate_student_ids")
+                            .and_then(|c| c.as_array())
+                            .is_some_and(|ids| ids.iter().any(|i| i.as_i64() == Some(student_id)))
+                    })
+                }),
+            };
+            if mentions {
+                import_ids.push(id);
+            }
+        }
+
+        for import_id in &import_ids {
+            self.discard_quarantined_import(*import_id).await?;
+        }
+        Ok(import_ids.len() as u64)
+    }
+
+    pub async fn discard_quarantined_import(&self, import_id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM quarantined_observations WHERE import_id = ?")
+            .bind(import_id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM quarantined_imports WHERE id = ?")
+            .bind(import_id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Staged changeset not found"));
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_applied_operations(&self, limit: Option<i64>) -> Result<Vec<SyncHistoryEntry>> {
+        let entries = sqlx::query_as::<_, SyncHistoryEntry>(
+            "SELECT * FROM sync_history WHERE direction = 'import' ORDER BY created_at DESC, id DESC LIMIT ?",
+        )
+        .bind(limit.unwrap_or(100))
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch applied operations")?;
+
+        Ok(entries)
+    }
+
+    /// Imports everything from a full backup that does not exist locally yet.
+    /// Runs in one transaction, so a cancelled or failed import leaves no trace.
+    pub async fn import_full_backup(&self, backup_data: &[u8], cancel: &CancellationToken) -> Result<String> {
+        self.import_full_backup_from_reader(std::io::Cursor::new(backup_data.to_vec()), cancel)
+            .await
+    }
+
+    /// Streaming variant of `import_full_backup`: records are parsed on a
+    /// blocking thread and inserted in chunks while the file is still being
+    /// read, so memory stays bounded regardless of the backup size.
+    pub async fn import_full_backup_from_reader<R: std::io::Read + Send + 'static>(
+        &self,
+        reader: R,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        self.ensure_not_frozen()?;
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(IMPORT_CHUNK_SIZE);
+        let parser = tokio::task::spawn_blocking(move || {
+            crate::backup_stream::read_backup_records(reader, &sender)
+        });
+
+        let mut counts = ImportCounts::default();
+        let mut tx = self.pool.begin().await?;
+
+        // Sections arrive in file order, e.g. observations before students;
+        // references only have to be complete at commit
+        sqlx::query("PRAGMA defer_foreign_keys = ON")
+            .execute(&mut *tx)
+            .await?;
+
+        let mut chunk = Vec::with_capacity(IMPORT_CHUNK_SIZE);
+        while let Some(record) = receiver.recv().await {
+            chunk.push(record);
+            while chunk.len() < IMPORT_CHUNK_SIZE {
+                match receiver.try_recv() {
+                    Ok(record) => chunk.push(record),
+                    Err(_) => break,
+                }
+            }
+
+            cancel.check()?;
+            for record in chunk.drain(..) {
+                Self::import_backup_record_on(&mut tx, record, &mut counts).await?;
+            }
+
+            // Give other commands a turn between chunks of a large import
+            tokio::task::yield_now().await;
+        }
+
+        let contents = parser
+            .await
+            .context("Backup parser stopped unexpectedly")?
+            .context("Invalid backup file format")?;
+        if let Some(manifest) = &contents.manifest {
+            manifest.verify(&contents.tables)?;
+        }
+
+        cancel.check()?;
+        tx.commit().await?;
+
+        Ok(format!(
+            "Successfully imported {} classes, {} students, {} observations, {} dictionary words",
+            counts.classes, counts.students, counts.observations, counts.dictionary_words
+        ))
+    }
+
+    /// Inserts one backup record unless a record with the same id or uuid exists.
+    async fn import_backup_record_on(
+        conn: &mut SqliteConnection,
+        record: BackupRecord,
+        counts: &mut ImportCounts,
+    ) -> Result<()> {
+        match record {
+            BackupRecord::Class(class) => {
+                let uuid = record_uuid(class.uuid.as_deref());
+                let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM classes WHERE id = ? OR uuid = ?")
+                    .bind(class.id)
+                    .bind(&uuid)
+                    .fetch_one(&mut *conn)
+                    .await?;
+
+                if exists == 0 {
+                    sqlx::query(
+                        "INSERT INTO classes (id, name, school_year, created_at, updated_at, source_device_id, uuid) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(class.id)
+                    .bind(class.name)
+                    .bind(class.school_year)
+                    .bind(class.created_at)
+                    .bind(class.updated_at)
+                    .bind(class.source_device_id)
+                    .bind(uuid)
+                    .execute(&mut *conn)
+                    .await?;
+
+                    counts.classes += 1;
+                }
+            }
+            BackupRecord::Student(student) => {
+                let uuid = record_uuid(student.uuid.as_deref());
+                let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM students WHERE id = ? OR uuid = ?")
+                    .bind(student.id)
+                    .bind(&uuid)
+                    .fetch_one(&mut *conn)
+                    .await?;
+
+                if exists == 0 {
+                    sqlx::query(
+                        "INSERT INTO students (id, class_id, first_name, last_name, status, created_at, updated_at, source_device_id, left_at, date_of_birth, notes, external_id, uuid) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(student.id)
+                    .bind(student.class_id)
+                    .bind(student.first_name)
+                    .bind(student.last_name)
+                    .bind(student.status)
+                    .bind(student.created_at)
+                    .bind(student.updated_at)
+                    .bind(student.source_device_id)
+                    .bind(student.left_at)
+                    .bind(student.date_of_birth)
+                    .bind(student.notes)
+                    .bind(student.external_id)
+                    .bind(uuid)
+                    .execute(&mut *conn)
+                    .await?;
+
+                    counts.students += 1;
+                }
+            }
+            BackupRecord::Observation(obs) => {
+                let uuid = record_uuid(obs.uuid.as_deref());
+                let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM observations WHERE id = ? OR uuid = ?")
+                    .bind(obs.id)
+                    .bind(&uuid)
+                    .fetch_one(&mut *conn)
+                    .await?;
+
+                if exists == 0 {
+                    sqlx::query(
+                        "INSERT INTO observations (id, student_id, author_id, category, text, tags, created_at, updated_at, source_device_id, local_only, cosigned_by, cosigned_at, visibility, subject, reported_by_user_id, reported_by, uuid) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(obs.id)
+                    .bind(obs.student_id)
+                    .bind(obs.author_id)
+                    .bind(obs.category)
+                    .bind(obs.text)
+                    .bind(obs.tags)
+                    .bind(obs.created_at)
+                    .bind(obs.updated_at)
+                    .bind(obs.source_device_id)
+                    .bind(obs.local_only)
+                    .bind(obs.cosigned_by)
+                    .bind(obs.cosigned_at)
+                    .bind(obs.visibility)
+                    .bind(obs.subject)
+                    .bind(obs.reported_by_user_id)
+                    .bind(obs.reported_by)
+                    .bind(uuid)
+                    .execute(&mut *conn)
+                    .await?;
+
+                    counts.observations += 1;
+                }
+            }
+            // Backups made before the dictionary existed have none
+            BackupRecord::DictionaryWord(word) => {
+                let Ok(word) = validate_dictionary_word(&word) else { return Ok(()) };
+                let result = sqlx::query("INSERT OR IGNORE INTO custom_dictionary (word) VALUES (?)")
+                    .bind(word)
+                    .execute(&mut *conn)
+                    .await?;
+                counts.dictionary_words += result.rows_affected();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restores part of a full backup into the live database. With `apply` unset
+    /// the same plan is computed and rolled back, so the result doubles as a preview.
+    /// Local records that differ from the backup are reported as conflicts and
+    /// kept unless `overwrite_conflicts` is set.
+    pub async fn restore_from_backup(
+        &self,
+        backup_data: &[u8],
+        selection: &RestoreSelection,
+        apply: bool,
+        cancel: &CancellationToken,
+    ) -> Result<RestoreReport> {
+        self.ensure_not_frozen()?;
+        let parsed: serde_json::Value = serde_json::from_slice(backup_data)
+            .context("Invalid backup file format")?;
+        let data = parsed.get("data").context("Missing data section in backup file")?;
+        manifest::verify_optional(parsed.get("manifest"), data)?;
+
+        let classes: Vec<Class> = backup_records(data, "classes");
+        let students: Vec<Student> = backup_records(data, "students");
+        let observations: Vec<Observation> = backup_records(data, "observations");
+
+        let mut report = RestoreReport {
+            applied: apply,
+            ..Default::default()
+        };
+
+        // Restored observations must sort after any local deletion of them
+        let restore_clock = if apply { self.next_logical_clock().await? } else { 0 };
+
+        // Every step runs inside the transaction; a preview simply never commits
+        let mut tx = self.pool.begin().await?;
+
+        let mut selected_students = Vec::new();
+        for student in students.into_iter().filter(|s| s.status != "deleted") {
+            cancel.check()?;
+            let local = sqlx::query_as::<_, Student>("SELECT * FROM students WHERE id = ?")
+                .bind(student.id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let deleted_locally = local.as_ref().map_or(true, |l| l.status == "deleted");
+
+            if selection.class_ids.contains(&student.class_id)
+                || (selection.deleted_students && deleted_locally)
+            {
+                selected_students.push((student, local));
+            }
+        }
+
+        let class_ids: std::collections::BTreeSet<i64> = selection
+            .class_ids
+            .iter()
+            .copied()
+            .chain(selected_students.iter().map(|(s, _)| s.class_id))
+            .collect();
+
+        for class in classes.iter().filter(|c| class_ids.contains(&c.id)) {
+            let local = sqlx::query_as::<_, Class>("SELECT * FROM classes WHERE id = ?")
+                .bind(class.id)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            let action = match local {
+                None => {
+                    sqlx::query(
+                        "INSERT INTO classes (id, name, school_year, created_at, updated_at, source_device_id, uuid) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(class.id)
+                    .bind(&class.name)
+                    .bind(&class.school_year)
+                    .bind(class.created_at)
+                    .bind(class.updated_at)
+                    .bind(&class.source_device_id)
+                    .bind(record_uuid(class.uuid.as_deref()))
+                    .execute(&mut *tx)
+                    .await?;
+                    "create"
+                }
+                Some(l) if l.name == class.name && l.school_year == class.school_year => "unchanged",
+                Some(_) if selection.overwrite_conflicts => {
+                    sqlx::query("UPDATE classes SET name = ?, school_year = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                        .bind(&class.name)
+                        .bind(&class.school_year)
+                        .bind(class.id)
+                        .execute(&mut *tx)
+                        .await?;
+                    "overwrite"
+                }
+                Some(_) => "conflict",
+            };
+            report.push("class", class.id, format!("{} ({})", class.name, class.school_year), action);
+        }
+
+        let mut restored_student_ids = std::collections::HashSet::new();
+        for (student, local) in &selected_students {
+            let label = format!("{} {}", student.first_name, student.last_name);
+
+            let class_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM classes WHERE id = ?")
+                .bind(student.class_id)
+                .fetch_one(&mut *tx)
+                .await?;
+            if class_exists == 0 {
+                // The class is neither present locally nor contained in the backup
+                report.push("student", student.id, label, "skipped");
+                continue;
+            }
+
+            let action = match local {
+                None => {
+                    sqlx::query(
+                        "INSERT INTO students (id, class_id, first_name, last_name, status, created_at, updated_at, source_device_id, left_at, date_of_birth, notes, external_id, uuid) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                    .bind(student.id)
+                    .bind(student.class_id)
+                    .bind(&student.first_name)
+                    .bind(&student.last_name)
+                    .bind(&student.status)
+                    .bind(student.created_at)
+                    .bind(student.updated_at)
+                    .bind(&student.source_device_id)
+                    .bind(student.left_at)
+                    .bind(student.date_of_birth)
+                    .bind(&student.notes)
+                    .bind(&student.external_id)
+                    .bind(record_uuid(student.uuid.as_deref()))
+                    .execute(&mut *tx)
+                    .await?;
+                    "create"
+                }
+                Some(l) if l.status == "deleted" || selection.overwrite_conflicts => {
+                    let unchanged = l.status != "deleted"
+                        && l.class_id == student.class_id
+                        && l.first_name == student.first_name
+                        && l.last_name == student.last_name;
+                    if unchanged {
+                        "unchanged"
+                    } else {
+                        sqlx::query(
+                            "UPDATE students SET class_id = ?, first_name = ?, last_name = ?, status = ?, left_at = ?, date_of_birth = ?, notes = ?, external_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+                        )
+                        .bind(student.class_id)
+                        .bind(&student.first_name)
+                        .bind(&student.last_name)
+                        .bind(&student.status)
+                        .bind(student.left_at)
+                        .bind(student.date_of_birth)
+                        .bind(&student.notes)
+                        .bind(&student.external_id)
+                        .bind(student.id)
+                        .execute(&mut *tx)
+                        .await?;
+                        if l.status == "deleted" { "undelete" } else { "overwrite" }
+                    }
+                }
+                Some(l)
+                    if l.class_id == student.class_id
+                        && l.first_name == student.first_name
+                        && l.last_name == student.last_name =>
+                {
+                    "unchanged"
+                }
+                Some(_) => "conflict",
+            };
+            restored_student_ids.insert(student.id);
+            report.push("student", student.id, label, action);
+        }
+
+        for obs in observations.iter().filter(|o| restored_student_ids.contains(&o.student_id)) {
+            cancel.check()?;
+            let local = sqlx::query_as::<_, Observation>("SELECT * FROM observations WHERE id = ?")
+                .bind(obs.id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let label = format!("{} ({})", obs.category, obs.created_at.format("%Y-%m-%d"));
+
+            let action = match local {
+                None => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO observations (id, student_id, author_id, category, text, tags, created_at, updated_at, source_device_id, logical_clock, local_only, cosigned_by, cosigned_at, visibility, subject, reported_by_user_id, reported_by, uuid)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        "#,
+                    )
+                    .bind(obs.id)
+                    .bind(obs.student_id)
+                    .bind(obs.author_id)
+                    .bind(&obs.category)
+                    .bind(&obs.text)
+                    .bind(&obs.tags)
+                    .bind(obs.created_at)
+                    .bind(obs.updated_at)
+                    .bind(&obs.source_device_id)
+                    .bind(restore_clock)
+                    .bind(obs.local_only)
+                    .bind(obs.cosigned_by)
+                    .bind(obs.cosigned_at)
+                    .bind(&obs.visibility)
+                    .bind(&obs.subject)
+                    .bind(obs.reported_by_user_id)
+                    .bind(&obs.reported_by)
+                    .bind(record_uuid(obs.uuid.as_deref()))
+                    .execute(&mut *tx)
+                    .await?;
+                    "create"
+                }
+                Some(l) if l.category == obs.category && l.text == obs.text && l.tags == obs.tags => "unchanged",
+                // Co-signed entries are immutable, even against a backup
+                Some(l) if l.cosigned_at.is_some() => "conflict",
+                Some(_) if selection.overwrite_conflicts => {
+                    Self::record_retractions_on(&mut tx, "o.id = ?", obs.id, "corrected").await?;
+                    sqlx::query(
+                        "UPDATE observations SET category = ?, text = ?, tags = ?, updated_at = CURRENT_TIMESTAMP, logical_clock = ? WHERE id = ?",
+                    )
+                    .bind(&obs.category)
+                    .bind(&obs.text)
+                    .bind(&obs.tags)
+                    .bind(restore_clock)
+                    .bind(obs.id)
+                    .execute(&mut *tx)
+                    .await?;
+                    "overwrite"
+                }
+                Some(_) => "conflict",
+            };
+            report.push("observation", obs.id, label, action);
+        }
+
+        if !apply {
+            tx.rollback().await?;
+            return Ok(report);
+        }
+
+        cancel.check()?;
+        tx.commit().await?;
+
+        // Restored records travel with the next changeset like any other change
+        for item in &report.items {
+            let operation = match item.action.as_str() {
+                "create" => "create",
+                "undelete" | "overwrite" => "update",
+                _ => continue,
+            };
+            self.record_change(&item.entity_type, item.entity_id, operation, None).await?;
+        }
+
+        Ok(report)
+    }
+
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Ids and creation times of all rows of a synced entity, soft-deleted ones included.
+    pub async fn get_entity_rows(&self, entity_type: &str) -> Result<Vec<(i64, chrono::DateTime<chrono::Utc>)>> {
+        let table = match entity_type {
+            "class" => "classes",
+            "student" => "students",
+            "observation" => "observations",
+            "guardian" => "guardians",
+            other => return Err(anyhow::anyhow!("Unknown entity type '{}'", other)),
+        };
+        let rows = sqlx::query_as::<_, (i64, chrono::DateTime<chrono::Utc>)>(&format!("SELECT id, created_at FROM {}", table))
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to read entity rows")?;
+
+        Ok(rows)
+    }
+
+    /// Attachment payload bytes grouped by the class of the observed student.
+    pub async fn get_attachment_usage_by_class(&self) -> Result<Vec<(i64, String, i64, i64)>> {
+        let usage = sqlx::query_as::<_, (i64, String, i64, i64)>(
+            r#"
+            SELECT c.id, c.name, COUNT(a.id), COALESCE(SUM(a.size_bytes), 0)
+            FROM classes c
+            JOIN students s ON s.class_id = c.id
+            JOIN observations o ON o.student_id = s.id
+            JOIN attachments a ON a.observation_id = o.id AND a.storage != 'remote'
+            GROUP BY c.id
+            ORDER BY 4 DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to compute attachment usage")?;
+
+        Ok(usage)
+    }
+
+    // Job operations
+    /// Records a long-running command as it starts. `params` must not hold
+    /// passphrases or keys. Only the newest `MAX_JOB_HISTORY` jobs are kept.
+    pub async fn create_job(
+        &self,
+        job_type: &str,
+        params: &serde_json::Value,
+        operation_id: Option<&str>,
+    ) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO jobs (job_type, params, operation_id) VALUES (?, ?, ?) RETURNING id",
+        )
+        .bind(job_type)
+        .bind(params.to_string())
+        .bind(operation_id)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to record job")?;
+
+        sqlx::query("DELETE FROM jobs WHERE id <= ? AND status != 'running'")
+            .bind(id - MAX_JOB_HISTORY)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(id)
+    }
+
+    pub async fn finish_job(
+        &self,
+        job_id: i64,
+        status: &str,
+        result_path: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE jobs
+            SET status = ?, progress = CASE WHEN ? = 'completed' THEN 1 ELSE progress END,
+                result_path = ?, error = ?, finished_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#,
+        )
+        .bind(status)
+        .bind(status)
+        .bind(result_path)
+        .bind(error)
+        .bind(job_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to update job")?;
+
+        Ok(())
+    }
+
+    /// Writes the progress of a running job without the application's
+    /// database lock, which the job itself usually holds.
+    pub fn job_progress_recorder(&self, job_id: i64) -> JobProgressRecorder {
+        JobProgressRecorder {
+            pool: self.pool.clone(),
+            job_id,
+        }
+    }
+
+    /// Newest first.
+    pub async fn get_jobs(&self, limit: u32) -> Result<Vec<Job>> {
+        let jobs = sqlx::query_as::<_, Job>("SELECT * FROM jobs ORDER BY id DESC LIMIT ?")
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch jobs")?;
+
+        Ok(jobs)
+    }
+
+    pub async fn get_job(&self, job_id: i64) -> Result<Option<Job>> {
+        let job = sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE id = ?")
+            .bind(job_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch job")?;
+
+        Ok(job)
+    }
+
+    /// Jobs still running at startup were cut off when the app closed.
+    pub async fn interrupt_running_jobs(&self) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE jobs SET status = 'interrupted', finished_at = CURRENT_TIMESTAMP WHERE status = 'running'",
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark interrupted jobs")?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Gives those of the given students, classes or observations that have
+    /// no reference code yet the next number of the year they were created
+    /// in, in order of creation. Returns how many codes were assigned.
+    pub async fn assign_reference_codes(&self, entity_type: &str, ids: &[i64], format: &str) -> Result<usize> {
+        let table = match entity_type {
+            "student" => "students",
+            "class" => "classes",
+            "observation" => "observations",
+            other => return Err(anyhow::anyhow!("No reference codes for {}", other)),
+        };
+        let prefix = crate::reference_codes::prefix(entity_type)?;
+
+        let mut tx = self.pool.begin().await?;
+        let missing = sqlx::query_as::<_, (i64, i32)>(&format!(
+            r#"
+            SELECT e.id, CAST(substr(e.created_at, 1, 4) AS INTEGER)
+            FROM {} e
+            WHERE e.id IN (SELECT value FROM json_each(?))
+              AND NOT EXISTS (SELECT 1 FROM reference_codes r WHERE r.entity_type = ? AND r.entity_id = e.id)
+            ORDER BY e.created_at, e.id
+            "#,
+            table
+        ))
+        .bind(serde_json::to_string(ids)?)
+        .bind(entity_type)
+        .fetch_all(&mut *tx)
+        .await
+        .context("Failed to find records without reference code")?;
+
+        let mut next_numbers: HashMap<i32, i64> = HashMap::new();
+        for (entity_id, year) in &missing {
+            let number = match next_numbers.get(year) {
+                Some(number) => *number,
+                None => {
+                    sqlx::query_scalar::<_, i64>(
+                        "SELECT COALESCE(MAX(number), 0) + 1 FROM reference_codes WHERE entity_type = ? AND year = ?",
+                    )
+                    .bind(entity_type)
+                    .bind(year)
+                    .fetch_one(&mut *tx)
+                    .await?
+                }
+            };
+            sqlx::query(
+                "INSERT INTO reference_codes (entity_type, entity_id, year, number, code) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(entity_type)
+            .bind(entity_id)
+            .bind(year)
+            .bind(number)
+            .bind(crate::reference_codes::render(format, prefix, *year, number))
+            .execute(&mut *tx)
+            .await
+            .context("Failed to store reference code")?;
+            next_numbers.insert(*year, number + 1);
+        }
+        tx.commit().await?;
+
+        Ok(missing.len())
+    }
+
+    /// Codes of the records a changeset carries and of the classes of its
+    /// students. Records of this device get their code before they leave it,
+    /// so the receiving device shows the same code instead of numbering them
+    /// itself. Records are named by uuid, as ids differ between devices.
+    async fn get_reference_codes_for_sync(
+        &self,
+        observation_ids: &[i64],
+        student_ids: &[i64],
+    ) -> Result<Vec<serde_json::Value>> {
+        let device_id = self.crypto.get_device_id();
+        let class_ids = sqlx::query_scalar::<_, i64>(
+            "SELECT DISTINCT class_id FROM students WHERE id IN (SELECT value FROM json_each(?))",
+        )
+        .bind(serde_json::to_string(student_ids)?)
+        .fetch_all(&self.pool)
+        .await?;
+        let format = crate::reference_codes::get_format(self).await?;
+
+        let mut records = Vec::new();
+        for (entity_type, table, ids) in [
+            ("observation", "observations", observation_ids),
+            ("student", "students", student_ids),
+            ("class", "classes", class_ids.as_slice()),
+        ] {
+            let ids = serde_json::to_string(ids)?;
+            // The export itself goes ahead while processing is frozen
+            if self.ensure_not_frozen().is_ok() {
+                let own = sqlx::query_scalar::<_, i64>(&format!(
+                    "SELECT id FROM {} WHERE source_device_id = ? AND id IN (SELECT value FROM json_each(?))",
+                    table
+                ))
+                .bind(&device_id)
+                .bind(&ids)
+                .fetch_all(&self.pool)
+                .await?;
+                self.assign_reference_codes(entity_type, &own, &format).await?;
+            }
+
+            let codes = sqlx::query_as::<_, (String, i64, i64, String)>(&format!(
+                r#"
+                SELECT e.uuid, r.year, r.number, r.code
+                FROM reference_codes r
+                JOIN {} e ON e.id = r.entity_id
+                WHERE r.entity_type = ? AND r.entity_id IN (SELECT value FROM json_each(?)) AND e.uuid IS NOT NULL
+                ORDER BY r.entity_id
+                "#,
+                table
+            ))
+            .bind(entity_type)
+            .bind(&ids)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to read reference codes for sync")?;
+            records.extend(codes.into_iter().map(|(uuid, year, number, code)| {
+                serde_json::json!({
+                    "entity_type": entity_type,
+                    "entity_uuid": uuid,
+                    "year": year,
+                    "number": number,
+                    "code": code,
+                })
+            }));
+        }
+        Ok(records)
+    }
+
+    /// Takes over the code the sending device gave a record. A record with a
+    /// code of its own here keeps it, and a code or number already given to
+    /// another record here is not given twice; both come back as a warning.
+    async fn apply_reference_code_on(conn: &mut SqliteConnection, record: &serde_json::Value) -> Result<Option<String>> {
+        let text = |key: &str| record.get(key).and_then(|v| v.as_str()).map(|v| v.to_string());
+        let number = |key: &str| record.get(key).and_then(|v| v.as_i64());
+        let (Some(entity_type), Some(uuid), Some(code), Some(year), Some(number)) =
+            (text("entity_type"), text("entity_uuid"), text("code"), number("year"), number("number"))
+        else {
+            return Ok(None);
+        };
+        let table = match entity_type.as_str() {
+            "student" => "students",
+            "class" => "classes",
+            "observation" => "observations",
+            _ => return Ok(None),
+        };
+        let Some(entity_id) = sqlx::query_scalar::<_, i64>(&format!("SELECT id FROM {} WHERE uuid = ?", table))
+            .bind(&uuid)
+            .fetch_optional(&mut *conn)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let taken = sqlx::query_as::<_, (String, i64, String)>(
+            r#"
+            SELECT entity_type, entity_id, code FROM reference_codes
+            WHERE (entity_type = ? AND (entity_id = ? OR (year = ? AND number = ?))) OR code = ?
+            "#,
+        )
+        .bind(&entity_type)
+        .bind(entity_id)
+        .bind(year)
+        .bind(number)
+        .bind(&code)
+        .fetch_all(&mut *conn)
+        .await?;
+        if let Some((_, _, local)) = taken.iter().find(|(t, id, _)| *t == entity_type && *id == entity_id) {
+            if *local == code {
+                return Ok(None);
+            }
+            return Ok(Some(format!(
+                "The {} with reference code {} here has the code {} on the sending device",
+                entity_type, local, code
+            )));
+        }
+        if !taken.is_empty() {
+            return Ok(Some(format!(
+                "Reference code {} of a {} from the sending device is already given to another record here; the {} gets a code of its own",
+                code, entity_type, entity_type
+            )));
+        }
+
+        sqlx::query(
+            "INSERT INTO reference_codes (entity_type, entity_id, year, number, code) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&entity_type)
+        .bind(entity_id)
+        .bind(year)
+        .bind(number)
+        .bind(&code)
+        .execute(&mut *conn)
+        .await
+        .context("Failed to store reference code")?;
+        Ok(None)
+    }
+
+    /// Reference codes of the given ids, see `reference_codes::codes_for`.
+    pub async fn get_reference_codes(&self, entity_type: &str, ids: &[i64]) -> Result<BTreeMap<i64, String>> {
+        let codes = sqlx::query_as::<_, (i64, String)>(
+            "SELECT entity_id, code FROM reference_codes WHERE entity_type = ? AND entity_id IN (SELECT value FROM json_each(?))",
+        )
+        .bind(entity_type)
+        .bind(serde_json::to_string(ids)?)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to read reference codes")?;
+
+        Ok(codes.into_iter().collect())
+    }
+
+    // Settings operations
+    pub async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let value = sqlx::query_scalar::<_, String>("SELECT value FROM app_settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to read setting")?;
+
+        Ok(value)
+    }
+
+    pub async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO app_settings (key, value) VALUES (?, ?)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await
+        .context("Failed to store setting")?;
+
+        Ok(())
+    }
+
+    pub async fn get_all_settings(&self) -> Result<BTreeMap<String, String>> {
+        let settings = sqlx::query_as::<_, (String, String)>("SELECT key, value FROM app_settings")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to read settings")?;
+
+        Ok(settings.into_iter().collect())
+    }
+
+    // User operations
+    pub async fn get_users(&self) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, User>("SELECT * FROM users ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch users")?;
+
+        Ok(users)
+    }
+
+    pub async fn get_user(&self, user_id: i64) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch user")?;
+
+        Ok(user)
+    }
+
+    pub async fn create_user(&self, name: String, role: String) -> Result<User> {
+        if !USER_ROLES.contains(&role.as_str()) {
+            return Err(anyhow::anyhow!("Role must be one of: {}", USER_ROLES.join(", ")));
+        }
+
+        let user = sqlx::query_as::<_, User>("INSERT INTO users (name, role) VALUES (?, ?) RETURNING *")
+            .bind(name.trim())
+            .bind(role)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to create user")?;
+
+        Ok(user)
+    }
+
+    /// Changes the role of a user; only an admin may, and the last admin
+    /// stays one. Returns the user before and after.
+    pub async fn set_user_role(&self, user_id: i64, role: &str, admin_id: i64) -> Result<(User, User)> {
+        self.require_admin(admin_id).await?;
+        if !USER_ROLES.contains(&role) {
+            return Err(anyhow::anyhow!("Role must be one of: {}", USER_ROLES.join(", ")));
+        }
+        let before = self
+            .get_user(user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Unknown user {}", user_id))?;
+        if before.role == "admin" && role != "admin" {
+            let admins = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE role = 'admin'")
+                .fetch_one(&self.pool)
+                .await?;
+            if admins <= 1 {
+                return Err(anyhow::anyhow!("The last admin cannot lose the admin role"));
+            }
+        }
+
+        let user = sqlx::query_as::<_, User>("UPDATE users SET role = ? WHERE id = ? RETURNING *")
+            .bind(role)
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to change user role")?;
+
+        Ok((before, user))
+    }
+
+    /// Fails unless `user_id` belongs to an admin.
+    pub async fn require_admin(&self, user_id: i64) -> Result<User> {
+        match self.get_user(user_id).await? {
+            Some(user) if user.role == "admin" => Ok(user),
+            Some(_) => Err(anyhow::anyhow!("Permission denied: this action requires an admin")),
+            None => Err(anyhow::anyhow!("Unknown user {}", user_id)),
+        }
+    }
+
+    // Erasure request operations
+    /// With `execute_at` set the request is carried out by the scheduler at
+    /// that time instead of waiting for approval.
+    pub async fn create_erasure_request(
+        &self,
+        student_id: i64,
+        requested_by: i64,
+        reason: &str,
+        execute_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<ErasureRequest> {
+        if self.get_student(student_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Student not found"));
+        }
+        if self.get_user(requested_by).await?.is_none() {
+            return Err(anyhow::anyhow!("Unknown user {}", requested_by));
+        }
+
+        let pending = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM erasure_requests WHERE student_id = ? AND status = 'pending'",
+        )
+        .bind(student_id)
+        .fetch_one(&self.pool)
+        .await?;
+        if pending > 0 {
+            return Err(anyhow::anyhow!("An erasure request for this student is already pending"));
+        }
+
+        let request = sqlx::query_as::<_, ErasureRequest>(
+            "INSERT INTO erasure_requests (student_id, requested_by, reason, execute_at) VALUES (?, ?, ?, ?) RETURNING *",
+        )
+        .bind(student_id)
+        .bind(requested_by)
+        .bind(reason.trim())
+        .bind(execute_at)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create erasure request")?;
+
+        Ok(request)
+    }
+
+    pub async fn get_erasure_request(&self, request_id: i64) -> Result<Option<ErasureRequest>> {
+        let request = sqlx::query_as::<_, ErasureRequest>("SELECT * FROM erasure_requests WHERE id = ?")
+            .bind(request_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch erasure request")?;
+
+        Ok(request)
+    }
+
+    /// Requests still waiting for a decision or their scheduled date, oldest first.
+    pub async fn get_open_requests(&self) -> Result<Vec<ErasureRequest>> {
+        let requests = sqlx::query_as::<_, ErasureRequest>(
+            "SELECT * FROM erasure_requests WHERE status = 'pending' ORDER BY requested_at, id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch open requests")?;
+
+        Ok(requests)
+    }
+
+    /// Approved erasures due before `until` that nobody has been told about yet.
+    pub async fn get_erasures_to_announce(
+        &self,
+        until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<ErasureRequest>> {
+        let requests = sqlx::query_as::<_, ErasureRequest>(
+            r#"
+            SELECT * FROM erasure_requests
+            WHERE status = 'pending' AND approved_by IS NOT NULL AND execute_at IS NOT NULL AND notified_at IS NULL
+              AND datetime(execute_at) <= datetime(?)
+            ORDER BY execute_at
+            "#,
+        )
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch upcoming erasures")?;
+
+        Ok(requests)
+    }
+
+    pub async fn mark_erasure_announced(&self, request_id: i64) -> Result<()> {
+        sqlx::query("UPDATE erasure_requests SET notified_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(request_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to update erasure request")?;
+
+        Ok(())
+    }
+
+    pub async fn get_due_erasures(&self, now: chrono::DateTime<chrono::Utc>) -> Result<Vec<ErasureRequest>> {
+        let requests = sqlx::query_as::<_, ErasureRequest>(
+            r#"
+            SELECT * FROM erasure_requests
+            WHERE status = 'pending' AND approved_by IS NOT NULL AND execute_at IS NOT NULL
+              AND datetime(execute_at) <= datetime(?)
+            ORDER BY execute_at
+            "#,
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch due erasures")?;
+
+        Ok(requests)
+    }
+
+    /// Records the decision; only pending requests can be decided.
+    /// Lets the scheduler carry out a scheduled erasure when it is due.
+    pub async fn approve_scheduled_erasure_request(&self, request_id: i64, approved_by: i64) -> Result<ErasureRequest> {
+        let request = sqlx::query_as::<_, ErasureRequest>(
+            r#"
+            UPDATE erasure_requests SET approved_by = ?, approved_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND status = 'pending' AND execute_at IS NOT NULL
+            RETURNING *
+            "#,
+        )
+        .bind(approved_by)
+        .bind(request_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to update erasure request")?;
+
+        request.ok_or_else(|| anyhow::anyhow!("Erasure request {} is not a pending scheduled erasure", request_id))
+    }
+
+    pub async fn decide_erasure_request(&self, request_id: i64, status: &str, decided_by: i64) -> Result<()> {
+        let result = sqlx::query(
+            r#"
+            UPDATE erasure_requests SET status = ?, decided_by = ?, decided_at = CURRENT_TIMESTAMP
+            WHERE id = ? AND status = 'pending'
+            "#,
+        )
+        .bind(status)
+        .bind(decided_by)
+        .bind(request_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to update erasure request")?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Erasure request {} is not pending", request_id));
+        }
+        Ok(())
+    }
+
+    // Observation lock operations
+    pub async fn get_observation_lock_days(&self) -> Result<Option<u32>> {
+        Ok(self
+            .get_setting(OBSERVATION_LOCK_SETTING)
+            .await?
+            .and_then(|days| days.parse::<u32>().ok())
+            .filter(|days| *days > 0))
+    }
+
+    pub async fn set_observation_lock_days(&self, days: Option<u32>) -> Result<()> {
+        self.set_setting(OBSERVATION_LOCK_SETTING, &days.unwrap_or(0).to_string()).await
+    }
+
+    /// Observations older than the configured lock period may only be changed
+    /// or deleted while an admin unlock is in effect.
+    /// Co-signed observations can never be changed again.
+    pub async fn ensure_observation_editable(&self, observation_id: i64) -> Result<()> {
+        let Some(observation) = self.get_observation(observation_id).await? else {
+            return Ok(());
+        };
+        if let Some(cosigned_at) = observation.cosigned_at {
+            return Err(anyhow::anyhow!(
+                "Observation {} was co-signed on {} and can no longer be changed",
+                observation_id,
+                cosigned_at.format("%d.%m.%Y")
+            ));
+        }
+        let Some(lock_days) = self.get_observation_lock_days().await? else {
+            return Ok(());
+        };
+
+        let locked_since = observation.created_at + chrono::Duration::days(lock_days as i64);
+        if locked_since > chrono::Utc::now() {
+            return Ok(());
+        }
+
+        let unlocked = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM observation_unlocks WHERE observation_id = ? AND datetime(expires_at) > datetime('now')",
+        )
+        .bind(observation_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        if unlocked == 0 {
+            return Err(anyhow::anyhow!(
+                "Observation {} is read-only since {}; an admin must unlock it first",
+                observation_id,
+                locked_since.format("%d.%m.%Y")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Lets a read-only observation be changed for `OBSERVATION_UNLOCK_HOURS`.
+    /// Returns when the unlock expires.
+    pub async fn unlock_observation(
+        &self,
+        observation_id: i64,
+        admin_id: i64,
+        reason: &str,
+    ) -> Result<chrono::DateTime<chrono::Utc>> {
+        self.require_admin(admin_id).await?;
+        if reason.trim().is_empty() {
+            return Err(anyhow::anyhow!("A reason is required to unlock an observation"));
+        }
+        if self.get_observation(observation_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Observation not found"));
+        }
+
+        let expires_at = chrono::Utc::now() + chrono::Duration::hours(OBSERVATION_UNLOCK_HOURS);
+        sqlx::query(
+            r#"
+            INSERT INTO observation_unlocks (observation_id, unlocked_by, reason, expires_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(observation_id) DO UPDATE SET
+                unlocked_by = excluded.unlocked_by, reason = excluded.reason,
+                unlocked_at = CURRENT_TIMESTAMP, expires_at = excluded.expires_at
+            "#,
+        )
+        .bind(observation_id)
+        .bind(admin_id)
+        .bind(reason.trim())
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        .context("Failed to unlock observation")?;
+
+        Ok(expires_at)
+    }
+
+    // Mention analysis
+    /// `(id, student_id, text)` of every observation, for the mention analyzer.
+    pub async fn get_observation_texts(&self) -> Result<Vec<(i64, i64, String)>> {
+        let texts = sqlx::query_as::<_, (i64, i64, String)>("SELECT id, student_id, text FROM observations ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch observation texts")?;
+
+        Ok(texts)
+    }
+
+    /// `(observation_id, mentioned_student_id, matched_text)` as last stored by the analyzer.
+    pub async fn get_stored_mentions(&self) -> Result<Vec<(i64, i64, String)>> {
+        let mentions = sqlx::query_as::<_, (i64, i64, String)>(
+            "SELECT observation_id, mentioned_student_id, matched_text FROM mention_warnings",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch stored mentions")?;
+
+        Ok(mentions)
+    }
+
+    /// Replaces the analyzer's findings for one observation; an empty list clears them.
+    pub async fn replace_mention_warnings(&self, observation_id: i64, mentions: &[(i64, String)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM mention_warnings WHERE observation_id = ?")
+            .bind(observation_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for (student_id, matched_text) in mentions {
+            sqlx::query(
+                "INSERT INTO mention_warnings (observation_id, mentioned_student_id, matched_text) VALUES (?, ?, ?)",
+            )
+            .bind(observation_id)
+            .bind(student_id)
+            .bind(matched_text)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to store mention warning")?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Private observations of other authors are left out, as `viewer_id` may not see them.
+    pub async fn get_mention_warnings(&self, class_id: Option<i64>, viewer_id: i64) -> Result<Vec<MentionWarning>> {
+        let warnings = sqlx::query_as::<_, MentionWarning>(
+            r#"
+            SELECT w.observation_id, o.student_id, s.class_id, w.mentioned_student_id,
+                   m.first_name || ' ' || m.last_name AS mentioned_name,
+                   w.matched_text, o.text, o.created_at, w.detected_at
+            FROM mention_warnings w
+            JOIN observations o ON o.id = w.observation_id
+            JOIN students s ON s.id = o.student_id
+            JOIN students m ON m.id = w.mentioned_student_id
+            WHERE s.status != 'deleted' AND (? IS NULL OR s.class_id = ?)
+              AND (o.visibility != ? OR o.author_id = ?)
+            ORDER BY o.created_at DESC, w.observation_id, mentioned_name
+            "#,
+        )
+        .bind(class_id)
+        .bind(class_id)
+        .bind(VISIBILITY_PRIVATE)
+        .bind(viewer_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch mention warnings")?;
+
+        Ok(warnings)
+    }
+
+    // Dictionary operations
+    /// Words the spellchecker should accept: the custom entries plus the names
+    /// of all current students, sorted and without duplicates.
+    pub async fn get_custom_dictionary(&self) -> Result<Vec<String>> {
+        let words = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT word FROM custom_dictionary
+            UNION SELECT first_name FROM students WHERE status != 'deleted'
+            UNION SELECT last_name FROM students WHERE status != 'deleted'
+            ORDER BY 1 COLLATE NOCASE
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch dictionary")?;
+
+        Ok(words)
+    }
+
+    /// Only the words added by users; this is what backups carry.
+    pub async fn get_dictionary_words(&self) -> Result<Vec<String>> {
+        let words = sqlx::query_scalar::<_, String>("SELECT word FROM custom_dictionary ORDER BY word")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch dictionary")?;
+
+        Ok(words)
+    }
+
+    /// Returns false if the word was already in the dictionary.
+    pub async fn add_dictionary_word(&self, word: &str) -> Result<bool> {
+        let word = validate_dictionary_word(word)?;
+
+        let result = sqlx::query("INSERT OR IGNORE INTO custom_dictionary (word) VALUES (?)")
+            .bind(word)
+            .execute(&self.pool)
+            .await
+            .context("Failed to add dictionary word")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove_dictionary_word(&self, word: &str) -> Result<()> {
+        sqlx::query("DELETE FROM custom_dictionary WHERE word = ?")
+            .bind(word.trim())
+            .execute(&self.pool)
+            .await
+            .context("Failed to remove dictionary word")?;
+
+        Ok(())
+    }
+
+    // Attachment operations
+    pub fn attachments_dir(&self) -> PathBuf {
+        self.db_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("attachments")
+    }
+
+    /// Payloads are content-addressed by the SHA-256 of their plaintext, so
+    /// identical files attached twice share one encrypted file.
+    fn attachment_file_path(&self, file_hash: &str) -> PathBuf {
+        self.attachments_dir().join(&file_hash[..2]).join(file_hash)
+    }
+
+    /// "database" keeps payloads as BLOBs, "external" writes them to the attachments directory.
+    pub async fn get_attachment_storage_mode(&self) -> Result<String> {
+        Ok(self
+            .get_setting(ATTACHMENT_STORAGE_SETTING)
+            .await?
+            .unwrap_or_else(|| "database".to_string()))
+    }
+
+    async fn write_attachment_file(&self, file_hash: &str, data: &[u8]) -> Result<()> {
+        let path = self.attachment_file_path(file_hash);
+        if path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("Failed to create attachments directory")?;
+        }
+
+        // Write to a temporary name first so a crash never leaves a truncated payload
+        let encrypted = self.crypto.encrypt_bytes(data)?;
+        let staged = path.with_extension("tmp");
+        tokio::fs::write(&staged, encrypted)
+            .await
+            .context("Failed to write attachment file")?;
+        tokio::fs::rename(&staged, &path)
+            .await
+            .context("Failed to store attachment file")?;
+
+        Ok(())
+    }
+
+    async fn read_attachment_file(&self, file_hash: &str) -> Result<Vec<u8>> {
+        let encrypted = tokio::fs::read(self.attachment_file_path(file_hash))
+            .await
+            .context("Attachment file is missing")?;
+        self.crypto.decrypt_bytes(&encrypted)
+    }
+
+    pub async fn store_attachment(
+        &self,
+        observation_id: i64,
+        filename: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> Result<Attachment> {
+        self.ensure_not_frozen()?;
+        let file_hash = format!("{:x}", Sha256::digest(data));
+        let storage = self.get_attachment_storage_mode().await?;
+
+        let file_data: &[u8] = if storage == "external" {
+            self.write_attachment_file(&file_hash, data).await?;
+            &[]
+        } else {
+            data
+        };
+
+        let attachment = sqlx::query_as::<_, Attachment>(
+            r#"
+            INSERT INTO attachments (observation_id, filename, content_type, file_data, file_hash, size_bytes, storage)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING id, observation_id, filename, content_type, file_hash, size_bytes, storage, created_at
+            "#,
+        )
+        .bind(observation_id)
+        .bind(filename)
+        .bind(content_type)
+        .bind(file_data)
+        .bind(&file_hash)
+        .bind(data.len() as i64)
+        .bind(&storage)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to store attachment")?;
+
+        Ok(attachment)
+    }
+
+    pub async fn get_attachment(&self, attachment_id: i64) -> Result<Option<Attachment>> {
+        let attachment = sqlx::query_as::<_, Attachment>(
+            "SELECT id, observation_id, filename, content_type, file_hash, size_bytes, storage, created_at FROM attachments WHERE id = ?",
+        )
+        .bind(attachment_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch attachment")?;
+
+        Ok(attachment)
+    }
+
+    pub async fn get_attachments(&self, observation_id: i64) -> Result<Vec<Attachment>> {
+        let attachments = sqlx::query_as::<_, Attachment>(
+            r#"
+            SELECT id, observation_id, filename, content_type, file_hash, size_bytes, storage, created_at
+            FROM attachments WHERE observation_id = ? ORDER BY created_at ASC, id ASC
+            "#,
+        )
+        .bind(observation_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch attachments")?;
+
+        Ok(attachments)
+    }
+
+    pub async fn count_student_attachments(&self, student_id: i64) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM attachments a JOIN observations o ON o.id = a.observation_id WHERE o.student_id = ?",
+        )
+        .bind(student_id)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to count attachments")?;
+
+        Ok(count)
+    }
+
+    /// Returns the payload wherever it is stored, verified against its hash.
+    pub async fn get_attachment_data(&self, attachment_id: i64) -> Result<Vec<u8>> {
+        let (storage, file_hash, file_data) = sqlx::query_as::<_, (String, String, Vec<u8>)>(
+            "SELECT storage, file_hash, file_data FROM attachments WHERE id = ?",
+        )
+        .bind(attachment_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch attachment")?
+        .context("Attachment not found")?;
+
+        let data = match storage.as_str() {
+            "external" => self.read_attachment_file(&file_hash).await?,
+            "remote" => {
+                return Err(anyhow::anyhow!(
+                    "Attachment is only stored on another device; request it with a missing attachments request"
+                ))
+            }
+            _ => file_data,
+        };
+
+        if format!("{:x}", Sha256::digest(&data)) != file_hash {
+            return Err(anyhow::anyhow!("Attachment integrity check failed"));
+        }
+
+        Ok(data)
+    }
+
+    pub fn thumbnails_dir(&self) -> PathBuf {
+        self.db_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("thumbnails")
+    }
+
+    fn thumbnail_path(&self, file_hash: &str, size: u32) -> PathBuf {
+        self.thumbnails_dir().join(format!("{}-{}.jpg", file_hash, size))
+    }
+
+    /// Returns a JPEG thumbnail of an image attachment, rendering and caching it
+    /// on first use. `size` is rounded up to one of `media::THUMBNAIL_SIZES`.
+    pub async fn get_attachment_thumbnail(&self, attachment_id: i64, size: u32) -> Result<Vec<u8>> {
+        let attachment = self
+            .get_attachment(attachment_id)
+            .await?
+            .context("Attachment not found")?;
+
+        let is_note = attachment.content_type == crate::media::STROKES_CONTENT_TYPE;
+        if !is_note && !crate::media::is_image(&attachment.content_type) {
+            return Err(anyhow::anyhow!(
+                "Attachment {} is not an image ({})",
+                attachment_id,
+                attachment.content_type
+            ));
+        }
+
+        let size = crate::media::thumbnail_size_for(size);
+        let cache_path = self.thumbnail_path(&attachment.file_hash, size);
+
+        if let Ok(cached) = tokio::fs::read(&cache_path).await {
+            if let Ok(thumbnail) = self.crypto.decrypt_bytes(&cached) {
+                return Ok(thumbnail);
+            }
+        }
+
+        // Without the payload only the thumbnail that came with it can be shown
+        if attachment.storage == "remote" {
+            let received = self.thumbnail_path(&attachment.file_hash, crate::attachment_sync::TRANSFER_THUMBNAIL_SIZE);
+            let cached = tokio::fs::read(&received)
+                .await
+                .context("Attachment is only stored on another device")?;
+            return self.crypto.decrypt_bytes(&cached);
+        }
+
+        let data = self.get_attachment_data(attachment_id).await?;
+        let thumbnail = tokio::task::spawn_blocking(move || {
+            if is_note {
+                crate::media::render_strokes_png(&data, size)
+            } else {
+                crate::media::render_thumbnail(&data, size)
+            }
+        })
+        .await
+            .context("Thumbnail rendering was interrupted")??;
+
+        // The cache is only an optimisation; failing to write it is not an error
+        if let Some(parent) = cache_path.parent() {
+            if tokio::fs::create_dir_all(parent).await.is_ok() {
+                let encrypted = self.crypto.encrypt_bytes(&thumbnail)?;
+                tokio::fs::write(&cache_path, encrypted).await.ok();
+            }
+        }
+
+        Ok(thumbnail)
+    }
+
+    /// Attachments known from a changeset whose payload is still on the other device.
+    pub async fn get_remote_attachments(&self) -> Result<Vec<Attachment>> {
+        let attachments = sqlx::query_as::<_, Attachment>(
+            r#"
+            SELECT id, observation_id, filename, content_type, file_hash, size_bytes, storage, created_at
+            FROM attachments WHERE storage = 'remote' ORDER BY observation_id, id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch remote attachments")?;
+
+        Ok(attachments)
+    }
+
+    /// Adds an attachment received from another device. Without payload it is
+    /// recorded as "remote"; a payload arriving later completes that row.
+    /// Returns whether anything changed.
+    pub async fn merge_synced_attachment(
+        &self,
+        attachment: &SyncedAttachment,
+        data: Option<&[u8]>,
+        thumbnail: Option<&[u8]>,
+    ) -> Result<bool> {
+        if data.is_some_and(|data| format!("{:x}", Sha256::digest(data)) != attachment.file_hash) {
+            return Err(anyhow::anyhow!("Attachment {} failed its integrity check", attachment.filename));
+        }
+        // Observations this device does not have, e.g. deleted ones, get no attachments
+        let observation_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM observations WHERE id = ?")
+            .bind(attachment.observation_id)
+            .fetch_one(&self.pool)
+            .await?;
+        if observation_exists == 0 {
+            return Ok(false);
+        }
+
+        if let Some(thumbnail) = thumbnail {
+            let path = self.thumbnail_path(&attachment.file_hash, crate::attachment_sync::TRANSFER_THUMBNAIL_SIZE);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&path, self.crypto.encrypt_bytes(thumbnail)?).await?;
+        }
+
+        let existing = sqlx::query_as::<_, (i64, String)>(
+            "SELECT id, storage FROM attachments WHERE observation_id = ? AND file_hash = ? ORDER BY id LIMIT 1",
+        )
+        .bind(attachment.observation_id)
+        .bind(&attachment.file_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match (existing, data) {
+            (Some((_, storage)), _) if storage != "remote" => Ok(false),
+            (Some(_), None) => Ok(false),
+            (Some((id, _)), Some(data)) => {
+                let storage = self.get_attachment_storage_mode().await?;
+                let file_data: &[u8] = if storage == "external" {
+                    self.write_attachment_file(&attachment.file_hash, data).await?;
+                    &[]
+                } else {
+                    data
+                };
+                sqlx::query("UPDATE attachments SET file_data = ?, storage = ? WHERE id = ?")
+                    .bind(file_data)
+                    .bind(&storage)
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+                Ok(true)
+            }
+            (None, Some(data)) => {
+                self.store_attachment(attachment.observation_id, &attachment.filename, &attachment.content_type, data)
+                    .await?;
+                Ok(true)
+            }
+            (None, None) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO attachments (observation_id, filename, content_type, file_data, file_hash, size_bytes, storage, created_at)
+                    VALUES (?, ?, ?, X'', ?, ?, 'remote', ?)
+                    "#,
+                )
+                .bind(attachment.observation_id)
+                .bind(&attachment.filename)
+                .bind(&attachment.content_type)
+                .bind(&attachment.file_hash)
+                .bind(attachment.size_bytes)
+                .bind(attachment.created_at)
+                .execute(&self.pool)
+                .await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Removes the attachments of all observations of a student; returns how many.
+    pub async fn delete_student_attachments(&self, student_id: i64) -> Result<i64> {
+        let result = sqlx::query(
+            "DELETE FROM attachments WHERE observation_id IN (SELECT id FROM observations WHERE student_id = ?)",
+        )
+        .bind(student_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete attachments")?;
+
+        self.prune_attachment_files().await?;
+        Ok(result.rows_affected() as i64)
+    }
+
+    /// Removes all observations of a student and their attachments, keeping
+    /// the student. Staged imports that hold observations of the student go
+    /// with them. Returns the ids of the observations.
+    pub async fn delete_student_observations(&self, student_id: i64) -> Result<Vec<i64>> {
+        self.purge_staged_imports_of_student(student_id).await?;
+        let observations = sqlx::query_as::<_, (i64, Option<String>)>(
+            "SELECT id, uuid FROM observations WHERE student_id = ? ORDER BY id",
+        )
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await?;
+        if observations.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = self.pool.acquire().await?;
+        Self::record_retractions_on(&mut conn, "o.student_id = ?", student_id, "deleted").await?;
+        drop(conn);
+
+        sqlx::query("DELETE FROM attachments WHERE observation_id IN (SELECT id FROM observations WHERE student_id = ?)")
+            .bind(student_id)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM observations WHERE student_id = ?")
+            .bind(student_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete observations")?;
+
+        let mut ids = Vec::with_capacity(observations.len());
+        for (id, uuid) in observations {
+            self.record_deletion("observation", id, uuid, "hard").await?;
+            ids.push(id);
+        }
+        self.prune_attachment_files().await?;
+        Ok(ids)
+    }
+
+    pub async fn delete_attachment(&self, attachment_id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM attachments WHERE id = ?")
+            .bind(attachment_id)
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete attachment")?;
+
+        self.prune_attachment_files().await
+    }
+
+    /// Removes external payload files and cached thumbnails no longer
+    /// referenced by any attachment row.
+    async fn prune_attachment_files(&self) -> Result<()> {
+        let thumbnails_dir = self.thumbnails_dir();
+        if thumbnails_dir.exists() {
+            let in_use: std::collections::HashSet<String> =
+                sqlx::query_scalar::<_, String>("SELECT DISTINCT file_hash FROM attachments")
+                    .fetch_all(&self.pool)
+                    .await?
+                    .into_iter()
+                    .collect();
+
+            let mut thumbnails = tokio::fs::read_dir(&thumbnails_dir).await?;
+            while let Some(thumbnail) = thumbnails.next_entry().await? {
+                let name = thumbnail.file_name().to_string_lossy().to_string();
+                let file_hash = name.split('-').next().unwrap_or_default();
+                if !in_use.contains(file_hash) {
+                    tokio::fs::remove_file(thumbnail.path()).await.ok();
+                }
+            }
+        }
+
+        let attachments_dir = self.attachments_dir();
+        if !attachments_dir.exists() {
+            return Ok(());
+        }
+
+        let referenced: std::collections::HashSet<String> = sqlx::query_scalar::<_, String>(
+            "SELECT DISTINCT file_hash FROM attachments WHERE storage = 'external'",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        let mut buckets = tokio::fs::read_dir(&attachments_dir).await?;
+        while let Some(bucket) = buckets.next_entry().await? {
+            if !bucket.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut files = tokio::fs::read_dir(bucket.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let name = file.file_name().to_string_lossy().to_string();
+                if !referenced.contains(&name) {
+                    tokio::fs::remove_file(file.path()).await.ok();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Switches the storage mode and moves every existing payload to the new
+    /// location. Returns how many attachments were moved. Each attachment is
+    /// moved on its own, so a cancelled migration leaves a consistent mix that
+    /// the next run completes.
+    pub async fn migrate_attachment_storage(&self, mode: &str, cancel: &CancellationToken) -> Result<u64> {
+        self.ensure_not_frozen()?;
+        if mode != "database" && mode != "external" {
+            return Err(anyhow::anyhow!("Unknown attachment storage mode: {}", mode));
+        }
+
+        // New attachments go to the target location while the existing ones move
+        self.set_setting(ATTACHMENT_STORAGE_SETTING, mode).await?;
+
+        let pending = sqlx::query_scalar::<_, i64>("SELECT id FROM attachments WHERE storage != ?")
+            .bind(mode)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut moved = 0;
+        let total = pending.len();
+        for (index, attachment_id) in pending.into_iter().enumerate() {
+            if cancel.is_cancelled() {
+                break;
+            }
+            cancel.report_progress(index, total);
+            // Reading verifies the hash, so a damaged payload is never propagated
+            let data = self.get_attachment_data(attachment_id).await?;
+            let file_hash = format!("{:x}", Sha256::digest(&data));
+
+            if mode == "external" {
+                self.write_attachment_file(&file_hash, &data).await?;
+                sqlx::query("UPDATE attachments SET storage = 'external', file_data = X'' WHERE id = ?")
+                    .bind(attachment_id)
+                    .execute(&self.pool)
+                    .await?;
+            } else {
+                sqlx::query("UPDATE attachments SET storage = 'database', file_data = ? WHERE id = ?")
+                    .bind(&data)
+                    .bind(attachment_id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+            moved += 1;
+        }
+
+        if mode == "database" {
+            self.prune_attachment_files().await?;
+        } else if moved > 0 {
+            // Reclaim the space the BLOBs occupied
+            sqlx::query("VACUUM").execute(&self.pool).await?;
+        }
+
+        Ok(moved)
+    }
+
+    /// Writes a consistent copy of the live database to `path`, see `create_snapshot`.
+    pub async fn write_copy(&self, path: &Path) -> Result<()> {
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await
+            .context("Failed to copy database")?;
+        Ok(())
+    }
+
+    // Snapshot operations
+    pub fn snapshots_dir(&self) -> PathBuf {
+        snapshots_dir_for(&self.db_path)
+    }
+
+    pub async fn create_snapshot(&self, label: &str) -> Result<SnapshotInfo> {
+        let snapshots_dir = self.snapshots_dir();
+        tokio::fs::create_dir_all(&snapshots_dir)
+            .await
+            .context("Failed to create snapshots directory")?;
+
+        let created_at = chrono::Utc::now();
+        let id = format!(
+            "{}-{}",
+            created_at.format("%Y%m%d-%H%M%S"),
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        );
+        let snapshot_path = snapshots_dir.join(format!("{}.db", id));
+
+        // SQLite copies the live database page by page under a read transaction,
+        // so the snapshot is consistent even while the WAL holds uncheckpointed writes
+        sqlx::query("VACUUM INTO ?")
+            .bind(snapshot_path.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await
+            .context("Failed to write snapshot")?;
+
+        let size_bytes = tokio::fs::metadata(&snapshot_path).await?.len();
+        let info = SnapshotInfo {
+            id,
+            label: label.to_string(),
+            created_at,
+            size_bytes,
+        };
+
+        tokio::fs::write(
+            snapshots_dir.join(format!("{}.json", info.id)),
+            serde_json::to_vec_pretty(&info)?,
+        )
+        .await
+        .context("Failed to write snapshot metadata")?;
+
+        Ok(info)
+    }
+
+    pub async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
+        list_snapshots_in(&self.snapshots_dir()).await
+    }
+
+    /// Replaces the live database with a snapshot. The current state is saved as
+    /// a snapshot first, so a rollback can itself be undone.
+    pub async fn rollback_to_snapshot(&mut self, snapshot_id: &str, confirmed: bool) -> Result<SnapshotInfo> {
+        self.ensure_not_frozen()?;
+        if !confirmed {
+            return Err(anyhow::anyhow!(
+                "Rolling back replaces all current data and must be confirmed"
+            ));
+        }
+
+        // Only ids from the listing are accepted, never arbitrary paths
+        let snapshot = self
+            .list_snapshots()
+            .await?
+            .into_iter()
+            .find(|s| s.id == snapshot_id)
+            .context("Snapshot not found")?;
+        let snapshot_path = self.snapshots_dir().join(format!("{}.db", snapshot.id));
+
+        let pre_rollback = self
+            .create_snapshot(&format!("Before rollback to '{}'", snapshot.label))
+            .await
+            .context("Failed to create pre-rollback snapshot")?;
+
+        // Stage the copy first so the live file is only ever swapped atomically
+        let staged_path = self.db_path.with_extension("rollback");
+        tokio::fs::copy(&snapshot_path, &staged_path)
+            .await
+            .context("Failed to stage snapshot")?;
+
+        self.pool.close().await;
+        self.read_pool.close().await;
+
+        for suffix in ["-wal", "-shm"] {
+            let mut sidecar = self.db_path.clone().into_os_string();
+            sidecar.push(suffix);
+            let _ = tokio::fs::remove_file(PathBuf::from(sidecar)).await;
+        }
+        let swapped = tokio::fs::rename(&staged_path, &self.db_path)
+            .await
+            .context("Failed to replace database file");
+
+        // Reopen in any case so the app keeps working on whichever file is in place
+        self.pool = Self::connect(&self.db_path).await?;
+        self.read_pool = Self::connect_read_only(&self.db_path).await?;
+        swapped?;
+        self.migrate().await?;
+
+        Ok(pre_rollback)
+    }
+
+    async fn seed_default_categories(&self) -> Result<()> {
+        // Check if categories already exist
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM categories")
+            .fetch_one(&self.pool)
+            .await?;
+
+        if count > 0 {
+            return Ok(()); // Categories already exist
+        }
+
+        let device_id = self.crypto.get_device_id();
+        
+        // Default categories with appealing colors
+        let default_categories = vec![
+            ("Sozial", "#10B981", "#D1FAE5", "#065F46", 1), // Green theme
+            ("Fachlich", "#3B82F6", "#DBEAFE", "#1E3A8A", 2), // Blue theme  
+            ("Verhalten", "#F59E0B", "#FEF3C7", "#92400E", 3), // Amber theme
+            ("Förderung", "#8B5CF6", "#EDE9FE", "#5B21B6", 4), // Purple theme
+            ("Sonstiges", "#6B7280", "#F3F4F6", "#374151", 5), // Gray theme
+        ];
+
+        let category_count = default_categories.len();
+
+        for (name, color, bg_color, text_color, sort_order) in default_categories {
+            sqlx::query(
+                r#"
+                INSERT INTO categories (name, color, background_color, text_color, is_active, sort_order, source_device_id)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#
+            )
+            .bind(name)
+            .bind(color)
+            .bind(bg_color) 
+            .bind(text_color)
+            .bind(true)
+            .bind(sort_order)
+            .bind(&device_id)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        println!("Created {} default categories", category_count);
+        Ok(())
+    }
+
+    // Category CRUD operations
+    pub async fn get_categories(&self) -> Result<Vec<crate::Category>> {
+        let categories = sqlx::query_as::<_, crate::Category>(
+            "SELECT * FROM categories WHERE is_active = 1 ORDER BY sort_order ASC"
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch categories")?;
+        Ok(categories)
+    }
+
+    pub async fn create_category(&self, name: String, color: String, background_color: String, text_color: String, icon: Option<String>) -> Result<crate::Category> {
+        let device_id = self.crypto.get_device_id();
+        
+        // Get next sort order
+        let max_order: i32 = sqlx::query_scalar("SELECT COALESCE(MAX(sort_order), 0) FROM categories")
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or(0);
+
+        let category = sqlx::query_as::<_, crate::Category>(
+            r#"
+            INSERT INTO categories (name, color, background_color, text_color, is_active, sort_order, source_device_id, icon)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING *
+            "#
+        )
+        .bind(&name)
+        .bind(&color)
+        .bind(&background_color)
+        .bind(&text_color)
+        .bind(true)
+        .bind(max_order + 1)
+        .bind(&device_id)
+        .bind(&icon)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create category")?;
+        Ok(category)
+    }
+
+    pub async fn update_category(&self, id: i64, name: String, color: String, background_color: String, text_color: String, icon: Option<String>) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE categories 
+            SET name = ?, color = ?, background_color = ?, text_color = ?, icon = ?, updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?
+            "#
+        )
+        .bind(&name)
+        .bind(&color)
+        .bind(&background_color)
+        .bind(&text_color)
+        .bind(&icon)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_category(&self, id: i64, force_delete: bool) -> Result<()> {
+        if !force_delete {
+            // Check if category is used in observations
+            let usage_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM observations WHERE category = (SELECT name FROM categories WHERE id = ?)")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+
+            if usage_count > 0 {
+                // Soft delete: mark as inactive
+                sqlx::query("UPDATE categories SET is_active = 0, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                    .bind(id)
+                    .execute(&self.pool)
+                    .await?;
+                return Ok(());
+            }
+        }
+        
+        // Hard delete: remove completely
+        sqlx::query("DELETE FROM categories WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Moves observations, templates and quick phrases of the source
+    /// category to the target and deactivates the source. The source keeps
+    /// pointing at the target, so observations still arriving from devices
+    /// that have not synced the merge are counted under the target, as are
+    /// co-signed and read-only observations, which keep their category.
+    pub async fn merge_categories(&self, source_id: i64, target_id: i64) -> Result<CategoryMerge> {
+        if source_id == target_id {
+            return Err(anyhow::anyhow!("A category cannot be merged into itself"));
+        }
+        let category = |id: i64| {
+            sqlx::query_as::<_, crate::Category>("SELECT * FROM categories WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&self.pool)
+        };
+        let source = category(source_id).await?.context("Source category not found")?;
+        let target = category(target_id).await?.context("Target category not found")?;
+        if target.merged_into.is_some() {
+            return Err(anyhow::anyhow!("{} was merged into another category itself", target.name));
+        }
+
+        let mut observation_ids = Vec::new();
+        let mut skipped_observations = Vec::new();
+        for observation_id in sqlx::query_scalar::<_, i64>("SELECT id FROM observations WHERE category = ?")
+            .bind(&source.name)
+            .fetch_all(&self.pool)
+            .await?
+        {
+            match self.ensure_observation_editable(observation_id).await {
+                Ok(()) => observation_ids.push(observation_id),
+                Err(_) => skipped_observations.push(observation_id),
+            }
+        }
+
+        let device_id = self.crypto.get_device_id();
+        let mut tx = self.pool.begin().await?;
+        // Each one is an update of its own, so peers reassign it as well
+        for observation_id in &observation_ids {
+            let logical_clock = Self::next_logical_clock_on(&mut tx, &device_id).await?;
+            sqlx::query(
+                "UPDATE observations SET category = ?, logical_clock = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            )
+            .bind(&target.name)
+            .bind(logical_clock)
+            .bind(observation_id)
+            .execute(&mut *tx)
+            .await?;
+            Self::record_change_on(&mut tx, &device_id, "observation", *observation_id, "update", None).await?;
+        }
+        let templates = sqlx::query("UPDATE observation_templates SET category = ?, updated_at = CURRENT_TIMESTAMP WHERE category = ?")
+            .bind(&target.name)
+            .bind(&source.name)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        let quick_phrases = sqlx::query("UPDATE quick_phrases SET category = ? WHERE category = ?")
+            .bind(&target.name)
+            .bind(&source.name)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        // Categories merged into the source earlier now point at the target
+        sqlx::query(
+            "UPDATE categories SET is_active = 0, merged_into = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? OR merged_into = ?",
+        )
+        .bind(target_id)
+        .bind(source_id)
+        .bind(source_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await.context("Failed to merge categories")?;
+
+        Ok(CategoryMerge {
+            source: source.name,
+            target: target.name,
+            observations: observation_ids.len() as u64,
+            skipped_observations,
+            templates,
+            quick_phrases,
+        })
+    }
+
+    /// Names of merged categories with the name of the category they were merged into.
+    pub async fn get_category_merges(&self) -> Result<HashMap<String, String>> {
+        let merges = sqlx::query_as::<_, (String, String)>(
+            "SELECT s.name, t.name FROM categories s JOIN categories t ON t.id = s.merged_into",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch category merges")?;
+        Ok(merges.into_iter().collect())
+    }
+
+    // Subject operations
+    async fn seed_default_subjects(&self) -> Result<()> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subjects")
+            .fetch_one(&self.pool)
+            .await?;
+        if count > 0 {
+            return Ok(());
+        }
+
+        let default_subjects = ["Deutsch", "Mathematik", "Sachunterricht", "Englisch", "Sport", "Kunst", "Musik"];
+        for (sort_order, name) in default_subjects.iter().enumerate() {
+            sqlx::query("INSERT INTO subjects (name, sort_order) VALUES (?, ?)")
+                .bind(name)
+                .bind(sort_order as i64 + 1)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_subjects(&self) -> Result<Vec<crate::Subject>> {
+        let subjects = sqlx::query_as::<_, crate::Subject>(
+            "SELECT * FROM subjects WHERE is_active = 1 ORDER BY sort_order ASC, name ASC",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch subjects")?;
+        Ok(subjects)
+    }
+
+    /// A subject removed earlier comes back instead of failing on the name.
+    pub async fn create_subject(&self, name: &str) -> Result<crate::Subject> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("Subject name is required"));
+        }
+        let subject = sqlx::query_as::<_, crate::Subject>(
+            r#"
+            INSERT INTO subjects (name, sort_order)
+            VALUES (?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM subjects))
+            ON CONFLICT(name) DO UPDATE SET is_active = 1
+            RETURNING *
+            "#,
+        )
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create subject")?;
+        Ok(subject)
+    }
+
+    /// Subjects still used by observations are only hidden, like categories.
+    pub async fn delete_subject(&self, id: i64) -> Result<()> {
+        let usage_count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM observations WHERE subject = (SELECT name FROM subjects WHERE id = ?)",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let sql = if usage_count > 0 {
+            "UPDATE subjects SET is_active = 0 WHERE id = ?"
+        } else {
+            "DELETE FROM subjects WHERE id = ?"
+        };
+        let result = sqlx::query(sql).bind(id).execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Subject not found"));
+        }
+        Ok(())
+    }
+
+    async fn ensure_subject_exists(&self, subject: &str) -> Result<()> {
+        let exists: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subjects WHERE name = ? AND is_active = 1")
+            .bind(subject)
+            .fetch_one(&self.pool)
+            .await?;
+        if exists == 0 {
+            return Err(anyhow::anyhow!("Unknown subject: {}", subject));
+        }
+        Ok(())
+    }
+
+    // Metric operations
+    /// Replaces the measured values of an observation. They stay on this
+    /// device, like rubric scores.
+    pub async fn set_observation_metrics(&self, observation_id: i64, metrics: &[ObservationMetric]) -> Result<()> {
+        self.get_observation(observation_id)
+            .await?
+            .context("Observation not found")?;
+        self.ensure_observation_editable(observation_id).await?;
+        validate_metrics(metrics)?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM observation_metrics WHERE observation_id = ?")
+            .bind(observation_id)
+            .execute(&mut *tx)
+            .await?;
+        for metric in metrics {
+            sqlx::query("INSERT INTO observation_metrics (observation_id, metric, value, unit) VALUES (?, ?, ?, ?)")
+                .bind(observation_id)
+                .bind(metric.metric.trim())
+                .bind(metric.value)
+                .bind(metric.unit.as_deref().map(str::trim).filter(|u| !u.is_empty()))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_observation_metrics(&self, observation_id: i64) -> Result<Vec<ObservationMetric>> {
+        let metrics = sqlx::query_as::<_, ObservationMetric>(
+            "SELECT metric, value, unit FROM observation_metrics WHERE observation_id = ? ORDER BY metric",
+        )
+        .bind(observation_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch observation metrics")?;
+        Ok(metrics)
+    }
+
+    /// Metric names in use, for a student or overall, to offer when recording.
+    pub async fn get_metric_names(&self, student_id: Option<i64>) -> Result<Vec<String>> {
+        let names = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT m.metric FROM observation_metrics m
+            JOIN observations o ON o.id = m.observation_id
+            WHERE (? IS NULL OR o.student_id = ?)
+            ORDER BY m.metric
+            "#,
+        )
+        .bind(student_id)
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch metric names")?;
+        Ok(names)
+    }
+
+    /// The values of one metric for a student, oldest first, from the
+    /// observations `viewer_id` may see.
+    pub async fn get_metric_series(&self, student_id: i64, metric: &str, viewer_id: i64) -> Result<Vec<MetricPoint>> {
+        let points = sqlx::query_as::<_, MetricPoint>(
+            r#"
+            SELECT m.observation_id, m.metric, m.value, m.unit, o.created_at AS observed_at
+            FROM observation_metrics m
+            JOIN observations o ON o.id = m.observation_id
+            WHERE o.student_id = ? AND m.metric = ? AND (o.visibility = 'team' OR o.author_id = ?)
+            ORDER BY o.created_at ASC, o.id ASC
+            "#,
+        )
+        .bind(student_id)
+        .bind(metric.trim())
+        .bind(viewer_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch metric series")?;
+        Ok(points)
+    }
+
+    /// Every measured value of a student, for the GDPR export.
+    pub async fn get_student_metrics(&self, student_id: i64) -> Result<Vec<MetricPoint>> {
+        let points = sqlx::query_as::<_, MetricPoint>(
+            r#"
+            SELECT m.observation_id, m.metric, m.value, m.unit, o.created_at AS observed_at
+            FROM observation_metrics m
+            JOIN observations o ON o.id = m.observation_id
+            WHERE o.student_id = ?
+            ORDER BY m.metric, o.created_at
+            "#,
+        )
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch student metrics")?;
+        Ok(points)
+    }
+
+    // Competency operations
+    async fn seed_default_competencies(&self) -> Result<()> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM competencies")
+            .fetch_one(&self.pool)
+            .await?;
+        if count > 0 {
+            return Ok(());
+        }
+
+        // Areas of the KMK Bildungsstandards for the Primarstufe
+        let default_competencies = [
+            ("Deutsch", "Sprechen und Zuhören"),
+            ("Deutsch", "Schreiben"),
+            ("Deutsch", "Lesen – mit Texten und Medien umgehen"),
+            ("Deutsch", "Sprache und Sprachgebrauch untersuchen"),
+            ("Mathematik", "Problemlösen"),
+            ("Mathematik", "Kommunizieren"),
+            ("Mathematik", "Argumentieren"),
+            ("Mathematik", "Modellieren"),
+            ("Mathematik", "Darstellen"),
+        ];
+        for (sort_order, (area, name)) in default_competencies.iter().enumerate() {
+            sqlx::query("INSERT INTO competencies (area, name, sort_order) VALUES (?, ?, ?)")
+                .bind(area)
+                .bind(name)
+                .bind(sort_order as i64 + 1)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn get_competencies(&self) -> Result<Vec<crate::Competency>> {
+        let competencies = sqlx::query_as::<_, crate::Competency>(
+            "SELECT * FROM competencies WHERE is_active = 1 ORDER BY sort_order ASC, area ASC, name ASC",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch competencies")?;
+        Ok(competencies)
+    }
+
+    /// Like subjects, a competency removed earlier comes back.
+    pub async fn create_competency(
+        &self,
+        area: &str,
+        name: &str,
+        description: Option<&str>,
+    ) -> Result<crate::Competency> {
+        let (area, name) = (area.trim(), name.trim());
+        if area.is_empty() || name.is_empty() {
+            return Err(anyhow::anyhow!("Competency area and name are required"));
+        }
+        let competency = sqlx::query_as::<_, crate::Competency>(
+            r#"
+            INSERT INTO competencies (area, name, description, sort_order)
+            VALUES (?, ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM competencies))
+            ON CONFLICT(area, name) DO UPDATE SET is_active = 1, description = COALESCE(excluded.description, description)
+            RETURNING *
+            "#,
+        )
+        .bind(area)
+        .bind(name)
+        .bind(description)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create competency")?;
+        Ok(competency)
+    }
+
+    /// Competencies linked to observations are only hidden.
+    pub async fn delete_competency(&self, id: i64) -> Result<()> {
+        let usage_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM observation_competencies WHERE competency_id = ?")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let sql = if usage_count > 0 {
+            "UPDATE competencies SET is_active = 0 WHERE id = ?"
+        } else {
+            "DELETE FROM competencies WHERE id = ?"
+        };
+        let result = sqlx::query(sql).bind(id).execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Competency not found"));
+        }
+        Ok(())
+    }
+
+    pub async fn check_competencies(&self, competency_ids: &[i64]) -> Result<()> {
+        for id in competency_ids {
+            let exists: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM competencies WHERE id = ? AND is_active = 1")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+            if exists == 0 {
+                return Err(anyhow::anyhow!("Unknown competency {}", id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the competencies an observation documents. Like rubric scores,
+    /// the links stay on this device.
+    pub async fn set_observation_competencies(&self, observation_id: i64, competency_ids: &[i64]) -> Result<()> {
+        self.get_observation(observation_id)
+            .await?
+            .context("Observation not found")?;
+        self.ensure_observation_editable(observation_id).await?;
+        self.check_competencies(competency_ids).await?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM observation_competencies WHERE observation_id = ?")
+            .bind(observation_id)
+            .execute(&mut *tx)
+            .await?;
+        for competency_id in competency_ids {
+            sqlx::query("INSERT OR IGNORE INTO observation_competencies (observation_id, competency_id) VALUES (?, ?)")
+                .bind(observation_id)
+                .bind(competency_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_observation_competencies(&self, observation_id: i64) -> Result<Vec<crate::Competency>> {
+        let competencies = sqlx::query_as::<_, crate::Competency>(
+            r#"
+            SELECT c.* FROM competencies c
+            JOIN observation_competencies oc ON oc.competency_id = c.id
+            WHERE oc.observation_id = ?
+            ORDER BY c.sort_order, c.name
+            "#,
+        )
+        .bind(observation_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch observation competencies")?;
+        Ok(competencies)
+    }
+
+    /// Every competency link of a student's observations, hidden competencies included.
+    pub async fn get_student_competency_links(&self, student_id: i64) -> Result<Vec<CompetencyLink>> {
+        let links = sqlx::query_as::<_, CompetencyLink>(
+            r#"
+            SELECT oc.observation_id, c.id AS competency_id, c.area, c.name
+            FROM observation_competencies oc
+            JOIN observations o ON o.id = oc.observation_id
+            JOIN competencies c ON c.id = oc.competency_id
+            WHERE o.student_id = ?
+            ORDER BY oc.observation_id, c.sort_order
+            "#,
+        )
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch competency links")?;
+        Ok(links)
+    }
+
+    /// Which competencies the observations `viewer_id` may see document for
+    /// a student, gaps included. Hidden competencies appear only where used.
+    pub async fn get_competency_coverage(&self, student_id: i64, viewer_id: i64) -> Result<Vec<CompetencyCoverage>> {
+        let coverage = sqlx::query_as::<_, CompetencyCoverage>(
+            r#"
+            SELECT c.id AS competency_id, c.area, c.name,
+                   COUNT(o.id) AS observations, MAX(o.created_at) AS last_observed_at
+            FROM competencies c
+            LEFT JOIN observation_competencies oc ON oc.competency_id = c.id
+            LEFT JOIN observations o ON o.id = oc.observation_id
+                AND o.student_id = ? AND (o.visibility = 'team' OR o.author_id = ?)
+            GROUP BY c.id
+            HAVING c.is_active = 1 OR COUNT(o.id) > 0
+            ORDER BY c.sort_order, c.area, c.name
+            "#,
+        )
+        .bind(student_id)
+        .bind(viewer_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to compute competency coverage")?;
+        Ok(coverage)
+    }
+
+    // Rubric operations
+    pub async fn create_rubric(&self, input: &RubricInput) -> Result<RubricDetails> {
+        input.validate()?;
+        let mut tx = self.pool.begin().await?;
+        let rubric_id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO rubrics (name, description) VALUES (?, ?) RETURNING id",
+        )
+        .bind(input.name.trim())
+        .bind(&input.description)
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to create rubric; is the name already taken?")?;
+        Self::save_rubric_criteria_on(&mut tx, rubric_id, &input.criteria).await?;
+        tx.commit().await?;
+
+        self.get_rubric(rubric_id).await?.context("Rubric not found")
+    }
+
+    /// Criteria left out are removed, as long as nothing was scored with them.
+    pub async fn update_rubric(&self, rubric_id: i64, input: &RubricInput) -> Result<RubricDetails> {
+        input.validate()?;
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            "UPDATE rubrics SET name = ?, description = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(input.name.trim())
+        .bind(&input.description)
+        .bind(rubric_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to update rubric; is the name already taken?")?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Rubric not found"));
+        }
+
+        let kept: Vec<i64> = input.criteria.iter().filter_map(|c| c.id).collect();
+        let existing = sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM rubric_criteria WHERE rubric_id = ?")
+            .bind(rubric_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        for id in &kept {
+            if !existing.iter().any(|(existing_id, _)| existing_id == id) {
+                return Err(anyhow::anyhow!("Criterion {} does not belong to this rubric", id));
+            }
+        }
+        for (criterion_id, name) in existing.iter().filter(|(id, _)| !kept.contains(id)) {
+            let scored = sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM observation_rubric_scores WHERE criterion_id = ?",
+            )
+            .bind(criterion_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if scored > 0 {
+                return Err(anyhow::anyhow!(
+                    "Criterion {} was used in {} observations and cannot be removed",
+                    name,
+                    scored
+                ));
+            }
+            sqlx::query("DELETE FROM rubric_levels WHERE criterion_id = ?")
+                .bind(criterion_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM rubric_criteria WHERE id = ?")
+                .bind(criterion_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        Self::save_rubric_criteria_on(&mut tx, rubric_id, &input.criteria).await?;
+        tx.commit().await?;
+
+        self.get_rubric(rubric_id).await?.context("Rubric not found")
+    }
+
+    async fn save_rubric_criteria_on(
+        conn: &mut SqliteConnection,
+        rubric_id: i64,
+        criteria: &[RubricCriterionInput],
+    ) -> Result<()> {
+        for (sort_order, criterion) in criteria.iter().enumerate() {
+            let criterion_id = match criterion.id {
+                Some(id) => {
+                    sqlx::query("UPDATE rubric_criteria SET name = ?, sort_order = ? WHERE id = ?")
+                        .bind(criterion.name.trim())
+                        .bind(sort_order as i64)
+                        .bind(id)
+                        .execute(&mut *conn)
+                        .await?;
+                    // Scores above the new highest level would lose their meaning
+                    let above = sqlx::query_scalar::<_, i64>(
+                        "SELECT COUNT(*) FROM observation_rubric_scores WHERE criterion_id = ? AND level > ?",
+                    )
+                    .bind(id)
+                    .bind(criterion.levels.len() as i64)
+                    .fetch_one(&mut *conn)
+                    .await?;
+                    if above > 0 {
+                        return Err(anyhow::anyhow!(
+                            "Criterion {} has scores above level {}",
+                            criterion.name.trim(),
+                            criterion.levels.len()
+                        ));
+                    }
+                    sqlx::query("DELETE FROM rubric_levels WHERE criterion_id = ?")
+                        .bind(id)
+                        .execute(&mut *conn)
+                        .await?;
+                    id
+                }
+                None => sqlx::query_scalar::<_, i64>(
+                    "INSERT INTO rubric_criteria (rubric_id, name, sort_order) VALUES (?, ?, ?) RETURNING id",
+                )
+                .bind(rubric_id)
+                .bind(criterion.name.trim())
+                .bind(sort_order as i64)
+                .fetch_one(&mut *conn)
+                .await?,
+            };
+
+            for (index, description) in criterion.levels.iter().enumerate() {
+                sqlx::query("INSERT INTO rubric_levels (criterion_id, level, description) VALUES (?, ?, ?)")
+                    .bind(criterion_id)
+                    .bind(index as i64 + 1)
+                    .bind(description.trim())
+                    .execute(&mut *conn)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get_rubric(&self, rubric_id: i64) -> Result<Option<RubricDetails>> {
+        let Some(rubric) = sqlx::query_as::<_, Rubric>("SELECT * FROM rubrics WHERE id = ?")
+            .bind(rubric_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to fetch rubric")?
+        else {
+            return Ok(None);
+        };
+
+        let criteria_rows = sqlx::query_as::<_, (i64, String)>(
+            "SELECT id, name FROM rubric_criteria WHERE rubric_id = ? ORDER BY sort_order, id",
+        )
+        .bind(rubric_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut criteria = Vec::with_capacity(criteria_rows.len());
+        for (id, name) in criteria_rows {
+            let levels = sqlx::query_as::<_, RubricLevel>(
+                "SELECT level, description FROM rubric_levels WHERE criterion_id = ? ORDER BY level",
+            )
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?;
+            criteria.push(RubricCriterion { id, name, levels });
+        }
+
+        Ok(Some(RubricDetails { rubric, criteria }))
+    }
+
+    pub async fn get_rubrics(&self) -> Result<Vec<RubricDetails>> {
+        let ids = sqlx::query_scalar::<_, i64>("SELECT id FROM rubrics WHERE is_active = 1 ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch rubrics")?;
+        let mut rubrics = Vec::with_capacity(ids.len());
+        for id in ids {
+            rubrics.extend(self.get_rubric(id).await?);
+        }
+        Ok(rubrics)
+    }
+
+    /// Rubrics with scores are only hidden, so the scores keep their meaning.
+    pub async fn delete_rubric(&self, rubric_id: i64) -> Result<()> {
+        let scored = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM observation_rubric_scores sc
+            JOIN rubric_criteria c ON c.id = sc.criterion_id
+            WHERE c.rubric_id = ?
+            "#,
+        )
+        .bind(rubric_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let mut tx = self.pool.begin().await?;
+        let result = if scored > 0 {
+            sqlx::query("UPDATE rubrics SET is_active = 0, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+                .bind(rubric_id)
+                .execute(&mut *tx)
+                .await?
+        } else {
+            sqlx::query(
+                "DELETE FROM rubric_levels WHERE criterion_id IN (SELECT id FROM rubric_criteria WHERE rubric_id = ?)",
+            )
+            .bind(rubric_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("DELETE FROM rubric_criteria WHERE rubric_id = ?")
+                .bind(rubric_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM rubrics WHERE id = ?")
+                .bind(rubric_id)
+                .execute(&mut *tx)
+                .await?
+        };
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Rubric not found"));
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Checks that every score names a known criterion, once, with one of its levels.
+    pub async fn check_rubric_scores(&self, scores: &[RubricScore]) -> Result<()> {
+        let mut scored = std::collections::HashSet::new();
+        for score in scores {
+            if !scored.insert(score.criterion_id) {
+                return Err(anyhow::anyhow!("Criterion {} is scored more than once", score.criterion_id));
+            }
+            let highest = sqlx::query_scalar::<_, Option<i64>>(
+                "SELECT MAX(level) FROM rubric_levels WHERE criterion_id = ?",
+            )
+            .bind(score.criterion_id)
+            .fetch_one(&self.pool)
+            .await?
+            .with_context(|| format!("Unknown rubric criterion {}", score.criterion_id))?;
+            if score.level < 1 || score.level > highest {
+                return Err(anyhow::anyhow!(
+                    "Level {} is out of range for criterion {} (1 to {})",
+                    score.level,
+                    score.criterion_id,
+                    highest
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the rubric scores of an observation; an empty list removes them.
+    /// Scores stay on this device, changesets do not carry them.
+    pub async fn set_observation_rubric_scores(&self, observation_id: i64, scores: &[RubricScore]) -> Result<()> {
+        self.get_observation(observation_id)
+            .await?
+            .context("Observation not found")?;
+        self.ensure_observation_editable(observation_id).await?;
+        self.check_rubric_scores(scores).await?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM observation_rubric_scores WHERE observation_id = ?")
+            .bind(observation_id)
+            .execute(&mut *tx)
+            .await?;
+        for score in scores {
+            sqlx::query("INSERT INTO observation_rubric_scores (observation_id, criterion_id, level) VALUES (?, ?, ?)")
+                .bind(observation_id)
+                .bind(score.criterion_id)
+                .bind(score.level)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_observation_rubric_scores(&self, observation_id: i64) -> Result<Vec<RubricScore>> {
+        let scores = sqlx::query_as::<_, RubricScore>(
+            "SELECT criterion_id, level FROM observation_rubric_scores WHERE observation_id = ? ORDER BY criterion_id",
+        )
+        .bind(observation_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch rubric scores")?;
+        Ok(scores)
+    }
+
+    /// All scores of a student's observations, named, for the GDPR export.
+    pub async fn get_student_rubric_scores(&self, student_id: i64) -> Result<Vec<NamedRubricScore>> {
+        let scores = sqlx::query_as::<_, NamedRubricScore>(
+            r#"
+            SELECT sc.observation_id, r.name AS rubric, c.name AS criterion, sc.level,
+                   COALESCE(l.description, '') AS level_description
+            FROM observation_rubric_scores sc
+            JOIN observations o ON o.id = sc.observation_id
+            JOIN rubric_criteria c ON c.id = sc.criterion_id
+            JOIN rubrics r ON r.id = c.rubric_id
+            LEFT JOIN rubric_levels l ON l.criterion_id = sc.criterion_id AND l.level = sc.level
+            WHERE o.student_id = ?
+            ORDER BY sc.observation_id, c.sort_order
+            "#,
+        )
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch rubric scores")?;
+        Ok(scores)
+    }
+
+    /// Scores per criterion over the observations `viewer_id` may see.
+    pub async fn get_rubric_aggregates(
+        &self,
+        filter: &RubricAggregateFilter,
+        viewer_id: i64,
+    ) -> Result<Vec<RubricAggregate>> {
+        let aggregates = sqlx::query_as::<_, RubricAggregate>(
+            r#"
+            SELECT r.id AS rubric_id, r.name AS rubric_name, c.id AS criterion_id, c.name AS criterion_name,
+                   COUNT(*) AS scores, AVG(sc.level) AS average_level,
+                   MIN(sc.level) AS lowest_level, MAX(sc.level) AS highest_level
+            FROM observation_rubric_scores sc
+            JOIN rubric_criteria c ON c.id = sc.criterion_id
+            JOIN rubrics r ON r.id = c.rubric_id
+            JOIN observations o ON o.id = sc.observation_id
+            JOIN students s ON s.id = o.student_id
+            WHERE (o.visibility = 'team' OR o.author_id = ?)
+              AND (? IS NULL OR o.student_id = ?)
+              AND (? IS NULL OR s.class_id = ?)
+              AND (? IS NULL OR o.session_id = ?)
+              AND (? IS NULL OR o.subject = ?)
+              AND (? IS NULL OR datetime(o.created_at) >= datetime(?))
+              AND (? IS NULL OR datetime(o.created_at) < datetime(?))
+            GROUP BY c.id
+            ORDER BY r.name, c.sort_order, c.id
+            "#,
+        )
+        .bind(viewer_id)
+        .bind(filter.student_id)
+        .bind(filter.student_id)
+        .bind(filter.class_id)
+        .bind(filter.class_id)
+        .bind(filter.session_id)
+        .bind(filter.session_id)
+        .bind(&filter.subject)
+        .bind(&filter.subject)
+        .bind(filter.from)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.to)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to aggregate rubric scores")?;
+        Ok(aggregates)
+    }
+
+    /// Including inactive ones, which a category pack should carry as well.
+    pub async fn get_all_categories(&self) -> Result<Vec<crate::Category>> {
+        let categories = sqlx::query_as::<_, crate::Category>("SELECT * FROM categories ORDER BY sort_order ASC")
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to fetch categories")?;
+        Ok(categories)
+    }
+
+    // Observation templates
+    pub async fn get_observation_templates(&self) -> Result<Vec<crate::ObservationTemplate>> {
+        let templates = sqlx::query_as::<_, crate::ObservationTemplate>(
+            "SELECT * FROM observation_templates ORDER BY sort_order ASC, name ASC",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch observation templates")?;
+        Ok(templates)
+    }
+
+    pub async fn create_observation_template(&self, name: String, category: String, text: String) -> Result<crate::ObservationTemplate> {
+        if name.trim().is_empty() || text.trim().is_empty() {
+            return Err(anyhow::anyhow!("Template name and text are required"));
+        }
+        let template = sqlx::query_as::<_, crate::ObservationTemplate>(
+            r#"
+            INSERT INTO observation_templates (name, category, text, sort_order)
+            VALUES (?, ?, ?, (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM observation_templates))
+            RETURNING *
+            "#,
+        )
+        .bind(name.trim())
+        .bind(&category)
+        .bind(&text)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create observation template")?;
+        Ok(template)
+    }
+
+    /// Replaces the names shown for other locales; `translations` maps locale to name.
+    pub async fn set_category_translations(&self, id: i64, translations: &BTreeMap<String, String>) -> Result<()> {
+        let updated = sqlx::query("UPDATE categories SET translations = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(serde_json::to_string(translations)?)
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if updated == 0 {
+            return Err(anyhow::anyhow!("Category not found"));
+        }
+        Ok(())
+    }
+
+    pub async fn set_template_translations(&self, id: i64, translations: &BTreeMap<String, String>) -> Result<()> {
+        let updated = sqlx::query("UPDATE observation_templates SET translations = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(serde_json::to_string(translations)?)
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if updated == 0 {
+            return Err(anyhow::anyhow!("Template not found"));
+        }
+        Ok(())
+    }
+
+    pub async fn delete_observation_template(&self, id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM observation_templates WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Quick phrases
+    /// Most used first, so the phrases needed during a lesson are at hand.
+    pub async fn get_quick_phrases(&self, category: Option<&str>) -> Result<Vec<crate::QuickPhrase>> {
+        let phrases = sqlx::query_as::<_, crate::QuickPhrase>(
+            r#"
+            SELECT * FROM quick_phrases
+            WHERE ? IS NULL OR category = ?
+            ORDER BY usage_count DESC, last_used_at DESC, text ASC
+            "#,
+        )
+        .bind(category)
+        .bind(category)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch quick phrases")?;
+        Ok(phrases)
+    }
+
+    /// Adding a phrase that exists already returns the existing one.
+    pub async fn create_quick_phrase(&self, category: &str, text: &str) -> Result<crate::QuickPhrase> {
+        let (category, text) = (category.trim(), text.trim());
+        if category.is_empty() || text.is_empty() {
+            return Err(anyhow::anyhow!("Quick phrase category and text are required"));
+        }
+        let phrase = sqlx::query_as::<_, crate::QuickPhrase>(
+            r#"
+            INSERT INTO quick_phrases (category, text) VALUES (?, ?)
+            ON CONFLICT(category, text) DO UPDATE SET text = excluded.text
+            RETURNING *
+            "#,
+        )
+        .bind(category)
+        .bind(text)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to create quick phrase")?;
+        Ok(phrase)
+    }
+
+    pub async fn update_quick_phrase(&self, id: i64, text: &str) -> Result<crate::QuickPhrase> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(anyhow::anyhow!("Quick phrase text is required"));
+        }
+        sqlx::query_as::<_, crate::QuickPhrase>("UPDATE quick_phrases SET text = ? WHERE id = ? RETURNING *")
+            .bind(text)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to update quick phrase; does the category have it already?")?
+            .context("Quick phrase not found")
+    }
+
+    pub async fn delete_quick_phrase(&self, id: i64) -> Result<()> {
+        let result = sqlx::query("DELETE FROM quick_phrases WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Quick phrase not found"));
+        }
+        Ok(())
+    }
+
+    pub async fn record_quick_phrase_use(&self, id: i64) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE quick_phrases SET usage_count = usage_count + 1, last_used_at = CURRENT_TIMESTAMP WHERE id = ?",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("Quick phrase not found"));
+        }
+        Ok(())
+    }
+
+    /// Merges a category pack by name: existing categories and templates take
+    /// over the pack's look and text, missing ones are created. Nothing is
+    /// deleted, so observations keep their categories.
+    pub async fn merge_category_pack(&self, pack: &crate::category_pack::CategoryPack) -> Result<crate::category_pack::CategoryPackImport> {
+        let device_id = self.crypto.get_device_id();
+        let mut report = crate::category_pack::CategoryPackImport::default();
+        let mut tx = self.pool.begin().await?;
+
+        for category in &pack.categories {
+            let updated = sqlx::query(
+                r#"
+                UPDATE categories
+                SET color = ?, background_color = ?, text_color = ?, icon = ?, sort_order = ?,
+                    is_active = ?, translations = ?, updated_at = CURRENT_TIMESTAMP
+                WHERE name = ?
+                "#,
+            )
+            .bind(&category.color)
+            .bind(&category.background_color)
+            .bind(&category.text_color)
+            .bind(&category.icon)
+            .bind(category.sort_order)
+            .bind(category.is_active)
+            .bind(serde_json::to_string(&category.translations)?)
+            .bind(&category.name)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+            if updated > 0 {
+                report.categories_updated += 1;
+            } else {
+                sqlx::query(
+                    r#"
+                    INSERT INTO categories (name, color, background_color, text_color, icon, sort_order, is_active, source_device_id, translations)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(&category.name)
+                .bind(&category.color)
+                .bind(&category.background_color)
+                .bind(&category.text_color)
+                .bind(&category.icon)
+                .bind(category.sort_order)
+                .bind(category.is_active)
+                .bind(&device_id)
+                .bind(serde_json::to_string(&category.translations)?)
+                .execute(&mut *tx)
+                .await?;
+                report.categories_created += 1;
+            }
+        }
+
+        for template in &pack.templates {
+            let updated = sqlx::query(
+                "UPDATE observation_templates SET category = ?, text = ?, sort_order = ?, translations = ?, updated_at = CURRENT_TIMESTAMP WHERE name = ?",
+            )
+            .bind(&template.category)
+            .bind(&template.text)
+            .bind(template.sort_order)
+            .bind(serde_json::to_string(&template.translations)?)
+            .bind(&template.name)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+            if updated > 0 {
+                report.templates_updated += 1;
+            } else {
+                sqlx::query("INSERT INTO observation_templates (name, category, text, sort_order, translations) VALUES (?, ?, ?, ?, ?)")
+                    .bind(&template.name)
+                    .bind(&template.category)
+                    .bind(&template.text)
+                    .bind(template.sort_order)
+                    .bind(serde_json::to_string(&template.translations)?)
+                    .execute(&mut *tx)
+                    .await?;
+                report.templates_created += 1;
+            }
+        }
+
+        for phrase in &pack.quick_phrases {
+            let inserted = sqlx::query("INSERT OR IGNORE INTO quick_phrases (category, text) VALUES (?, ?)")
+                .bind(phrase.category.trim())
+                .bind(phrase.text.trim())
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            report.quick_phrases_created += inserted as usize;
+        }
+
+        tx.commit().await?;
+        Ok(report)
+    }
+
+    // Report artifacts (generated summaries kept for later reference)
+    pub async fn store_report_artifact(
+        &self,
+        class_id: i64,
+        report_type: &str,
+        period_start: &str,
+        period_end: &str,
+        format: &str,
+        content: &[u8],
+    ) -> Result<ReportArtifact> {
+        let device_id = self.crypto.get_device_id();
+
+        let mut hasher = Sha256::new();
+        hasher.update(content);
+        let content_hash = format!("{:x}", hasher.finalize());
+
+        let artifact = sqlx::query_as::<_, ReportArtifact>(
+            r#"
+            INSERT INTO report_artifacts (class_id, report_type, period_start, period_end, format, content, content_hash, source_device_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            RETURNING id, class_id, report_type, period_start, period_end, format, content_hash, created_at, source_device_id
+            "#,
+        )
+        .bind(class_id)
+        .bind(report_type)
+        .bind(period_start)
+        .bind(period_end)
+        .bind(format)
+        .bind(content)
+        .bind(&content_hash)
+        .bind(&device_id)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to store report artifact")?;
+
+        Ok(artifact)
+    }
+
+    pub async fn get_report_artifacts(&self, class_id: Option<i64>) -> Result<Vec<ReportArtifact>> {
+        let artifacts = sqlx::query_as::<_, ReportArtifact>(
+            r#"
+            SELECT id, class_id, report_type, period_start, period_end, format, content_hash, created_at, source_device_id
+            FROM report_artifacts
+            WHERE ? IS NULL OR class_id = ?
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(class_id)
+        .bind(class_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch report artifacts")?;
+
+        Ok(artifacts)
+    }
+
+    pub async fn get_report_artifact_content(&self, artifact_id: i64) -> Result<Vec<u8>> {
+        let (content, stored_hash): (Vec<u8>, String) = sqlx::query_as(
+            "SELECT content, content_hash FROM report_artifacts WHERE id = ?",
+        )
+        .bind(artifact_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch report artifact")?
+        .context("Report artifact not found")?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(&content);
+        if format!("{:x}", hasher.finalize()) != stored_hash {
+            return Err(anyhow::anyhow!("Report artifact {} failed hash verification", artifact_id));
+        }
+
+        Ok(content)
+    }
+
+    pub async fn clear_all_data(&self) -> Result<()> {
+        sqlx::query("DELETE FROM report_artifacts").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM attachments").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM observations").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM observation_exports").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM retractions").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM handover_imports").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM students").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM classes").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM categories").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM observation_templates").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM custom_dictionary").execute(&self.pool).await?;
+        self.prune_attachment_files().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, FullExportVersion};
+    use tempfile::TempDir;
+    
+    async fn create_test_db() -> (Database, TempDir) {
+        fixtures::test_db().await
+    }
+
+    #[tokio::test]
+    async fn test_create_and_get_class() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Create a class
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        assert_eq!(class.name, "5a");
+        assert_eq!(class.school_year, "2023/24");
+
+        // Get all classes
+        let classes = db.get_classes().await.unwrap();
+        assert_eq!(classes.len(), 1);
+        assert_eq!(classes[0].name, "5a");
+    }
+
+    #[tokio::test]
+    async fn test_create_and_get_student() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Create a class first
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+
+        // Create a student
+        let student = db.create_student(
+            class.id,
+            "Max".to_string(),
+            "Mustermann".to_string(),
+            Some("active".to_string()),
+        ).await.unwrap();
+
+        assert_eq!(student.first_name, "Max");
+        assert_eq!(student.last_name, "Mustermann");
+        assert_eq!(student.class_id, class.id);
+
+        // Get all students
+        let students = db.get_students().await.unwrap();
+        assert_eq!(students.len(), 1);
+        assert_eq!(students[0].first_name, "Max");
+    }
+
+    #[tokio::test]
+    async fn test_soft_delete_student() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Create class and student
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        // Soft delete student
+        db.delete_student(student.id, false).await.unwrap();
+
+        // Student should not appear in regular queries
+        let students = db.get_students().await.unwrap();
+        assert_eq!(students.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_hard_delete_student() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Create class and student
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        // Create observation for student
+        let _observation = db.create_observation(
+            student.id,
+            1,
+            "social".to_string(),
+            "Test observation".to_string(),
+            vec!["test".to_string()],
+        ).await.unwrap();
+
+        // Hard delete student
+        db.delete_student(student.id, true).await.unwrap();
+
+        // Student should be completely gone
+        let students = db.get_students().await.unwrap();
+        assert_eq!(students.len(), 0);
+
+        // Observations should also be gone
+        let observations = db.search_observations(None, None, None).await.unwrap();
+        assert_eq!(observations.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_and_search_observations() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Setup class and student
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        // Create observation
+        let observation = db.create_observation(
+            student.id,
+            1,
+            "social".to_string(),
+            "Shows excellent teamwork skills".to_string(),
+            vec!["teamwork".to_string(), "social".to_string()],
+        ).await.unwrap();
+
+        assert_eq!(observation.student_id, student.id);
+        assert_eq!(observation.category, "social");
+        assert!(observation.text.contains("teamwork"));
+
+        // Search observations
+        let observations = db.search_observations(None, None, None).await.unwrap();
+        assert_eq!(observations.len(), 1);
+
+        // Search by student ID
+        let student_observations = db.search_observations(None, Some(student.id), None).await.unwrap();
+        assert_eq!(student_observations.len(), 1);
+
+        // Search by text query
+        let text_search = db.search_observations(Some("teamwork".to_string()), None, None).await.unwrap();
+        assert_eq!(text_search.len(), 1);
+
+        // Search by category
+        let category_search = db.search_observations(None, None, Some("social".to_string())).await.unwrap();
+        assert_eq!(category_search.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_observation_permissions() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Setup
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let observation = db.create_observation(
+            student.id,
+            1, // Author ID 1
+            "social".to_string(),
+            "Test observation".to_string(),
+            vec![],
+        ).await.unwrap();
+
+        // Author should be able to delete their observation
+        let result = db.delete_observation(observation.id, 1, false).await;
+        assert!(result.is_ok());
+
+        // Recreate observation for next test
+        let observation2 = db.create_observation(
+            student.id,
+            1, // Author ID 1
+            "social".to_string(),
+            "Test observation 2".to_string(),
+            vec![],
+        ).await.unwrap();
+
+        // Different user should not be able to delete
+        let result = db.delete_observation(observation2.id, 2, false).await; // Different author ID
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("Permission denied"));
+
+        // Force delete should work regardless of author
+        let result = db.delete_observation(observation2.id, 2, true).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_observations_since() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Setup
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        // Create observation
+        let _observation = db.create_observation(
+            student.id,
+            1,
+            "social".to_string(),
+            "Recent observation".to_string(),
+            vec![],
+        ).await.unwrap();
+
+        // Get observations from 1 hour ago
+        let one_hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+        let recent_observations = db.get_observations_since(one_hour_ago).await.unwrap();
+        assert_eq!(recent_observations.len(), 1);
+
+        // Get observations from 1 hour in the future (should be empty)
+        let future_time = chrono::Utc::now() + chrono::Duration::hours(1);
+        let future_observations = db.get_observations_since(future_time).await.unwrap();
+        assert_eq!(future_observations.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_changeset_file_operations() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        // Setup test data
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let _observation = db.create_observation(
+            student.id,
+            1,
+            "social".to_string(),
+            "Test for changeset".to_string(),
+            vec!["test".to_string()],
+        ).await.unwrap();
+
+        // Create changeset file
+        let changeset_data = db.create_changeset_file(30).await.unwrap();
+        assert!(!changeset_data.is_empty());
+
+        // Parse the changeset to verify structure
+        let content = String::from_utf8(changeset_data.clone()).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&content).unwrap();
+        
+        assert!(parsed.get("checksum").is_some());
+        assert!(parsed.get("data").is_some());
+
+        // Clear database and re-import
+        db.clear_all_data().await.unwrap();
+        let empty_observations = db.search_observations(None, None, None).await.unwrap();
+        assert_eq!(empty_observations.len(), 0);
+
+        // Apply changeset
+        let result = db.apply_changeset_file(&changeset_data).await.unwrap();
+        assert!(result.contains("Successfully imported"));
+
+        // Verify data was restored
+        let restored_observations = db.search_observations(None, None, None).await.unwrap();
+        assert_eq!(restored_observations.len(), 1);
+        assert_eq!(restored_observations[0].text, "Test for changeset");
+    }
+
+    #[tokio::test]
+    async fn test_changeset_conflicts_resolved_by_logical_clock() {
+        let (db, _temp_dir) = create_test_db().await;
 
-            sqlx::query("DELETE FROM classes WHERE id = ?")
-                .bind(class_id)
-                .execute(&self.pool)
-                .await?;
-        }
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let local = db.create_observation(student.id, 1, "social".to_string(), "Local text".to_string(), vec![]).await.unwrap();
+        assert!(local.logical_clock > 0);
 
-        Ok(())
+        // Remote device has a clock set years in the past but a higher logical clock
+        let old_time = chrono::Utc::now() - chrono::Duration::days(800);
+        let newer = fixtures::changeset_file(serde_json::json!({
+            "timestamp": chrono::Utc::now(),
+            "device_id": "remote-device",
+            "logical_clock": local.logical_clock + 5,
+            "changes": { "observations": [{
+                "id": local.id, "student_id": student.id, "author_id": 1, "category": "social",
+                "text": "Remote edit", "tags": "[]", "created_at": old_time, "updated_at": old_time,
+                "source_device_id": "remote-device", "logical_clock": local.logical_clock + 5
+            }]}
+        }));
+        let result = db.apply_changeset_file(&newer).await.unwrap();
+        assert!(result.contains("1 updated"));
+        let stored = db.get_observation(local.id).await.unwrap().unwrap();
+        assert_eq!(stored.text, "Remote edit");
+
+        // Local clock has moved past the remote value
+        assert!(db.next_logical_clock().await.unwrap() > local.logical_clock + 5);
+
+        // A stale change with a future wall-clock timestamp loses and is flagged
+        let future_time = chrono::Utc::now() + chrono::Duration::days(3);
+        let stale = fixtures::changeset_file(serde_json::json!({
+            "timestamp": future_time,
+            "device_id": "skewed-device",
+            "logical_clock": 1,
+            "changes": { "observations": [{
+                "id": local.id, "student_id": student.id, "author_id": 1, "category": "social",
+                "text": "Stale edit", "tags": "[]", "created_at": future_time, "updated_at": future_time,
+                "source_device_id": "skewed-device", "logical_clock": 1
+            }]}
+        }));
+        let result = db.apply_changeset_file(&stale).await.unwrap();
+        assert!(result.contains("1 unchanged"));
+        assert!(result.contains("Warning"));
+        let stored = db.get_observation(local.id).await.unwrap().unwrap();
+        assert_eq!(stored.text, "Remote edit");
     }
 
-    // Student operations
-    pub async fn create_student(
-        &self,
-        class_id: i64,
-        first_name: String,
-        last_name: String,
-        status: Option<String>,
-    ) -> Result<Student> {
-        let device_id = self.crypto.get_device_id();
-        let status = status.unwrap_or_else(|| "active".to_string());
+    #[tokio::test]
+    async fn test_reimporting_changeset_is_noop() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        let student = sqlx::query_as::<_, Student>(
-            r#"
-            INSERT INTO students (class_id, first_name, last_name, status, source_device_id)
-            VALUES (?, ?, ?, ?, ?)
-            RETURNING *
-            "#,
-        )
-        .bind(class_id)
-        .bind(&first_name)
-        .bind(&last_name)
-        .bind(&status)
-        .bind(&device_id)
-        .fetch_one(&self.pool)
-        .await
-        .context("Failed to create student")?;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
 
-        Ok(student)
+        let changeset = fixtures::changeset_file(serde_json::json!({
+            "operation_id": "op-replay-test",
+            "timestamp": chrono::Utc::now(),
+            "device_id": "remote-device",
+            "changes": { "observations": [{
+                "id": 500, "student_id": student.id, "author_id": 1, "category": "social",
+                "text": "Replay me", "tags": "[]", "created_at": chrono::Utc::now(),
+                "updated_at": chrono::Utc::now(), "source_device_id": "remote-device", "logical_clock": 1
+            }]}
+        }));
+
+        let first = db.apply_changeset_file(&changeset).await.unwrap();
+        assert!(first.contains("Successfully imported 1 observations"));
+
+        // Remove the imported row: a replay must not bring it back
+        db.delete_observation(500, 1, true).await.unwrap();
+        let second = db.apply_changeset_file(&changeset).await.unwrap();
+        assert!(second.contains("already applied"));
+        assert!(db.get_observation(500).await.unwrap().is_none());
+
+        let applied = db.get_applied_operations(None).await.unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].operation_id, "op-replay-test");
+        assert_eq!(applied[0].peer_device_id.as_deref(), Some("remote-device"));
     }
 
-    pub async fn get_students(&self) -> Result<Vec<Student>> {
-        let students = sqlx::query_as::<_, Student>(
-            "SELECT * FROM students WHERE status != 'deleted' ORDER BY last_name, first_name",
-        )
-        .fetch_all(&self.pool)
-        .await
-        .context("Failed to fetch students")?;
+    #[tokio::test]
+    async fn test_tombstones_propagate_deletions() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let soft = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let hard = notebook.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+
+        // Same records already exist on the other device
+        let backup = serde_json::json!({ "data": {
+            "classes": [&class], "students": [&soft, &hard], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+
+        notebook.delete_student(soft.id, false).await.unwrap();
+        notebook.delete_student(hard.id, true).await.unwrap();
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+
+        // Without confirmation only the soft deletion is applied
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        assert!(result.contains("1 deletions applied"));
+        assert!(result.contains(&format!("need confirmation (student {})", hard.id)));
+        let remaining = computer.get_students().await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, hard.id);
+
+        // Confirmed re-import removes the hard-deleted student too
+        let result = computer.apply_changeset_file_with_options(&changeset, true, &CancellationToken::new()).await.unwrap();
+        assert!(result.contains("1 deletions applied"));
+        assert!(computer.get_students().await.unwrap().is_empty());
+    }
 
-        Ok(students)
+    #[tokio::test]
+    async fn test_tombstones_leave_unrelated_records_with_the_same_id() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        // Both devices number their own records from 1
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let other_class = computer.create_class("7c".to_string(), "2023/24".to_string()).await.unwrap();
+        let erika = computer.create_student(other_class.id, "Erika".to_string(), "Muster".to_string(), None).await.unwrap();
+        assert_eq!((class.id, max.id), (other_class.id, erika.id));
+        assert_ne!(max.uuid, erika.uuid);
+
+        notebook.delete_student(max.id, true).await.unwrap();
+        notebook.delete_class(class.id, true).await.unwrap();
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+
+        let result = computer.apply_changeset_file_with_options(&changeset, true, &CancellationToken::new()).await.unwrap();
+        assert!(!result.contains("deletions applied"), "{}", result);
+        assert_eq!(computer.get_students().await.unwrap()[0].first_name, "Erika");
+        assert!(computer.get_class(other_class.id).await.unwrap().is_some());
     }
 
-    pub async fn delete_student(&self, student_id: i64, force_delete: bool) -> Result<()> {
-        if force_delete {
-            // Hard delete: remove student and all observations
-            sqlx::query("DELETE FROM observations WHERE student_id = ?")
-                .bind(student_id)
-                .execute(&self.pool)
-                .await?;
+    #[tokio::test]
+    async fn test_sync_status_dry_run() {
+        let (db, _temp_dir) = create_test_db().await;
 
-            sqlx::query("DELETE FROM students WHERE id = ?")
-                .bind(student_id)
-                .execute(&self.pool)
-                .await?;
-        } else {
-            // Soft delete: mark as deleted
-            sqlx::query("UPDATE students SET status = 'deleted', updated_at = CURRENT_TIMESTAMP WHERE id = ?")
-                .bind(student_id)
-                .execute(&self.pool)
-                .await?;
-        }
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let obs = db.create_observation(student.id, 1, "social".to_string(), "Note".to_string(), vec![]).await.unwrap();
+        db.delete_observation(obs.id, 1, false).await.unwrap();
+
+        let status = db.get_sync_status(DEFAULT_CHANGESET_DAYS_BACK).await.unwrap();
+        assert_eq!(status.scope_creations, 3);
+        assert_eq!(status.scope_deletions, 1);
+        assert_eq!(status.pending_changes, 4);
+        assert!(status.last_sync.is_none());
+        assert!(status.devices[0].is_local);
+
+        // Exporting resets the pending counter but not the scope preview. Timestamps have
+        // second precision, so the changes are dated before the export instead of waiting
+        sqlx::query("UPDATE change_log SET changed_at = datetime('now', '-1 minutes')").execute(&db.pool).await.unwrap();
+        db.create_changeset_file(DEFAULT_CHANGESET_DAYS_BACK).await.unwrap();
+        let status = db.get_sync_status(DEFAULT_CHANGESET_DAYS_BACK).await.unwrap();
+        assert_eq!(status.pending_changes, 0);
+        assert_eq!(status.scope_creations, 3);
+        assert!(status.devices[0].last_export.is_some());
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn test_selective_restore_from_backup() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        let class_a = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let class_b = db.create_class("5b".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class_a.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class_b.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let obs = db.create_observation(max.id, 1, "social".to_string(), "Kept".to_string(), vec![]).await.unwrap();
+
+        let backup = serde_json::json!({ "format": "full_export", "data": {
+            "classes": db.get_classes().await.unwrap(),
+            "students": db.get_students().await.unwrap(),
+            "observations": db.search_observations(None, None, None).await.unwrap(),
+        }})
+        .to_string();
+
+        // Lose Max entirely, soft-delete Anna, edit class 5b locally
+        db.delete_student(max.id, true).await.unwrap();
+        db.delete_student(anna.id, false).await.unwrap();
+        sqlx::query("UPDATE classes SET name = '5b neu' WHERE id = ?")
+            .bind(class_b.id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let selection = RestoreSelection {
+            deleted_students: true,
+            ..Default::default()
+        };
+
+        let preview = db.restore_from_backup(backup.as_bytes(), &selection, false, &CancellationToken::new()).await.unwrap();
+        assert!(!preview.applied);
+        assert_eq!(preview.count("create"), 2); // Max and his observation
+        assert_eq!(preview.count("undelete"), 1);
+        assert_eq!(preview.count("conflict"), 1); // renamed class 5b
+        assert!(db.get_students().await.unwrap().is_empty());
+
+        let report = db.restore_from_backup(backup.as_bytes(), &selection, true, &CancellationToken::new()).await.unwrap();
+        assert_eq!(report.count("create"), 2);
+        assert_eq!(db.get_students().await.unwrap().len(), 2);
+        assert_eq!(db.get_observation(obs.id).await.unwrap().unwrap().text, "Kept");
+        assert_eq!(db.get_class(class_b.id).await.unwrap().unwrap().name, "5b neu");
+
+        // The restore supersedes the earlier deletion for peers
+        let since = chrono::Utc::now() - chrono::Duration::days(1);
+        let tombstones = db.get_tombstones_since(since).await.unwrap();
+        assert!(tombstones.is_empty());
     }
 
-    // Observation operations
-    pub async fn create_observation(
-        &self,
-        student_id: i64,
-        author_id: i64,
-        category: String,
-        text: String,
-        tags: Vec<String>,
-    ) -> Result<Observation> {
-        let device_id = self.crypto.get_device_id();
-        let tags_json = serde_json::to_string(&tags).unwrap_or_else(|_| "[]".to_string());
+    #[tokio::test]
+    async fn test_snapshot_rollback() {
+        let (mut db, _temp_dir) = create_test_db().await;
 
-        let observation = sqlx::query_as::<_, Observation>(
-            r#"
-            INSERT INTO observations (student_id, author_id, category, text, tags, source_device_id)
-            VALUES (?, ?, ?, ?, ?, ?)
-            RETURNING *
-            "#,
-        )
-        .bind(student_id)
-        .bind(author_id)
-        .bind(&category)
-        .bind(&text)
-        .bind(&tags_json)
-        .bind(&device_id)
-        .fetch_one(&self.pool)
-        .await
-        .context("Failed to create observation")?;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let snapshot = db.create_snapshot("before import").await.unwrap();
 
-        Ok(observation)
-    }
+        db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        assert_eq!(db.get_students().await.unwrap().len(), 2);
 
-    pub async fn get_observation(&self, observation_id: i64) -> Result<Option<Observation>> {
-        let observation = sqlx::query_as::<_, Observation>(
-            "SELECT * FROM observations WHERE id = ?",
-        )
-        .bind(observation_id)
-        .fetch_optional(&self.pool)
-        .await
-        .context("Failed to fetch observation")?;
+        assert!(db.rollback_to_snapshot(&snapshot.id, false).await.is_err());
+        assert!(db.rollback_to_snapshot("../observations", true).await.is_err());
 
-        Ok(observation)
+        let pre_rollback = db.rollback_to_snapshot(&snapshot.id, true).await.unwrap();
+        assert_eq!(db.get_students().await.unwrap().len(), 1);
+
+        let snapshots = db.list_snapshots().await.unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert!(snapshots.iter().any(|s| s.id == pre_rollback.id));
+
+        // Undo the rollback via the automatic snapshot
+        db.rollback_to_snapshot(&pre_rollback.id, true).await.unwrap();
+        assert_eq!(db.get_students().await.unwrap().len(), 2);
     }
 
-    pub async fn search_observations(
-        &self,
-        query: Option<String>,
-        student_id: Option<i64>,
-        category: Option<String>,
-    ) -> Result<Vec<Observation>> {
-        let mut sql = "SELECT * FROM observations WHERE 1=1".to_string();
-        let mut params: Vec<String> = Vec::new();
+    #[tokio::test]
+    async fn test_attachment_storage_migration() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        if let Some(q) = query {
-            sql.push_str(" AND text LIKE ?");
-            params.push(format!("%{}%", q));
-        }
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let obs = db.create_observation(student.id, 1, "social".to_string(), "Note".to_string(), vec![]).await.unwrap();
 
-        if let Some(sid) = student_id {
-            sql.push_str(" AND student_id = ?");
-            params.push(sid.to_string());
-        }
+        let in_db = db.store_attachment(obs.id, "a.txt", "text/plain", b"first").await.unwrap();
+        assert_eq!(in_db.storage, "database");
 
-        if let Some(cat) = category {
-            sql.push_str(" AND category = ?");
-            params.push(cat);
-        }
+        assert_eq!(db.migrate_attachment_storage("external", &CancellationToken::new()).await.unwrap(), 1);
+        let external = db.store_attachment(obs.id, "b.txt", "text/plain", b"second").await.unwrap();
+        assert_eq!(external.storage, "external");
+        let stored = std::fs::read(db.attachment_file_path(&in_db.file_hash)).unwrap();
+        assert!(CryptoManager::is_encrypted(&stored) && !stored.ends_with(b"first"));
+        assert_eq!(db.get_attachment_data(in_db.id).await.unwrap(), b"first");
 
-        sql.push_str(" ORDER BY created_at DESC");
+        // Files written before they were encrypted are still read
+        std::fs::write(db.attachment_file_path(&in_db.file_hash), b"first").unwrap();
+        assert_eq!(db.get_attachment_data(in_db.id).await.unwrap(), b"first");
 
-        let mut query_builder = sqlx::query_as::<_, Observation>(&sql);
-        
-        for param in params {
-            query_builder = query_builder.bind(param);
-        }
+        // Moving back empties the attachments directory
+        assert_eq!(db.migrate_attachment_storage("database", &CancellationToken::new()).await.unwrap(), 2);
+        assert!(!db.attachment_file_path(&external.file_hash).exists());
+        assert_eq!(db.get_attachment_data(external.id).await.unwrap(), b"second");
 
-        let observations = query_builder
-            .fetch_all(&self.pool)
-            .await
-            .context("Failed to search observations")?;
+        db.delete_observation(obs.id, 1, true).await.unwrap();
+        assert!(db.get_attachments(obs.id).await.unwrap().is_empty());
+    }
 
-        Ok(observations)
+    #[tokio::test]
+    async fn test_attachment_thumbnail_is_cached_and_pruned() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let obs = db.create_observation(student.id, 1, "work".to_string(), "Drawing".to_string(), vec![]).await.unwrap();
+
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(800, 600))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let picture = db.store_attachment(obs.id, "drawing.png", "image/png", &png).await.unwrap();
+        let text = db.store_attachment(obs.id, "notes.txt", "text/plain", b"notes").await.unwrap();
+
+        let thumbnail = db.get_attachment_thumbnail(picture.id, 100).await.unwrap();
+        assert_eq!(image::load_from_memory(&thumbnail).unwrap().width(), 256);
+        assert!(CryptoManager::is_encrypted(&std::fs::read(db.thumbnail_path(&picture.file_hash, 256)).unwrap()));
+        assert!(db.get_attachment_thumbnail(text.id, 96).await.is_err());
+
+        db.delete_attachment(picture.id).await.unwrap();
+        assert!(!db.thumbnail_path(&picture.file_hash, 256).exists());
     }
 
-    pub async fn get_observations_since(&self, since: chrono::DateTime<chrono::Utc>) -> Result<Vec<Observation>> {
-        let observations = sqlx::query_as::<_, Observation>(
-            "SELECT * FROM observations WHERE created_at >= ? ORDER BY created_at DESC",
-        )
-        .bind(since)
-        .fetch_all(&self.pool)
-        .await
-        .context("Failed to fetch observations since timestamp")?;
+    #[tokio::test]
+    async fn test_custom_dictionary() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        Ok(observations)
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        db.create_student(class.id, "Jolina".to_string(), "Wischnewski".to_string(), None).await.unwrap();
+
+        assert!(db.add_dictionary_word("Lernzielkontrolle").await.unwrap());
+        assert!(!db.add_dictionary_word("lernzielkontrolle").await.unwrap());
+        assert!(db.add_dictionary_word("two words").await.is_err());
+
+        let words = db.get_custom_dictionary().await.unwrap();
+        assert_eq!(words, vec!["Jolina", "Lernzielkontrolle", "Wischnewski"]);
+
+        // Backups carry only the custom words
+        let backup = serde_json::json!({ "data": { "dictionary": ["Förderplan", "Lernzielkontrolle"] } });
+        let result = db.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+        assert!(result.contains("1 dictionary words"));
+        assert_eq!(db.get_dictionary_words().await.unwrap().len(), 2);
     }
 
-    pub async fn delete_observation(
-        &self,
-        observation_id: i64,
-        author_id: i64,
-        force_delete: bool,
-    ) -> Result<()> {
-        if force_delete {
-            // Hard delete: remove completely
-            sqlx::query("DELETE FROM observations WHERE id = ?")
-                .bind(observation_id)
-                .execute(&self.pool)
-                .await?;
-        } else {
-            // Check if user is the author
-            let author_check = sqlx::query_scalar::<_, i64>(
-                "SELECT COUNT(*) FROM observations WHERE id = ? AND author_id = ?",
-            )
-            .bind(observation_id)
-            .bind(author_id)
-            .fetch_one(&self.pool)
-            .await?;
+    #[tokio::test]
+    async fn test_create_observations_batch() {
+        let (db, _temp_dir) = create_test_db().await;
 
-            if author_check == 0 {
-                return Err(anyhow::anyhow!(
-                    "Permission denied: You can only delete your own observations"
-                ));
-            }
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
 
-            sqlx::query("DELETE FROM observations WHERE id = ? AND author_id = ?")
-                .bind(observation_id)
-                .bind(author_id)
-                .execute(&self.pool)
-                .await?;
+        let entry = |text: &str, student_id| NewObservation {
+            student_id,
+            category: "social".to_string(),
+            text: text.to_string(),
+            tags: vec![],
+            created_at: None,
+            local_only: false,
+            visibility: None,
+            subject: None,
+            reported_by_user_id: None,
+            reported_by: None,
+        };
+
+        let created = db
+            .create_observations_batch(1, vec![entry("one", student.id), entry("two", student.id)])
+            .await
+            .unwrap();
+        assert_eq!(created.len(), 2);
+        assert!(created[1].logical_clock > created[0].logical_clock);
+        assert_eq!(db.get_sync_status(DEFAULT_CHANGESET_DAYS_BACK).await.unwrap().scope_creations, 4);
+
+        // A failing entry rolls back the whole batch
+        let result = db
+            .create_observations_batch(1, vec![entry("three", student.id), entry("orphan", 9999)])
+            .await;
+        assert!(result.is_err());
+        assert_eq!(db.search_observations(None, None, None).await.unwrap().len(), 2);
+    }
+
+    /// Rough comparison of single inserts and batched inserts.
+    /// Run with `cargo test bench_observation_inserts -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn bench_observation_inserts() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let count = 200;
+
+        let started = std::time::Instant::now();
+        for i in 0..count {
+            db.create_observation(student.id, 1, "social".to_string(), format!("single {}", i), vec![])
+                .await
+                .unwrap();
         }
+        let single = started.elapsed();
+
+        let entries = (0..count)
+            .map(|i| NewObservation {
+                student_id: student.id,
+                category: "social".to_string(),
+                text: format!("batch {}", i),
+                tags: vec![],
+                created_at: None,
+                local_only: false,
+                visibility: None,
+                subject: None,
+                reported_by_user_id: None,
+                reported_by: None,
+            })
+            .collect();
+        let started = std::time::Instant::now();
+        db.create_observations_batch(1, entries).await.unwrap();
+        let batched = started.elapsed();
+
+        println!("{} observations: single {:?}, batched {:?}", count, single, batched);
+    }
 
-        Ok(())
+    async fn query_plan(db: &Database, sql: &str) -> String {
+        sqlx::query_as::<_, (i64, i64, i64, String)>(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(_, _, _, detail)| detail)
+            .collect::<Vec<_>>()
+            .join("\n")
     }
 
-    // Sync and changeset operations
-    pub async fn get_pending_changesets(&self, _operation: &str) -> Result<Vec<u8>> {
-        // Placeholder for changeset export functionality
-        // In a real implementation, this would track changes and serialize them
-        let changeset = serde_json::json!({
-            "format": "changeset_v1",
-            "timestamp": chrono::Utc::now(),
-            "changes": []
-        });
+    #[tokio::test]
+    async fn test_hot_queries_use_indexes() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        Ok(changeset.to_string().into_bytes())
+        let plan = query_plan(&db, "SELECT * FROM observations WHERE student_id = ? ORDER BY created_at DESC").await;
+        assert!(plan.contains("USING INDEX idx_observations_student_created"), "{}", plan);
+        assert!(!plan.contains("TEMP B-TREE"), "{}", plan);
+
+        let plan = query_plan(&db, "SELECT * FROM observations WHERE category = ? ORDER BY created_at DESC").await;
+        assert!(plan.contains("USING INDEX idx_observations_category"), "{}", plan);
+
+        let plan = query_plan(
+            &db,
+            "SELECT * FROM students WHERE class_id = ? AND status != 'deleted' ORDER BY last_name, first_name",
+        )
+        .await;
+        assert!(plan.contains("USING INDEX idx_students_class_status"), "{}", plan);
+
+        let plan = query_plan(
+            &db,
+            "SELECT o.* FROM observations o JOIN students s ON s.id = o.student_id WHERE s.class_id = ?",
+        )
+        .await;
+        assert!(!plan.contains("SCAN o"), "{}", plan);
     }
 
-    pub async fn apply_changeset(&self, _changeset: &[u8], _operation: &str) -> Result<()> {
-        // Placeholder for changeset import functionality
-        // In a real implementation, this would parse and apply changes
-        Ok(())
+    #[tokio::test]
+    async fn test_concurrent_writes_do_not_fail_busy() {
+        let (db, _temp_dir) = create_test_db().await;
+        let db = Arc::new(db);
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    for j in 0..10 {
+                        db.create_observation(student.id, 1, "social".to_string(), format!("{}-{}", i, j), vec![])
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.await.unwrap();
+        }
+
+        assert_eq!(db.search_observations(None, Some(student.id), None).await.unwrap().len(), 80);
+        let health = db.get_database_health().await.unwrap();
+        assert_eq!(health.journal_mode, "wal");
+        assert_eq!(health.busy_errors, 0);
     }
 
-    pub async fn create_changeset_file(&self, days_back: u32) -> Result<Vec<u8>> {
-        let device_id = self.crypto.get_device_id();
-        let cutoff_date = chrono::Utc::now() - chrono::Duration::days(days_back as i64);
+    #[tokio::test]
+    async fn test_reader_is_read_only_and_sees_writes() {
+        let (db, _temp_dir) = create_test_db().await;
+        let reader = db.reader();
 
-        let recent_observations = self.get_observations_since(cutoff_date).await?;
-        
-        let changeset = serde_json::json!({
-            "format": "changeset_file_v1",
-            "version": "1.0",
-            "timestamp": chrono::Utc::now(),
-            "device_id": device_id,
-            "days_back": days_back,
-            "changes": {
-                "observations": recent_observations
-            }
-        });
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        assert_eq!(reader.get_classes().await.unwrap().len(), 1);
 
-        // Calculate checksum for integrity
-        let content = changeset.to_string();
-        let mut hasher = Sha256::new();
-        hasher.update(content.as_bytes());
-        let checksum = format!("{:x}", hasher.finalize());
+        // A read transaction kept open on the reader does not block writes
+        let mut long_read = reader.pool.begin().await.unwrap();
+        sqlx::query("SELECT COUNT(*) FROM classes").fetch_one(&mut *long_read).await.unwrap();
+        db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        long_read.rollback().await.unwrap();
+
+        assert!(reader.create_class("5b".to_string(), "2023/24".to_string()).await.is_err());
+    }
 
-        let final_changeset = serde_json::json!({
-            "checksum": checksum,
-            "data": changeset
-        });
+    #[tokio::test]
+    async fn test_cancelled_import_rolls_back() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        Ok(final_changeset.to_string().into_bytes())
+        let backup = fixtures::full_export(
+            serde_json::json!({ "classes": [fixtures::class_record(1, "5a")], "dictionary": ["Förderplan"] }),
+            FullExportVersion::Legacy,
+        );
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let result = db.import_full_backup(backup.to_string().as_bytes(), &cancel).await;
+        assert!(result.unwrap_err().is::<crate::operations::OperationCancelled>());
+        assert!(db.get_classes().await.unwrap().is_empty());
+        assert!(db.get_dictionary_words().await.unwrap().is_empty());
     }
 
-    pub async fn apply_changeset_file(&self, changeset_data: &[u8]) -> Result<String> {
-        let content = String::from_utf8(changeset_data.to_vec())
-            .context("Invalid changeset file encoding")?;
+    #[tokio::test]
+    async fn test_streamed_import_spans_chunks_in_file_order() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        let parsed: serde_json::Value = serde_json::from_str(&content)
-            .context("Invalid changeset file format")?;
+        let observations: Vec<_> = (1..=IMPORT_CHUNK_SIZE as i64 * 2 + 1)
+            .map(|id| fixtures::observation_record(id, 7, &format!("Eintrag {}", id)))
+            .collect();
+        let student = fixtures::student_record(7, 3, "Max", "Mustermann");
+        let class = fixtures::class_record(3, "5a");
 
-        // Verify checksum
-        let stored_checksum = parsed.get("checksum")
-            .and_then(|c| c.as_str())
-            .context("Missing checksum in changeset file")?;
+        // Referencing records come first, the way an export without key sorting writes them
+        let backup = format!(
+            r#"{{"format": "full_export", "data": {{"observations": {}, "attachments": [{{"blob": "ignored"}}], "students": [{}], "classes": [{}]}}}}"#,
+            serde_json::Value::from(observations), student, class
+        );
 
-        let data_section = parsed.get("data")
-            .context("Missing data section in changeset file")?;
+        let result = db
+            .import_full_backup_from_reader(std::io::Cursor::new(backup.into_bytes()), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert!(result.contains("1 classes, 1 students, 1001 observations"));
+        assert_eq!(db.search_observations(None, Some(7), None).await.unwrap().len(), 1001);
 
-        let data_content = data_section.to_string();
-        let mut hasher = Sha256::new();
-        hasher.update(data_content.as_bytes());
-        let calculated_checksum = format!("{:x}", hasher.finalize());
+        // Truncated files are rejected without importing anything
+        let (db, _temp_dir) = create_test_db().await;
+        let truncated = r#"{"data": {"classes": [{"id": 3, "name": "5a", "school_year": "2023/24"#;
+        assert!(db.import_full_backup(truncated.as_bytes(), &CancellationToken::new()).await.is_err());
+        assert!(db.get_classes().await.unwrap().is_empty());
+    }
 
-        if stored_checksum != calculated_checksum {
-            return Err(anyhow::anyhow!("Checksum verification failed"));
-        }
+    #[tokio::test]
+    async fn test_import_verifies_export_manifest() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        // Extract observations and merge them
-        let observations_data = data_section.get("changes")
-            .and_then(|c| c.get("observations"))
-            .and_then(|o| o.as_array())
-            .context("Invalid observations data in changeset")?;
+        let data = serde_json::json!({
+            "classes": [fixtures::class_record(3, "5a")],
+            "students": [
+                fixtures::student_record(7, 3, "Max", "Mustermann"),
+                fixtures::student_record(8, 3, "Anna", "Schmidt")
+            ]
+        });
+        let manifest = ExportManifest::describe(&data);
 
-        let mut imported_count = 0;
-        for obs_value in observations_data {
-            if let Ok(obs) = serde_json::from_value::<Observation>(obs_value.clone()) {
-                // Check if observation already exists
-                let exists = sqlx::query_scalar::<_, i64>(
-                    "SELECT COUNT(*) FROM observations WHERE id = ?",
-                )
-                .bind(obs.id)
-                .fetch_one(&self.pool)
-                .await?;
+        // A student went missing on the way
+        let mut truncated = data.clone();
+        truncated["students"].as_array_mut().unwrap().pop();
+        let backup = serde_json::json!({ "format": "full_export", "manifest": manifest, "data": truncated });
 
-                if exists == 0 {
-                    // Insert new observation (preserving original ID and timestamps)
-                    sqlx::query(
-                        r#"
-                        INSERT INTO observations (id, student_id, author_id, category, text, tags, created_at, updated_at, source_device_id)
-                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-                        "#,
-                    )
-                    .bind(obs.id)
-                    .bind(obs.student_id)
-                    .bind(obs.author_id)
-                    .bind(obs.category)
-                    .bind(obs.text)
-                    .bind(obs.tags)
-                    .bind(obs.created_at)
-                    .bind(obs.updated_at)
-                    .bind(obs.source_device_id)
-                    .execute(&self.pool)
-                    .await?;
+        let err = db
+            .import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("students: expected 2, found 1"), "{}", err);
+        assert!(db.get_classes().await.unwrap().is_empty());
 
-                    imported_count += 1;
-                }
-            }
-        }
+        let err = db
+            .restore_from_backup(backup.to_string().as_bytes(), &RestoreSelection::default(), false, &CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("students: expected 2, found 1"));
+
+        let backup = serde_json::json!({ "format": "full_export", "manifest": manifest, "data": data });
+        let result = db
+            .import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert!(result.contains("1 classes, 2 students"));
 
-        Ok(format!("Successfully imported {} observations", imported_count))
+        // Changesets carry a manifest too and pass their own verification
+        let changeset = db.create_changeset_file(30).await.unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&changeset).unwrap();
+        assert_eq!(parsed["data"]["manifest"]["schema_version"], manifest::SCHEMA_VERSION);
+        assert!(db.apply_changeset_file(&changeset).await.is_ok());
     }
 
-    pub async fn import_full_backup(&self, backup_data: &[u8]) -> Result<String> {
-        let content = String::from_utf8(backup_data.to_vec())
-            .context("Invalid backup file encoding")?;
+    #[tokio::test]
+    async fn test_old_observations_are_read_only_until_unlocked() {
+        let (db, _temp_dir) = create_test_db().await;
 
-        let parsed: serde_json::Value = serde_json::from_str(&content)
-            .context("Invalid backup file format")?;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let old = db.create_observation(student.id, 1, "Sozial".to_string(), "Alt".to_string(), vec![]).await.unwrap();
+        let recent = db.create_observation(student.id, 1, "Sozial".to_string(), "Neu".to_string(), vec![]).await.unwrap();
+        sqlx::query("UPDATE observations SET created_at = datetime('now', '-40 days') WHERE id = ?")
+            .bind(old.id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
 
-        let mut imported_students = 0;
-        let mut imported_classes = 0;
-        let mut imported_observations = 0;
+        db.set_observation_lock_days(Some(30)).await.unwrap();
+        assert_eq!(db.get_observation_lock_days().await.unwrap(), Some(30));
 
-        // Import classes first (due to foreign key constraints)
-        if let Some(classes_data) = parsed.get("data").and_then(|d| d.get("classes")).and_then(|c| c.as_array()) {
-            for class_value in classes_data {
-                if let Ok(class) = serde_json::from_value::<Class>(class_value.clone()) {
-                    let exists = sqlx::query_scalar::<_, i64>(
-                        "SELECT COUNT(*) FROM classes WHERE id = ?",
-                    )
-                    .bind(class.id)
-                    .fetch_one(&self.pool)
-                    .await?;
+        let err = db.delete_observation(old.id, 1, false).await.unwrap_err();
+        assert!(err.to_string().contains("read-only"));
+        db.delete_observation(recent.id, 1, false).await.unwrap();
 
-                    if exists == 0 {
-                        sqlx::query(
-                            "INSERT INTO classes (id, name, school_year, created_at, updated_at, source_device_id) VALUES (?, ?, ?, ?, ?, ?)",
-                        )
-                        .bind(class.id)
-                        .bind(class.name)
-                        .bind(class.school_year)
-                        .bind(class.created_at)
-                        .bind(class.updated_at)
-                        .bind(class.source_device_id)
-                        .execute(&self.pool)
-                        .await?;
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+        assert!(db.unlock_observation(old.id, teacher.id, "Tippfehler").await.is_err());
+        assert!(db.unlock_observation(old.id, 1, " ").await.is_err());
 
-                        imported_classes += 1;
-                    }
-                }
-            }
-        }
+        db.unlock_observation(old.id, 1, "Falscher Schüler").await.unwrap();
+        db.delete_observation(old.id, 1, false).await.unwrap();
+        assert!(db.get_observation(old.id).await.unwrap().is_none());
+    }
 
-        // Import students
-        if let Some(students_data) = parsed.get("data").and_then(|d| d.get("students")).and_then(|s| s.as_array()) {
-            for student_value in students_data {
-                if let Ok(student) = serde_json::from_value::<Student>(student_value.clone()) {
-                    let exists = sqlx::query_scalar::<_, i64>(
-                        "SELECT COUNT(*) FROM students WHERE id = ?",
-                    )
-                    .bind(student.id)
-                    .fetch_one(&self.pool)
-                    .await?;
+    #[tokio::test]
+    async fn test_cosigned_observations_are_immutable() {
+        let (db, _temp_dir) = create_test_db().await;
 
-                    if exists == 0 {
-                        sqlx::query(
-                            "INSERT INTO students (id, class_id, first_name, last_name, status, created_at, updated_at, source_device_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
-                        )
-                        .bind(student.id)
-                        .bind(student.class_id)
-                        .bind(student.first_name)
-                        .bind(student.last_name)
-                        .bind(student.status)
-                        .bind(student.created_at)
-                        .bind(student.updated_at)
-                        .bind(student.source_device_id)
-                        .execute(&self.pool)
-                        .await?;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let observation = db
+            .create_observation(student.id, 1, "Kinderschutz".to_string(), "Blaue Flecken am Arm".to_string(), vec![])
+            .await
+            .unwrap();
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
 
-                        imported_students += 1;
-                    }
-                }
-            }
-        }
+        assert!(db.cosign_observation(observation.id, 1).await.is_err());
+        assert!(db.cosign_observation(observation.id, 999).await.is_err());
 
-        // Import observations
-        if let Some(observations_data) = parsed.get("data").and_then(|d| d.get("observations")).and_then(|o| o.as_array()) {
-            for obs_value in observations_data {
-                if let Ok(obs) = serde_json::from_value::<Observation>(obs_value.clone()) {
-                    let exists = sqlx::query_scalar::<_, i64>(
-                        "SELECT COUNT(*) FROM observations WHERE id = ?",
-                    )
-                    .bind(obs.id)
-                    .fetch_one(&self.pool)
-                    .await?;
+        let cosigned = db.cosign_observation(observation.id, teacher.id).await.unwrap();
+        assert_eq!(cosigned.cosigned_by, Some(teacher.id));
+        assert!(cosigned.cosigned_at.is_some());
+        assert!(cosigned.logical_clock > observation.logical_clock);
+        assert!(db.cosign_observation(observation.id, teacher.id).await.is_err());
 
-                    if exists == 0 {
-                        sqlx::query(
-                            "INSERT INTO observations (id, student_id, author_id, category, text, tags, created_at, updated_at, source_device_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
-                        )
-                        .bind(obs.id)
-                        .bind(obs.student_id)
-                        .bind(obs.author_id)
-                        .bind(obs.category)
-                        .bind(obs.text)
-                        .bind(obs.tags)
-                        .bind(obs.created_at)
-                        .bind(obs.updated_at)
-                        .bind(obs.source_device_id)
-                        .execute(&self.pool)
-                        .await?;
+        let err = db.delete_observation(observation.id, 1, true).await.unwrap_err();
+        assert!(err.to_string().contains("co-signed"));
+        assert!(db.get_observation(observation.id).await.unwrap().is_some());
+    }
 
-                        imported_observations += 1;
-                    }
-                }
-            }
-        }
+    #[tokio::test]
+    async fn test_transfer_keeps_history_and_syncs() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        let old_class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let new_class = notebook.create_class("5b".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = notebook.create_student(old_class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let before = notebook.create_observation(student.id, 1, "Sozial".to_string(), "Vorher".to_string(), vec![]).await.unwrap();
+        sqlx::query("UPDATE observations SET created_at = datetime('now', '-10 days') WHERE id = ?")
+            .bind(before.id)
+            .execute(&notebook.pool)
+            .await
+            .unwrap();
 
-        Ok(format!(
-            "Successfully imported {} classes, {} students, {} observations",
-            imported_classes, imported_students, imported_observations
-        ))
-    }
+        let backup = serde_json::json!({ "data": {
+            "classes": [&old_class, &new_class], "students": [&student], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
 
-    async fn seed_default_categories(&self) -> Result<()> {
-        // Check if categories already exist
-        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM categories")
-            .fetch_one(&self.pool)
-            .await?;
+        let transfer_date = chrono::Utc::now() - chrono::Duration::days(2);
+        assert!(notebook.transfer_student(student.id, old_class.id, transfer_date, true).await.is_err());
+        assert!(notebook
+            .transfer_student(student.id, new_class.id, chrono::Utc::now() + chrono::Duration::days(1), true)
+            .await
+            .is_err());
+        let transfer = notebook.transfer_student(student.id, new_class.id, transfer_date, true).await.unwrap();
+        assert_eq!(transfer.from_class_id, old_class.id);
+        let after = notebook.create_observation(student.id, 1, "Sozial".to_string(), "Nachher".to_string(), vec![]).await.unwrap();
+
+        // Reports attribute each observation to the class the student was in at the time
+        let from = chrono::Utc::now() - chrono::Duration::days(30);
+        let to = chrono::Utc::now() + chrono::Duration::days(1);
+        let old_ids: Vec<i64> = notebook.get_class_observations_between(old_class.id, from, to).await.unwrap().iter().map(|o| o.id).collect();
+        let new_ids: Vec<i64> = notebook.get_class_observations_between(new_class.id, from, to).await.unwrap().iter().map(|o| o.id).collect();
+        assert_eq!(old_ids, vec![before.id]);
+        assert_eq!(new_ids, vec![after.id]);
+
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        assert!(result.contains("1 class transfers applied"));
+        assert_eq!(computer.get_student(student.id).await.unwrap().unwrap().class_id, new_class.id);
+        assert_eq!(computer.get_student_transfers(student.id).await.unwrap().len(), 1);
+        let old_ids: Vec<i64> = computer.get_class_observations_between(old_class.id, from, to).await.unwrap().iter().map(|o| o.id).collect();
+        assert_eq!(old_ids, vec![before.id]);
+    }
 
-        if count > 0 {
-            return Ok(()); // Categories already exist
+    #[tokio::test]
+    async fn test_reference_codes_travel_in_changesets() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        let class = notebook.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let max = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = notebook.create_student(class.id, "Anna".to_string(), "Albrecht".to_string(), None).await.unwrap();
+        let observation = notebook.create_observation(max.id, 1, "Sozial".to_string(), "Hilft".to_string(), vec![]).await.unwrap();
+        let backup = serde_json::json!({ "data": { "classes": [&class], "students": [&max, &anna], "observations": [] }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+        // The computer numbered Anna before the notebook did
+        let anna_code = crate::reference_codes::code_for(&computer, "student", anna.id).await.unwrap();
+
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        for (entity_type, id) in [("observation", observation.id), ("class", class.id)] {
+            assert_eq!(
+                crate::reference_codes::code_for(&computer, entity_type, id).await.unwrap(),
+                crate::reference_codes::code_for(&notebook, entity_type, id).await.unwrap()
+            );
         }
+        assert_eq!(crate::reference_codes::code_for(&computer, "student", anna.id).await.unwrap(), anna_code);
+        assert_ne!(
+            crate::reference_codes::code_for(&computer, "student", max.id).await.unwrap(),
+            crate::reference_codes::code_for(&notebook, "student", max.id).await.unwrap()
+        );
+        assert!(result.contains("here has the code"), "{}", result);
+        assert!(result.contains("is already given to another record here"), "{}", result);
+    }
 
-        let device_id = self.crypto.get_device_id();
-        
-        // Default categories with appealing colors
-        let default_categories = vec![
-            ("Sozial", "#10B981", "#D1FAE5", "#065F46", 1), // Green theme
-            ("Fachlich", "#3B82F6", "#DBEAFE", "#1E3A8A", 2), // Blue theme  
-            ("Verhalten", "#F59E0B", "#FEF3C7", "#92400E", 3), // Amber theme
-            ("Förderung", "#8B5CF6", "#EDE9FE", "#5B21B6", 4), // Purple theme
-            ("Sonstiges", "#6B7280", "#F3F4F6", "#374151", 5), // Gray theme
-        ];
-
-        let category_count = default_categories.len();
+    #[tokio::test]
+    async fn test_guardian_sensitive_fields_stay_local() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let backup = serde_json::json!({ "data": {
+            "classes": [&class], "students": [&student], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+
+        let input = |sensitive: &[&str]| GuardianInput {
+            name: "Erika Mustermann".to_string(),
+            contact: Some("0170 1234567".to_string()),
+            relationship: "Mutter".to_string(),
+            consent_reference: Some("EV-2023-17".to_string()),
+            sensitive_fields: sensitive.iter().map(|f| f.to_string()).collect(),
+        };
+        assert!(notebook.create_guardian(student.id, input(&["birthday"])).await.is_err());
+        let guardian = notebook.create_guardian(student.id, input(&["contact"])).await.unwrap();
+
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        assert!(result.contains("1 guardians updated"));
+        let synced = &computer.get_guardians(student.id).await.unwrap()[0];
+        assert_eq!(synced.uuid, guardian.uuid);
+        assert_eq!(synced.name, "Erika Mustermann");
+        assert_eq!(synced.contact, None);
+        assert_eq!(synced.consent_reference.as_deref(), Some("EV-2023-17"));
+
+        // Erasure of the student takes the guardians along
+        notebook.delete_student(student.id, true).await.unwrap();
+        assert!(notebook.get_guardians(student.id).await.unwrap().is_empty());
+    }
 
-        for (name, color, bg_color, text_color, sort_order) in default_categories {
-            sqlx::query(
-                r#"
-                INSERT INTO categories (name, color, background_color, text_color, is_active, sort_order, source_device_id)
-                VALUES (?, ?, ?, ?, ?, ?, ?)
-                "#
-            )
-            .bind(name)
-            .bind(color)
-            .bind(bg_color) 
-            .bind(text_color)
-            .bind(true)
-            .bind(sort_order)
-            .bind(&device_id)
-            .execute(&self.pool)
-            .await?;
-        }
+    #[tokio::test]
+    async fn test_guardians_follow_the_student_uuid() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let backup = serde_json::json!({ "data": {
+            "classes": [&class], "students": [&student], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+        // The computer knows the student under another id, and has a guardian with the sender's id
+        sqlx::query("UPDATE students SET id = 42 WHERE id = ?").bind(student.id).execute(&computer.pool).await.unwrap();
+        let own = computer.create_student(class.id, "Erika".to_string(), "Muster".to_string(), None).await.unwrap();
+        let input = |name: &str| GuardianInput {
+            name: name.to_string(),
+            contact: None,
+            relationship: "Mutter".to_string(),
+            consent_reference: None,
+            sensitive_fields: vec![],
+        };
+        let unrelated = computer.create_guardian(own.id, input("Eva Muster")).await.unwrap();
+        let guardian = notebook.create_guardian(student.id, input("Erika Mustermann")).await.unwrap();
+        assert_eq!(guardian.id, unrelated.id);
+
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        assert!(result.contains("1 guardians updated"), "{}", result);
+        let synced = computer.get_guardians(42).await.unwrap();
+        assert_eq!((synced.len(), synced[0].uuid.as_ref()), (1, guardian.uuid.as_ref()));
+        assert_eq!(computer.get_guardians(own.id).await.unwrap()[0].name, "Eva Muster");
+    }
 
-        println!("Created {} default categories", category_count);
-        Ok(())
+    #[tokio::test]
+    async fn test_class_layout_syncs_and_forgets_erased_students() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let erika = notebook.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        let backup = serde_json::json!({ "data": {
+            "classes": [&class], "students": [&max, &erika], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+
+        assert!(notebook.set_class_layout(class.id, "[]").await.is_err());
+        assert!(notebook.set_class_layout(9999, "{}").await.is_err());
+        let layout = format!(r#"{{"groups": [{{"name": "Tisch 1", "student_ids": [{}, {}]}}]}}"#, max.id, erika.id);
+        notebook.set_class_layout(class.id, &layout).await.unwrap();
+
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        assert!(result.contains("1 class layouts updated"));
+        let synced = computer.get_class_layout(class.id).await.unwrap().unwrap();
+        assert_eq!(synced.layout, notebook.get_class_layout(class.id).await.unwrap().unwrap().layout);
+        // The same file a second time is recognised and not applied again
+        assert!(!computer.apply_changeset_file(&changeset).await.unwrap().contains("class layouts"));
+
+        notebook.delete_student(max.id, true).await.unwrap();
+        let layout: serde_json::Value =
+            serde_json::from_str(&notebook.get_class_layout(class.id).await.unwrap().unwrap().layout).unwrap();
+        assert_eq!(layout["groups"][0]["student_ids"], serde_json::json!([erika.id]));
+
+        notebook.delete_class(class.id, true).await.unwrap();
+        assert!(notebook.get_class_layout(class.id).await.unwrap().is_none());
     }
 
-    // Category CRUD operations
-    pub async fn get_categories(&self) -> Result<Vec<crate::Category>> {
-        let categories = sqlx::query_as::<_, crate::Category>(
-            "SELECT * FROM categories WHERE is_active = 1 ORDER BY sort_order ASC"
-        )
-        .fetch_all(&self.pool)
-        .await
-        .context("Failed to fetch categories")?;
-        Ok(categories)
+    #[tokio::test]
+    async fn test_observation_session_groups_observations_of_its_class() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let other_class = db.create_class("6b".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let erika = db.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        let paul = db.create_student(other_class.id, "Paul".to_string(), "Beispiel".to_string(), None).await.unwrap();
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+
+        let before = db.create_observation(max.id, 1, "Sozial".to_string(), "Vorher".to_string(), vec![]).await.unwrap();
+        assert!(db.end_observation_session(1).await.is_err());
+
+        let session = db.start_observation_session(class.id, Some("Hospitation 3. Stunde"), None, 1).await.unwrap();
+        assert!(session.ended_at.is_none());
+        assert!(db.start_observation_session(other_class.id, None, None, 1).await.is_err());
+
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Hilft anderen".to_string(), vec![]).await.unwrap();
+        db.create_observation(erika.id, 1, "Arbeitsverhalten".to_string(), "Konzentriert".to_string(), vec![]).await.unwrap();
+        db.create_observation(max.id, 1, "Arbeitsverhalten".to_string(), "Meldet sich".to_string(), vec![]).await.unwrap();
+        db.set_default_visibility(teacher.id, VISIBILITY_PRIVATE).await.unwrap();
+        db.create_observation(erika.id, teacher.id, "Sozial".to_string(), "Eigene Notiz".to_string(), vec![]).await.unwrap();
+        let elsewhere = db.create_observation(paul.id, 1, "Sozial".to_string(), "Andere Klasse".to_string(), vec![]).await.unwrap();
+        assert_eq!(elsewhere.session_id, None);
+
+        // The private note only counts for its author
+        let ended = db.end_observation_session(1).await.unwrap();
+        assert!(ended.ended_at.is_some());
+        assert_eq!(ended.observation_count, 3);
+        assert_eq!(db.get_observation_session(session.id, teacher.id).await.unwrap().unwrap().observation_count, 4);
+        let after = db.create_observation(max.id, 1, "Sozial".to_string(), "Nachher".to_string(), vec![]).await.unwrap();
+        assert_eq!(before.session_id, None);
+        assert_eq!(after.session_id, None);
+
+        let observations = db.get_session_observations(session.id, 1).await.unwrap();
+        assert_eq!(
+            observations.iter().map(|o| o.text.as_str()).collect::<Vec<_>>(),
+            vec!["Hilft anderen", "Konzentriert", "Meldet sich"]
+        );
+        assert_eq!(db.get_session_observations(session.id, teacher.id).await.unwrap().len(), 4);
+
+        let statistics = db.get_session_statistics(session.id, 1, None).await.unwrap();
+        assert_eq!(statistics.observations, 3);
+        assert_eq!(statistics.students_in_class, 2);
+        assert_eq!(statistics.by_category["Arbeitsverhalten"], 2);
+        assert_eq!(statistics.by_student[&max.id], 2);
+
+        assert_eq!(db.get_observation_sessions(Some(class.id), 1).await.unwrap()[0].observation_count, 3);
+        assert!(db.get_observation_sessions(Some(other_class.id), 1).await.unwrap().is_empty());
     }
 
-    pub async fn create_category(&self, name: String, color: String, background_color: String, text_color: String) -> Result<crate::Category> {
-        let device_id = self.crypto.get_device_id();
-        
-        // Get next sort order
-        let max_order: i32 = sqlx::query_scalar("SELECT COALESCE(MAX(sort_order), 0) FROM categories")
-            .fetch_one(&self.pool)
+    #[tokio::test]
+    async fn test_subjects_separate_observations() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        assert!(db.get_subjects().await.unwrap().iter().any(|s| s.name == "Mathematik"));
+        let chemistry = db.create_subject(" Chemie ").await.unwrap();
+        assert_eq!(chemistry.name, "Chemie");
+        assert!(db.create_subject("").await.is_err());
+
+        let entry = |text: &str, subject: Option<&str>| NewObservation {
+            student_id: student.id,
+            category: "Fachlich".to_string(),
+            text: text.to_string(),
+            tags: vec![],
+            created_at: None,
+            local_only: false,
+            visibility: None,
+            subject: subject.map(str::to_string),
+            reported_by_user_id: None,
+            reported_by: None,
+        };
+        assert!(db.create_observations_batch(1, vec![entry("Unbekannt", Some("Latein"))]).await.is_err());
+        db.create_observations_batch(1, vec![entry("Rechnet sicher", Some("Mathematik")), entry("Ohne Fach", None)])
             .await
-            .unwrap_or(0);
+            .unwrap();
 
-        let category = sqlx::query_as::<_, crate::Category>(
-            r#"
-            INSERT INTO categories (name, color, background_color, text_color, is_active, sort_order, source_device_id)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
-            RETURNING *
-            "#
-        )
-        .bind(&name)
-        .bind(&color)
-        .bind(&background_color)
-        .bind(&text_color)
-        .bind(true)
-        .bind(max_order + 1)
-        .bind(&device_id)
-        .fetch_one(&self.pool)
-        .await
-        .context("Failed to create category")?;
-        Ok(category)
-    }
+        // The session's subject is the default, an explicit one wins
+        let session = db.start_observation_session(class.id, None, Some("Sport"), 1).await.unwrap();
+        let created = db
+            .create_observations_batch(1, vec![entry("Fair im Spiel", None), entry("Zählt Punkte", Some("Mathematik"))])
+            .await
+            .unwrap();
+        db.end_observation_session(1).await.unwrap();
+        assert_eq!(created[0].subject.as_deref(), Some("Sport"));
+        assert_eq!(created[1].subject.as_deref(), Some("Mathematik"));
 
-    pub async fn update_category(&self, id: i64, name: String, color: String, background_color: String, text_color: String) -> Result<()> {
-        sqlx::query(
-            r#"
-            UPDATE categories 
-            SET name = ?, color = ?, background_color = ?, text_color = ?, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?
-            "#
-        )
-        .bind(&name)
-        .bind(&color)
-        .bind(&background_color)
-        .bind(&text_color)
-        .bind(id)
-        .execute(&self.pool)
-        .await?;
-        Ok(())
+        let maths = db
+            .search_observations_in_subject(None, Some(student.id), None, Some("Mathematik".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(maths.len(), 2);
+        assert_eq!(db.search_observations(None, Some(student.id), None).await.unwrap().len(), 4);
+
+        let statistics = db.get_session_statistics(session.id, 1, Some("Sport")).await.unwrap();
+        assert_eq!(statistics.observations, 1);
+        assert_eq!(statistics.by_subject["Sport"], 1);
+
+        // A subject in use is only hidden
+        let maths_id = db.get_subjects().await.unwrap().into_iter().find(|s| s.name == "Mathematik").unwrap().id;
+        db.delete_subject(maths_id).await.unwrap();
+        assert!(!db.get_subjects().await.unwrap().iter().any(|s| s.name == "Mathematik"));
+        assert_eq!(db.search_observations_in_subject(None, None, None, Some("Mathematik".to_string())).await.unwrap().len(), 2);
+        db.delete_subject(chemistry.id).await.unwrap();
+        assert!(db.delete_subject(chemistry.id).await.is_err());
     }
 
-    pub async fn delete_category(&self, id: i64, force_delete: bool) -> Result<()> {
-        if !force_delete {
-            // Check if category is used in observations
-            let usage_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM observations WHERE category = (SELECT name FROM categories WHERE id = ?)")
-                .bind(id)
-                .fetch_one(&self.pool)
-                .await?;
+    #[tokio::test]
+    async fn test_rubric_scores_are_validated_and_aggregated() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
 
-            if usage_count > 0 {
-                // Soft delete: mark as inactive
-                sqlx::query("UPDATE categories SET is_active = 0, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
-                    .bind(id)
-                    .execute(&self.pool)
-                    .await?;
-                return Ok(());
-            }
-        }
-        
-        // Hard delete: remove completely
-        sqlx::query("DELETE FROM categories WHERE id = ?")
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+        let criterion = |name: &str, levels: &[&str]| RubricCriterionInput {
+            id: None,
+            name: name.to_string(),
+            levels: levels.iter().map(|l| l.to_string()).collect(),
+        };
+        let mut input = RubricInput {
+            name: "Lesen".to_string(),
+            description: None,
+            criteria: vec![
+                criterion("Flüssigkeit", &["stockend", "teilweise flüssig", "flüssig", "betont"]),
+                criterion("Verständnis", &["kaum", "teilweise", "sicher"]),
+            ],
+        };
+        assert!(db
+            .create_rubric(&RubricInput { criteria: vec![criterion("Zu viel", &["1", "2", "3", "4", "5"])], ..input.clone() })
+            .await
+            .is_err());
+        let rubric = db.create_rubric(&input).await.unwrap();
+        assert_eq!(rubric.criteria.len(), 2);
+        assert_eq!(rubric.criteria[1].levels.len(), 3);
+        let (fluency, comprehension) = (rubric.criteria[0].id, rubric.criteria[1].id);
+
+        let first = db.create_observation(student.id, 1, "Fachlich".to_string(), "Liest vor".to_string(), vec![]).await.unwrap();
+        let second = db.create_observation(student.id, 1, "Fachlich".to_string(), "Liest still".to_string(), vec![]).await.unwrap();
+        let score = |criterion_id, level| RubricScore { criterion_id, level };
+        assert!(db.set_observation_rubric_scores(first.id, &[score(comprehension, 4)]).await.is_err());
+        assert!(db.set_observation_rubric_scores(first.id, &[score(fluency, 2), score(fluency, 3)]).await.is_err());
+        db.set_observation_rubric_scores(first.id, &[score(fluency, 2), score(comprehension, 3)]).await.unwrap();
+        db.set_observation_rubric_scores(second.id, &[score(fluency, 4)]).await.unwrap();
+        assert_eq!(db.get_observation_rubric_scores(second.id).await.unwrap(), vec![score(fluency, 4)]);
+
+        let filter = RubricAggregateFilter { student_id: Some(student.id), ..Default::default() };
+        let aggregates = db.get_rubric_aggregates(&filter, 1).await.unwrap();
+        assert_eq!(aggregates.len(), 2);
+        assert_eq!(aggregates[0].criterion_name, "Flüssigkeit");
+        assert_eq!(aggregates[0].scores, 2);
+        assert_eq!(aggregates[0].average_level, 3.0);
+        assert_eq!((aggregates[0].lowest_level, aggregates[0].highest_level), (2, 4));
+
+        // Scored criteria cannot be dropped, nor lose the levels they were scored with
+        input.criteria = vec![RubricCriterionInput { id: Some(fluency), ..input.criteria[0].clone() }];
+        assert!(db.update_rubric(rubric.rubric.id, &input).await.is_err());
+        input.criteria = vec![
+            RubricCriterionInput { id: Some(fluency), ..criterion("Flüssigkeit", &["stockend", "flüssig"]) },
+            RubricCriterionInput { id: Some(comprehension), ..criterion("Textverständnis", &["kaum", "teilweise", "sicher"]) },
+        ];
+        assert!(db.update_rubric(rubric.rubric.id, &input).await.is_err());
+        input.criteria[0] = RubricCriterionInput { id: Some(fluency), ..criterion("Flüssigkeit", &["a", "b", "c", "d"]) };
+        let updated = db.update_rubric(rubric.rubric.id, &input).await.unwrap();
+        assert_eq!(updated.criteria[1].name, "Textverständnis");
+        assert_eq!(updated.criteria[0].id, fluency);
+
+        // Scores go with their observation; a scored rubric is only hidden
+        db.delete_observation(second.id, 1, true).await.unwrap();
+        assert_eq!(db.get_rubric_aggregates(&filter, 1).await.unwrap()[0].scores, 1);
+        db.delete_rubric(rubric.rubric.id).await.unwrap();
+        assert!(db.get_rubrics().await.unwrap().is_empty());
+        assert_eq!(db.get_student_rubric_scores(student.id).await.unwrap().len(), 2);
     }
 
-    pub async fn clear_all_data(&self) -> Result<()> {
-        sqlx::query("DELETE FROM observations").execute(&self.pool).await?;
-        sqlx::query("DELETE FROM students").execute(&self.pool).await?;
-        sqlx::query("DELETE FROM classes").execute(&self.pool).await?;
-        sqlx::query("DELETE FROM categories").execute(&self.pool).await?;
-        Ok(())
+    #[tokio::test]
+    async fn test_competency_coverage_shows_gaps() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+
+        let competencies = db.get_competencies().await.unwrap();
+        assert_eq!(competencies.len(), 9);
+        let reading = competencies.iter().find(|c| c.name.starts_with("Lesen")).unwrap().id;
+        let arguing = competencies.iter().find(|c| c.name == "Argumentieren").unwrap().id;
+        let custom = db.create_competency("Sozial", " Konflikte lösen ", None).await.unwrap();
+        assert_eq!(custom.name, "Konflikte lösen");
+
+        let first = db.create_observation(max.id, 1, "Fachlich".to_string(), "Liest vor".to_string(), vec![]).await.unwrap();
+        let second = db.create_observation(max.id, 1, "Fachlich".to_string(), "Begründet".to_string(), vec![]).await.unwrap();
+        let other = db.create_observation(anna.id, 1, "Fachlich".to_string(), "Liest".to_string(), vec![]).await.unwrap();
+        assert!(db.set_observation_competencies(first.id, &[reading, 9999]).await.is_err());
+        db.set_observation_competencies(first.id, &[reading, arguing]).await.unwrap();
+        db.set_observation_competencies(second.id, &[arguing, custom.id]).await.unwrap();
+        db.set_observation_competencies(other.id, &[reading]).await.unwrap();
+        assert_eq!(db.get_observation_competencies(first.id).await.unwrap().len(), 2);
+
+        let coverage = db.get_competency_coverage(max.id, 1).await.unwrap();
+        assert_eq!(coverage.len(), 10);
+        let count = |id| coverage.iter().find(|c| c.competency_id == id).unwrap().observations;
+        assert_eq!((count(reading), count(arguing), count(custom.id)), (1, 2, 1));
+        assert_eq!(coverage.iter().filter(|c| c.observations == 0).count(), 7);
+
+        // A used competency is hidden from the picker but stays in the coverage
+        db.delete_competency(custom.id).await.unwrap();
+        assert_eq!(db.get_competencies().await.unwrap().len(), 9);
+        assert_eq!(db.get_competency_coverage(max.id, 1).await.unwrap().len(), 10);
+        assert_eq!(db.get_competency_coverage(anna.id, 1).await.unwrap().len(), 9);
+        assert_eq!(db.get_student_competency_links(max.id).await.unwrap().len(), 4);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
-    
-    async fn create_test_db() -> (Database, TempDir) {
-        let temp_dir = TempDir::new().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let crypto = Arc::new(CryptoManager::new().unwrap());
-        let db = Database::new(&db_path, crypto).await.unwrap();
-        (db, temp_dir)
+    #[tokio::test]
+    async fn test_metric_series_follows_observation_dates() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("2a".to_string(), "2024/25".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let entry = |text: &str, days_ago: i64| NewObservation {
+            student_id: student.id,
+            category: "Fachlich".to_string(),
+            text: text.to_string(),
+            tags: vec![],
+            created_at: Some(chrono::Utc::now() - chrono::Duration::days(days_ago)),
+            local_only: false,
+            visibility: None,
+            subject: None,
+            reported_by_user_id: None,
+            reported_by: None,
+        };
+        let created = db
+            .create_observations_batch(1, vec![entry("Lesetest Juni", 10), entry("Lesetest März", 90)])
+            .await
+            .unwrap();
+        let fluency = |value| ObservationMetric {
+            metric: "Lesegeschwindigkeit".to_string(),
+            value,
+            unit: Some("Wörter/min".to_string()),
+        };
+        let duplicate = [fluency(40.0), fluency(41.0)];
+        assert!(db.set_observation_metrics(created[0].id, &duplicate).await.is_err());
+        assert!(db.set_observation_metrics(created[0].id, &[fluency(f64::NAN)]).await.is_err());
+        db.set_observation_metrics(created[0].id, &[fluency(62.5)]).await.unwrap();
+        db.set_observation_metrics(created[1].id, &[fluency(48.0)]).await.unwrap();
+
+        let series = db.get_metric_series(student.id, "Lesegeschwindigkeit", 1).await.unwrap();
+        assert_eq!(series.iter().map(|p| p.value).collect::<Vec<_>>(), vec![48.0, 62.5]);
+        assert_eq!(db.get_metric_names(Some(student.id)).await.unwrap(), vec!["Lesegeschwindigkeit"]);
+
+        let csv = crate::reports::render_metric_series_csv(&series);
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(1).unwrap().ends_with(",48,Wörter/min"));
+
+        db.set_observation_metrics(created[0].id, &[]).await.unwrap();
+        assert_eq!(db.get_metric_series(student.id, "Lesegeschwindigkeit", 1).await.unwrap().len(), 1);
     }
 
     #[tokio::test]
-    async fn test_create_and_get_class() {
+    async fn test_quick_phrases_sort_by_use() {
         let (db, _temp_dir) = create_test_db().await;
 
-        // Create a class
-        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
-        assert_eq!(class.name, "5a");
-        assert_eq!(class.school_year, "2023/24");
+        let late = db.create_quick_phrase("Sozial", "kommt zu spät").await.unwrap();
+        let helps = db.create_quick_phrase("Sozial", " hilft Mitschülern ").await.unwrap();
+        db.create_quick_phrase("Fachlich", "arbeitet konzentriert").await.unwrap();
+        assert_eq!(helps.text, "hilft Mitschülern");
+        assert_eq!(db.create_quick_phrase("Sozial", "kommt zu spät").await.unwrap().id, late.id);
+        assert!(db.create_quick_phrase("Sozial", " ").await.is_err());
+
+        db.record_quick_phrase_use(helps.id).await.unwrap();
+        db.record_quick_phrase_use(helps.id).await.unwrap();
+        db.record_quick_phrase_use(late.id).await.unwrap();
+        let social = db.get_quick_phrases(Some("Sozial")).await.unwrap();
+        assert_eq!(social.iter().map(|p| p.usage_count).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(db.get_quick_phrases(None).await.unwrap().len(), 3);
+
+        assert!(db.update_quick_phrase(late.id, "hilft Mitschülern").await.is_err());
+        assert_eq!(db.update_quick_phrase(late.id, "kommt pünktlich").await.unwrap().usage_count, 1);
+        db.delete_quick_phrase(late.id).await.unwrap();
+        assert!(db.record_quick_phrase_use(late.id).await.is_err());
+    }
 
-        // Get all classes
-        let classes = db.get_classes().await.unwrap();
-        assert_eq!(classes.len(), 1);
-        assert_eq!(classes[0].name, "5a");
+    #[tokio::test]
+    async fn test_scanned_notes_wait_for_confirmed_students() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+
+        let note = |text: &str, student: Option<i64>| crate::ocr::ScannedNote {
+            file_path: "/scans/ausflug.png".to_string(),
+            text: text.to_string(),
+            date: chrono::NaiveDate::from_ymd_opt(2024, 9, 12),
+            proposed_student_id: student,
+            candidate_student_ids: student.into_iter().collect(),
+        };
+        let notes = [note("Baut eine Brücke", Some(max.id)), note("Sammelt Blätter", None)];
+        let staged = db.quarantine_scanned_notes("2 Scans", &notes, "Sozial").await.unwrap();
+        assert_eq!(staged.kind, "scanned_notes");
+        assert_eq!((staged.new_observations, staged.invalid_observations), (1, 1));
+        assert!(staged.problems.contains("could not be matched"));
+        assert!(db.search_observations(None, None, None).await.unwrap().is_empty());
+
+        // Promoted only with confirmed students, never as a changeset
+        assert!(db.promote_quarantined_import(staged.id, false, &CancellationToken::new()).await.is_err());
+        assert_eq!(db.get_scanned_notes(staged.id).await.unwrap()[1].text, "Sammelt Blätter");
+        let confirmed = [
+            ConfirmedScan { index: 0, student_id: max.id, category: "Sozial".to_string(), text: None },
+            ConfirmedScan { index: 1, student_id: anna.id, category: "Sozial".to_string(), text: Some("Sammelt bunte Blätter".to_string()) },
+        ];
+        let created = db.promote_scanned_notes(staged.id, 1, &confirmed).await.unwrap();
+        assert_eq!(created.len(), 2);
+        assert_eq!(created[1].text, "Sammelt bunte Blätter");
+        assert_eq!(created[0].created_at.date_naive(), chrono::NaiveDate::from_ymd_opt(2024, 9, 12).unwrap());
+        assert!(db.list_quarantined_imports().await.unwrap().is_empty());
     }
 
     #[tokio::test]
-    async fn test_create_and_get_student() {
+    async fn test_class_agreements_are_checked_by_date() {
         let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let date = |y, m, d| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let mut input = ClassAgreementInput {
+            kind: "photo".to_string(),
+            collected_on: date(2024, 9, 1),
+            valid_until: Some(date(2025, 7, 31)),
+            reference: Some("  Elternbriefe, Ordner 3 ".to_string()),
+            notes: Some(String::new()),
+        };
+        assert!(db.require_class_agreement(class.id, "photo").await.is_err());
+
+        let agreement = db.set_class_agreement(class.id, &input).await.unwrap();
+        assert_eq!(agreement.reference.as_deref(), Some("Elternbriefe, Ordner 3"));
+        assert_eq!(agreement.notes, None);
+        assert!(db.get_class_agreement_in_force(class.id, "photo", date(2024, 8, 31)).await.unwrap().is_none());
+        assert!(db.get_class_agreement_in_force(class.id, "photo", date(2025, 7, 31)).await.unwrap().is_some());
+        assert!(db.get_class_agreement_in_force(class.id, "video", date(2025, 1, 1)).await.unwrap().is_none());
+
+        // Renewed for the next school year: still one agreement
+        input.valid_until = Some(date(2026, 7, 31));
+        db.set_class_agreement(class.id, &input).await.unwrap();
+        assert_eq!(db.get_class_agreements(Some(class.id)).await.unwrap().len(), 1);
+
+        input.kind = "fotos".to_string();
+        assert!(db.set_class_agreement(class.id, &input).await.is_err());
+        input.kind = "audio".to_string();
+        input.valid_until = Some(date(2024, 1, 1));
+        assert!(db.set_class_agreement(class.id, &input).await.is_err());
+
+        db.delete_class_agreement(class.id, "photo").await.unwrap();
+        assert!(db.delete_class_agreement(class.id, "photo").await.is_err());
+        assert!(db.get_class_agreements(None).await.unwrap().is_empty());
+    }
 
-        // Create a class first
-        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+    #[tokio::test]
+    async fn test_observation_languages_follow_the_text() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
 
-        // Create a student
-        let student = db.create_student(
-            class.id,
-            "Max".to_string(),
-            "Mustermann".to_string(),
-            Some("active".to_string()),
-        ).await.unwrap();
+        let german = db
+            .create_observation(student.id, 1, "Sozial".to_string(), "Hilft heute den anderen und ist sehr geduldig".to_string(), vec![])
+            .await
+            .unwrap();
+        let english = db
+            .create_observation(student.id, 1, "Fachlich".to_string(), "Presented the results to the class very clearly".to_string(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(german.language.as_deref(), Some("de"));
+        assert_eq!(english.language.as_deref(), Some("en"));
 
-        assert_eq!(student.first_name, "Max");
-        assert_eq!(student.last_name, "Mustermann");
-        assert_eq!(student.class_id, class.id);
+        let in_english = db
+            .search_observations_in_language(None, Some(student.id), None, None, Some("en".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(in_english.iter().map(|o| o.id).collect::<Vec<_>>(), vec![english.id]);
 
-        // Get all students
-        let students = db.get_students().await.unwrap();
-        assert_eq!(students.len(), 1);
-        assert_eq!(students[0].first_name, "Max");
+        // Any change of the text, e.g. by sync, needs a new detection
+        sqlx::query("UPDATE observations SET text = 'Il a aidé la classe avec le projet' WHERE id = ?")
+            .bind(english.id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(db.get_observation(english.id).await.unwrap().unwrap().language, None);
+        assert_eq!(db.refresh_observation_languages().await.unwrap(), 1);
+        assert_eq!(db.get_observation(english.id).await.unwrap().unwrap().language.as_deref(), Some("fr"));
+
+        let corrected = db.set_observation_language(german.id, "und").await.unwrap();
+        assert_eq!(corrected.language.as_deref(), Some("und"));
+        assert_eq!(db.refresh_observation_languages().await.unwrap(), 0);
+        assert!(db.set_observation_language(german.id, "deutsch").await.is_err());
     }
 
     #[tokio::test]
-    async fn test_soft_delete_student() {
+    async fn test_merge_categories_moves_everything_to_the_target() {
         let (db, _temp_dir) = create_test_db().await;
-
-        // Create class and student
-        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let class = db.create_class("4a".to_string(), "2024/25".to_string()).await.unwrap();
         let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let source = db.get_categories().await.unwrap().into_iter().find(|c| c.name == "Verhalten").unwrap();
+        let target = db
+            .create_category(
+                "Sozialverhalten".to_string(),
+                "#10B981".to_string(),
+                "#D1FAE5".to_string(),
+                "#065F46".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+        for text in ["Hilft beim Aufräumen", "Wartet ab"] {
+            db.create_observation(student.id, 1, "Verhalten".to_string(), text.to_string(), vec![])
+                .await
+                .unwrap();
+        }
+        let colleague = db.create_user("Frau Weber".to_string(), "teacher".to_string()).await.unwrap();
+        let cosigned = db
+            .create_observation(student.id, 1, "Verhalten".to_string(), "Gespräch mit den Eltern".to_string(), vec![])
+            .await
+            .unwrap();
+        db.cosign_observation(cosigned.id, colleague.id).await.unwrap();
+        db.create_quick_phrase("Verhalten", "meldet sich").await.unwrap();
+        let clock_before = db.current_logical_clock().await.unwrap();
+
+        let merge = db.merge_categories(source.id, target.id).await.unwrap();
+        assert_eq!((merge.observations, merge.templates, merge.quick_phrases), (2, 0, 1));
+        assert_eq!(merge.skipped_observations, vec![cosigned.id]);
+        let observations = db.search_observations(None, Some(student.id), None).await.unwrap();
+        assert!(observations.iter().filter(|o| o.id != cosigned.id).all(|o| o.category == "Sozialverhalten"));
+        assert_eq!(db.get_observation(cosigned.id).await.unwrap().unwrap().category, "Verhalten");
+        // Peers receive the reassignment as updates
+        assert!(db.current_logical_clock().await.unwrap() >= clock_before + 4);
+
+        assert!(!db.get_categories().await.unwrap().iter().any(|c| c.id == source.id));
+        let merged = db.get_all_categories().await.unwrap().into_iter().find(|c| c.id == source.id).unwrap();
+        assert_eq!(merged.merged_into, Some(target.id));
+        assert_eq!(db.get_category_merges().await.unwrap()["Verhalten"], "Sozialverhalten");
+
+        assert!(db.merge_categories(target.id, target.id).await.is_err());
+        assert!(db.merge_categories(target.id, source.id).await.is_err());
+    }
 
-        // Soft delete student
-        db.delete_student(student.id, false).await.unwrap();
+    #[tokio::test]
+    async fn test_observations_keep_who_reported_them() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("6b".to_string(), "2024/25".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let colleague = db.create_user("Frau Weber".to_string(), "teacher".to_string()).await.unwrap();
+        let entry = |reported_by_user_id, reported_by: Option<&str>| NewObservation {
+            student_id: student.id,
+            category: "Sozial".to_string(),
+            text: "Hat in der Pause geschlichtet".to_string(),
+            tags: vec![],
+            created_at: None,
+            local_only: false,
+            visibility: None,
+            subject: None,
+            reported_by_user_id,
+            reported_by: reported_by.map(str::to_string),
+        };
+
+        let created = db
+            .create_observations_batch(1, vec![entry(Some(colleague.id), None), entry(None, Some(" Schulbegleitung "))])
+            .await
+            .unwrap();
+        assert_eq!(created[0].author_id, 1);
+        assert_eq!(created[0].reported_by_user_id, Some(colleague.id));
+        assert_eq!(created[1].reported_by.as_deref(), Some("Schulbegleitung"));
+        let users = db.get_users().await.unwrap().into_iter().map(|u| (u.id, u.name)).collect();
+        assert_eq!(created[0].reporter(&users).as_deref(), Some("Frau Weber"));
+
+        assert!(db.create_observations_batch(1, vec![entry(Some(colleague.id), Some("Frau Weber"))]).await.is_err());
+        assert!(db.create_observations_batch(1, vec![entry(Some(1), None)]).await.is_err());
+        assert!(db.create_observations_batch(1, vec![entry(Some(999), None)]).await.is_err());
+        assert!(db.create_observations_batch(1, vec![entry(None, Some("  "))]).await.is_err());
+    }
 
-        // Student should not appear in regular queries
-        let students = db.get_students().await.unwrap();
-        assert_eq!(students.len(), 0);
+    #[tokio::test]
+    async fn test_class_comparison_hides_small_groups() {
+        let (db, _temp_dir) = create_test_db().await;
+        let earlier = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let later = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let mut students = Vec::new();
+        for (class_id, name) in [(earlier.id, "Anna"), (earlier.id, "Ben"), (earlier.id, "Cem"), (later.id, "Dana"), (later.id, "Emil")] {
+            students.push(db.create_student(class_id, name.to_string(), "Muster".to_string(), None).await.unwrap());
+        }
+        for student in &students {
+            db.create_observation(student.id, 1, "Sozial".to_string(), "Hilft".to_string(), vec![])
+                .await
+                .unwrap();
+        }
+        db.create_observation(students[0].id, 1, "Fachlich".to_string(), "Liest vor".to_string(), vec![])
+            .await
+            .unwrap();
+
+        let comparison = db.compare_class_statistics(&[earlier.id, later.id], 1).await.unwrap();
+        assert_eq!(comparison.categories, vec!["Fachlich", "Sozial"]);
+        let (first, second) = (&comparison.classes[0], &comparison.classes[1]);
+        // One student only in Fachlich, so Sozial is hidden with it
+        assert_eq!((first.students, first.observations), (3, Some(4)));
+        assert_eq!(first.by_category["Fachlich"].observations, None);
+        assert_eq!(first.by_category["Sozial"].observations, None);
+        // Two in the later class, whose total would tell Sozial
+        assert_eq!((second.students, second.observations), (2, None));
+        assert_eq!(second.by_category["Sozial"].observations, None);
+        assert_eq!(second.by_category["Fachlich"].observations, Some(0));
+
+        // Shown where enough students were observed in every category
+        for student in &students[1..3] {
+            db.create_observation(student.id, 1, "Fachlich".to_string(), "Rechnet".to_string(), vec![])
+                .await
+                .unwrap();
+        }
+        let comparison = db.compare_class_statistics(&[earlier.id], 1).await.unwrap();
+        let first = &comparison.classes[0];
+        assert_eq!(first.observations, Some(6));
+        assert_eq!(first.by_category["Sozial"].observations, Some(3));
+        assert_eq!(first.by_category["Sozial"].percent, Some(50.0));
+
+        assert!(db.compare_class_statistics(&[], 1).await.is_err());
+        assert!(db.compare_class_statistics(&[999], 1).await.is_err());
     }
 
     #[tokio::test]
-    async fn test_hard_delete_student() {
+    async fn test_students_who_left_become_inactive_on_their_last_day() {
         let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let moved = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let leaving = db.create_student(class.id, "Anna".to_string(), "Albrecht".to_string(), None).await.unwrap();
+        let today = chrono::Utc::now().date_naive();
+
+        let moved = db.mark_student_left(moved.id, today).await.unwrap();
+        assert_eq!((moved.status.as_str(), moved.left_at), ("inactive", Some(today)));
+
+        let last_day = today + chrono::Duration::days(14);
+        let leaving = db.mark_student_left(leaving.id, last_day).await.unwrap();
+        assert_eq!((leaving.status.as_str(), leaving.left_at), ("active", Some(last_day)));
+        assert!(db.deactivate_students_who_left(last_day - chrono::Duration::days(1)).await.unwrap().is_empty());
+        let deactivated = db.deactivate_students_who_left(last_day).await.unwrap();
+        assert_eq!(deactivated.iter().map(|s| s.id).collect::<Vec<_>>(), vec![leaving.id]);
+        // Still there, only no longer active
+        assert_eq!(db.get_students().await.unwrap().len(), 2);
+
+        assert!(db.mark_student_left(leaving.id, today - chrono::Duration::days(1)).await.is_err());
+        db.delete_student(leaving.id, false).await.unwrap();
+        assert!(db.mark_student_left(leaving.id, last_day).await.is_err());
+    }
 
-        // Create class and student
-        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+    #[tokio::test]
+    async fn test_update_observation_changes_only_what_is_given() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
         let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let observation = db
+            .create_observation(student.id, 1, "Sozial".to_string(), "Hilft".to_string(), vec!["Pause".to_string()])
+            .await
+            .unwrap();
+
+        let edit = ObservationEdit {
+            text: Some("Max hat heute in der Pause sehr gut geholfen.".to_string()),
+            ..Default::default()
+        };
+        let (before, after) = db.update_observation(observation.id, 1, edit).await.unwrap();
+        assert_eq!(before.text, "Hilft");
+        assert_eq!(after.text, "Max hat heute in der Pause sehr gut geholfen.");
+        assert_eq!(after.language.as_deref(), Some("de"));
+        assert_eq!((after.category.as_str(), after.tags.as_str()), ("Sozial", "[\"Pause\"]"));
+        assert!(after.logical_clock > before.logical_clock);
+
+        let edit = ObservationEdit {
+            category: Some("Fachlich".to_string()),
+            tags: Some(vec![]),
+            ..Default::default()
+        };
+        let (_, after) = db.update_observation(observation.id, 1, edit).await.unwrap();
+        assert_eq!((after.category.as_str(), after.tags.as_str()), ("Fachlich", "[]"));
+
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+        let edit = || ObservationEdit { text: Some("Anders".to_string()), ..Default::default() };
+        assert!(db.update_observation(observation.id, teacher.id, edit()).await.is_err());
+        let empty = ObservationEdit { text: Some("  ".to_string()), ..Default::default() };
+        assert!(db.update_observation(observation.id, 1, empty).await.is_err());
+        assert!(db.update_observation(999, 1, edit()).await.is_err());
+    }
 
-        // Create observation for student
-        let _observation = db.create_observation(
-            student.id,
-            1,
-            "social".to_string(),
-            "Test observation".to_string(),
-            vec!["test".to_string()],
-        ).await.unwrap();
+    #[tokio::test]
+    async fn test_update_student_corrects_the_record() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let other = db.create_class("5b".to_string(), "2024/25".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Maks".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let edit = StudentEdit {
+            first_name: Some(" Max ".to_string()),
+            class_id: Some(other.id),
+            ..Default::default()
+        };
+        let (before, after) = db.update_student(student.id, edit).await.unwrap();
+        assert_eq!(before.first_name, "Maks");
+        assert_eq!((after.first_name.as_str(), after.last_name.as_str()), ("Max", "Mustermann"));
+        assert_eq!(after.class_id, other.id);
+        // A correction, not a transfer
+        assert!(db.get_student_transfers(student.id).await.unwrap().is_empty());
+
+        let blank = StudentEdit {
+            last_name: Some("  ".to_string()),
+            ..Default::default()
+        };
+        assert!(db.update_student(student.id, blank).await.is_err());
+        let deleted = StudentEdit {
+            status: Some("deleted".to_string()),
+            ..Default::default()
+        };
+        assert!(db.update_student(student.id, deleted).await.is_err());
+        let unknown_class = StudentEdit {
+            class_id: Some(999),
+            ..Default::default()
+        };
+        assert!(db.update_student(student.id, unknown_class).await.is_err());
+
+        // Marked as left by mistake
+        let today = chrono::Utc::now().date_naive();
+        db.mark_student_left(student.id, today).await.unwrap();
+        let back = StudentEdit {
+            status: Some("active".to_string()),
+            ..Default::default()
+        };
+        let (_, after) = db.update_student(student.id, back).await.unwrap();
+        assert_eq!((after.status.as_str(), after.left_at), ("active", None));
+        assert!(db.deactivate_students_who_left(today).await.unwrap().is_empty());
 
-        // Hard delete student
-        db.delete_student(student.id, true).await.unwrap();
+        db.delete_student(student.id, false).await.unwrap();
+        assert!(db.update_student(student.id, StudentEdit::default()).await.is_err());
+    }
 
-        // Student should be completely gone
-        let students = db.get_students().await.unwrap();
-        assert_eq!(students.len(), 0);
+    #[tokio::test]
+    async fn test_student_details_are_checked_and_synced() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+        let class = notebook.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let details = StudentDetails {
+            date_of_birth: chrono::NaiveDate::from_ymd_opt(2014, 3, 9),
+            notes: Some(" Brille, sitzt vorne ".to_string()),
+            external_id: Some("ASV-10231".to_string()),
+        };
+        let max = notebook
+            .create_student_with_details(class.id, "Max".to_string(), "Mustermann".to_string(), None, details.clone())
+            .await
+            .unwrap();
+        assert_eq!(max.notes.as_deref(), Some("Brille, sitzt vorne"));
+        assert_eq!(max.external_id.as_deref(), Some("ASV-10231"));
 
-        // Observations should also be gone
-        let observations = db.search_observations(None, None, None).await.unwrap();
-        assert_eq!(observations.len(), 0);
+        // The external id identifies one student
+        assert!(notebook
+            .create_student_with_details(class.id, "Anna".to_string(), "Albrecht".to_string(), None, details)
+            .await
+            .is_err());
+        let tomorrow = chrono::Utc::now().date_naive() + chrono::Duration::days(1);
+        let unborn = StudentDetails {
+            date_of_birth: Some(tomorrow),
+            ..Default::default()
+        };
+        assert!(notebook
+            .create_student_with_details(class.id, "Anna".to_string(), "Albrecht".to_string(), None, unborn)
+            .await
+            .is_err());
+        let invalid = StudentEdit {
+            date_of_birth: Some("09.03.2014".to_string()),
+            ..Default::default()
+        };
+        assert!(notebook.update_student(max.id, invalid).await.is_err());
+
+        let backup = serde_json::json!({ "data": {
+            "classes": [&class], "students": [&max], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+        assert_eq!(computer.get_student(max.id).await.unwrap().unwrap().external_id.as_deref(), Some("ASV-10231"));
+
+        let edit = StudentEdit {
+            date_of_birth: Some("2014-03-19".to_string()),
+            notes: Some(String::new()),
+            ..Default::default()
+        };
+        let (_, after) = notebook.update_student(max.id, edit).await.unwrap();
+        assert_eq!(after.date_of_birth, chrono::NaiveDate::from_ymd_opt(2014, 3, 19));
+        assert_eq!((after.notes, after.external_id.as_deref()), (None, Some("ASV-10231")));
+
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        assert!(result.contains("1 student records updated"), "{}", result);
+        let synced = computer.get_student(max.id).await.unwrap().unwrap();
+        assert_eq!(synced.date_of_birth, chrono::NaiveDate::from_ymd_opt(2014, 3, 19));
+        assert_eq!(synced.notes, None);
+
+        // A student only the computer has may carry the id of another notebook student
+        let erika = notebook.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        let paul = computer.create_student(class.id, "Paul".to_string(), "Beispiel".to_string(), None).await.unwrap();
+        assert_eq!(erika.id, paul.id);
+        let notes = StudentEdit {
+            notes: Some("Nachteilsausgleich".to_string()),
+            ..Default::default()
+        };
+        notebook.update_student(erika.id, notes).await.unwrap();
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        computer.apply_changeset_file(&changeset).await.unwrap();
+        assert_eq!(computer.get_student(paul.id).await.unwrap().unwrap().notes, None);
     }
 
     #[tokio::test]
-    async fn test_create_and_search_observations() {
+    async fn test_quarantined_changesets_apply_only_when_promoted() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let erika = notebook.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        let backup = serde_json::json!({ "data": {
+            "classes": [&class], "students": [&max], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+
+        let observation = notebook
+            .create_observation(max.id, 1, "Sozial".to_string(), "Hilft anderen".to_string(), vec![])
+            .await
+            .unwrap();
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+
+        let staged = computer.quarantine_changeset("/media/usb/5a.sbchange", &changeset).await.unwrap();
+        assert_eq!(staged.new_observations, 1);
+        assert_eq!(staged.invalid_observations, 0);
+        assert_eq!(staged.problems, "[]");
+        assert!(computer.get_observation(observation.id).await.unwrap().is_none());
+        assert_eq!(computer.list_quarantined_imports().await.unwrap().len(), 1);
+
+        let result = computer.promote_quarantined_import(staged.id, false, &CancellationToken::new()).await.unwrap();
+        assert!(result.contains("Successfully imported 1 observations"));
+        assert!(computer.get_observation(observation.id).await.unwrap().is_some());
+        assert!(computer.list_quarantined_imports().await.unwrap().is_empty());
+
+        // The same file again is recognised in quarantine already
+        let again = computer.quarantine_changeset("/media/usb/5a.sbchange", &changeset).await.unwrap();
+        assert_eq!(again.unchanged_observations, 1);
+        assert!(again.problems.contains("already applied"));
+        computer.discard_quarantined_import(again.id).await.unwrap();
+        assert!(computer.discard_quarantined_import(again.id).await.is_err());
+
+        // A student the computer does not know makes the file fail on promotion
+        notebook
+            .create_observation(erika.id, 1, "Sozial".to_string(), "Arbeitet konzentriert".to_string(), vec![])
+            .await
+            .unwrap();
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        let invalid = computer.quarantine_changeset("/media/usb/5a.sbchange", &changeset).await.unwrap();
+        assert_eq!(invalid.invalid_observations, 1);
+        assert_eq!(invalid.unchanged_observations, 1);
+        assert!(invalid.problems.contains("unknown on this device"));
+        assert!(computer.promote_quarantined_import(invalid.id, false, &CancellationToken::new()).await.is_err());
+        assert_eq!(computer.list_quarantined_imports().await.unwrap()[0].id, invalid.id);
+    }
+
+    #[tokio::test]
+    async fn test_staged_imports_are_encrypted_and_go_with_the_student() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let erika = notebook.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        let backup = serde_json::json!({ "data": {
+            "classes": [&class], "students": [&max, &erika], "observations": []
+        }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+
+        let observation = notebook
+            .create_observation(max.id, 1, "Sozial".to_string(), "Hilft anderen".to_string(), vec![])
+            .await
+            .unwrap();
+        let staged = computer
+            .quarantine_changeset("/media/usb/5a.sbchange", &notebook.create_changeset_file(30).await.unwrap())
+            .await
+            .unwrap();
+        let (payload, text) = sqlx::query_as::<_, (Vec<u8>, String)>(
+            "SELECT q.payload, o.text FROM quarantined_imports q JOIN quarantined_observations o ON o.import_id = q.id",
+        )
+        .fetch_one(&computer.pool)
+        .await
+        .unwrap();
+        assert!(!String::from_utf8_lossy(&payload).contains("Hilft anderen"));
+        assert!(!text.contains("Hilft anderen"));
+
+        // The same entry under another id is still recognised
+        sqlx::query("INSERT INTO observations (student_id, author_id, category, text, tags, created_at, source_device_id) VALUES (?, 1, 'Sozial', 'Hilft anderen', '[]', ?, 'usb')")
+            .bind(max.id)
+            .bind(observation.created_at)
+            .execute(&computer.pool)
+            .await
+            .unwrap();
+        computer.discard_quarantined_import(staged.id).await.unwrap();
+        let staged = computer
+            .quarantine_changeset("/media/usb/5a.sbchange", &notebook.create_changeset_file(30).await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(staged.duplicate_observations, 1);
+
+        let rows = crate::clipboard_import::prepare_rows(
+            "Max;Sozial;Störte\nErika;Sozial;Fehlt",
+            &computer.get_students_by_class(class.id).await.unwrap(),
+            &computer.get_categories().await.unwrap(),
+            "Sonstiges",
+        )
+        .unwrap();
+        let copied = computer.quarantine_clipboard_rows(&rows).await.unwrap();
+
+        // Deleting Max takes the changeset with his observation and the copied lines naming him
+        computer.delete_student(max.id, true).await.unwrap();
+        assert!(computer.get_quarantined_import(staged.id).await.unwrap().is_none());
+        assert!(computer.get_quarantined_import(copied.id).await.unwrap().is_none());
+
+        let copied = computer.quarantine_clipboard_rows(&rows[1..]).await.unwrap();
+        assert_eq!(computer.purge_staged_imports_of_student(max.id).await.unwrap(), 0);
+        computer.delete_student_observations(erika.id).await.unwrap();
+        assert!(computer.list_quarantined_imports().await.unwrap().iter().all(|i| i.id != copied.id));
+    }
+
+    #[tokio::test]
+    async fn test_copied_lines_go_only_to_matched_students() {
         let (db, _temp_dir) = create_test_db().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let erika = db.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        let rows = crate::clipboard_import::prepare_rows(
+            "Max;Sozial;Störte",
+            &db.get_students_by_class(class.id).await.unwrap(),
+            &db.get_categories().await.unwrap(),
+            "Sonstiges",
+        )
+        .unwrap();
+        let staged = db.quarantine_clipboard_rows(&rows).await.unwrap();
+        let confirm = |student_id| ConfirmedScan { index: 0, student_id, category: "Sozial".to_string(), text: None };
 
-        // Setup class and student
+        assert!(db.promote_clipboard_rows(staged.id, 1, &[confirm(erika.id)]).await.is_err());
+        let created = db.promote_clipboard_rows(staged.id, 1, &[confirm(max.id)]).await.unwrap();
+        assert_eq!((created[0].student_id, created[0].text.as_str()), (max.id, "Störte"));
+    }
+
+    #[tokio::test]
+    async fn test_jobs_keep_their_history_across_restarts() {
+        let (db, _temp_dir) = create_test_db().await;
+
+        let params = serde_json::json!({ "file_path": "/media/usb/5a.sbchange", "days_back": 30 });
+        let exported = db.create_job("export_changeset", &params, None).await.unwrap();
+        db.finish_job(exported, "completed", Some("/media/usb/5a.sbchange"), None).await.unwrap();
+        let failed = db.create_job("import_full_backup", &serde_json::json!({}), Some("import-1")).await.unwrap();
+        db.finish_job(failed, "failed", None, Some("Failed to read backup")).await.unwrap();
+        let running = db.create_job("export_all_data", &serde_json::json!({}), None).await.unwrap();
+        db.job_progress_recorder(running).record(0.4).await.unwrap();
+        db.job_progress_recorder(failed).record(0.9).await.unwrap();
+
+        // The app closed while the last job was running
+        assert_eq!(db.interrupt_running_jobs().await.unwrap(), 1);
+        assert_eq!(db.interrupt_running_jobs().await.unwrap(), 0);
+
+        let jobs = db.get_jobs(10).await.unwrap();
+        assert_eq!(jobs.iter().map(|j| j.id).collect::<Vec<_>>(), vec![running, failed, exported]);
+        assert_eq!(jobs[0].status, "interrupted");
+        assert_eq!((jobs[0].progress, jobs[1].progress), (0.4, 0.0));
+        assert!(jobs[0].finished_at.is_some());
+        assert_eq!(jobs[1].error.as_deref(), Some("Failed to read backup"));
+        assert_eq!(jobs[1].operation_id.as_deref(), Some("import-1"));
+        assert_eq!(jobs[2].progress, 1.0);
+        assert_eq!(jobs[2].result_path.as_deref(), Some("/media/usb/5a.sbchange"));
+        let stored: serde_json::Value = serde_json::from_str(&jobs[2].params).unwrap();
+        assert_eq!(stored, params);
+        assert!(db.get_job(9999).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_local_only_observations_stay_out_of_changesets() {
+        let (db, _temp_dir) = create_test_db().await;
         let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
         let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
 
-        // Create observation
-        let observation = db.create_observation(
-            student.id,
-            1,
-            "social".to_string(),
-            "Shows excellent teamwork skills".to_string(),
-            vec!["teamwork".to_string(), "social".to_string()],
-        ).await.unwrap();
+        let entry = |text: &str, local_only| NewObservation {
+            student_id: student.id,
+            category: "Sozial".to_string(),
+            text: text.to_string(),
+            tags: vec![],
+            created_at: None,
+            local_only,
+            visibility: None,
+            subject: None,
+            reported_by_user_id: None,
+            reported_by: None,
+        };
+        let created = db
+            .create_observations_batch(1, vec![entry("Geteilt", false), entry("Nur für mich", true)])
+            .await
+            .unwrap();
 
-        assert_eq!(observation.student_id, student.id);
-        assert_eq!(observation.category, "social");
-        assert!(observation.text.contains("teamwork"));
+        let changeset = String::from_utf8(db.create_changeset_file(30).await.unwrap()).unwrap();
+        assert!(changeset.contains("Geteilt"));
+        assert!(!changeset.contains("Nur für mich"));
 
-        // Search observations
-        let observations = db.search_observations(None, None, None).await.unwrap();
-        assert_eq!(observations.len(), 1);
+        db.set_observation_local_only(created[1].id, false).await.unwrap();
+        let changeset = String::from_utf8(db.create_changeset_file(30).await.unwrap()).unwrap();
+        assert!(changeset.contains("Nur für mich"));
+        assert!(db.set_observation_local_only(9999, true).await.is_err());
+    }
 
-        // Search by student ID
-        let student_observations = db.search_observations(None, Some(student.id), None).await.unwrap();
-        assert_eq!(student_observations.len(), 1);
+    #[tokio::test]
+    async fn test_only_admins_change_roles() {
+        let (db, _temp_dir) = create_test_db().await;
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
 
-        // Search by text query
-        let text_search = db.search_observations(Some("teamwork".to_string()), None, None).await.unwrap();
-        assert_eq!(text_search.len(), 1);
+        assert!(db.set_user_role(teacher.id, "admin", teacher.id).await.is_err());
+        assert!(db.set_user_role(teacher.id, "principal", 1).await.is_err());
+        assert!(db.set_user_role(1, "teacher", 1).await.is_err()); // The only admin
+        let (before, after) = db.set_user_role(teacher.id, "admin", 1).await.unwrap();
+        assert_eq!((before.role.as_str(), after.role.as_str()), ("teacher", "admin"));
 
-        // Search by category
-        let category_search = db.search_observations(None, None, Some("social".to_string())).await.unwrap();
-        assert_eq!(category_search.len(), 1);
+        db.set_user_role(1, "teacher", teacher.id).await.unwrap();
+        assert!(db.set_user_role(teacher.id, "teacher", teacher.id).await.is_err());
+        assert_eq!(db.get_user(teacher.id).await.unwrap().unwrap().role, "admin");
     }
 
     #[tokio::test]
-    async fn test_delete_observation_permissions() {
+    async fn test_default_visibility_applies_per_user() {
         let (db, _temp_dir) = create_test_db().await;
-
-        // Setup
         let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
         let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
-        let observation = db.create_observation(
-            student.id,
-            1, // Author ID 1
-            "social".to_string(),
-            "Test observation".to_string(),
-            vec![],
-        ).await.unwrap();
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+
+        assert!(db.set_default_visibility(teacher.id, "secret").await.is_err());
+        assert!(db.set_default_visibility(9999, VISIBILITY_PRIVATE).await.is_err());
+        db.set_default_visibility(teacher.id, VISIBILITY_PRIVATE).await.unwrap();
+
+        let private = db.create_observation(student.id, teacher.id, "Sozial".to_string(), "Eigene Notiz".to_string(), vec![]).await.unwrap();
+        let shared = db.create_observation(student.id, 1, "Sozial".to_string(), "Für alle".to_string(), vec![]).await.unwrap();
+        assert_eq!(private.visibility, VISIBILITY_PRIVATE);
+        assert_eq!(shared.visibility, VISIBILITY_TEAM);
+        assert!(private.is_visible_to(teacher.id));
+        assert!(!private.is_visible_to(1));
+        assert!(shared.is_visible_to(teacher.id));
+
+        // Only the author may share it
+        assert!(db.set_observation_visibility(private.id, 1, VISIBILITY_TEAM).await.is_err());
+        db.set_observation_visibility(private.id, teacher.id, VISIBILITY_TEAM).await.unwrap();
+        assert!(db.get_observation(private.id).await.unwrap().unwrap().is_visible_to(1));
+    }
 
-        // Author should be able to delete their observation
-        let result = db.delete_observation(observation.id, 1, false).await;
-        assert!(result.is_ok());
+    #[tokio::test]
+    async fn test_list_students_sorts_by_last_observation() {
+        let (db, _temp_dir) = create_test_db().await;
+        let class_b = db.create_class("5b".to_string(), "2023/24".to_string()).await.unwrap();
+        let class_a = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let anna = db.create_student(class_b.id, "Anna".to_string(), "Albrecht".to_string(), None).await.unwrap();
+        let max = db.create_student(class_a.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let erika = db.create_student(class_b.id, "Erika".to_string(), "Musterfrau".to_string(), Some("inactive".to_string())).await.unwrap();
+
+        db.create_observation(erika.id, 1, "Sozial".to_string(), "Älter".to_string(), vec![]).await.unwrap();
+        sqlx::query("UPDATE observations SET created_at = datetime('now', '-3 days')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Neu".to_string(), vec![]).await.unwrap();
 
-        // Recreate observation for next test
-        let observation2 = db.create_observation(
-            student.id,
-            1, // Author ID 1
-            "social".to_string(),
-            "Test observation 2".to_string(),
-            vec![],
-        ).await.unwrap();
+        let ids = |students: Vec<Student>| students.iter().map(|s| s.id).collect::<Vec<_>>();
+        let by_name = db.list_students(&StudentListOptions::default(), 1).await.unwrap();
+        assert_eq!(ids(by_name), vec![anna.id, erika.id, max.id]);
 
-        // Different user should not be able to delete
-        let result = db.delete_observation(observation2.id, 2, false).await; // Different author ID
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("Permission denied"));
+        let options = StudentListOptions { sort: StudentSort::LastObservation, ..Default::default() };
+        let by_observation = db.list_students(&options, 1).await.unwrap();
+        assert!(by_observation[0].last_observation_at.unwrap() > by_observation[1].last_observation_at.unwrap());
+        assert_eq!(by_observation[2].last_observation_at, None);
+        assert_eq!(ids(by_observation), vec![max.id, erika.id, anna.id]);
 
-        // Force delete should work regardless of author
-        let result = db.delete_observation(observation2.id, 2, true).await;
-        assert!(result.is_ok());
+        let grouped = StudentListOptions { group_by_class: true, ..Default::default() };
+        assert_eq!(ids(db.list_students(&grouped, 1).await.unwrap()), vec![max.id, anna.id, erika.id]);
+
+        let inactive = StudentListOptions { status: Some("inactive".to_string()), ..Default::default() };
+        assert_eq!(ids(db.list_students(&inactive, 1).await.unwrap()), vec![erika.id]);
     }
 
     #[tokio::test]
-    async fn test_get_observations_since() {
+    async fn test_sidebar_badges_count_new_visible_observations() {
         let (db, _temp_dir) = create_test_db().await;
-
-        // Setup
         let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
-        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
-
-        // Create observation
-        let _observation = db.create_observation(
-            student.id,
-            1,
-            "social".to_string(),
-            "Recent observation".to_string(),
-            vec![],
-        ).await.unwrap();
-
-        // Get observations from 1 hour ago
-        let one_hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
-        let recent_observations = db.get_observations_since(one_hour_ago).await.unwrap();
-        assert_eq!(recent_observations.len(), 1);
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let erika = db.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
 
-        // Get observations from 1 hour in the future (should be empty)
-        let future_time = chrono::Utc::now() + chrono::Duration::hours(1);
-        let future_observations = db.get_observations_since(future_time).await.unwrap();
-        assert_eq!(future_observations.len(), 0);
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Alt".to_string(), vec![]).await.unwrap();
+        sqlx::query("UPDATE observations SET created_at = datetime('now', '-2 days')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let since = chrono::Utc::now() - chrono::Duration::days(1);
+
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Neu".to_string(), vec![]).await.unwrap();
+        db.create_observation(erika.id, 1, "Fachlich".to_string(), "Neu".to_string(), vec![]).await.unwrap();
+        db.set_default_visibility(teacher.id, VISIBILITY_PRIVATE).await.unwrap();
+        db.create_observation(erika.id, teacher.id, "Sozial".to_string(), "Privat".to_string(), vec![]).await.unwrap();
+
+        let badges = db.get_sidebar_badges(since, 1).await.unwrap();
+        assert_eq!(badges.new_by_student.get(&max.id), Some(&1));
+        assert_eq!(badges.new_by_student.get(&erika.id), Some(&1));
+        assert_eq!(badges.new_by_class.get(&class.id), Some(&2));
+        // Nothing exported yet, so every change is pending
+        assert_eq!(badges.pending_sync_changes, db.get_sync_status(30).await.unwrap().pending_changes as i64);
+        assert_eq!(badges.open_erasure_requests, 0);
+
+        let badges = db.get_sidebar_badges(since, teacher.id).await.unwrap();
+        assert_eq!(badges.new_by_class.get(&class.id), Some(&3));
     }
 
     #[tokio::test]
-    async fn test_changeset_file_operations() {
+    async fn test_deleting_exported_observation_records_retraction() {
         let (db, _temp_dir) = create_test_db().await;
-
-        // Setup test data
         let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
         let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
-        let _observation = db.create_observation(
-            student.id,
-            1,
-            "social".to_string(),
-            "Test for changeset".to_string(),
-            vec!["test".to_string()],
-        ).await.unwrap();
-
-        // Create changeset file
-        let changeset_data = db.create_changeset_file(30).await.unwrap();
-        assert!(!changeset_data.is_empty());
+        let exported = db.create_observation(student.id, 1, "Sozial".to_string(), "Exportiert".to_string(), vec![]).await.unwrap();
+        let unexported = db.create_observation(student.id, 1, "Sozial".to_string(), "Entwurf".to_string(), vec![]).await.unwrap();
+        db.set_observation_local_only(unexported.id, true).await.unwrap();
+
+        let first: serde_json::Value = serde_json::from_slice(&db.create_changeset_file(30).await.unwrap()).unwrap();
+        let operation_id = first["data"]["operation_id"].as_str().unwrap().to_string();
+        db.delete_observation(exported.id, 1, false).await.unwrap();
+        db.delete_observation(unexported.id, 1, false).await.unwrap();
+
+        // Only what actually left the device needs a retraction
+        let pending = db.get_retractions(true).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].observation_id, exported.id);
+        assert_eq!(pending[0].reason, "deleted");
+        assert!(pending[0].exported_in.contains(&operation_id));
+        assert!(!pending[0].label.contains("Exportiert"));
+
+        let second: serde_json::Value = serde_json::from_slice(&db.create_changeset_file(30).await.unwrap()).unwrap();
+        let carried = second["data"]["changes"]["retractions"].as_array().unwrap();
+        assert_eq!(carried.len(), 1);
+        assert_eq!(carried[0]["observation_id"], exported.id);
+
+        assert!(db.get_retractions(true).await.unwrap().is_empty());
+        let all = db.get_retractions(false).await.unwrap();
+        assert_eq!(all[0].carried_in.as_deref(), second["data"]["operation_id"].as_str());
+    }
 
-        // Parse the changeset to verify structure
-        let content = String::from_utf8(changeset_data.clone()).unwrap();
-        let parsed: serde_json::Value = serde_json::from_str(&content).unwrap();
-        
-        assert!(parsed.get("checksum").is_some());
-        assert!(parsed.get("data").is_some());
+    #[tokio::test]
+    async fn test_sync_receipt_confirms_exports() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let changeset = notebook.create_changeset_file(30).await.unwrap();
+        // Keep the later changes clearly after the confirmed export
+        sqlx::query("UPDATE change_log SET changed_at = datetime('now', '-2 minutes')").execute(&notebook.pool).await.unwrap();
+        sqlx::query("UPDATE sync_history SET created_at = datetime('now', '-1 minutes')").execute(&notebook.pool).await.unwrap();
+        computer.apply_changeset_file(&changeset).await.unwrap();
+        let receipt = computer.create_sync_receipt(None).await.unwrap();
+
+        // A receipt is not accepted by the device that wrote it
+        assert!(computer.apply_sync_receipt(&receipt).await.is_err());
+        let result = notebook.apply_sync_receipt(&receipt).await.unwrap();
+        assert!(result.starts_with("1 of this device's exports confirmed"));
+
+        let status = notebook.get_sync_status(30).await.unwrap();
+        assert_eq!(status.pending_changes, 0);
+        assert!(status.devices.iter().any(|d| !d.is_local && d.confirmed_until.is_some()));
+
+        // An export the peer has not confirmed keeps its changes pending
+        notebook.create_class("5b".to_string(), "2023/24".to_string()).await.unwrap();
+        notebook.create_changeset_file(30).await.unwrap();
+        assert!(notebook.get_sync_status(30).await.unwrap().pending_changes > 0);
+
+        let mut tampered: serde_json::Value = serde_json::from_slice(&receipt).unwrap();
+        tampered["data"]["operation_ids"] = serde_json::json!([]);
+        assert!(notebook.apply_sync_receipt(tampered.to_string().as_bytes()).await.is_err());
+    }
 
-        // Clear database and re-import
-        db.clear_all_data().await.unwrap();
-        let empty_observations = db.search_observations(None, None, None).await.unwrap();
-        assert_eq!(empty_observations.len(), 0);
+    #[tokio::test]
+    async fn test_compressed_changeset_matches_estimate() {
+        let (notebook, _notebook_dir) = create_test_db().await;
+        let (computer, _computer_dir) = create_test_db().await;
+        let class = notebook.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = notebook.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        for i in 0..50 {
+            notebook
+                .create_observation(student.id, 1, "Sozial".to_string(), format!("Hilft in der Gruppenarbeit {}", i), vec![])
+                .await
+                .unwrap();
+        }
+        let backup = serde_json::json!({ "data": { "classes": [&class], "students": [&student], "observations": [] }});
+        computer.import_full_backup(backup.to_string().as_bytes(), &CancellationToken::new()).await.unwrap();
+
+        let estimate = notebook.estimate_changeset_size(30, &AttachmentPolicy::default()).await.unwrap();
+        assert_eq!(estimate.observations, 50);
+        assert!(estimate.compressed_bytes < estimate.plain_bytes / 2);
+        // Estimating records nothing
+        let exports = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM sync_history")
+            .fetch_one(&notebook.pool)
+            .await
+            .unwrap();
+        assert_eq!(exports, 0);
 
-        // Apply changeset
-        let result = db.apply_changeset_file(&changeset_data).await.unwrap();
-        assert!(result.contains("Successfully imported"));
+        let changeset = crate::changeset_codec::compress(
+            &notebook.create_changeset_file_with_policy(30, &AttachmentPolicy::default()).await.unwrap(),
+        )
+        .unwrap();
+        assert!(crate::changeset_codec::is_compressed(&changeset));
+        // Only the operation id and timestamp differ from the estimate
+        assert!(changeset.len().abs_diff(estimate.compressed_bytes) < 64);
 
-        // Verify data was restored
-        let restored_observations = db.search_observations(None, None, None).await.unwrap();
-        assert_eq!(restored_observations.len(), 1);
-        assert_eq!(restored_observations[0].text, "Test for changeset");
+        let result = computer.apply_changeset_file(&changeset).await.unwrap();
+        assert!(result.contains("Successfully imported 50 observations"));
     }
 
     #[tokio::test]
@@ -1168,7 +11653,7 @@ mod tests {
         db.clear_all_data().await.unwrap();
 
         // Import backup
-        let result = db.import_full_backup(&backup_data).await.unwrap();
+        let result = db.import_full_backup(&backup_data, &CancellationToken::new()).await.unwrap();
         assert!(result.contains("Successfully imported"));
 
         // Verify imported data
@@ -1481,7 +11966,7 @@ mod tests {
         let backup_data = backup_json.to_string().into_bytes();
 
         // Import backup (should handle conflicts by updating existing records)
-        let result = db.import_full_backup(&backup_data).await.unwrap();
+        let result = db.import_full_backup(&backup_data, &CancellationToken::new()).await.unwrap();
         assert!(result.contains("Successfully imported"));
 
         // Verify conflict resolution - existing records should be updated
@@ -0,0 +1,290 @@
+use crate::database::Database;
+use crate::reports::escape_html;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+const ANSWERS_SETTING: &str = "dpia_answers";
+
+pub const ANSWERS: [&str; 4] = ["yes", "partial", "no", "not_applicable"];
+
+/// One question of the Datenschutz-Folgenabschätzung (Art. 35 DSGVO).
+pub struct DpiaQuestion {
+    pub id: &'static str,
+    pub section: &'static str,
+    pub question: &'static str,
+    pub guidance: &'static str,
+}
+
+/// Follows the structure of Art. 35 (7) DSGVO: description, necessity, risks, measures.
+pub const QUESTIONS: &[DpiaQuestion] = &[
+    DpiaQuestion {
+        id: "purpose",
+        section: "Beschreibung der Verarbeitung",
+        question: "Sind Zweck und Umfang der Schülerbeobachtungen schriftlich festgelegt?",
+        guidance: "Pädagogische Dokumentation, Förderplanung, Elterngespräche; keine Leistungsbewertung ohne Rechtsgrundlage.",
+    },
+    DpiaQuestion {
+        id: "legal_basis",
+        section: "Beschreibung der Verarbeitung",
+        question: "Ist die Rechtsgrundlage (Schulgesetz des Landes, Art. 6 Abs. 1 lit. e DSGVO) benannt?",
+        guidance: "Die Landesvorschrift zur Verarbeitung von Schülerdaten mit Paragraph angeben.",
+    },
+    DpiaQuestion {
+        id: "data_categories",
+        section: "Beschreibung der Verarbeitung",
+        question: "Sind die verarbeiteten Datenkategorien vollständig erfasst, einschließlich Anhängen und Sprachaufnahmen?",
+        guidance: "Der Datenflussbericht der Anwendung listet alle Speicherorte auf.",
+    },
+    DpiaQuestion {
+        id: "special_categories",
+        section: "Beschreibung der Verarbeitung",
+        question: "Ist geregelt, ob und wie Gesundheitsdaten oder andere besondere Kategorien (Art. 9 DSGVO) notiert werden dürfen?",
+        guidance: "Z. B. Hinweise auf Förderbedarf, Diagnosen oder Religionszugehörigkeit.",
+    },
+    DpiaQuestion {
+        id: "necessity",
+        section: "Notwendigkeit und Verhältnismäßigkeit",
+        question: "Werden nur Beobachtungen erfasst, die für den festgelegten Zweck erforderlich sind?",
+        guidance: "Datenminimierung: Freitexte sachlich und ohne Wertungen über das Privatleben formulieren.",
+    },
+    DpiaQuestion {
+        id: "retention",
+        section: "Notwendigkeit und Verhältnismäßigkeit",
+        question: "Sind Löschfristen festgelegt und werden sie regelmäßig umgesetzt?",
+        guidance: "Aufbewahrungsfristen nach Landesrecht, z. B. Ende des Schuljahres nach Verlassen der Schule.",
+    },
+    DpiaQuestion {
+        id: "data_subject_rights",
+        section: "Notwendigkeit und Verhältnismäßigkeit",
+        question: "Ist der Ablauf für Auskunfts- und Löschanträge (Art. 15, 17 DSGVO) beschrieben?",
+        guidance: "Die Anwendung bietet Export je Schüler sowie weiche und endgültige Löschung.",
+    },
+    DpiaQuestion {
+        id: "risk_device_loss",
+        section: "Risiken",
+        question: "Wurde das Risiko des Verlusts oder Diebstahls eines Geräts bewertet?",
+        guidance: "Notebooks werden mitgenommen; Anhangdateien, Vorschaubilder und zur Prüfung abgelegte Importe sind verschlüsselt, die Datenbank selbst mit den darin gespeicherten Anhängen nicht.",
+    },
+    DpiaQuestion {
+        id: "risk_transfer",
+        section: "Risiken",
+        question: "Wurde das Risiko beim Austausch von Exportdateien (USB-Stick, Netzlaufwerk) bewertet?",
+        guidance: "Changesets und Vollexporte enthalten personenbezogene Daten im Klartext.",
+    },
+    DpiaQuestion {
+        id: "risk_unauthorized_access",
+        section: "Risiken",
+        question: "Wurde das Risiko unbefugten Zugriffs durch andere Nutzer des Geräts bewertet?",
+        guidance: "Gemeinsam genutzte Geräte, Benutzerkonten des Betriebssystems, Bildschirmsperre.",
+    },
+    DpiaQuestion {
+        id: "measure_encryption",
+        section: "Abhilfemaßnahmen",
+        question: "Ist die Festplattenverschlüsselung auf allen Geräten aktiviert?",
+        guidance: "BitLocker, FileVault oder LUKS.",
+    },
+    DpiaQuestion {
+        id: "measure_access",
+        section: "Abhilfemaßnahmen",
+        question: "Sind Zugriffsrechte und Rollen der Lehrkräfte festgelegt?",
+        guidance: "Wer darf welche Klassen sehen, wer darf Löschungen bestätigen.",
+    },
+    DpiaQuestion {
+        id: "measure_audit",
+        section: "Abhilfemaßnahmen",
+        question: "Wird das Protokoll regelmäßig auf Auffälligkeiten geprüft?",
+        guidance: "Exporte, Importe und Löschungen werden im Audit-Log festgehalten.",
+    },
+    DpiaQuestion {
+        id: "measure_backup",
+        section: "Abhilfemaßnahmen",
+        question: "Gibt es ein Verfahren für Sicherungen und deren sichere Aufbewahrung?",
+        guidance: "Sicherungen unterliegen denselben Löschfristen wie die Datenbank.",
+    },
+    DpiaQuestion {
+        id: "dpo_consulted",
+        section: "Abschluss",
+        question: "Wurde die/der Datenschutzbeauftragte angehört (Art. 35 Abs. 2 DSGVO)?",
+        guidance: "Stellungnahme mit Datum dokumentieren.",
+    },
+];
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DpiaAnswer {
+    pub answer: String,
+    pub notes: Option<String>,
+    pub answered_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DpiaItem {
+    pub id: &'static str,
+    pub section: &'static str,
+    pub question: &'static str,
+    pub guidance: &'static str,
+    pub answer: Option<DpiaAnswer>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DpiaChecklist {
+    pub items: Vec<DpiaItem>,
+    pub answered: usize,
+    pub total: usize,
+    /// Items answered "no" or "partial", i.e. risks without sufficient measures.
+    pub open_risks: usize,
+}
+
+async fn load_answers(db: &Database) -> Result<BTreeMap<String, DpiaAnswer>> {
+    Ok(db
+        .get_setting(ANSWERS_SETTING)
+        .await?
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default())
+}
+
+pub async fn get_checklist(db: &Database) -> Result<DpiaChecklist> {
+    let mut answers = load_answers(db).await?;
+
+    let items: Vec<DpiaItem> = QUESTIONS
+        .iter()
+        .map(|q| DpiaItem {
+            id: q.id,
+            section: q.section,
+            question: q.question,
+            guidance: q.guidance,
+            answer: answers.remove(q.id),
+        })
+        .collect();
+
+    let answered = items.iter().filter(|i| i.answer.is_some()).count();
+    let open_risks = items
+        .iter()
+        .filter_map(|i| i.answer.as_ref())
+        .filter(|a| a.answer == "no" || a.answer == "partial")
+        .count();
+
+    Ok(DpiaChecklist {
+        total: items.len(),
+        items,
+        answered,
+        open_risks,
+    })
+}
+
+pub async fn answer_item(db: &Database, item_id: &str, answer: &str, notes: Option<String>) -> Result<()> {
+    if !QUESTIONS.iter().any(|q| q.id == item_id) {
+        return Err(anyhow::anyhow!("Unknown DPIA item '{}'", item_id));
+    }
+    if !ANSWERS.contains(&answer) {
+        return Err(anyhow::anyhow!("Answer must be one of: {}", ANSWERS.join(", ")));
+    }
+
+    let mut answers = load_answers(db).await?;
+    answers.insert(
+        item_id.to_string(),
+        DpiaAnswer {
+            answer: answer.to_string(),
+            notes: notes.filter(|n| !n.trim().is_empty()),
+            answered_at: Utc::now(),
+        },
+    );
+    db.set_setting(ANSWERS_SETTING, &serde_json::to_string(&answers)?).await
+}
+
+/// Printable HTML of the assessment for the school's privacy documentation.
+pub fn render_assessment_html(checklist: &DpiaChecklist, school: Option<&str>) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Datenschutz-Folgenabschätzung</title>\n");
+    html.push_str(
+        "<style>body{font-family:sans-serif;margin:2em}h2{margin-top:1.5em;border-bottom:1px solid #ccc}\
+         td,th{vertical-align:top;padding:.3em .6em;border-bottom:1px solid #eee;text-align:left}\
+         .meta{color:#6B7280;font-size:.9em}.open{color:#B91C1C}</style>\n</head>\n<body>\n",
+    );
+    html.push_str("<h1>Datenschutz-Folgenabschätzung (Art. 35 DSGVO)</h1>\n");
+    if let Some(school) = school {
+        html.push_str(&format!("<p>{}</p>\n", escape_html(school)));
+    }
+    html.push_str(&format!(
+        "<p class=\"meta\">{} von {} Fragen beantwortet · {} offene Risiken</p>\n",
+        checklist.answered, checklist.total, checklist.open_risks
+    ));
+
+    let mut section = "";
+    for item in &checklist.items {
+        if item.section != section {
+            if !section.is_empty() {
+                html.push_str("</table>\n");
+            }
+            section = item.section;
+            html.push_str(&format!(
+                "<h2>{}</h2>\n<table>\n<tr><th>Frage</th><th>Antwort</th><th>Anmerkungen</th></tr>\n",
+                escape_html(section)
+            ));
+        }
+
+        let (answer, class, notes) = match &item.answer {
+            Some(a) => (
+                answer_label(&a.answer),
+                if a.answer == "no" || a.answer == "partial" { " class=\"open\"" } else { "" },
+                format!(
+                    "{} <span class=\"meta\">({})</span>",
+                    escape_html(a.notes.as_deref().unwrap_or("")),
+                    a.answered_at.format("%d.%m.%Y")
+                ),
+            ),
+            None => ("offen", " class=\"open\"", String::new()),
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td{}>{}</td><td>{}</td></tr>\n",
+            escape_html(item.question),
+            class,
+            answer,
+            notes
+        ));
+    }
+    if !section.is_empty() {
+        html.push_str("</table>\n");
+    }
+
+    html.push_str(&format!(
+        "<p class=\"meta\">Erstellt am {}</p>\n</body>\n</html>\n",
+        Utc::now().format("%d.%m.%Y %H:%M UTC")
+    ));
+    html
+}
+
+fn answer_label(answer: &str) -> &'static str {
+    match answer {
+        "yes" => "ja",
+        "partial" => "teilweise",
+        "no" => "nein",
+        _ => "nicht zutreffend",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_answers_are_stored_and_exported() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        answer_item(&db, "purpose", "yes", Some("Förderplanung".to_string())).await.unwrap();
+        answer_item(&db, "risk_device_loss", "partial", None).await.unwrap();
+        assert!(answer_item(&db, "unknown", "yes", None).await.is_err());
+        assert!(answer_item(&db, "purpose", "maybe", None).await.is_err());
+
+        let checklist = get_checklist(&db).await.unwrap();
+        assert_eq!(checklist.total, QUESTIONS.len());
+        assert_eq!(checklist.answered, 2);
+        assert_eq!(checklist.open_risks, 1);
+        assert_eq!(checklist.items[0].answer.as_ref().unwrap().notes.as_deref(), Some("Förderplanung"));
+
+        let html = render_assessment_html(&checklist, Some("Grundschule <Am See>"));
+        assert!(html.contains("Grundschule &lt;Am See&gt;"));
+        assert!(html.contains("teilweise"));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::changeset_codec;
+use crate::crypto::{self, CryptoManager};
+use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+const ESCROW_FORMAT: &str = "escrow_backup_v1";
+
+/// Generated for the school management, which keeps the private key outside
+/// the app, e.g. printed in the school safe.
+#[derive(Debug, serde::Serialize)]
+pub struct SchoolKeyPair {
+    pub public_key: String,
+    pub private_key: String,
+    pub fingerprint: String,
+}
+
+/// What the readable envelope of an escrow backup tells about it.
+#[derive(Debug, serde::Serialize)]
+pub struct EscrowInfo {
+    pub source_device_id: String,
+    pub school_key_fingerprint: String,
+    pub created_at: DateTime<Utc>,
+}
+
+pub fn generate_school_key_pair() -> Result<SchoolKeyPair> {
+    let (public_key, private_key) = crypto::generate_key_pair();
+    let fingerprint = crypto::key_fingerprint(&public_key)?;
+    Ok(SchoolKeyPair {
+        public_key,
+        private_key,
+        fingerprint,
+    })
+}
+
+/// Seals a full backup for the school key, so the data can be recovered
+/// when the teacher leaves or forgets their password.
+pub fn seal_backup(crypto: &CryptoManager, backup: &[u8], school_public_key: &str) -> Result<(Vec<u8>, EscrowInfo)> {
+    let info = EscrowInfo {
+        source_device_id: crypto.get_device_id(),
+        school_key_fingerprint: crypto::key_fingerprint(school_public_key)?,
+        created_at: Utc::now(),
+    };
+    let sealed = crypto.seal_for(school_public_key, &changeset_codec::compress(backup)?)?;
+
+    let package = json!({
+        "format": ESCROW_FORMAT,
+        "source_device_id": info.source_device_id,
+        "school_key_fingerprint": info.school_key_fingerprint,
+        "created_at": info.created_at,
+        "payload": BASE64_STANDARD.encode(sealed),
+    });
+    Ok((package.to_string().into_bytes(), info))
+}
+
+/// Returns the plain backup; only the school's private key opens it.
+pub fn open_backup(package: &[u8], school_private_key: &str) -> Result<(Vec<u8>, EscrowInfo)> {
+    let envelope: Value = serde_json::from_slice(package).context("Invalid escrow backup")?;
+    if envelope.get("format").and_then(|f| f.as_str()) != Some(ESCROW_FORMAT) {
+        return Err(anyhow::anyhow!("File is not an escrow backup"));
+    }
+    let info = EscrowInfo {
+        source_device_id: envelope
+            .get("source_device_id")
+            .and_then(|d| d.as_str())
+            .context("Missing device in escrow backup")?
+            .to_string(),
+        school_key_fingerprint: envelope
+            .get("school_key_fingerprint")
+            .and_then(|f| f.as_str())
+            .context("Missing key fingerprint in escrow backup")?
+            .to_string(),
+        created_at: envelope
+            .get("created_at")
+            .and_then(|c| serde_json::from_value(c.clone()).ok())
+            .context("Missing creation time in escrow backup")?,
+    };
+
+    let fingerprint = crypto::key_fingerprint(&crypto::public_key_of(school_private_key)?)?;
+    if fingerprint != info.school_key_fingerprint {
+        return Err(anyhow::anyhow!(
+            "This backup was made for school key {}, not {}",
+            info.school_key_fingerprint,
+            fingerprint
+        ));
+    }
+
+    let sealed = BASE64_STANDARD
+        .decode(envelope.get("payload").and_then(|p| p.as_str()).unwrap_or_default())
+        .context("Invalid escrow payload")?;
+    let compressed = crypto::open_sealed_with_key(school_private_key, &sealed)?;
+    Ok((changeset_codec::decode(&compressed)?.into_owned(), info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_opens_only_with_school_key() {
+        let crypto = CryptoManager::new().unwrap();
+        let school = generate_school_key_pair().unwrap();
+        let backup = br#"{"format":"full_export","data":{"students":[{"last_name":"Mustermann"}]}}"#;
+
+        let (package, info) = seal_backup(&crypto, backup, &school.public_key).unwrap();
+        assert_eq!(info.school_key_fingerprint, school.fingerprint);
+        assert!(!String::from_utf8_lossy(&package).contains("Mustermann"));
+
+        let other = generate_school_key_pair().unwrap();
+        assert!(open_backup(&package, &other.private_key).is_err());
+        assert!(open_backup(&package, &school.public_key).is_err());
+
+        let (opened, info) = open_backup(&package, &school.private_key).unwrap();
+        assert_eq!(opened, backup);
+        assert_eq!(info.source_device_id, crypto.get_device_id());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::audit::AuditLogger;
+use crate::database::Database;
+use crate::export_target::ExportResult;
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::MutexGuard;
+
+pub const HOOK_PROGRAM_SETTING: &str = "export_hook.program";
+
+/// A virus scan of a large archive may take a while; a hook that hangs
+/// must not keep the export from finishing.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// What a school's own post-export program, e.g. encryption, a copy to a
+/// mandated share or a virus scan, did with an export.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HookRun {
+    pub program: String,
+    pub exit_code: Option<i32>, // None when the program did not start or timed out
+    pub success: bool,
+    pub error: Option<String>, // The end of stderr, or why the program did not run
+}
+
+impl HookRun {
+    fn describe(&self) -> String {
+        match (self.exit_code, &self.error) {
+            (Some(code), _) => format!("exit code {}", code),
+            (None, Some(error)) => error.clone(),
+            (None, None) => "no exit code".to_string(),
+        }
+    }
+
+    fn describe_for_audit(&self) -> String {
+        match self.exit_code {
+            Some(code) => format!("exit code {}", code),
+            None if self.error.as_deref().is_some_and(|e| e.starts_with("timed out")) => "timed out".to_string(),
+            None => "did not start".to_string(),
+        }
+    }
+}
+
+pub async fn configured_program(db: &Database) -> Result<Option<String>> {
+    Ok(db
+        .get_setting(HOOK_PROGRAM_SETTING)
+        .await?
+        .filter(|p| !p.trim().is_empty()))
+}
+
+/// The program runs with the rights of the app on every export, so only an
+/// admin sets it, and only as an absolute path: a bare name would be looked
+/// up in whatever PATH the app was started with. `None` removes the hook.
+pub async fn set_program(db: &Database, program: Option<&str>, admin_id: i64) -> Result<()> {
+    db.require_admin(admin_id).await?;
+    let program = program.map(str::trim).unwrap_or_default();
+    if !program.is_empty() {
+        let path = Path::new(program);
+        if !path.is_absolute() {
+            return Err(anyhow::anyhow!("The export hook must be given as an absolute path"));
+        }
+        if !path.is_file() {
+            return Err(anyhow::anyhow!("{} is not a file", program));
+        }
+    }
+    db.set_setting(HOOK_PROGRAM_SETTING, program).await
+}
+
+/// Runs `<program> <path> <export type>` through the shell plugin once an
+/// export is on disk, and audits the run with its exit code. A failing hook
+/// leaves the export in place; the caller reports the outcome instead of
+/// hiding a file that exists. Returns `None` without a configured hook.
+/// The database lock is released before the program starts, so a slow
+/// scan does not block the app for up to `HOOK_TIMEOUT`.
+pub async fn run_for_path<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    db: MutexGuard<'_, Database>,
+    audit: &AuditLogger,
+    path: &str,
+    export_type: &str,
+    object_id: i64,
+    user_id: i64,
+) -> Result<Option<HookRun>> {
+    let program = configured_program(&db).await?;
+    drop(db);
+    let Some(program) = program else {
+        return Ok(None);
+    };
+
+    let command = app
+        .shell()
+        .command(program.clone())
+        .args(vec![path.to_string(), export_type.to_string()]);
+    let run = match tokio::time::timeout(HOOK_TIMEOUT, command.output()).await {
+        Err(_) => HookRun {
+            program,
+            exit_code: None,
+            success: false,
+            error: Some(format!("timed out after {} s", HOOK_TIMEOUT.as_secs())),
+        },
+        Ok(Err(e)) => HookRun {
+            program,
+            exit_code: None,
+            success: false,
+            error: Some(format!("failed to start: {}", e)),
+        },
+        Ok(Ok(output)) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr = stderr.trim();
+            // The end of stderr is where scripts say what went wrong
+            let tail: String = stderr.chars().rev().take(500).collect::<Vec<_>>().into_iter().rev().collect();
+            HookRun {
+                program,
+                exit_code: output.status.code(),
+                success: output.status.success(),
+                error: (!output.status.success() && !tail.is_empty()).then_some(tail),
+            }
+        }
+    };
+
+    // The hook's own output stays out of the log, it may quote the export
+    audit
+        .log_action(
+            "run",
+            "export_hook",
+            object_id,
+            user_id,
+            Some(&format!("{} {}: {} ({})", export_type, path, run.program, run.describe_for_audit())),
+        )
+        .await?;
+    Ok(Some(run))
+}
+
+/// `run_for_path` for the file of an export, noting the outcome in the
+/// message the UI shows.
+pub async fn run<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    db: MutexGuard<'_, Database>,
+    audit: &AuditLogger,
+    result: &mut ExportResult,
+    export_type: &str,
+    object_id: i64,
+    user_id: i64,
+) -> Result<()> {
+    let run = run_for_path(app, db, audit, &result.file_path, export_type, object_id, user_id).await?;
+    if let Some(run) = &run {
+        result.message.push_str(&outcome_note(run));
+    }
+    result.post_export = run;
+    Ok(())
+}
+
+pub fn outcome_note(run: &HookRun) -> String {
+    if run.success {
+        format!("; export hook finished ({})", run.describe())
+    } else {
+        format!("; export hook FAILED ({}), the file was kept", run.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_only_admins_set_an_existing_absolute_program() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let script = temp_dir.path().join("scan.sh");
+        std::fs::write(&script, "#!/bin/sh\nexit 0\n").unwrap();
+        let script = script.to_string_lossy().into_owned();
+
+        assert_eq!(configured_program(&db).await.unwrap(), None);
+        assert!(set_program(&db, Some("scan.sh"), 1).await.is_err());
+        assert!(set_program(&db, Some(&format!("{}.missing", script)), 1).await.is_err());
+        assert!(set_program(&db, Some(&script), 999).await.is_err());
+        set_program(&db, Some(&format!(" {} ", script)), 1).await.unwrap();
+        assert_eq!(configured_program(&db).await.unwrap(), Some(script));
+
+        set_program(&db, None, 1).await.unwrap();
+        assert_eq!(configured_program(&db).await.unwrap(), None);
+
+        let failed = HookRun {
+            program: "/opt/scan".to_string(),
+            exit_code: Some(2),
+            success: false,
+            error: Some("infected".to_string()),
+        };
+        assert_eq!(outcome_note(&failed), "; export hook FAILED (exit code 2), the file was kept");
+        assert_eq!(failed.describe_for_audit(), "exit code 2");
+    }
+}
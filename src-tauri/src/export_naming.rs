@@ -0,0 +1,149 @@
+use crate::crypto::CryptoManager;
+use crate::database::Database;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use std::path::Path;
+
+const TEMPLATE_SETTING: &str = "export_filename_template";
+pub const DEFAULT_TEMPLATE: &str = "{date}_{class}_{type}";
+
+const PLACEHOLDERS: [&str; 5] = ["date", "time", "class", "type", "device"];
+
+/// What is being exported, for the file name of an export into a directory.
+pub struct ExportName<'a> {
+    pub export_type: &'a str,
+    pub class: Option<&'a str>,
+    pub extension: &'a str,
+}
+
+pub async fn get_template(db: &Database) -> Result<String> {
+    Ok(db
+        .get_setting(TEMPLATE_SETTING)
+        .await?
+        .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()))
+}
+
+pub async fn set_template(db: &Database, template: &str) -> Result<()> {
+    validate_template(template)?;
+    db.set_setting(TEMPLATE_SETTING, template.trim()).await
+}
+
+pub fn validate_template(template: &str) -> Result<()> {
+    let template = template.trim();
+    if template.is_empty() {
+        return Err(anyhow::anyhow!("The file name template must not be empty"));
+    }
+    if template.contains(['/', '\\']) {
+        return Err(anyhow::anyhow!("The file name template must not contain folders"));
+    }
+
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in file name template"))?;
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(anyhow::anyhow!(
+                "Unknown placeholder {{{}}}; available are {}",
+                name,
+                PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// Values end up in file names, which must be valid on every system the
+/// USB stick is plugged into.
+pub fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '-' })
+        .collect();
+    cleaned.trim_matches(['-', '.']).to_string()
+}
+
+pub fn render(template: &str, name: &ExportName, device: &str, now: DateTime<Local>) -> String {
+    let class = name.class.map(sanitize).filter(|c| !c.is_empty()).unwrap_or_else(|| "alle".to_string());
+    let rendered = template
+        .trim()
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H%M").to_string())
+        .replace("{class}", &class)
+        .replace("{type}", &sanitize(name.export_type))
+        .replace("{device}", &sanitize(device));
+    let rendered = sanitize(&rendered);
+    if rendered.is_empty() {
+        sanitize(name.export_type)
+    } else {
+        rendered
+    }
+}
+
+/// `requested` is used as given unless it is a directory; then the file is
+/// named by the template, numbered if the name is taken.
+pub async fn resolve_path(db: &Database, crypto: &CryptoManager, requested: &str, name: &ExportName<'_>) -> Result<String> {
+    let directory = Path::new(requested);
+    if !directory.is_dir() {
+        return Ok(requested.to_string());
+    }
+
+    let device = crypto
+        .get_device_config()?
+        .remove("device_name")
+        .unwrap_or_else(|| crypto.get_device_id().chars().take(8).collect());
+    let base = render(&get_template(db).await?, name, &device, Local::now());
+
+    let mut candidate = directory.join(format!("{}.{}", base, name.extension));
+    let mut number = 2;
+    while candidate.exists() {
+        candidate = directory.join(format!("{}_{}.{}", base, number, name.extension));
+        number += 1;
+    }
+    Ok(candidate.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_render_and_validate_template() {
+        let now = Local.with_ymd_and_hms(2024, 3, 15, 14, 5, 0).unwrap();
+        let changeset = ExportName { export_type: "changeset", class: None, extension: "sbchange" };
+        let handover = ExportName { export_type: "handover", class: Some("5a / Musik"), extension: "json" };
+
+        assert_eq!(render(DEFAULT_TEMPLATE, &changeset, "Notebook", now), "2024-03-15_alle_changeset");
+        assert_eq!(render(DEFAULT_TEMPLATE, &handover, "Notebook", now), "2024-03-15_5a---Musik_handover");
+        assert_eq!(render("{device}-{time}", &changeset, "Büro PC", now), "Büro-PC-1405");
+
+        assert!(validate_template("{date}_{type}").is_ok());
+        assert!(validate_template("{datum}").is_err());
+        assert!(validate_template("{date").is_err());
+        assert!(validate_template("usb/{date}").is_err());
+        assert!(validate_template(" ").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_directory_exports_get_numbered_names() {
+        let (db, crypto, temp_dir) = crate::fixtures::test_db_with_crypto().await;
+        let target = temp_dir.path().join("usb");
+        std::fs::create_dir(&target).unwrap();
+        set_template(&db, "{type}").await.unwrap();
+
+        let name = ExportName { export_type: "changeset", class: None, extension: "sbchange" };
+        let requested = target.to_string_lossy().into_owned();
+        let first = resolve_path(&db, &crypto, &requested, &name).await.unwrap();
+        assert!(first.ends_with("changeset.sbchange"));
+        std::fs::write(&first, b"{}").unwrap();
+        let second = resolve_path(&db, &crypto, &requested, &name).await.unwrap();
+        assert!(second.ends_with("changeset_2.sbchange"));
+
+        // An explicit file is used as given, even if it exists
+        assert_eq!(resolve_path(&db, &crypto, &first, &name).await.unwrap(), first);
+    }
+}
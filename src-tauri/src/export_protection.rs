@@ -0,0 +1,124 @@
+use crate::crypto::CryptoManager;
+use crate::database::Database;
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+
+const REQUIRED_SETTING: &str = "exports_must_be_encrypted";
+
+/// Protected exports start with this header, a version byte and the mode,
+/// followed by the encrypted file.
+const MAGIC: &[u8; 4] = b"SBEX";
+const VERSION: u8 = 1;
+const MODE_PASSPHRASE: u8 = 1;
+const MODE_RECIPIENT: u8 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+/// How an export is to be encrypted; a recipient key wins over a passphrase.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct ExportProtection {
+    pub passphrase: Option<String>,
+    pub recipient_key: Option<String>,
+}
+
+pub async fn is_required(db: &Database) -> Result<bool> {
+    Ok(db.get_setting(REQUIRED_SETTING).await?.as_deref() == Some("true"))
+}
+
+/// Whether exports may be written in plain text is a decision of the school.
+pub async fn set_required(db: &Database, required: bool, admin_id: i64) -> Result<()> {
+    db.require_admin(admin_id).await?;
+    db.set_setting(REQUIRED_SETTING, if required { "true" } else { "false" })
+        .await
+}
+
+pub fn is_protected(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts `data` as requested. Without a passphrase or key the data stays
+/// plain, unless an admin has required encrypted exports.
+pub async fn protect(
+    db: &Database,
+    crypto: &CryptoManager,
+    data: Vec<u8>,
+    protection: Option<&ExportProtection>,
+) -> Result<Vec<u8>> {
+    let protection = protection.cloned().unwrap_or_default();
+    let recipient_key = protection.recipient_key.filter(|k| !k.trim().is_empty());
+    let passphrase = protection.passphrase.filter(|p| !p.is_empty());
+
+    let (mode, encrypted) = match (recipient_key, passphrase) {
+        (Some(key), _) => (MODE_RECIPIENT, crypto.seal_for(&key, &data)?),
+        (None, Some(passphrase)) => (MODE_PASSPHRASE, crypto.encrypt_with_passphrase(&passphrase, &data)?),
+        (None, None) if is_required(db).await? => {
+            return Err(anyhow::anyhow!(
+                "Exports must be encrypted: give a passphrase or the recipient's device key"
+            ));
+        }
+        (None, None) => return Ok(data),
+    };
+
+    let mut output = Vec::with_capacity(HEADER_LEN + encrypted.len());
+    output.extend_from_slice(MAGIC);
+    output.push(VERSION);
+    output.push(mode);
+    output.extend_from_slice(&encrypted);
+    Ok(output)
+}
+
+/// Returns the plain file; files that were never protected pass through.
+pub fn open<'a>(crypto: &CryptoManager, data: &'a [u8], passphrase: Option<&str>) -> Result<Cow<'a, [u8]>> {
+    if !is_protected(data) {
+        return Ok(Cow::Borrowed(data));
+    }
+    let version = *data.get(MAGIC.len()).context("Truncated encrypted file")?;
+    if version > VERSION {
+        return Err(anyhow::anyhow!(
+            "Encryption version {} is newer than supported ({})",
+            version,
+            VERSION
+        ));
+    }
+
+    let encrypted = &data[HEADER_LEN.min(data.len())..];
+    match data.get(MAGIC.len() + 1).copied() {
+        Some(MODE_RECIPIENT) => Ok(Cow::Owned(crypto.open_sealed(encrypted)?)),
+        Some(MODE_PASSPHRASE) => {
+            let passphrase = passphrase.context("This file is encrypted; the passphrase is required")?;
+            Ok(Cow::Owned(crypto.decrypt_with_passphrase(passphrase, encrypted)?))
+        }
+        _ => Err(anyhow::anyhow!("Unknown encryption mode")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_policy_refuses_plain_exports() {
+        let (db, crypto, _temp_dir) = crate::fixtures::test_db_with_crypto().await;
+        let data = b"{\"data\":{}}".to_vec();
+
+        assert_eq!(protect(&db, &crypto, data.clone(), None).await.unwrap(), data);
+
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+        assert!(set_required(&db, true, teacher.id).await.is_err());
+        set_required(&db, true, 1).await.unwrap();
+        let err = protect(&db, &crypto, data.clone(), None).await.unwrap_err();
+        assert!(err.to_string().contains("must be encrypted"));
+
+        let with_passphrase = ExportProtection { passphrase: Some("Schulhof2024".to_string()), recipient_key: None };
+        let protected = protect(&db, &crypto, data.clone(), Some(&with_passphrase)).await.unwrap();
+        assert!(is_protected(&protected));
+        assert!(open(&crypto, &protected, None).is_err());
+        assert!(open(&crypto, &protected, Some("Schulhof2025")).is_err());
+        assert_eq!(open(&crypto, &protected, Some("Schulhof2024")).unwrap().as_ref(), data.as_slice());
+
+        let for_device = ExportProtection { passphrase: None, recipient_key: Some(crypto.device_public_key().unwrap()) };
+        let sealed = protect(&db, &crypto, data.clone(), Some(&for_device)).await.unwrap();
+        assert_eq!(open(&crypto, &sealed, None).unwrap().as_ref(), data.as_slice());
+
+        assert!(matches!(open(&crypto, &data, None).unwrap(), Cow::Borrowed(_)));
+    }
+}
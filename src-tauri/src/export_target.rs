@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+
+/// What an export command reports back, so the UI can advise to eject a
+/// USB stick before pulling it.
+#[derive(Debug, serde::Serialize)]
+pub struct ExportResult {
+    pub message: String,
+    pub file_path: String,
+    pub removable_media: bool,
+    pub post_export: Option<crate::export_hook::HookRun>, // Set when an export hook is configured
+}
+
+/// Writes an export and waits until it is on the medium: a stick yanked
+/// while the data is still in the write cache leaves a truncated file.
+/// Returns whether `path` is on removable media.
+pub fn write_synced(path: &str, data: &[u8]) -> Result<bool> {
+    let path = Path::new(path);
+    let mut file = std::fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(data)
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    // The directory entry has to reach the medium as well
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::File::open(parent).and_then(|dir| dir.sync_all()).ok();
+    }
+
+    Ok(is_removable(path))
+}
+
+#[cfg(target_os = "linux")]
+pub fn is_removable(path: &Path) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    let Ok(mounts) = std::fs::read_to_string("/proc/mounts") else {
+        return false;
+    };
+
+    // The innermost mount point containing the path; spaces are escaped in /proc/mounts
+    let mount = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?.to_string(), fields.next()?.replace("\\040", " ")))
+        })
+        .filter(|(_, point)| path.starts_with(point))
+        .max_by_key(|(_, point)| point.len());
+    let Some((device, point)) = mount else {
+        return false;
+    };
+
+    if let (Some(name), Ok(blocks)) = (device.strip_prefix("/dev/"), std::fs::read_dir("/sys/block")) {
+        // Partitions such as sdb1 or mmcblk0p1 are named after their block device
+        for block in blocks.flatten() {
+            if !name.starts_with(block.file_name().to_string_lossy().as_ref()) {
+                continue;
+            }
+            let removable = std::fs::read_to_string(block.path().join("removable")).is_ok_and(|r| r.trim() == "1");
+            let usb = std::fs::read_link(block.path()).is_ok_and(|target| target.to_string_lossy().contains("/usb"));
+            if removable || usb {
+                return true;
+            }
+        }
+    }
+    // Where desktop environments mount sticks
+    point.starts_with("/media/") || point.starts_with("/run/media/")
+}
+
+#[cfg(target_os = "macos")]
+pub fn is_removable(path: &Path) -> bool {
+    // The system volume resolves to `/`, everything else under /Volumes is external
+    path.canonicalize().is_ok_and(|path| path.starts_with("/Volumes/"))
+}
+
+#[cfg(windows)]
+pub fn is_removable(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::{Component, Prefix};
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetDriveTypeW(root_path: *const u16) -> u32;
+    }
+    const DRIVE_REMOVABLE: u32 = 2;
+
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return false;
+    };
+    let letter = match prefix.kind() {
+        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => letter as char,
+        _ => return false,
+    };
+    let root: Vec<u16> = std::ffi::OsStr::new(&format!("{}:\\", letter))
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    // SAFETY: `root` is a NUL-terminated UTF-16 string that outlives the call
+    unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOVABLE }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn is_removable(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_synced_writes_complete_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("changeset.sbchange");
+
+        let removable = write_synced(path.to_str().unwrap(), b"{\"data\":{}}").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"{\"data\":{}}");
+        // The temporary directory is on the system disk
+        assert!(!removable);
+
+        assert!(write_synced(temp_dir.path().join("missing/file.json").to_str().unwrap(), b"{}").is_err());
+    }
+}
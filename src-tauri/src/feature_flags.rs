@@ -0,0 +1,187 @@
+use crate::database::Database;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// Overrides of the defaults, as a JSON map of flag name to bool. Kept in
+/// the settings of the database, so every data root (profile) has its own.
+const FEATURE_FLAGS_SETTING: &str = "feature_flags";
+
+/// A subsystem whose commands can be switched off while it is in flux.
+pub struct FeatureFlag {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub commands: &'static [&'static str], // Rejected before they run while the flag is off
+    pub default_enabled: bool,
+}
+
+/// These subsystems shipped before the registry existed and default to on,
+/// so an update takes nothing away; new experimental ones register off.
+pub const FLAGS: &[FeatureFlag] = &[
+    FeatureFlag {
+        name: "attachment_sync",
+        description: "Transfer of attachments between devices",
+        commands: &["export_attachment_request", "export_missing_attachments", "import_attachment_transfer"],
+        default_enabled: true,
+    },
+    FeatureFlag {
+        name: "handover_sync",
+        description: "Handover packages for passing a class to another teacher",
+        commands: &["generate_handover_package", "import_handover_package", "get_handover_imports"],
+        default_enabled: true,
+    },
+    FeatureFlag {
+        name: "school_escrow",
+        description: "Backups encrypted for the school's escrow key",
+        commands: &["generate_school_escrow_key", "create_escrow_backup", "import_escrow_backup"],
+        default_enabled: true,
+    },
+    FeatureFlag {
+        name: "companion_api",
+        description: "Local API for companion tools",
+        commands: &["set_companion_api_enabled"],
+        default_enabled: true,
+    },
+    FeatureFlag {
+        name: "transcription",
+        description: "Transcription of voice notes",
+        commands: &["set_transcription_config", "transcribe_attachment"],
+        default_enabled: true,
+    },
+];
+
+/// A flag as the settings show it.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FlagStatus {
+    pub name: String,
+    pub description: String,
+    pub enabled: bool,
+    pub default_enabled: bool,
+    pub commands: Vec<String>,
+}
+
+/// The flags of the open database, managed by Tauri so the invoke handler
+/// can check them without waiting for the database lock.
+pub struct FeatureFlags {
+    overrides: RwLock<BTreeMap<String, bool>>,
+}
+
+fn find(name: &str) -> Result<&'static FeatureFlag> {
+    FLAGS
+        .iter()
+        .find(|flag| flag.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown feature flag: {}", name))
+}
+
+async fn load_overrides(db: &Database) -> Result<BTreeMap<String, bool>> {
+    Ok(db
+        .get_setting(FEATURE_FLAGS_SETTING)
+        .await?
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default())
+}
+
+/// Reads a flag from the database itself, for code that runs outside the
+/// invoke handler, such as the companion API listener.
+pub async fn is_enabled(db: &Database, name: &str) -> Result<bool> {
+    let flag = find(name)?;
+    Ok(load_overrides(db).await?.get(flag.name).copied().unwrap_or(flag.default_enabled))
+}
+
+impl FeatureFlags {
+    pub async fn load(db: &Database) -> Result<FeatureFlags> {
+        Ok(FeatureFlags { overrides: RwLock::new(load_overrides(db).await?) })
+    }
+
+    /// Picks up flags the database holds now, e.g. older ones after a
+    /// rollback to a snapshot.
+    pub async fn reload(&self, db: &Database) -> Result<()> {
+        let overrides = load_overrides(db).await?;
+        *self.overrides.write().unwrap_or_else(|e| e.into_inner()) = overrides;
+        Ok(())
+    }
+
+    fn is_enabled(&self, flag: &FeatureFlag) -> bool {
+        let overrides = self.overrides.read().unwrap_or_else(|e| e.into_inner());
+        overrides.get(flag.name).copied().unwrap_or(flag.default_enabled)
+    }
+
+    /// The flag that currently switches `command` off, if any.
+    pub fn disabled_by(&self, command: &str) -> Option<&'static str> {
+        FLAGS
+            .iter()
+            .find(|flag| flag.commands.contains(&command) && !self.is_enabled(flag))
+            .map(|flag| flag.name)
+    }
+
+    pub fn list(&self) -> Vec<FlagStatus> {
+        FLAGS
+            .iter()
+            .map(|flag| FlagStatus {
+                name: flag.name.to_string(),
+                description: flag.description.to_string(),
+                enabled: self.is_enabled(flag),
+                default_enabled: flag.default_enabled,
+                commands: flag.commands.iter().map(|c| c.to_string()).collect(),
+            })
+            .collect()
+    }
+
+    /// Only an admin switches subsystems. Stored before it takes effect, so
+    /// the flag survives a restart. Setting the default removes the override,
+    /// letting a later release change the default.
+    pub async fn set(&self, db: &Database, name: &str, enabled: bool, admin_id: i64) -> Result<FlagStatus> {
+        db.require_admin(admin_id).await?;
+        let flag = find(name)?;
+
+        let mut overrides = self.overrides.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if enabled == flag.default_enabled {
+            overrides.remove(flag.name);
+        } else {
+            overrides.insert(flag.name.to_string(), enabled);
+        }
+        db.set_setting(FEATURE_FLAGS_SETTING, &serde_json::to_string(&overrides)?).await?;
+        *self.overrides.write().unwrap_or_else(|e| e.into_inner()) = overrides;
+
+        self.list()
+            .into_iter()
+            .find(|status| status.name == flag.name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown feature flag: {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_flags_gate_commands_and_persist() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+        let flags = FeatureFlags::load(&db).await.unwrap();
+        assert!(flags.list().iter().all(|status| status.enabled == status.default_enabled));
+        assert_eq!(flags.disabled_by("transcribe_attachment"), None);
+
+        assert!(flags.set(&db, "transcription", false, 999).await.is_err());
+        assert!(flags.set(&db, "time_travel", false, 1).await.is_err());
+        let status = flags.set(&db, "transcription", false, 1).await.unwrap();
+        assert!(!status.enabled);
+        assert_eq!(flags.disabled_by("transcribe_attachment"), Some("transcription"));
+        assert_eq!(flags.disabled_by("create_observation"), None);
+
+        // Survives a restart
+        let reloaded = FeatureFlags::load(&db).await.unwrap();
+        assert_eq!(reloaded.disabled_by("set_transcription_config"), Some("transcription"));
+
+        reloaded.set(&db, "transcription", true, 1).await.unwrap();
+        assert_eq!(db.get_setting(FEATURE_FLAGS_SETTING).await.unwrap().as_deref(), Some("{}"));
+        assert_eq!(reloaded.disabled_by("transcribe_attachment"), None);
+        assert!(is_enabled(&db, "transcription").await.unwrap());
+
+        // Stored by other means, e.g. a snapshot brought back
+        db.set_setting(FEATURE_FLAGS_SETTING, r#"{"companion_api": false}"#).await.unwrap();
+        assert!(!is_enabled(&db, "companion_api").await.unwrap());
+        assert_eq!(reloaded.disabled_by("set_companion_api_enabled"), None);
+        reloaded.reload(&db).await.unwrap();
+        assert_eq!(reloaded.disabled_by("set_companion_api_enabled"), Some("companion_api"));
+    }
+}
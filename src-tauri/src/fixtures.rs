@@ -0,0 +1,174 @@
+//! Sample data for the tests. Records and export files are built here, so a
+//! format change updates one place instead of every test that imports a
+//! file. The records are those a current export writes; the file builders
+//! cover each format version the importers still accept. Tests that reopen
+//! a database file at a path of their own, e.g. across a restart, open it
+//! themselves.
+
+use crate::crypto::CryptoManager;
+use crate::database::{changeset_checksum, Database};
+use crate::manifest::ExportManifest;
+use crate::{Class, Observation, Student};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Device the records of other devices come from.
+pub const OTHER_DEVICE: &str = "other";
+
+pub async fn test_db() -> (Database, TempDir) {
+    let (db, _crypto, temp_dir) = test_db_with_crypto().await;
+    (db, temp_dir)
+}
+
+/// For tests that hand the crypto manager of the database to the code under test.
+pub async fn test_db_with_crypto() -> (Database, Arc<CryptoManager>, TempDir) {
+    let temp_dir = TempDir::new().unwrap();
+    let crypto = Arc::new(CryptoManager::new().unwrap());
+    let db = Database::new(&temp_dir.path().join("test.db"), crypto.clone()).await.unwrap();
+    (db, crypto, temp_dir)
+}
+
+/// A class as another device exports it.
+pub fn class_record(id: i64, name: &str) -> Value {
+    let now = chrono::Utc::now();
+    json!({
+        "id": id, "name": name, "school_year": "2023/24",
+        "created_at": now, "updated_at": now, "source_device_id": OTHER_DEVICE
+    })
+}
+
+pub fn student_record(id: i64, class_id: i64, first_name: &str, last_name: &str) -> Value {
+    let now = chrono::Utc::now();
+    json!({
+        "id": id, "class_id": class_id, "first_name": first_name, "last_name": last_name, "status": "active",
+        "created_at": now, "updated_at": now, "source_device_id": OTHER_DEVICE
+    })
+}
+
+/// Tags are a JSON string in exports, as in the database.
+pub fn observation_record(id: i64, student_id: i64, text: &str) -> Value {
+    let now = chrono::Utc::now();
+    json!({
+        "id": id, "student_id": student_id, "author_id": 1, "category": "Sozial", "text": text,
+        "tags": "[]", "created_at": now, "updated_at": now, "source_device_id": OTHER_DEVICE
+    })
+}
+
+/// Format versions of full exports the import accepts.
+#[derive(Debug, Clone, Copy)]
+pub enum FullExportVersion {
+    /// Only a data section, as written before exports had a format marker
+    Legacy,
+    /// "full_export" 1.0 without a manifest
+    V1,
+    /// "full_export" 1.0 with the manifest current exports carry
+    V1WithManifest,
+}
+
+pub fn full_export(data: Value, version: FullExportVersion) -> Value {
+    match version {
+        FullExportVersion::Legacy => json!({ "data": data }),
+        FullExportVersion::V1 => json!({ "format": "full_export", "version": "1.0", "data": data }),
+        FullExportVersion::V1WithManifest => json!({
+            "format": "full_export",
+            "version": "1.0",
+            "timestamp": chrono::Utc::now(),
+            "manifest": ExportManifest::describe(&data),
+            "data": data
+        }),
+    }
+}
+
+/// A changeset file around `data`, with the checksum import checks. `data`
+/// is taken as given, so tests can leave out or break parts of it.
+pub fn changeset_file(data: Value) -> Vec<u8> {
+    json!({ "checksum": changeset_checksum(&data), "data": data }).to_string().into_bytes()
+}
+
+/// A "changeset_file_v1" as `create_changeset_file` writes it.
+pub fn changeset_v1(device_id: &str, logical_clock: i64, changes: Value) -> Vec<u8> {
+    changeset_file(json!({
+        "format": "changeset_file_v1",
+        "version": "1.0",
+        "operation_id": uuid::Uuid::new_v4().to_string(),
+        "timestamp": chrono::Utc::now(),
+        "device_id": device_id,
+        "logical_clock": logical_clock,
+        "days_back": 30,
+        "manifest": ExportManifest::describe(&changes),
+        "changes": changes
+    }))
+}
+
+/// Class 5a with Max Mustermann and Anna Schmidt and one observation each,
+/// created through the database like a user would.
+pub struct SampleClass {
+    pub class: Class,
+    pub max: Student,
+    pub anna: Student,
+    pub observations: Vec<Observation>,
+}
+
+impl SampleClass {
+    pub async fn create(db: &Database) -> SampleClass {
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let mut observations = Vec::new();
+        for (student, text) in [(&max, "Hilft anderen"), (&anna, "Liest vor")] {
+            observations.push(
+                db.create_observation(student.id, 1, "social".to_string(), text.to_string(), vec![])
+                    .await
+                    .unwrap(),
+            );
+        }
+        SampleClass { class, max, anna, observations }
+    }
+
+    /// The records as a full export, to give a second device the same state.
+    pub fn full_export(&self, version: FullExportVersion) -> Vec<u8> {
+        let data = json!({
+            "classes": [&self.class],
+            "students": [&self.max, &self.anna],
+            "observations": &self.observations
+        });
+        full_export(data, version).to_string().into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::CancellationToken;
+
+    #[tokio::test]
+    async fn test_every_export_version_imports() {
+        let versions = [FullExportVersion::Legacy, FullExportVersion::V1, FullExportVersion::V1WithManifest];
+        let (source, _source_dir) = test_db().await;
+        let sample = SampleClass::create(&source).await;
+
+        for version in versions {
+            let (db, _temp_dir) = test_db().await;
+            db.import_full_backup(&sample.full_export(version), &CancellationToken::new())
+                .await
+                .unwrap_or_else(|e| panic!("{:?}: {}", version, e));
+            assert_eq!(db.get_students().await.unwrap().len(), 2, "{:?}", version);
+            assert_eq!(db.search_observations(None, None, None).await.unwrap().len(), 2, "{:?}", version);
+
+            let changeset = changeset_v1(
+                OTHER_DEVICE,
+                100,
+                json!({ "observations": [observation_record(50, sample.max.id, "Aus dem Changeset")], "tombstones": [] }),
+            );
+            let result = db.apply_changeset_file(&changeset).await.unwrap();
+            assert!(result.contains("Successfully imported 1 observations"), "{}", result);
+        }
+
+        let records = json!({"classes": [class_record(3, "6b")], "students": [student_record(7, 3, "Erika", "Muster")]});
+        let (db, _temp_dir) = test_db().await;
+        let export = full_export(records, FullExportVersion::V1WithManifest).to_string();
+        db.import_full_backup(export.as_bytes(), &CancellationToken::new()).await.unwrap();
+        assert_eq!(db.get_students_by_class(3).await.unwrap()[0].first_name, "Erika");
+    }
+}
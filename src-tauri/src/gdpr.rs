@@ -1,7 +1,11 @@
-use crate::database::Database;
-use crate::{Observation, Student};
+use crate::audit::{AuditLogger, TransferDestination};
+use crate::pdf::{self, Font, ReportWriter};
+use crate::database::{CompetencyLink, Database, MetricPoint, NamedRubricScore};
+use crate::manifest::ExportManifest;
+use crate::{ClassAgreement, ErasureRequest, Guardian, Observation, Student, StudentGoal};
 use anyhow::{Context, Result};
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::collections::BTreeMap;
 use serde_json::{json, Value};
 
 pub struct GdprManager;
@@ -10,9 +14,94 @@ pub struct GdprManager;
 pub struct StudentExport {
     pub student: Student,
     pub observations: Vec<Observation>,
+    #[serde(default)]
+    pub guardians: Vec<Guardian>,
+    /// Seats and groups of the class layout naming the student, nothing else of it
+    #[serde(default)]
+    pub seating: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub rubric_scores: Vec<NamedRubricScore>,
+    #[serde(default)]
+    pub competencies: Vec<CompetencyLink>,
+    #[serde(default)]
+    pub metrics: Vec<MetricPoint>,
+    #[serde(default)]
+    pub goals: Vec<StudentGoal>,
+    /// Reference codes of the student and their observations, as reports and
+    /// letters print them, see `reference_codes`
+    #[serde(default)]
+    pub reference_code: Option<String>,
+    #[serde(default)]
+    pub observation_references: BTreeMap<i64, String>,
     pub export_timestamp: DateTime<Utc>,
     pub export_reason: String,
     pub data_controller: String,
+    #[serde(default)]
+    pub scope: Option<ExportScope>, // Set for partial exports only
+}
+
+/// Limits an export to part of the observations, e.g. when parents only ask
+/// for everything about one incident. Student and guardian data are always
+/// included.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct ExportScope {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>, // Exclusive
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+impl ExportScope {
+    /// `from` and `to` are dates (YYYY-MM-DD), both days included.
+    pub fn parse(from: Option<&str>, to: Option<&str>, categories: Vec<String>) -> Result<Self> {
+        let day = |date: &str| -> Result<DateTime<Utc>> {
+            let date = chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+                .with_context(|| format!("Invalid date '{}', expected YYYY-MM-DD", date))?;
+            Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()))
+        };
+        let scope = Self {
+            from: from.map(day).transpose()?,
+            to: to.map(|to| day(to).map(|to| to + Duration::days(1))).transpose()?,
+            categories: categories.into_iter().filter(|c| !c.trim().is_empty()).collect(),
+        };
+        if let (Some(from), Some(to)) = (scope.from, scope.to) {
+            if from >= to {
+                return Err(anyhow::anyhow!("The export period ends before it starts"));
+            }
+        }
+        Ok(scope)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.from.is_none() && self.to.is_none() && self.categories.is_empty()
+    }
+
+    pub fn matches(&self, observation: &Observation) -> bool {
+        self.from.map_or(true, |from| observation.created_at >= from)
+            && self.to.map_or(true, |to| observation.created_at < to)
+            && (self.categories.is_empty() || self.categories.contains(&observation.category))
+    }
+
+    /// The exact scope in words, for the export metadata and the audit log.
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        match (self.from, self.to) {
+            (None, None) => {}
+            (from, to) => parts.push(format!(
+                "observations from {} to {}",
+                from.map_or("the beginning".to_string(), |from| from.format("%Y-%m-%d").to_string()),
+                to.map_or("today".to_string(), |to| (to - Duration::days(1)).format("%Y-%m-%d").to_string())
+            )),
+        }
+        if !self.categories.is_empty() {
+            parts.push(format!("categories {}", self.categories.join(", ")));
+        }
+        if parts.is_empty() {
+            "complete".to_string()
+        } else {
+            parts.join("; ")
+        }
+    }
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -23,6 +112,164 @@ pub struct DataRetentionPolicy {
     pub anonymization_after_days: i32,
 }
 
+/// One logged access to a student's data.
+#[derive(Debug, serde::Serialize)]
+pub struct AccessRecord {
+    pub timestamp: DateTime<Utc>,
+    pub kind: String, // "read", "write" or "export"
+    pub action: String,
+    pub object_type: String,
+    pub object_id: i64,
+    pub object_reference: Option<String>, // Reference code of students and observations
+    pub user_id: i64,
+    pub user_name: Option<String>,
+    pub description: String, // For the data subject, see `describe_access`
+    pub clock_note: Option<String>, // Set when the time may be wrong
+}
+
+/// Who accessed a student's data, for answers under GDPR Article 15.
+/// Covers the student record, observations, attachments, guardians, goals,
+/// exports of the student and exports of the student's classes and
+/// sessions, as far as the audit log still holds them.
+#[derive(Debug, serde::Serialize)]
+pub struct AccessHistory {
+    pub student_id: i64,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>, // Exclusive
+    pub generated_at: DateTime<Utc>,
+    pub records: Vec<AccessRecord>, // Oldest first
+}
+
+impl AccessHistory {
+    /// Appends the history as a section of a PDF report.
+    pub fn write_to(&self, report: &mut ReportWriter) {
+        report.gap();
+        report.line(50.0, 12.0, Font::Bold, &format!("Zugriffe ({})", self.records.len()));
+        let period = match (self.from, self.to) {
+            (None, None) => "Gesamter Zeitraum des Protokolls".to_string(),
+            (from, to) => format!(
+                "Zeitraum {} bis {}",
+                from.map_or("Beginn".to_string(), |from| from.format("%d.%m.%Y").to_string()),
+                to.map_or("heute".to_string(), |to| (to - Duration::days(1)).format("%d.%m.%Y").to_string())
+            ),
+        };
+        report.line(50.0, 9.0, Font::Regular, &period);
+        for record in &self.records {
+            let user = record.user_name.clone().unwrap_or_else(|| format!("Benutzer {}", record.user_id));
+            let kind = match record.kind.as_str() {
+                "read" => "Lesen",
+                "export" => "Export",
+                _ => "Änderung",
+            };
+            let clock = if record.clock_note.is_some() { " (Uhrzeit unsicher)" } else { "" };
+            report.line(
+                60.0,
+                9.0,
+                Font::Regular,
+                &format!("{}{} · {} · {}", record.timestamp.format("%d.%m.%Y %H:%M"), clock, kind, user),
+            );
+            let description = match &record.object_reference {
+                Some(reference) => format!("{} ({})", record.description, reference),
+                None => record.description.clone(),
+            };
+            for line in pdf::wrap_text(&description, 88) {
+                report.line(70.0, 8.0, Font::Regular, &line);
+            }
+        }
+    }
+}
+
+/// Reads, exports and everything else, which changes data.
+fn access_kind(action: &str) -> &'static str {
+    match action {
+        "read" | "view" => "read",
+        "export" | "convert" => "export",
+        _ => "write",
+    }
+}
+
+/// What an audit entry means for the student, in words for the data subject.
+/// The raw details are internal: they hold file paths, program names and
+/// counts of other records, so only the format of an export and the week of
+/// a summary are taken from them.
+fn describe_access(action: &str, object_type: &str, details: Option<&str>) -> String {
+    let object = match object_type {
+        "student" => "Stammdaten",
+        "student_data" => "Auskunftsexport der Daten",
+        "archive_bundle" => "Archivpaket der Daten",
+        "support_plan" => "Förderplan",
+        "metric_series" => "Verlauf einer Messreihe",
+        "observation" => "Beobachtung",
+        "attachment" => "Anhang einer Beobachtung",
+        "guardian" => "Angaben zu Erziehungsberechtigten",
+        "student_goal" => "Förderziel",
+        "substitute_brief" => "Vertretungsinfo der Klasse",
+        "class_photos" => "Fotos der Klasse",
+        "weekly_summary" => "Wochenübersicht der Klasse",
+        "handover_package" => "Übergabepaket der Klasse",
+        "observation_session" => "Beobachtungsstunde mit Beobachtungen",
+        _ => "Daten",
+    };
+    let verb = match action {
+        "create" => "angelegt",
+        "update" | "measure" | "score" | "link_competencies" => "geändert",
+        "delete" => "gelöscht",
+        "read" | "view" => "angesehen",
+        "export" | "convert" => "exportiert",
+        "transfer" => "in eine andere Klasse übernommen",
+        "set_visibility" => "in der Sichtbarkeit geändert",
+        "transcribe" => "transkribiert",
+        _ => "bearbeitet",
+    };
+    let detail = details.and_then(|d| d.split([' ', ',']).next()).filter(|d| !d.is_empty());
+    match (object_type, detail) {
+        ("student_data", Some(format @ ("json" | "csv"))) => format!("{} {} ({})", object, verb, format.to_uppercase()),
+        ("weekly_summary", Some(week)) if week.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') => {
+            format!("{} {} ({})", object, verb, week)
+        }
+        _ => format!("{} {}", object, verb),
+    }
+}
+
+/// When one kind of a student's records is next deleted or anonymized.
+#[derive(Debug, serde::Serialize)]
+pub struct RetentionDeadline {
+    pub category: String, // "observations", "attachments" or "audit"
+    pub action: String,   // "delete" or "anonymize"
+    pub records: i64,
+    pub due_at: Option<DateTime<Utc>>, // None while the countdown has not started
+    pub basis: String,
+}
+
+/// What the retention policy means for one student, for hints like
+/// "wird gelöscht am …".
+#[derive(Debug, serde::Serialize)]
+pub struct RetentionStatus {
+    pub student_id: i64,
+    pub student_status: String,
+    pub countdown_started_at: Option<DateTime<Utc>>, // When the student left or will leave
+    pub erasure_request: Option<ErasureRequest>,
+    pub deadlines: Vec<RetentionDeadline>,
+}
+
+/// What one pass of `GdprManager::apply_retention_policy` removed or anonymized.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RetentionRun {
+    pub deleted_observations: Vec<i64>,
+    pub attachments_deleted: i64,
+    pub observations_anonymized: i64,
+}
+
+/// When the countdown of a student's observations and attachments starts:
+/// the last school day, or for students no longer active without one, when
+/// they were added. Neither moves when the record is edited later.
+fn countdown_start(student: &Student) -> Option<DateTime<Utc>> {
+    match student.left_at {
+        Some(left_at) => Some(Utc.from_utc_datetime(&left_at.and_hms_opt(0, 0, 0).unwrap())),
+        None => (student.status != "active").then_some(student.created_at),
+    }
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct GdprComplianceReport {
     pub total_students: i64,
@@ -31,10 +278,39 @@ pub struct GdprComplianceReport {
     pub observations_count: i64,
     pub oldest_observation: Option<DateTime<Utc>>,
     pub data_retention_policy: DataRetentionPolicy,
+    pub class_agreements: Vec<ClassAgreement>,
     pub compliance_status: String,
     pub recommendations: Vec<String>,
 }
 
+/// A place on this device where personal data is kept.
+#[derive(Debug, serde::Serialize)]
+pub struct DataStore {
+    pub name: String,
+    pub location: String,
+    pub contents: String,
+    pub encrypted: bool,
+    pub present: bool,
+}
+
+/// A way data can leave or enter the device.
+#[derive(Debug, serde::Serialize)]
+pub struct DataTransport {
+    pub name: String,
+    pub enabled: bool,
+    pub description: String,
+}
+
+/// Where personal data is stored and how it moves, for the school's record of
+/// processing activities (Art. 30 GDPR).
+#[derive(Debug, serde::Serialize)]
+pub struct DataFlowReport {
+    pub generated_at: DateTime<Utc>,
+    pub stores: Vec<DataStore>,
+    pub transports: Vec<DataTransport>,
+    pub transfers: Vec<TransferDestination>,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct DeletionResult {
     pub success: bool,
@@ -54,6 +330,15 @@ impl GdprManager {
         &self,
         db: &Database,
         student_id: i64,
+    ) -> Result<StudentExport> {
+        self.export_student_data_in_scope(db, student_id, &ExportScope::default()).await
+    }
+
+    pub async fn export_student_data_in_scope(
+        &self,
+        db: &Database,
+        student_id: i64,
+        scope: &ExportScope,
     ) -> Result<StudentExport> {
         // Get student information
         let students = db.get_students().await?;
@@ -63,14 +348,51 @@ impl GdprManager {
             .context("Student not found")?;
 
         // Get all observations for the student
-        let observations = db.search_observations(None, Some(student_id), None).await?;
-
+        let mut observations = db.search_observations(None, Some(student_id), None).await?;
+        observations.retain(|observation| scope.matches(observation));
+        let guardians = db.get_guardians(student_id).await?;
+        let seating = match db.get_class_layout(student.class_id).await? {
+            Some(layout) => serde_json::from_str(&layout.layout)
+                .map(|layout| crate::class_layout::positions_of(&layout, student_id))
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let mut rubric_scores = db.get_student_rubric_scores(student_id).await?;
+        rubric_scores.retain(|score| observations.iter().any(|o| o.id == score.observation_id));
+        let mut competencies = db.get_student_competency_links(student_id).await?;
+        competencies.retain(|link| observations.iter().any(|o| o.id == link.observation_id));
+        let mut metrics = db.get_student_metrics(student_id).await?;
+        metrics.retain(|point| observations.iter().any(|o| o.id == point.observation_id));
+        let goals = db.get_student_goals(student_id).await?;
+        // Read only, so the export runs on the read-only handle; codes are
+        // assigned beforehand, see `reference_codes::assign`
+        let reference_code = db.get_reference_codes("student", &[student_id]).await?.remove(&student_id);
+        let observation_ids: Vec<i64> = observations.iter().map(|o| o.id).collect();
+        let observation_references = db.get_reference_codes("observation", &observation_ids).await?;
+
+        let (export_reason, scope) = if scope.is_full() {
+            ("Data subject request (GDPR Article 15)".to_string(), None)
+        } else {
+            (
+                format!("Data subject request (GDPR Article 15), limited to {}", scope.describe()),
+                Some(scope.clone()),
+            )
+        };
         let export = StudentExport {
             student,
             observations,
+            guardians,
+            seating,
+            rubric_scores,
+            competencies,
+            metrics,
+            goals,
+            reference_code,
+            observation_references,
             export_timestamp: Utc::now(),
-            export_reason: "Data subject request (GDPR Article 15)".to_string(),
+            export_reason,
             data_controller: "Educational Institution".to_string(),
+            scope,
         };
 
         Ok(export)
@@ -82,27 +404,36 @@ impl GdprManager {
         student_id: i64,
     ) -> Result<String> {
         let export = self.export_student_data(db, student_id).await?;
+        self.render_student_export_csv(db, &export).await
+    }
+
+    /// The export as CSV, after scope and redaction were applied to it.
+    pub async fn render_student_export_csv(&self, db: &Database, export: &StudentExport) -> Result<String> {
+        let users: BTreeMap<i64, String> = db.get_users().await?.into_iter().map(|u| (u.id, u.name)).collect();
         
         let mut csv = String::new();
-        csv.push_str("export_timestamp,data_controller,export_reason,student_id,first_name,last_name,class_id,status,student_created_at,observation_id,observation_text,category,tags,observation_created_at\n");
+        csv.push_str("export_timestamp,data_controller,export_reason,student_id,student_reference,first_name,last_name,class_id,status,student_created_at,observation_id,observation_reference,observation_text,category,tags,observation_created_at,reported_by\n");
         
         for observation in &export.observations {
             let row = format!(
-                "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
                 export.export_timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
                 export.data_controller,
                 export.export_reason,
                 export.student.id,
+                export.reference_code.as_deref().unwrap_or(""),
                 export.student.first_name,
                 export.student.last_name,
                 export.student.class_id,
                 export.student.status,
                 export.student.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
                 observation.id,
+                export.observation_references.get(&observation.id).map(String::as_str).unwrap_or(""),
                 observation.text.replace(',', ";").replace('\n', " "),
                 observation.category,
                 observation.tags.replace(',', ";"),
-                observation.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+                observation.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                observation.reporter(&users).unwrap_or_default().replace(',', ";")
             );
             csv.push_str(&row);
         }
@@ -110,11 +441,12 @@ impl GdprManager {
         // If no observations, still show student data
         if export.observations.is_empty() {
             let row = format!(
-                "{},{},{},{},{},{},{},{},{},,,,,\n",
+                "{},{},{},{},{},{},{},{},{},{},,,,,,,\n",
                 export.export_timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
                 export.data_controller,
                 export.export_reason,
                 export.student.id,
+                export.reference_code.as_deref().unwrap_or(""),
                 export.student.first_name,
                 export.student.last_name,
                 export.student.class_id,
@@ -124,6 +456,32 @@ impl GdprManager {
             csv.push_str(&row);
         }
 
+        let student = &export.student;
+        if student.date_of_birth.is_some() || student.notes.is_some() || student.external_id.is_some() {
+            csv.push_str("\ndate_of_birth,external_id,notes\n");
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                student.date_of_birth.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+                student.external_id.as_deref().unwrap_or("").replace(',', ";"),
+                student.notes.as_deref().unwrap_or("").replace(',', ";").replace('\n', " ")
+            ));
+        }
+
+        if !export.guardians.is_empty() {
+            csv.push_str("\nguardian_id,name,contact,relationship,consent_reference,guardian_created_at\n");
+            for guardian in &export.guardians {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    guardian.id,
+                    guardian.name.replace(',', ";"),
+                    guardian.contact.as_deref().unwrap_or("").replace(',', ";"),
+                    guardian.relationship.replace(',', ";"),
+                    guardian.consent_reference.as_deref().unwrap_or("").replace(',', ";"),
+                    guardian.created_at.format("%Y-%m-%d %H:%M:%S UTC")
+                ));
+            }
+        }
+
         Ok(csv)
     }
 
@@ -160,6 +518,7 @@ impl GdprManager {
         // Get observation count before deletion
         let observations = db.search_observations(None, Some(student_id), None).await?;
         let observation_count = observations.len() as i64;
+        let guardian_count = db.get_guardians(student_id).await?.len();
 
         // Perform hard delete
         db.delete_student(student_id, true).await?;
@@ -170,28 +529,156 @@ impl GdprManager {
             deletion_type: "hard".to_string(),
             observations_deleted: observation_count,
             timestamp: Utc::now(),
-            notes: Some("Hard delete - all data permanently removed (GDPR Article 17)".to_string()),
+            notes: Some(format!(
+                "Hard delete - all data permanently removed including {} guardian records (GDPR Article 17)",
+                guardian_count
+            )),
         })
     }
 
+    /// First step of the two-person rule: any user may request a hard deletion.
+    pub async fn request_erasure(
+        &self,
+        db: &Database,
+        student_id: i64,
+        requested_by: i64,
+        reason: &str,
+    ) -> Result<ErasureRequest> {
+        if reason.trim().is_empty() {
+            return Err(anyhow::anyhow!("A reason is required for an erasure request"));
+        }
+        db.create_erasure_request(student_id, requested_by, reason, None).await
+    }
+
+    /// An admin schedules the erasure for a later date, e.g. after the notice
+    /// period owed to the parents. Like any erasure it waits for a second
+    /// admin, see `approve_scheduled_erasure`, unless the admin is the only
+    /// user; once approved the scheduler carries it out at `execute_at`.
+    pub async fn schedule_erasure(
+        &self,
+        db: &Database,
+        student_id: i64,
+        execute_at: DateTime<Utc>,
+        admin_id: i64,
+        reason: &str,
+    ) -> Result<ErasureRequest> {
+        db.require_admin(admin_id).await?;
+        if reason.trim().is_empty() {
+            return Err(anyhow::anyhow!("A reason is required for an erasure request"));
+        }
+        if execute_at <= Utc::now() {
+            return Err(anyhow::anyhow!("The erasure date must be in the future"));
+        }
+        let request = db.create_erasure_request(student_id, admin_id, reason, Some(execute_at)).await?;
+        if db.get_users().await?.len() > 1 {
+            return Ok(request);
+        }
+        db.approve_scheduled_erasure_request(request.id, admin_id).await
+    }
+
+    /// Second step for a scheduled erasure: an admin other than the one who
+    /// scheduled it confirms, and the scheduler may carry it out when due.
+    pub async fn approve_scheduled_erasure(&self, db: &Database, request_id: i64, admin_id: i64) -> Result<ErasureRequest> {
+        let request = self.pending_erasure(db, request_id, admin_id).await?;
+        if request.execute_at.is_none() {
+            return Err(anyhow::anyhow!("Erasure request {} is not scheduled; use approve_erasure", request_id));
+        }
+        if request.approved_by.is_some() {
+            return Err(anyhow::anyhow!("Erasure request {} is already approved", request_id));
+        }
+        db.approve_scheduled_erasure_request(request_id, admin_id).await
+    }
+
+    /// Carries out a scheduled erasure that has reached its date by `now`.
+    pub async fn execute_scheduled_erasure(
+        &self,
+        db: &Database,
+        request: &ErasureRequest,
+        now: DateTime<Utc>,
+    ) -> Result<DeletionResult> {
+        if request.execute_at.map_or(true, |at| at > now) {
+            return Err(anyhow::anyhow!("Erasure request {} is not due", request.id));
+        }
+        if request.approved_by.is_none() {
+            return Err(anyhow::anyhow!("Erasure request {} has not been approved", request.id));
+        }
+
+        let result = self.delete_student_hard(db, request.student_id).await?;
+        db.decide_erasure_request(request.id, "executed", request.requested_by).await?;
+        Ok(result)
+    }
+
+    /// Second step: an admin other than the requester confirms, and only then
+    /// is the student deleted.
+    pub async fn approve_erasure(
+        &self,
+        db: &Database,
+        request_id: i64,
+        admin_id: i64,
+    ) -> Result<(ErasureRequest, DeletionResult)> {
+        let request = self.pending_erasure(db, request_id, admin_id).await?;
+        if request.execute_at.is_some() {
+            return Err(anyhow::anyhow!(
+                "Erasure request {} is scheduled; use approve_scheduled_erasure",
+                request_id
+            ));
+        }
+
+        let result = self.delete_student_hard(db, request.student_id).await?;
+        db.decide_erasure_request(request_id, "executed", admin_id).await?;
+
+        Ok((request, result))
+    }
+
+    pub async fn reject_erasure(&self, db: &Database, request_id: i64, admin_id: i64) -> Result<ErasureRequest> {
+        let request = self.pending_erasure(db, request_id, admin_id).await?;
+        db.decide_erasure_request(request_id, "rejected", admin_id).await?;
+        Ok(request)
+    }
+
+    async fn pending_erasure(&self, db: &Database, request_id: i64, admin_id: i64) -> Result<ErasureRequest> {
+        db.require_admin(admin_id).await?;
+        let request = db
+            .get_erasure_request(request_id)
+            .await?
+            .context("Erasure request not found")?;
+
+        if request.status != "pending" {
+            return Err(anyhow::anyhow!("Erasure request {} is already {}", request_id, request.status));
+        }
+        if request.requested_by == admin_id {
+            return Err(anyhow::anyhow!("An erasure must be confirmed by a second user"));
+        }
+        Ok(request)
+    }
+
+    /// Replaces the identifying passages in the texts of observations older
+    /// than the threshold with the output of `redaction::anonymizer`. Category,
+    /// date and tags stay for statistics. Returns how many were anonymized.
     pub async fn anonymize_old_data(
         &self,
         db: &Database,
         anonymization_threshold_days: i32,
     ) -> Result<i64> {
-        // This would normally anonymize personal data after a certain period
-        // For now, we'll simulate by checking how many records would be affected
         let cutoff_date = Utc::now() - Duration::days(anonymization_threshold_days as i64);
-        
-        let old_observations = db.get_observations_since(cutoff_date).await?;
-        let affected_count = old_observations.len() as i64;
+        self.anonymize_observations_before(db, cutoff_date).await
+    }
 
-        // In a real implementation, you would:
-        // 1. Replace personal identifiers with hashed versions
-        // 2. Remove detailed text that could identify students
-        // 3. Keep statistical data for research purposes
+    async fn anonymize_observations_before(&self, db: &Database, cutoff: DateTime<Utc>) -> Result<i64> {
+        let old_observations = db.get_observations_to_anonymize(cutoff).await?;
+        if old_observations.is_empty() {
+            return Ok(0);
+        }
 
-        Ok(affected_count)
+        // The identifying passages of the texts, from the anonymization dictionary
+        let anonymizer = crate::redaction::anonymizer(db).await?;
+        for observation in &old_observations {
+            let (text, replacements) = anonymizer.redact(&observation.text);
+            db.anonymize_observation(observation.id, (replacements > 0).then_some(text.as_str()))
+                .await?;
+        }
+
+        Ok(old_observations.len() as i64)
     }
 
     pub async fn generate_compliance_report(
@@ -237,6 +724,19 @@ impl GdprManager {
             );
         }
 
+        // Photos of a class without the parents' permission on file
+        let class_agreements = db.get_class_agreements(None).await?;
+        let today = Utc::now().date_naive();
+        for class_id in db.get_class_ids_with_photos().await? {
+            if db.get_class_agreement_in_force(class_id, "photo", today).await?.is_none() {
+                let class_name = db.get_class(class_id).await?.map(|c| c.name).unwrap_or_default();
+                recommendations.push(format!(
+                    "Class {} has photos attached to observations but no photo agreement in force",
+                    class_name
+                ));
+            }
+        }
+
         let compliance_status = if recommendations.is_empty() {
             "Compliant".to_string()
         } else {
@@ -250,11 +750,82 @@ impl GdprManager {
             observations_count,
             oldest_observation,
             data_retention_policy: policy,
+            class_agreements,
             compliance_status,
             recommendations,
         })
     }
 
+    /// Describes the data flows from the live configuration rather than from
+    /// documentation, so the report matches what this installation actually does.
+    pub async fn generate_data_flow_report(
+        &self,
+        db: &Database,
+        audit: &AuditLogger,
+    ) -> Result<DataFlowReport> {
+        let attachment_storage = db.get_attachment_storage_mode().await?;
+        let transcription_configured = crate::transcription::configured_transcriber(db).await?.is_some();
+
+        let store = |name: &str, location: &std::path::Path, contents: &str, encrypted: bool| DataStore {
+            name: name.to_string(),
+            location: location.display().to_string(),
+            contents: contents.to_string(),
+            encrypted,
+            present: location.exists(),
+        };
+
+        let stores = vec![
+            store(
+                "Database",
+                db.db_path(),
+                &format!(
+                    "Classes, students, observations, dictionary and settings; imports staged for review are encrypted; attachments stored in: {}",
+                    attachment_storage
+                ),
+                false,
+            ),
+            store("Attachment files", db.attachments_dir().as_path(), "Attachments of observations (external storage mode)", true),
+            store("Thumbnails", db.thumbnails_dir().as_path(), "Preview images of image attachments", true),
+            store("Snapshots", db.snapshots_dir().as_path(), "Complete copies of the database for rollback", false),
+            store("Audit log", audit.db_path(), "Record of accesses, exports, imports and deletions", false),
+        ];
+
+        let transports = vec![
+            DataTransport {
+                name: "Changeset files".to_string(),
+                enabled: true,
+                description: "Observations and deletions are exchanged between devices as files chosen by the user".to_string(),
+            },
+            DataTransport {
+                name: "Full exports and backups".to_string(),
+                enabled: true,
+                description: "Complete data exports written to files chosen by the user".to_string(),
+            },
+            DataTransport {
+                name: "Network synchronisation".to_string(),
+                enabled: false,
+                description: "Not available; devices never connect to each other directly".to_string(),
+            },
+            DataTransport {
+                name: "Speech recognition".to_string(),
+                enabled: transcription_configured,
+                description: "Audio attachments are transcribed by a local whisper.cpp installation; no data leaves the device".to_string(),
+            },
+            DataTransport {
+                name: "Clipboard".to_string(),
+                enabled: true,
+                description: "Images can be pasted as attachments; nothing is copied to the clipboard".to_string(),
+            },
+        ];
+
+        Ok(DataFlowReport {
+            generated_at: Utc::now(),
+            stores,
+            transports,
+            transfers: audit.get_transfer_destinations().await?,
+        })
+    }
+
     pub async fn validate_data_subject_request(
         &self,
         _student_id: i64,
@@ -279,6 +850,207 @@ impl GdprManager {
         }
     }
 
+    /// Audit entries about a student between `from` and `to` (exclusive).
+    /// Entries about observations or attachments deleted since cannot be told
+    /// apart from others' and are missing.
+    pub async fn get_access_history(
+        &self,
+        db: &Database,
+        audit: &AuditLogger,
+        student_id: i64,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<AccessHistory> {
+        let student = db.get_student(student_id).await?.context("Student not found")?;
+        let observations = db.search_observations(None, Some(student_id), None).await?;
+        let observation_ids: Vec<i64> = observations.iter().map(|o| o.id).collect();
+        let mut session_ids: Vec<i64> = observations.iter().filter_map(|o| o.session_id).collect();
+        session_ids.sort();
+        session_ids.dedup();
+        // Class exports cover the student in every class it attended
+        let mut class_ids = vec![student.class_id];
+        for transfer in db.get_student_transfers(student_id).await? {
+            class_ids.extend([transfer.from_class_id, transfer.to_class_id]);
+        }
+        class_ids.sort();
+        class_ids.dedup();
+        let mut attachment_ids = Vec::new();
+        for observation_id in &observation_ids {
+            attachment_ids.extend(db.get_attachments(*observation_id).await?.iter().map(|a| a.id));
+        }
+        let guardian_ids = db.get_guardians(student_id).await?.iter().map(|g| g.id).collect();
+        let goal_ids = db.get_student_goals(student_id).await?.iter().map(|g| g.id).collect();
+        let student_code = crate::reference_codes::code_for(db, "student", student_id).await?;
+        let observation_codes = crate::reference_codes::codes_for(db, "observation", &observation_ids).await?;
+        let objects = [
+            ("student", vec![student_id]),
+            ("student_data", vec![student_id]),
+            ("archive_bundle", vec![student_id]),
+            ("support_plan", vec![student_id]),
+            ("metric_series", vec![student_id]),
+            ("observation", observation_ids),
+            ("attachment", attachment_ids),
+            ("guardian", guardian_ids),
+            ("student_goal", goal_ids),
+            ("substitute_brief", class_ids.clone()),
+            ("class_photos", class_ids.clone()),
+            ("weekly_summary", class_ids.clone()),
+            ("handover_package", class_ids),
+            ("observation_session", session_ids),
+        ];
+
+        let users: BTreeMap<i64, String> = db.get_users().await?.into_iter().map(|u| (u.id, u.name)).collect();
+        let records = audit
+            .get_entries_about(&objects, from, to)
+            .await?
+            .into_iter()
+            .map(|entry| AccessRecord {
+                timestamp: entry.timestamp,
+                kind: access_kind(&entry.action).to_string(),
+                object_reference: match entry.object_type.as_str() {
+                    "student" | "student_data" | "archive_bundle" | "support_plan" | "metric_series" => {
+                        Some(student_code.clone())
+                    }
+                    "observation" => observation_codes.get(&entry.object_id).cloned(),
+                    _ => None,
+                },
+                user_name: users.get(&entry.user_id).cloned(),
+                description: describe_access(&entry.action, &entry.object_type, entry.details.as_deref()),
+                action: entry.action,
+                object_type: entry.object_type,
+                object_id: entry.object_id,
+                user_id: entry.user_id,
+                clock_note: entry.clock_note,
+            })
+            .collect();
+
+        Ok(AccessHistory {
+            student_id,
+            from,
+            to,
+            generated_at: Utc::now(),
+            records,
+        })
+    }
+
+    /// Due dates of a student's observations, attachments and audit entries
+    /// under `get_data_retention_policy`, as `apply_retention_policy` carries
+    /// them out. Observations and attachments are kept while the student
+    /// attends; their countdown starts on the day they leave, see
+    /// `countdown_start`. A scheduled erasure comes first if it is earlier.
+    /// Audit entries age on their own, whatever the status.
+    pub async fn get_retention_status(
+        &self,
+        db: &Database,
+        audit: &AuditLogger,
+        student_id: i64,
+    ) -> Result<RetentionStatus> {
+        let student = db.get_student(student_id).await?.context("Student not found")?;
+        let policy = self.get_data_retention_policy();
+        let days = |days: i32| Duration::days(days as i64);
+
+        let countdown_started_at = countdown_start(&student);
+        let erasure_request = db
+            .get_open_requests()
+            .await?
+            .into_iter()
+            .find(|request| request.student_id == student_id);
+        let erasure = erasure_request
+            .as_ref()
+            .and_then(|request| request.execute_at.map(|at| (request.id, at)));
+        let deletion = |retention_days: i32| -> (Option<DateTime<Utc>>, String) {
+            let after_leaving = countdown_started_at.map(|at| at + days(retention_days));
+            match (after_leaving, erasure) {
+                (Some(due), Some((_, erasure_at))) if due <= erasure_at => {
+                    (Some(due), format!("{} days after the student left", retention_days))
+                }
+                (_, Some((request_id, erasure_at))) => (Some(erasure_at), format!("Erasure request {}", request_id)),
+                (Some(due), None) => (Some(due), format!("{} days after the student left", retention_days)),
+                (None, None) => (None, format!("{} days after the student leaves", retention_days)),
+            }
+        };
+
+        let mut deadlines = Vec::new();
+        let observations = db.search_observations(None, Some(student_id), None).await?;
+        let (deletion_at, basis) = deletion(policy.observation_retention_days);
+        // Observations still there after years are anonymized before they are deleted
+        if let Some(oldest) = observations.iter().map(|o| o.created_at).min() {
+            let anonymization_at = oldest + days(policy.anonymization_after_days);
+            if deletion_at.map_or(true, |deletion_at| anonymization_at < deletion_at) {
+                deadlines.push(RetentionDeadline {
+                    category: "observations".to_string(),
+                    action: "anonymize".to_string(),
+                    records: observations
+                        .iter()
+                        .filter(|o| o.created_at + days(policy.anonymization_after_days) <= anonymization_at)
+                        .count() as i64,
+                    due_at: Some(anonymization_at),
+                    basis: format!("{} days after the oldest observation", policy.anonymization_after_days),
+                });
+            }
+        }
+        deadlines.push(RetentionDeadline {
+            category: "observations".to_string(),
+            action: "delete".to_string(),
+            records: observations.len() as i64,
+            due_at: deletion_at,
+            basis,
+        });
+
+        let attachments = db.count_student_attachments(student_id).await?;
+        let (due_at, basis) = deletion(policy.attachment_retention_days);
+        deadlines.push(RetentionDeadline {
+            category: "attachments".to_string(),
+            action: "delete".to_string(),
+            records: attachments,
+            due_at,
+            basis,
+        });
+
+        let entries = audit.get_entries_about(&[("student", vec![student_id])], None, None).await?;
+        deadlines.push(RetentionDeadline {
+            category: "audit".to_string(),
+            action: "delete".to_string(),
+            records: entries.len() as i64,
+            due_at: entries.iter().map(|e| e.timestamp).min().map(|oldest| oldest + days(policy.audit_log_retention_days)),
+            basis: format!("{} days after the oldest entry, also after an erasure", policy.audit_log_retention_days),
+        });
+
+        Ok(RetentionStatus {
+            student_id,
+            student_status: student.status,
+            countdown_started_at,
+            erasure_request,
+            deadlines,
+        })
+    }
+
+    /// Deletes the attachments and observations of students who left once
+    /// their retention period after `countdown_start` has passed by `now`,
+    /// and anonymizes what is older than `anonymization_after_days`.
+    /// The student records stay until an erasure.
+    pub async fn apply_retention_policy(&self, db: &Database, now: DateTime<Utc>) -> Result<RetentionRun> {
+        let policy = self.get_data_retention_policy();
+        let days = |days: i32| Duration::days(days as i64);
+        let mut run = RetentionRun::default();
+
+        for student in db.get_students_no_longer_attending().await? {
+            let Some(started_at) = countdown_start(&student) else {
+                continue;
+            };
+            if started_at + days(policy.attachment_retention_days) <= now {
+                run.attachments_deleted += db.delete_student_attachments(student.id).await?;
+            }
+            if started_at + days(policy.observation_retention_days) <= now {
+                run.deleted_observations.extend(db.delete_student_observations(student.id).await?);
+            }
+        }
+        run.observations_anonymized = self
+            .anonymize_observations_before(db, now - days(policy.anonymization_after_days))
+            .await?;
+        Ok(run)
+    }
+
     pub async fn export_full_database(
         &self,
         db: &Database,
@@ -295,6 +1067,12 @@ impl GdprManager {
 
         let observations = db.search_observations(None, None, None).await?;
 
+        let data = json!({
+            "classes": classes,
+            "students": students,
+            "observations": observations
+        });
+
         let export = json!({
             "format": "full_export",
             "version": "1.0",
@@ -302,11 +1080,8 @@ impl GdprManager {
             "export_reason": "Full database backup",
             "data_controller": "Educational Institution",
             "include_deleted": include_deleted,
-            "data": {
-                "classes": classes,
-                "students": students,
-                "observations": observations
-            }
+            "manifest": ExportManifest::describe(&data),
+            "data": data
         });
 
         Ok(export)
@@ -358,15 +1133,10 @@ impl Default for GdprManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crypto::CryptoManager;
-    use std::sync::Arc;
     use tempfile::TempDir;
 
     async fn create_test_setup() -> (Database, GdprManager, TempDir) {
-        let temp_dir = TempDir::new().unwrap();
-        let db_path = temp_dir.path().join("test.db");
-        let crypto = Arc::new(CryptoManager::new().unwrap());
-        let db = Database::new(&db_path, crypto).await.unwrap();
+        let (db, temp_dir) = crate::fixtures::test_db().await;
         let gdpr = GdprManager::new();
         (db, gdpr, temp_dir)
     }
@@ -402,6 +1172,93 @@ mod tests {
         assert_eq!(export.data_controller, "Educational Institution");
     }
 
+    #[tokio::test]
+    async fn test_partial_export_by_period_and_category() {
+        let (db, gdpr, _temp_dir) = create_test_setup().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_observation(student.id, 1, "Verhalten".to_string(), "Streit auf dem Hof".to_string(), vec![]).await.unwrap();
+        db.create_observation(student.id, 1, "Fachlich".to_string(), "Rechnet sicher".to_string(), vec![]).await.unwrap();
+        db.create_observation(student.id, 1, "Verhalten".to_string(), "Entschuldigt sich".to_string(), vec![]).await.unwrap();
+
+        let march = ExportScope::parse(Some("2024-03-01"), Some("2024-03-31"), vec![]).unwrap();
+        let export = gdpr.export_student_data_in_scope(&db, student.id, &march).await.unwrap();
+        assert!(export.observations.is_empty());
+        assert_eq!(march.describe(), "observations from 2024-03-01 to 2024-03-31");
+        assert!(export.export_reason.ends_with(&march.describe()));
+        assert!(export.scope.is_some());
+
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        let scope = ExportScope::parse(Some(&today), Some(&today), vec!["Verhalten".to_string()]).unwrap();
+        let export = gdpr.export_student_data_in_scope(&db, student.id, &scope).await.unwrap();
+        assert_eq!(export.observations.len(), 2);
+        assert!(export.observations.iter().all(|o| o.category == "Verhalten"));
+
+        let full = gdpr.export_student_data(&db, student.id).await.unwrap();
+        assert_eq!(full.observations.len(), 3);
+        assert!(full.scope.is_none());
+
+        assert!(ExportScope::parse(Some("2024-03-31"), Some("2024-03-01"), vec![]).is_err());
+        assert!(ExportScope::parse(Some("14.03.2024"), None, vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_data_flow_report_lists_stores_and_export_destinations() {
+        let (db, gdpr, temp_dir) = create_test_setup().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+
+        let destination = "/media/usb/changeset.json";
+        audit.log_transfer("export", "changeset_file", 0, 1, Some(destination), destination).await.unwrap();
+        audit.log_transfer("export", "changeset_file", 0, 1, Some(destination), destination).await.unwrap();
+        audit.log_action("create", "observation", 1, 1, None).await.unwrap();
+        // Details name counts and notes as well, never a destination
+        audit.log_action("export", "metric_series", 1, 1, Some("3 values of Lesen to /tmp/a.csv")).await.unwrap();
+
+        let report = gdpr.generate_data_flow_report(&db, &audit).await.unwrap();
+
+        let database = report.stores.iter().find(|s| s.name == "Database").unwrap();
+        assert_eq!(database.location, db.db_path().display().to_string());
+        assert!(database.present);
+        assert!(!database.encrypted);
+        assert!(report.stores.iter().find(|s| s.name == "Thumbnails").unwrap().encrypted);
+        assert!(!report.transports.iter().find(|t| t.name == "Speech recognition").unwrap().enabled);
+
+        assert_eq!(report.transfers.len(), 1);
+        assert_eq!(report.transfers[0].destination, "/media/usb/changeset.json");
+        assert_eq!(report.transfers[0].uses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_erasure_needs_a_second_admin() {
+        let (db, gdpr, _temp_dir) = create_test_setup().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+
+        let request = gdpr.request_erasure(&db, student.id, teacher.id, "Antrag der Eltern").await.unwrap();
+        assert!(gdpr.request_erasure(&db, student.id, 1, "doppelt").await.is_err());
+        assert_eq!(db.get_open_requests().await.unwrap().len(), 1);
+
+        // Teachers cannot confirm, and neither can the admin who asked
+        assert!(gdpr.approve_erasure(&db, request.id, teacher.id).await.is_err());
+        let other = db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let own = gdpr.request_erasure(&db, other.id, 1, "Schulwechsel").await.unwrap();
+        assert!(gdpr.approve_erasure(&db, own.id, 1).await.is_err());
+        assert!(db.get_student(student.id).await.unwrap().is_some());
+
+        let (approved, result) = gdpr.approve_erasure(&db, request.id, 1).await.unwrap();
+        assert_eq!(approved.requested_by, teacher.id);
+        assert_eq!(result.deletion_type, "hard");
+        assert!(db.get_student(student.id).await.unwrap().is_none());
+
+        let request = db.get_erasure_request(request.id).await.unwrap().unwrap();
+        assert_eq!(request.status, "executed");
+        assert_eq!(request.decided_by, Some(1));
+        assert!(gdpr.approve_erasure(&db, request.id, 1).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_export_student_data_csv() {
         let (db, gdpr, _temp_dir) = create_test_setup().await;
@@ -422,6 +1279,7 @@ mod tests {
             "Shows excellent teamwork".to_string(),
             vec!["teamwork".to_string()]
         ).await.unwrap();
+        let code = crate::reference_codes::code_for(&db, "student", student.id).await.unwrap();
 
         // Export as CSV
         let csv = gdpr.export_student_data_csv(&db, student.id).await.unwrap();
@@ -433,6 +1291,7 @@ mod tests {
         assert!(lines[1].contains("Max"));
         assert!(lines[1].contains("Mustermann"));
         assert!(lines[1].contains("Shows excellent teamwork"));
+        assert!(lines[1].contains(&code));
     }
 
     #[tokio::test]
@@ -551,6 +1410,23 @@ mod tests {
         
         // Should have at least one recommendation
         assert!(!report.recommendations.is_empty());
+
+        let observation = db.search_observations(None, Some(student1.id), None).await.unwrap().remove(0);
+        db.store_attachment(observation.id, "tafel.png", "image/png", b"not really a png").await.unwrap();
+        let report = gdpr.generate_compliance_report(&db).await.unwrap();
+        assert!(report.recommendations.iter().any(|r| r.contains("Class 5a has photos")));
+
+        let agreement = crate::database::ClassAgreementInput {
+            kind: "photo".to_string(),
+            collected_on: chrono::NaiveDate::from_ymd_opt(2023, 9, 1).unwrap(),
+            valid_until: None,
+            reference: Some("Ordner 3".to_string()),
+            notes: None,
+        };
+        db.set_class_agreement(class.id, &agreement).await.unwrap();
+        let report = gdpr.generate_compliance_report(&db).await.unwrap();
+        assert_eq!(report.class_agreements.len(), 1);
+        assert!(!report.recommendations.iter().any(|r| r.contains("Class 5a has photos")));
     }
 
     #[tokio::test]
@@ -565,6 +1441,116 @@ mod tests {
         assert_eq!(policy.anonymization_after_days, 1095); // 3 years
     }
 
+    #[tokio::test]
+    async fn test_retention_countdown_starts_when_the_student_leaves() {
+        let (db, gdpr, temp_dir) = create_test_setup().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_observation(student.id, 1, "Sozial".to_string(), "Hilft".to_string(), vec![])
+            .await
+            .unwrap();
+        audit.log_action("update", "student", student.id, 1, None).await.unwrap();
+
+        let status = gdpr.get_retention_status(&db, &audit, student.id).await.unwrap();
+        assert!(status.countdown_started_at.is_none());
+        let deadline = |status: &RetentionStatus, category: &str, action: &str| {
+            status
+                .deadlines
+                .iter()
+                .find(|d| d.category == category && d.action == action)
+                .map(|d| (d.records, d.due_at))
+        };
+        // Kept while active, anonymized after three years
+        assert_eq!(deadline(&status, "observations", "delete").unwrap(), (1, None));
+        let (_, anonymized_at) = deadline(&status, "observations", "anonymize").unwrap();
+        assert!(anonymized_at.unwrap() > Utc::now() + Duration::days(1094));
+        let (entries, audit_due) = deadline(&status, "audit", "delete").unwrap();
+        assert_eq!(entries, 1);
+        assert!(audit_due.unwrap() > Utc::now() + Duration::days(2554));
+
+        let left = db
+            .create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), Some("inactive".to_string()))
+            .await
+            .unwrap();
+        db.create_observation(left.id, 1, "Sozial".to_string(), "Hilft".to_string(), vec![])
+            .await
+            .unwrap();
+        let status = gdpr.get_retention_status(&db, &audit, left.id).await.unwrap();
+        let left_at = status.countdown_started_at.unwrap();
+        assert_eq!(deadline(&status, "observations", "delete").unwrap(), (1, Some(left_at + Duration::days(365))));
+        assert_eq!(deadline(&status, "attachments", "delete").unwrap(), (0, Some(left_at + Duration::days(365))));
+        assert!(deadline(&status, "observations", "anonymize").is_none());
+
+        // Known in advance for a student leaving at the end of the month
+        let last_day = Utc::now().date_naive() + Duration::days(20);
+        db.mark_student_left(student.id, last_day).await.unwrap();
+        let status = gdpr.get_retention_status(&db, &audit, student.id).await.unwrap();
+        let (_, due_at) = deadline(&status, "observations", "delete").unwrap();
+        assert_eq!(due_at.unwrap().date_naive(), last_day + Duration::days(365));
+
+        // An earlier scheduled erasure wins
+        let execute_at = Utc::now() + Duration::days(30);
+        let request = gdpr.schedule_erasure(&db, left.id, execute_at, 1, "Auf Wunsch der Eltern").await.unwrap();
+        let status = gdpr.get_retention_status(&db, &audit, left.id).await.unwrap();
+        assert_eq!(status.erasure_request.unwrap().id, request.id);
+        assert_eq!(deadline(&status, "observations", "delete").unwrap().1, Some(execute_at));
+        assert_eq!(deadline(&status, "audit", "delete").unwrap(), (0, None));
+
+        assert!(gdpr.get_retention_status(&db, &audit, 999).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_access_history_lists_entries_about_the_student() {
+        let (db, gdpr, temp_dir) = create_test_setup().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Albrecht".to_string(), None).await.unwrap();
+        let observation = db
+            .create_observation(max.id, 1, "Sozial".to_string(), "Hilft".to_string(), vec![])
+            .await
+            .unwrap();
+        let other = db
+            .create_observation(anna.id, 1, "Sozial".to_string(), "Liest vor".to_string(), vec![])
+            .await
+            .unwrap();
+
+        audit.log_action("create", "observation", observation.id, 1, None).await.unwrap();
+        audit.log_action("create", "observation", other.id, 1, None).await.unwrap();
+        audit.log_action("export", "student_data", max.id, 1, Some("json")).await.unwrap();
+        audit.log_action("transfer", "student", max.id, 1, Some("class 1 -> 2 as of 2024-09-01")).await.unwrap();
+        audit
+            .log_action("export", "substitute_brief", class.id, 1, Some("2 students, 1 pinned notes, 0 goals to /media/usb/vertretung.pdf"))
+            .await
+            .unwrap();
+        audit.log_action("export", "weekly_summary", class.id + 1, 1, Some("2024-W36")).await.unwrap();
+
+        let history = gdpr.get_access_history(&db, &audit, max.id, None, None).await.unwrap();
+        let kinds: Vec<(&str, &str)> =
+            history.records.iter().map(|r| (r.kind.as_str(), r.action.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![("write", "create"), ("export", "export"), ("write", "transfer"), ("export", "export")]
+        );
+        assert_eq!(history.records[1].description, "Auskunftsexport der Daten exportiert (JSON)");
+        // No file paths or counts of other students
+        assert_eq!(history.records[3].description, "Vertretungsinfo der Klasse exportiert");
+        assert!(history.records[0].user_name.is_some());
+        let year = Utc::now().format("%Y");
+        assert_eq!(history.records[0].object_reference, Some(format!("BE-{}-0001", year)));
+        assert_eq!(history.records[2].object_reference, Some(format!("ST-{}-0001", year)));
+
+        let tomorrow = Utc::now() + Duration::days(1);
+        let later = gdpr.get_access_history(&db, &audit, max.id, Some(tomorrow), None).await.unwrap();
+        assert!(later.records.is_empty());
+
+        let mut report = ReportWriter::new();
+        history.write_to(&mut report);
+        assert!(pdf::render("Zugriffe", &report.finish()).starts_with(b"%PDF-1.4"));
+        assert!(gdpr.get_access_history(&db, &audit, 999, None, None).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_validate_data_subject_request() {
         let (_db, gdpr, _temp_dir) = create_test_setup().await;
@@ -640,23 +1626,28 @@ mod tests {
         // Create test data
         let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
         let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
-        let _observation = db.create_observation(
+        let observation = db.create_observation(
             student.id,
             1,
             "test".to_string(),
-            "Recent observation".to_string(),
+            "Max hilft beim Aufräumen".to_string(),
             vec![]
         ).await.unwrap();
+        let plain = db.create_observation(student.id, 1, "test".to_string(), "Ruhig".to_string(), vec![]).await.unwrap();
 
-        // Test anonymization (currently just counts affected records)
-        let affected_count = gdpr.anonymize_old_data(&db, 30).await.unwrap();
-        
-        // Since our observation is recent, it should be included in the count
-        assert_eq!(affected_count, 1);
+        // Recent observations are kept as they are
+        assert_eq!(gdpr.anonymize_old_data(&db, 30).await.unwrap(), 0);
+        assert_eq!(db.get_observation(observation.id).await.unwrap().unwrap().text, "Max hilft beim Aufräumen");
+
+        let affected = gdpr.anonymize_observations_before(&db, Utc::now() + Duration::days(1)).await.unwrap();
+        assert_eq!(affected, 2);
+        let anonymized = db.get_observation(observation.id).await.unwrap().unwrap();
+        assert!(!anonymized.text.contains("Max"), "{}", anonymized.text);
+        assert!(anonymized.text.ends_with("hilft beim Aufräumen"));
+        assert_eq!(db.get_observation(plain.id).await.unwrap().unwrap().text, "Ruhig");
 
-        // Test with very old threshold (should affect nothing)
-        let affected_old = gdpr.anonymize_old_data(&db, 3650).await.unwrap(); // 10 years
-        assert_eq!(affected_old, 1); // Still gets our recent observation
+        // Each observation only once
+        assert_eq!(gdpr.anonymize_observations_before(&db, Utc::now() + Duration::days(1)).await.unwrap(), 0);
     }
 
     #[tokio::test]
@@ -0,0 +1,189 @@
+use crate::attachment_sync::{self, AttachmentPolicy, SyncedAttachment};
+use crate::changeset_codec;
+use crate::crypto::CryptoManager;
+use crate::database::{changeset_checksum, Database, HandoverImport};
+use crate::manifest::{self, ExportManifest};
+use crate::{Class, Observation, Student};
+use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+const PACKAGE_FORMAT: &str = "handover_package_v1";
+
+/// What a class hands over when it changes teachers.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct HandoverContents {
+    pub class: Class,
+    pub students: Vec<Student>,
+    pub observations: Vec<Observation>,
+    pub attachments: Vec<SyncedAttachment>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct HandoverSummary {
+    pub class_name: String,
+    pub students: usize,
+    pub observations: usize,
+    pub attachments: usize,
+}
+
+/// Packs a class for the device holding `recipient_key`. Only the envelope
+/// stays readable, so the recipient can tell a package is meant for it.
+pub async fn generate_package(
+    db: &Database,
+    crypto: &CryptoManager,
+    class_id: i64,
+    recipient_key: &str,
+) -> Result<(Vec<u8>, HandoverSummary)> {
+    db.ensure_not_frozen()?;
+    let class = db.get_class(class_id).await?.context("Class not found")?;
+    let students = db.get_students_by_class(class_id).await?;
+
+    let mut observations = Vec::new();
+    for student in &students {
+        // Local-only and private notes are personal and stay with their author
+        observations.extend(
+            db.search_observations(None, Some(student.id), None)
+                .await?
+                .into_iter()
+                .filter(Observation::leaves_device),
+        );
+    }
+
+    let observation_ids: Vec<i64> = observations.iter().map(|o| o.id).collect();
+    let policy = AttachmentPolicy {
+        mode: "full".to_string(),
+        max_attachment_bytes: u64::MAX,
+        max_total_bytes: u64::MAX,
+    };
+    let attachments = attachment_sync::collect(db, &observation_ids, &policy).await?;
+
+    let summary = HandoverSummary {
+        class_name: class.name.clone(),
+        students: students.len(),
+        observations: observations.len(),
+        attachments: attachments.len(),
+    };
+    let contents = serde_json::to_value(HandoverContents {
+        class,
+        students,
+        observations,
+        attachments,
+    })?;
+    let inner = json!({
+        "checksum": changeset_checksum(&contents),
+        "manifest": ExportManifest::describe(&contents),
+        "data": contents
+    })
+    .to_string();
+    let sealed = crypto.seal_for(recipient_key, &changeset_codec::compress(inner.as_bytes())?)?;
+
+    let package = json!({
+        "format": PACKAGE_FORMAT,
+        "source_device_id": crypto.get_device_id(),
+        "recipient_key": recipient_key.trim(),
+        "created_at": Utc::now(),
+        "payload": BASE64_STANDARD.encode(sealed),
+    });
+    Ok((package.to_string().into_bytes(), summary))
+}
+
+/// Absorbs a package addressed to this device as a new class and records
+/// where it came from.
+pub async fn import_package(db: &Database, crypto: &CryptoManager, package: &[u8]) -> Result<HandoverImport> {
+    let envelope: Value = serde_json::from_slice(package).context("Invalid handover package")?;
+    if envelope.get("format").and_then(|f| f.as_str()) != Some(PACKAGE_FORMAT) {
+        return Err(anyhow::anyhow!("File is not a handover package"));
+    }
+    if envelope.get("recipient_key").and_then(|k| k.as_str()) != Some(crypto.device_public_key()?.as_str()) {
+        return Err(anyhow::anyhow!("This handover package is addressed to another device"));
+    }
+    let source_device_id = envelope
+        .get("source_device_id")
+        .and_then(|d| d.as_str())
+        .context("Missing sender in handover package")?;
+    let created_at: DateTime<Utc> = envelope
+        .get("created_at")
+        .and_then(|c| serde_json::from_value(c.clone()).ok())
+        .context("Missing creation time in handover package")?;
+    let sealed = BASE64_STANDARD
+        .decode(envelope.get("payload").and_then(|p| p.as_str()).unwrap_or_default())
+        .context("Invalid handover payload")?;
+
+    let compressed = crypto.open_sealed(&sealed)?;
+    let inner: Value = serde_json::from_slice(&changeset_codec::decode(&compressed)?).context("Invalid handover payload")?;
+    let data = inner.get("data").context("Missing data section in handover package")?;
+    if inner.get("checksum").and_then(|c| c.as_str()) != Some(changeset_checksum(data).as_str()) {
+        return Err(anyhow::anyhow!("Checksum verification failed"));
+    }
+    manifest::verify_optional(inner.get("manifest"), data)?;
+    let contents: HandoverContents = serde_json::from_value(data.clone()).context("Invalid handover contents")?;
+
+    let package_hash = format!("{:x}", Sha256::digest(package));
+    let (record, observation_ids) = db.absorb_handover(&contents, source_device_id, &package_hash, created_at).await?;
+
+    // Attachment files are written outside the transaction, like for changesets
+    let attachments: Vec<SyncedAttachment> = contents
+        .attachments
+        .into_iter()
+        .filter_map(|mut attachment| {
+            attachment.observation_id = *observation_ids.get(&attachment.observation_id)?;
+            Some(attachment)
+        })
+        .collect();
+    attachment_sync::merge(db, &attachments).await?;
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_package_moves_class_to_recipient() {
+        let (previous, crypto, _previous_dir) = crate::fixtures::test_db_with_crypto().await;
+        let (next, _next_dir) = crate::fixtures::test_db().await;
+
+        // The recipient already has data of its own with the same ids
+        next.create_class("7c".to_string(), "2024/25".to_string()).await.unwrap();
+
+        let class = previous.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = previous.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let observation = previous.create_observation(student.id, 1, "Sozial".to_string(), "Hilft gern".to_string(), vec![]).await.unwrap();
+        previous.store_attachment(observation.id, "plakat.txt", "text/plain", b"Plakat zum Thema Wald").await.unwrap();
+        previous.set_observation_local_only(
+            previous.create_observation(student.id, 1, "Notiz".to_string(), "Privat".to_string(), vec![]).await.unwrap().id,
+            true,
+        )
+        .await
+        .unwrap();
+
+        let other_device = "09".repeat(32);
+        let (elsewhere, _) = generate_package(&previous, &crypto, class.id, &other_device).await.unwrap();
+        assert!(import_package(&next, &crypto, &elsewhere).await.is_err());
+
+        let own_key = crypto.device_public_key().unwrap();
+        let (package, summary) = generate_package(&previous, &crypto, class.id, &own_key).await.unwrap();
+        assert_eq!((summary.students, summary.observations, summary.attachments), (1, 1, 1));
+        assert!(!String::from_utf8_lossy(&package).contains("Mustermann"));
+
+        let record = import_package(&next, &crypto, &package).await.unwrap();
+        assert_ne!(record.class_id, class.id);
+        assert_eq!(record.source_device_id, crypto.get_device_id());
+        assert_eq!((record.students, record.observations, record.attachments), (1, 1, 1));
+
+        let students = next.get_students_by_class(record.class_id).await.unwrap();
+        assert_eq!(students.len(), 1);
+        let observations = next.search_observations(None, Some(students[0].id), None).await.unwrap();
+        assert_eq!(observations.len(), 1);
+        assert_eq!(observations[0].text, "Hilft gern");
+        let attachments = next.get_attachments(observations[0].id).await.unwrap();
+        assert_eq!(next.get_attachment_data(attachments[0].id).await.unwrap(), b"Plakat zum Thema Wald");
+
+        assert!(import_package(&next, &crypto, &package).await.is_err());
+        assert_eq!(next.get_handover_imports().await.unwrap().len(), 1);
+    }
+}
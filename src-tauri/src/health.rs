@@ -0,0 +1,105 @@
+use crate::audit::AuditLogger;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+/// Enough to notice entries removed since the last check without reading
+/// the whole log every time.
+const AUDIT_TAIL_ENTRIES: i64 = 500;
+
+/// Result of one pass of the health monitor. Disk space is checked with the
+/// storage quotas in the same pass, see `storage::collect_usage`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HealthReport {
+    pub checked_at: DateTime<Utc>,
+    pub database_problems: Vec<String>,  // From SQLite's quick check
+    pub audit_log_problems: Vec<String>, // The same for the audit database
+    pub audit_tail_problems: Vec<String>,
+}
+
+impl HealthReport {
+    pub fn is_healthy(&self) -> bool {
+        self.warnings().is_empty()
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.database_problems.is_empty() {
+            warnings.push(format!(
+                "The database is damaged ({}); create a backup and restore a snapshot before more data is lost",
+                self.database_problems.join("; ")
+            ));
+        }
+        if !self.audit_log_problems.is_empty() {
+            warnings.push(format!("The audit log is damaged ({})", self.audit_log_problems.join("; ")));
+        }
+        if !self.audit_tail_problems.is_empty() {
+            warnings.push(format!(
+                "The audit log was changed outside the app ({})",
+                self.audit_tail_problems.join("; ")
+            ));
+        }
+        warnings
+    }
+}
+
+async fn quick_check(path: &Path) -> Vec<String> {
+    crate::maintenance::quick_check(path)
+        .await
+        .unwrap_or_else(|e| vec![format!("{:#}", e)])
+}
+
+/// Runs the quick checks on the live files through read-only connections,
+/// so the database stays usable meanwhile. A check that cannot run is
+/// reported as a problem: a file that cannot be read is what this is about.
+pub async fn check(db_path: &Path, audit: &AuditLogger) -> Result<HealthReport> {
+    Ok(HealthReport {
+        checked_at: Utc::now(),
+        database_problems: quick_check(db_path).await,
+        audit_log_problems: quick_check(audit.db_path()).await,
+        audit_tail_problems: audit.verify_tail(AUDIT_TAIL_ENTRIES).await?,
+    })
+}
+
+/// Warnings of `current` not already raised by the previous pass, so a
+/// damaged file is reported once and not every half hour.
+pub fn new_warnings(previous: Option<&HealthReport>, current: &HealthReport) -> Vec<String> {
+    let previous = previous.map(HealthReport::warnings).unwrap_or_default();
+    current
+        .warnings()
+        .into_iter()
+        .filter(|warning| !previous.contains(warning))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_removed_audit_entries_are_reported_once() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let audit_path = temp_dir.path().join("audit.db");
+        let audit = AuditLogger::new(&audit_path).await.unwrap();
+        for object_id in 1..=4 {
+            audit.log_action("create", "observation", object_id, 1, None).await.unwrap();
+        }
+
+        let healthy = check(db.db_path(), &audit).await.unwrap();
+        assert!(healthy.is_healthy(), "{:?}", healthy.warnings());
+
+        // Removed with another program
+        let editor = sqlx::SqlitePool::connect(&format!("sqlite://{}", audit_path.display())).await.unwrap();
+        sqlx::query("DELETE FROM audit_log WHERE object_id IN (2, 3)")
+            .execute(&editor)
+            .await
+            .unwrap();
+        editor.close().await;
+
+        let report = check(db.db_path(), &audit).await.unwrap();
+        assert!(report.database_problems.is_empty());
+        assert_eq!(report.audit_tail_problems, vec!["Audit entries 2 to 3 are missing"]);
+        assert_eq!(new_warnings(Some(&healthy), &report).len(), 1);
+        assert!(new_warnings(Some(&report), &report).is_empty());
+    }
+}
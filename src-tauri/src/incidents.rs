@@ -0,0 +1,166 @@
+use crate::audit::Incident;
+use crate::database::Database;
+use crate::reports::escape_html;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+/// Art. 33 (1) GDPR: notify the supervisory authority within 72 hours of
+/// becoming aware of a breach.
+pub const NOTIFICATION_DEADLINE_HOURS: i64 = 72;
+
+pub fn notification_deadline(incident: &Incident) -> DateTime<Utc> {
+    incident.discovered_at + Duration::hours(NOTIFICATION_DEADLINE_HOURS)
+}
+
+/// Whether the deadline was missed, or is missed already without a decision.
+pub fn is_overdue(incident: &Incident, now: DateTime<Utc>) -> bool {
+    let deadline = notification_deadline(incident);
+    match incident.notification_status.as_str() {
+        "notified" => incident.notified_at.is_some_and(|at| at > deadline),
+        "pending" => now > deadline,
+        _ => false,
+    }
+}
+
+/// Students referenced by an incident, resolved at export time. Erased
+/// students only appear with their reference code, or their id if they
+/// never had one.
+pub async fn affected_students(db: &Database, incident: &Incident) -> Result<Vec<(String, Option<String>)>> {
+    let ids: Vec<i64> = serde_json::from_str(&incident.affected_student_ids).unwrap_or_default();
+    let codes = crate::reference_codes::codes_for(db, "student", &ids).await?;
+    let mut students = Vec::with_capacity(ids.len());
+    for id in ids {
+        let reference = codes.get(&id).cloned().unwrap_or_else(|| format!("ID {}", id));
+        let name = match db.get_student(id).await? {
+            Some(student) => {
+                let class = db.get_class(student.class_id).await?.map(|c| c.name).unwrap_or_default();
+                Some(format!("{} {} ({})", student.first_name, student.last_name, class))
+            }
+            None => None,
+        };
+        students.push((reference, name));
+    }
+    Ok(students)
+}
+
+/// Printable HTML documentation of one incident (Art. 33 (5) GDPR).
+pub fn render_incident_report(incident: &Incident, students: &[(String, Option<String>)]) -> String {
+    let deadline = notification_deadline(incident);
+    let format_time = |at: DateTime<Utc>| at.format("%d.%m.%Y %H:%M UTC").to_string();
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>Datenschutzvorfall {}</title>\n", incident.id));
+    html.push_str(
+        "<style>body{font-family:sans-serif;margin:2em}th,td{vertical-align:top;padding:.3em .6em;\
+         border-bottom:1px solid #eee;text-align:left}.meta{color:#6B7280;font-size:.9em}\
+         .open{color:#B91C1C}</style>\n</head>\n<body>\n",
+    );
+    html.push_str(&format!(
+        "<h1>Datenschutzvorfall: {}</h1>\n<p class=\"meta\">Vorgang {} · dokumentiert nach Art. 33 Abs. 5 DSGVO</p>\n<table>\n",
+        escape_html(&incident.title),
+        incident.id
+    ));
+
+    let mut row = |label: &str, value: String| {
+        html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", label, value));
+    };
+    row("Was ist passiert", escape_html(&incident.description).replace('\n', "<br>"));
+    row(
+        "Zeitpunkt des Vorfalls",
+        incident.occurred_at.map(format_time).unwrap_or_else(|| "unbekannt".to_string()),
+    );
+    row("Bekannt geworden", format_time(incident.discovered_at));
+    row(
+        "Betroffene Schüler",
+        if students.is_empty() {
+            "keine erfasst".to_string()
+        } else {
+            students
+                .iter()
+                .map(|(reference, name)| match name {
+                    Some(name) => escape_html(name),
+                    None => format!("gelöscht ({})", escape_html(reference)),
+                })
+                .collect::<Vec<_>>()
+                .join("<br>")
+        },
+    );
+    row(
+        "Ergriffene Maßnahmen",
+        if incident.measures.is_empty() {
+            "<span class=\"open\">noch keine</span>".to_string()
+        } else {
+            escape_html(&incident.measures).replace('\n', "<br>")
+        },
+    );
+    row("Meldefrist", format_time(deadline));
+    let status = match incident.notification_status.as_str() {
+        "notified" => format!(
+            "gemeldet am {}",
+            incident.notified_at.map(format_time).unwrap_or_default()
+        ),
+        "not_required" => "nicht meldepflichtig (voraussichtlich kein Risiko)".to_string(),
+        _ => "Meldung offen".to_string(),
+    };
+    let status = if is_overdue(incident, Utc::now()) {
+        format!("<span class=\"open\">{} – Frist überschritten, Gründe der Verzögerung angeben</span>", status)
+    } else {
+        status
+    };
+    row("Meldung an die Aufsichtsbehörde", status);
+    if let Some(notes) = incident.notes.as_deref().filter(|n| !n.trim().is_empty()) {
+        row("Anmerkungen", escape_html(notes).replace('\n', "<br>"));
+    }
+
+    html.push_str(&format!(
+        "</table>\n<p class=\"meta\">Zuletzt geändert am {} · erstellt am {}</p>\n</body>\n</html>\n",
+        format_time(incident.updated_at),
+        format_time(Utc::now())
+    ));
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditLogger, IncidentInput};
+
+    #[tokio::test]
+    async fn test_incident_report_tracks_notification_deadline() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let mut input = IncidentInput {
+            title: "USB-Stick verloren".to_string(),
+            description: "Stick mit Changeset im Bus liegen gelassen".to_string(),
+            occurred_at: None,
+            discovered_at: Utc::now() - Duration::hours(80),
+            affected_student_ids: vec![student.id, 999],
+            measures: String::new(),
+            notification_status: "notified".to_string(),
+            notified_at: None,
+            notes: None,
+        };
+        assert!(audit.record_incident(&input, 1).await.is_err());
+
+        input.notification_status = "pending".to_string();
+        let incident = audit.record_incident(&input, 1).await.unwrap();
+        assert!(is_overdue(&incident, Utc::now()));
+
+        input.notification_status = "notified".to_string();
+        input.notified_at = Some(Utc::now() - Duration::hours(20));
+        input.measures = "Gerätesperre, Eltern informiert".to_string();
+        let incident = audit.update_incident(incident.id, &input).await.unwrap();
+        assert!(!is_overdue(&incident, Utc::now()));
+
+        let students = affected_students(&db, &incident).await.unwrap();
+        let html = render_incident_report(&incident, &students);
+        assert!(html.contains("Max Mustermann (5a)"));
+        assert!(html.contains("gelöscht (ID 999)"));
+        assert!(html.contains("gemeldet am"));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::crypto::CryptoManager;
+use crate::database::Database;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+
+/// Written next to the database when the app closes and removed when it
+/// starts. Detects edits with other programs, e.g. a SQLite browser; someone
+/// who can also read the device secrets can forge it.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct IntegritySeal {
+    digest: String,
+    signed_at: DateTime<Utc>,
+    signature: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    Verified,         // Unchanged since the app last closed
+    Modified,         // Changed while the app was closed
+    Unsigned,         // The app did not close properly, or the seal was removed
+    InvalidSignature, // The seal was edited or not written on this device
+    NewDatabase,      // Nothing to check before the first close
+    CheckFailed,
+}
+
+/// Result of the check on startup, shown in the security status.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IntegrityCheck {
+    pub status: IntegrityStatus,
+    pub signed_at: Option<DateTime<Utc>>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl IntegrityCheck {
+    pub fn failed() -> Self {
+        Self {
+            status: IntegrityStatus::CheckFailed,
+            signed_at: None,
+            checked_at: Utc::now(),
+        }
+    }
+
+    pub fn warning(&self) -> Option<String> {
+        let signed_at = self
+            .signed_at
+            .map(|at| format!(" (zuletzt geschlossen am {})", at.format("%d.%m.%Y %H:%M UTC")))
+            .unwrap_or_default();
+        match self.status {
+            IntegrityStatus::Verified | IntegrityStatus::NewDatabase => None,
+            IntegrityStatus::Modified => Some(format!(
+                "Die Datenbank wurde geändert, während die App geschlossen war{}; möglicherweise mit einem anderen Programm",
+                signed_at
+            )),
+            IntegrityStatus::Unsigned => Some(
+                "Die App wurde zuletzt nicht ordnungsgemäß beendet; Änderungen an der Datenbank außerhalb der App können nicht ausgeschlossen werden"
+                    .to_string(),
+            ),
+            IntegrityStatus::InvalidSignature => Some(format!(
+                "Die Prüfsumme der Datenbank{} ist ungültig; sie wurde verändert oder stammt von einem anderen Gerät",
+                signed_at
+            )),
+            IntegrityStatus::CheckFailed => Some("Die Integrität der Datenbank konnte nicht geprüft werden".to_string()),
+        }
+    }
+}
+
+fn seal_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_os_string();
+    path.push(".integrity");
+    PathBuf::from(path)
+}
+
+fn signed_message(digest: &str, signed_at: DateTime<Utc>) -> String {
+    format!("{}|{}", digest, signed_at.to_rfc3339())
+}
+
+/// Signs the digest of the critical tables; called when the app closes.
+pub async fn seal(db: &Database, crypto: &CryptoManager) -> Result<()> {
+    let digest = db.critical_tables_digest().await?;
+    let signed_at = Utc::now();
+    let seal = IntegritySeal {
+        signature: crypto.integrity_signature(signed_message(&digest, signed_at).as_bytes())?,
+        digest,
+        signed_at,
+    };
+    let path = seal_path(db.db_path());
+    std::fs::write(&path, serde_json::to_vec_pretty(&seal)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Compares the database with the seal of the last close. The seal is
+/// removed afterwards: it vouches for one close only, and after a crash the
+/// next start must not trust it. `new_database` tells a first start from a
+/// missing seal.
+pub async fn verify(db: &Database, crypto: &CryptoManager, new_database: bool) -> Result<IntegrityCheck> {
+    let path = seal_path(db.db_path());
+    let (status, signed_at) = match std::fs::read(&path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            (if new_database { IntegrityStatus::NewDatabase } else { IntegrityStatus::Unsigned }, None)
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        Ok(data) => match serde_json::from_slice::<IntegritySeal>(&data) {
+            Err(_) => (IntegrityStatus::InvalidSignature, None),
+            Ok(seal) => {
+                let expected = crypto.integrity_signature(signed_message(&seal.digest, seal.signed_at).as_bytes())?;
+                let status = if expected != seal.signature {
+                    IntegrityStatus::InvalidSignature
+                } else if db.critical_tables_digest().await? != seal.digest {
+                    IntegrityStatus::Modified
+                } else {
+                    IntegrityStatus::Verified
+                };
+                (status, Some(seal.signed_at))
+            }
+        },
+    };
+
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => {}
+    }
+    Ok(IntegrityCheck {
+        status,
+        signed_at,
+        checked_at: Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_edits_while_closed_are_detected() {
+        let (db, crypto, temp_dir) = crate::fixtures::test_db_with_crypto().await;
+        let db_path = temp_dir.path().join("test.db");
+        let class = db.create_class("7c".to_string(), "2024/25".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_observation(student.id, 1, "Sozial".to_string(), "Hilft anderen".to_string(), vec![])
+            .await
+            .unwrap();
+
+        assert_eq!(verify(&db, &crypto, true).await.unwrap().status, IntegrityStatus::NewDatabase);
+        seal(&db, &crypto).await.unwrap();
+        assert_eq!(verify(&db, &crypto, false).await.unwrap().status, IntegrityStatus::Verified);
+        // The seal is used up, as after a crash
+        assert_eq!(verify(&db, &crypto, false).await.unwrap().status, IntegrityStatus::Unsigned);
+
+        // Edited with another program
+        seal(&db, &crypto).await.unwrap();
+        let editor = sqlx::SqlitePool::connect(&format!("sqlite://{}", db_path.display())).await.unwrap();
+        sqlx::query("UPDATE observations SET text = 'Stört den Unterricht'")
+            .execute(&editor)
+            .await
+            .unwrap();
+        editor.close().await;
+        let check = verify(&db, &crypto, false).await.unwrap();
+        assert_eq!(check.status, IntegrityStatus::Modified);
+        assert!(check.warning().unwrap().contains("geändert"));
+
+        // The edit cleared the language; detecting it again on startup is not an edit
+        seal(&db, &crypto).await.unwrap();
+        db.refresh_observation_languages().await.unwrap();
+        assert_eq!(verify(&db, &crypto, false).await.unwrap().status, IntegrityStatus::Verified);
+
+        seal(&db, &crypto).await.unwrap();
+        let seal_file = seal_path(&db_path);
+        let mut forged: serde_json::Value = serde_json::from_slice(&std::fs::read(&seal_file).unwrap()).unwrap();
+        forged["digest"] = serde_json::Value::String("0".repeat(64));
+        std::fs::write(&seal_file, forged.to_string()).unwrap();
+        assert_eq!(verify(&db, &crypto, false).await.unwrap().status, IntegrityStatus::InvalidSignature);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+
+/// ISO 639-2 code stored when no language stands out, e.g. for "Sehr gut!".
+pub const UNDETERMINED: &str = "und";
+
+/// Frequent short words per language (ISO 639-1). Enough to tell the
+/// languages of bilingual schools apart in observations of a few sentences;
+/// words shared by several languages count for each of them.
+const STOPWORDS: [(&str, &[&str]); 6] = [
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "ein", "eine", "sich", "im", "auf", "zu", "bei", "auch",
+            "noch", "hat", "sehr", "heute", "wieder", "es", "sie", "er", "dem", "den", "von", "beim", "gut",
+        ],
+    ),
+    (
+        "en",
+        &[
+            "the", "and", "is", "was", "with", "to", "of", "on", "has", "he", "she", "they", "very", "well", "today",
+            "not", "at", "his", "her", "a", "it", "for", "during", "again",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "est", "avec", "une", "un", "des", "du", "très", "bien", "il", "elle", "pas", "dans",
+            "pour", "sur", "au", "aujourd'hui", "a", "ne",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "con", "una", "muy", "bien", "hoy", "está", "por", "para", "del", "pero", "la", "no",
+            "se",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "lo", "gli", "e", "è", "con", "molto", "bene", "oggi", "non", "della", "che", "per", "una", "ha", "si",
+        ],
+    ),
+    (
+        "tr",
+        &["ve", "bir", "bu", "çok", "iyi", "ile", "için", "değil", "bugün", "ama", "daha", "gibi", "olarak"],
+    ),
+];
+
+/// Letters that only some of the languages use.
+fn letter_hint(c: char) -> Option<&'static str> {
+    match c {
+        'ä' | 'ö' | 'ü' | 'ß' => Some("de"),
+        'ç' | 'œ' | 'ê' | 'î' | 'û' => Some("fr"),
+        'ñ' | '¿' | '¡' => Some("es"),
+        'ğ' | 'ş' | 'ı' => Some("tr"),
+        _ => None,
+    }
+}
+
+/// The language an observation is written in, or `UNDETERMINED` when the
+/// text is too short or mixes languages evenly.
+pub fn detect(text: &str) -> &'static str {
+    let mut scores: HashMap<&'static str, usize> = HashMap::new();
+    let lowercase = text.to_lowercase();
+    for word in lowercase.split(|c: char| !(c.is_alphabetic() || c == '\'')) {
+        for (language, words) in STOPWORDS {
+            if words.contains(&word) {
+                *scores.entry(language).or_default() += 1;
+            }
+        }
+    }
+    let mut hinted = std::collections::HashSet::new();
+    for language in lowercase.chars().filter_map(letter_hint) {
+        // One point per language, however often the letter appears
+        if hinted.insert(language) {
+            *scores.entry(language).or_default() += 1;
+        }
+    }
+
+    let mut ranked: Vec<(&'static str, usize)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1));
+    match ranked.as_slice() {
+        [(language, best), rest @ ..] if *best >= 2 && rest.first().map_or(true, |(_, second)| second < best) => {
+            *language
+        }
+        _ => UNDETERMINED,
+    }
+}
+
+/// German name of a language code for reports.
+pub fn display_name(code: &str) -> &str {
+    match code {
+        "de" => "Deutsch",
+        "en" => "Englisch",
+        "fr" => "Französisch",
+        "es" => "Spanisch",
+        "it" => "Italienisch",
+        "tr" => "Türkisch",
+        UNDETERMINED => "Sprache unbestimmt",
+        other => other,
+    }
+}
+
+/// Whether `code` can be stored as or searched for a language.
+pub fn is_known(code: &str) -> bool {
+    code == UNDETERMINED || STOPWORDS.iter().any(|(language, _)| *language == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_languages_of_observations() {
+        assert_eq!(detect("Max hat heute im Sachunterricht sehr gut mitgearbeitet."), "de");
+        assert_eq!(detect("She explained the experiment to her group very well."), "en");
+        assert_eq!(detect("Il a très bien lu le texte avec la classe."), "fr");
+        assert_eq!(detect("Hoy trabajó muy bien con los compañeros."), "es");
+        assert_eq!(detect("Bugün derste çok iyi katıldı ve yardım etti."), "tr");
+
+        // Too little to tell, or evenly mixed
+        assert_eq!(detect("Super!"), UNDETERMINED);
+        assert_eq!(detect("Prima"), UNDETERMINED);
+        assert_eq!(detect("the cat and der Hund und"), UNDETERMINED);
+
+        assert!(is_known("en"));
+        assert!(is_known(UNDETERMINED));
+        assert!(!is_known("english"));
+        assert_eq!(display_name("fr"), "Französisch");
+    }
+}
@@ -0,0 +1,91 @@
+use crate::database::Database;
+use crate::{Category, ObservationTemplate};
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+const LOCALE_SETTING: &str = "locale";
+
+/// Category names are entered in German unless translated.
+pub const DEFAULT_LOCALE: &str = "de";
+
+pub async fn get_locale(db: &Database) -> Result<String> {
+    Ok(db
+        .get_setting(LOCALE_SETTING)
+        .await?
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string()))
+}
+
+/// Accepts language tags such as "de", "en" or "en-GB".
+pub async fn set_locale(db: &Database, locale: &str) -> Result<()> {
+    let valid = (2..=12).contains(&locale.len())
+        && locale.split('-').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+    if !valid {
+        return Err(anyhow::anyhow!("Invalid locale '{}'", locale));
+    }
+    db.set_setting(LOCALE_SETTING, locale).await
+}
+
+/// Falls back from "en-GB" to "en" and then to the name as entered.
+pub fn translate(name: &str, translations: &str, locale: &str) -> String {
+    let translations: BTreeMap<String, String> = serde_json::from_str(translations).unwrap_or_default();
+    let language = locale.split('-').next().unwrap_or(locale);
+    [locale, language]
+        .iter()
+        .find_map(|key| {
+            translations
+                .iter()
+                .find(|(candidate, _)| candidate.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.trim())
+                .filter(|value| !value.is_empty())
+        })
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Fills in `display_name`; `name` stays the key observations refer to.
+pub fn localize_categories(categories: &mut [Category], locale: &str) {
+    for category in categories {
+        category.display_name = Some(translate(&category.name, &category.translations, locale));
+    }
+}
+
+pub fn localize_templates(templates: &mut [ObservationTemplate], locale: &str) {
+    for template in templates {
+        template.display_name = Some(translate(&template.name, &template.translations, locale));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_falls_back() {
+        let translations = r#"{"en": "Social", "fr": ""}"#;
+        assert_eq!(translate("Sozial", translations, "en"), "Social");
+        assert_eq!(translate("Sozial", translations, "en-GB"), "Social");
+        assert_eq!(translate("Sozial", translations, "fr"), "Sozial");
+        assert_eq!(translate("Sozial", "{}", "en"), "Sozial");
+        assert_eq!(translate("Sozial", "kaputt", "en"), "Sozial");
+    }
+
+    #[tokio::test]
+    async fn test_locale_switches_display_names() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        let social = db.get_categories().await.unwrap().into_iter().find(|c| c.name == "Sozial").unwrap();
+        db.set_category_translations(social.id, &BTreeMap::from([("en".to_string(), "Social".to_string())]))
+            .await
+            .unwrap();
+        assert!(set_locale(&db, "en GB").await.is_err());
+
+        let mut categories = db.get_categories().await.unwrap();
+        localize_categories(&mut categories, &get_locale(&db).await.unwrap());
+        assert_eq!(categories[0].display_name.as_deref(), Some("Sozial"));
+
+        set_locale(&db, "en").await.unwrap();
+        localize_categories(&mut categories, &get_locale(&db).await.unwrap());
+        assert_eq!(categories[0].display_name.as_deref(), Some("Social"));
+        assert_eq!(categories[0].name, "Sozial");
+    }
+}
@@ -1,23 +1,73 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod app_mode;
+mod archive;
+mod attachment_sync;
+mod backup_stream;
+mod category_pack;
+mod changeset_codec;
+mod class_layout;
+mod clipboard_import;
+mod companion_api;
+mod config_audit;
+mod consistency;
+mod convert;
 mod crypto;
+mod data_root;
 mod database;
+mod dpia;
+mod escrow;
+mod export_hook;
+mod export_naming;
+mod export_protection;
+mod export_target;
+mod feature_flags;
 // mod p2p; // Removed - using file-based changeset sync
 mod audit;
 mod gdpr;
+mod handover;
+mod health;
+mod incidents;
+mod integrity;
+mod language;
+mod localization;
+mod maintenance;
+mod manifest;
+mod media;
+mod mentions;
+mod notes_import;
+mod ocr;
+mod operations;
+mod pdf;
+mod privacy;
+mod reference_codes;
+mod redaction;
+mod reports;
+mod scheduler;
+mod security;
+mod storage;
+mod substitute;
+mod support_plan;
+mod transcription;
+mod transfer_locations;
+mod trusted_timestamp;
+mod xapi;
 
+#[cfg(test)]
+mod fixtures;
 #[cfg(test)]
 mod tests;
 
+use anyhow::Context;
 use base64::Engine;
 use std::sync::Arc;
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 use tokio::sync::Mutex;
 // use p2p::ActivePin; // Removed - using file-based changeset sync
 
 // Data structures
-#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
 pub struct Student {
     pub id: i64,
     pub class_id: i64,
@@ -27,6 +77,28 @@ pub struct Student {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
     pub source_device_id: String,
+    // Only filled by `Database::list_students`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub last_observation_at: Option<chrono::DateTime<chrono::Utc>>,
+    // Last school day of a student who left, see `Database::mark_student_left`
+    #[serde(default)]
+    #[sqlx(default)]
+    pub left_at: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub date_of_birth: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub notes: Option<String>,
+    // Id of the student in the school administration software
+    #[serde(default)]
+    #[sqlx(default)]
+    pub external_id: Option<String>,
+    // Same on every device, unlike `id`; see `Database::record_change_on`
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -43,6 +115,45 @@ pub struct Class {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
     pub source_device_id: String,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
+}
+
+/// A school subject observations can be filed under, so subject teachers can
+/// keep their documentation apart.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct Subject {
+    pub id: i64,
+    pub name: String,
+    pub is_active: bool,
+    pub sort_order: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A competency observations can be linked to, grouped by area (e.g. the
+/// subject of a KMK Bildungsstandard).
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct Competency {
+    pub id: i64,
+    pub area: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub sort_order: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A structured assessment scale; its criteria and levels are listed by
+/// `Database::get_rubric`.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct Rubric {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
@@ -57,6 +168,39 @@ pub struct Category {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
     pub source_device_id: String,
+    pub icon: Option<String>, // Icon name like "users" or an emoji
+    pub translations: String, // JSON map of locale to name, e.g. {"en": "Social"}
+    #[serde(default)]
+    #[sqlx(default)]
+    pub merged_into: Option<i64>, // Set once merged into another category, see `merge_categories`
+    #[sqlx(default)]
+    pub display_name: Option<String>, // Name in the current locale, filled in by `localization`
+}
+
+/// Reusable text for quick entry; contains no personal data and is shared in category packs.
+/// A short phrase offered for one-tap insertion while writing an observation.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct QuickPhrase {
+    pub id: i64,
+    pub category: String,
+    pub text: String,
+    pub usage_count: i64,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct ObservationTemplate {
+    pub id: i64,
+    pub name: String,
+    pub category: String,
+    pub text: String,
+    pub sort_order: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub translations: String,
+    #[sqlx(default)]
+    pub display_name: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
@@ -70,13 +214,271 @@ pub struct Observation {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
     pub source_device_id: String,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub logical_clock: i64, // Per-device Lamport counter used for sync conflict ordering
+    #[serde(default)]
+    #[sqlx(default)]
+    pub local_only: bool, // Personal note that never leaves this device
+    #[serde(default)]
+    #[sqlx(default)]
+    pub cosigned_by: Option<i64>, // Second teacher confirming a sensitive entry
+    #[serde(default)]
+    #[sqlx(default)]
+    pub cosigned_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default = "default_visibility")]
+    #[sqlx(default)]
+    pub visibility: String, // "team" or "private" to the author
+    #[serde(default)]
+    #[sqlx(default)]
+    pub session_id: Option<i64>, // Observation session on this device, see `ObservationSession`
+    #[serde(default)]
+    #[sqlx(default)]
+    pub subject: Option<String>, // One of the managed `Subject`s, e.g. "Mathematik"
+    #[serde(default)]
+    #[sqlx(default)]
+    pub language: Option<String>, // Detected on each device, see `language::detect`; ignored by sync
+    #[serde(default)]
+    #[sqlx(default)]
+    pub reported_by_user_id: Option<i64>, // Colleague who told the author, see `NewObservation::reported_by`
+    #[serde(default)]
+    #[sqlx(default)]
+    pub reported_by: Option<String>, // Same for someone without an account, e.g. "Schulbegleitung"
+    #[serde(default)]
+    #[sqlx(default)]
+    pub pinned: bool, // Goes into the substitute brief, see `substitute`; kept on this device
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
+}
+
+// Observations from before visibility existed were visible to everyone
+fn default_visibility() -> String {
+    database::VISIBILITY_TEAM.to_string()
+}
+
+impl Observation {
+    pub fn is_visible_to(&self, user_id: i64) -> bool {
+        self.visibility != database::VISIBILITY_PRIVATE || self.author_id == user_id
+    }
+
+    /// Whether it may go to another device or person, with its attachments.
+    /// Local-only notes never leave the device, private ones are for their author.
+    pub fn leaves_device(&self) -> bool {
+        !self.local_only && self.visibility != database::VISIBILITY_PRIVATE
+    }
+
+    /// Name of the colleague the author heard it from, for exports.
+    /// `user_names` maps user ids to names.
+    pub fn reporter(&self, user_names: &std::collections::BTreeMap<i64, String>) -> Option<String> {
+        match (self.reported_by_user_id, &self.reported_by) {
+            (Some(user_id), _) => Some(user_names.get(&user_id).cloned().unwrap_or_else(|| format!("Benutzer {}", user_id))),
+            (None, name) => name.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct ReportArtifact {
+    pub id: i64,
+    pub class_id: i64,
+    pub report_type: String, // e.g. "weekly_summary"
+    pub period_start: String,
+    pub period_end: String,
+    pub format: String, // "html"
+    pub content_hash: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub source_device_id: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
+pub struct SyncHistoryEntry {
+    pub id: i64,
+    pub operation_id: String,
+    pub direction: String, // "export" or "import"
+    pub peer_device_id: Option<String>,
+    pub summary: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct Attachment {
+    pub id: i64,
+    pub observation_id: i64,
+    pub filename: String,
+    pub content_type: String,
+    pub file_hash: String,
+    pub size_bytes: i64,
+    pub storage: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub role: String, // "teacher" or "admin"
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A request to permanently erase a student (Art. 17 GDPR) awaiting a second user's approval.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct ErasureRequest {
+    pub id: i64,
+    pub student_id: i64,
+    pub requested_by: i64,
+    pub reason: String,
+    pub status: String, // "pending", "executed" or "rejected"
+    pub decided_by: Option<i64>,
+    pub requested_at: chrono::DateTime<chrono::Utc>,
+    pub decided_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub execute_at: Option<chrono::DateTime<chrono::Utc>>, // set for erasures scheduled after a notice period
+    pub notified_at: Option<chrono::DateTime<chrono::Utc>>,
+    // A scheduled erasure waits for its date once a second admin approved it
+    #[serde(default)]
+    #[sqlx(default)]
+    pub approved_by: Option<i64>,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub approved_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Seating plan and group assignments of a class (`layout`, a JSON object);
+/// see `class_layout::validate` for how it refers to students.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct ClassLayout {
+    pub class_id: i64,
+    pub layout: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub source_device_id: String,
+    pub logical_clock: i64,
+}
+
+/// A permission collected for a whole class, e.g. the parents' consent to
+/// photos, with where the signed forms are filed. One per class and `kind`.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct ClassAgreement {
+    pub id: i64,
+    pub class_id: i64,
+    pub kind: String, // One of `database::CLASS_AGREEMENT_KINDS`
+    pub collected_on: chrono::NaiveDate,
+    pub valid_until: Option<chrono::NaiveDate>, // Open-ended when not set
+    pub reference: Option<String>,             // e.g. "Elternbriefe 2024/25, Ordner 3"
+    pub notes: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A custom entry of the anonymization dictionary, e.g. a nickname. Student
+/// names are in the dictionary without an entry, see
+/// `redaction::anonymization_dictionary`.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct AnonymizationTerm {
+    pub id: i64,
+    pub term: String,
+    pub student_id: Option<i64>, // Whose nickname it is; None for terms about nobody in particular
+    pub note: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What a student is currently working towards, e.g. "Meldet sich, bevor
+/// er spricht". Only active goals go into the substitute brief.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct StudentGoal {
+    pub id: i64,
+    pub student_id: i64,
+    pub text: String,
+    pub status: String, // "active", "achieved" or "dropped"
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A lesson observed as a whole, e.g. "Hospitation 3. Stunde". Sessions are
+/// kept on the device they were held on.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct ObservationSession {
+    pub id: i64,
+    pub class_id: i64,
+    pub title: Option<String>,
+    pub subject: Option<String>, // Given to observations made during the session
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub ended_at: Option<chrono::DateTime<chrono::Utc>>, // None while the session runs
+    pub observation_count: i64,
+}
+
+/// A long-running command as recorded in the job history. `params` (JSON)
+/// are the arguments needed to retry it, without passphrases or keys.
+/// `status` is one of running, completed, failed, cancelled or interrupted.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct Job {
+    pub id: i64,
+    pub job_type: String,
+    pub params: String,
+    pub status: String,
+    pub progress: f64,
+    pub operation_id: Option<String>,
+    pub result_path: Option<String>,
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A parent or other guardian of a student. Fields named in `sensitive_fields`
+/// (a JSON list) stay on this device and are left out of changesets.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct Guardian {
+    pub id: i64,
+    pub student_id: i64,
+    pub name: String,
+    pub contact: Option<String>,
+    pub relationship: String,
+    pub consent_reference: Option<String>, // e.g. file number of the signed consent form
+    pub sensitive_fields: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub source_device_id: String,
+    pub logical_clock: i64,
+    #[serde(default)]
+    #[sqlx(default)]
+    pub uuid: Option<String>,
+}
+
+/// A student's move to another class. Observations made before `transferred_at`
+/// stay with the old class in reports when `keep_history` is set.
+#[derive(Debug, serde::Serialize, serde::Deserialize, sqlx::FromRow, Clone)]
+pub struct StudentTransfer {
+    pub id: i64,
+    pub student_id: i64,
+    pub from_class_id: i64,
+    pub to_class_id: i64,
+    pub transferred_at: chrono::DateTime<chrono::Utc>,
+    pub keep_history: bool,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub source_device_id: String,
+    pub logical_clock: i64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct DeviceSyncInfo {
+    pub device_id: String,
+    pub is_local: bool,
+    pub last_export: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_import: Option<chrono::DateTime<chrono::Utc>>,
+    pub confirmed_until: Option<chrono::DateTime<chrono::Utc>>, // Newest own export the peer confirmed by receipt
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct SyncStatus {
     pub peer_connected: bool,
     pub last_sync: Option<chrono::DateTime<chrono::Utc>>,
-    pub pending_changes: u32,
+    pub pending_changes: u32, // Local changes the peer has not confirmed, or since the last export without receipts
+    // Dry run of an export with the default scope
+    pub scope_days: u32,
+    pub scope_creations: u32,
+    pub scope_updates: u32,
+    pub scope_deletions: u32,
+    pub devices: Vec<DeviceSyncInfo>,
 }
 
 // Application state
@@ -87,17 +489,31 @@ pub struct AppState {
     // p2p: Removed - using file-based changeset sync
     pub audit: Arc<audit::AuditLogger>,
     pub gdpr: Arc<gdpr::GdprManager>,
+    pub reports: Arc<reports::ReportGenerator>,
+    pub operations: Arc<operations::OperationRegistry>,
 }
 
 // Tauri commands
 #[tauri::command]
-async fn get_sync_status(_state: tauri::State<'_, AppState>) -> Result<SyncStatus, String> {
+async fn get_sync_status(state: tauri::State<'_, AppState>) -> Result<SyncStatus, String> {
     // File-based sync status - no real-time peer connection
-    Ok(SyncStatus {
-        peer_connected: false, // Always false for file-based sync
-        last_sync: None,       // Could be read from settings in future
-        pending_changes: 0,    // Could be calculated from database
-    })
+    let db = state.db.lock().await;
+    db.get_sync_status(database::DEFAULT_CHANGESET_DAYS_BACK)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// `since` is when the viewer last looked, e.g. the previous app start.
+#[tauri::command]
+async fn get_sidebar_badges(
+    state: tauri::State<'_, AppState>,
+    since: chrono::DateTime<chrono::Utc>,
+    viewer_id: Option<i64>,
+) -> Result<database::SidebarBadges, String> {
+    let db = state.db.lock().await;
+    db.get_sidebar_badges(since, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -107,179 +523,280 @@ async fn create_observation(
     category: String,
     text: String,
     tags: Vec<String>,
+    local_only: Option<bool>,
+    visibility: Option<String>,
+    subject: Option<String>,
+    rubric_scores: Option<Vec<database::RubricScore>>,
+    competency_ids: Option<Vec<i64>>,
+    metrics: Option<Vec<database::ObservationMetric>>,
+    reported_by_user_id: Option<i64>,
+    reported_by: Option<String>,
 ) -> Result<Observation, String> {
     let db = state.db.lock().await;
+    let rubric_scores = rubric_scores.unwrap_or_default();
+    let competency_ids = competency_ids.unwrap_or_default();
+    db.check_rubric_scores(&rubric_scores).await.map_err(|e| e.to_string())?;
+    db.check_competencies(&competency_ids).await.map_err(|e| e.to_string())?;
+    let metrics = metrics.unwrap_or_default();
+    database::validate_metrics(&metrics).map_err(|e| e.to_string())?;
     let observation = db
-        .create_observation(student_id, 1, category, text, tags)
+        .create_observations_batch(
+            1,
+            vec![database::NewObservation {
+                student_id,
+                category,
+                text,
+                tags,
+                created_at: None,
+                local_only: local_only.unwrap_or(false),
+                visibility,
+                subject,
+                reported_by_user_id,
+                reported_by,
+            }],
+        )
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())?
+        .pop()
+        .ok_or_else(|| "Failed to create observation".to_string())?;
+    if !rubric_scores.is_empty() {
+        db.set_observation_rubric_scores(observation.id, &rubric_scores)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    if !competency_ids.is_empty() {
+        db.set_observation_competencies(observation.id, &competency_ids)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    if !metrics.is_empty() {
+        db.set_observation_metrics(observation.id, &metrics)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
 
     // Log the creation
+    let mut details: Vec<String> = observation.local_only.then(|| "local_only".to_string()).into_iter().collect();
+    details.extend(reported_by_details(&observation));
     state
         .audit
-        .log_action("create", "observation", observation.id, 1, None)
+        .log_action(
+            "create",
+            "observation",
+            observation.id,
+            1,
+            (!details.is_empty()).then(|| details.join(", ")).as_deref(),
+        )
         .await
         .map_err(|e| e.to_string())?;
 
     Ok(observation)
 }
 
-#[tauri::command]
-async fn get_students(state: tauri::State<'_, AppState>) -> Result<Vec<Student>, String> {
-    let db = state.db.lock().await;
-    db.get_students().await.map_err(|e| e.to_string())
+/// Who the author heard the observation from, for the audit entry of its creation.
+fn reported_by_details(observation: &Observation) -> Option<String> {
+    match (observation.reported_by_user_id, &observation.reported_by) {
+        (Some(user_id), _) => Some(format!("reported_by_user: {}", user_id)),
+        (None, Some(name)) => Some(format!("reported_by: {}", name)),
+        (None, None) => None,
+    }
 }
 
 #[tauri::command]
-async fn get_classes(state: tauri::State<'_, AppState>) -> Result<Vec<Class>, String> {
+async fn get_observation_visibility_default(state: tauri::State<'_, AppState>, user_id: i64) -> Result<String, String> {
     let db = state.db.lock().await;
-    db.get_classes().await.map_err(|e| e.to_string())
+    db.get_default_visibility(user_id).await.map_err(|e| e.to_string())
 }
 
+/// "private" keeps a user's new observations to themselves, "team" shows
+/// them to everyone working with the class.
 #[tauri::command]
-async fn create_class(
+async fn set_observation_visibility_default(
     state: tauri::State<'_, AppState>,
-    name: String,
-    school_year: String,
-) -> Result<Class, String> {
+    user_id: i64,
+    visibility: String,
+) -> Result<(), String> {
     let db = state.db.lock().await;
-    db.create_class(name, school_year)
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    db.set_default_visibility(user_id, &visibility)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("configure", "observation_visibility_default", user_id, user_id, Some(&visibility))
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn create_student(
+async fn set_observation_visibility(
     state: tauri::State<'_, AppState>,
-    class_id: i64,
-    first_name: String,
-    last_name: String,
-    status: Option<String>,
-) -> Result<Student, String> {
+    observation_id: i64,
+    user_id: i64,
+    visibility: String,
+) -> Result<(), String> {
     let db = state.db.lock().await;
-    db.create_student(class_id, first_name, last_name, status)
+    db.set_observation_visibility(observation_id, user_id, &visibility)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("set_visibility", "observation", observation_id, user_id, Some(&visibility))
         .await
         .map_err(|e| e.to_string())
 }
 
+/// Local-only observations are left out of every changeset; clearing the flag
+/// lets the next changeset carry the observation.
 #[tauri::command]
-async fn search_observations(
+async fn set_observation_local_only(
     state: tauri::State<'_, AppState>,
-    query: Option<String>,
-    student_id: Option<i64>,
-    category: Option<String>,
-) -> Result<Vec<Observation>, String> {
+    observation_id: i64,
+    local_only: bool,
+) -> Result<(), String> {
     let db = state.db.lock().await;
-    db.search_observations(query, student_id, category)
+    db.set_observation_local_only(observation_id, local_only)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "update",
+            "observation",
+            observation_id,
+            1,
+            Some(if local_only { "local_only" } else { "shared" }),
+        )
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn export_student_data(
+async fn cosign_observation(
     state: tauri::State<'_, AppState>,
-    student_id: i64,
-    format: String,
-) -> Result<String, String> {
+    observation_id: i64,
+    cosigned_by: i64,
+) -> Result<Observation, String> {
     let db = state.db.lock().await;
-    let export_data = state
-        .gdpr
-        .export_student_data(&*db, student_id)
+    let observation = db
+        .cosign_observation(observation_id, cosigned_by)
         .await
         .map_err(|e| e.to_string())?;
 
-    // Log the export
     state
         .audit
-        .log_action("export", "student_data", student_id, 1, Some(&format))
+        .log_action(
+            "cosign",
+            "observation",
+            observation_id,
+            cosigned_by,
+            Some(&format!("author {}, co-signed by {}", observation.author_id, cosigned_by)),
+        )
         .await
         .map_err(|e| e.to_string())?;
 
-    match format.as_str() {
-        "json" => Ok(serde_json::to_string_pretty(&export_data).map_err(|e| e.to_string())?),
-        "csv" => {
-            // Convert to CSV format
-            // Implementation would go here
-            Ok("CSV data".to_string())
-        }
-        _ => Err("Unsupported export format".to_string()),
-    }
+    Ok(observation)
 }
 
-// P2P sync commands removed - using file-based changeset sync
-
 #[tauri::command]
-async fn export_changeset(state: tauri::State<'_, AppState>) -> Result<String, String> {
+async fn create_observations_batch(
+    state: tauri::State<'_, AppState>,
+    observations: Vec<database::NewObservation>,
+) -> Result<Vec<Observation>, String> {
     let db = state.db.lock().await;
-    let changeset = db
-        .get_pending_changesets("export")
+    let created = db
+        .create_observations_batch(1, observations)
         .await
         .map_err(|e| e.to_string())?;
 
-    // Convert to base64 for safe transport (legacy compatibility)
-    let encoded = base64::prelude::BASE64_STANDARD.encode(&changeset);
-
-    // Log the export
+    let entries: Vec<audit::PendingAuditEntry> = created
+        .iter()
+        .map(|observation| audit::PendingAuditEntry {
+            action: "create".to_string(),
+            object_type: "observation".to_string(),
+            object_id: observation.id,
+            user_id: 1,
+            details: Some(match reported_by_details(observation) {
+                Some(details) => format!("batch, {}", details),
+                None => "batch".to_string(),
+            }),
+        })
+        .collect();
     state
         .audit
-        .log_action("export", "changeset", 0, 1, None)
+        .log_actions_batch(&entries)
         .await
         .map_err(|e| e.to_string())?;
 
-    Ok(encoded)
+    Ok(created)
 }
 
 #[tauri::command]
-async fn export_changeset_to_file(
-    state: tauri::State<'_, AppState>,
-    file_path: String,
-    days_back: Option<u32>,
-) -> Result<String, String> {
-    let days_back = days_back.unwrap_or(30); // Default to 30 days
+async fn get_subjects(state: tauri::State<'_, AppState>) -> Result<Vec<Subject>, String> {
+    let db = state.db.lock().await;
+    db.get_subjects().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_subject(state: tauri::State<'_, AppState>, name: String) -> Result<Subject, String> {
     let db = state.db.lock().await;
+    let subject = db.create_subject(&name).await.map_err(|e| e.to_string())?;
 
-    // Generate enhanced changeset with metadata
-    let changeset_data = db
-        .create_changeset_file(days_back)
+    state
+        .audit
+        .log_action("create", "subject", subject.id, 1, Some(&subject.name))
         .await
         .map_err(|e| e.to_string())?;
 
-    // Write to file
-    std::fs::write(&file_path, &changeset_data)
-        .map_err(|e| format!("Failed to write changeset file: {}", e))?;
+    Ok(subject)
+}
+
+#[tauri::command]
+async fn delete_subject(state: tauri::State<'_, AppState>, id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_subject(id).await.map_err(|e| e.to_string())?;
 
-    // Log the export with file path
     state
         .audit
-        .log_action("export", "changeset_file", 0, 1, Some(&file_path))
+        .log_action("delete", "subject", id, 1, None)
         .await
         .map_err(|e| e.to_string())?;
 
-    let file_size = changeset_data.len();
-    Ok(format!(
-        "Changeset exported to {} ({} bytes)",
-        file_path, file_size
-    ))
+    Ok(())
 }
 
 #[tauri::command]
-async fn import_changeset(
+async fn get_observation_metrics(
     state: tauri::State<'_, AppState>,
-    changeset_data: String,
-) -> Result<(), String> {
-    let changeset = base64::prelude::BASE64_STANDARD
-        .decode(&changeset_data)
-        .map_err(|e| format!("Invalid changeset format: {}", e))?;
+    observation_id: i64,
+) -> Result<Vec<database::ObservationMetric>, String> {
+    let db = state.db.lock().await;
+    db.get_observation_metrics(observation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
 
+#[tauri::command]
+async fn set_observation_metrics(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    metrics: Vec<database::ObservationMetric>,
+) -> Result<(), String> {
     let db = state.db.lock().await;
-    db.apply_changeset(&changeset, "import")
+    db.set_observation_metrics(observation_id, &metrics)
         .await
         .map_err(|e| e.to_string())?;
 
-    // Log the import
     state
         .audit
-        .log_action("import", "changeset", 0, 1, None)
+        .log_action("measure", "observation", observation_id, 1, None)
         .await
         .map_err(|e| e.to_string())?;
 
@@ -287,420 +804,5780 @@ async fn import_changeset(
 }
 
 #[tauri::command]
-async fn import_changeset_from_file(
+async fn get_metric_names(state: tauri::State<'_, AppState>, student_id: Option<i64>) -> Result<Vec<String>, String> {
+    let db = state.db.lock().await;
+    db.get_metric_names(student_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_metric_series(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    metric: String,
+    viewer_id: Option<i64>,
+) -> Result<Vec<database::MetricPoint>, String> {
+    let db = state.db.lock().await;
+    db.get_metric_series(student_id, &metric, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Writes one metric of a student as CSV, e.g. for a progress chart in a spreadsheet.
+#[tauri::command]
+async fn export_metric_series(
     state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    student_id: i64,
+    metric: String,
     file_path: String,
-) -> Result<String, String> {
-    // Read changeset file
-    let changeset_data =
-        std::fs::read(&file_path).map_err(|e| format!("Failed to read changeset file: {}", e))?;
+    viewer_id: Option<i64>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let viewer_id = viewer_id.unwrap_or(1);
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let student = db
+        .get_student(student_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Student not found".to_string())?;
+    let class = db.get_class(student.class_id).await.map_err(|e| e.to_string())?;
+    let points = db
+        .get_metric_series(student_id, &metric, viewer_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let csv = reports::render_metric_series_csv(&points).into_bytes();
+    let csv = export_protection::protect(&db, &state.crypto, csv, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName {
+            export_type: "metric",
+            class: class.as_ref().map(|c| c.name.as_str()),
+            extension: "csv",
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &csv).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "export",
+            "metric_series",
+            student_id,
+            viewer_id,
+            Some(&format!("{} values of {} to {}", points.len(), metric, file_path)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("{} values exported to {}", points.len(), file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "metric_series", student_id, viewer_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn get_competencies(state: tauri::State<'_, AppState>) -> Result<Vec<Competency>, String> {
+    let db = state.db.lock().await;
+    db.get_competencies().await.map_err(|e| e.to_string())
+}
 
+#[tauri::command]
+async fn create_competency(
+    state: tauri::State<'_, AppState>,
+    area: String,
+    name: String,
+    description: Option<String>,
+) -> Result<Competency, String> {
     let db = state.db.lock().await;
-    let import_result = db
-        .apply_changeset_file(&changeset_data)
+    let competency = db
+        .create_competency(&area, &name, description.as_deref())
         .await
         .map_err(|e| e.to_string())?;
 
-    // Log the import with file path
     state
         .audit
-        .log_action("import", "changeset_file", 0, 1, Some(&file_path))
+        .log_action("create", "competency", competency.id, 1, None)
         .await
         .map_err(|e| e.to_string())?;
 
-    Ok(format!(
-        "Imported changeset from {}: {}",
-        file_path, import_result
-    ))
+    Ok(competency)
 }
 
 #[tauri::command]
-async fn export_all_data(
+async fn delete_competency(state: tauri::State<'_, AppState>, id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_competency(id).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("delete", "competency", id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_observation_competencies(
     state: tauri::State<'_, AppState>,
-    days_back: Option<i32>,
-) -> Result<String, String> {
+    observation_id: i64,
+) -> Result<Vec<Competency>, String> {
     let db = state.db.lock().await;
-    
-    // Get all students, classes, and observations
-    let students = db.get_students().await.map_err(|e| e.to_string())?;
-    let classes = db.get_classes().await.map_err(|e| e.to_string())?;
-    
-    // Filter observations by date if specified
-    let observations = if let Some(days) = days_back {
-        if days > 0 {
-            // Get observations from specific time range
-            let cutoff_date = chrono::Utc::now() - chrono::Duration::days(days as i64);
-            db.get_observations_since(cutoff_date).await.map_err(|e| e.to_string())?
-        } else {
-            // days_back <= 0 means all data
-            db.search_observations(None, None, None).await.map_err(|e| e.to_string())?
-        }
-    } else {
-        // No filter - get all observations
-        db.search_observations(None, None, None).await.map_err(|e| e.to_string())?
-    };
+    db.get_observation_competencies(observation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    // Get device config for metadata
-    let device_config = state.crypto.get_device_config().map_err(|e| e.to_string())?;
-    
-    // Create comprehensive export data
-    let export_data = serde_json::json!({
-        "format": "full_export",
-        "version": "1.0",
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "export_scope": {
-            "days_back": days_back,
-            "total_students": students.len(),
-            "total_classes": classes.len(), 
-            "total_observations": observations.len()
+#[tauri::command]
+async fn set_observation_competencies(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    competency_ids: Vec<i64>,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.set_observation_competencies(observation_id, &competency_ids)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("link_competencies", "observation", observation_id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_competency_coverage(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    viewer_id: Option<i64>,
+) -> Result<Vec<database::CompetencyCoverage>, String> {
+    let db = state.db.lock().await;
+    db.get_competency_coverage(student_id, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Writes a printable PDF with one blank observation form per active student,
+/// for notes taken on paper and typed in later.
+#[tauri::command]
+async fn export_blank_observation_sheets(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    class_id: i64,
+    file_path: String,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let class = db
+        .get_class(class_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Class not found".to_string())?;
+    let mut students = db.get_students_by_class(class_id).await.map_err(|e| e.to_string())?;
+    students.retain(|s| s.status == "active");
+    if students.is_empty() {
+        return Err("The class has no active students".to_string());
+    }
+    let mut categories = db.get_categories().await.map_err(|e| e.to_string())?;
+    let locale = localization::get_locale(&db).await.map_err(|e| e.to_string())?;
+    localization::localize_categories(&mut categories, &locale);
+    let categories: Vec<String> = categories
+        .into_iter()
+        .map(|c| c.display_name.unwrap_or(c.name))
+        .collect();
+
+    let data = reports::render_blank_observation_sheets(&class.name, &students, &categories);
+    let data = export_protection::protect(&db, &state.crypto, data, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName {
+            export_type: "blank_sheets",
+            class: Some(&class.name),
+            extension: "pdf",
         },
-        "source_device": {
-            "device_type": device_config.get("device_type").unwrap_or(&"unknown".to_string()),
-            "device_name": device_config.get("device_name")
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &data).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "export",
+            "blank_sheets",
+            class_id,
+            1,
+            Some(&format!("{} students to {}", students.len(), file_path)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("{} observation sheets exported to {}", students.len(), file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "blank_sheets", class_id, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// Writes the deliberately reduced brief for a substitute teacher as PDF,
+/// see `substitute::build_substitute_brief` for what goes in.
+#[tauri::command]
+async fn export_substitute_brief(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    class_id: i64,
+    file_path: String,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let brief = substitute::build_substitute_brief(&db, class_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let notes: usize = brief.students.iter().map(|s| s.notes.len()).sum();
+    let goals: usize = brief.students.iter().map(|s| s.goals.len()).sum();
+
+    let data = substitute::render_substitute_brief_pdf(&brief);
+    let data = export_protection::protect(&db, &state.crypto, data, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName {
+            export_type: "substitute_brief",
+            class: Some(&brief.class_name),
+            extension: "pdf",
         },
-        "data": {
-            "students": students,
-            "classes": classes,
-            "observations": observations
-        }
-    });
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &data).map_err(|e| e.to_string())?;
 
-    // Log the export
-    let scope_description = match days_back {
-        Some(days) if days > 0 => format!("last {} days", days),
-        _ => "all data".to_string()
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "export",
+            "substitute_brief",
+            class_id,
+            1,
+            Some(&format!(
+                "{} students, {} pinned notes, {} goals to {}",
+                brief.students.len(),
+                notes,
+                goals,
+                file_path
+            )),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("Substitute brief for {} students exported to {}", brief.students.len(), file_path),
+        file_path,
+        removable_media,
+        post_export: None,
     };
-    
+    export_hook::run(&app, db, &state.audit, &mut result, "substitute_brief", class_id, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn set_observation_pinned(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    pinned: bool,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.set_observation_pinned(observation_id, pinned)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(if pinned { "pin" } else { "unpin" }, "observation", observation_id, 1, None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_student_goals(state: tauri::State<'_, AppState>, student_id: i64) -> Result<Vec<StudentGoal>, String> {
+    let db = state.db.lock().await;
+    db.get_student_goals(student_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_student_goal(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    text: String,
+) -> Result<StudentGoal, String> {
+    let db = state.db.lock().await;
+    let goal = db.create_student_goal(student_id, &text).await.map_err(|e| e.to_string())?;
+
     state
         .audit
-        .log_action("export", "all_data", 0, 1, Some(&scope_description))
+        .log_action("create", "student_goal", goal.id, 1, Some(&format!("student {}", student_id)))
         .await
         .map_err(|e| e.to_string())?;
 
-    Ok(export_data.to_string())
+    Ok(goal)
 }
 
 #[tauri::command]
-async fn import_full_backup(
+async fn set_student_goal_status(
     state: tauri::State<'_, AppState>,
-    file_path: String,
-) -> Result<String, String> {
-    // Read backup file
-    let backup_data = std::fs::read(&file_path).map_err(|e| e.to_string())?;
+    goal_id: i64,
+    status: String,
+) -> Result<StudentGoal, String> {
+    let db = state.db.lock().await;
+    let goal = db.set_student_goal_status(goal_id, &status).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "student_goal", goal_id, 1, Some(&status))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(goal)
+}
 
+/// Writes the Förderplan basis of a student as printable HTML: competency
+/// coverage with the observations behind it.
+#[tauri::command]
+async fn export_support_plan(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    student_id: i64,
+    file_path: String,
+    viewer_id: Option<i64>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let viewer_id = viewer_id.unwrap_or(1);
     let db = state.db.lock().await;
-    let import_result = db
-        .import_full_backup(&backup_data)
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let plan = support_plan::build_support_plan(&db, student_id, viewer_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let html = support_plan::render_support_plan_html(&plan).into_bytes();
+    let html = export_protection::protect(&db, &state.crypto, html, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName {
+            export_type: "support_plan",
+            class: Some(&plan.class_name),
+            extension: "html",
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &html).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
         .await
         .map_err(|e| e.to_string())?;
 
-    // Log the import with file path
     state
         .audit
-        .log_action("import", "full_backup", 0, 1, Some(&file_path))
+        .log_transfer("export", "support_plan", student_id, viewer_id, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("Support plan exported to {}", file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "support_plan", student_id, viewer_id)
         .await
         .map_err(|e| e.to_string())?;
+    Ok(result)
+}
 
-    Ok(format!(
-        "Imported full backup from {}: {}",
-        file_path, import_result
-    ))
+#[tauri::command]
+async fn get_rubrics(state: tauri::State<'_, AppState>) -> Result<Vec<database::RubricDetails>, String> {
+    let db = state.db.lock().await;
+    db.get_rubrics().await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn import_changeset_data(
+async fn create_rubric(
     state: tauri::State<'_, AppState>,
-    changeset_data: String,
-) -> Result<String, String> {
+    rubric: database::RubricInput,
+) -> Result<database::RubricDetails, String> {
+    let db = state.db.lock().await;
+    let created = db.create_rubric(&rubric).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "rubric", created.rubric.id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(created)
+}
+
+#[tauri::command]
+async fn update_rubric(
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    rubric: database::RubricInput,
+) -> Result<database::RubricDetails, String> {
     let db = state.db.lock().await;
-    let import_result = db
-        .apply_changeset_file(changeset_data.as_bytes())
+    let updated = db.update_rubric(id, &rubric).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "rubric", id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(updated)
+}
+
+#[tauri::command]
+async fn delete_rubric(state: tauri::State<'_, AppState>, id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_rubric(id).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("delete", "rubric", id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_observation_rubric_scores(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+) -> Result<Vec<database::RubricScore>, String> {
+    let db = state.db.lock().await;
+    db.get_observation_rubric_scores(observation_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_observation_rubric_scores(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    scores: Vec<database::RubricScore>,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.set_observation_rubric_scores(observation_id, &scores)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("score", "observation", observation_id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_rubric_aggregates(
+    state: tauri::State<'_, AppState>,
+    filter: database::RubricAggregateFilter,
+    viewer_id: Option<i64>,
+) -> Result<Vec<database::RubricAggregate>, String> {
+    let db = state.db.lock().await;
+    db.get_rubric_aggregates(&filter, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn start_observation_session(
+    state: tauri::State<'_, AppState>,
+    class_id: i64,
+    title: Option<String>,
+    subject: Option<String>,
+    viewer_id: Option<i64>,
+) -> Result<ObservationSession, String> {
+    let db = state.db.lock().await;
+    let session = db
+        .start_observation_session(class_id, title.as_deref(), subject.as_deref(), viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("start", "observation_session", session.id, 1, session.title.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(session)
+}
+
+#[tauri::command]
+async fn end_observation_session(
+    state: tauri::State<'_, AppState>,
+    viewer_id: Option<i64>,
+) -> Result<ObservationSession, String> {
+    let db = state.db.lock().await;
+    let session = db
+        .end_observation_session(viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "end",
+            "observation_session",
+            session.id,
+            1,
+            Some(&format!("{} observations", session.observation_count)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(session)
+}
+
+#[tauri::command]
+async fn get_active_observation_session(
+    state: tauri::State<'_, AppState>,
+    viewer_id: Option<i64>,
+) -> Result<Option<ObservationSession>, String> {
+    let db = state.db.lock().await;
+    db.get_active_observation_session(viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_observation_sessions(
+    state: tauri::State<'_, AppState>,
+    class_id: Option<i64>,
+    viewer_id: Option<i64>,
+) -> Result<Vec<ObservationSession>, String> {
+    let db = state.db.lock().await;
+    db.get_observation_sessions(class_id, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_session_observations(
+    state: tauri::State<'_, AppState>,
+    session_id: i64,
+    viewer_id: Option<i64>,
+) -> Result<Vec<Observation>, String> {
+    let db = state.db.lock().await;
+    let mut observations = db
+        .get_session_observations(session_id, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&db).await.map_err(|e| e.to_string())? {
+        pseudonymizer.observations(&mut observations);
+    }
+    Ok(observations)
+}
+
+#[tauri::command]
+async fn get_session_statistics(
+    state: tauri::State<'_, AppState>,
+    session_id: i64,
+    viewer_id: Option<i64>,
+    subject: Option<String>,
+) -> Result<database::SessionStatistics, String> {
+    let db = state.db.lock().await;
+    db.get_session_statistics(session_id, viewer_id.unwrap_or(1), subject.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Counts per category only, so the result can go to school leadership.
+#[tauri::command]
+async fn compare_class_statistics(
+    state: tauri::State<'_, AppState>,
+    class_ids: Vec<i64>,
+    viewer_id: Option<i64>,
+) -> Result<database::ClassStatisticsComparison, String> {
+    let db = state.db.lock().await;
+    db.compare_class_statistics(&class_ids, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Writes the session with its statistics and observations as JSON.
+#[tauri::command]
+async fn export_observation_session(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    session_id: i64,
+    file_path: String,
+    viewer_id: Option<i64>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let viewer_id = viewer_id.unwrap_or(1);
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let statistics = db
+        .get_session_statistics(session_id, viewer_id, None)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut observations = db
+        .get_session_observations(session_id, viewer_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&db).await.map_err(|e| e.to_string())? {
+        pseudonymizer.observations(&mut observations);
+    }
+    let class = db.get_class(statistics.session.class_id).await.map_err(|e| e.to_string())?;
+
+    let records = serde_json::json!({ "observations": &observations });
+    let export = serde_json::json!({
+        "format": "observation_session",
+        "statistics": statistics,
+        "manifest": manifest::ExportManifest::describe(&records),
+        "observations": observations,
+    });
+    let data = serde_json::to_vec_pretty(&export).map_err(|e| e.to_string())?;
+    let data = export_protection::protect(&db, &state.crypto, data, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName {
+            export_type: "session",
+            class: class.as_ref().map(|c| c.name.as_str()),
+            extension: "json",
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &data).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "export",
+            "observation_session",
+            session_id,
+            viewer_id,
+            Some(&format!("{} observations to {}", observations.len(), file_path)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("Session exported to {} ({} observations)", file_path, observations.len()),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "observation_session", session_id, viewer_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn preview_notes_import(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    file_path: String,
+    splitting_rules: Option<notes_import::SplittingRules>,
+) -> Result<notes_import::NotesImportPreview, String> {
+    let db = state.db.lock().await;
+    notes_import::preview_notes_file(&db, student_id, &file_path, &splitting_rules.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Splits a text or Markdown file into observations by its date headings;
+/// `preview_notes_import` shows the result first.
+#[tauri::command]
+async fn import_notes_file(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    file_path: String,
+    splitting_rules: Option<notes_import::SplittingRules>,
+) -> Result<Vec<Observation>, String> {
+    let db = state.db.lock().await;
+    let preview = notes_import::preview_notes_file(&db, student_id, &file_path, &splitting_rules.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())?;
+    let created = notes_import::import_notes(&db, 1, preview)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "import", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "import",
+            "notes",
+            student_id,
+            1,
+            Some(&format!("{} observations from {}", created.len(), file_path)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(created)
+}
+
+#[tauri::command]
+async fn get_students(
+    state: tauri::State<'_, AppState>,
+    options: Option<database::StudentListOptions>,
+    viewer_id: Option<i64>,
+) -> Result<Vec<Student>, String> {
+    let db = state.db.lock().await;
+    let mut students = db
+        .list_students(&options.unwrap_or_default(), viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&db).await.map_err(|e| e.to_string())? {
+        pseudonymizer.students(&mut students);
+    }
+    Ok(students)
+}
+
+#[tauri::command]
+async fn get_classes(state: tauri::State<'_, AppState>) -> Result<Vec<Class>, String> {
+    let db = state.db.lock().await;
+    db.get_classes().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_class_layout(state: tauri::State<'_, AppState>, class_id: i64) -> Result<Option<ClassLayout>, String> {
+    let db = state.db.lock().await;
+    db.get_class_layout(class_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_class_layout(
+    state: tauri::State<'_, AppState>,
+    class_id: i64,
+    layout: String,
+) -> Result<ClassLayout, String> {
+    let db = state.db.lock().await;
+    let stored = db
+        .set_class_layout(class_id, &layout)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "class_layout", class_id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(stored)
+}
+
+#[tauri::command]
+async fn get_class_agreements(
+    state: tauri::State<'_, AppState>,
+    class_id: Option<i64>,
+) -> Result<Vec<ClassAgreement>, String> {
+    let db = state.db.lock().await;
+    db.get_class_agreements(class_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_class_agreement(
+    state: tauri::State<'_, AppState>,
+    class_id: i64,
+    agreement: database::ClassAgreementInput,
+) -> Result<ClassAgreement, String> {
+    let db = state.db.lock().await;
+    let stored = db
+        .set_class_agreement(class_id, &agreement)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "update",
+            "class_agreement",
+            stored.id,
+            1,
+            Some(&format!("{} for class {}, collected {}", stored.kind, class_id, stored.collected_on)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(stored)
+}
+
+#[tauri::command]
+async fn delete_class_agreement(state: tauri::State<'_, AppState>, class_id: i64, kind: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_class_agreement(class_id, &kind)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("delete", "class_agreement", class_id, 1, Some(&kind))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Copies the photos attached to a class's observations into `dir`, named
+/// by student and date, each encrypted with `protection` as other exports.
+/// Needs a photo agreement in force for the class.
+#[tauri::command]
+async fn export_class_photos(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    class_id: i64,
+    dir: String,
+    viewer_id: Option<i64>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let viewer_id = viewer_id.unwrap_or(1);
+    let params = serde_json::json!({
+        "class_id": class_id,
+        "dir": dir,
+        "viewer_id": viewer_id,
+        "recipient_key": protection.as_ref().and_then(|p| p.recipient_key.clone()),
+        "passphrase_protected": protection.as_ref().is_some_and(|p| p.passphrase.is_some()),
+    });
+    run_job(&state, "export_class_photos", params, None, async {
+        let db = state.db.lock().await;
+        db.ensure_not_frozen().map_err(|e| e.to_string())?;
+        let agreement = db
+            .require_class_agreement(class_id, "photo")
+            .await
+            .map_err(|e| e.to_string())?;
+        let directory = std::path::Path::new(&dir);
+        if !directory.is_dir() {
+            return Err(format!("{} is not a directory", dir));
+        }
+
+        let students: std::collections::HashMap<i64, Student> = db
+            .get_students_by_class(class_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|s| (s.id, s))
+            .collect();
+        let mut photos = db.get_class_photos(class_id).await.map_err(|e| e.to_string())?;
+        photos.retain(|(_, observation)| observation.is_visible_to(viewer_id));
+        if photos.is_empty() {
+            return Err("The class has no photos".to_string());
+        }
+
+        let mut removable_media = false;
+        for (attachment, observation) in &photos {
+            let student = students
+                .get(&observation.student_id)
+                .map(|s| format!("{}_{}", s.last_name, s.first_name))
+                .unwrap_or_default();
+            let extension = std::path::Path::new(&attachment.filename)
+                .extension()
+                .map(|e| export_naming::sanitize(&e.to_string_lossy()))
+                .filter(|e| !e.is_empty())
+                .unwrap_or_else(|| "jpg".to_string());
+            let name = format!(
+                "{}_{}_{}.{}",
+                export_naming::sanitize(&student),
+                observation.created_at.format("%Y-%m-%d"),
+                attachment.id,
+                extension
+            );
+            let path = directory.join(name);
+            if path.exists() {
+                return Err(format!("{} exists already", path.display()));
+            }
+            let data = db.get_attachment_data(attachment.id).await.map_err(|e| e.to_string())?;
+            // Each photo on its own, so one can be opened without the others
+            let data = export_protection::protect(&db, &state.crypto, data, protection.as_ref())
+                .await
+                .map_err(|e| e.to_string())?;
+            removable_media = export_target::write_synced(&path.to_string_lossy(), &data).map_err(|e| e.to_string())?;
+        }
+
+        transfer_locations::record(&db, "export", &dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        state
+            .audit
+            .log_transfer(
+                "export",
+                "class_photos",
+                class_id,
+                viewer_id,
+                Some(&format!("{} photos to {} (photo agreement {})", photos.len(), dir, agreement.id)),
+                &dir,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut result = export_target::ExportResult {
+            message: format!("{} photos exported to {}", photos.len(), dir),
+            file_path: dir.clone(),
+            removable_media,
+            post_export: None,
+        };
+        export_hook::run(&app, db, &state.audit, &mut result, "class_photos", class_id, viewer_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(result)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn create_class(
+    state: tauri::State<'_, AppState>,
+    name: String,
+    school_year: String,
+) -> Result<Class, String> {
+    let db = state.db.lock().await;
+    db.create_class(name, school_year)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_student(
+    state: tauri::State<'_, AppState>,
+    class_id: i64,
+    first_name: String,
+    last_name: String,
+    status: Option<String>,
+    date_of_birth: Option<chrono::NaiveDate>,
+    notes: Option<String>,
+    external_id: Option<String>,
+) -> Result<Student, String> {
+    let db = state.db.lock().await;
+    let details = database::StudentDetails {
+        date_of_birth,
+        notes,
+        external_id,
+    };
+    db.create_student_with_details(class_id, first_name, last_name, status, details)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Rectification under GDPR Art. 16. The audit entry lists the changes as
+/// `{"changes": [{"field", "original_value", "new_value"}]}`; names, date of
+/// birth, notes and external id are listed with `field` only, so they stay
+/// out of the log.
+#[tauri::command]
+async fn update_student(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    status: Option<String>,
+    class_id: Option<i64>,
+    date_of_birth: Option<String>,
+    notes: Option<String>,
+    external_id: Option<String>,
+    user_id: Option<i64>,
+) -> Result<Student, String> {
+    let user_id = user_id.unwrap_or(1);
+    let db = state.db.lock().await;
+    let edit = database::StudentEdit {
+        first_name,
+        last_name,
+        status,
+        class_id,
+        date_of_birth,
+        notes,
+        external_id,
+    };
+    let (before, after) = db.update_student(student_id, edit).await.map_err(|e| e.to_string())?;
+
+    // Status, class and leaving date with their values; the personal data only by field
+    let mut changes = Vec::new();
+    for (field, original_value, new_value) in [
+        ("status", serde_json::json!(before.status), serde_json::json!(after.status)),
+        ("class_id", serde_json::json!(before.class_id), serde_json::json!(after.class_id)),
+        ("left_at", serde_json::json!(before.left_at), serde_json::json!(after.left_at)),
+    ] {
+        if original_value != new_value {
+            changes.push(serde_json::json!({ "field": field, "original_value": original_value, "new_value": new_value }));
+        }
+    }
+    for (field, changed) in [
+        ("first_name", before.first_name != after.first_name),
+        ("last_name", before.last_name != after.last_name),
+        ("date_of_birth", before.date_of_birth != after.date_of_birth),
+        ("notes", before.notes != after.notes),
+        ("external_id", before.external_id != after.external_id),
+    ] {
+        if changed {
+            changes.push(serde_json::json!({ "field": field }));
+        }
+    }
+    if !changes.is_empty() {
+        let details = serde_json::json!({ "changes": changes }).to_string();
+        state
+            .audit
+            .log_action("update", "student", student_id, user_id, Some(&details))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(after)
+}
+
+#[tauri::command]
+async fn get_guardians(state: tauri::State<'_, AppState>, student_id: i64) -> Result<Vec<Guardian>, String> {
+    let db = state.db.lock().await;
+    let mut guardians = db.get_guardians(student_id).await.map_err(|e| e.to_string())?;
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&db).await.map_err(|e| e.to_string())? {
+        pseudonymizer.guardians(&mut guardians);
+    }
+    Ok(guardians)
+}
+
+#[tauri::command]
+async fn create_guardian(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    guardian: database::GuardianInput,
+) -> Result<Guardian, String> {
+    let db = state.db.lock().await;
+    let guardian = db
+        .create_guardian(student_id, guardian)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "guardian", guardian.id, 1, Some(&format!("student {}", student_id)))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(guardian)
+}
+
+#[tauri::command]
+async fn update_guardian(
+    state: tauri::State<'_, AppState>,
+    guardian_id: i64,
+    guardian: database::GuardianInput,
+) -> Result<Guardian, String> {
+    let db = state.db.lock().await;
+    let guardian = db
+        .update_guardian(guardian_id, guardian)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "guardian", guardian_id, 1, Some(&format!("student {}", guardian.student_id)))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(guardian)
+}
+
+#[tauri::command]
+async fn delete_guardian(state: tauri::State<'_, AppState>, guardian_id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_guardian(guardian_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("delete", "guardian", guardian_id, 1, None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn search_observations(
+    state: tauri::State<'_, AppState>,
+    query: Option<String>,
+    student_id: Option<i64>,
+    category: Option<String>,
+    viewer_id: Option<i64>,
+    subject: Option<String>,
+    language: Option<String>,
+) -> Result<Vec<Observation>, String> {
+    let db = state.db.lock().await;
+    if language.is_some() {
+        db.refresh_observation_languages().await.map_err(|e| e.to_string())?;
+    }
+    let mut observations = db
+        .search_observations_in_language(query, student_id, category, subject, language)
+        .await
+        .map_err(|e| e.to_string())?;
+    observations.retain(|o| o.is_visible_to(viewer_id.unwrap_or(1)));
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&db).await.map_err(|e| e.to_string())? {
+        pseudonymizer.observations(&mut observations);
+    }
+    Ok(observations)
+}
+
+#[tauri::command]
+async fn set_observation_language(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    language: String,
+) -> Result<Observation, String> {
+    let db = state.db.lock().await;
+    let observation = db
+        .set_observation_language(observation_id, &language)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "observation_language", observation_id, 1, Some(&language))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(observation)
+}
+
+#[tauri::command]
+async fn export_student_data(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    format: String,
+    redact: Option<bool>,
+    from: Option<String>,
+    to: Option<String>,
+    categories: Option<Vec<String>>,
+) -> Result<String, String> {
+    let params = serde_json::json!({
+        "student_id": student_id,
+        "format": format,
+        "redact": redact,
+        "from": from,
+        "to": to,
+        "categories": categories,
+    });
+    run_job(&state, "export_student_data", params, None, async {
+        let scope = gdpr::ExportScope::parse(from.as_deref(), to.as_deref(), categories.unwrap_or_default())
+            .map_err(|e| e.to_string())?;
+        let reader = {
+            let db = state.db.lock().await;
+            // The export reads through the read-only handle, so the reference
+            // codes it prints are given out first; a frozen database keeps the ones it has
+            if db.ensure_not_frozen().is_ok() {
+                let observation_ids: Vec<i64> = db
+                    .search_observations(None, Some(student_id), None)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .iter()
+                    .map(|o| o.id)
+                    .collect();
+                reference_codes::assign(&db, "student", &[student_id]).await.map_err(|e| e.to_string())?;
+                reference_codes::assign(&db, "observation", &observation_ids).await.map_err(|e| e.to_string())?;
+            }
+            db.reader()
+        };
+        let mut export_data = state
+            .gdpr
+            .export_student_data_in_scope(&reader, student_id, &scope)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Exports for third parties must not carry other children's data
+        let redact = redact.unwrap_or(false);
+        if redact {
+            let redactor = redaction::redactor_for_student(&reader, student_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            redaction::redact_observations(&redactor, &mut export_data.observations);
+        }
+
+        // Log the export
+        let mut details = if redact { format!("{} (redacted)", format) } else { format.clone() };
+        if !scope.is_full() {
+            details.push_str(&format!(", {}", scope.describe()));
+        }
+        state
+            .audit
+            .log_action("export", "student_data", student_id, 1, Some(&details))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        match format.as_str() {
+            "json" => Ok(serde_json::to_string_pretty(&export_data).map_err(|e| e.to_string())?),
+            "csv" => Ok(state
+                .gdpr
+                .render_student_export_csv(&reader, &export_data)
+                .await
+                .map_err(|e| e.to_string())?),
+            _ => Err("Unsupported export format".to_string()),
+        }
+    })
+    .await
+}
+
+#[tauri::command]
+async fn generate_weekly_summary(
+    state: tauri::State<'_, AppState>,
+    class_id: i64,
+    week: String,
+    store: Option<bool>,
+    viewer_id: Option<i64>,
+    languages: Option<reports::LanguageDisplay>,
+) -> Result<reports::WeeklySummary, String> {
+    let reader = state.db.lock().await.reader();
+    let mut summary = state
+        .reports
+        .generate_weekly_summary(&reader, class_id, &week, viewer_id.unwrap_or(1), languages.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if store.unwrap_or(false) {
+        let db = state.db.lock().await;
+        state
+            .reports
+            .store_weekly_summary(&db, &mut summary)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    // Log the report generation
+    state
+        .audit
+        .log_action("export", "weekly_summary", class_id, 1, Some(&week))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&reader).await.map_err(|e| e.to_string())? {
+        pseudonymizer.weekly_summary(&mut summary);
+    }
+    Ok(summary)
+}
+
+/// Writes the weekly summary as PDF, for printing or filing.
+#[tauri::command]
+async fn export_weekly_summary_pdf(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    class_id: i64,
+    week: String,
+    file_path: String,
+    viewer_id: Option<i64>,
+    languages: Option<reports::LanguageDisplay>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let reader = state.db.lock().await.reader();
+    let mut summary = state
+        .reports
+        .generate_weekly_summary(&reader, class_id, &week, viewer_id.unwrap_or(1), languages.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&reader).await.map_err(|e| e.to_string())? {
+        pseudonymizer.weekly_summary(&mut summary);
+    }
+
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let data = reports::render_weekly_summary_pdf(&summary);
+    let data = export_protection::protect(&db, &state.crypto, data, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName {
+            export_type: "weekly_summary",
+            class: Some(&summary.class_name),
+            extension: "pdf",
+        },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &data).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "export",
+            "weekly_summary",
+            class_id,
+            1,
+            Some(&format!("{} as PDF to {}", week, file_path)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!(
+            "Weekly summary with {} observations exported to {}",
+            summary.total_observations, file_path
+        ),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "weekly_summary", class_id, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn get_report_artifacts(
+    state: tauri::State<'_, AppState>,
+    class_id: Option<i64>,
+) -> Result<Vec<ReportArtifact>, String> {
+    let db = state.db.lock().await;
+    db.get_report_artifacts(class_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_report_artifact_content(
+    state: tauri::State<'_, AppState>,
+    artifact_id: i64,
+) -> Result<String, String> {
+    let db = state.db.lock().await;
+    let content = db
+        .get_report_artifact_content(artifact_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("read", "report_artifact", artifact_id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    String::from_utf8(content).map_err(|e| e.to_string())
+}
+
+// P2P sync commands removed - using file-based changeset sync
+
+#[tauri::command]
+async fn export_changeset(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let db = state.db.lock().await;
+    let changeset = db
+        .get_pending_changesets("export")
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Convert to base64 for safe transport (legacy compatibility)
+    let encoded = base64::prelude::BASE64_STANDARD.encode(&changeset);
+
+    // Log the export
+    state
+        .audit
+        .log_action("export", "changeset", 0, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(encoded)
+}
+
+#[tauri::command]
+async fn export_changeset_to_file(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    file_path: String,
+    days_back: Option<u32>,
+    compress: Option<bool>,
+    attachment_policy: Option<attachment_sync::AttachmentPolicy>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let params = serde_json::json!({
+        "file_path": file_path,
+        "days_back": days_back,
+        "compress": compress,
+        "attachment_policy": attachment_policy,
+        "recipient_key": protection.as_ref().and_then(|p| p.recipient_key.clone()),
+        "passphrase_protected": protection.as_ref().is_some_and(|p| p.passphrase.is_some()),
+    });
+    run_job(&state, "export_changeset", params, None, async {
+        let days_back = days_back.unwrap_or(database::DEFAULT_CHANGESET_DAYS_BACK);
+        let attachment_policy = attachment_policy.unwrap_or_default();
+        let db = state.db.lock().await;
+        let retractions = db.get_retractions(true).await.map_err(|e| e.to_string())?.len();
+
+        // Generate enhanced changeset with metadata
+        let changeset_data = db
+            .create_changeset_file_with_policy(days_back, &attachment_policy)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Opt-in; once a school configured a TSA, an export without its token fails
+        let tsa_url = trusted_timestamp::configured_tsa(&db).await.map_err(|e| e.to_string())?;
+        let changeset_data = match &tsa_url {
+            Some(tsa_url) => trusted_timestamp::attach_to_changeset(tsa_url, &changeset_data)
+                .await
+                .map_err(|e| format!("Failed to get a trusted timestamp: {}", e))?,
+            None => changeset_data,
+        };
+        // Compressed unless a peer with an older app version has to read it
+        let changeset_data = if compress.unwrap_or(true) {
+            changeset_codec::compress(&changeset_data).map_err(|e| e.to_string())?
+        } else {
+            changeset_data
+        };
+        let changeset_data = export_protection::protect(&db, &state.crypto, changeset_data, protection.as_ref())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let file_path = export_naming::resolve_path(
+            &db,
+            &state.crypto,
+            &file_path,
+            &export_naming::ExportName { export_type: "changeset", class: None, extension: "sbchange" },
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+        // Write to file
+        let removable_media = export_target::write_synced(&file_path, &changeset_data)
+            .map_err(|e| format!("Failed to write changeset file: {}", e))?;
+
+        transfer_locations::record(&db, "export", &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Log the export with file path
+        state
+            .audit
+            .log_transfer("export", "changeset_file", 0, 1, Some(&file_path), &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let file_size = changeset_data.len();
+        let mut message = format!(
+            "Changeset exported to {} ({} bytes)",
+            file_path, file_size
+        );
+        if let Some(tsa_url) = &tsa_url {
+            message.push_str(&format!("\nTimestamped by {}", tsa_url));
+        }
+        if retractions > 0 {
+            message.push_str(&format!(
+                "\n{} retractions included for observations corrected or deleted after an earlier export",
+                retractions
+            ));
+        }
+        let mut result = export_target::ExportResult { message, file_path, removable_media, post_export: None };
+        export_hook::run(&app, db, &state.audit, &mut result, "changeset_file", 0, 1)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(result)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn estimate_changeset_size(
+    state: tauri::State<'_, AppState>,
+    days_back: Option<u32>,
+    attachment_policy: Option<attachment_sync::AttachmentPolicy>,
+) -> Result<database::ChangesetSizeEstimate, String> {
+    let db = state.db.lock().await;
+    db.estimate_changeset_size(
+        days_back.unwrap_or(database::DEFAULT_CHANGESET_DAYS_BACK),
+        &attachment_policy.unwrap_or_default(),
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Request file for the attachments changesets only listed, to be answered
+/// by the other device with `export_missing_attachments`.
+#[tauri::command]
+async fn export_attachment_request(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    file_path: String,
+) -> Result<export_target::ExportResult, String> {
+    let db = state.db.lock().await;
+    let request = attachment_sync::build_request(&db, &state.crypto.get_device_id())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "attachment-request", class: None, extension: "json" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &request)
+        .map_err(|e| format!("Failed to write request file: {}", e))?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("export", "attachment_request", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("Attachment request exported to {}", file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "attachment_request", 0, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn export_missing_attachments(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    request_file: String,
+    file_path: String,
+    attachment_policy: Option<attachment_sync::AttachmentPolicy>,
+    protection: Option<export_protection::ExportProtection>,
+    passphrase: Option<String>,
+) -> Result<export_target::ExportResult, String> {
+    let request = std::fs::read(&request_file).map_err(|e| format!("Failed to read request file: {}", e))?;
+    let request = export_protection::open(&state.crypto, &request, passphrase.as_deref()).map_err(|e| e.to_string())?;
+
+    let db = state.db.lock().await;
+    let (transfer, count) = attachment_sync::export_missing(
+        &db,
+        &state.crypto.get_device_id(),
+        &request,
+        &attachment_policy.unwrap_or_default(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let transfer = export_protection::protect(&db, &state.crypto, transfer, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "attachments", class: None, extension: "json" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &transfer)
+        .map_err(|e| format!("Failed to write attachment file: {}", e))?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("export", "attachments", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("{} attachments exported to {}", count, file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "attachments", 0, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn import_attachment_transfer(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    passphrase: Option<String>,
+) -> Result<String, String> {
+    let transfer = std::fs::read(&file_path).map_err(|e| format!("Failed to read attachment file: {}", e))?;
+    let transfer = export_protection::open(&state.crypto, &transfer, passphrase.as_deref()).map_err(|e| e.to_string())?;
+
+    let db = state.db.lock().await;
+    let received = attachment_sync::apply_transfer(&db, &transfer)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "import", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("import", "attachments", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(format!("{} attachments received", received))
+}
+
+/// The key another device needs to address a handover package to this one.
+#[tauri::command]
+async fn get_device_public_key(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    state.crypto.device_public_key().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn generate_handover_package(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    class_id: i64,
+    recipient_device: String,
+    file_path: String,
+) -> Result<export_target::ExportResult, String> {
+    let db = state.db.lock().await;
+    let (package, summary) = handover::generate_package(&db, &state.crypto, class_id, &recipient_device)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "handover", class: Some(&summary.class_name), extension: "json" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &package)
+        .map_err(|e| format!("Failed to write handover package: {}", e))?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "export",
+            "handover_package",
+            class_id,
+            1,
+            Some(&format!("for device key {} to {}", recipient_device.trim(), file_path)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!(
+            "Handover package for class {} written to {}: {} students, {} observations, {} attachments",
+            summary.class_name, file_path, summary.students, summary.observations, summary.attachments
+        ),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "handover_package", class_id, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn import_handover_package(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+) -> Result<database::HandoverImport, String> {
+    let package = std::fs::read(&file_path).map_err(|e| format!("Failed to read handover package: {}", e))?;
+
+    let db = state.db.lock().await;
+    let record = handover::import_package(&db, &state.crypto, &package)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "import", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "import",
+            "handover_package",
+            record.class_id,
+            1,
+            Some(&format!("from device {}, package {}", record.source_device_id, record.package_hash)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(record)
+}
+
+#[tauri::command]
+async fn get_handover_imports(state: tauri::State<'_, AppState>) -> Result<Vec<database::HandoverImport>, String> {
+    let db = state.db.lock().await;
+    db.get_handover_imports().await.map_err(|e| e.to_string())
+}
+
+/// Observations corrected or deleted after they were exported, i.e. data that
+/// may still exist in its old form on other devices.
+#[tauri::command]
+async fn get_retractions(
+    state: tauri::State<'_, AppState>,
+    pending_only: Option<bool>,
+) -> Result<Vec<database::Retraction>, String> {
+    let db = state.db.lock().await;
+    db.get_retractions(pending_only.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn import_changeset(
+    state: tauri::State<'_, AppState>,
+    changeset_data: String,
+) -> Result<(), String> {
+    let changeset = base64::prelude::BASE64_STANDARD
+        .decode(&changeset_data)
+        .map_err(|e| format!("Invalid changeset format: {}", e))?;
+
+    let db = state.db.lock().await;
+    db.apply_changeset(&changeset, "import")
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Log the import
+    state
+        .audit
+        .log_action("import", "changeset", 0, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn import_changeset_from_file(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    confirm_hard_deletions: Option<bool>,
+    justification: Option<String>,
+    operation_id: Option<String>,
+    passphrase: Option<String>,
+    quarantine: Option<bool>,
+) -> Result<String, String> {
+    let confirm_hard_deletions = confirm_hard_deletions.unwrap_or(false);
+    let details = if confirm_hard_deletions {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!("{} - hard deletions confirmed: {}", file_path, justification)
+    } else {
+        file_path.clone()
+    };
+    let operation = state.operations.register(operation_id);
+    let params = serde_json::json!({
+        "file_path": file_path,
+        "confirm_hard_deletions": confirm_hard_deletions,
+        "justification": justification,
+        "passphrase_protected": passphrase.is_some(),
+        "quarantine": quarantine,
+    });
+    run_job(&state, "import_changeset", params, Some(&operation), async {
+        // Read changeset file
+        let changeset_data =
+            std::fs::read(&file_path).map_err(|e| format!("Failed to read changeset file: {}", e))?;
+        let changeset_data =
+            export_protection::open(&state.crypto, &changeset_data, passphrase.as_deref()).map_err(|e| e.to_string())?;
+
+        let db = state.db.lock().await;
+        if quarantine.unwrap_or(false) {
+            let staged = db
+                .quarantine_changeset(&file_path, &changeset_data)
+                .await
+                .map_err(|e| e.to_string())?;
+            transfer_locations::record(&db, "import", &file_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            state
+                .audit
+                .log_action("quarantine", "changeset_file", staged.id, 1, Some(&file_path))
+                .await
+                .map_err(|e| e.to_string())?;
+
+            return Ok(format!(
+                "Changeset from {} staged for review as import {}: {} new, {} updated, {} unchanged, {} duplicates, {} invalid",
+                file_path,
+                staged.id,
+                staged.new_observations,
+                staged.updated_observations,
+                staged.unchanged_observations,
+                staged.duplicate_observations,
+                staged.invalid_observations
+            ));
+        }
+
+        let import_result = db
+            .apply_changeset_file_with_options(
+                &changeset_data,
+                confirm_hard_deletions,
+                &operation.token,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        transfer_locations::record(&db, "import", &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Log the import with file path
+        state
+            .audit
+            .log_transfer("import", "changeset_file", 0, 1, Some(&details), &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!(
+            "Imported changeset from {}: {}",
+            file_path, import_result
+        ))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_quarantined_imports(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<database::QuarantinedImport>, String> {
+    let db = state.db.lock().await;
+    db.list_quarantined_imports().await.map_err(|e| e.to_string())
+}
+
+/// Applies a changeset staged with `import_changeset_from_file(quarantine)`.
+#[tauri::command]
+async fn promote_import(
+    state: tauri::State<'_, AppState>,
+    import_id: i64,
+    confirm_hard_deletions: Option<bool>,
+    justification: Option<String>,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let params = serde_json::json!({
+        "import_id": import_id,
+        "confirm_hard_deletions": confirm_hard_deletions,
+        "justification": justification,
+    });
+    let confirm_hard_deletions = confirm_hard_deletions.unwrap_or(false);
+    let justification = if confirm_hard_deletions {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!(" - hard deletions confirmed: {}", justification)
+    } else {
+        String::new()
+    };
+    let operation = state.operations.register(operation_id);
+    run_job(&state, "promote_import", params, Some(&operation), async {
+        let db = state.db.lock().await;
+        let staged = db
+            .get_quarantined_import(import_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Staged import {} not found", import_id))?;
+        let import_result = db
+            .promote_quarantined_import(import_id, confirm_hard_deletions, &operation.token)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        state
+            .audit
+            .log_transfer(
+                "import",
+                "changeset_file",
+                0,
+                1,
+                Some(&format!("{} (staged as import {}){}", staged.file_path, import_id, justification)),
+                &staged.file_path,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!("Imported changeset from {}: {}", staged.file_path, import_result))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn discard_import(state: tauri::State<'_, AppState>, import_id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.discard_quarantined_import(import_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("discard", "quarantined_import", import_id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Recognizes scanned paper notes with the configured OCR program and stages
+/// them in the quarantine, each with the student of the class whose name was
+/// found. Nothing becomes an observation before `promote_scanned_notes`.
+#[tauri::command]
+async fn import_scanned_notes(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    file_paths: Vec<String>,
+    class_id: i64,
+    operation_id: Option<String>,
+) -> Result<database::QuarantinedImport, String> {
+    if file_paths.is_empty() {
+        return Err("No scans selected".to_string());
+    }
+    let params = serde_json::json!({ "file_paths": file_paths, "class_id": class_id });
+    let operation = state.operations.register(operation_id);
+    run_job(&state, "import_scanned_notes", params, Some(&operation), async {
+        let (engine, students, categories, default_category) = {
+            let db = state.db.lock().await;
+            let engine = ocr::configured_engine(&db)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("No OCR program configured, set {} first", ocr::OCR_BINARY_SETTING))?;
+            let mut students = db.get_students_by_class(class_id).await.map_err(|e| e.to_string())?;
+            students.retain(|s| s.status == "active");
+            let mut categories = db.get_categories().await.map_err(|e| e.to_string())?;
+            let default_category = categories
+                .iter()
+                .find(|c| c.is_active)
+                .map(|c| c.name.clone())
+                .ok_or_else(|| "No active categories".to_string())?;
+            let locale = localization::get_locale(&db).await.map_err(|e| e.to_string())?;
+            localization::localize_categories(&mut categories, &locale);
+            // The sheets print the localized names
+            let categories: Vec<String> = categories
+                .into_iter()
+                .flat_map(|c| c.display_name.into_iter().chain(std::iter::once(c.name)))
+                .collect();
+            (engine, students, categories, default_category)
+        };
+
+        // The database is not locked while the OCR program runs
+        let mut notes = Vec::with_capacity(file_paths.len());
+        for (index, file_path) in file_paths.iter().enumerate() {
+            operation.token.check().map_err(|e| e.to_string())?;
+            operation.token.report_progress(index, file_paths.len());
+            let text = engine.recognize(&app, file_path).await.map_err(|e| e.to_string())?;
+            notes.push(ocr::prepare_note(file_path, &text, &students, &categories));
+        }
+
+        let db = state.db.lock().await;
+        let staged = db
+            .quarantine_scanned_notes(&format!("{} Scans", notes.len()), &notes, &default_category)
+            .await
+            .map_err(|e| e.to_string())?;
+        state
+            .audit
+            .log_action(
+                "import",
+                "scanned_notes",
+                staged.id,
+                1,
+                Some(&format!("{} scans, {} unmatched", notes.len(), staged.invalid_observations)),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(staged)
+    })
+    .await
+}
+
+/// Only an admin chooses the program that is run on every scan.
+#[tauri::command]
+async fn set_ocr_config(
+    state: tauri::State<'_, AppState>,
+    binary: String,
+    language: Option<String>,
+    admin_id: i64,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    ocr::set_config(&db, &binary, language.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_scanned_notes(state: tauri::State<'_, AppState>, import_id: i64) -> Result<Vec<ocr::ScannedNote>, String> {
+    let db = state.db.lock().await;
+    db.get_scanned_notes(import_id).await.map_err(|e| e.to_string())
+}
+
+/// Creates observations for the scanned notes the user confirmed a student
+/// for; the rest are discarded with the import.
+#[tauri::command]
+async fn promote_scanned_notes(
+    state: tauri::State<'_, AppState>,
+    import_id: i64,
+    confirmed: Vec<database::ConfirmedScan>,
+    author_id: Option<i64>,
+) -> Result<Vec<Observation>, String> {
+    let db = state.db.lock().await;
+    let author_id = author_id.unwrap_or(1);
+    let created = db
+        .promote_scanned_notes(import_id, author_id, &confirmed)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "import",
+            "scanned_notes",
+            import_id,
+            author_id,
+            Some(&format!("{} observations created", created.len())),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(created)
+}
+
+/// Stages the lines a teacher copied from a spreadsheet for the class's
+/// students, with the matches found. Nothing becomes an observation before
+/// `promote_clipboard_rows`.
+#[tauri::command]
+async fn import_observations_clipboard(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    class_id: i64,
+) -> Result<database::QuarantinedImport, String> {
+    use tauri_plugin_clipboard_manager::ClipboardExt;
+
+    let content = app
+        .clipboard()
+        .read_text()
+        .map_err(|e| format!("Clipboard does not contain text: {}", e))?;
+
+    let db = state.db.lock().await;
+    let mut students = db.get_students_by_class(class_id).await.map_err(|e| e.to_string())?;
+    students.retain(|s| s.status == "active");
+    let mut categories = db.get_categories().await.map_err(|e| e.to_string())?;
+    let default_category = categories
+        .iter()
+        .find(|c| c.is_active)
+        .map(|c| c.name.clone())
+        .ok_or_else(|| "No active categories".to_string())?;
+    let locale = localization::get_locale(&db).await.map_err(|e| e.to_string())?;
+    localization::localize_categories(&mut categories, &locale);
+
+    let rows = clipboard_import::prepare_rows(&content, &students, &categories, &default_category)
+        .map_err(|e| e.to_string())?;
+    let staged = db.quarantine_clipboard_rows(&rows).await.map_err(|e| e.to_string())?;
+    state
+        .audit
+        .log_action(
+            "import",
+            "clipboard_observations",
+            staged.id,
+            1,
+            Some(&format!("class {}: {} lines, {} unmatched", class_id, rows.len(), staged.invalid_observations)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(staged)
+}
+
+#[tauri::command]
+async fn get_clipboard_rows(
+    state: tauri::State<'_, AppState>,
+    import_id: i64,
+) -> Result<Vec<clipboard_import::ClipboardRow>, String> {
+    let db = state.db.lock().await;
+    db.get_clipboard_rows(import_id).await.map_err(|e| e.to_string())
+}
+
+/// Creates observations for the copied lines the user confirmed a student
+/// for; the rest are discarded with the import.
+#[tauri::command]
+async fn promote_clipboard_rows(
+    state: tauri::State<'_, AppState>,
+    import_id: i64,
+    confirmed: Vec<database::ConfirmedScan>,
+    author_id: Option<i64>,
+) -> Result<Vec<Observation>, String> {
+    let db = state.db.lock().await;
+    let author_id = author_id.unwrap_or(1);
+    let created = db
+        .promote_clipboard_rows(import_id, author_id, &confirmed)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "import",
+            "clipboard_observations",
+            import_id,
+            author_id,
+            Some(&format!("{} observations created", created.len())),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(created)
+}
+
+#[tauri::command]
+async fn export_sync_receipt(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    file_path: String,
+    peer_device_id: Option<String>,
+) -> Result<export_target::ExportResult, String> {
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let receipt = db
+        .create_sync_receipt(peer_device_id.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "receipt", class: None, extension: "json" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &receipt)
+        .map_err(|e| format!("Failed to write receipt file: {}", e))?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("export", "sync_receipt", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("Sync receipt exported to {}", file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "sync_receipt", 0, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn import_sync_receipt(state: tauri::State<'_, AppState>, file_path: String) -> Result<String, String> {
+    let receipt = std::fs::read(&file_path).map_err(|e| format!("Failed to read receipt file: {}", e))?;
+
+    let db = state.db.lock().await;
+    let result = db.apply_sync_receipt(&receipt).await.map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "import", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("import", "sync_receipt", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(result)
+}
+
+/// Cross-checks the audit log against the stored data, see `consistency`.
+#[tauri::command]
+async fn run_consistency_audit(state: tauri::State<'_, AppState>) -> Result<consistency::ConsistencyReport, String> {
+    let db = state.db.lock().await;
+    let report = consistency::run_consistency_audit(&db, &state.audit)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "consistency_audit",
+            "app",
+            0,
+            1,
+            Some(&format!("{} anomalies", report.anomalies.len())),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(report)
+}
+
+#[tauri::command]
+async fn get_applied_operations(
+    state: tauri::State<'_, AppState>,
+    limit: Option<i64>,
+) -> Result<Vec<SyncHistoryEntry>, String> {
+    let db = state.db.lock().await;
+    db.get_applied_operations(limit)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// What a finished job leaves behind, shown in the job history.
+trait JobResult {
+    fn result_path(&self) -> Option<String> {
+        None
+    }
+}
+
+impl JobResult for String {}
+
+impl JobResult for database::RestoreReport {}
+
+impl JobResult for database::QuarantinedImport {}
+
+impl JobResult for archive::ArchiveBundle {
+    fn result_path(&self) -> Option<String> {
+        Some(self.directory.clone())
+    }
+}
+
+impl JobResult for export_target::ExportResult {
+    fn result_path(&self) -> Option<String> {
+        Some(self.file_path.clone())
+    }
+}
+
+/// How often the progress a job reports on its token is written to the history.
+const JOB_PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Runs a long-running command as a job, so it shows up in the history and
+/// can be retried. The history is bookkeeping only: if it cannot be written,
+/// the command still runs. Progress the work reports on the operation's
+/// token is recorded while it runs.
+async fn run_job<T: JobResult>(
+    state: &AppState,
+    job_type: &str,
+    params: serde_json::Value,
+    operation: Option<&operations::OperationGuard<'_>>,
+    work: impl std::future::Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    let operation_id = operation.and_then(|o| o.operation_id());
+    let (job_id, recorder) = {
+        let db = state.db.lock().await;
+        match db.create_job(job_type, &params, operation_id).await {
+            Ok(job_id) => (Some(job_id), Some(db.job_progress_recorder(job_id))),
+            Err(e) => {
+                eprintln!("Failed to record {} job: {}", job_type, e);
+                (None, None)
+            }
+        }
+    };
+
+    // A task of its own, so a write waiting for the job's transaction never stalls the job
+    let progress_task = recorder.zip(operation).map(|(recorder, operation)| {
+        let token = operation.token.clone();
+        tokio::spawn(async move {
+            let mut recorded = 0.0;
+            loop {
+                tokio::time::sleep(JOB_PROGRESS_INTERVAL).await;
+                let progress = token.progress();
+                if progress > recorded {
+                    if let Err(e) = recorder.record(progress).await {
+                        eprintln!("Failed to record job progress: {}", e);
+                    }
+                    recorded = progress;
+                }
+            }
+        })
+    });
+
+    let result = work.await;
+    if let Some(task) = progress_task {
+        task.abort();
+    }
+
+    if let Some(job_id) = job_id {
+        let (status, result_path, error) = match &result {
+            Ok(value) => ("completed", value.result_path(), None),
+            Err(e) if operation.is_some_and(|o| o.token.is_cancelled()) => ("cancelled", None, Some(e.as_str())),
+            Err(e) => ("failed", None, Some(e.as_str())),
+        };
+        let db = state.db.lock().await;
+        if let Err(e) = db.finish_job(job_id, status, result_path.as_deref(), error).await {
+            eprintln!("Failed to update job {}: {}", job_id, e);
+        }
+    }
+    result
+}
+
+/// The full backup as written by `export_all_data`, shared with escrow backups.
+async fn build_full_export(
+    state: &AppState,
+    days_back: Option<i32>,
+    token: &operations::CancellationToken,
+) -> Result<serde_json::Value, String> {
+    // Large exports read through the read-only pool instead of holding the database lock
+    let db = {
+        let db = state.db.lock().await;
+        db.ensure_not_frozen().map_err(|e| e.to_string())?;
+        db.reader()
+    };
+    
+    // Get all students, classes, and observations
+    let students = db.get_students().await.map_err(|e| e.to_string())?;
+    let classes = db.get_classes().await.map_err(|e| e.to_string())?;
+    token.check().map_err(|e| e.to_string())?;
+    
+    // Filter observations by date if specified
+    let observations = if let Some(days) = days_back {
+        if days > 0 {
+            // Get observations from specific time range
+            let cutoff_date = chrono::Utc::now() - chrono::Duration::days(days as i64);
+            db.get_observations_since(cutoff_date).await.map_err(|e| e.to_string())?
+        } else {
+            // days_back <= 0 means all data
+            db.search_observations(None, None, None).await.map_err(|e| e.to_string())?
+        }
+    } else {
+        // No filter - get all observations
+        db.search_observations(None, None, None).await.map_err(|e| e.to_string())?
+    };
+
+    token.check().map_err(|e| e.to_string())?;
+
+    let dictionary = db.get_dictionary_words().await.map_err(|e| e.to_string())?;
+
+    // Get device config for metadata
+    let device_config = state.crypto.get_device_config().map_err(|e| e.to_string())?;
+    
+    let data = serde_json::json!({
+        "students": students,
+        "classes": classes,
+        "observations": observations,
+        "dictionary": dictionary
+    });
+
+    // Create comprehensive export data
+    let export_data = serde_json::json!({
+        "format": "full_export",
+        "version": "1.0",
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "export_scope": {
+            "days_back": days_back,
+            "total_students": students.len(),
+            "total_classes": classes.len(), 
+            "total_observations": observations.len()
+        },
+        "source_device": {
+            "device_type": device_config.get("device_type").unwrap_or(&"unknown".to_string()),
+            "device_name": device_config.get("device_name")
+        },
+        "manifest": manifest::ExportManifest::describe(&data),
+        "data": data
+    });
+
+    Ok(export_data)
+}
+
+#[tauri::command]
+async fn export_all_data(
+    state: tauri::State<'_, AppState>,
+    days_back: Option<i32>,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let operation = state.operations.register(operation_id);
+    let params = serde_json::json!({ "days_back": days_back });
+    run_job(&state, "export_all_data", params, Some(&operation), async {
+        let export_data = build_full_export(&state, days_back, &operation.token).await?;
+
+        // Log the export
+        let scope_description = match days_back {
+            Some(days) if days > 0 => format!("last {} days", days),
+            _ => "all data".to_string()
+        };
+    
+        state
+            .audit
+            .log_action("export", "all_data", 0, 1, Some(&scope_description))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(export_data.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_companion_api_status(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<companion_api::CompanionApiStatus, String> {
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let db = state.db.lock().await;
+    let enabled = companion_api::is_enabled(&db).await.map_err(|e| e.to_string())?;
+    Ok(companion_api::status(&app_data_dir, enabled))
+}
+
+/// Enabling returns a fresh token for the companion tool; it is only shown once.
+#[tauri::command]
+async fn set_companion_api_enabled(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    enabled: bool,
+    admin_id: i64,
+    user_id: Option<i64>, // The teacher the companion tool writes for, the admin when not given
+) -> Result<Option<String>, String> {
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let db = state.db.lock().await;
+    db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    companion_api::set_enabled(&db, enabled, user_id.unwrap_or(admin_id))
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    drop(db);
+
+    let token = if enabled {
+        let token = state.crypto.rotate_api_token().map_err(|e| e.to_string())?;
+        companion_api::start(state.inner().clone(), app_data_dir);
+        Some(token)
+    } else {
+        None
+    };
+
+    state
+        .audit
+        .log_action(
+            "set_companion_api",
+            "app",
+            0,
+            admin_id,
+            Some(if enabled { "enabled" } else { "disabled" }),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(token)
+}
+
+#[tauri::command]
+async fn get_xapi_export_enabled(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    let db = state.db.lock().await;
+    xapi::is_enabled(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_xapi_export_enabled(
+    state: tauri::State<'_, AppState>,
+    enabled: bool,
+    admin_id: i64,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    xapi::set_enabled(&db, enabled, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "set_xapi_export",
+            "app",
+            0,
+            admin_id,
+            Some(if enabled { "enabled" } else { "disabled" }),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Writes pseudonymized xAPI-style statements for a learning analytics pilot.
+#[tauri::command]
+async fn export_xapi_statements(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    file_path: String,
+    class_id: Option<i64>,
+    days_back: Option<i32>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<usize, String> {
+    // Held until the location is recorded, so a freeze cannot begin in between
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let since = days_back
+        .filter(|days| *days > 0)
+        .map(|days| chrono::Utc::now() - chrono::Duration::days(days as i64));
+    let statements = xapi::build_statements(&db, &state.crypto, class_id, since)
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "xapi", class: None, extension: "json" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let json = serde_json::to_vec_pretty(&statements).map_err(|e| e.to_string())?;
+    let json = export_protection::protect(&db, &state.crypto, json, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    export_target::write_synced(&file_path, &json).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "export",
+            "xapi_statements",
+            class_id.unwrap_or(0),
+            1,
+            Some(&format!("{} statements to {}", statements.len(), file_path)),
+            &file_path,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let hook = export_hook::run_for_path(
+        &app,
+        db,
+        &state.audit,
+        &file_path,
+        "xapi_statements",
+        class_id.unwrap_or(0),
+        1,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    if let Some(hook) = hook.filter(|hook| !hook.success) {
+        return Err(format!("Statements written to {}{}", file_path, export_hook::outcome_note(&hook)));
+    }
+    Ok(statements.len())
+}
+
+#[tauri::command]
+async fn import_full_backup(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let operation = state.operations.register(operation_id);
+    let params = serde_json::json!({ "file_path": file_path });
+    run_job(&state, "import_full_backup", params, Some(&operation), async {
+        // Backups can be large, so the file is parsed while it is read
+        let backup_file = std::fs::File::open(&file_path).map_err(|e| e.to_string())?;
+
+        let db = state.db.lock().await;
+        let import_result = db
+            .import_full_backup_from_reader(std::io::BufReader::new(backup_file), &operation.token)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        transfer_locations::record(&db, "import", &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Log the import with file path
+        state
+            .audit
+            .log_transfer("import", "full_backup", 0, 1, Some(&file_path), &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!(
+            "Imported full backup from {}: {}",
+            file_path, import_result
+        ))
+    })
+    .await
+}
+
+/// The private key is shown once; the school keeps it, the app does not.
+#[tauri::command]
+async fn generate_school_escrow_key(
+    state: tauri::State<'_, AppState>,
+    admin_id: i64,
+) -> Result<escrow::SchoolKeyPair, String> {
+    let db = state.db.lock().await;
+    db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+    let key_pair = escrow::generate_school_key_pair().map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "generate_escrow_key",
+            "app",
+            0,
+            admin_id,
+            Some(&format!("school key {}", key_pair.fingerprint)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(key_pair)
+}
+
+#[tauri::command]
+async fn create_escrow_backup(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    file_path: String,
+    school_public_key: String,
+    operation_id: Option<String>,
+) -> Result<export_target::ExportResult, String> {
+    let operation = state.operations.register(operation_id);
+    let params = serde_json::json!({ "file_path": file_path, "school_public_key": school_public_key });
+    run_job(&state, "create_escrow_backup", params, Some(&operation), async {
+        let backup = build_full_export(&state, None, &operation.token).await?;
+        let (package, info) = escrow::seal_backup(&state.crypto, backup.to_string().as_bytes(), &school_public_key)
+            .map_err(|e| e.to_string())?;
+
+        let db = state.db.lock().await;
+        // A freeze may have begun while the backup was sealed without the lock
+        db.ensure_not_frozen().map_err(|e| e.to_string())?;
+        let file_path = export_naming::resolve_path(
+            &db,
+            &state.crypto,
+            &file_path,
+            &export_naming::ExportName { export_type: "escrow", class: None, extension: "json" },
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        let removable_media = export_target::write_synced(&file_path, &package).map_err(|e| e.to_string())?;
+
+        transfer_locations::record(&db, "export", &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        state
+            .audit
+            .log_transfer(
+                "export",
+                "escrow_backup",
+                0,
+                1,
+                Some(&format!("{} for school key {}", file_path, info.school_key_fingerprint)),
+                &file_path,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut result = export_target::ExportResult {
+            message: format!(
+                "Escrow backup written to {} for school key {}",
+                file_path, info.school_key_fingerprint
+            ),
+            file_path,
+            removable_media,
+            post_export: None,
+        };
+        export_hook::run(&app, db, &state.audit, &mut result, "escrow_backup", 0, 1)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(result)
+    })
+    .await
+}
+
+/// Recovery by the school: needs its private key and an admin of this device.
+#[tauri::command]
+async fn import_escrow_backup(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    school_private_key: String,
+    admin_id: i64,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let operation = state.operations.register(operation_id);
+    // The school's private key is never stored; a retry asks for it again
+    let params = serde_json::json!({ "file_path": file_path, "admin_id": admin_id });
+    run_job(&state, "import_escrow_backup", params, Some(&operation), async {
+        let package = std::fs::read(&file_path).map_err(|e| format!("Failed to read escrow backup: {}", e))?;
+
+        let db = state.db.lock().await;
+        db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+        let (backup, info) = match escrow::open_backup(&package, &school_private_key) {
+            Ok(opened) => opened,
+            Err(e) => {
+                // Failed attempts are documented as well
+                state
+                    .audit
+                    .log_action("import_failed", "escrow_backup", 0, admin_id, Some(&format!("{}: {}", file_path, e)))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                return Err(e.to_string());
+            }
+        };
+        let import_result = db
+            .import_full_backup_from_reader(std::io::Cursor::new(backup), &operation.token)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        transfer_locations::record(&db, "import", &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        state
+            .audit
+            .log_transfer(
+                "import",
+                "escrow_backup",
+                0,
+                admin_id,
+                Some(&format!(
+                    "{}: made on device {} at {}, school key {}",
+                    file_path,
+                    info.source_device_id,
+                    info.created_at.to_rfc3339(),
+                    info.school_key_fingerprint
+                )),
+                &file_path,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!("Recovered escrow backup from {}: {}", file_path, import_result))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn preview_backup_restore(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    selection: database::RestoreSelection,
+    operation_id: Option<String>,
+) -> Result<database::RestoreReport, String> {
+    let operation = state.operations.register(operation_id);
+    let backup_data = std::fs::read(&file_path).map_err(|e| e.to_string())?;
+
+    let db = state.db.lock().await;
+    db.restore_from_backup(&backup_data, &selection, false, &operation.token)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn restore_from_backup(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+    selection: database::RestoreSelection,
+    justification: Option<String>,
+    operation_id: Option<String>,
+) -> Result<database::RestoreReport, String> {
+    let params = serde_json::json!({
+        "file_path": file_path,
+        "selection": selection,
+        "justification": justification,
+    });
+
+    // Overwriting local records replaces what the teacher entered since the backup
+    let justification = if selection.overwrite_conflicts {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!(" - conflicts overwritten: {}", justification)
+    } else {
+        String::new()
+    };
+    let operation = state.operations.register(operation_id);
+    run_job(&state, "restore_from_backup", params, Some(&operation), async {
+        let backup_data = std::fs::read(&file_path).map_err(|e| e.to_string())?;
+
+        let db = state.db.lock().await;
+        let report = db
+            .restore_from_backup(&backup_data, &selection, true, &operation.token)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        transfer_locations::record(&db, "import", &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        state
+            .audit
+            .log_transfer(
+                "restore",
+                "full_backup",
+                0,
+                1,
+                Some(&format!("{} - {}{}", file_path, report, justification)),
+                &file_path,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(report)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_jobs(state: tauri::State<'_, AppState>, limit: Option<u32>) -> Result<Vec<Job>, String> {
+    let db = state.db.lock().await;
+    db.get_jobs(limit.unwrap_or(50)).await.map_err(|e| e.to_string())
+}
+
+fn job_param<T: serde::de::DeserializeOwned>(params: &serde_json::Value, name: &str) -> Result<T, String> {
+    serde_json::from_value(params.get(name).cloned().unwrap_or_default())
+        .map_err(|e| format!("Invalid job parameter {}: {}", name, e))
+}
+
+/// Runs a failed, cancelled or interrupted job again with its recorded
+/// parameters, as a new job. Passphrases and keys are not recorded, so jobs
+/// that needed one take it as `secret`.
+#[tauri::command]
+async fn retry_job(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    job_id: i64,
+    secret: Option<String>,
+    operation_id: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let job = {
+        let db = state.db.lock().await;
+        db.get_job(job_id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Job {} not found", job_id))?
+    };
+    if !["failed", "cancelled", "interrupted"].contains(&job.status.as_str()) {
+        return Err(format!("Job {} is {} and cannot be retried", job_id, job.status));
+    }
+    let params: serde_json::Value = serde_json::from_str(&job.params).map_err(|e| e.to_string())?;
+    let needs_secret = |needed: bool| match (&secret, needed) {
+        (None, true) => Err(format!("Job {} needs the passphrase or key again", job_id)),
+        _ => Ok(secret.clone()),
+    };
+
+    let result = match job.job_type.as_str() {
+        "export_changeset" => {
+            let protection = export_protection::ExportProtection {
+                passphrase: needs_secret(job_param(&params, "passphrase_protected")?)?,
+                recipient_key: job_param(&params, "recipient_key")?,
+            };
+            serde_json::to_value(
+                export_changeset_to_file(
+                    state,
+                    job_param(&params, "file_path")?,
+                    job_param(&params, "days_back")?,
+                    job_param(&params, "compress")?,
+                    job_param(&params, "attachment_policy")?,
+                    Some(protection),
+                )
+                .await?,
+            )
+        }
+        "import_changeset" => serde_json::to_value(
+            import_changeset_from_file(
+                state,
+                job_param(&params, "file_path")?,
+                job_param(&params, "confirm_hard_deletions")?,
+                job_param(&params, "justification")?,
+                operation_id,
+                needs_secret(job_param(&params, "passphrase_protected")?)?,
+                job_param(&params, "quarantine")?,
+            )
+            .await?,
+        ),
+        "promote_import" => serde_json::to_value(
+            promote_import(
+                state,
+                job_param(&params, "import_id")?,
+                job_param(&params, "confirm_hard_deletions")?,
+                job_param(&params, "justification")?,
+                operation_id,
+            )
+            .await?,
+        ),
+        "import_scanned_notes" => serde_json::to_value(
+            import_scanned_notes(
+                app,
+                state,
+                job_param(&params, "file_paths")?,
+                job_param(&params, "class_id")?,
+                operation_id,
+            )
+            .await?,
+        ),
+        "export_all_data" => {
+            serde_json::to_value(export_all_data(state, job_param(&params, "days_back")?, operation_id).await?)
+        }
+        "import_full_backup" => {
+            serde_json::to_value(import_full_backup(state, job_param(&params, "file_path")?, operation_id).await?)
+        }
+        "create_escrow_backup" => serde_json::to_value(
+            create_escrow_backup(
+                state,
+                job_param(&params, "file_path")?,
+                job_param(&params, "school_public_key")?,
+                operation_id,
+            )
+            .await?,
+        ),
+        "import_escrow_backup" => serde_json::to_value(
+            import_escrow_backup(
+                state,
+                job_param(&params, "file_path")?,
+                needs_secret(true)?.unwrap_or_default(),
+                job_param(&params, "admin_id")?,
+                operation_id,
+            )
+            .await?,
+        ),
+        "restore_from_backup" => serde_json::to_value(
+            restore_from_backup(
+                state,
+                job_param(&params, "file_path")?,
+                job_param(&params, "selection")?,
+                job_param(&params, "justification")?,
+                operation_id,
+            )
+            .await?,
+        ),
+        "migrate_attachment_storage" => serde_json::to_value(
+            migrate_attachment_storage(state, job_param(&params, "mode")?, operation_id).await?,
+        ),
+        other => return Err(format!("Jobs of type {} cannot be retried", other)),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/// Due dates of a student's records under the retention policy.
+#[tauri::command]
+async fn get_retention_status(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+) -> Result<gdpr::RetentionStatus, String> {
+    let db = state.db.lock().await;
+    state
+        .gdpr
+        .get_retention_status(&db, &state.audit, student_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn generate_data_flow_report(
+    state: tauri::State<'_, AppState>,
+) -> Result<gdpr::DataFlowReport, String> {
+    let db = state.db.lock().await;
+    state
+        .gdpr
+        .generate_data_flow_report(&db, &state.audit)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_redaction_settings(
+    state: tauri::State<'_, AppState>,
+) -> Result<redaction::RedactionSettings, String> {
+    let db = state.db.lock().await;
+    redaction::load_settings(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_redaction_settings(
+    state: tauri::State<'_, AppState>,
+    settings: redaction::RedactionSettings,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    redaction::save_settings(&db, &settings).await.map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn preview_redaction(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    viewer_id: Option<i64>,
+) -> Result<redaction::RedactionPreview, String> {
+    let db = state.db.lock().await;
+    redaction::preview_redaction(&db, observation_id, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Student names and custom terms; names cannot be removed here, they
+/// follow the student records.
+#[tauri::command]
+async fn get_anonymization_dictionary(
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<redaction::DictionaryEntry>, String> {
+    let db = state.db.lock().await;
+    redaction::anonymization_dictionary(&db).await.map_err(|e| e.to_string())
+}
+
+/// The audit entry leaves out the term itself, nicknames identify a student.
+fn anonymization_term_details(term: &AnonymizationTerm) -> String {
+    match term.student_id {
+        Some(student_id) => format!("term for student {}", student_id),
+        None => "general term".to_string(),
+    }
+}
+
+#[tauri::command]
+async fn create_anonymization_term(
+    state: tauri::State<'_, AppState>,
+    term: database::AnonymizationTermInput,
+) -> Result<AnonymizationTerm, String> {
+    let db = state.db.lock().await;
+    let stored = db.create_anonymization_term(&term).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "anonymization_term", stored.id, 1, Some(&anonymization_term_details(&stored)))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(stored)
+}
+
+#[tauri::command]
+async fn update_anonymization_term(
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    term: database::AnonymizationTermInput,
+) -> Result<AnonymizationTerm, String> {
+    let db = state.db.lock().await;
+    let stored = db.update_anonymization_term(id, &term).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "anonymization_term", id, 1, Some(&anonymization_term_details(&stored)))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(stored)
+}
+
+#[tauri::command]
+async fn delete_anonymization_term(state: tauri::State<'_, AppState>, id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_anonymization_term(id).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("delete", "anonymization_term", id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Runs the analyzer first, so notes written since the last background pass are included.
+#[tauri::command]
+async fn get_cross_mention_warnings(
+    state: tauri::State<'_, AppState>,
+    class_id: Option<i64>,
+    viewer_id: Option<i64>,
+) -> Result<Vec<database::MentionWarning>, String> {
+    let db = state.db.lock().await;
+    mentions::analyze_observations(&db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut warnings = db
+        .get_mention_warnings(class_id, viewer_id.unwrap_or(1))
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&db).await.map_err(|e| e.to_string())? {
+        pseudonymizer.mention_warnings(&mut warnings);
+    }
+    Ok(warnings)
+}
+
+#[tauri::command]
+async fn get_privacy_display_mode(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let db = state.db.lock().await;
+    privacy::get_display_mode(&db).await.map_err(|e| e.to_string())
+}
+
+/// "pseudonymized" shortens names in all read commands, e.g. for projecting
+/// the app in class; the stored names are not changed.
+#[tauri::command]
+async fn set_privacy_display_mode(state: tauri::State<'_, AppState>, mode: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    privacy::set_display_mode(&db, &mode)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("set_display_mode", "app", 0, 1, Some(&mode))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Applies to every open window; not supported on Linux, where it is a no-op.
+fn apply_content_protection(app: &tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    for window in app.webview_windows().values() {
+        window
+            .set_content_protected(enabled)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_content_protection(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    let db = state.db.lock().await;
+    privacy::get_content_protection(&db).await.map_err(|e| e.to_string())
+}
+
+/// Keeps the app out of screenshots and screen sharing, e.g. during video calls.
+#[tauri::command]
+async fn set_content_protection(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    enabled: bool,
+) -> Result<(), String> {
+    apply_content_protection(&app, enabled)?;
+
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    privacy::set_content_protection(&db, enabled)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "set_content_protection",
+            "app",
+            0,
+            1,
+            Some(if enabled { "enabled" } else { "disabled" }),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_app_mode(state: tauri::State<'_, AppState>) -> Result<app_mode::AppMode, String> {
+    let db = state.db.lock().await;
+    app_mode::load(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_feature_flags(
+    flags: tauri::State<'_, feature_flags::FeatureFlags>,
+) -> Result<Vec<feature_flags::FlagStatus>, String> {
+    Ok(flags.list())
+}
+
+/// Switches an experimental subsystem for this profile; its commands are
+/// rejected from the next call on.
+#[tauri::command]
+async fn set_feature_flag(
+    state: tauri::State<'_, AppState>,
+    flags: tauri::State<'_, feature_flags::FeatureFlags>,
+    name: String,
+    enabled: bool,
+    admin_id: i64,
+) -> Result<feature_flags::FlagStatus, String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    let status = flags
+        .set(&db, &name, enabled, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(status)
+}
+
+/// Returns the new recovery code; it is shown once, to be printed.
+#[tauri::command]
+async fn set_app_password(
+    state: tauri::State<'_, AppState>,
+    new_password: String,
+    current_password: Option<String>,
+) -> Result<String, String> {
+    let recovery_code = match state.crypto.set_app_password(&new_password, current_password.as_deref()) {
+        Ok(code) => code,
+        Err(e) => {
+            state
+                .audit
+                .log_action("set_password_failed", "app", 0, 1, Some(&e.to_string()))
+                .await
+                .map_err(|e| e.to_string())?;
+            return Err(e.to_string());
+        }
+    };
+
+    state
+        .audit
+        .log_action("set_password", "app", 0, 1, Some("new recovery code issued"))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(recovery_code)
+}
+
+/// Wrong passwords lock unlocking for increasing durations; every attempt
+/// is audited, the entered password never.
+#[tauri::command]
+async fn unlock_app(state: tauri::State<'_, AppState>, password: String) -> Result<(), String> {
+    match state.crypto.unlock_with_password(&password) {
+        Ok(()) => state
+            .audit
+            .log_action("unlock", "app", 0, 1, None)
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => {
+            state
+                .audit
+                .log_action("unlock_failed", "app", 0, 1, Some(&e.to_string()))
+                .await
+                .map_err(|e| e.to_string())?;
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_unlock_status(state: tauri::State<'_, AppState>) -> Result<crypto::UnlockStatus, String> {
+    state.crypto.unlock_status().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn has_recovery_code(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    state.crypto.has_recovery_code().map_err(|e| e.to_string())
+}
+
+/// Sets a new app password with the printed recovery code and returns the
+/// code replacing it. Every attempt is audited.
+#[tauri::command]
+async fn recover_with_code(
+    state: tauri::State<'_, AppState>,
+    recovery_code: String,
+    new_password: String,
+) -> Result<String, String> {
+    match state.crypto.recover_with_code(&recovery_code, &new_password) {
+        Ok(new_code) => {
+            state
+                .audit
+                .log_action("recover_password", "app", 0, 1, Some("app password reset with recovery code"))
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(new_code)
+        }
+        Err(e) => {
+            state
+                .audit
+                .log_action("recover_password_failed", "app", 0, 1, Some(&e.to_string()))
+                .await
+                .map_err(|e| e.to_string())?;
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Emergency stop for a suspected data incident: read-only, no sync.
+#[tauri::command]
+async fn freeze_all_processing(
+    state: tauri::State<'_, AppState>,
+    reason: String,
+    admin_id: i64,
+) -> Result<app_mode::AppMode, String> {
+    let mut db = state.db.lock().await;
+    let mode = app_mode::freeze(&mut db, &state.crypto, &reason, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("freeze", "app", 0, admin_id, Some(reason.trim()))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(mode)
+}
+
+#[tauri::command]
+async fn unfreeze_processing(
+    state: tauri::State<'_, AppState>,
+    password: String,
+    user_id: i64,
+) -> Result<app_mode::AppMode, String> {
+    let mut db = state.db.lock().await;
+    match app_mode::unfreeze(&mut db, &state.crypto, &password, user_id).await {
+        Ok(mode) => {
+            state
+                .audit
+                .log_action("unfreeze", "app", 0, user_id, None)
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(mode)
+        }
+        Err(e) => {
+            // Failed attempts are part of the incident record too
+            state
+                .audit
+                .log_action("unfreeze_failed", "app", 0, user_id, Some(&e.to_string()))
+                .await
+                .map_err(|e| e.to_string())?;
+            Err(e.to_string())
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_dpia_checklist(state: tauri::State<'_, AppState>) -> Result<dpia::DpiaChecklist, String> {
+    let db = state.db.lock().await;
+    dpia::get_checklist(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn answer_dpia_item(
+    state: tauri::State<'_, AppState>,
+    item_id: String,
+    answer: String,
+    notes: Option<String>,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    dpia::answer_item(&db, &item_id, &answer, notes)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("answer", "dpia_item", 0, 1, Some(&format!("{}: {}", item_id, answer)))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Writes the assessment as printable HTML for the school's documentation.
+#[tauri::command]
+async fn export_dpia_assessment(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    file_path: String,
+    school_name: Option<String>,
+) -> Result<export_target::ExportResult, String> {
+    let db = state.db.lock().await;
+    let checklist = dpia::get_checklist(&db).await.map_err(|e| e.to_string())?;
+    let html = dpia::render_assessment_html(&checklist, school_name.as_deref());
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "dsfa", class: None, extension: "html" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, html.as_bytes()).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("export", "dpia_assessment", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut result = export_target::ExportResult {
+        message: format!("Assessment exported to {}", file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "dpia_assessment", 0, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn record_incident(
+    state: tauri::State<'_, AppState>,
+    incident: audit::IncidentInput,
+    user_id: i64,
+) -> Result<audit::Incident, String> {
+    let incident = state
+        .audit
+        .record_incident(&incident, user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("record_incident", "incident", incident.id, user_id, Some(&incident.title))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(incident)
+}
+
+#[tauri::command]
+async fn update_incident(
+    state: tauri::State<'_, AppState>,
+    incident_id: i64,
+    incident: audit::IncidentInput,
+    user_id: i64,
+) -> Result<audit::Incident, String> {
+    let incident = state
+        .audit
+        .update_incident(incident_id, &incident)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "update_incident",
+            "incident",
+            incident_id,
+            user_id,
+            Some(&format!("notification: {}", incident.notification_status)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(incident)
+}
+
+#[tauri::command]
+async fn get_incidents(state: tauri::State<'_, AppState>) -> Result<Vec<audit::Incident>, String> {
+    state.audit.get_incidents().await.map_err(|e| e.to_string())
+}
+
+/// Writes the incident documentation as HTML; affected students are resolved
+/// by reference from the live database. Works while writes are frozen.
+#[tauri::command]
+async fn export_incident_report(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    incident_id: i64,
+    file_path: String,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<export_target::ExportResult, String> {
+    let incident = state
+        .audit
+        .get_incident(incident_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Incident not found".to_string())?;
+
+    let db = state.db.lock().await;
+    let students = incidents::affected_students(&db, &incident)
+        .await
+        .map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "incident", class: None, extension: "html" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let html = incidents::render_incident_report(&incident, &students).into_bytes();
+    let html = export_protection::protect(&db, &state.crypto, html, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    drop(db);
+
+    let removable_media = export_target::write_synced(&file_path, &html).map_err(|e| e.to_string())?;
+
+    // Incidents are documented during a freeze, too: the audit log takes the
+    // export, only the list of recent locations in the frozen database is skipped
+    let db = state.db.lock().await;
+    if db.ensure_not_frozen().is_ok() {
+        transfer_locations::record(&db, "export", &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    drop(db);
+
+    state
+        .audit
+        .log_transfer("export", "incident_report", incident_id, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut result = export_target::ExportResult {
+        message: format!("Incident report exported to {}", file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, state.db.lock().await, &state.audit, &mut result, "incident_report", incident_id, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// Converts an export file between the full export and changeset formats.
+/// Works on the files alone; the live database is not touched.
+#[tauri::command]
+async fn convert_export_file(
+    state: tauri::State<'_, AppState>,
+    input: String,
+    output: String,
+    target_format: String,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<String, String> {
+    let target = convert::ExportFormat::parse(&target_format).map_err(|e| e.to_string())?;
+    let input_data = std::fs::read(&input).map_err(|e| e.to_string())?;
+
+    let conversion = convert::convert_export(&input_data, target).map_err(|e| e.to_string())?;
+
+    // The converted file is a new export and follows the rules of every other one
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let data = export_protection::protect(&db, &state.crypto, conversion.output, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let output = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &output,
+        &export_naming::ExportName { export_type: target.name(), class: None, extension: target.extension() },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    export_target::write_synced(&output, &data).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &output)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer(
+            "convert",
+            "export_file",
+            0,
+            1,
+            Some(&format!("{} -> {} ({})", input, output, target.name())),
+            &output,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(conversion.summary)
+}
+
+/// Asks a running import, export or migration to stop. Returns false if no
+/// operation with this id is running (anymore).
+#[tauri::command]
+async fn cancel_operation(
+    state: tauri::State<'_, AppState>,
+    operation_id: String,
+) -> Result<bool, String> {
+    Ok(state.operations.cancel(&operation_id))
+}
+
+#[tauri::command]
+async fn get_attachment_storage_mode(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let db = state.db.lock().await;
+    db.get_attachment_storage_mode().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn migrate_attachment_storage(
+    state: tauri::State<'_, AppState>,
+    mode: String,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let operation = state.operations.register(operation_id);
+    let params = serde_json::json!({ "mode": mode });
+    run_job(&state, "migrate_attachment_storage", params, Some(&operation), async {
+        let db = state.db.lock().await;
+        let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+        let moved = db
+            .migrate_attachment_storage(&mode, &operation.token)
+            .await
+            .map_err(|e| e.to_string())?;
+        settings_change
+            .finish(&db, &state.audit, 1)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        state
+            .audit
+            .log_action(
+                "migrate",
+                "attachment_storage",
+                0,
+                1,
+                Some(&format!("{} ({} attachments moved)", mode, moved)),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!("Moved {} attachments to {} storage", moved, mode))
+    })
+    .await
+}
+
+/// Attaches a file from disk, e.g. a photo of student work. Images lose
+/// their metadata on the way in, see `media::prepare_attachment_file`.
+#[tauri::command]
+async fn add_attachment(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    file_path: String,
+    user_id: Option<i64>,
+) -> Result<Attachment, String> {
+    let user_id = user_id.unwrap_or(1);
+    let size = std::fs::metadata(&file_path)
+        .map_err(|e| format!("Failed to read {}: {}", file_path, e))?
+        .len();
+    if size > media::MAX_ATTACHMENT_FILE_BYTES {
+        return Err(format!(
+            "{} is too large to attach ({} MB at most)",
+            file_path,
+            media::MAX_ATTACHMENT_FILE_BYTES / (1024 * 1024)
+        ));
+    }
+    let data = std::fs::read(&file_path).map_err(|e| format!("Failed to read {}: {}", file_path, e))?;
+    let (data, content_type) = media::prepare_attachment_file(&data).map_err(|e| e.to_string())?;
+
+    let db = state.db.lock().await;
+    db.get_observation(observation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .filter(|o| o.is_visible_to(user_id))
+        .ok_or_else(|| "Observation not found".to_string())?;
+    db.ensure_observation_editable(observation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let filename = std::path::Path::new(&file_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "attachment".to_string());
+    let attachment = db
+        .store_attachment(observation_id, &filename, content_type, &data)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "attachment", attachment.id, user_id, Some(&format!("file, {}", content_type)))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(attachment)
+}
+
+#[tauri::command]
+async fn get_attachments_for_observation(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    viewer_id: Option<i64>,
+) -> Result<Vec<Attachment>, String> {
+    let db = state.db.lock().await;
+    let visible = db
+        .get_observation(observation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .is_some_and(|o| o.is_visible_to(viewer_id.unwrap_or(1)));
+    if !visible {
+        return Err("Observation not found".to_string());
+    }
+    db.get_attachments(observation_id).await.map_err(|e| e.to_string())
+}
+
+/// Writes the payload to `file_path` after checking it against its hash;
+/// a damaged or tampered payload is not handed out.
+#[tauri::command]
+async fn download_attachment(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    attachment_id: i64,
+    file_path: String,
+    protection: Option<export_protection::ExportProtection>,
+    user_id: Option<i64>,
+) -> Result<export_target::ExportResult, String> {
+    let user_id = user_id.unwrap_or(1);
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let attachment = db
+        .get_attachment(attachment_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Attachment not found".to_string())?;
+    db.get_observation(attachment.observation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .filter(|o| o.is_visible_to(user_id))
+        .ok_or_else(|| "Attachment not found".to_string())?;
+
+    let data = db.get_attachment_data(attachment_id).await.map_err(|e| e.to_string())?;
+    let data = export_protection::protect(&db, &state.crypto, data, protection.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+    let extension = std::path::Path::new(&attachment.filename)
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "bin".to_string());
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "attachment", class: None, extension: &extension },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &data).map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("export", "attachment", attachment_id, user_id, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut result = export_target::ExportResult {
+        message: format!("Attachment saved to {} ({} bytes)", file_path, data.len()),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "attachment", attachment_id, user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn delete_attachment(
+    state: tauri::State<'_, AppState>,
+    attachment_id: i64,
+    user_id: Option<i64>,
+) -> Result<(), String> {
+    let user_id = user_id.unwrap_or(1);
+    let db = state.db.lock().await;
+    let attachment = db
+        .get_attachment(attachment_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Attachment not found".to_string())?;
+    db.get_observation(attachment.observation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .filter(|o| o.is_visible_to(user_id))
+        .ok_or_else(|| "Attachment not found".to_string())?;
+    db.ensure_observation_editable(attachment.observation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    db.delete_attachment(attachment_id).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "delete",
+            "attachment",
+            attachment_id,
+            user_id,
+            Some(&format!("observation {}", attachment.observation_id)),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_attachment_from_clipboard(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+) -> Result<Attachment, String> {
+    use tauri_plugin_clipboard_manager::ClipboardExt;
+
+    let image = app
+        .clipboard()
+        .read_image()
+        .map_err(|e| format!("Clipboard does not contain an image: {}", e))?;
+    let (data, content_type) = media::sanitize_rgba(image.rgba(), image.width(), image.height())
+        .map_err(|e| e.to_string())?;
+
+    let db = state.db.lock().await;
+    db.get_observation(observation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Observation not found".to_string())?;
+    db.ensure_observation_editable(observation_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let filename = format!("clipboard-{}.png", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    let attachment = db
+        .store_attachment(observation_id, &filename, content_type, &data)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "attachment", attachment.id, 1, Some("clipboard"))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(attachment)
+}
+
+#[tauri::command]
+async fn add_handwritten_note(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    strokes: String,
+) -> Result<Attachment, String> {
+    // Reject malformed stroke data before it is stored and synced
+    media::parse_strokes(strokes.as_bytes()).map_err(|e| e.to_string())?;
+
+    let db = state.db.lock().await;
+    db.get_observation(observation_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Observation not found".to_string())?;
+
+    let filename = format!("note-{}.strokes.json", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    let attachment = db
+        .store_attachment(
+            observation_id,
+            &filename,
+            media::STROKES_CONTENT_TYPE,
+            strokes.as_bytes(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "attachment", attachment.id, 1, Some("handwritten note"))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(attachment)
+}
+
+#[tauri::command]
+async fn render_handwritten_note(
+    state: tauri::State<'_, AppState>,
+    attachment_id: i64,
+    format: String,
+) -> Result<tauri::ipc::Response, String> {
+    let db = state.db.lock().await;
+    let attachment = db
+        .get_attachment(attachment_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Attachment not found".to_string())?;
+    if attachment.content_type != media::STROKES_CONTENT_TYPE {
+        return Err("Attachment is not a handwritten note".to_string());
+    }
+
+    let data = db
+        .get_attachment_data(attachment_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let rendered = match format.as_str() {
+        "svg" => media::render_strokes_svg(&data).map(String::into_bytes),
+        "png" => media::render_strokes_png(&data, media::MAX_ATTACHMENT_IMAGE_EDGE),
+        _ => return Err(format!("Unsupported format: {}", format)),
+    }
+    .map_err(|e| e.to_string())?;
+
+    Ok(tauri::ipc::Response::new(rendered))
+}
+
+/// Emits `storage-warning` and shows a system notification for every exceeded quota.
+fn notify_storage_warnings(app: &tauri::AppHandle, warnings: &[String]) {
+    use tauri_plugin_notification::NotificationExt;
+
+    for warning in warnings {
+        let _ = app.emit("storage-warning", warning);
+        let _ = app
+            .notification()
+            .builder()
+            .title("Speicherplatz")
+            .body(warning)
+            .show();
+    }
+}
+
+/// One pass of the scheduler: reminds of upcoming erasures and performs due ones.
+async fn run_scheduled_tasks(app: &tauri::AppHandle, state: &AppState) -> Result<(), String> {
+    use tauri_plugin_notification::NotificationExt;
+
+    let db = state.db.lock().await;
+    // Scheduled erasures wait until a freeze is lifted
+    if db.is_frozen() {
+        return Ok(());
+    }
+    let run = scheduler::run_due_tasks(&db, &state.gdpr, &state.audit, chrono::Utc::now())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for request in &run.announced {
+        let student = db.get_student(request.student_id).await.ok().flatten();
+        let name = student
+            .map(|s| format!("{} {}", s.first_name, s.last_name))
+            .unwrap_or_else(|| format!("Schüler {}", request.student_id));
+        let due = request.execute_at.map(|at| at.format("%d.%m.%Y %H:%M").to_string()).unwrap_or_default();
+
+        let _ = app.emit("erasure-due", request);
+        let _ = app
+            .notification()
+            .builder()
+            .title("Geplante Löschung")
+            .body(format!("{} wird am {} endgültig gelöscht.", name, due))
+            .show();
+    }
+
+    if !run.deactivated.is_empty() {
+        let _ = app.emit("students-left", &run.deactivated);
+    }
+    if !run.retention.deleted_observations.is_empty()
+        || run.retention.attachments_deleted > 0
+        || run.retention.observations_anonymized > 0
+    {
+        let _ = app.emit("retention-applied", &run.retention);
+    }
+
+    match mentions::analyze_observations(&db).await {
+        Ok(flagged) if flagged > 0 => {
+            let _ = app.emit("mention-warnings", flagged);
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Mention analysis failed: {}", e),
+    }
+    drop(db);
+
+    for result in &run.executed {
+        let _ = app.emit("erasure-executed", result);
+    }
+    for failure in &run.failed {
+        eprintln!("Scheduled erasure failed: {}", failure);
+    }
+
+    Ok(())
+}
+
+async fn check_storage(app: &tauri::AppHandle, state: &AppState) -> Result<storage::StorageUsage, String> {
+    let db = state.db.lock().await;
+    let usage = storage::collect_usage(&db, state.audit.db_path())
+        .await
+        .map_err(|e| e.to_string())?;
+    drop(db);
+
+    notify_storage_warnings(app, &usage.warnings);
+    Ok(usage)
+}
+
+/// Emits `health-warning` with the report and shows a system notification
+/// for warnings the previous pass did not raise yet.
+async fn check_health(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    previous: Option<&health::HealthReport>,
+) -> Result<health::HealthReport, String> {
+    use tauri_plugin_notification::NotificationExt;
+
+    let db_path = state.db.lock().await.db_path().to_path_buf();
+    let report = health::check(&db_path, &state.audit).await.map_err(|e| e.to_string())?;
+
+    let warnings = health::new_warnings(previous, &report);
+    if !warnings.is_empty() {
+        let _ = app.emit("health-warning", &report);
+    }
+    for warning in &warnings {
+        let _ = app
+            .notification()
+            .builder()
+            .title("Datenintegrität")
+            .body(warning)
+            .show();
+    }
+    Ok(report)
+}
+
+/// Runs the checks of the health monitor now, e.g. after a warning.
+#[tauri::command]
+async fn get_health_report(state: tauri::State<'_, AppState>) -> Result<health::HealthReport, String> {
+    let db_path = state.db.lock().await.db_path().to_path_buf();
+    health::check(&db_path, &state.audit).await.map_err(|e| e.to_string())
+}
+
+/// Re-applies the startup hardening and reports file permissions and risky locations.
+#[tauri::command]
+async fn get_security_status(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    integrity: tauri::State<'_, integrity::IntegrityCheck>,
+) -> Result<security::SecurityStatus, String> {
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let data_paths = data_root::resolve(&app_data_dir, &data_root::load_config(&app_data_dir));
+    let secrets_path = crypto::secrets_file().map_err(|e| e.to_string())?;
+    let db = state.db.lock().await;
+    let mut status = security::check_and_harden(
+        &data_paths.root,
+        db.db_path(),
+        state.audit.db_path(),
+        &secrets_path,
+    );
+    if let Some(warning) = state.audit.clock_warning().await.map_err(|e| e.to_string())? {
+        status.warnings.push(warning);
+    }
+    status.warnings.extend(integrity.warning());
+    status.integrity = Some(integrity.inner().clone());
+    Ok(status)
+}
+
+#[tauri::command]
+async fn get_database_health(
+    state: tauri::State<'_, AppState>,
+) -> Result<database::DatabaseHealth, String> {
+    let db = state.db.lock().await;
+    db.get_database_health().await.map_err(|e| e.to_string())
+}
+
+/// Whether the app started in maintenance mode, and why.
+#[tauri::command]
+async fn get_startup_status(
+    status: tauri::State<'_, maintenance::StartupStatus>,
+) -> Result<maintenance::StartupStatus, String> {
+    Ok(status.inner().clone())
+}
+
+/// Registered instead of all other commands while the database cannot be
+/// opened; they work on the database file, without `AppState`.
+mod maintenance_commands {
+    use crate::maintenance::{self, StartupStatus};
+
+    #[tauri::command]
+    pub async fn get_database_health(
+        status: tauri::State<'_, StartupStatus>,
+    ) -> Result<maintenance::DatabaseFileHealth, String> {
+        maintenance::check_database_file(&status.database_path)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    #[tauri::command]
+    pub async fn run_self_test(
+        status: tauri::State<'_, StartupStatus>,
+    ) -> Result<Vec<maintenance::SelfTestCheck>, String> {
+        Ok(maintenance::run_self_test(&status.database_path).await)
+    }
+
+    /// Restarts the app on the snapshot, or on an empty database without one.
+    #[tauri::command]
+    pub async fn restore_backup(
+        app: tauri::AppHandle,
+        status: tauri::State<'_, StartupStatus>,
+        snapshot_id: Option<String>,
+        confirmed: bool,
+    ) -> Result<(), String> {
+        if !confirmed {
+            return Err("Replacing the database must be confirmed".to_string());
+        }
+        let set_aside = maintenance::restore_backup(&status.database_path, snapshot_id.as_deref())
+            .await
+            .map_err(|e| e.to_string())?;
+        eprintln!("Maintenance: damaged database moved to {}", set_aside.display());
+        app.restart()
+    }
+}
+
+#[tauri::command]
+async fn get_storage_usage(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<storage::StorageUsage, String> {
+    check_storage(&app, &state).await
+}
+
+#[tauri::command]
+async fn set_storage_quotas(
+    state: tauri::State<'_, AppState>,
+    quotas: storage::StorageQuotas,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    storage::save_quotas(&db, &quotas).await.map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_custom_dictionary(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    let db = state.db.lock().await;
+    db.get_custom_dictionary().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn add_dictionary_word(state: tauri::State<'_, AppState>, word: String) -> Result<bool, String> {
+    let db = state.db.lock().await;
+    db.add_dictionary_word(&word).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn remove_dictionary_word(state: tauri::State<'_, AppState>, word: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.remove_dictionary_word(&word).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_transcription_config(
+    state: tauri::State<'_, AppState>,
+    whisper_binary: String,
+    whisper_model: String,
+    language: Option<String>,
+    admin_id: i64,
+) -> Result<(), String> {
+    for path in [&whisper_binary, &whisper_model] {
+        if !std::path::Path::new(path).is_file() {
+            return Err(format!("File not found: {}", path));
+        }
+    }
+
+    let db = state.db.lock().await;
+    db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    db.set_setting(transcription::WHISPER_BINARY_SETTING, &whisper_binary)
+        .await
+        .map_err(|e| e.to_string())?;
+    db.set_setting(transcription::WHISPER_MODEL_SETTING, &whisper_model)
+        .await
+        .map_err(|e| e.to_string())?;
+    db.set_setting(
+        transcription::WHISPER_LANGUAGE_SETTING,
+        language.as_deref().unwrap_or("de"),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn transcribe_attachment(
+    state: tauri::State<'_, AppState>,
+    attachment_id: i64,
+) -> Result<String, String> {
+    let params = serde_json::json!({ "attachment_id": attachment_id });
+    run_job(&state, "transcribe_attachment", params, None, async {
+        let (transcriber, audio, attachment) = {
+            let db = state.db.lock().await;
+            let attachment = db
+                .get_attachment(attachment_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| "Attachment not found".to_string())?;
+            if !transcription::is_audio(&attachment.content_type) {
+                return Err("Attachment is not an audio recording".to_string());
+            }
+
+            let transcriber = transcription::configured_transcriber(&db)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| "No speech-to-text engine configured".to_string())?;
+            let audio = db
+                .get_attachment_data(attachment_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            (transcriber, audio, attachment)
+        };
+
+        // Transcription can take minutes; the database lock is not held meanwhile
+        let extension = std::path::Path::new(&attachment.filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("wav")
+            .to_string();
+        let engine = transcriber.name().to_string();
+        let text = transcription::transcribe_audio(transcriber, audio, &extension)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        state
+            .audit
+            .log_action("transcribe", "attachment", attachment_id, 1, Some(&engine))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(text)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_attachment_thumbnail(
+    state: tauri::State<'_, AppState>,
+    attachment_id: i64,
+    size: u32,
+) -> Result<tauri::ipc::Response, String> {
+    let db = state.db.lock().await;
+    let thumbnail = db
+        .get_attachment_thumbnail(attachment_id, size)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Raw bytes instead of a JSON number array keep list views cheap
+    Ok(tauri::ipc::Response::new(thumbnail))
+}
+
+#[tauri::command]
+async fn create_snapshot(
+    state: tauri::State<'_, AppState>,
+    label: String,
+) -> Result<database::SnapshotInfo, String> {
+    let db = state.db.lock().await;
+    let snapshot = db.create_snapshot(&label).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "snapshot", 0, 1, Some(&snapshot.id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(snapshot)
+}
+
+#[tauri::command]
+async fn list_snapshots(state: tauri::State<'_, AppState>) -> Result<Vec<database::SnapshotInfo>, String> {
+    let db = state.db.lock().await;
+    db.list_snapshots().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn rollback_to_snapshot(
+    state: tauri::State<'_, AppState>,
+    flags: tauri::State<'_, feature_flags::FeatureFlags>,
+    snapshot_id: String,
+    confirm: bool,
+) -> Result<database::SnapshotInfo, String> {
+    let mut db = state.db.lock().await;
+    let pre_rollback = db
+        .rollback_to_snapshot(&snapshot_id, confirm)
+        .await
+        .map_err(|e| e.to_string())?;
+    // The snapshot has the flags of its time
+    flags.reload(&db).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "rollback",
+            "snapshot",
+            0,
+            1,
+            Some(&format!("{} (previous state saved as {})", snapshot_id, pre_rollback.id)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(pre_rollback)
+}
+
+#[tauri::command]
+async fn import_changeset_data(
+    state: tauri::State<'_, AppState>,
+    changeset_data: String,
+    confirm_hard_deletions: Option<bool>,
+    justification: Option<String>,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let confirm_hard_deletions = confirm_hard_deletions.unwrap_or(false);
+    let details = if confirm_hard_deletions {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!("direct - hard deletions confirmed: {}", justification)
+    } else {
+        "direct".to_string()
+    };
+    let operation = state.operations.register(operation_id);
+    // The data itself is not kept in the history
+    let params = serde_json::json!({
+        "source": "direct",
+        "confirm_hard_deletions": confirm_hard_deletions,
+        "justification": justification,
+    });
+    run_job(&state, "import_changeset_data", params, Some(&operation), async {
+        let db = state.db.lock().await;
+        let import_result = db
+            .apply_changeset_file_with_options(
+                changeset_data.as_bytes(),
+                confirm_hard_deletions,
+                &operation.token,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Log the import
+        state
+            .audit
+            .log_action("import", "changeset_data", 0, 1, Some(&details))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!("Imported changeset data: {}", import_result))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn import_full_backup_data(
+    state: tauri::State<'_, AppState>,
+    backup_data: String,
+    operation_id: Option<String>,
+) -> Result<String, String> {
+    let operation = state.operations.register(operation_id);
+    let params = serde_json::json!({ "source": "direct" });
+    run_job(&state, "import_full_backup_data", params, Some(&operation), async {
+        let db = state.db.lock().await;
+        let import_result = db
+            .import_full_backup_from_reader(std::io::Cursor::new(backup_data.into_bytes()), &operation.token)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        // Log the import
+        state
+            .audit
+            .log_action("import", "full_backup_data", 0, 1, Some("direct"))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!("Imported full backup data: {}", import_result))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_device_config(state: tauri::State<'_, AppState>) -> Result<DeviceConfig, String> {
+    let config = state
+        .crypto
+        .get_device_config()
+        .map_err(|e| e.to_string())?;
+    
+    // Convert HashMap to DeviceConfig struct
+    let device_config = DeviceConfig {
+        device_type: config.get("device_type").unwrap_or(&"unknown".to_string()).clone(),
+        device_name: config.get("device_name").cloned(),
+    };
+    
+    Ok(device_config)
+}
+
+#[tauri::command]
+async fn set_device_config(
+    state: tauri::State<'_, AppState>,
+    device_type: String,
+    device_name: Option<String>,
+) -> Result<(), String> {
+    let _config = DeviceConfig {
+        device_type: device_type.clone(),
+        device_name: device_name.clone(),
+    };
+
+    let before = state.crypto.get_device_config().map_err(|e| e.to_string())?;
+    state
+        .crypto
+        .set_device_config(Some(device_type.clone()), device_name)
+        .map_err(|e| e.to_string())?;
+    let after = state.crypto.get_device_config().map_err(|e| e.to_string())?;
+    for setting in ["device_type", "device_name"] {
+        config_audit::log_change(
+            &state.audit,
+            setting,
+            before.get(setting).cloned(),
+            after.get(setting).cloned(),
+            1,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    // Log the configuration change
+    state
+        .audit
+        .log_action("update", "device_config", 0, 1, Some(&device_type))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_student(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    force_delete: Option<bool>,
+    justification: Option<String>,
+) -> Result<(), String> {
+    let force_delete = force_delete.unwrap_or(false);
+    let db = state.db.lock().await;
+
+    // With several users, permanent erasure goes through request_erasure/approve_erasure
+    if force_delete && db.get_users().await.map_err(|e| e.to_string())?.len() > 1 {
+        return Err("Hard deletion needs a second user's approval; use request_erasure".to_string());
+    }
+
+    // Log the deletion attempt
+    let details = if force_delete {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!("hard_delete: {}", justification)
+    } else {
+        "soft_delete".to_string()
+    };
+    state
+        .audit
+        .log_action("delete", "student", student_id, 1, Some(&details))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    db.delete_student(student_id, force_delete)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// `transfer_date` is a calendar day; the transfer takes effect at its start.
+#[tauri::command]
+async fn transfer_student(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    new_class_id: i64,
+    transfer_date: chrono::NaiveDate,
+    keep_history: bool,
+) -> Result<StudentTransfer, String> {
+    use chrono::TimeZone;
+
+    let transfer_date = chrono::Utc.from_utc_datetime(&transfer_date.and_hms_opt(0, 0, 0).unwrap());
+    let db = state.db.lock().await;
+    let transfer = db
+        .transfer_student(student_id, new_class_id, transfer_date, keep_history)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "transfer",
+            "student",
+            student_id,
+            1,
+            Some(&format!(
+                "class {} -> {} as of {}{}",
+                transfer.from_class_id,
+                transfer.to_class_id,
+                transfer.transferred_at.format("%Y-%m-%d"),
+                if keep_history { "" } else { ", history moved along" }
+            )),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(transfer)
+}
+
+/// Moves a student to another class now. Their observations stay where they
+/// are and count for the old class in reports up to the move; the move is
+/// recorded in `student_class_history` like a dated transfer.
+#[tauri::command]
+async fn move_student_to_class(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    new_class_id: i64,
+) -> Result<StudentTransfer, String> {
+    let db = state.db.lock().await;
+    let transfer = db
+        .transfer_student(student_id, new_class_id, chrono::Utc::now(), true)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "transfer",
+            "student",
+            student_id,
+            1,
+            Some(&format!("class {} -> {}", transfer.from_class_id, transfer.to_class_id)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(transfer)
+}
+
+/// Records that a student leaves the school on `date`; their data is kept
+/// until the retention period after it ends.
+#[tauri::command]
+async fn mark_student_left(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    date: chrono::NaiveDate,
+) -> Result<Student, String> {
+    let db = state.db.lock().await;
+    let student = db.mark_student_left(student_id, date).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "mark_left",
+            "student",
+            student_id,
+            1,
+            Some(&format!("left on {}, now {}", date.format("%Y-%m-%d"), student.status)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(student)
+}
+
+#[tauri::command]
+async fn get_student_transfers(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+) -> Result<Vec<StudentTransfer>, String> {
+    let db = state.db.lock().await;
+    db.get_student_transfers(student_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_class(
+    state: tauri::State<'_, AppState>,
+    class_id: i64,
+    force_delete: Option<bool>,
+    justification: Option<String>,
+) -> Result<(), String> {
+    let force_delete = force_delete.unwrap_or(false);
+    let db = state.db.lock().await;
+
+    // Erasing the students of the class follows the rules for erasing one student
+    let student_ids = if force_delete {
+        db.get_student_ids_in_class(class_id).await.map_err(|e| e.to_string())?
+    } else {
+        Vec::new()
+    };
+    if !student_ids.is_empty() && db.get_users().await.map_err(|e| e.to_string())?.len() > 1 {
+        return Err(
+            "Hard deletion of the class's students needs a second user's approval; use request_erasure for each student first"
+                .to_string(),
+        );
+    }
+
+    // Log the deletion attempt
+    let details = if force_delete {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!("force_delete: {}", justification)
+    } else {
+        "safe_delete".to_string()
+    };
+    state
+        .audit
+        .log_action("delete", "class", class_id, 1, Some(&details))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for student_id in student_ids {
+        state
+            .audit
+            .log_action("delete", "student", student_id, 1, Some(&format!("hard_delete: class {} deleted", class_id)))
+            .await
+            .map_err(|e| e.to_string())?;
+        state
+            .gdpr
+            .delete_student_hard(&db, student_id)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    db.delete_class(class_id, force_delete)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// Category management commands
+#[tauri::command]
+async fn get_categories(state: tauri::State<'_, AppState>) -> Result<Vec<Category>, String> {
+    let db = state.db.lock().await;
+    let mut categories = db.get_categories().await.map_err(|e| e.to_string())?;
+    let locale = localization::get_locale(&db).await.map_err(|e| e.to_string())?;
+    localization::localize_categories(&mut categories, &locale);
+    Ok(categories)
+}
+
+#[tauri::command]
+async fn get_locale(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let db = state.db.lock().await;
+    localization::get_locale(&db).await.map_err(|e| e.to_string())
+}
+
+/// Switches the language category and template names are shown in.
+#[tauri::command]
+async fn set_locale(state: tauri::State<'_, AppState>, locale: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    localization::set_locale(&db, &locale).await.map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_category_translations(
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    translations: std::collections::BTreeMap<String, String>,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.set_category_translations(id, &translations)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "category", id, 1, Some("translations"))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_template_translations(
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    translations: std::collections::BTreeMap<String, String>,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.set_template_translations(id, &translations)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "observation_template", id, 1, Some("translations"))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_category(
+    state: tauri::State<'_, AppState>,
+    name: String,
+    color: String,
+    background_color: String,
+    text_color: String,
+    icon: Option<String>,
+) -> Result<Category, String> {
+    let db = state.db.lock().await;
+    let category = db
+        .create_category(name.clone(), color, background_color, text_color, icon)
+        .await
+        .map_err(|e| e.to_string())?;
+        
+    // Log the creation
+    state
+        .audit
+        .log_action("create", "category", category.id, 1, Some(&name))
+        .await
+        .map_err(|e| e.to_string())?;
+        
+    Ok(category)
+}
+
+#[tauri::command]
+async fn update_category(
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    name: String,
+    color: String,
+    background_color: String,
+    text_color: String,
+    icon: Option<String>,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.update_category(id, name.clone(), color, background_color, text_color, icon)
+        .await
+        .map_err(|e| e.to_string())?;
+        
+    // Log the update
+    state
+        .audit
+        .log_action("update", "category", id, 1, Some(&name))
+        .await
+        .map_err(|e| e.to_string())?;
+        
+    Ok(())
+}
+
+/// Audited once for the whole merge, with the counts of what was moved.
+#[tauri::command]
+async fn merge_categories(
+    state: tauri::State<'_, AppState>,
+    source_id: i64,
+    target_id: i64,
+) -> Result<database::CategoryMerge, String> {
+    let db = state.db.lock().await;
+    let merge = db
+        .merge_categories(source_id, target_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "merge",
+            "category",
+            source_id,
+            1,
+            Some(&format!(
+                "{} into {} (id {}): {} observations, {} templates, {} quick phrases, {} observations left as they are",
+                merge.source,
+                merge.target,
+                target_id,
+                merge.observations,
+                merge.templates,
+                merge.quick_phrases,
+                merge.skipped_observations.len()
+            )),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(merge)
+}
+
+#[tauri::command]
+async fn delete_category(
+    state: tauri::State<'_, AppState>,
+    id: i64,
+    force_delete: Option<bool>,
+    justification: Option<String>,
+) -> Result<(), String> {
+    let force_delete = force_delete.unwrap_or(false);
+    let db = state.db.lock().await;
+    
+    // Log the deletion attempt
+    let details = if force_delete {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!("force_delete: {}", justification)
+    } else {
+        "safe_delete".to_string()
+    };
+    state
+        .audit
+        .log_action("delete", "category", id, 1, Some(&details))
+        .await
+        .map_err(|e| e.to_string())?;
+        
+    db.delete_category(id, force_delete)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_observation_templates(state: tauri::State<'_, AppState>) -> Result<Vec<ObservationTemplate>, String> {
+    let db = state.db.lock().await;
+    let mut templates = db.get_observation_templates().await.map_err(|e| e.to_string())?;
+    let locale = localization::get_locale(&db).await.map_err(|e| e.to_string())?;
+    localization::localize_templates(&mut templates, &locale);
+    Ok(templates)
+}
+
+#[tauri::command]
+async fn create_observation_template(
+    state: tauri::State<'_, AppState>,
+    name: String,
+    category: String,
+    text: String,
+) -> Result<ObservationTemplate, String> {
+    let db = state.db.lock().await;
+    let template = db
+        .create_observation_template(name, category, text)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "observation_template", template.id, 1, Some(&template.name))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(template)
+}
+
+#[tauri::command]
+async fn delete_observation_template(state: tauri::State<'_, AppState>, id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_observation_template(id).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("delete", "observation_template", id, 1, None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn get_quick_phrases(
+    state: tauri::State<'_, AppState>,
+    category: Option<String>,
+) -> Result<Vec<QuickPhrase>, String> {
+    let db = state.db.lock().await;
+    db.get_quick_phrases(category.as_deref()).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_quick_phrase(
+    state: tauri::State<'_, AppState>,
+    category: String,
+    text: String,
+) -> Result<QuickPhrase, String> {
+    let db = state.db.lock().await;
+    let phrase = db.create_quick_phrase(&category, &text).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "quick_phrase", phrase.id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(phrase)
+}
+
+#[tauri::command]
+async fn update_quick_phrase(state: tauri::State<'_, AppState>, id: i64, text: String) -> Result<QuickPhrase, String> {
+    let db = state.db.lock().await;
+    let phrase = db.update_quick_phrase(id, &text).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("update", "quick_phrase", id, 1, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(phrase)
+}
+
+#[tauri::command]
+async fn delete_quick_phrase(state: tauri::State<'_, AppState>, id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_quick_phrase(id).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("delete", "quick_phrase", id, 1, None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Called when a phrase is inserted, so the most used ones come first.
+#[tauri::command]
+async fn record_quick_phrase_use(state: tauri::State<'_, AppState>, id: i64) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.record_quick_phrase_use(id).await.map_err(|e| e.to_string())
+}
+
+/// Categories, templates and quick phrases only, for sharing the taxonomy with other schools.
+#[tauri::command]
+async fn export_category_pack(
+    state: tauri::State<'_, AppState>,
+    app: tauri::AppHandle,
+    file_path: String,
+) -> Result<export_target::ExportResult, String> {
+    // Held until the location is recorded, so a freeze cannot begin in between
+    let db = state.db.lock().await;
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+    let pack = category_pack::build_pack(&db).await.map_err(|e| e.to_string())?;
+    let file_path = export_naming::resolve_path(
+        &db,
+        &state.crypto,
+        &file_path,
+        &export_naming::ExportName { export_type: "categories", class: None, extension: "json" },
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let json = serde_json::to_vec_pretty(&pack).map_err(|e| e.to_string())?;
+    let removable_media = export_target::write_synced(&file_path, &json).map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "export", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("export", "category_pack", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut result = export_target::ExportResult {
+        message: format!("Category pack exported to {}", file_path),
+        file_path,
+        removable_media,
+        post_export: None,
+    };
+    export_hook::run(&app, db, &state.audit, &mut result, "category_pack", 0, 1)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn import_category_pack(
+    state: tauri::State<'_, AppState>,
+    file_path: String,
+) -> Result<category_pack::CategoryPackImport, String> {
+    let data = std::fs::read(&file_path).map_err(|e| e.to_string())?;
+    let pack = category_pack::parse_pack(&data).map_err(|e| e.to_string())?;
+
+    let db = state.db.lock().await;
+    let report = db.merge_category_pack(&pack).await.map_err(|e| e.to_string())?;
+
+    transfer_locations::record(&db, "import", &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_transfer("import", "category_pack", 0, 1, Some(&file_path), &file_path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(report)
+}
+
+/// The audit entry lists each changed field of text, category and tags
+/// under "changes" with its `original_value` and `new_value`, so the log
+/// holds the old and new text of an edited observation.
+#[tauri::command]
+async fn update_observation(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    text: Option<String>,
+    category: Option<String>,
+    tags: Option<Vec<String>>,
+    user_id: Option<i64>,
+) -> Result<Observation, String> {
+    let user_id = user_id.unwrap_or(1);
+    let db = state.db.lock().await;
+    let (before, after) = db
+        .update_observation(observation_id, user_id, database::ObservationEdit { text, category, tags })
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut changes = Vec::new();
+    for (field, original_value, new_value) in [
+        ("text", &before.text, &after.text),
+        ("category", &before.category, &after.category),
+        ("tags", &before.tags, &after.tags),
+    ] {
+        if original_value != new_value {
+            changes.push(serde_json::json!({
+                "field": field,
+                "original_value": original_value,
+                "new_value": new_value
+            }));
+        }
+    }
+    if !changes.is_empty() {
+        let details = serde_json::json!({ "changes": changes }).to_string();
+        state
+            .audit
+            .log_action("update", "observation", observation_id, user_id, Some(&details))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(after)
+}
+
+#[tauri::command]
+async fn delete_observation(
+    state: tauri::State<'_, AppState>,
+    observation_id: i64,
+    force_delete: Option<bool>,
+    justification: Option<String>,
+) -> Result<(), String> {
+    let force_delete = force_delete.unwrap_or(false);
+    let db = state.db.lock().await;
+
+    // In a real system, you would get the current user ID from session/auth
+    // For now, using author_id = 1 as default
+    let author_id = 1;
+
+    // Log the deletion attempt
+    let details = if force_delete {
+        let justification = audit::require_justification(justification.as_deref()).map_err(|e| e.to_string())?;
+        format!("force_delete: {}", justification)
+    } else {
+        "author_delete".to_string()
+    };
+    state
+        .audit
+        .log_action(
+            "delete",
+            "observation",
+            observation_id,
+            author_id,
+            Some(&details),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    db.delete_observation(observation_id, author_id, force_delete)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_users(state: tauri::State<'_, AppState>) -> Result<Vec<User>, String> {
+    let db = state.db.lock().await;
+    db.get_users().await.map_err(|e| e.to_string())
+}
+
+/// Only an admin adds users, as the role decides what they may do.
+#[tauri::command]
+async fn create_user(
+    state: tauri::State<'_, AppState>,
+    name: String,
+    role: String,
+    admin_id: i64,
+) -> Result<User, String> {
+    let db = state.db.lock().await;
+    db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+    let user = db.create_user(name, role).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action("create", "user", user.id, admin_id, Some(&user.role))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(user)
+}
+
+#[tauri::command]
+async fn set_user_role(
+    state: tauri::State<'_, AppState>,
+    user_id: i64,
+    role: String,
+    admin_id: i64,
+) -> Result<User, String> {
+    let db = state.db.lock().await;
+    let (before, user) = db.set_user_role(user_id, &role, admin_id).await.map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "change_role",
+            "user",
+            user.id,
+            admin_id,
+            Some(&format!("{} -> {}", before.role, user.role)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(user)
+}
+
+#[tauri::command]
+async fn request_erasure(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    requested_by: i64,
+    reason: String,
+) -> Result<ErasureRequest, String> {
+    let db = state.db.lock().await;
+    let request = state
+        .gdpr
+        .request_erasure(&db, student_id, requested_by, &reason)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "request_erasure",
+            "student",
+            student_id,
+            requested_by,
+            Some(&format!("request {}: {}", request.id, request.reason)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(request)
+}
+
+#[tauri::command]
+async fn approve_erasure(
+    state: tauri::State<'_, AppState>,
+    request_id: i64,
+    admin_id: i64,
+) -> Result<gdpr::DeletionResult, String> {
+    let db = state.db.lock().await;
+    let (request, result) = state
+        .gdpr
+        .approve_erasure(&db, request_id, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "approve_erasure",
+            "student",
+            request.student_id,
+            admin_id,
+            Some(&format!(
+                "request {}: requested by user {}, approved by user {}, {} observations deleted",
+                request.id, request.requested_by, admin_id, result.observations_deleted
+            )),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(result)
+}
+
+/// Confirms an erasure scheduled by another admin; it is carried out at its date.
+#[tauri::command]
+async fn approve_scheduled_erasure(
+    state: tauri::State<'_, AppState>,
+    request_id: i64,
+    admin_id: i64,
+) -> Result<ErasureRequest, String> {
+    let db = state.db.lock().await;
+    let request = state
+        .gdpr
+        .approve_scheduled_erasure(&db, request_id, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "approve_scheduled_erasure",
+            "student",
+            request.student_id,
+            admin_id,
+            Some(&format!(
+                "request {}: requested by user {}, approved by user {}, due {}",
+                request.id,
+                request.requested_by,
+                admin_id,
+                request.execute_at.map(|at| at.to_rfc3339()).unwrap_or_default()
+            )),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(request)
+}
+
+#[tauri::command]
+async fn reject_erasure(
+    state: tauri::State<'_, AppState>,
+    request_id: i64,
+    admin_id: i64,
+) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let request = state
+        .gdpr
+        .reject_erasure(&db, request_id, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "reject_erasure",
+            "student",
+            request.student_id,
+            admin_id,
+            Some(&format!(
+                "request {}: requested by user {}, rejected by user {}",
+                request.id, request.requested_by, admin_id
+            )),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn schedule_erasure(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    execute_at: chrono::DateTime<chrono::Utc>,
+    admin_id: i64,
+    reason: String,
+) -> Result<ErasureRequest, String> {
+    let db = state.db.lock().await;
+    let request = state
+        .gdpr
+        .schedule_erasure(&db, student_id, execute_at, admin_id, &reason)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    state
+        .audit
+        .log_action(
+            "schedule_erasure",
+            "student",
+            student_id,
+            admin_id,
+            Some(&format!("request {}: due {}: {}", request.id, execute_at.to_rfc3339(), request.reason)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(request)
+}
+
+/// Who read, changed or exported a student's data, for answers under
+/// Article 15. `from` and `to` are dates (YYYY-MM-DD), both days included.
+#[tauri::command]
+async fn get_access_history(
+    state: tauri::State<'_, AppState>,
+    student_id: i64,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<gdpr::AccessHistory, String> {
+    let period = gdpr::ExportScope::parse(from.as_deref(), to.as_deref(), Vec::new()).map_err(|e| e.to_string())?;
+    let db = state.db.lock().await;
+    let history = state
+        .gdpr
+        .get_access_history(&db, &state.audit, student_id, period.from, period.to)
         .await
         .map_err(|e| e.to_string())?;
 
-    // Log the import
     state
         .audit
-        .log_action("import", "changeset_data", 0, 1, Some("direct"))
+        .log_action(
+            "read",
+            "access_history",
+            student_id,
+            1,
+            Some(&format!(
+                "{} to {}",
+                from.as_deref().unwrap_or("the beginning"),
+                to.as_deref().unwrap_or("today")
+            )),
+        )
         .await
         .map_err(|e| e.to_string())?;
 
-    Ok(format!("Imported changeset data: {}", import_result))
+    Ok(history)
 }
 
+/// Archives the records of a student who left the school, see
+/// `archive::export_archive_bundle`. With `erase_at` the live records are
+/// scheduled for erasure at that date, like `schedule_erasure`: the request
+/// names the admin and waits for `approve_scheduled_erasure` by a second one.
+/// `protection` encrypts the files of the bundle.
 #[tauri::command]
-async fn import_full_backup_data(
+async fn export_archive_bundle(
     state: tauri::State<'_, AppState>,
-    backup_data: String,
-) -> Result<String, String> {
-    let db = state.db.lock().await;
-    let import_result = db
-        .import_full_backup(backup_data.as_bytes())
+    app: tauri::AppHandle,
+    student_id: i64,
+    dir: String,
+    erase_at: Option<chrono::DateTime<chrono::Utc>>,
+    admin_id: Option<i64>,
+    include_access_history: Option<bool>,
+    protection: Option<export_protection::ExportProtection>,
+) -> Result<archive::ArchiveBundle, String> {
+    let params = serde_json::json!({
+        "student_id": student_id,
+        "dir": dir,
+        "erase_at": erase_at,
+        "admin_id": admin_id,
+        "include_access_history": include_access_history,
+        "recipient_key": protection.as_ref().and_then(|p| p.recipient_key.clone()),
+        "passphrase_protected": protection.as_ref().is_some_and(|p| p.passphrase.is_some()),
+    });
+    run_job(&state, "export_archive_bundle", params, None, async {
+        let db = state.db.lock().await;
+        db.ensure_not_frozen().map_err(|e| e.to_string())?;
+        let user_id = admin_id.unwrap_or(1);
+        // Checked up front so a refused erasure does not leave an archive behind
+        if let Some(erase_at) = erase_at {
+            let Some(admin_id) = admin_id else {
+                return Err("Scheduling the erasure needs the admin who requests it".to_string());
+            };
+            db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+            if erase_at <= chrono::Utc::now() {
+                return Err("The erasure date must be in the future".to_string());
+            }
+        }
+
+        let access_history = if include_access_history.unwrap_or(false) {
+            Some(
+                state
+                    .gdpr
+                    .get_access_history(&db, &state.audit, student_id, None, None)
+                    .await
+                    .map_err(|e| e.to_string())?,
+            )
+        } else {
+            None
+        };
+        let mut bundle = archive::export_archive_bundle(
+            &db,
+            &state.gdpr,
+            &state.crypto,
+            student_id,
+            std::path::Path::new(&dir),
+            access_history.as_ref(),
+            protection.as_ref(),
+        )
         .await
         .map_err(|e| e.to_string())?;
-
-    // Log the import
-    state
-        .audit
-        .log_action("import", "full_backup_data", 0, 1, Some("direct"))
+        transfer_locations::record(&db, "export", &dir)
+            .await
+            .map_err(|e| e.to_string())?;
+        state
+            .audit
+            .log_transfer("export", "archive_bundle", student_id, user_id, Some(&bundle.directory), &bundle.directory)
+            .await
+            .map_err(|e| e.to_string())?;
+        bundle.post_export = export_hook::run_for_path(
+            &app,
+            db,
+            &state.audit,
+            &bundle.directory,
+            "archive_bundle",
+            student_id,
+            user_id,
+        )
         .await
         .map_err(|e| e.to_string())?;
+        // The hook may be what copies the archive to the school's share
+        if let Some(hook) = bundle.post_export.as_ref().filter(|hook| !hook.success && erase_at.is_some()) {
+            return Err(format!(
+                "Archived to {}{}; the erasure was not scheduled",
+                bundle.directory,
+                export_hook::outcome_note(hook)
+            ));
+        }
+
+        if let Some(erase_at) = erase_at {
+            let db = state.db.lock().await;
+            let reason = format!("Archiviert in {}", bundle.directory);
+            let request = state
+                .gdpr
+                .schedule_erasure(&db, student_id, erase_at, user_id, &reason)
+                .await
+                .map_err(|e| format!("Archived to {}, but the erasure could not be scheduled: {}", bundle.directory, e))?;
+            state
+                .audit
+                .log_action(
+                    "schedule_erasure",
+                    "student",
+                    student_id,
+                    user_id,
+                    Some(&format!("request {}: due {}: {}", request.id, erase_at.to_rfc3339(), request.reason)),
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            bundle.erasure_request = Some(request);
+        }
 
-    Ok(format!("Imported full backup data: {}", import_result))
+        Ok(bundle)
+    })
+    .await
 }
 
 #[tauri::command]
-async fn get_device_config(state: tauri::State<'_, AppState>) -> Result<DeviceConfig, String> {
-    let config = state
-        .crypto
-        .get_device_config()
-        .map_err(|e| e.to_string())?;
-    
-    // Convert HashMap to DeviceConfig struct
-    let device_config = DeviceConfig {
-        device_type: config.get("device_type").unwrap_or(&"unknown".to_string()).clone(),
-        device_name: config.get("device_name").cloned(),
-    };
-    
-    Ok(device_config)
+async fn get_open_requests(state: tauri::State<'_, AppState>) -> Result<Vec<ErasureRequest>, String> {
+    let db = state.db.lock().await;
+    db.get_open_requests().await.map_err(|e| e.to_string())
 }
 
+/// Files and directories of recent imports and exports, paths only.
 #[tauri::command]
-async fn set_device_config(
+async fn get_recent_transfer_locations(
     state: tauri::State<'_, AppState>,
-    device_type: String,
-    device_name: Option<String>,
-) -> Result<(), String> {
-    let _config = DeviceConfig {
-        device_type: device_type.clone(),
-        device_name: device_name.clone(),
-    };
+) -> Result<transfer_locations::RecentTransferLocations, String> {
+    let db = state.db.lock().await;
+    transfer_locations::get_recent(&db).await.map_err(|e| e.to_string())
+}
 
-    state
-        .crypto
-        .set_device_config(Some(device_type.clone()), device_name)
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn clear_recent_transfer_locations(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let db = state.db.lock().await;
+    transfer_locations::clear(&db).await.map_err(|e| e.to_string())
+}
 
-    // Log the configuration change
-    state
-        .audit
-        .log_action("update", "device_config", 0, 1, Some(&device_type))
+/// Names exports written into a directory, e.g. `{date}_{class}_{type}`.
+#[tauri::command]
+async fn get_export_filename_template(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let db = state.db.lock().await;
+    export_naming::get_template(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_export_filename_template(state: tauri::State<'_, AppState>, template: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    export_naming::set_template(&db, &template).await.map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
         .await
         .map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// Reference codes such as `ST-2024-0031` to show instead of ids, by id.
+/// `entity_type` is "student", "class" or "observation".
 #[tauri::command]
-async fn delete_student(
+async fn get_reference_codes(
     state: tauri::State<'_, AppState>,
-    student_id: i64,
-    force_delete: Option<bool>,
-) -> Result<(), String> {
-    let force_delete = force_delete.unwrap_or(false);
+    entity_type: String,
+    ids: Vec<i64>,
+) -> Result<std::collections::BTreeMap<i64, String>, String> {
     let db = state.db.lock().await;
-
-    // Log the deletion attempt
-    let delete_type = if force_delete {
-        "hard_delete"
-    } else {
-        "soft_delete"
-    };
-    state
-        .audit
-        .log_action("delete", "student", student_id, 1, Some(delete_type))
+    reference_codes::codes_for(&db, &entity_type, &ids)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())
+}
 
-    db.delete_student(student_id, force_delete)
+/// Format of new reference codes, e.g. `{prefix}-{year}-{number}`.
+#[tauri::command]
+async fn get_reference_code_format(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    let db = state.db.lock().await;
+    reference_codes::get_format(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_reference_code_format(state: tauri::State<'_, AppState>, format: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    reference_codes::set_format(&db, &format).await.map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, 1)
         .await
         .map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
+/// Exports of changesets carry an RFC 3161 token from this TSA once set.
 #[tauri::command]
-async fn delete_class(
+async fn get_trusted_timestamp_authority(state: tauri::State<'_, AppState>) -> Result<Option<String>, String> {
+    let db = state.db.lock().await;
+    trusted_timestamp::configured_tsa(&db).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_trusted_timestamp_authority(
     state: tauri::State<'_, AppState>,
-    class_id: i64,
-    force_delete: Option<bool>,
+    tsa_url: Option<String>,
+    certificate_pem: Option<String>,
+    admin_id: i64,
 ) -> Result<(), String> {
-    let force_delete = force_delete.unwrap_or(false);
     let db = state.db.lock().await;
-
-    // Log the deletion attempt
-    let delete_type = if force_delete {
-        "force_delete"
-    } else {
-        "safe_delete"
-    };
-    state
-        .audit
-        .log_action("delete", "class", class_id, 1, Some(delete_type))
+    db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    trusted_timestamp::set_tsa(&db, tsa_url.as_deref(), certificate_pem.as_deref())
         .await
         .map_err(|e| e.to_string())?;
-
-    db.delete_class(class_id, force_delete)
+    settings_change
+        .finish(&db, &state.audit, admin_id)
         .await
         .map_err(|e| e.to_string())?;
 
     Ok(())
 }
 
-// Category management commands
+/// Run after every export with the path of the written file or directory.
 #[tauri::command]
-async fn get_categories(state: tauri::State<'_, AppState>) -> Result<Vec<Category>, String> {
+async fn get_export_hook(state: tauri::State<'_, AppState>) -> Result<Option<String>, String> {
     let db = state.db.lock().await;
-    db.get_categories().await.map_err(|e| e.to_string())
+    export_hook::configured_program(&db).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn create_category(
+async fn set_export_hook(
     state: tauri::State<'_, AppState>,
-    name: String,
-    color: String,
-    background_color: String,
-    text_color: String,
-) -> Result<Category, String> {
+    program: Option<String>,
+    admin_id: i64,
+) -> Result<(), String> {
     let db = state.db.lock().await;
-    let category = db
-        .create_category(name.clone(), color, background_color, text_color)
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    export_hook::set_program(&db, program.as_deref(), admin_id)
         .await
         .map_err(|e| e.to_string())?;
-        
-    // Log the creation
-    state
-        .audit
-        .log_action("create", "category", category.id, 1, Some(&name))
+    settings_change
+        .finish(&db, &state.audit, admin_id)
         .await
         .map_err(|e| e.to_string())?;
-        
-    Ok(category)
+
+    Ok(())
 }
 
 #[tauri::command]
-async fn update_category(
+async fn get_exports_must_be_encrypted(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    let db = state.db.lock().await;
+    export_protection::is_required(&db).await.map_err(|e| e.to_string())
+}
+
+/// Applies to exports carrying personal data: changesets, attachment
+/// transfers, xAPI statements and incident reports. Handover packages are
+/// always sealed; category packs, DPIA documents, receipts and attachment
+/// requests hold no personal data.
+#[tauri::command]
+async fn set_exports_must_be_encrypted(
     state: tauri::State<'_, AppState>,
-    id: i64,
-    name: String,
-    color: String,
-    background_color: String,
-    text_color: String,
+    required: bool,
+    admin_id: i64,
 ) -> Result<(), String> {
     let db = state.db.lock().await;
-    db.update_category(id, name.clone(), color, background_color, text_color)
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    export_protection::set_required(&db, required, admin_id)
         .await
         .map_err(|e| e.to_string())?;
-        
-    // Log the update
+    settings_change
+        .finish(&db, &state.audit, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
     state
         .audit
-        .log_action("update", "category", id, 1, Some(&name))
+        .log_action(
+            "set_export_encryption",
+            "app",
+            0,
+            admin_id,
+            Some(if required { "required" } else { "optional" }),
+        )
         .await
-        .map_err(|e| e.to_string())?;
-        
-    Ok(())
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-async fn delete_category(
+async fn get_observation_lock_days(state: tauri::State<'_, AppState>) -> Result<Option<u32>, String> {
+    let db = state.db.lock().await;
+    db.get_observation_lock_days().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_observation_lock_days(
     state: tauri::State<'_, AppState>,
-    id: i64,
-    force_delete: Option<bool>,
+    days: Option<u32>,
+    admin_id: i64,
 ) -> Result<(), String> {
-    let force_delete = force_delete.unwrap_or(false);
     let db = state.db.lock().await;
-    
-    // Log the deletion attempt
-    let delete_type = if force_delete {
-        "force_delete"
-    } else {
-        "safe_delete"
+    db.require_admin(admin_id).await.map_err(|e| e.to_string())?;
+    let settings_change = config_audit::begin(&db).await.map_err(|e| e.to_string())?;
+    db.set_observation_lock_days(days).await.map_err(|e| e.to_string())?;
+    settings_change
+        .finish(&db, &state.audit, admin_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let details = match days {
+        Some(days) if days > 0 => format!("read-only after {} days", days),
+        _ => "disabled".to_string(),
     };
     state
         .audit
-        .log_action("delete", "category", id, 1, Some(delete_type))
-        .await
-        .map_err(|e| e.to_string())?;
-        
-    db.delete_category(id, force_delete)
+        .log_action("configure", "observation_lock", 0, admin_id, Some(&details))
         .await
-        .map_err(|e| e.to_string())?;
-    Ok(())
+        .map_err(|e| e.to_string())
 }
 
+/// Admin override for a read-only observation; always audited with the reason.
 #[tauri::command]
-async fn delete_observation(
+async fn unlock_observation(
     state: tauri::State<'_, AppState>,
     observation_id: i64,
-    force_delete: Option<bool>,
-) -> Result<(), String> {
-    let force_delete = force_delete.unwrap_or(false);
+    admin_id: i64,
+    reason: String,
+) -> Result<chrono::DateTime<chrono::Utc>, String> {
     let db = state.db.lock().await;
+    let expires_at = db
+        .unlock_observation(observation_id, admin_id, &reason)
+        .await
+        .map_err(|e| e.to_string())?;
 
-    // In a real system, you would get the current user ID from session/auth
-    // For now, using author_id = 1 as default
-    let author_id = 1;
-
-    // Log the deletion attempt
-    let delete_type = if force_delete {
-        "force_delete"
-    } else {
-        "author_delete"
-    };
     state
         .audit
         .log_action(
-            "delete",
+            "unlock",
             "observation",
             observation_id,
-            author_id,
-            Some(delete_type),
+            admin_id,
+            Some(&format!("{} (until {})", reason.trim(), expires_at.to_rfc3339())),
         )
         .await
         .map_err(|e| e.to_string())?;
 
-    db.delete_observation(observation_id, author_id, force_delete)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
+    Ok(expires_at)
 }
 
 #[tauri::command]
 async fn get_observation(
     state: tauri::State<'_, AppState>,
     observation_id: i64,
+    viewer_id: Option<i64>,
 ) -> Result<Option<Observation>, String> {
     let db = state.db.lock().await;
-    db.get_observation(observation_id)
+    let mut observation = db
+        .get_observation(observation_id)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?
+        .filter(|o| o.is_visible_to(viewer_id.unwrap_or(1)));
+    if let Some(pseudonymizer) = privacy::display_pseudonymizer(&db).await.map_err(|e| e.to_string())? {
+        pseudonymizer.observations(observation.as_mut_slice());
+    }
+    Ok(observation)
 }
 
 #[tauri::command]
 async fn get_database_path(app: tauri::AppHandle) -> Result<String, String> {
     let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-
-    let default_path = app_data_dir.join("observations.db");
-
-    // Check if custom path is stored in config
-    let config_path = app_data_dir.join("config.json");
-    if config_path.exists() {
-        if let Ok(config_data) = std::fs::read_to_string(&config_path) {
-            if let Ok(config) = serde_json::from_str::<serde_json::Value>(&config_data) {
-                if let Some(custom_path) = config.get("database_path").and_then(|p| p.as_str()) {
-                    return Ok(custom_path.to_string());
-                }
-            }
-        }
-    }
-
-    Ok(default_path.to_string_lossy().to_string())
+    let data_paths = data_root::resolve(&app_data_dir, &data_root::load_config(&app_data_dir));
+    Ok(data_paths.database.to_string_lossy().to_string())
 }
 
 #[tauri::command]
-async fn set_database_path(app: tauri::AppHandle, new_path: String) -> Result<(), String> {
+async fn set_database_path(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    new_path: String,
+) -> Result<(), String> {
+    // Another database file would start without the freeze
+    state.db.lock().await.ensure_not_frozen().map_err(|e| e.to_string())?;
+
     let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
 
     // Validate the new path
@@ -714,24 +6591,376 @@ async fn set_database_path(app: tauri::AppHandle, new_path: String) -> Result<()
     }
 
     // Store the custom path in config
-    let config_path = app_data_dir.join("config.json");
-    let config = serde_json::json!({
-        "database_path": new_path
-    });
+    let mut config = data_root::load_config(&app_data_dir);
+    let before = config.database_path.replace(new_path.clone());
+    data_root::save_config(&app_data_dir, &config).map_err(|e| e.to_string())?;
 
-    std::fs::create_dir_all(&app_data_dir)
-        .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    config_audit::log_change(&state.audit, "database_path", before, Some(new_path), 1)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    std::fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
-        .map_err(|e| format!("Failed to save configuration: {}", e))?;
+/// Moves database, attachments, snapshots, audit log and secrets to `path`
+/// together, e.g. to an encrypted partition, and restarts the app there.
+#[tauri::command]
+async fn set_app_data_root(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    path: String,
+) -> Result<(), String> {
+    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let secrets_path = crypto::secrets_file().map_err(|e| e.to_string())?;
 
-    Ok(())
+    let db = state.db.lock().await;
+    // The copy would start without the freeze
+    db.ensure_not_frozen().map_err(|e| e.to_string())?;
+
+    // Logged first so the entry is part of the copied audit log
+    state
+        .audit
+        .log_action("relocate", "app_data", 0, 1, Some(&path))
+        .await
+        .map_err(|e| e.to_string())?;
+    let target = data_root::relocate(
+        &app_data_dir,
+        &db,
+        &state.audit,
+        &secrets_path,
+        std::path::Path::new(&path),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let status = security::check_and_harden(&target.root, &target.database, &target.audit, &target.root.join("secure.json"));
+    for warning in &status.warnings {
+        eprintln!("Security check: {}", warning);
+    }
+
+    // Nothing may write to the old files after the copy
+    app.restart()
+}
+
+/// Opens keys, database and audit log. An error here starts the app in
+/// maintenance mode instead of closing it without a word.
+fn initialize_state(app: &tauri::App, data_paths: &data_root::DataPaths) -> anyhow::Result<AppState> {
+    let crypto = Arc::new(crypto::CryptoManager::new().context("Failed to initialize CryptoManager")?);
+
+    let db_path = data_paths.database.clone();
+    let new_database = !db_path.exists();
+    let (db, content_protection) = tauri::async_runtime::block_on(async {
+        let mut db = database::Database::new(db_path, crypto.clone()).await?;
+        // Before anything else writes, so only changes made while the app was closed count
+        let integrity = match integrity::verify(&db, &crypto, new_database).await {
+            Ok(check) => check,
+            Err(e) => {
+                eprintln!("Integrity check failed: {}", e);
+                integrity::IntegrityCheck::failed()
+            }
+        };
+        if let Some(warning) = integrity.warning() {
+            eprintln!("Integrity check: {}", warning);
+        }
+        app.manage(integrity);
+        app_mode::restore(&mut db).await?;
+        app.manage(feature_flags::FeatureFlags::load(&db).await?);
+        match db.interrupt_running_jobs().await {
+            Ok(0) => {}
+            Ok(count) => eprintln!("{} jobs were interrupted when the app last closed", count),
+            Err(e) => eprintln!("Failed to check for interrupted jobs: {}", e),
+        }
+        let content_protection = privacy::get_content_protection(&db).await?;
+        anyhow::Ok((db, content_protection))
+    })
+    .context("Failed to open the database")?;
+    if content_protection {
+        if let Err(e) = apply_content_protection(app.handle(), true) {
+            eprintln!("Failed to enable content protection: {}", e);
+        }
+    }
+
+    let audit_path = data_paths.audit.clone();
+    let audit = Arc::new(
+        tauri::async_runtime::block_on(async { audit::AuditLogger::new(audit_path).await })
+            .context("Failed to open the audit log")?,
+    );
+
+    // Audit and retention rely on the system clock being plausible
+    match tauri::async_runtime::block_on(audit.check_clock()) {
+        Ok(Some(jump)) => eprintln!(
+            "Clock check: clock set {} (last audit entry at {}), {} entries annotated",
+            jump.direction, jump.last_entry_at, jump.annotated_entries
+        ),
+        Ok(None) => {}
+        Err(e) => eprintln!("Clock check failed: {}", e),
+    }
+
+    // Tighten file permissions on every start, not only the first
+    match crypto::secrets_file() {
+        Ok(secrets_path) => {
+            let status = security::check_and_harden(&data_paths.root, db.db_path(), audit.db_path(), &secrets_path);
+            for warning in &status.warnings {
+                eprintln!("Security check: {}", warning);
+            }
+        }
+        Err(e) => eprintln!("Security check skipped: {}", e),
+    }
+
+    Ok(AppState {
+        db: Arc::new(Mutex::new(db)),
+        crypto,
+        // p2p: Removed - using file-based changeset sync
+        audit,
+        gdpr: Arc::new(gdpr::GdprManager::new()),
+        reports: Arc::new(reports::ReportGenerator::new()),
+        operations: Arc::new(operations::OperationRegistry::new()),
+    })
 }
 
 fn main() {
     // A simple logger that prints to the console
     env_logger::init();
 
+    let app_commands: fn(tauri::ipc::Invoke) -> bool = tauri::generate_handler![
+        get_sync_status,
+        get_sidebar_badges,
+        create_observation,
+        create_observations_batch,
+        set_observation_local_only,
+        cosign_observation,
+        get_observation_visibility_default,
+        set_observation_visibility_default,
+        set_observation_visibility,
+        preview_notes_import,
+        import_notes_file,
+        get_observation,
+        update_observation,
+        delete_observation,
+        get_users,
+        create_user,
+        set_user_role,
+        request_erasure,
+        approve_erasure,
+        approve_scheduled_erasure,
+        reject_erasure,
+        get_open_requests,
+        schedule_erasure,
+        export_archive_bundle,
+        get_access_history,
+        get_recent_transfer_locations,
+        clear_recent_transfer_locations,
+        get_export_filename_template,
+        set_export_filename_template,
+        get_reference_codes,
+        get_reference_code_format,
+        set_reference_code_format,
+        get_trusted_timestamp_authority,
+        set_trusted_timestamp_authority,
+        get_export_hook,
+        set_export_hook,
+        get_exports_must_be_encrypted,
+        set_exports_must_be_encrypted,
+        get_observation_lock_days,
+        set_observation_lock_days,
+        unlock_observation,
+        get_students,
+        get_classes,
+        search_observations,
+        set_observation_language,
+        export_student_data,
+        generate_weekly_summary,
+        export_weekly_summary_pdf,
+        get_report_artifacts,
+        get_report_artifact_content,
+        create_class,
+        create_student,
+        update_student,
+        delete_student,
+        transfer_student,
+        move_student_to_class,
+        mark_student_left,
+        get_student_transfers,
+        get_class_layout,
+        set_class_layout,
+        get_jobs,
+        retry_job,
+        list_quarantined_imports,
+        promote_import,
+        discard_import,
+        get_subjects,
+        create_subject,
+        delete_subject,
+        get_observation_metrics,
+        set_observation_metrics,
+        get_metric_names,
+        get_metric_series,
+        export_metric_series,
+        get_competencies,
+        create_competency,
+        delete_competency,
+        get_observation_competencies,
+        set_observation_competencies,
+        get_competency_coverage,
+        export_support_plan,
+        export_blank_observation_sheets,
+        export_substitute_brief,
+        set_observation_pinned,
+        get_student_goals,
+        create_student_goal,
+        set_student_goal_status,
+        get_class_agreements,
+        set_class_agreement,
+        delete_class_agreement,
+        export_class_photos,
+        import_scanned_notes,
+        set_ocr_config,
+        get_scanned_notes,
+        promote_scanned_notes,
+        import_observations_clipboard,
+        get_clipboard_rows,
+        promote_clipboard_rows,
+        get_rubrics,
+        create_rubric,
+        update_rubric,
+        delete_rubric,
+        get_observation_rubric_scores,
+        set_observation_rubric_scores,
+        get_rubric_aggregates,
+        start_observation_session,
+        end_observation_session,
+        get_active_observation_session,
+        get_observation_sessions,
+        get_session_observations,
+        get_session_statistics,
+        compare_class_statistics,
+        export_observation_session,
+        get_guardians,
+        create_guardian,
+        update_guardian,
+        delete_guardian,
+        delete_class,
+        get_categories,
+        create_category,
+        update_category,
+        delete_category,
+        merge_categories,
+        get_locale,
+        set_locale,
+        set_category_translations,
+        set_template_translations,
+        get_observation_templates,
+        create_observation_template,
+        delete_observation_template,
+        get_quick_phrases,
+        create_quick_phrase,
+        update_quick_phrase,
+        delete_quick_phrase,
+        record_quick_phrase_use,
+        export_category_pack,
+        import_category_pack,
+        // P2P commands removed - using file-based changeset sync:
+        // start_p2p_sync, stop_p2p_sync, pair_device, generate_pairing_pin,
+        // get_pairing_code, get_current_pairing_pin, clear_pairing_pin, trigger_sync
+        export_changeset,
+        import_changeset,
+        export_changeset_to_file,
+        estimate_changeset_size,
+        export_attachment_request,
+        export_missing_attachments,
+        import_attachment_transfer,
+        get_device_public_key,
+        generate_handover_package,
+        import_handover_package,
+        get_handover_imports,
+        get_retractions,
+        import_changeset_from_file,
+        get_applied_operations,
+        run_consistency_audit,
+        export_sync_receipt,
+        import_sync_receipt,
+        export_all_data,
+        import_full_backup,
+        preview_backup_restore,
+        restore_from_backup,
+        cancel_operation,
+        convert_export_file,
+        generate_data_flow_report,
+        get_retention_status,
+        get_redaction_settings,
+        set_redaction_settings,
+        preview_redaction,
+        get_anonymization_dictionary,
+        create_anonymization_term,
+        update_anonymization_term,
+        delete_anonymization_term,
+        get_cross_mention_warnings,
+        get_privacy_display_mode,
+        set_privacy_display_mode,
+        get_content_protection,
+        get_companion_api_status,
+        set_companion_api_enabled,
+        get_xapi_export_enabled,
+        set_xapi_export_enabled,
+        export_xapi_statements,
+        set_content_protection,
+        get_app_mode,
+        get_feature_flags,
+        set_feature_flag,
+        set_app_password,
+        unlock_app,
+        get_unlock_status,
+        has_recovery_code,
+        recover_with_code,
+        freeze_all_processing,
+        unfreeze_processing,
+        get_dpia_checklist,
+        answer_dpia_item,
+        export_dpia_assessment,
+        record_incident,
+        update_incident,
+        get_incidents,
+        export_incident_report,
+        get_attachment_storage_mode,
+        migrate_attachment_storage,
+        add_attachment,
+        get_attachments_for_observation,
+        download_attachment,
+        delete_attachment,
+        add_attachment_from_clipboard,
+        add_handwritten_note,
+        render_handwritten_note,
+        get_database_health,
+        get_storage_usage,
+        get_health_report,
+        get_security_status,
+        set_storage_quotas,
+        get_custom_dictionary,
+        add_dictionary_word,
+        remove_dictionary_word,
+        set_transcription_config,
+        transcribe_attachment,
+        get_attachment_thumbnail,
+        create_snapshot,
+        list_snapshots,
+        rollback_to_snapshot,
+        import_changeset_data,
+        import_full_backup_data,
+        generate_school_escrow_key,
+        create_escrow_backup,
+        import_escrow_backup,
+        get_device_config,
+        set_device_config,
+        get_database_path,
+        set_database_path,
+        set_app_data_root,
+        get_startup_status
+    ];
+    // Without a database only the diagnostics work
+    let diagnostic_commands: fn(tauri::ipc::Invoke) -> bool = tauri::generate_handler![
+        get_startup_status,
+        maintenance_commands::get_database_health,
+        maintenance_commands::run_self_test,
+        maintenance_commands::restore_backup
+    ];
+
     tauri::Builder::default()
         .setup(|app| {
             let app_data_dir = app
@@ -739,94 +6968,111 @@ fn main() {
                 .app_data_dir()
                 .expect("Failed to get app data directory");
 
-            // Initialize crypto manager (do not panic if secure storage is unavailable)
-            let crypto =
-                Arc::new(crypto::CryptoManager::new().expect("Failed to initialize CryptoManager"));
-
-            // Initialize database - check for custom path
-            let config_path = app_data_dir.join("config.json");
-            let db_path = if config_path.exists() {
-                if let Ok(config_data) = std::fs::read_to_string(&config_path) {
-                    if let Ok(config) = serde_json::from_str::<serde_json::Value>(&config_data) {
-                        if let Some(custom_path) =
-                            config.get("database_path").and_then(|p| p.as_str())
-                        {
-                            std::path::PathBuf::from(custom_path)
-                        } else {
-                            app_data_dir.join("observations.db")
-                        }
-                    } else {
-                        app_data_dir.join("observations.db")
-                    }
-                } else {
-                    app_data_dir.join("observations.db")
+            // Files of a previous relocation are no longer open now
+            if let Err(e) = data_root::finish_pending_cleanup(&app_data_dir) {
+                eprintln!("Failed to clean up the previous data directory: {}", e);
+            }
+            let data_paths = data_root::resolve(&app_data_dir, &data_root::load_config(&app_data_dir));
+            if let Some(secrets_path) = &data_paths.secrets {
+                if let Some(dir) = secrets_path.parent() {
+                    crypto::set_data_dir(dir.to_path_buf());
+                }
+            }
+
+            let state = match initialize_state(app, &data_paths) {
+                Ok(state) => state,
+                Err(e) => {
+                    // Keep the window usable, so the teacher sees why and can restore a snapshot
+                    eprintln!("Startup failed, starting in maintenance mode: {:#}", e);
+                    app.manage(maintenance::StartupStatus::failed(data_paths.database.clone(), &e));
+                    return Ok(());
                 }
-            } else {
-                app_data_dir.join("observations.db")
             };
+            app.manage(maintenance::StartupStatus::ready(data_paths.database.clone()));
+            app.manage(state.clone());
 
-            let db = tauri::async_runtime::block_on(async {
-                database::Database::new(db_path, crypto.clone()).await
+            let companion_enabled = tauri::async_runtime::block_on(async {
+                companion_api::is_enabled(&*state.db.lock().await).await
             })
-            .unwrap();
-
-            // Initialize audit logger
-            let audit_path = app_data_dir.join("audit.db");
-            let audit = Arc::new(
-                tauri::async_runtime::block_on(async { audit::AuditLogger::new(audit_path).await })
-                    .unwrap(),
-            );
+            .unwrap_or(false);
+            if companion_enabled {
+                companion_api::start(state.clone(), app_data_dir.clone());
+            }
 
-            let gdpr = Arc::new(gdpr::GdprManager::new());
+            // Scheduled erasures are checked more often than storage, a missed one waits at most 10 minutes
+            let app_handle = app.handle().clone();
+            let scheduler_state = state.clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(10 * 60));
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = run_scheduled_tasks(&app_handle, &scheduler_state).await {
+                        eprintln!("Scheduler run failed: {}", e);
+                    }
+                }
+            });
 
-            let state = AppState {
-                db: Arc::new(Mutex::new(db)),
-                crypto,
-                // p2p: Removed - using file-based changeset sync
-                audit,
-                gdpr,
-            };
+            // Warn about low disk space and damaged files early, not only when
+            // someone opens the settings or an export fails at the worst moment
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(30 * 60));
+                let mut last_health = None;
+                loop {
+                    interval.tick().await;
+                    if let Err(e) = check_storage(&app_handle, &state).await {
+                        eprintln!("Storage check failed: {}", e);
+                    }
+                    match check_health(&app_handle, &state, last_health.as_ref()).await {
+                        Ok(report) => last_health = Some(report),
+                        Err(e) => eprintln!("Health check failed: {}", e),
+                    }
+                }
+            });
 
-            app.manage(state.clone());
             Ok(())
         })
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_notification::init())
-        .invoke_handler(tauri::generate_handler![
-            get_sync_status,
-            create_observation,
-            get_observation,
-            delete_observation,
-            get_students,
-            get_classes,
-            search_observations,
-            export_student_data,
-            create_class,
-            create_student,
-            delete_student,
-            delete_class,
-            get_categories,
-            create_category,
-            update_category,
-            delete_category,
-            // P2P commands removed - using file-based changeset sync:
-            // start_p2p_sync, stop_p2p_sync, pair_device, generate_pairing_pin,
-            // get_pairing_code, get_current_pairing_pin, clear_pairing_pin, trigger_sync
-            export_changeset,
-            import_changeset,
-            export_changeset_to_file,
-            import_changeset_from_file,
-            export_all_data,
-            import_full_backup,
-            import_changeset_data,
-            import_full_backup_data,
-            get_device_config,
-            set_device_config,
-            get_database_path,
-            set_database_path
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .invoke_handler(move |invoke| {
+            let maintenance_mode = invoke
+                .message
+                .webview()
+                .try_state::<maintenance::StartupStatus>()
+                .is_some_and(|status| status.maintenance_mode);
+            if maintenance_mode {
+                return diagnostic_commands(invoke);
+            }
+            // Commands of a subsystem switched off for this profile never run
+            let disabled_by = invoke
+                .message
+                .webview()
+                .try_state::<feature_flags::FeatureFlags>()
+                .and_then(|flags| flags.disabled_by(invoke.message.command()));
+            if let Some(flag) = disabled_by {
+                let message = format!("{} is disabled by the feature flag {}", invoke.message.command(), flag);
+                invoke.resolver.reject(message);
+                return true;
+            }
+            app_commands(invoke)
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Seal the database, so the next start can tell whether it was edited in between
+            if let tauri::RunEvent::Exit = event {
+                if let Some(state) = app.try_state::<AppState>() {
+                    let sealed = tauri::async_runtime::block_on(async {
+                        let db = state.db.lock().await;
+                        integrity::seal(&db, &state.crypto).await
+                    });
+                    if let Err(e) = sealed {
+                        eprintln!("Failed to seal the database: {}", e);
+                    }
+                }
+            }
+        });
 }
@@ -0,0 +1,262 @@
+use crate::crypto::CryptoManager;
+use crate::database::{self, Database, SnapshotInfo};
+use anyhow::{Context, Result};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Managed in either mode, so the frontend can ask before it loads any data.
+/// In maintenance mode only the diagnostic commands are available.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StartupStatus {
+    pub maintenance_mode: bool,
+    pub error: Option<String>,
+    pub database_path: PathBuf,
+}
+
+impl StartupStatus {
+    pub fn ready(database_path: PathBuf) -> Self {
+        Self {
+            maintenance_mode: false,
+            error: None,
+            database_path,
+        }
+    }
+
+    pub fn failed(database_path: PathBuf, error: &anyhow::Error) -> Self {
+        Self {
+            maintenance_mode: true,
+            error: Some(format!("{:#}", error)),
+            database_path,
+        }
+    }
+}
+
+/// What can be told about the database file without running the migrations.
+#[derive(Debug, serde::Serialize)]
+pub struct DatabaseFileHealth {
+    pub path: String,
+    pub exists: bool,
+    pub size_bytes: u64,
+    pub wal_bytes: u64,
+    pub sqlite_header: bool,
+    pub problems: Vec<String>, // Empty when SQLite's quick check passed
+    pub snapshots: Vec<SnapshotInfo>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub passed: bool,
+    pub details: String,
+}
+
+impl SelfTestCheck {
+    fn new(name: &str, result: Result<String>) -> Self {
+        let (passed, details) = match result {
+            Ok(details) => (true, details),
+            Err(e) => (false, format!("{:#}", e)),
+        };
+        Self {
+            name: name.to_string(),
+            passed,
+            details,
+        }
+    }
+}
+
+fn sidecar(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn has_sqlite_header(db_path: &Path) -> bool {
+    use std::io::Read;
+    let mut header = [0u8; 16];
+    std::fs::File::open(db_path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| &header == SQLITE_HEADER)
+}
+
+/// Runs SQLite's quick check on a read-only connection.
+pub async fn quick_check(db_path: &Path) -> Result<Vec<String>> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(db_path)
+        .read_only(true)
+        .connect()
+        .await
+        .context("Failed to open database file")?;
+    let results = sqlx::query_scalar::<_, String>("PRAGMA quick_check")
+        .fetch_all(&mut conn)
+        .await
+        .context("Failed to check database file")?;
+    conn.close().await.ok();
+
+    Ok(results.into_iter().filter(|r| r != "ok").collect())
+}
+
+pub async fn check_database_file(db_path: &Path) -> Result<DatabaseFileHealth> {
+    let metadata = tokio::fs::metadata(db_path).await.ok();
+    let wal_bytes = tokio::fs::metadata(sidecar(db_path, "-wal"))
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0);
+    let sqlite_header = has_sqlite_header(db_path);
+
+    let problems = if metadata.is_none() {
+        vec!["The database file does not exist".to_string()]
+    } else if !sqlite_header {
+        vec!["The file is not an SQLite database".to_string()]
+    } else {
+        quick_check(db_path).await.unwrap_or_else(|e| vec![format!("{:#}", e)])
+    };
+
+    Ok(DatabaseFileHealth {
+        path: db_path.display().to_string(),
+        exists: metadata.is_some(),
+        size_bytes: metadata.map(|m| m.len()).unwrap_or(0),
+        wal_bytes,
+        sqlite_header,
+        problems,
+        snapshots: database::list_snapshots_in(&database::snapshots_dir_for(db_path)).await?,
+    })
+}
+
+/// Tells a damaged file from a failing migration: the migrations run on a
+/// copy next to the database, which is removed afterwards.
+pub async fn run_self_test(db_path: &Path) -> Vec<SelfTestCheck> {
+    let mut checks = Vec::new();
+
+    let directory = db_path.parent().unwrap_or_else(|| Path::new("."));
+    checks.push(SelfTestCheck::new("data_directory", {
+        let probe = directory.join(format!(".selftest-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&probe, b"probe")
+            .and_then(|_| std::fs::remove_file(&probe))
+            .map(|_| format!("{} is writable", directory.display()))
+            .with_context(|| format!("{} is not writable", directory.display()))
+    }));
+
+    let crypto = CryptoManager::new().map(Arc::new);
+    checks.push(SelfTestCheck::new(
+        "secrets",
+        crypto.as_ref().map(|_| "Device keys loaded".to_string()).map_err(|e| anyhow::anyhow!("{:#}", e)),
+    ));
+
+    let health = check_database_file(db_path).await;
+    checks.push(SelfTestCheck::new(
+        "database_file",
+        health.and_then(|health| match health.problems.first() {
+            None => Ok(format!("{} bytes, integrity ok", health.size_bytes)),
+            Some(_) => Err(anyhow::anyhow!(health.problems.join("; "))),
+        }),
+    ));
+
+    if let Ok(crypto) = crypto {
+        checks.push(SelfTestCheck::new("migration", migrate_copy(db_path, crypto).await));
+    }
+    checks
+}
+
+async fn migrate_copy(db_path: &Path, crypto: Arc<CryptoManager>) -> Result<String> {
+    let copy = db_path.with_extension(format!("selftest-{}", uuid::Uuid::new_v4().simple()));
+    tokio::fs::copy(db_path, &copy).await.context("Failed to copy database")?;
+    if tokio::fs::try_exists(sidecar(db_path, "-wal")).await.unwrap_or(false) {
+        tokio::fs::copy(sidecar(db_path, "-wal"), sidecar(&copy, "-wal")).await.ok();
+    }
+
+    let result = match Database::new(&copy, crypto).await {
+        Ok(db) => {
+            db.close().await;
+            Ok("Migrations succeed on a copy of the database".to_string())
+        }
+        Err(e) => Err(e),
+    };
+    for path in [sidecar(&copy, "-wal"), sidecar(&copy, "-shm"), copy] {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    result
+}
+
+/// Sets the database that failed to open aside, kept with its WAL for later
+/// analysis, and puts a snapshot in its place. Without a snapshot the app
+/// starts with an empty database, into which a full backup can be restored.
+/// Returns where the old file was moved.
+pub async fn restore_backup(db_path: &Path, snapshot_id: Option<&str>) -> Result<PathBuf> {
+    // Only ids from the listing are accepted, never arbitrary paths
+    let staged = match snapshot_id {
+        Some(snapshot_id) => {
+            let snapshots_dir = database::snapshots_dir_for(db_path);
+            let snapshot = database::list_snapshots_in(&snapshots_dir)
+                .await?
+                .into_iter()
+                .find(|s| s.id == snapshot_id)
+                .context("Snapshot not found")?;
+            let staged = db_path.with_extension("restore");
+            tokio::fs::copy(snapshots_dir.join(format!("{}.db", snapshot.id)), &staged)
+                .await
+                .context("Failed to stage snapshot")?;
+            Some(staged)
+        }
+        None => None,
+    };
+
+    let set_aside = db_path.with_extension(format!("broken-{}", chrono::Utc::now().format("%Y%m%d-%H%M%S")));
+    if tokio::fs::try_exists(db_path).await.unwrap_or(false) {
+        tokio::fs::rename(db_path, &set_aside)
+            .await
+            .context("Failed to move the damaged database aside")?;
+    }
+    for suffix in ["-wal", "-shm"] {
+        let _ = tokio::fs::rename(sidecar(db_path, suffix), sidecar(&set_aside, suffix)).await;
+    }
+
+    if let Some(staged) = staged {
+        tokio::fs::rename(&staged, db_path)
+            .await
+            .context("Failed to put the snapshot in place")?;
+    }
+    Ok(set_aside)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_damaged_database_is_diagnosed_and_restored() {
+        let temp_dir = TempDir::new().unwrap();
+        let crypto = Arc::new(CryptoManager::new().unwrap());
+        let db = Database::new(&temp_dir.path().join("good.db"), crypto.clone()).await.unwrap();
+        db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let snapshot = db.create_snapshot("Before the holidays").await.unwrap();
+        db.close().await;
+
+        let broken = temp_dir.path().join("test.db");
+        std::fs::write(&broken, b"not a database, just noise").unwrap();
+
+        let health = check_database_file(&broken).await.unwrap();
+        assert!(health.exists);
+        assert!(!health.sqlite_header);
+        assert!(!health.problems.is_empty());
+        assert_eq!(health.snapshots.len(), 1);
+
+        let checks = run_self_test(&broken).await;
+        assert!(checks.iter().find(|c| c.name == "data_directory").unwrap().passed);
+        assert!(!checks.iter().find(|c| c.name == "migration").unwrap().passed);
+        // The copy the migrations ran on is gone
+        assert!(!std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .any(|e| e.unwrap().file_name().to_string_lossy().contains("selftest")));
+
+        assert!(restore_backup(&broken, Some("../good")).await.is_err());
+        let set_aside = restore_backup(&broken, Some(&snapshot.id)).await.unwrap();
+        assert_eq!(std::fs::read(&set_aside).unwrap(), b"not a database, just noise");
+        let restored = Database::new(&broken, crypto).await.unwrap();
+        assert_eq!(restored.get_classes().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// Bump whenever `Database::migrate` changes the layout of exported tables.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TableSummary {
+    pub count: u64,
+    pub sha256: String,
+}
+
+/// Describes the tables of an export file so the importing device can tell
+/// a truncated or edited file from a complete one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub app_version: String,
+    pub schema_version: u32,
+    pub tables: BTreeMap<String, TableSummary>,
+}
+
+/// Counts and hashes the records of one table. Each record is hashed in its
+/// compact JSON form, so the same records give the same hash whether they
+/// are read from a `Value` or streamed from a file.
+#[derive(Default)]
+pub struct TableHasher {
+    count: u64,
+    hasher: Sha256,
+}
+
+impl TableHasher {
+    pub fn add(&mut self, record: &Value) {
+        self.count += 1;
+        self.hasher.update(record.to_string().as_bytes());
+        self.hasher.update(b"\n");
+    }
+
+    pub fn finish(self) -> TableSummary {
+        TableSummary {
+            count: self.count,
+            sha256: format!("{:x}", self.hasher.finalize()),
+        }
+    }
+}
+
+impl ExportManifest {
+    /// Builds the manifest for a data section whose entries are lists of records.
+    pub fn describe(data: &Value) -> Self {
+        Self {
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_version: SCHEMA_VERSION,
+            tables: summarize_tables(data),
+        }
+    }
+
+    /// Compares the manifest with what was actually found in the file. Tables
+    /// this version does not read are not part of `found` and are skipped.
+    pub fn verify(&self, found: &BTreeMap<String, TableSummary>) -> Result<()> {
+        if self.schema_version > SCHEMA_VERSION {
+            return Err(anyhow::anyhow!(
+                "File was exported by app version {} with schema version {}; this app only supports schema version {}",
+                self.app_version,
+                self.schema_version,
+                SCHEMA_VERSION
+            ));
+        }
+
+        let mut mismatches = Vec::new();
+        for (table, expected) in &self.tables {
+            let Some(actual) = found.get(table) else { continue };
+            if actual.count != expected.count {
+                mismatches.push(format!("{}: expected {}, found {}", table, expected.count, actual.count));
+            } else if actual.sha256 != expected.sha256 {
+                mismatches.push(format!("{}: content differs from the manifest", table));
+            }
+        }
+        for (table, actual) in found {
+            if actual.count > 0 && !self.tables.contains_key(table) {
+                mismatches.push(format!("{}: expected 0, found {}", table, actual.count));
+            }
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Export manifest mismatch: {}", mismatches.join("; ")))
+        }
+    }
+}
+
+/// Summaries of every list in `data`, e.g. the `data` section of a full export.
+pub fn summarize_tables(data: &Value) -> BTreeMap<String, TableSummary> {
+    data.as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(table, records)| {
+            let records = records.as_array()?;
+            let mut hasher = TableHasher::default();
+            records.iter().for_each(|record| hasher.add(record));
+            Some((table.clone(), hasher.finish()))
+        })
+        .collect()
+}
+
+/// Reads an optional manifest, e.g. `parsed.get("manifest")`. Files written
+/// before manifests existed have none and are accepted unverified.
+pub fn verify_optional(manifest: Option<&Value>, data: &Value) -> Result<()> {
+    let Some(manifest) = manifest else { return Ok(()) };
+    let manifest: ExportManifest = serde_json::from_value(manifest.clone())
+        .map_err(|e| anyhow::anyhow!("Invalid export manifest: {}", e))?;
+    manifest.verify(&summarize_tables(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_manifest_reports_precise_mismatches() {
+        let data = json!({
+            "students": [{"id": 1}, {"id": 2}],
+            "classes": [{"id": 1}]
+        });
+        let manifest = ExportManifest::describe(&data);
+        assert_eq!(manifest.tables["students"].count, 2);
+        assert!(manifest.verify(&summarize_tables(&data)).is_ok());
+
+        let truncated = json!({ "students": [{"id": 1}], "classes": [{"id": 1}] });
+        let err = manifest.verify(&summarize_tables(&truncated)).unwrap_err().to_string();
+        assert!(err.contains("students: expected 2, found 1"));
+        assert!(!err.contains("classes"));
+
+        let edited = json!({ "students": [{"id": 1}, {"id": 3}], "classes": [{"id": 1}] });
+        let err = manifest.verify(&summarize_tables(&edited)).unwrap_err().to_string();
+        assert!(err.contains("students: content differs"));
+
+        // Older files without a manifest are accepted
+        assert!(verify_optional(None, &edited).is_ok());
+    }
+}
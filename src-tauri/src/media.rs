@@ -0,0 +1,358 @@
+use anyhow::{Context, Result};
+use image::GenericImageView;
+
+/// Edge lengths (in pixels) thumbnails are rendered at. Requests are rounded up
+/// to the next size so the cache only ever holds these variants.
+pub const THUMBNAIL_SIZES: [u32; 3] = [96, 256, 512];
+
+pub const THUMBNAIL_CONTENT_TYPE: &str = "image/jpeg";
+
+const THUMBNAIL_JPEG_QUALITY: u8 = 80;
+
+/// Longest edge of images stored as attachments; larger pictures are downscaled.
+pub const MAX_ATTACHMENT_IMAGE_EDGE: u32 = 2048;
+
+const ATTACHMENT_JPEG_QUALITY: u8 = 90;
+
+/// Files from disk above this size are not attached; photos are downscaled
+/// anyway and a scanned worksheet of a few pages stays well below.
+pub const MAX_ATTACHMENT_FILE_BYTES: u64 = 25 * 1024 * 1024;
+
+const PDF_CONTENT_TYPE: &str = "application/pdf";
+
+/// Content type of handwritten notes: stroke data captured by stylus input.
+pub const STROKES_CONTENT_TYPE: &str = "application/vnd.schuelerbeobachtung.strokes+json";
+
+const MAX_STROKE_CANVAS_EDGE: u32 = 10_000;
+const MAX_STROKE_POINTS: usize = 200_000;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Stroke {
+    #[serde(default = "default_stroke_color")]
+    pub color: String, // "#rrggbb" or "#rgb"
+    #[serde(default = "default_stroke_width")]
+    pub width: f32,
+    pub points: Vec<[f32; 2]>,
+}
+
+/// A handwritten note in canvas coordinates, as sent by the drawing surface.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StrokeDocument {
+    pub width: u32,
+    pub height: u32,
+    pub strokes: Vec<Stroke>,
+}
+
+fn default_stroke_color() -> String {
+    "#000000".to_string()
+}
+
+fn default_stroke_width() -> f32 {
+    2.0
+}
+
+pub fn is_image(content_type: &str) -> bool {
+    matches!(
+        content_type,
+        "image/png" | "image/jpeg" | "image/jpg" | "image/gif" | "image/webp" | "image/bmp"
+    )
+}
+
+pub fn thumbnail_size_for(requested: u32) -> u32 {
+    THUMBNAIL_SIZES
+        .iter()
+        .copied()
+        .find(|size| *size >= requested)
+        .unwrap_or(THUMBNAIL_SIZES[THUMBNAIL_SIZES.len() - 1])
+}
+
+/// Scales an image to fit a `size`x`size` box, keeping the aspect ratio, and
+/// encodes it as JPEG. Images smaller than the box are not enlarged.
+pub fn render_thumbnail(data: &[u8], size: u32) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(data).context("Unsupported or damaged image")?;
+
+    let (width, height) = image.dimensions();
+    let image = if width > size || height > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    };
+
+    let rgb = image.to_rgb8();
+    let mut output = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut output, THUMBNAIL_JPEG_QUALITY)
+        .encode(&rgb, rgb.width(), rgb.height(), image::ColorType::Rgb8)
+        .context("Failed to encode thumbnail")?;
+
+    Ok(output)
+}
+
+/// Prepares an image for storage as an attachment: it is decoded and re-encoded,
+/// which drops EXIF and other metadata (GPS position, device serials), and
+/// downscaled to `MAX_ATTACHMENT_IMAGE_EDGE`. JPEGs stay JPEG, everything else
+/// becomes PNG. Returns the new bytes and their content type.
+pub fn sanitize_image(data: &[u8]) -> Result<(Vec<u8>, &'static str)> {
+    let format = image::guess_format(data).context("Unsupported or damaged image")?;
+    let image = image::load_from_memory_with_format(data, format)
+        .context("Unsupported or damaged image")?;
+
+    encode_attachment_image(image, format == image::ImageFormat::Jpeg)
+}
+
+/// Same pipeline for raw RGBA pixels, as delivered by the system clipboard.
+pub fn sanitize_rgba(rgba: &[u8], width: u32, height: u32) -> Result<(Vec<u8>, &'static str)> {
+    let buffer = image::RgbaImage::from_raw(width, height, rgba.to_vec())
+        .context("Clipboard image has an unexpected pixel layout")?;
+
+    encode_attachment_image(image::DynamicImage::ImageRgba8(buffer), false)
+}
+
+/// Prepares a file chosen from disk, e.g. a photo of student work: images
+/// go through `sanitize_image`, PDFs are stored as they are. Anything else
+/// is refused, the payload is recognized by its content, not its name.
+pub fn prepare_attachment_file(data: &[u8]) -> Result<(Vec<u8>, &'static str)> {
+    if data.starts_with(b"%PDF-") {
+        return Ok((data.to_vec(), PDF_CONTENT_TYPE));
+    }
+    if image::guess_format(data).is_err() {
+        return Err(anyhow::anyhow!("Only images and PDF files can be attached"));
+    }
+    sanitize_image(data)
+}
+
+fn encode_attachment_image(image: image::DynamicImage, as_jpeg: bool) -> Result<(Vec<u8>, &'static str)> {
+    let (width, height) = image.dimensions();
+    let image = if width > MAX_ATTACHMENT_IMAGE_EDGE || height > MAX_ATTACHMENT_IMAGE_EDGE {
+        image.resize(
+            MAX_ATTACHMENT_IMAGE_EDGE,
+            MAX_ATTACHMENT_IMAGE_EDGE,
+            image::imageops::FilterType::Lanczos3,
+        )
+    } else {
+        image
+    };
+
+    let mut output = Vec::new();
+    if as_jpeg {
+        let rgb = image.to_rgb8();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut output, ATTACHMENT_JPEG_QUALITY)
+            .encode(&rgb, rgb.width(), rgb.height(), image::ColorType::Rgb8)
+            .context("Failed to encode image")?;
+        Ok((output, "image/jpeg"))
+    } else {
+        image
+            .write_to(&mut std::io::Cursor::new(&mut output), image::ImageOutputFormat::Png)
+            .context("Failed to encode image")?;
+        Ok((output, "image/png"))
+    }
+}
+
+/// Parses and validates stroke data so only well-formed notes are stored.
+pub fn parse_strokes(data: &[u8]) -> Result<StrokeDocument> {
+    let document: StrokeDocument =
+        serde_json::from_slice(data).context("Invalid handwritten note data")?;
+
+    if document.width == 0
+        || document.height == 0
+        || document.width > MAX_STROKE_CANVAS_EDGE
+        || document.height > MAX_STROKE_CANVAS_EDGE
+    {
+        return Err(anyhow::anyhow!("Handwritten note canvas size is out of range"));
+    }
+    let total_points: usize = document.strokes.iter().map(|s| s.points.len()).sum();
+    if total_points > MAX_STROKE_POINTS {
+        return Err(anyhow::anyhow!("Handwritten note has too many points"));
+    }
+    for stroke in &document.strokes {
+        parse_hex_color(&stroke.color)?;
+        if !stroke.width.is_finite() || stroke.width <= 0.0 {
+            return Err(anyhow::anyhow!("Invalid stroke width"));
+        }
+        if stroke.points.iter().flatten().any(|c| !c.is_finite()) {
+            return Err(anyhow::anyhow!("Invalid stroke coordinates"));
+        }
+    }
+
+    Ok(document)
+}
+
+fn parse_hex_color(color: &str) -> Result<[u8; 3]> {
+    let hex = color.strip_prefix('#').context("Stroke color must start with #")?;
+    if !hex.is_ascii() {
+        return Err(anyhow::anyhow!("Invalid stroke color"));
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).context("Invalid stroke color");
+
+    match hex.len() {
+        6 => Ok([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+        3 => {
+            let [r, g, b] = [&hex[0..1], &hex[1..2], &hex[2..3]].map(|d| d.repeat(2));
+            Ok([channel(&r)?, channel(&g)?, channel(&b)?])
+        }
+        _ => Err(anyhow::anyhow!("Invalid stroke color")),
+    }
+}
+
+/// Renders a handwritten note as SVG for exports. Colors are re-emitted from
+/// their parsed value, so stored data cannot inject markup.
+pub fn render_strokes_svg(data: &[u8]) -> Result<String> {
+    let document = parse_strokes(data)?;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n<rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>\n",
+        w = document.width,
+        h = document.height
+    );
+    for stroke in &document.strokes {
+        let [r, g, b] = parse_hex_color(&stroke.color)?;
+        let points: Vec<String> = stroke
+            .points
+            .iter()
+            .map(|[x, y]| format!("{:.1},{:.1}", x, y))
+            .collect();
+        svg.push_str(&format!(
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"#{:02x}{:02x}{:02x}\" stroke-width=\"{:.1}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"/>\n",
+            points.join(" "),
+            r,
+            g,
+            b,
+            stroke.width
+        ));
+    }
+    svg.push_str("</svg>\n");
+
+    Ok(svg)
+}
+
+/// Rasterizes a handwritten note to PNG with its longer edge scaled to `edge`.
+pub fn render_strokes_png(data: &[u8], edge: u32) -> Result<Vec<u8>> {
+    let document = parse_strokes(data)?;
+
+    let scale = edge as f32 / document.width.max(document.height) as f32;
+    let width = ((document.width as f32 * scale).round() as u32).max(1);
+    let height = ((document.height as f32 * scale).round() as u32).max(1);
+    let mut canvas = image::RgbImage::from_pixel(width, height, image::Rgb([255, 255, 255]));
+
+    for stroke in &document.strokes {
+        let color = image::Rgb(parse_hex_color(&stroke.color)?);
+        let radius = (stroke.width * scale / 2.0).max(0.5);
+        let points: Vec<(f32, f32)> = stroke.points.iter().map(|[x, y]| (x * scale, y * scale)).collect();
+
+        if let [single] = points.as_slice() {
+            stamp_dot(&mut canvas, *single, radius, color);
+        }
+        for segment in points.windows(2) {
+            let (from, to) = (segment[0], segment[1]);
+            let length = ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2)).sqrt();
+            let steps = (length / (radius * 0.5)).ceil().max(1.0) as u32;
+            for step in 0..=steps {
+                let t = step as f32 / steps as f32;
+                stamp_dot(
+                    &mut canvas,
+                    (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t),
+                    radius,
+                    color,
+                );
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    image::DynamicImage::ImageRgb8(canvas)
+        .write_to(&mut std::io::Cursor::new(&mut output), image::ImageOutputFormat::Png)
+        .context("Failed to encode handwritten note")?;
+
+    Ok(output)
+}
+
+fn stamp_dot(canvas: &mut image::RgbImage, (cx, cy): (f32, f32), radius: f32, color: image::Rgb<u8>) {
+    let min_x = (cx - radius).floor().max(0.0) as u32;
+    let min_y = (cy - radius).floor().max(0.0) as u32;
+    let max_x = ((cx + radius).ceil() as i64).clamp(0, canvas.width() as i64 - 1) as u32;
+    let max_y = ((cy + radius).ceil() as i64).clamp(0, canvas.height() as i64 - 1) as u32;
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            if dx * dx + dy * dy <= radius * radius {
+                canvas.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::RgbaImage::from_pixel(width, height, image::Rgba([200, 30, 30, 255]));
+        let mut data = Vec::new();
+        image::DynamicImage::ImageRgba8(image)
+            .write_to(&mut std::io::Cursor::new(&mut data), image::ImageOutputFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_thumbnail_size_rounding() {
+        assert_eq!(thumbnail_size_for(10), 96);
+        assert_eq!(thumbnail_size_for(200), 256);
+        assert_eq!(thumbnail_size_for(4000), 512);
+    }
+
+    #[test]
+    fn test_render_thumbnail_keeps_aspect_ratio() {
+        let thumbnail = render_thumbnail(&sample_png(1000, 500), 256).unwrap();
+        let decoded = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!(decoded.dimensions(), (256, 128));
+
+        // Small images are re-encoded but not enlarged
+        let thumbnail = render_thumbnail(&sample_png(40, 30), 256).unwrap();
+        assert_eq!(image::load_from_memory(&thumbnail).unwrap().dimensions(), (40, 30));
+
+        assert!(render_thumbnail(b"not an image", 96).is_err());
+    }
+
+    #[test]
+    fn test_render_strokes() {
+        let note = br##"{"width": 200, "height": 100, "strokes": [
+            {"color": "#f00", "width": 4, "points": [[10, 50], [190, 50]]},
+            {"points": [[100, 10]]}
+        ]}"##;
+
+        let svg = render_strokes_svg(note).unwrap();
+        assert!(svg.contains("stroke=\"#ff0000\""));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+
+        let png = image::load_from_memory(&render_strokes_png(note, 100).unwrap()).unwrap().to_rgb8();
+        assert_eq!(png.dimensions(), (100, 50));
+        assert_eq!(png.get_pixel(50, 25), &image::Rgb([255, 0, 0]));
+        assert_eq!(png.get_pixel(50, 45), &image::Rgb([255, 255, 255]));
+
+        assert!(parse_strokes(br#"{"width": 10, "height": 10, "strokes": [{"color": "red\" onload=\"x", "points": []}]}"#).is_err());
+        assert!(parse_strokes(br#"{"width": 0, "height": 10, "strokes": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_sanitize_image_downscales() {
+        let (data, content_type) = sanitize_image(&sample_png(4096, 1024)).unwrap();
+        assert_eq!(content_type, "image/png");
+        let decoded = image::load_from_memory(&data).unwrap();
+        assert_eq!(decoded.dimensions(), (MAX_ATTACHMENT_IMAGE_EDGE, 512));
+
+        let rgba = vec![255u8; 4 * 20 * 10];
+        let (data, _) = sanitize_rgba(&rgba, 20, 10).unwrap();
+        assert_eq!(image::load_from_memory(&data).unwrap().dimensions(), (20, 10));
+        assert!(sanitize_rgba(&rgba, 30, 10).is_err());
+    }
+
+    #[test]
+    fn test_attachment_files_are_recognized_by_content() {
+        let (_, content_type) = prepare_attachment_file(&sample_png(40, 30)).unwrap();
+        assert_eq!(content_type, "image/png");
+        let pdf = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        assert_eq!(prepare_attachment_file(&pdf).unwrap(), (pdf, PDF_CONTENT_TYPE));
+        assert!(prepare_attachment_file(b"MZ\x90\x00 not a picture").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::database::Database;
+use crate::Student;
+use anyhow::Result;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+/// A student whose first or last name may turn up in a classmate's observation.
+pub struct Classmate {
+    pub id: i64,
+    pattern: Regex,
+}
+
+impl Classmate {
+    /// `None` for students without a usable name, who can never be mentioned.
+    pub fn new(student: &Student) -> Result<Option<Self>> {
+        let names: Vec<String> = [&student.first_name, &student.last_name]
+            .iter()
+            .map(|name| name.trim())
+            .filter(|name| name.chars().count() > 1)
+            .map(regex::escape)
+            .collect();
+        if names.is_empty() {
+            return Ok(None);
+        }
+
+        let pattern = format!(r"\b(?:{})\b", names.join("|"));
+        Ok(Some(Self {
+            id: student.id,
+            pattern: RegexBuilder::new(&pattern).case_insensitive(true).build()?,
+        }))
+    }
+}
+
+/// Classmates named in `text` about `student`, each with the first word that
+/// matched. A name the student shares with a classmate is not a mention.
+pub fn find_mentions(text: &str, student: &Student, classmates: &[Classmate]) -> Vec<(i64, String)> {
+    let own_name = |word: &str| {
+        word.to_lowercase() == student.first_name.trim().to_lowercase()
+            || word.to_lowercase() == student.last_name.trim().to_lowercase()
+    };
+
+    classmates
+        .iter()
+        .filter(|classmate| classmate.id != student.id)
+        .filter_map(|classmate| {
+            classmate
+                .pattern
+                .find_iter(text)
+                .map(|m| m.as_str())
+                .find(|word| !own_name(word))
+                .map(|word| (classmate.id, word.to_string()))
+        })
+        .collect()
+}
+
+/// Checks all observations against the current class lists and stores what
+/// changed, so renamed students, transfers and synced notes are covered too.
+/// Returns how many observations got new or different findings.
+pub async fn analyze_observations(db: &Database) -> Result<usize> {
+    let students = db.get_students().await?;
+    let mut classes: HashMap<i64, Vec<Classmate>> = HashMap::new();
+    for student in &students {
+        if let Some(classmate) = Classmate::new(student)? {
+            classes.entry(student.class_id).or_default().push(classmate);
+        }
+    }
+    let students: HashMap<i64, &Student> = students.iter().map(|s| (s.id, s)).collect();
+
+    let mut stored: HashMap<i64, Vec<(i64, String)>> = HashMap::new();
+    for (observation_id, student_id, matched_text) in db.get_stored_mentions().await? {
+        stored.entry(observation_id).or_default().push((student_id, matched_text));
+    }
+
+    let mut changed = 0;
+    for (observation_id, student_id, text) in db.get_observation_texts().await? {
+        let mut mentions = match students.get(&student_id) {
+            Some(student) => find_mentions(
+                &text,
+                student,
+                classes.get(&student.class_id).map(|c| c.as_slice()).unwrap_or_default(),
+            ),
+            // Deleted students' notes are no longer shown to anyone
+            None => Vec::new(),
+        };
+        mentions.sort();
+
+        let mut previous = stored.remove(&observation_id).unwrap_or_default();
+        previous.sort();
+        if mentions != previous {
+            if !mentions.is_empty() {
+                changed += 1;
+            }
+            db.replace_mention_warnings(observation_id, &mentions).await?;
+        }
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_flags_classmates_but_not_other_classes() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let other_class = db.create_class("5b".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let lena = db.create_student(class.id, "Lena".to_string(), "Max".to_string(), None).await.unwrap();
+        db.create_student(other_class.id, "Jörg".to_string(), "Schmidt".to_string(), None).await.unwrap();
+
+        let named = db
+            .create_observation(max.id, 1, "Sozial".to_string(), "Max hat mit lena und Jörg gestritten".to_string(), vec![])
+            .await
+            .unwrap();
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Max arbeitet konzentriert".to_string(), vec![])
+            .await
+            .unwrap();
+
+        assert_eq!(analyze_observations(&db).await.unwrap(), 1);
+        let warnings = db.get_mention_warnings(Some(class.id), 1).await.unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].observation_id, named.id);
+        assert_eq!(warnings[0].mentioned_student_id, lena.id);
+        assert_eq!(warnings[0].matched_text, "lena");
+        assert!(db.get_mention_warnings(Some(other_class.id), 1).await.unwrap().is_empty());
+
+        // Unchanged findings are not reported again
+        assert_eq!(analyze_observations(&db).await.unwrap(), 0);
+        assert_eq!(db.get_mention_warnings(None, 1).await.unwrap().len(), 1);
+
+        db.create_student(class.id, "Jörg".to_string(), "Becker".to_string(), None).await.unwrap();
+        assert_eq!(analyze_observations(&db).await.unwrap(), 1);
+        assert_eq!(db.get_mention_warnings(Some(class.id), 1).await.unwrap().len(), 2);
+
+        // A private note is only flagged to its author
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+        db.set_default_visibility(teacher.id, crate::database::VISIBILITY_PRIVATE).await.unwrap();
+        db.create_observation(max.id, teacher.id, "Sozial".to_string(), "Lena war heute traurig".to_string(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(analyze_observations(&db).await.unwrap(), 1);
+        assert_eq!(db.get_mention_warnings(Some(class.id), 1).await.unwrap().len(), 2);
+        assert_eq!(db.get_mention_warnings(Some(class.id), teacher.id).await.unwrap().len(), 3);
+    }
+}
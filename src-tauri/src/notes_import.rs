@@ -0,0 +1,250 @@
+use crate::database::{Database, NewObservation};
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, TimeZone, Utc};
+use std::collections::BTreeMap;
+
+/// Text and Markdown files above this size are not notes but something else.
+const MAX_NOTES_FILE_BYTES: u64 = 5 * 1024 * 1024;
+
+fn default_date_formats() -> Vec<String> {
+    // Two-digit years first, "%Y" would read "12.09.24" as the year 24
+    ["%d.%m.%y", "%d.%m.%Y", "%Y-%m-%d", "%d/%m/%Y"]
+        .iter()
+        .map(|f| f.to_string())
+        .collect()
+}
+
+/// How an unstructured notes file is cut into observations.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SplittingRules {
+    /// chrono formats a line has to start with to count as a date heading.
+    #[serde(default = "default_date_formats")]
+    pub date_formats: Vec<String>,
+    /// Each paragraph below a heading becomes its own observation.
+    #[serde(default)]
+    pub split_paragraphs: bool,
+    /// Falls back to the first active category when not set.
+    #[serde(default)]
+    pub default_category: Option<String>,
+    /// Keyword (case-insensitive) to category, e.g. "#sozial" to "Sozialverhalten".
+    /// The first keyword found in a note decides.
+    #[serde(default)]
+    pub category_keywords: BTreeMap<String, String>,
+}
+
+impl Default for SplittingRules {
+    fn default() -> Self {
+        Self {
+            date_formats: default_date_formats(),
+            split_paragraphs: false,
+            default_category: None,
+            category_keywords: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ImportedNote {
+    /// `None` for text above the first date heading.
+    pub date: Option<NaiveDate>,
+    pub category: String,
+    pub text: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct NotesImportPreview {
+    pub student_id: i64,
+    pub notes: Vec<ImportedNote>,
+    pub warnings: Vec<String>,
+}
+
+/// A date heading such as "## 12.09.2024", "**2024-09-12:** Mathe" or
+/// "- 12.09.24". Returns the date and the rest of the line.
+fn parse_heading<'a>(line: &'a str, formats: &[String]) -> Option<(NaiveDate, &'a str)> {
+    let stripped = line
+        .trim()
+        .trim_start_matches(['#', '*', '-', '_', ' '])
+        .trim_start();
+    let end = stripped
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '/'))
+        .unwrap_or(stripped.len());
+    let candidate = stripped[..end].trim_end_matches(['.', '-', '/']);
+    let date = formats
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(candidate, format).ok())?;
+    let rest = stripped[end..].trim_start_matches(['*', '_', ':', ' ']).trim();
+    Some((date, rest))
+}
+
+/// Cuts `content` into dated sections; categories are filled in later.
+pub fn split_notes(content: &str, rules: &SplittingRules) -> Vec<(Option<NaiveDate>, String)> {
+    let mut sections: Vec<(Option<NaiveDate>, Vec<&str>)> = vec![(None, Vec::new())];
+    for line in content.lines() {
+        match parse_heading(line, &rules.date_formats) {
+            Some((date, rest)) => {
+                let mut lines = Vec::new();
+                if !rest.is_empty() {
+                    lines.push(rest);
+                }
+                sections.push((Some(date), lines));
+            }
+            None => sections.last_mut().expect("at least one section").1.push(line),
+        }
+    }
+
+    let mut notes = Vec::new();
+    for (date, lines) in sections {
+        let body = lines.join("\n");
+        let parts: Vec<String> = if rules.split_paragraphs {
+            body.split("\n\n").map(|p| p.trim().to_string()).collect()
+        } else {
+            vec![body.trim().to_string()]
+        };
+        notes.extend(parts.into_iter().filter(|p| !p.is_empty()).map(|p| (date, p)));
+    }
+    notes
+}
+
+fn category_for(text: &str, rules: &SplittingRules, default_category: &str) -> String {
+    let lower = text.to_lowercase();
+    rules
+        .category_keywords
+        .iter()
+        .find(|(keyword, _)| lower.contains(&keyword.to_lowercase()))
+        .map(|(_, category)| category.clone())
+        .unwrap_or_else(|| default_category.to_string())
+}
+
+/// What `import_notes_file` would create, without storing anything.
+pub async fn preview_notes_file(
+    db: &Database,
+    student_id: i64,
+    file_path: &str,
+    rules: &SplittingRules,
+) -> Result<NotesImportPreview> {
+    db.get_student(student_id).await?.context("Student not found")?;
+    let size = std::fs::metadata(file_path).context("Notes file not found")?.len();
+    if size > MAX_NOTES_FILE_BYTES {
+        return Err(anyhow::anyhow!("Notes file is larger than 5 MB"));
+    }
+    let content = std::fs::read_to_string(file_path).context("Notes file is not UTF-8 text")?;
+
+    let categories: Vec<String> = db.get_categories().await?.into_iter().map(|c| c.name).collect();
+    let default_category = match &rules.default_category {
+        Some(category) => category.clone(),
+        None => categories.first().cloned().context("No active category to import into")?,
+    };
+
+    let mut warnings = Vec::new();
+    let notes: Vec<ImportedNote> = split_notes(&content, rules)
+        .into_iter()
+        .map(|(date, text)| ImportedNote {
+            date,
+            category: category_for(&text, rules, &default_category),
+            text,
+        })
+        .collect();
+
+    let undated = notes.iter().filter(|n| n.date.is_none()).count();
+    if undated > 0 {
+        warnings.push(format!(
+            "{} Notiz(en) ohne Datumsüberschrift werden mit dem heutigen Datum importiert",
+            undated
+        ));
+    }
+    let mut unknown: Vec<&str> = notes
+        .iter()
+        .map(|n| n.category.as_str())
+        .filter(|c| !categories.iter().any(|known| known == c))
+        .collect();
+    unknown.sort();
+    unknown.dedup();
+    for category in unknown {
+        warnings.push(format!("Kategorie '{}' ist nicht angelegt", category));
+    }
+
+    Ok(NotesImportPreview {
+        student_id,
+        notes,
+        warnings,
+    })
+}
+
+/// Creates one observation per note in a single transaction, dated at noon
+/// UTC of its heading so the day does not shift between time zones.
+pub async fn import_notes(db: &Database, author_id: i64, preview: NotesImportPreview) -> Result<Vec<crate::Observation>> {
+    if preview.notes.is_empty() {
+        return Err(anyhow::anyhow!("No notes found in the file"));
+    }
+    let entries = preview
+        .notes
+        .into_iter()
+        .map(|note| NewObservation {
+            student_id: preview.student_id,
+            category: note.category,
+            text: note.text,
+            tags: vec!["import".to_string()],
+            created_at: note
+                .date
+                .and_then(|date| date.and_hms_opt(12, 0, 0))
+                .map(|at| Utc.from_utc_datetime(&at)),
+            local_only: false,
+            visibility: None,
+            subject: None,
+            reported_by_user_id: None,
+            reported_by: None,
+        })
+        .collect();
+    db.create_observations_batch(author_id, entries).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTES: &str = "Allgemeines zum Schuljahr\n\n## 12.09.2024\nHilft Mitschülern #sozial\n\nRechnet sicher\n\n**2024-09-19:** Fehlt ohne Entschuldigung\n";
+
+    #[test]
+    fn test_split_notes_by_date_headings() {
+        let rules = SplittingRules::default();
+        let notes = split_notes(NOTES, &rules);
+        assert_eq!(notes.len(), 3);
+        assert_eq!(notes[0], (None, "Allgemeines zum Schuljahr".to_string()));
+        assert_eq!(notes[1].0, NaiveDate::from_ymd_opt(2024, 9, 12));
+        assert_eq!(notes[1].1, "Hilft Mitschülern #sozial\n\nRechnet sicher");
+        assert_eq!(notes[2].1, "Fehlt ohne Entschuldigung");
+
+        let rules = SplittingRules {
+            split_paragraphs: true,
+            ..Default::default()
+        };
+        assert_eq!(split_notes(NOTES, &rules).len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_import_notes_file_with_category_mapping() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let file_path = temp_dir.path().join("notizen.md");
+        std::fs::write(&file_path, NOTES).unwrap();
+        let rules = SplittingRules {
+            split_paragraphs: true,
+            default_category: Some("Leistung".to_string()),
+            category_keywords: BTreeMap::from([("#sozial".to_string(), "Sozialverhalten".to_string())]),
+            ..Default::default()
+        };
+
+        let preview = preview_notes_file(&db, student.id, file_path.to_str().unwrap(), &rules).await.unwrap();
+        assert_eq!(preview.notes.len(), 4);
+        assert_eq!(preview.notes[1].category, "Sozialverhalten");
+        assert_eq!(preview.notes[2].category, "Leistung");
+        assert!(preview.warnings.iter().any(|w| w.contains("ohne Datumsüberschrift")));
+        assert!(db.search_observations(None, Some(student.id), None).await.unwrap().is_empty());
+
+        let created = import_notes(&db, 1, preview).await.unwrap();
+        assert_eq!(created.len(), 4);
+        assert_eq!(created[1].created_at.date_naive(), NaiveDate::from_ymd_opt(2024, 9, 12).unwrap());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::database::Database;
+use crate::Student;
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use std::collections::HashSet;
+use tauri_plugin_shell::ShellExt;
+
+pub const OCR_BINARY_SETTING: &str = "ocr.binary";
+pub const OCR_LANGUAGE_SETTING: &str = "ocr.language";
+
+/// Scans above this size are photos of something else, not a note.
+const MAX_SCAN_BYTES: u64 = 25 * 1024 * 1024;
+
+/// Printed on the blank sheets of `reports::render_blank_observation_sheets`;
+/// lines starting with these are form, not note.
+const SHEET_LABELS: [&str; 6] = ["Beobachtungsbogen", "Klasse ", "Datum", "Kategorie", "Beobachtung", "Vertraulich"];
+
+/// The recognized text of one scan with the students it seems to be about.
+/// Nothing is stored as an observation before the user confirmed the match.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ScannedNote {
+    pub file_path: String,
+    pub text: String,
+    pub date: Option<NaiveDate>,
+    pub proposed_student_id: Option<i64>, // Set when exactly one student matches best
+    pub candidate_student_ids: Vec<i64>,  // Every student whose name was found
+}
+
+/// A locally installed OCR command line tool, e.g. Tesseract, and the
+/// language to recognize. Scans never leave the device.
+pub struct OcrEngine {
+    binary: String,
+    language: String,
+}
+
+/// Returns the OCR engine configured in the settings, if any.
+pub async fn configured_engine(db: &Database) -> Result<Option<OcrEngine>> {
+    let Some(binary) = db.get_setting(OCR_BINARY_SETTING).await?.filter(|b| !b.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let language = db
+        .get_setting(OCR_LANGUAGE_SETTING)
+        .await?
+        .unwrap_or_else(|| "deu".to_string());
+    Ok(Some(OcrEngine { binary, language }))
+}
+
+/// Chooses the OCR program and its language; an empty `binary` switches
+/// scanning off. A program name is looked up on the PATH when it runs, a
+/// path has to exist now. `language` uses Tesseract's codes, e.g. "deu+eng".
+pub async fn set_config(db: &Database, binary: &str, language: Option<&str>) -> Result<()> {
+    let binary = binary.trim();
+    if binary.contains(['/', '\\']) {
+        if !std::path::Path::new(binary).is_file() {
+            return Err(anyhow::anyhow!("File not found: {}", binary));
+        }
+    } else if !binary.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        return Err(anyhow::anyhow!("Not a program name: {}", binary));
+    }
+    let language = language.map(str::trim).filter(|l| !l.is_empty()).unwrap_or("deu");
+    if !language.chars().all(|c| c.is_ascii_lowercase() || c == '_' || c == '+') {
+        return Err(anyhow::anyhow!("Not an OCR language code: {}", language));
+    }
+
+    db.set_setting(OCR_BINARY_SETTING, binary).await?;
+    db.set_setting(OCR_LANGUAGE_SETTING, language).await
+}
+
+impl OcrEngine {
+    /// Runs `<binary> <image> stdout -l <language>`, as Tesseract expects,
+    /// through the shell plugin.
+    pub async fn recognize<R: tauri::Runtime>(&self, app: &tauri::AppHandle<R>, image_path: &str) -> Result<String> {
+        let size = std::fs::metadata(image_path)
+            .with_context(|| format!("Failed to read {}", image_path))?
+            .len();
+        if size > MAX_SCAN_BYTES {
+            return Err(anyhow::anyhow!("{} is too large for a scanned note", image_path));
+        }
+
+        let output = app
+            .shell()
+            .command(self.binary.clone())
+            .args(vec![image_path.to_string(), "stdout".to_string(), "-l".to_string(), self.language.clone()])
+            .output()
+            .await
+            .context("Failed to start the OCR program")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "OCR failed for {} ({:?}): {}",
+                image_path,
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '\''))
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// 2 when first and last name appear, 1 for either; names of several
+/// words ("von Berg") need all of them.
+fn name_score(found: &HashSet<String>, student: &Student) -> usize {
+    [&student.first_name, &student.last_name]
+        .iter()
+        .filter(|name| {
+            let parts = words(name);
+            !parts.is_empty() && parts.iter().all(|part| found.contains(part))
+        })
+        .count()
+}
+
+/// The first date in the text, e.g. from the "Datum" line of a sheet.
+fn find_date(text: &str) -> Option<NaiveDate> {
+    let pattern = regex::Regex::new(r"\b(\d{1,2}\.\d{1,2}\.\d{2,4})\b").expect("valid date pattern");
+    pattern.captures_iter(text).find_map(|captures| {
+        let date = &captures[1];
+        // "%Y" reads "12.09.24" as the year 24, so two-digit years fall through to "%y"
+        ["%d.%m.%Y", "%d.%m.%y"]
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(date, format).ok().filter(|d| d.year() >= 2000))
+    })
+}
+
+/// Cleans the recognized text and proposes the student it is about.
+/// `categories` are the labels printed on the sheet.
+pub fn prepare_note(file_path: &str, raw_text: &str, students: &[Student], categories: &[String]) -> ScannedNote {
+    let found = words(raw_text);
+    let scored: Vec<(i64, usize)> = students
+        .iter()
+        .map(|s| (s.id, name_score(&found, s)))
+        .filter(|(_, score)| *score > 0)
+        .collect();
+    let best = scored.iter().map(|(_, score)| *score).max().unwrap_or(0);
+    let mut best_matches = scored.iter().filter(|(_, score)| *score == best);
+    let proposed_student_id = match (best_matches.next(), best_matches.next()) {
+        (Some((id, _)), None) => Some(*id),
+        _ => None,
+    };
+    let proposed = proposed_student_id.and_then(|id| students.iter().find(|s| s.id == id));
+
+    let category_words: HashSet<String> = categories.iter().flat_map(|c| words(c)).collect();
+    let text = raw_text
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            let line_words = words(line);
+            !line.is_empty()
+                && !SHEET_LABELS.iter().any(|label| line.starts_with(label))
+                && !line_words.is_empty()
+                // Checkbox rows read as category names and stray glyphs
+                && !line_words.iter().all(|w| category_words.contains(w) || w.chars().count() < 2)
+                && !proposed.is_some_and(|s| line_words == words(&format!("{} {}", s.first_name, s.last_name)))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    ScannedNote {
+        file_path: file_path.to_string(),
+        date: find_date(raw_text),
+        text,
+        proposed_student_id,
+        candidate_student_ids: scored.into_iter().map(|(id, _)| id).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scanned_sheet_is_matched_and_cleaned() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_student(class.id, "Max".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let students = db.get_students_by_class(class.id).await.unwrap();
+        let categories = vec!["Sozial".to_string(), "Fachlich".to_string()];
+
+        let scan = "Beobachtungsbogen\nMustermann, Max\nKlasse 5a   Datum: 12.09.2024\nKategorie\n[ ] Sozial  [x] Fachlich\n\
+                    Beobachtung\nBaut im Wald eine Brücke\naus Ästen mit Paul.\nVertraulich: Enthält ...";
+        let note = prepare_note("/scans/1.png", scan, &students, &categories);
+        assert_eq!(note.proposed_student_id, Some(max.id));
+        assert_eq!(note.candidate_student_ids.len(), 2);
+        assert_eq!(note.date, NaiveDate::from_ymd_opt(2024, 9, 12));
+        assert_eq!(note.text, "Baut im Wald eine Brücke\naus Ästen mit Paul.");
+
+        // Only the first name: both students named Max are equally likely
+        let note = prepare_note("/scans/2.png", "Max hat geholfen", &students, &categories);
+        assert_eq!(note.proposed_student_id, None);
+        assert_eq!(note.candidate_student_ids.len(), 2);
+
+        assert!(configured_engine(&db).await.unwrap().is_none());
+        assert!(set_config(&db, "tesseract; rm", None).await.is_err());
+        assert!(set_config(&db, "/nowhere/tesseract", None).await.is_err());
+        assert!(set_config(&db, "tesseract", Some("deu eng")).await.is_err());
+        set_config(&db, "tesseract", Some("deu+eng")).await.unwrap();
+        let engine = configured_engine(&db).await.unwrap().unwrap();
+        assert_eq!((engine.binary.as_str(), engine.language.as_str()), ("tesseract", "deu+eng"));
+        set_config(&db, "", None).await.unwrap();
+        assert!(configured_engine(&db).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Steps of the progress an operation reports, see `report_progress`.
+const PROGRESS_STEPS: u32 = 1000;
+
+/// Shared flag a long-running operation polls to find out whether the user
+/// asked to stop it. It also carries how far the operation got, so the loops
+/// that poll it report progress without another parameter. Cloning yields a
+/// handle to the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    progress: Arc<AtomicU32>,
+}
+
+#[derive(Debug)]
+pub struct OperationCancelled;
+
+impl std::fmt::Display for OperationCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation was cancelled")
+    }
+}
+
+impl std::error::Error for OperationCancelled {}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// `done` of `total` steps are finished.
+    pub fn report_progress(&self, done: usize, total: usize) {
+        if total > 0 {
+            let steps = (done.min(total) as u64 * PROGRESS_STEPS as u64 / total as u64) as u32;
+            self.progress.store(steps, Ordering::SeqCst);
+        }
+    }
+
+    /// Between 0 and 1.
+    pub fn progress(&self) -> f64 {
+        f64::from(self.progress.load(Ordering::SeqCst)) / f64::from(PROGRESS_STEPS)
+    }
+
+    /// Returns `OperationCancelled` once cancelled; use with `?` inside loops
+    /// so open transactions are dropped and rolled back.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            return Err(OperationCancelled.into());
+        }
+        Ok(())
+    }
+}
+
+/// Tokens of running operations by the id the frontend chose for them.
+#[derive(Default)]
+pub struct OperationRegistry {
+    tokens: Mutex<HashMap<String, CancellationToken>>,
+}
+
+/// Keeps an operation registered while it runs and unregisters it on drop.
+pub struct OperationGuard<'a> {
+    registry: &'a OperationRegistry,
+    operation_id: Option<String>,
+    pub token: CancellationToken,
+}
+
+impl OperationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Without an id the operation simply cannot be cancelled.
+    pub fn register(&self, operation_id: Option<String>) -> OperationGuard<'_> {
+        let token = CancellationToken::new();
+        if let Some(id) = &operation_id {
+            self.tokens.lock().unwrap().insert(id.clone(), token.clone());
+        }
+        OperationGuard {
+            registry: self,
+            operation_id,
+            token,
+        }
+    }
+
+    /// Returns false if no operation with this id is running.
+    pub fn cancel(&self, operation_id: &str) -> bool {
+        match self.tokens.lock().unwrap().get(operation_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl OperationGuard<'_> {
+    pub fn operation_id(&self) -> Option<&str> {
+        self.operation_id.as_deref()
+    }
+}
+
+impl Drop for OperationGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = &self.operation_id {
+            self.registry.tokens.lock().unwrap().remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_cancels_running_operations_only() {
+        let registry = OperationRegistry::new();
+
+        {
+            let guard = registry.register(Some("export-1".to_string()));
+            assert!(guard.token.check().is_ok());
+            assert!(registry.cancel("export-1"));
+            assert!(guard.token.is_cancelled());
+            assert!(guard.token.check().unwrap_err().is::<OperationCancelled>());
+        }
+
+        let guard = registry.register(None);
+        assert_eq!(guard.token.progress(), 0.0);
+        guard.token.report_progress(1, 4);
+        assert_eq!(guard.token.clone().progress(), 0.25);
+        guard.token.report_progress(9, 4);
+        assert_eq!(guard.token.progress(), 1.0);
+        drop(guard);
+
+        // Finished operations are no longer known
+        assert!(!registry.cancel("export-1"));
+        assert!(!registry.cancel("unknown"));
+    }
+}
@@ -0,0 +1,321 @@
+//! A minimal PDF writer for printable forms and reports: text in the
+//! standard Helvetica fonts, lines and rectangles on A4 pages. Enough for
+//! what the app prints without pulling in a layout engine.
+
+use sha2::{Digest, Sha256};
+
+pub const PAGE_WIDTH: f32 = 595.0;
+pub const PAGE_HEIGHT: f32 = 842.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+/// One page. Coordinates are points from the top left corner, unlike PDF's
+/// own bottom left origin.
+#[derive(Debug, Default)]
+pub struct PdfPage {
+    content: Vec<u8>,
+}
+
+impl PdfPage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, size: f32, font: Font, text: &str) {
+        self.content.extend_from_slice(
+            format!("BT /{} {:.1} Tf {:.2} {:.2} Td (", font.resource(), size, x, PAGE_HEIGHT - y).as_bytes(),
+        );
+        self.content.extend(encode_text(text));
+        self.content.extend_from_slice(b") Tj ET\n");
+    }
+
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, width: f32) {
+        self.content.extend_from_slice(
+            format!(
+                "{:.2} w {:.2} {:.2} m {:.2} {:.2} l S\n",
+                width,
+                x1,
+                PAGE_HEIGHT - y1,
+                x2,
+                PAGE_HEIGHT - y2
+            )
+            .as_bytes(),
+        );
+    }
+
+    /// An outlined rectangle; `y` is its top edge.
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.content.extend_from_slice(
+            format!("0.8 w {:.2} {:.2} {:.2} {:.2} re S\n", x, PAGE_HEIGHT - y - height, width, height).as_bytes(),
+        );
+    }
+}
+
+/// Text in the WinAnsi encoding of the standard fonts, escaped for a PDF
+/// string. Characters the encoding lacks become '?'.
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        let byte = match c {
+            '(' | ')' | '\\' => {
+                encoded.push(b'\\');
+                c as u8
+            }
+            ' '..='~' | '\u{A0}'..='\u{FF}' => c as u32 as u8,
+            '€' => 0x80,
+            '„' => 0x84,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            '\t' | '\n' | '\r' => b' ',
+            _ => b'?',
+        };
+        encoded.push(byte);
+    }
+    encoded
+}
+
+const LINE_HEIGHT: f32 = 14.0;
+const TOP_MARGIN: f32 = 60.0;
+const BOTTOM_MARGIN: f32 = 780.0;
+
+/// Places lines top to bottom and starts a new page when one is full.
+pub struct ReportWriter {
+    pages: Vec<PdfPage>,
+    page: PdfPage,
+    y: f32,
+}
+
+impl ReportWriter {
+    pub fn new() -> Self {
+        Self {
+            pages: Vec::new(),
+            page: PdfPage::new(),
+            y: TOP_MARGIN,
+        }
+    }
+
+    pub fn line(&mut self, x: f32, size: f32, font: Font, text: &str) {
+        if self.y > BOTTOM_MARGIN {
+            self.pages.push(std::mem::take(&mut self.page));
+            self.y = TOP_MARGIN;
+        }
+        self.page.text(x, self.y, size, font, text);
+        self.y += LINE_HEIGHT * size / 10.0;
+    }
+
+    pub fn gap(&mut self) {
+        self.y += LINE_HEIGHT / 2.0;
+    }
+
+    pub fn finish(mut self) -> Vec<PdfPage> {
+        self.pages.push(self.page);
+        self.pages
+    }
+}
+
+impl Default for ReportWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits text into lines of at most `max_chars`, at spaces where possible.
+pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word = word.to_string();
+            while word.chars().count() > max_chars {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                let rest = word.split_off(word.char_indices().nth(max_chars).map(|(i, _)| i).unwrap_or(word.len()));
+                lines.push(std::mem::replace(&mut word, rest));
+            }
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn pdf_date(at: chrono::DateTime<chrono::Utc>) -> String {
+    at.format("D:%Y%m%d%H%M%SZ").to_string()
+}
+
+fn xmp_metadata(title: &str, at: chrono::DateTime<chrono::Utc>) -> Vec<u8> {
+    let title = title.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    let xmp = format!(
+        "<?xpacket begin=\"\u{FEFF}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\">\n\
+         <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n\
+         <xmp:CreateDate>{}</xmp:CreateDate>\n<pdf:Producer>Schuelerbeobachtung</pdf:Producer>\n\
+         </rdf:Description></rdf:RDF></x:xmpmeta>\n<?xpacket end=\"w\"?>",
+        title,
+        at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    );
+    let mut stream = format!("<< /Type /Metadata /Subtype /XML /Length {} >>\nstream\n", xmp.len()).into_bytes();
+    stream.extend_from_slice(xmp.as_bytes());
+    stream.extend_from_slice(b"\nendstream");
+    stream
+}
+
+/// Writes the pages as a PDF document.
+pub fn render(title: &str, pages: &[PdfPage]) -> Vec<u8> {
+    write_document(title, pages, false)
+}
+
+/// Like `render`, with what archives look for besides the pages: XMP
+/// metadata and a document ID. The fonts are still the standard fonts,
+/// referenced but not embedded, so the file does not pass a PDF/A check.
+pub fn render_for_archive(title: &str, pages: &[PdfPage]) -> Vec<u8> {
+    write_document(title, pages, true)
+}
+
+fn write_document(title: &str, pages: &[PdfPage], archival: bool) -> Vec<u8> {
+    let created_at = chrono::Utc::now();
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    // 1: catalog, 2: page tree, 3 and 4: fonts, 5: info, then page and content
+    // pairs and, for archives, the metadata
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 6 + 2 * i).collect();
+    let metadata_id = 6 + 2 * pages.len();
+    objects.push(if archival {
+        format!("<< /Type /Catalog /Pages 2 0 R /Metadata {} 0 R >>", metadata_id).into_bytes()
+    } else {
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec()
+    });
+    objects.push(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+    );
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec());
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec());
+    let mut info = b"<< /Title (".to_vec();
+    info.extend(encode_text(title));
+    info.extend_from_slice(
+        format!(") /Producer (Schuelerbeobachtung) /CreationDate ({}) >>", pdf_date(created_at)).as_bytes(),
+    );
+    objects.push(info);
+
+    for (page, id) in pages.iter().zip(&page_ids) {
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                id + 1
+            )
+            .into_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", page.content.len()).into_bytes();
+        stream.extend_from_slice(&page.content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+    if archival {
+        objects.push(xmp_metadata(title, created_at));
+    }
+
+    let mut pdf = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    let id = if archival {
+        let hash = Sha256::digest(&pdf);
+        let hex: String = hash[..16].iter().map(|b| format!("{:02X}", b)).collect();
+        format!(" /ID [<{}> <{}>]", hex, hex)
+    } else {
+        String::new()
+    };
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R{} >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            id,
+            xref_offset
+        )
+        .as_bytes(),
+    );
+    pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_structure_and_text_encoding() {
+        let mut page = PdfPage::new();
+        page.text(50.0, 60.0, 12.0, Font::Bold, "Müller (5a) – 100 €");
+        page.rect(50.0, 80.0, 10.0, 10.0);
+        page.line(50.0, 120.0, 545.0, 120.0, 0.5);
+        let pdf = render("Test", &[page, PdfPage::new()]);
+
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        let text = b"(M\xFCller \\(5a\\) \x96 100 \x80) Tj";
+        assert!(pdf.windows(text.len()).any(|w| w == text));
+        let as_text = String::from_utf8_lossy(&pdf);
+        assert!(as_text.contains("/Count 2"));
+
+        // Every xref entry points at its object
+        let startxref: usize = as_text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+        let xref = String::from_utf8_lossy(&pdf[startxref..]).to_string();
+        for (index, entry) in xref.lines().skip(3).take(9).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", index + 1).as_bytes()));
+        }
+
+        let archived = String::from_utf8_lossy(&render_for_archive("Akte <Max>", &[PdfPage::new()])).to_string();
+        assert!(archived.contains("/Metadata 8 0 R"));
+        assert!(archived.contains("8 0 obj\n<< /Type /Metadata /Subtype /XML"));
+        assert!(archived.contains("Akte &lt;Max&gt;"));
+        assert!(archived.contains("/ID [<"));
+
+        assert_eq!(wrap_text("Liest flüssig und betont vor", 12), vec!["Liest", "flüssig und", "betont vor"]);
+        assert_eq!(wrap_text("Donaudampfschifffahrt", 10), vec!["Donaudampf", "schifffahr", "t"]);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::database::{Database, MentionWarning};
+use crate::reports::{render_weekly_summary_html, WeeklySummary};
+use crate::{Guardian, Observation, Student};
+use anyhow::Result;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+const DISPLAY_MODE_SETTING: &str = "privacy_display_mode";
+const CONTENT_PROTECTION_SETTING: &str = "content_protection";
+
+pub const DISPLAY_MODES: [&str; 2] = ["full", "pseudonymized"];
+
+pub async fn get_display_mode(db: &Database) -> Result<String> {
+    Ok(db
+        .get_setting(DISPLAY_MODE_SETTING)
+        .await?
+        .filter(|mode| DISPLAY_MODES.contains(&mode.as_str()))
+        .unwrap_or_else(|| "full".to_string()))
+}
+
+pub async fn set_display_mode(db: &Database, mode: &str) -> Result<()> {
+    if !DISPLAY_MODES.contains(&mode) {
+        return Err(anyhow::anyhow!("Display mode must be one of: {}", DISPLAY_MODES.join(", ")));
+    }
+    db.set_setting(DISPLAY_MODE_SETTING, mode).await
+}
+
+/// Whether windows are excluded from screenshots and screen sharing. Off
+/// unless enabled, since some teachers present the app on purpose.
+pub async fn get_content_protection(db: &Database) -> Result<bool> {
+    Ok(db.get_setting(CONTENT_PROTECTION_SETTING).await?.as_deref() == Some("true"))
+}
+
+pub async fn set_content_protection(db: &Database, enabled: bool) -> Result<()> {
+    db.set_setting(CONTENT_PROTECTION_SETTING, if enabled { "true" } else { "false" })
+        .await
+}
+
+fn abbreviate(name: &str, chars: usize) -> String {
+    let short: String = name.trim().chars().take(chars).collect();
+    if short.is_empty() {
+        short
+    } else {
+        format!("{}.", short)
+    }
+}
+
+/// "Max Mustermann" becomes "M. Mu."; for names given in one field the last
+/// word counts as the last name.
+pub fn pseudonym(full_name: &str) -> String {
+    let words: Vec<&str> = full_name.split_whitespace().collect();
+    match words.as_slice() {
+        [] => String::new(),
+        [only] => abbreviate(only, 2),
+        [first, .., last] => format!("{} {}", abbreviate(first, 1), abbreviate(last, 2)),
+    }
+}
+
+/// Shortens student names in everything shown on screen while the full names
+/// stay stored, e.g. when the app is projected in class.
+pub struct Pseudonymizer {
+    pattern: Option<Regex>,
+    replacements: HashMap<String, String>,
+}
+
+impl Pseudonymizer {
+    pub fn new(students: &[Student]) -> Result<Self> {
+        let mut replacements = HashMap::new();
+        // First names win where a word is both, the initial is the shorter leak
+        for student in students {
+            replacements.insert(student.last_name.trim().to_lowercase(), abbreviate(&student.last_name, 2));
+        }
+        for student in students {
+            replacements.insert(student.first_name.trim().to_lowercase(), abbreviate(&student.first_name, 1));
+        }
+        replacements.retain(|name, _| name.chars().count() > 1);
+
+        let mut names: Vec<&String> = replacements.keys().collect();
+        // Longest first, so "Anna-Lena" is not cut short at "Anna"
+        names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+        let pattern = if names.is_empty() {
+            None
+        } else {
+            let alternatives: Vec<String> = names.iter().map(|name| regex::escape(name)).collect();
+            Some(
+                RegexBuilder::new(&format!(r"\b(?:{})\b", alternatives.join("|")))
+                    .case_insensitive(true)
+                    .build()?,
+            )
+        };
+
+        Ok(Self { pattern, replacements })
+    }
+
+    pub fn text(&self, text: &str) -> String {
+        match &self.pattern {
+            Some(pattern) => pattern
+                .replace_all(text, |caps: &regex::Captures| {
+                    self.replacements
+                        .get(&caps[0].to_lowercase())
+                        .cloned()
+                        .unwrap_or_else(|| caps[0].to_string())
+                })
+                .into_owned(),
+            None => text.to_string(),
+        }
+    }
+
+    pub fn students(&self, students: &mut [Student]) {
+        for student in students {
+            student.first_name = abbreviate(&student.first_name, 1);
+            student.last_name = abbreviate(&student.last_name, 2);
+        }
+    }
+
+    pub fn observations(&self, observations: &mut [Observation]) {
+        for observation in observations {
+            observation.text = self.text(&observation.text);
+        }
+    }
+
+    pub fn guardians(&self, guardians: &mut [Guardian]) {
+        for guardian in guardians {
+            guardian.name = pseudonym(&guardian.name);
+        }
+    }
+
+    pub fn mention_warnings(&self, warnings: &mut [MentionWarning]) {
+        for warning in warnings {
+            warning.mentioned_name = pseudonym(&warning.mentioned_name);
+            warning.matched_text = self.text(&warning.matched_text);
+            warning.text = self.text(&warning.text);
+        }
+    }
+
+    /// Stored summaries keep the full names; only the returned copy changes.
+    pub fn weekly_summary(&self, summary: &mut WeeklySummary) {
+        for student in &mut summary.students {
+            student.first_name = abbreviate(&student.first_name, 1);
+            student.last_name = abbreviate(&student.last_name, 2);
+            for group in &mut student.categories {
+                self.observations(&mut group.observations);
+            }
+        }
+        summary.html = render_weekly_summary_html(summary);
+    }
+}
+
+/// `None` unless the pseudonymized display mode is on.
+pub async fn display_pseudonymizer(db: &Database) -> Result<Option<Pseudonymizer>> {
+    if get_display_mode(db).await? != "pseudonymized" {
+        return Ok(None);
+    }
+    Ok(Some(Pseudonymizer::new(&db.get_students().await?)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::CryptoManager;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_pseudonymized_mode_shortens_names_everywhere() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_student(class.id, "Anna-Lena".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Max Mustermann half anna-lena".to_string(), vec![])
+            .await
+            .unwrap();
+
+        assert!(display_pseudonymizer(&db).await.unwrap().is_none());
+        assert!(set_display_mode(&db, "hidden").await.is_err());
+        set_display_mode(&db, "pseudonymized").await.unwrap();
+        let pseudonymizer = display_pseudonymizer(&db).await.unwrap().unwrap();
+
+        let mut students = db.get_students().await.unwrap();
+        pseudonymizer.students(&mut students);
+        assert_eq!(format!("{} {}", students[0].first_name, students[0].last_name), "M. Mu.");
+
+        let mut observations = db.search_observations(None, Some(max.id), None).await.unwrap();
+        pseudonymizer.observations(&mut observations);
+        assert_eq!(observations[0].text, "M. Mu. half A.");
+
+        // Stored data is unchanged
+        assert_eq!(db.get_student(max.id).await.unwrap().unwrap().first_name, "Max");
+        assert_eq!(pseudonym("Erika Maria Mustermann"), "E. Mu.");
+    }
+
+    #[tokio::test]
+    async fn test_content_protection_preference_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let crypto = Arc::new(CryptoManager::new().unwrap());
+        let db = Database::new(&db_path, crypto.clone()).await.unwrap();
+
+        assert!(!get_content_protection(&db).await.unwrap());
+        set_content_protection(&db, true).await.unwrap();
+
+        drop(db);
+        let db = Database::new(&db_path, crypto).await.unwrap();
+        assert!(get_content_protection(&db).await.unwrap());
+    }
+}
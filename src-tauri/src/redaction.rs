@@ -0,0 +1,321 @@
+use crate::database::Database;
+use crate::{AnonymizationTerm, Observation, Student};
+use anyhow::{Context, Result};
+use regex::{NoExpand, Regex, RegexBuilder};
+
+const REDACTION_SETTING: &str = "redaction_rules";
+const ANONYMIZED: &str = "[anonymisiert]";
+
+/// A term or regular expression removed from observation texts in exports
+/// that leave the school, e.g. to parents or a school psychologist.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RedactionRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub is_regex: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RedactionSettings {
+    #[serde(default)]
+    pub rules: Vec<RedactionRule>,
+    /// Also removes first and last names of every other student.
+    #[serde(default = "default_true")]
+    pub redact_other_students: bool,
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_replacement() -> String {
+    "[geschwärzt]".to_string()
+}
+
+impl Default for RedactionSettings {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            redact_other_students: true,
+            replacement: default_replacement(),
+        }
+    }
+}
+
+/// One identifying string the anonymization looks for.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DictionaryEntry {
+    pub term: String,
+    pub student_id: Option<i64>,
+    pub term_id: Option<i64>, // None for names, which follow the student record
+    pub note: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RedactionPreview {
+    pub observation_id: i64,
+    pub original: String,
+    pub redacted: String,
+    pub replacements: usize,
+}
+
+pub struct Redactor {
+    patterns: Vec<Regex>,
+    replacement: String,
+}
+
+impl Redactor {
+    /// `other_names` are matched case-insensitively as whole words.
+    pub fn new(settings: &RedactionSettings, other_names: &[String]) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for rule in &settings.rules {
+            patterns.push(compile_rule(rule)?);
+        }
+
+        let names: Vec<String> = other_names
+            .iter()
+            .map(|name| name.trim())
+            .filter(|name| name.chars().count() > 1)
+            .map(regex::escape)
+            .collect();
+        if !names.is_empty() {
+            let pattern = format!(r"\b(?:{})\b", names.join("|"));
+            patterns.push(RegexBuilder::new(&pattern).case_insensitive(true).build()?);
+        }
+
+        Ok(Self {
+            patterns,
+            replacement: settings.replacement.clone(),
+        })
+    }
+
+    /// Returns the redacted text and how many passages were replaced.
+    pub fn redact(&self, text: &str) -> (String, usize) {
+        let mut text = text.to_string();
+        let mut replacements = 0;
+        for pattern in &self.patterns {
+            replacements += pattern.find_iter(&text).count();
+            text = pattern.replace_all(&text, NoExpand(&self.replacement)).into_owned();
+        }
+        (text, replacements)
+    }
+}
+
+fn compile_rule(rule: &RedactionRule) -> Result<Regex> {
+    let pattern = if rule.is_regex {
+        rule.pattern.clone()
+    } else {
+        format!(r"\b{}\b", regex::escape(rule.pattern.trim()))
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(true)
+        .build()
+        .with_context(|| format!("Invalid redaction pattern '{}'", rule.pattern))
+}
+
+pub async fn load_settings(db: &Database) -> Result<RedactionSettings> {
+    Ok(db
+        .get_setting(REDACTION_SETTING)
+        .await?
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default())
+}
+
+/// Rejects rules that do not compile, so exports never fail on stored settings.
+pub async fn save_settings(db: &Database, settings: &RedactionSettings) -> Result<()> {
+    for rule in &settings.rules {
+        if rule.pattern.trim().is_empty() {
+            return Err(anyhow::anyhow!("Redaction patterns must not be empty"));
+        }
+        compile_rule(rule)?;
+    }
+    db.set_setting(REDACTION_SETTING, &serde_json::to_string(settings)?).await
+}
+
+/// Redactor for texts about `student`: every other student's name and
+/// nicknames are removed, as are dictionary terms about nobody in particular.
+pub async fn redactor_for_student(db: &Database, student_id: i64) -> Result<Redactor> {
+    let settings = load_settings(db).await?;
+    let mut other_names = if settings.redact_other_students {
+        other_student_names(&db.get_students().await?, student_id)
+    } else {
+        Vec::new()
+    };
+    other_names.extend(other_dictionary_terms(
+        &db.get_anonymization_terms().await?,
+        student_id,
+        settings.redact_other_students,
+    ));
+    Redactor::new(&settings, &other_names)
+}
+
+fn other_dictionary_terms(terms: &[AnonymizationTerm], student_id: i64, redact_other_students: bool) -> Vec<String> {
+    terms
+        .iter()
+        .filter(|t| match t.student_id {
+            None => true,
+            Some(id) => id != student_id && redact_other_students,
+        })
+        .map(|t| t.term.clone())
+        .collect()
+}
+
+/// Names of all students and the custom terms, sorted by term.
+pub async fn anonymization_dictionary(db: &Database) -> Result<Vec<DictionaryEntry>> {
+    let mut entries: Vec<DictionaryEntry> = db
+        .get_students()
+        .await?
+        .into_iter()
+        .flat_map(|s| {
+            [s.first_name, s.last_name].map(|term| DictionaryEntry {
+                term,
+                student_id: Some(s.id),
+                term_id: None,
+                note: None,
+            })
+        })
+        .filter(|e| !e.term.trim().is_empty())
+        .collect();
+    entries.extend(db.get_anonymization_terms().await?.into_iter().map(|t| DictionaryEntry {
+        term: t.term,
+        student_id: t.student_id,
+        term_id: Some(t.id),
+        note: t.note,
+    }));
+    entries.sort_by_key(|e| (e.term.to_lowercase(), e.term_id));
+    Ok(entries)
+}
+
+/// Replaces everything in the dictionary and the redaction rules, whoever
+/// the text is about.
+pub async fn anonymizer(db: &Database) -> Result<Redactor> {
+    let settings = RedactionSettings {
+        replacement: ANONYMIZED.to_string(),
+        ..load_settings(db).await?
+    };
+    let terms: Vec<String> = anonymization_dictionary(db).await?.into_iter().map(|e| e.term).collect();
+    Redactor::new(&settings, &terms)
+}
+
+fn other_student_names(students: &[Student], student_id: i64) -> Vec<String> {
+    let own = students.iter().find(|s| s.id == student_id);
+    let mut names: Vec<String> = students
+        .iter()
+        .filter(|s| s.id != student_id)
+        .flat_map(|s| [s.first_name.clone(), s.last_name.clone()])
+        // A classmate sharing the student's own name must not blank the student out
+        .filter(|name| {
+            own.map_or(true, |own| {
+                !name.eq_ignore_ascii_case(&own.first_name) && !name.eq_ignore_ascii_case(&own.last_name)
+            })
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn redact_observations(redactor: &Redactor, observations: &mut [Observation]) {
+    for observation in observations {
+        observation.text = redactor.redact(&observation.text).0;
+    }
+}
+
+pub async fn preview_redaction(db: &Database, observation_id: i64, viewer_id: i64) -> Result<RedactionPreview> {
+    let observation = db
+        .get_observation(observation_id)
+        .await?
+        .filter(|o| o.is_visible_to(viewer_id))
+        .context("Observation not found")?;
+    let redactor = redactor_for_student(db, observation.student_id).await?;
+    let (redacted, replacements) = redactor.redact(&observation.text);
+
+    Ok(RedactionPreview {
+        observation_id,
+        original: observation.text,
+        redacted,
+        replacements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_preview_removes_other_students_and_rules() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_student(class.id, "Jörg".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let observation = db
+            .create_observation(max.id, 1, "Sozial".to_string(), "Max hat mit jörg gestritten, Therapie bei Dr. Weber".to_string(), vec![])
+            .await
+            .unwrap();
+
+        let settings = RedactionSettings {
+            rules: vec![
+                RedactionRule { pattern: "Therapie".to_string(), is_regex: false },
+                RedactionRule { pattern: r"Dr\. \w+".to_string(), is_regex: true },
+            ],
+            ..Default::default()
+        };
+        save_settings(&db, &settings).await.unwrap();
+        assert!(save_settings(&db, &RedactionSettings {
+            rules: vec![RedactionRule { pattern: "(".to_string(), is_regex: true }],
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        let preview = preview_redaction(&db, observation.id, 1).await.unwrap();
+        assert_eq!(preview.redacted, "Max hat mit [geschwärzt] gestritten, [geschwärzt] bei [geschwärzt]");
+        assert_eq!(preview.replacements, 3);
+
+        // Only the author previews a private observation
+        let teacher = db.create_user("Frau Schmidt".to_string(), "teacher".to_string()).await.unwrap();
+        db.set_observation_visibility(observation.id, 1, crate::database::VISIBILITY_PRIVATE).await.unwrap();
+        assert!(preview_redaction(&db, observation.id, teacher.id).await.is_err());
+        assert!(preview_redaction(&db, observation.id, 1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_dictionary_terms_are_redacted_and_anonymized() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let joerg = db.create_student(class.id, "Jörg".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let term = |term: &str, student_id: Option<i64>| crate::database::AnonymizationTermInput {
+            term: term.to_string(),
+            student_id,
+            note: None,
+        };
+        db.create_anonymization_term(&term("Maxi", Some(max.id))).await.unwrap();
+        db.create_anonymization_term(&term("Schmiddi", Some(joerg.id))).await.unwrap();
+        db.create_anonymization_term(&term("Hof Lindenau", None)).await.unwrap();
+        assert!(db.create_anonymization_term(&term("maxi", Some(max.id))).await.is_err());
+        assert!(db.create_anonymization_term(&term("M", None)).await.is_err());
+
+        let observation = db
+            .create_observation(max.id, 1, "Sozial".to_string(), "Maxi und Schmiddi spielen am Hof Lindenau".to_string(), vec![])
+            .await
+            .unwrap();
+        // The student's own nickname stays in their file
+        let preview = preview_redaction(&db, observation.id, 1).await.unwrap();
+        assert_eq!(preview.redacted, "Maxi und [geschwärzt] spielen am [geschwärzt]");
+
+        let (anonymized, _) = anonymizer(&db).await.unwrap().redact(&observation.text);
+        assert_eq!(anonymized, "[anonymisiert] und [anonymisiert] spielen am [anonymisiert]");
+        let dictionary = anonymization_dictionary(&db).await.unwrap();
+        assert_eq!(dictionary.len(), 7);
+        assert!(dictionary.iter().any(|e| e.term == "Mustermann" && e.term_id.is_none()));
+
+        // Nicknames go with the student
+        db.delete_student(joerg.id, true).await.unwrap();
+        assert_eq!(db.get_anonymization_terms().await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::database::Database;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+const FORMAT_SETTING: &str = "reference_code_format";
+pub const DEFAULT_FORMAT: &str = "{prefix}-{year}-{number}";
+
+const PLACEHOLDERS: [&str; 3] = ["prefix", "year", "number"];
+
+/// Entities that get reference codes, with the prefix of their codes.
+pub const ENTITY_PREFIXES: [(&str, &str); 3] = [("student", "ST"), ("class", "KL"), ("observation", "BE")];
+
+pub fn prefix(entity_type: &str) -> Result<&'static str> {
+    ENTITY_PREFIXES
+        .iter()
+        .find(|(entity, _)| *entity == entity_type)
+        .map(|(_, prefix)| *prefix)
+        .ok_or_else(|| anyhow::anyhow!("No reference codes for {}", entity_type))
+}
+
+pub async fn get_format(db: &Database) -> Result<String> {
+    Ok(db
+        .get_setting(FORMAT_SETTING)
+        .await?
+        .unwrap_or_else(|| DEFAULT_FORMAT.to_string()))
+}
+
+/// Applies to codes assigned afterwards; codes already printed in reports
+/// keep their meaning.
+pub async fn set_format(db: &Database, format: &str) -> Result<()> {
+    validate_format(format)?;
+    db.set_setting(FORMAT_SETTING, format.trim()).await
+}
+
+/// Numbers start again every year, so all three placeholders are needed to
+/// keep codes unique.
+pub fn validate_format(format: &str) -> Result<()> {
+    let format = format.trim();
+    let mut found = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in reference code format"))?;
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(anyhow::anyhow!(
+                "Unknown placeholder {{{}}}; available are {}",
+                name,
+                PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+            ));
+        }
+        found.push(name);
+        rest = &rest[start + end + 1..];
+    }
+    if let Some(missing) = PLACEHOLDERS.iter().find(|p| !found.contains(*p)) {
+        return Err(anyhow::anyhow!("The reference code format needs {{{}}}", missing));
+    }
+    Ok(())
+}
+
+/// The number is padded to four digits, e.g. `ST-2024-0031`.
+pub fn render(format: &str, prefix: &str, year: i32, number: i64) -> String {
+    format
+        .trim()
+        .replace("{prefix}", prefix)
+        .replace("{year}", &year.to_string())
+        .replace("{number}", &format!("{:04}", number))
+}
+
+/// Codes of the given entities by id, assigning codes to those that have
+/// none yet. Codes are numbered per year of creation and travel in
+/// changesets, so a record keeps its code on every device; the ids stay what
+/// identifies records in sync.
+pub async fn codes_for(db: &Database, entity_type: &str, ids: &[i64]) -> Result<BTreeMap<i64, String>> {
+    assign(db, entity_type, ids).await?;
+    db.get_reference_codes(entity_type, ids).await
+}
+
+/// Assigns the missing codes only, for exports that read the codes through
+/// the read-only handle afterwards.
+pub async fn assign(db: &Database, entity_type: &str, ids: &[i64]) -> Result<()> {
+    db.assign_reference_codes(entity_type, ids, &get_format(db).await?).await?;
+    Ok(())
+}
+
+/// The code of one entity, or its id for entities without codes.
+pub async fn code_for(db: &Database, entity_type: &str, id: i64) -> Result<String> {
+    if prefix(entity_type).is_err() {
+        return Ok(id.to_string());
+    }
+    Ok(codes_for(db, entity_type, &[id])
+        .await?
+        .remove(&id)
+        .unwrap_or_else(|| id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reference_codes_are_numbered_per_entity_and_year() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Albrecht".to_string(), None).await.unwrap();
+        let year = max.created_at.format("%Y").to_string();
+
+        let codes = codes_for(&db, "student", &[max.id, anna.id]).await.unwrap();
+        assert_eq!(codes[&max.id], format!("ST-{}-0001", year));
+        assert_eq!(codes[&anna.id], format!("ST-{}-0002", year));
+        assert_eq!(code_for(&db, "class", class.id).await.unwrap(), format!("KL-{}-0001", year));
+
+        // A new format applies to new codes only
+        assert!(set_format(&db, "{year}-{number}").await.is_err());
+        assert!(set_format(&db, "{prefix}-{year}-{nummer}").await.is_err());
+        set_format(&db, "{prefix}{year}/{number}").await.unwrap();
+        let erika = db.create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), None).await.unwrap();
+        assert_eq!(code_for(&db, "student", erika.id).await.unwrap(), format!("ST{}/0003", year));
+        assert_eq!(code_for(&db, "student", max.id).await.unwrap(), format!("ST-{}-0001", year));
+
+        // Numbers of deleted students are not given out again
+        db.delete_student(erika.id, true).await.unwrap();
+        let leon = db.create_student(class.id, "Leon".to_string(), "Lehmann".to_string(), None).await.unwrap();
+        assert_eq!(code_for(&db, "student", leon.id).await.unwrap(), format!("ST{}/0004", year));
+
+        assert_eq!(code_for(&db, "guardian", 7).await.unwrap(), "7");
+        assert_eq!(render(DEFAULT_FORMAT, "ST", 2024, 31), "ST-2024-0031");
+    }
+}
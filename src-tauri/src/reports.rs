@@ -0,0 +1,628 @@
+use crate::archive::csv_field;
+use crate::database::{Database, MetricPoint, RubricAggregate, RubricAggregateFilter};
+use crate::pdf::{self, Font, PdfPage};
+use crate::{Observation, Student};
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use std::collections::BTreeMap;
+
+pub struct ReportGenerator;
+
+#[derive(Debug, serde::Serialize)]
+pub struct CategoryGroup {
+    pub category: String,
+    pub observations: Vec<Observation>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct StudentWeekSummary {
+    pub student_id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub categories: Vec<CategoryGroup>,
+    pub rubrics: Vec<RubricAggregate>, // Scores from this week's observations
+}
+
+/// How reports show the languages observations are written in, for
+/// bilingual schools.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LanguageDisplay {
+    #[default]
+    None,
+    Label, // Each observation is marked with its language
+    Group, // Observations of a category are grouped by language
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct WeeklySummary {
+    pub class_id: i64,
+    pub class_name: String,
+    pub school_year: String,
+    pub week_start: NaiveDate,
+    pub week_end: NaiveDate,
+    pub total_observations: usize,
+    pub students: Vec<StudentWeekSummary>,
+    pub signatories: BTreeMap<i64, String>, // Names of authors and co-signers of co-signed entries, and of reporting colleagues
+    pub languages: LanguageDisplay,
+    pub html: String,
+    pub artifact_id: Option<i64>,
+}
+
+impl ReportGenerator {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Only reads from `db`, so it can run on `Database::reader`.
+    pub async fn generate_weekly_summary(
+        &self,
+        db: &Database,
+        class_id: i64,
+        week: &str,
+        viewer_id: i64,
+        languages: LanguageDisplay,
+    ) -> Result<WeeklySummary> {
+        let class = db.get_class(class_id).await?.context("Class not found")?;
+
+        let week_start = parse_week(week)?;
+        let week_end = week_start + Duration::days(6);
+        let from = Utc.from_utc_datetime(&week_start.and_hms_opt(0, 0, 0).unwrap());
+        let to = from + Duration::days(7);
+
+        let mut students = db.get_students_by_class(class_id).await?;
+        let mut observations = db.get_class_observations_between(class_id, from, to).await?;
+        observations.retain(|o| o.is_visible_to(viewer_id));
+        // The reader cannot store detections for observations that came in by sync
+        for observation in observations.iter_mut().filter(|o| o.language.is_none()) {
+            observation.language = Some(crate::language::detect(&observation.text).to_string());
+        }
+        // Notes synced in under a category merged since then
+        let merges = db.get_category_merges().await?;
+        for observation in observations.iter_mut() {
+            if let Some(target) = merges.get(&observation.category) {
+                observation.category = target.clone();
+            }
+        }
+        let total_observations = observations.len();
+
+        let users: BTreeMap<i64, String> = db.get_users().await?.into_iter().map(|u| (u.id, u.name)).collect();
+        let signatories = observations
+            .iter()
+            .filter_map(|o| Some([o.author_id, o.cosigned_by?]))
+            .flatten()
+            .chain(observations.iter().filter_map(|o| o.reported_by_user_id))
+            .map(|id| (id, users.get(&id).cloned().unwrap_or_else(|| format!("Benutzer {}", id))))
+            .collect();
+
+        // Students transferred out since then still appear with their notes from this class
+        let mut missing: Vec<i64> = observations
+            .iter()
+            .map(|o| o.student_id)
+            .filter(|id| !students.iter().any(|s| s.id == *id))
+            .collect();
+        missing.sort();
+        missing.dedup();
+        for student_id in missing {
+            if let Some(student) = db.get_student(student_id).await? {
+                students.push(student);
+            }
+        }
+
+        let mut students = group_by_student(students, observations);
+        for student in &mut students {
+            let filter = RubricAggregateFilter {
+                student_id: Some(student.student_id),
+                class_id: Some(class_id),
+                from: Some(from),
+                to: Some(to),
+                ..Default::default()
+            };
+            student.rubrics = db.get_rubric_aggregates(&filter, viewer_id).await?;
+        }
+
+        let mut summary = WeeklySummary {
+            class_id,
+            class_name: class.name,
+            school_year: class.school_year,
+            week_start,
+            week_end,
+            total_observations,
+            students,
+            signatories,
+            languages,
+            html: String::new(),
+            artifact_id: None,
+        };
+        summary.html = render_weekly_summary_html(&summary);
+
+        Ok(summary)
+    }
+
+    /// Keeps the rendered summary as a report artifact for later reference.
+    pub async fn store_weekly_summary(&self, db: &Database, summary: &mut WeeklySummary) -> Result<()> {
+        let artifact = db
+            .store_report_artifact(
+                summary.class_id,
+                "weekly_summary",
+                &summary.week_start.to_string(),
+                &summary.week_end.to_string(),
+                "html",
+                summary.html.as_bytes(),
+            )
+            .await?;
+        summary.artifact_id = Some(artifact.id);
+
+        Ok(())
+    }
+}
+
+impl Default for ReportGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Accepts an ISO week ("2024-W37") or any date within the week ("2024-09-11")
+/// and returns the Monday the week starts on.
+pub fn parse_week(week: &str) -> Result<NaiveDate> {
+    let week = week.trim();
+
+    if let Some((year, week_no)) = week.split_once("-W") {
+        let year: i32 = year.parse().context("Invalid year in week")?;
+        let week_no: u32 = week_no.parse().context("Invalid week number")?;
+        return NaiveDate::from_isoywd_opt(year, week_no, Weekday::Mon)
+            .context("Week number out of range");
+    }
+
+    let date = NaiveDate::parse_from_str(week, "%Y-%m-%d")
+        .context("Week must be given as YYYY-Www or YYYY-MM-DD")?;
+    Ok(date - Duration::days(date.weekday().num_days_from_monday() as i64))
+}
+
+fn group_by_student(students: Vec<Student>, observations: Vec<Observation>) -> Vec<StudentWeekSummary> {
+    let mut by_student: BTreeMap<i64, BTreeMap<String, Vec<Observation>>> = BTreeMap::new();
+    for observation in observations {
+        by_student
+            .entry(observation.student_id)
+            .or_default()
+            .entry(observation.category.clone())
+            .or_default()
+            .push(observation);
+    }
+
+    // Keep the class list order (last name, first name); students without new
+    // observations are left out of the summary
+    students
+        .into_iter()
+        .filter_map(|student| {
+            let categories = by_student.remove(&student.id)?;
+            Some(StudentWeekSummary {
+                student_id: student.id,
+                first_name: student.first_name,
+                last_name: student.last_name,
+                categories: categories
+                    .into_iter()
+                    .map(|(category, observations)| CategoryGroup { category, observations })
+                    .collect(),
+                rubrics: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+/// Printed on every blank sheet, which carries personal data like the app.
+const BLANK_SHEET_NOTICE: &str = "Vertraulich: Enthält personenbezogene Daten. Bitte sicher aufbewahren, \
+    zeitnah in die App übertragen und danach vernichten.";
+
+/// One page per student to fill in by hand, e.g. on excursions: name, date,
+/// category boxes and ruled lines, to be typed in later.
+pub fn render_blank_observation_sheets(class_name: &str, students: &[Student], categories: &[String]) -> Vec<u8> {
+    let pages: Vec<PdfPage> = students
+        .iter()
+        .map(|student| {
+            let mut page = PdfPage::new();
+            page.text(50.0, 60.0, 18.0, Font::Bold, "Beobachtungsbogen");
+            page.text(
+                50.0,
+                90.0,
+                14.0,
+                Font::Bold,
+                &format!("{}, {}", student.last_name, student.first_name),
+            );
+            page.text(50.0, 110.0, 11.0, Font::Regular, &format!("Klasse {}", class_name));
+            page.text(300.0, 110.0, 11.0, Font::Regular, "Datum: ______________  Uhrzeit: ________");
+
+            page.text(50.0, 145.0, 11.0, Font::Bold, "Kategorie");
+            for (index, category) in categories.iter().enumerate() {
+                let x = 50.0 + (index % 3) as f32 * 165.0;
+                let y = 158.0 + (index / 3) as f32 * 20.0;
+                page.rect(x, y, 10.0, 10.0);
+                let label = pdf::wrap_text(category, 26).into_iter().next().unwrap_or_default();
+                page.text(x + 16.0, y + 9.0, 10.0, Font::Regular, &label);
+            }
+
+            let mut y = 158.0 + categories.len().div_ceil(3) as f32 * 20.0 + 25.0;
+            page.text(50.0, y, 11.0, Font::Bold, "Beobachtung");
+            y += 28.0;
+            while y < 770.0 {
+                page.line(50.0, y, pdf::PAGE_WIDTH - 50.0, y, 0.4);
+                y += 24.0;
+            }
+
+            for (index, line) in pdf::wrap_text(BLANK_SHEET_NOTICE, 110).iter().enumerate() {
+                page.text(50.0, 795.0 + index as f32 * 10.0, 8.0, Font::Regular, line);
+            }
+            page
+        })
+        .collect();
+
+    pdf::render(&format!("Beobachtungsbögen Klasse {}", class_name), &pages)
+}
+
+/// One row per value, for spreadsheets and progress charts outside the app.
+pub fn render_metric_series_csv(points: &[MetricPoint]) -> String {
+    let mut csv = String::from("observed_at,observation_id,metric,value,unit\n");
+    for point in points {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            point.observed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            point.observation_id,
+            csv_field(&point.metric),
+            point.value,
+            csv_field(point.unit.as_deref().unwrap_or(""))
+        ));
+    }
+    csv
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+pub fn signatory_name(summary: &WeeklySummary, user_id: i64) -> &str {
+    summary.signatories.get(&user_id).map(String::as_str).unwrap_or("?")
+}
+
+fn observation_language(observation: &Observation) -> &str {
+    observation.language.as_deref().unwrap_or(crate::language::UNDETERMINED)
+}
+
+fn render_observation_item(summary: &WeeklySummary, observation: &Observation) -> String {
+    let signatures = match (observation.cosigned_by, observation.cosigned_at) {
+        (Some(cosigner), Some(cosigned_at)) => format!(
+            " <span class=\"meta\">(verfasst von {}, gegengezeichnet von {} am {})</span>",
+            escape_html(signatory_name(summary, observation.author_id)),
+            escape_html(signatory_name(summary, cosigner)),
+            cosigned_at.format("%d.%m.%Y")
+        ),
+        _ => String::new(),
+    };
+    let reporter = observation
+        .reporter(&summary.signatories)
+        .map(|name| format!(" <span class=\"meta\">(berichtet von {})</span>", escape_html(&name)))
+        .unwrap_or_default();
+    // Screen readers and hyphenation follow the language of the text
+    let language = observation_language(observation);
+    let (lang_attribute, label) = match language {
+        crate::language::UNDETERMINED => (String::new(), String::new()),
+        code if summary.languages == LanguageDisplay::Label => (
+            format!(" lang=\"{}\"", code),
+            format!(" <span class=\"lang\">{}</span>", escape_html(crate::language::display_name(code))),
+        ),
+        code if summary.languages == LanguageDisplay::Group => (format!(" lang=\"{}\"", code), String::new()),
+        _ => (String::new(), String::new()),
+    };
+    format!(
+        "<li{}><span class=\"meta\">{}</span> {}{}{}{}</li>\n",
+        lang_attribute,
+        observation.created_at.format("%d.%m. %H:%M"),
+        escape_html(&observation.text),
+        label,
+        reporter,
+        signatures
+    )
+}
+
+pub fn render_weekly_summary_html(summary: &WeeklySummary) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!(
+        "<title>Wochenübersicht {} ({} – {})</title>\n",
+        escape_html(&summary.class_name),
+        summary.week_start.format("%d.%m.%Y"),
+        summary.week_end.format("%d.%m.%Y")
+    ));
+    html.push_str(
+        "<style>body{font-family:sans-serif;margin:2em}h2{margin-top:1.5em;border-bottom:1px solid #ccc}\
+         h3{margin-bottom:.2em;color:#374151}h4{margin:.4em 0 0;font-size:.95em;color:#4B5563}\
+         li{margin:.2em 0}.meta{color:#6B7280;font-size:.9em}\
+         .lang{font-size:.8em;color:#1E3A8A;background:#EBF8FF;border-radius:.3em;padding:0 .3em}\
+         table{border-collapse:collapse}td,th{border:1px solid #ccc;padding:.2em .6em;text-align:left}\
+         @media print{h2{page-break-after:avoid}}</style>\n</head>\n<body>\n",
+    );
+    html.push_str(&format!(
+        "<h1>Wochenübersicht Klasse {} ({})</h1>\n<p class=\"meta\">{} – {} · {} neue Beobachtungen</p>\n",
+        escape_html(&summary.class_name),
+        escape_html(&summary.school_year),
+        summary.week_start.format("%d.%m.%Y"),
+        summary.week_end.format("%d.%m.%Y"),
+        summary.total_observations
+    ));
+
+    if summary.students.is_empty() {
+        html.push_str("<p>Keine neuen Beobachtungen in dieser Woche.</p>\n");
+    }
+
+    for student in &summary.students {
+        html.push_str(&format!(
+            "<h2>{}, {}</h2>\n",
+            escape_html(&student.last_name),
+            escape_html(&student.first_name)
+        ));
+        for group in &student.categories {
+            html.push_str(&format!("<h3>{}</h3>\n", escape_html(&group.category)));
+            if summary.languages == LanguageDisplay::Group {
+                let mut by_language: BTreeMap<&str, Vec<&Observation>> = BTreeMap::new();
+                for observation in &group.observations {
+                    by_language.entry(observation_language(observation)).or_default().push(observation);
+                }
+                // Undetermined ones last
+                let undetermined = by_language
+                    .remove(crate::language::UNDETERMINED)
+                    .map(|observations| (crate::language::UNDETERMINED, observations));
+                for (language, observations) in by_language.into_iter().chain(undetermined) {
+                    html.push_str(&format!(
+                        "<h4>{}</h4>\n<ul>\n",
+                        escape_html(crate::language::display_name(language))
+                    ));
+                    for observation in observations {
+                        html.push_str(&render_observation_item(summary, observation));
+                    }
+                    html.push_str("</ul>\n");
+                }
+            } else {
+                html.push_str("<ul>\n");
+                for observation in &group.observations {
+                    html.push_str(&render_observation_item(summary, observation));
+                }
+                html.push_str("</ul>\n");
+            }
+        }
+        if !student.rubrics.is_empty() {
+            html.push_str(
+                "<h3>Bewertungsraster</h3>\n<table>\n<tr><th>Kriterium</th><th>Ø Stufe</th><th>Bewertungen</th></tr>\n",
+            );
+            for aggregate in &student.rubrics {
+                html.push_str(&format!(
+                    "<tr><td>{}: {}</td><td>{:.1}</td><td>{}</td></tr>\n",
+                    escape_html(&aggregate.rubric_name),
+                    escape_html(&aggregate.criterion_name),
+                    aggregate.average_level,
+                    aggregate.scores
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+    }
+
+    html.push_str(&format!(
+        "<p class=\"meta\">Erstellt am {}</p>\n</body>\n</html>\n",
+        Utc::now().format("%d.%m.%Y %H:%M UTC")
+    ));
+    html
+}
+
+fn observation_notes(summary: &WeeklySummary, observation: &Observation) -> String {
+    let mut notes = String::new();
+    if summary.languages == LanguageDisplay::Label && observation_language(observation) != crate::language::UNDETERMINED {
+        notes.push_str(&format!(" [{}]", crate::language::display_name(observation_language(observation))));
+    }
+    if let Some(name) = observation.reporter(&summary.signatories) {
+        notes.push_str(&format!(" (berichtet von {})", name));
+    }
+    if let (Some(cosigner), Some(cosigned_at)) = (observation.cosigned_by, observation.cosigned_at) {
+        notes.push_str(&format!(
+            " (verfasst von {}, gegengezeichnet von {} am {})",
+            signatory_name(summary, observation.author_id),
+            signatory_name(summary, cosigner),
+            cosigned_at.format("%d.%m.%Y")
+        ));
+    }
+    notes
+}
+
+fn write_observation(report: &mut pdf::ReportWriter, summary: &WeeklySummary, observation: &Observation) {
+    let text = format!(
+        "{} {}{}",
+        observation.created_at.format("%d.%m. %H:%M"),
+        observation.text,
+        observation_notes(summary, observation)
+    );
+    for (index, line) in pdf::wrap_text(&text, 84).iter().enumerate() {
+        let bullet = if index == 0 { "- " } else { "  " };
+        report.line(66.0, 10.0, Font::Regular, &format!("{}{}", bullet, line));
+    }
+}
+
+/// The same summary as `render_weekly_summary_html`, as a PDF to print or file.
+pub fn render_weekly_summary_pdf(summary: &WeeklySummary) -> Vec<u8> {
+    let title = format!("Wochenübersicht Klasse {} ({})", summary.class_name, summary.school_year);
+    let mut report = pdf::ReportWriter::new();
+    report.line(50.0, 16.0, Font::Bold, &title);
+    report.line(
+        50.0,
+        9.0,
+        Font::Regular,
+        &format!(
+            "{} – {} · {} neue Beobachtungen",
+            summary.week_start.format("%d.%m.%Y"),
+            summary.week_end.format("%d.%m.%Y"),
+            summary.total_observations
+        ),
+    );
+
+    if summary.students.is_empty() {
+        report.gap();
+        report.line(50.0, 10.0, Font::Regular, "Keine neuen Beobachtungen in dieser Woche.");
+    }
+    for student in &summary.students {
+        report.gap();
+        report.line(50.0, 12.0, Font::Bold, &format!("{}, {}", student.last_name, student.first_name));
+        for group in &student.categories {
+            report.line(60.0, 10.0, Font::Bold, &group.category);
+            if summary.languages == LanguageDisplay::Group {
+                let mut by_language: BTreeMap<&str, Vec<&Observation>> = BTreeMap::new();
+                for observation in &group.observations {
+                    by_language.entry(observation_language(observation)).or_default().push(observation);
+                }
+                let undetermined = by_language
+                    .remove(crate::language::UNDETERMINED)
+                    .map(|observations| (crate::language::UNDETERMINED, observations));
+                for (language, observations) in by_language.into_iter().chain(undetermined) {
+                    report.line(60.0, 9.0, Font::Bold, crate::language::display_name(language));
+                    for observation in observations {
+                        write_observation(&mut report, summary, observation);
+                    }
+                }
+            } else {
+                for observation in &group.observations {
+                    write_observation(&mut report, summary, observation);
+                }
+            }
+        }
+        if !student.rubrics.is_empty() {
+            report.line(60.0, 10.0, Font::Bold, "Bewertungsraster");
+            for aggregate in &student.rubrics {
+                let line = format!(
+                    "{}: {} · Ø Stufe {:.1} · {} Bewertungen",
+                    aggregate.rubric_name, aggregate.criterion_name, aggregate.average_level, aggregate.scores
+                );
+                for line in pdf::wrap_text(&line, 88) {
+                    report.line(66.0, 10.0, Font::Regular, &line);
+                }
+            }
+        }
+    }
+
+    report.gap();
+    report.line(
+        50.0,
+        8.0,
+        Font::Regular,
+        &format!("Erstellt am {}", Utc::now().format("%d.%m.%Y %H:%M UTC")),
+    );
+    pdf::render(&title, &report.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn create_test_setup() -> (Database, ReportGenerator, TempDir) {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        (db, ReportGenerator::new(), temp_dir)
+    }
+
+    #[tokio::test]
+    async fn test_blank_sheets_have_one_page_per_student() {
+        let (db, _reports, _temp_dir) = create_test_setup().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let students = db.get_students_by_class(class.id).await.unwrap();
+
+        let categories = vec!["Sozial".to_string(), "Fachlich".to_string(), "Motorik".to_string(), "Sprache".to_string()];
+        let pdf = render_blank_observation_sheets(&class.name, &students, &categories);
+
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("(Mustermann, Max) Tj"));
+        assert!(text.contains("(Sprache) Tj"));
+    }
+
+    #[test]
+    fn test_parse_week() {
+        let monday = NaiveDate::from_ymd_opt(2024, 9, 9).unwrap();
+
+        assert_eq!(parse_week("2024-W37").unwrap(), monday);
+        assert_eq!(parse_week("2024-09-11").unwrap(), monday);
+        assert_eq!(parse_week("2024-09-09").unwrap(), monday);
+        assert!(parse_week("2024-W60").is_err());
+        assert!(parse_week("next week").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_weekly_summary_groups_by_student_and_category() {
+        let (db, reports, _temp_dir) = create_test_setup().await;
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        let _quiet = db.create_student(class.id, "Ben".to_string(), "Zander".to_string(), None).await.unwrap();
+
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Hilft <gern>".to_string(), vec![]).await.unwrap();
+        db.create_observation(max.id, 1, "Fachlich".to_string(), "Rechnet sicher".to_string(), vec![]).await.unwrap();
+        db.create_observation(anna.id, 1, "Sozial".to_string(), "Teamarbeit".to_string(), vec![]).await.unwrap();
+
+        let today = Utc::now().date_naive().to_string();
+        let mut summary = reports
+            .generate_weekly_summary(&db.reader(), class.id, &today, 1, LanguageDisplay::None)
+            .await
+            .unwrap();
+        reports.store_weekly_summary(&db, &mut summary).await.unwrap();
+
+        assert_eq!(summary.total_observations, 3);
+        assert_eq!(summary.students.len(), 2); // Ben has no observations
+        assert_eq!(summary.students[0].last_name, "Mustermann");
+        assert_eq!(summary.students[0].categories.len(), 2);
+        assert!(summary.html.contains("Hilft &lt;gern&gt;"));
+        let pdf = String::from_utf8_lossy(&render_weekly_summary_pdf(&summary)).to_string();
+        assert!(pdf.contains("(Mustermann, Max) Tj"));
+        assert!(pdf.contains("(Fachlich) Tj"));
+
+        // Stored artifact can be read back for later reference
+        let artifact_id = summary.artifact_id.unwrap();
+        let artifacts = db.get_report_artifacts(Some(class.id)).await.unwrap();
+        assert_eq!(artifacts.len(), 1);
+        assert_eq!(artifacts[0].report_type, "weekly_summary");
+        let content = db.get_report_artifact_content(artifact_id).await.unwrap();
+        assert_eq!(String::from_utf8(content).unwrap(), summary.html);
+    }
+
+    #[tokio::test]
+    async fn test_weekly_summary_shows_languages() {
+        let (db, reports, _temp_dir) = create_test_setup().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        db.create_observation(max.id, 1, "Sprache".to_string(), "Liest heute sehr flüssig vor".to_string(), vec![])
+            .await
+            .unwrap();
+        db.create_observation(max.id, 1, "Sprache".to_string(), "Asked the class for help with the poem".to_string(), vec![])
+            .await
+            .unwrap();
+
+        let today = Utc::now().date_naive().to_string();
+        let labelled = reports
+            .generate_weekly_summary(&db.reader(), class.id, &today, 1, LanguageDisplay::Label)
+            .await
+            .unwrap();
+        assert!(labelled.html.contains("<li lang=\"en\">"));
+        assert!(labelled.html.contains("<span class=\"lang\">Englisch</span>"));
+        assert!(!labelled.html.contains("<h4>"));
+
+        let grouped = reports
+            .generate_weekly_summary(&db.reader(), class.id, &today, 1, LanguageDisplay::Group)
+            .await
+            .unwrap();
+        let german = grouped.html.find("<h4>Deutsch</h4>").unwrap();
+        let english = grouped.html.find("<h4>Englisch</h4>").unwrap();
+        assert!(german < english);
+        assert!(!grouped.html.contains("class=\"lang\""));
+    }
+}
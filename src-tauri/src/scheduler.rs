@@ -0,0 +1,192 @@
+use crate::audit::AuditLogger;
+use crate::database::Database;
+use crate::gdpr::{DeletionResult, GdprManager, RetentionRun};
+use crate::{ErasureRequest, Student};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+/// How long before a scheduled erasure the users are reminded of it.
+pub const ERASURE_NOTICE_HOURS: i64 = 48;
+
+/// What one scheduler pass did, so the caller can notify the user.
+#[derive(Debug, Default)]
+pub struct SchedulerRun {
+    pub announced: Vec<ErasureRequest>,
+    pub deactivated: Vec<Student>, // Students whose last school day has come
+    pub executed: Vec<DeletionResult>,
+    pub retention: RetentionRun,
+    pub failed: Vec<String>,
+}
+
+/// Announces erasures coming up within the notice period and carries out the
+/// ones that are due. A failing erasure stays pending and is retried next pass.
+/// Students who left become inactive on their last school day, and their
+/// records go when the retention policy says so.
+pub async fn run_due_tasks(
+    db: &Database,
+    gdpr: &GdprManager,
+    audit: &AuditLogger,
+    now: DateTime<Utc>,
+) -> Result<SchedulerRun> {
+    let mut run = SchedulerRun::default();
+
+    for request in db
+        .get_erasures_to_announce(now + Duration::hours(ERASURE_NOTICE_HOURS))
+        .await?
+    {
+        db.mark_erasure_announced(request.id).await?;
+        run.announced.push(request);
+    }
+
+    // Due dates mean nothing while the clock may have been set forward
+    if let Some(warning) = audit.clock_warning().await? {
+        run.failed.push(format!("Scheduled erasures postponed: {}", warning));
+        return Ok(run);
+    }
+
+    run.deactivated = db.deactivate_students_who_left(now.date_naive()).await?;
+
+    // Logged like deletions by hand, but without a user
+    run.retention = gdpr.apply_retention_policy(db, now).await?;
+    for observation_id in &run.retention.deleted_observations {
+        audit
+            .log_action("delete", "observation", *observation_id, 0, Some("retention_period"))
+            .await?;
+    }
+    if run.retention.attachments_deleted > 0 || run.retention.observations_anonymized > 0 {
+        audit
+            .log_action(
+                "apply_retention",
+                "app",
+                0,
+                0,
+                Some(&format!(
+                    "{} attachments deleted, {} observations anonymized",
+                    run.retention.attachments_deleted, run.retention.observations_anonymized
+                )),
+            )
+            .await?;
+    }
+
+    for request in db.get_due_erasures(now).await? {
+        match gdpr.execute_scheduled_erasure(db, &request, now).await {
+            Ok(result) => {
+                audit
+                    .log_action(
+                        "execute_erasure",
+                        "student",
+                        request.student_id,
+                        request.requested_by,
+                        Some(&format!(
+                            "request {}: scheduled for {}, {} observations deleted",
+                            request.id,
+                            request.execute_at.map(|at| at.to_rfc3339()).unwrap_or_default(),
+                            result.observations_deleted
+                        )),
+                    )
+                    .await?;
+                run.executed.push(result);
+            }
+            Err(e) => run.failed.push(format!("Erasure request {}: {}", request.id, e)),
+        }
+    }
+
+    Ok(run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scheduled_erasure_is_announced_then_executed() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+        let gdpr = GdprManager::new();
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let execute_at = Utc::now() + Duration::days(14);
+        assert!(gdpr.schedule_erasure(&db, student.id, Utc::now() - Duration::days(1), 1, "Frist").await.is_err());
+        let request = gdpr.schedule_erasure(&db, student.id, execute_at, 1, "Ende der Aufbewahrungsfrist").await.unwrap();
+        assert_eq!(db.get_open_requests().await.unwrap()[0].id, request.id);
+
+        // Too early for anything
+        let run = run_due_tasks(&db, &gdpr, &audit, Utc::now()).await.unwrap();
+        assert!(run.announced.is_empty() && run.executed.is_empty());
+
+        // Within the notice period: announced once, not yet deleted
+        let run = run_due_tasks(&db, &gdpr, &audit, execute_at - Duration::hours(1)).await.unwrap();
+        assert_eq!(run.announced.len(), 1);
+        assert!(run.executed.is_empty());
+        let run = run_due_tasks(&db, &gdpr, &audit, execute_at - Duration::minutes(30)).await.unwrap();
+        assert!(run.announced.is_empty());
+
+        let run = run_due_tasks(&db, &gdpr, &audit, execute_at + Duration::minutes(1)).await.unwrap();
+        assert_eq!(run.executed.len(), 1);
+        assert!(db.get_student(student.id).await.unwrap().is_none());
+        assert!(db.get_open_requests().await.unwrap().is_empty());
+        assert_eq!(audit.get_entries_by_action("execute_erasure", None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retention_period_removes_records_of_students_who_left() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+        let gdpr = GdprManager::new();
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let staying = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let leaving = db.create_student(class.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        db.create_observation(staying.id, 1, "Sozial".to_string(), "Hilft".to_string(), vec![]).await.unwrap();
+        let observation = db.create_observation(leaving.id, 1, "Sozial".to_string(), "Liest vor".to_string(), vec![]).await.unwrap();
+        db.mark_student_left(leaving.id, Utc::now().date_naive()).await.unwrap();
+
+        // Editing the student later does not restart the countdown
+        let status = gdpr.get_retention_status(&db, &audit, leaving.id).await.unwrap();
+        let (_, due_at) = status
+            .deadlines
+            .iter()
+            .find(|d| d.category == "observations" && d.action == "delete")
+            .map(|d| (d.records, d.due_at.unwrap()))
+            .unwrap();
+
+        let run = run_due_tasks(&db, &gdpr, &audit, due_at - Duration::hours(1)).await.unwrap();
+        assert!(run.retention.deleted_observations.is_empty());
+        let run = run_due_tasks(&db, &gdpr, &audit, due_at + Duration::minutes(1)).await.unwrap();
+        assert_eq!(run.retention.deleted_observations, vec![observation.id]);
+        assert!(db.get_observation(observation.id).await.unwrap().is_none());
+        assert!(db.get_student(leaving.id).await.unwrap().is_some());
+        assert_eq!(db.search_observations(None, Some(staying.id), None).await.unwrap().len(), 1);
+        assert_eq!(audit.get_entries_by_action("delete", None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_erasure_waits_for_a_second_admin() {
+        let (db, temp_dir) = crate::fixtures::test_db().await;
+        let audit = AuditLogger::new(temp_dir.path().join("audit.db")).await.unwrap();
+        let gdpr = GdprManager::new();
+
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let second = db.create_user("Herr Weber".to_string(), "admin".to_string()).await.unwrap();
+
+        let execute_at = Utc::now() + Duration::days(14);
+        let request = gdpr.schedule_erasure(&db, student.id, execute_at, 1, "Ende der Aufbewahrungsfrist").await.unwrap();
+        assert_eq!(request.approved_by, None);
+        assert!(gdpr.approve_erasure(&db, request.id, second.id).await.is_err());
+
+        // Neither announced nor carried out without approval
+        let run = run_due_tasks(&db, &gdpr, &audit, execute_at + Duration::minutes(1)).await.unwrap();
+        assert!(run.announced.is_empty() && run.executed.is_empty());
+        assert!(db.get_student(student.id).await.unwrap().is_some());
+
+        assert!(gdpr.approve_scheduled_erasure(&db, request.id, 1).await.is_err());
+        let approved = gdpr.approve_scheduled_erasure(&db, request.id, second.id).await.unwrap();
+        assert_eq!(approved.approved_by, Some(second.id));
+        let run = run_due_tasks(&db, &gdpr, &audit, execute_at + Duration::minutes(1)).await.unwrap();
+        assert_eq!(run.executed.len(), 1);
+        assert!(db.get_student(student.id).await.unwrap().is_none());
+    }
+}
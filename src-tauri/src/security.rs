@@ -0,0 +1,236 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+
+/// Folder names of sync clients that copy files to servers outside the school.
+const CLOUD_SYNC_FOLDERS: [(&str, &str); 8] = [
+    ("onedrive", "OneDrive"),
+    ("dropbox", "Dropbox"),
+    ("google drive", "Google Drive"),
+    ("googledrive", "Google Drive"),
+    ("icloud drive", "iCloud"),
+    ("mobile documents", "iCloud"),
+    ("nextcloud", "Nextcloud"),
+    ("owncloud", "ownCloud"),
+];
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileSecurity {
+    pub path: String,
+    pub exists: bool,
+    /// Only the current user can read and write the file.
+    pub restricted: bool,
+    /// Permissions were tightened by this check.
+    pub tightened: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SecurityStatus {
+    pub files: Vec<FileSecurity>,
+    pub warnings: Vec<String>,
+    pub checked_at: DateTime<Utc>,
+    /// Set by `get_security_status`, see `integrity::verify`.
+    pub integrity: Option<crate::integrity::IntegrityCheck>,
+}
+
+/// SQLite keeps uncommitted data in the `-wal` file, so it needs the same protection.
+fn with_sqlite_companions(path: &Path) -> Vec<PathBuf> {
+    ["-wal", "-shm"]
+        .iter()
+        .map(|suffix| {
+            let mut file = path.as_os_str().to_os_string();
+            file.push(suffix);
+            PathBuf::from(file)
+        })
+        .collect()
+}
+
+/// Returns whether the permissions had to be changed.
+#[cfg(unix)]
+fn restrict_to_owner(path: &Path, mode: u32) -> Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let current = std::fs::metadata(path)?.permissions().mode() & 0o777;
+    if current & !mode == 0 {
+        return Ok(false);
+    }
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(true)
+}
+
+/// Removes inherited entries and grants access to the current user only.
+#[cfg(windows)]
+fn restrict_to_owner(path: &Path, _mode: u32) -> Result<bool> {
+    use anyhow::Context;
+
+    let user = std::env::var("USERNAME").context("USERNAME is not set")?;
+    let output = std::process::Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r"])
+        .arg(format!("{}:F", user))
+        .output()
+        .context("Failed to run icacls")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "icacls failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(true)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn restrict_to_owner(_path: &Path, _mode: u32) -> Result<bool> {
+    Err(anyhow::anyhow!("Restricting permissions is not supported on this platform"))
+}
+
+fn harden_file(path: &Path) -> FileSecurity {
+    let mut file = FileSecurity {
+        path: path.display().to_string(),
+        exists: path.exists(),
+        restricted: false,
+        tightened: false,
+        error: None,
+    };
+    if !file.exists {
+        return file;
+    }
+    match restrict_to_owner(path, 0o600) {
+        Ok(tightened) => {
+            file.restricted = true;
+            file.tightened = tightened;
+        }
+        Err(e) => file.error = Some(e.to_string()),
+    }
+    file
+}
+
+pub fn cloud_sync_provider(path: &Path) -> Option<&'static str> {
+    path.ancestors()
+        .filter_map(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .find_map(|name| {
+            CLOUD_SYNC_FOLDERS
+                .iter()
+                .find(|(folder, _)| name == *folder || name.starts_with(&format!("{} - ", folder)))
+                .map(|(_, provider)| *provider)
+        })
+}
+
+/// Other users can list or enter the folder holding the file.
+#[cfg(unix)]
+fn is_world_readable(dir: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(dir)
+        .map(|metadata| metadata.permissions().mode() & 0o005 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_world_readable(_dir: &Path) -> bool {
+    false
+}
+
+/// Tightens the permissions of the database, the audit log and the secrets
+/// file and reports locations that expose them anyway. Folders are only
+/// tightened inside the app data directory; a custom database folder is left
+/// as the school set it up and only warned about.
+pub fn check_and_harden(app_data_dir: &Path, db_path: &Path, audit_path: &Path, secrets_path: &Path) -> SecurityStatus {
+    let mut warnings = Vec::new();
+
+    for dir in [Some(app_data_dir), secrets_path.parent()].into_iter().flatten() {
+        if dir.exists() {
+            if let Err(e) = restrict_to_owner(dir, 0o700) {
+                warnings.push(format!("Berechtigungen von {} konnten nicht eingeschränkt werden: {}", dir.display(), e));
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    for path in [db_path, audit_path, secrets_path] {
+        files.push(harden_file(path));
+    }
+    // Companion files only exist while the database is open, so missing ones are not listed
+    for path in [db_path, audit_path] {
+        files.extend(
+            with_sqlite_companions(path)
+                .into_iter()
+                .map(|companion| harden_file(&companion))
+                .filter(|f| f.exists),
+        );
+    }
+    for file in &files {
+        if let Some(error) = &file.error {
+            warnings.push(format!("Berechtigungen von {} konnten nicht eingeschränkt werden: {}", file.path, error));
+        }
+    }
+
+    for (label, path) in [("Datenbank", db_path), ("Protokoll", audit_path)] {
+        if let Some(provider) = cloud_sync_provider(path) {
+            warnings.push(format!(
+                "{} liegt in einem von {} synchronisierten Ordner ({}); Schülerdaten verlassen so das Gerät",
+                label,
+                provider,
+                path.display()
+            ));
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.starts_with(app_data_dir)) {
+            if is_world_readable(dir) {
+                warnings.push(format!(
+                    "Der Ordner {} ist für andere Benutzer dieses Geräts lesbar",
+                    dir.display()
+                ));
+            }
+        }
+    }
+
+    SecurityStatus {
+        files,
+        warnings,
+        checked_at: Utc::now(),
+        integrity: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cloud_sync_provider() {
+        assert_eq!(cloud_sync_provider(Path::new("/home/lehrer/OneDrive - Schule/db/observations.db")), Some("OneDrive"));
+        assert_eq!(cloud_sync_provider(Path::new("/home/lehrer/Dropbox/observations.db")), Some("Dropbox"));
+        assert_eq!(cloud_sync_provider(Path::new("/home/lehrer/.local/share/app/observations.db")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_and_harden_restricts_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let app_dir = temp_dir.path().join("app");
+        std::fs::create_dir(&app_dir).unwrap();
+        let db_path = app_dir.join("observations.db");
+        let audit_path = app_dir.join("audit.db");
+        let secrets_path = app_dir.join("secure.json");
+        for path in [&db_path, &audit_path, &secrets_path] {
+            std::fs::write(path, b"x").unwrap();
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        }
+
+        let status = check_and_harden(&app_dir, &db_path, &audit_path, &secrets_path);
+        assert_eq!(status.files.len(), 3);
+        assert!(status.files.iter().all(|f| f.restricted && f.tightened));
+        assert!(status.warnings.is_empty());
+        let mode = std::fs::metadata(&db_path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+
+        // Nothing left to tighten on the next start
+        let status = check_and_harden(&app_dir, &db_path, &audit_path, &secrets_path);
+        assert!(status.files.iter().all(|f| f.restricted && !f.tightened));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::database::Database;
+use anyhow::Result;
+use std::path::Path;
+
+const QUOTAS_SETTING: &str = "storage_quotas";
+
+/// Below this much free space SQLite risks failing mid-write, so it is always checked.
+const DEFAULT_MIN_FREE_BYTES: u64 = 500 * 1024 * 1024;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StorageQuotas {
+    pub database_bytes: Option<u64>,
+    pub attachment_bytes: Option<u64>,
+    pub audit_bytes: Option<u64>,
+    pub min_free_bytes: u64,
+}
+
+impl Default for StorageQuotas {
+    fn default() -> Self {
+        Self {
+            database_bytes: None,
+            attachment_bytes: None,
+            audit_bytes: None,
+            min_free_bytes: DEFAULT_MIN_FREE_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ClassStorageUsage {
+    pub class_id: i64,
+    pub class_name: String,
+    pub attachment_count: i64,
+    pub attachment_bytes: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct StorageUsage {
+    pub database_bytes: u64,
+    pub attachment_bytes: u64,
+    pub attachments_by_class: Vec<ClassStorageUsage>,
+    pub audit_bytes: u64,
+    pub free_bytes: Option<u64>,
+    pub quotas: StorageQuotas,
+    pub warnings: Vec<String>,
+}
+
+/// Size of a SQLite database including its WAL and shared-memory files.
+fn sqlite_size(db_path: &Path) -> u64 {
+    ["", "-wal", "-shm"]
+        .iter()
+        .filter_map(|suffix| {
+            let mut path = db_path.as_os_str().to_os_string();
+            path.push(suffix);
+            std::fs::metadata(path).ok()
+        })
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+pub async fn load_quotas(db: &Database) -> Result<StorageQuotas> {
+    Ok(db
+        .get_setting(QUOTAS_SETTING)
+        .await?
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .unwrap_or_default())
+}
+
+pub async fn save_quotas(db: &Database, quotas: &StorageQuotas) -> Result<()> {
+    db.set_setting(QUOTAS_SETTING, &serde_json::to_string(quotas)?).await
+}
+
+pub async fn collect_usage(db: &Database, audit_path: &Path) -> Result<StorageUsage> {
+    let attachments_by_class: Vec<ClassStorageUsage> = db
+        .get_attachment_usage_by_class()
+        .await?
+        .into_iter()
+        .map(|(class_id, class_name, attachment_count, attachment_bytes)| ClassStorageUsage {
+            class_id,
+            class_name,
+            attachment_count,
+            attachment_bytes,
+        })
+        .collect();
+
+    let data_dir = db.db_path().parent().unwrap_or_else(|| Path::new("."));
+    let mut usage = StorageUsage {
+        database_bytes: sqlite_size(db.db_path()),
+        attachment_bytes: attachments_by_class.iter().map(|c| c.attachment_bytes as u64).sum(),
+        attachments_by_class,
+        audit_bytes: sqlite_size(audit_path),
+        free_bytes: fs2::available_space(data_dir).ok(),
+        quotas: load_quotas(db).await?,
+        warnings: Vec::new(),
+    };
+    usage.warnings = evaluate_quotas(&usage);
+
+    Ok(usage)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+pub fn evaluate_quotas(usage: &StorageUsage) -> Vec<String> {
+    let quotas = &usage.quotas;
+    let mut warnings = Vec::new();
+
+    let checks = [
+        ("Database", usage.database_bytes, quotas.database_bytes),
+        ("Attachments", usage.attachment_bytes, quotas.attachment_bytes),
+        ("Audit log", usage.audit_bytes, quotas.audit_bytes),
+    ];
+    for (label, used, limit) in checks {
+        if let Some(limit) = limit.filter(|limit| used >= *limit) {
+            warnings.push(format!(
+                "{} uses {} and exceeds its limit of {}",
+                label,
+                format_bytes(used),
+                format_bytes(limit)
+            ));
+        }
+    }
+
+    if let Some(free) = usage.free_bytes.filter(|free| *free < quotas.min_free_bytes) {
+        warnings.push(format!(
+            "Only {} of disk space left; free up space or create a backup before it runs out",
+            format_bytes(free)
+        ));
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_quotas() {
+        let mut usage = StorageUsage {
+            database_bytes: 10 * 1024 * 1024,
+            attachment_bytes: 0,
+            attachments_by_class: Vec::new(),
+            audit_bytes: 0,
+            free_bytes: Some(10 * 1024 * 1024 * 1024),
+            quotas: StorageQuotas::default(),
+            warnings: Vec::new(),
+        };
+        assert!(evaluate_quotas(&usage).is_empty());
+
+        usage.quotas.database_bytes = Some(5 * 1024 * 1024);
+        usage.free_bytes = Some(100 * 1024 * 1024);
+        let warnings = evaluate_quotas(&usage);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].starts_with("Database uses 10.0 MB"));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::database::Database;
+use crate::pdf::{self, Font, ReportWriter};
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+/// The newest pins per student that go into a brief; a substitute needs the
+/// essentials, not a record.
+pub const MAX_PINNED_PER_STUDENT: usize = 3;
+
+/// Printed on every brief.
+const BRIEF_NOTICE: &str = "Vertraulich: Nur für die Vertretung dieser Klasse. Enthält personenbezogene Daten; \
+    nach der Vertretung bitte vernichten.";
+
+#[derive(Debug, serde::Serialize)]
+pub struct BriefNote {
+    pub date: NaiveDate,
+    pub category: String,
+    pub text: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BriefStudent {
+    pub first_name: String,
+    pub last_name: String,
+    pub notes: Vec<BriefNote>,
+    pub goals: Vec<String>,
+}
+
+/// What a substitute teacher gets to know about a class: pinned notes and
+/// active goals of the active students, nothing else. Authors, tags,
+/// subjects and the history stay in the app.
+#[derive(Debug, serde::Serialize)]
+pub struct SubstituteBrief {
+    pub class_name: String,
+    pub created_at: DateTime<Utc>,
+    pub students: Vec<BriefStudent>, // Only those with notes or goals
+    pub omitted_notes: usize,        // Pins beyond `MAX_PINNED_PER_STUDENT`
+}
+
+/// Private and local-only observations stay out even when pinned; the brief
+/// leaves the device and is read by someone else.
+pub async fn build_substitute_brief(db: &Database, class_id: i64) -> Result<SubstituteBrief> {
+    let class = db.get_class(class_id).await?.context("Class not found")?;
+    let mut notes: BTreeMap<i64, Vec<BriefNote>> = BTreeMap::new();
+    let mut omitted_notes = 0;
+    for observation in db.get_pinned_observations(class_id).await? {
+        if !observation.leaves_device() {
+            continue;
+        }
+        let student_notes = notes.entry(observation.student_id).or_default();
+        if student_notes.len() == MAX_PINNED_PER_STUDENT {
+            omitted_notes += 1;
+            continue;
+        }
+        student_notes.push(BriefNote {
+            date: observation.created_at.date_naive(),
+            category: observation.category,
+            text: observation.text,
+        });
+    }
+    let mut goals: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+    for goal in db.get_active_class_goals(class_id).await? {
+        goals.entry(goal.student_id).or_default().push(goal.text);
+    }
+
+    let students = db
+        .get_students_by_class(class_id)
+        .await?
+        .into_iter()
+        .filter(|s| s.status == "active")
+        .filter_map(|student| {
+            let notes = notes.remove(&student.id).unwrap_or_default();
+            let goals = goals.remove(&student.id).unwrap_or_default();
+            (!notes.is_empty() || !goals.is_empty()).then_some(BriefStudent {
+                first_name: student.first_name,
+                last_name: student.last_name,
+                notes,
+                goals,
+            })
+        })
+        .collect();
+
+    Ok(SubstituteBrief {
+        class_name: class.name,
+        created_at: Utc::now(),
+        students,
+        omitted_notes,
+    })
+}
+
+pub fn render_substitute_brief_pdf(brief: &SubstituteBrief) -> Vec<u8> {
+    let mut report = ReportWriter::new();
+    report.line(50.0, 16.0, Font::Bold, &format!("Vertretung Klasse {}", brief.class_name));
+    report.line(
+        50.0,
+        9.0,
+        Font::Regular,
+        &format!("Stand {} · nur angeheftete Hinweise und aktuelle Ziele", brief.created_at.format("%d.%m.%Y")),
+    );
+    for line in pdf::wrap_text(BRIEF_NOTICE, 100) {
+        report.line(50.0, 8.0, Font::Regular, &line);
+    }
+
+    if brief.students.is_empty() {
+        report.gap();
+        report.line(50.0, 10.0, Font::Regular, "Für diese Klasse gibt es keine Hinweise.");
+    }
+    for student in &brief.students {
+        report.gap();
+        report.line(50.0, 12.0, Font::Bold, &format!("{}, {}", student.last_name, student.first_name));
+        for note in &student.notes {
+            report.line(60.0, 9.0, Font::Bold, &format!("{} · {}", note.date.format("%d.%m.%Y"), note.category));
+            for line in pdf::wrap_text(&note.text, 88) {
+                report.line(60.0, 10.0, Font::Regular, &line);
+            }
+        }
+        if !student.goals.is_empty() {
+            report.line(60.0, 9.0, Font::Bold, "Aktuelle Ziele");
+            for goal in &student.goals {
+                for (index, line) in pdf::wrap_text(goal, 84).iter().enumerate() {
+                    let bullet = if index == 0 { "- " } else { "  " };
+                    report.line(66.0, 10.0, Font::Regular, &format!("{}{}", bullet, line));
+                }
+            }
+        }
+    }
+
+    pdf::render(&format!("Vertretung Klasse {}", brief.class_name), &report.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_brief_holds_only_pins_and_active_goals() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+        let class = db.create_class("5a".to_string(), "2024/25".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(class.id, "Anna".to_string(), "Albrecht".to_string(), None).await.unwrap();
+        let left = db
+            .create_student(class.id, "Erika".to_string(), "Musterfrau".to_string(), Some("inactive".to_string()))
+            .await
+            .unwrap();
+
+        let allergy = db
+            .create_observation(max.id, 1, "Sonstiges".to_string(), "Nussallergie, Notfallset im Ranzen".to_string(), vec![])
+            .await
+            .unwrap();
+        db.set_observation_pinned(allergy.id, true).await.unwrap();
+        db.create_observation(max.id, 1, "Verhalten".to_string(), "Unruhig in der 6. Stunde".to_string(), vec![])
+            .await
+            .unwrap();
+        let private = db
+            .create_observation(anna.id, 1, "Förderung".to_string(), "Gespräch mit den Eltern".to_string(), vec![])
+            .await
+            .unwrap();
+        db.set_observation_visibility(private.id, 1, crate::database::VISIBILITY_PRIVATE).await.unwrap();
+        db.set_observation_pinned(private.id, true).await.unwrap();
+        for text in ["Eins", "Zwei", "Drei", "Vier"] {
+            let pinned = db.create_observation(left.id, 1, "Sozial".to_string(), text.to_string(), vec![]).await.unwrap();
+            db.set_observation_pinned(pinned.id, true).await.unwrap();
+        }
+
+        db.create_student_goal(anna.id, "Meldet sich, bevor sie spricht").await.unwrap();
+        let achieved = db.create_student_goal(max.id, "Bringt Hausaufgaben mit").await.unwrap();
+        db.set_student_goal_status(achieved.id, "achieved").await.unwrap();
+        assert!(db.set_student_goal_status(achieved.id, "vergessen").await.is_err());
+
+        let brief = build_substitute_brief(&db, class.id).await.unwrap();
+        let names: Vec<&str> = brief.students.iter().map(|s| s.first_name.as_str()).collect();
+        assert_eq!(names, vec!["Anna", "Max"]);
+        assert!(brief.students[0].notes.is_empty());
+        assert_eq!(brief.students[0].goals, vec!["Meldet sich, bevor sie spricht"]);
+        assert_eq!(brief.students[1].notes.len(), 1);
+        assert_eq!(brief.students[1].notes[0].text, "Nussallergie, Notfallset im Ranzen");
+        assert!(brief.students[1].goals.is_empty());
+
+        // At most three pins per student
+        db.set_observation_pinned(allergy.id, false).await.unwrap();
+        for text in ["Eins", "Zwei", "Drei", "Vier"] {
+            let pinned = db.create_observation(max.id, 1, "Sozial".to_string(), text.to_string(), vec![]).await.unwrap();
+            db.set_observation_pinned(pinned.id, true).await.unwrap();
+        }
+        let brief = build_substitute_brief(&db, class.id).await.unwrap();
+        assert_eq!(brief.students[1].notes.len(), MAX_PINNED_PER_STUDENT);
+        assert_eq!(brief.omitted_notes, 1);
+
+        assert!(render_substitute_brief_pdf(&brief).starts_with(b"%PDF-1.4"));
+        assert!(build_substitute_brief(&db, 999).await.is_err());
+    }
+}
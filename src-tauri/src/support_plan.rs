@@ -0,0 +1,127 @@
+use crate::database::{CompetencyCoverage, CompetencyLink, Database};
+use crate::reports::escape_html;
+use crate::{Observation, Student};
+use anyhow::{Context, Result};
+use chrono::Utc;
+
+/// The basis of a Förderplan: which competencies are documented for a
+/// student, with the observations behind them, and which are not yet.
+#[derive(Debug, serde::Serialize)]
+pub struct SupportPlan {
+    pub student: Student,
+    pub class_name: String,
+    pub coverage: Vec<CompetencyCoverage>,
+    pub links: Vec<CompetencyLink>,
+    pub observations: Vec<Observation>, // Only those linked to a competency
+}
+
+pub async fn build_support_plan(db: &Database, student_id: i64, viewer_id: i64) -> Result<SupportPlan> {
+    let student = db.get_student(student_id).await?.context("Student not found")?;
+    let class_name = db.get_class(student.class_id).await?.map(|c| c.name).unwrap_or_default();
+    let coverage = db.get_competency_coverage(student_id, viewer_id).await?;
+
+    let mut observations = db.search_observations(None, Some(student_id), None).await?;
+    observations.retain(|o| o.is_visible_to(viewer_id));
+    let mut links = db.get_student_competency_links(student_id).await?;
+    links.retain(|link| observations.iter().any(|o| o.id == link.observation_id));
+    observations.retain(|o| links.iter().any(|link| link.observation_id == o.id));
+
+    Ok(SupportPlan {
+        student,
+        class_name,
+        coverage,
+        links,
+        observations,
+    })
+}
+
+pub fn render_support_plan_html(plan: &SupportPlan) -> String {
+    let name = format!("{} {}", plan.student.first_name, plan.student.last_name);
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>Förderplan {}</title>\n", escape_html(&name)));
+    html.push_str(
+        "<style>body{font-family:sans-serif;margin:2em}h2{margin-top:1.5em;border-bottom:1px solid #ccc}\
+         th,td{vertical-align:top;padding:.3em .6em;border-bottom:1px solid #eee;text-align:left}\
+         li{margin:.2em 0}.meta{color:#6B7280;font-size:.9em}.open{color:#B91C1C}</style>\n</head>\n<body>\n",
+    );
+    html.push_str(&format!(
+        "<h1>Förderplan: {}</h1>\n<p class=\"meta\">Klasse {}</p>\n",
+        escape_html(&name),
+        escape_html(&plan.class_name)
+    ));
+
+    html.push_str(
+        "<h2>Kompetenzabdeckung</h2>\n<table>\n\
+         <tr><th>Bereich</th><th>Kompetenz</th><th>Beobachtungen</th><th>Zuletzt</th></tr>\n",
+    );
+    for competency in &plan.coverage {
+        let last = match competency.last_observed_at {
+            Some(at) => at.format("%d.%m.%Y").to_string(),
+            None => "<span class=\"open\">noch nicht beobachtet</span>".to_string(),
+        };
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&competency.area),
+            escape_html(&competency.name),
+            competency.observations,
+            last
+        ));
+    }
+    html.push_str("</table>\n");
+
+    for competency in plan.coverage.iter().filter(|c| c.observations > 0) {
+        html.push_str(&format!(
+            "<h2>{}: {}</h2>\n<ul>\n",
+            escape_html(&competency.area),
+            escape_html(&competency.name)
+        ));
+        let linked = plan.links.iter().filter(|link| link.competency_id == competency.competency_id);
+        for link in linked {
+            if let Some(observation) = plan.observations.iter().find(|o| o.id == link.observation_id) {
+                html.push_str(&format!(
+                    "<li><span class=\"meta\">{}</span> {}</li>\n",
+                    observation.created_at.format("%d.%m.%Y"),
+                    escape_html(&observation.text)
+                ));
+            }
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str(&format!(
+        "<p class=\"meta\">Erstellt am {}</p>\n</body>\n</html>\n",
+        Utc::now().format("%d.%m.%Y %H:%M UTC")
+    ));
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_support_plan_lists_documented_and_missing_competencies() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+        let class = db.create_class("3b".to_string(), "2024/25".to_string()).await.unwrap();
+        let student = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+
+        let writing = db.get_competencies().await.unwrap().into_iter().find(|c| c.name == "Schreiben").unwrap();
+        let linked = db
+            .create_observation(student.id, 1, "Fachlich".to_string(), "Schreibt <Sätze>".to_string(), vec![])
+            .await
+            .unwrap();
+        db.create_observation(student.id, 1, "Sozial".to_string(), "Hilft anderen".to_string(), vec![])
+            .await
+            .unwrap();
+        db.set_observation_competencies(linked.id, &[writing.id]).await.unwrap();
+
+        let plan = build_support_plan(&db, student.id, 1).await.unwrap();
+        assert_eq!(plan.observations.len(), 1);
+        let html = render_support_plan_html(&plan);
+        assert!(html.contains("<h2>Deutsch: Schreiben</h2>"));
+        assert!(html.contains("Schreibt &lt;Sätze&gt;"));
+        assert!(!html.contains("Hilft anderen"));
+        assert!(html.contains("noch nicht beobachtet"));
+    }
+}
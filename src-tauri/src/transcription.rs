@@ -0,0 +1,147 @@
+use crate::database::Database;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const WHISPER_BINARY_SETTING: &str = "transcription.whisper_binary";
+pub const WHISPER_MODEL_SETTING: &str = "transcription.whisper_model";
+pub const WHISPER_LANGUAGE_SETTING: &str = "transcription.language";
+
+/// Converts recorded speech into text. Implementations must run entirely on
+/// this device: audio of lessons never leaves it.
+pub trait Transcriber: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// `audio_path` points to a temporary copy of the attachment payload.
+    fn transcribe(&self, audio_path: &Path) -> Result<String>;
+}
+
+/// Runs a locally installed whisper.cpp command line binary (`whisper-cli` or
+/// the older `main`) with a downloaded ggml model.
+pub struct WhisperCppTranscriber {
+    binary: PathBuf,
+    model: PathBuf,
+    language: String,
+}
+
+impl WhisperCppTranscriber {
+    pub fn new(binary: PathBuf, model: PathBuf, language: String) -> Self {
+        Self {
+            binary,
+            model,
+            language,
+        }
+    }
+}
+
+impl Transcriber for WhisperCppTranscriber {
+    fn name(&self) -> &str {
+        "whisper.cpp"
+    }
+
+    fn transcribe(&self, audio_path: &Path) -> Result<String> {
+        let output_prefix = audio_path.with_extension("transcript");
+
+        let output = Command::new(&self.binary)
+            .arg("-m")
+            .arg(&self.model)
+            .arg("-l")
+            .arg(&self.language)
+            .arg("-f")
+            .arg(audio_path)
+            .arg("--no-timestamps")
+            .arg("--output-txt")
+            .arg("--output-file")
+            .arg(&output_prefix)
+            .output()
+            .context("Failed to start whisper.cpp")?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "whisper.cpp failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let transcript = std::fs::read_to_string(output_prefix.with_extension("transcript.txt"))
+            .context("whisper.cpp did not produce a transcript")?;
+
+        Ok(transcript.trim().to_string())
+    }
+}
+
+pub fn is_audio(content_type: &str) -> bool {
+    content_type.starts_with("audio/")
+}
+
+/// Returns the transcriber configured in the settings, if any.
+pub async fn configured_transcriber(db: &Database) -> Result<Option<Box<dyn Transcriber>>> {
+    let (Some(binary), Some(model)) = (
+        db.get_setting(WHISPER_BINARY_SETTING).await?,
+        db.get_setting(WHISPER_MODEL_SETTING).await?,
+    ) else {
+        return Ok(None);
+    };
+    let language = db
+        .get_setting(WHISPER_LANGUAGE_SETTING)
+        .await?
+        .unwrap_or_else(|| "de".to_string());
+
+    Ok(Some(Box::new(WhisperCppTranscriber::new(
+        PathBuf::from(binary),
+        PathBuf::from(model),
+        language,
+    ))))
+}
+
+/// Writes the audio to a private temporary directory, runs the transcriber on
+/// it and removes every intermediate file again, also when transcription fails.
+pub async fn transcribe_audio(
+    transcriber: Box<dyn Transcriber>,
+    audio: Vec<u8>,
+    extension: &str,
+) -> Result<String> {
+    let work_dir = std::env::temp_dir().join(format!("transcribe-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&work_dir).context("Failed to create temporary directory")?;
+    let audio_path = work_dir.join(format!("audio.{}", extension));
+
+    let result = match std::fs::write(&audio_path, audio) {
+        Ok(()) => tokio::task::spawn_blocking(move || transcriber.transcribe(&audio_path))
+            .await
+            .context("Transcription was interrupted")
+            .and_then(|result| result),
+        Err(e) => Err(e).context("Failed to write temporary audio file"),
+    };
+
+    std::fs::remove_dir_all(&work_dir).ok();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_whisper_cpp_invocation() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stand-in for whisper.cpp that writes the file named by --output-file
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let binary = temp_dir.path().join("whisper-cli");
+        std::fs::write(
+            &binary,
+            "#!/bin/sh\nwhile [ \"$1\" != \"--output-file\" ]; do shift; done\necho ' Hallo Klasse ' > \"$2.txt\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let transcriber = WhisperCppTranscriber::new(binary, PathBuf::from("model.bin"), "de".to_string());
+        let text = transcribe_audio(Box::new(transcriber), b"RIFF".to_vec(), "wav").await.unwrap();
+        assert_eq!(text, "Hallo Klasse");
+
+        let missing = WhisperCppTranscriber::new(temp_dir.path().join("missing"), PathBuf::new(), "de".to_string());
+        assert!(transcribe_audio(Box::new(missing), vec![], "wav").await.is_err());
+    }
+}
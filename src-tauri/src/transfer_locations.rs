@@ -0,0 +1,106 @@
+use crate::database::Database;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+
+const RECENT_SETTING: &str = "recent_transfer_locations";
+
+/// Kept per direction; older entries are dropped.
+const MAX_PER_DIRECTION: usize = 10;
+
+/// A file recently exported to or imported from. Only the path is kept,
+/// never anything about the contents.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TransferLocation {
+    pub direction: String, // "export" or "import"
+    pub path: String,
+    pub used_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RecentTransferLocations {
+    pub files: Vec<TransferLocation>,
+    pub directories: Vec<String>, // Most recent first
+}
+
+async fn load(db: &Database) -> Result<Vec<TransferLocation>> {
+    Ok(db
+        .get_setting(RECENT_SETTING)
+        .await?
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+pub async fn record(db: &Database, direction: &str, path: &str) -> Result<()> {
+    let mut locations = load(db).await?;
+    locations.retain(|l| !(l.direction == direction && l.path == path));
+    locations.insert(
+        0,
+        TransferLocation {
+            direction: direction.to_string(),
+            path: path.to_string(),
+            used_at: Utc::now(),
+        },
+    );
+
+    let mut kept = 0;
+    locations.retain(|l| {
+        if l.direction != direction {
+            return true;
+        }
+        kept += 1;
+        kept <= MAX_PER_DIRECTION
+    });
+    db.set_setting(RECENT_SETTING, &serde_json::to_string(&locations)?).await
+}
+
+pub async fn get_recent(db: &Database) -> Result<RecentTransferLocations> {
+    let files = load(db).await?;
+    let mut directories: Vec<String> = Vec::new();
+    for location in &files {
+        let Some(directory) = Path::new(&location.path).parent().map(|d| d.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        if !directory.is_empty() && !directories.contains(&directory) {
+            directories.push(directory);
+        }
+    }
+    Ok(RecentTransferLocations { files, directories })
+}
+
+pub async fn clear(db: &Database) -> Result<()> {
+    db.set_setting(RECENT_SETTING, "[]").await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recent_locations_are_capped_and_clearable() {
+        let (db, _temp_dir) = crate::fixtures::test_db().await;
+
+        record(&db, "import", "/media/usb/notebook.sbchange").await.unwrap();
+        for i in 0..12 {
+            record(&db, "export", &format!("/media/usb/export_{}.sbchange", i)).await.unwrap();
+        }
+        record(&db, "export", "/home/lehrer/export_3.sbchange").await.unwrap();
+        record(&db, "export", "/media/usb/export_5.sbchange").await.unwrap();
+
+        let recent = get_recent(&db).await.unwrap();
+        let exports: Vec<&str> = recent
+            .files
+            .iter()
+            .filter(|l| l.direction == "export")
+            .map(|l| l.path.as_str())
+            .collect();
+        assert_eq!(exports.len(), MAX_PER_DIRECTION);
+        assert_eq!(exports[0], "/media/usb/export_5.sbchange");
+        assert_eq!(exports.iter().filter(|p| p.ends_with("export_5.sbchange")).count(), 1);
+        assert!(recent.files.iter().any(|l| l.direction == "import"));
+        assert_eq!(recent.directories, vec!["/media/usb", "/home/lehrer"]);
+
+        clear(&db).await.unwrap();
+        assert!(get_recent(&db).await.unwrap().files.is_empty());
+    }
+}
@@ -0,0 +1,631 @@
+use crate::database::Database;
+use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// URL of the time stamping authority. Unset or empty, exports carry no
+/// trusted timestamp.
+pub const TSA_URL_SETTING: &str = "trusted_timestamp.tsa_url";
+
+/// PEM certificate of the TSA. Tokens in imported changesets are only
+/// trusted when signed with its key.
+pub const TSA_CERTIFICATE_SETTING: &str = "trusted_timestamp.tsa_certificate";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A time stamp response is a few kilobytes; anything larger is not one.
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+// DER of the object identifiers involved
+const SHA256_OID: [u8; 9] = [0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]; // 2.16.840.1.101.3.4.2.1
+const SIGNED_DATA_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02]; // 1.2.840.113549.1.7.2
+const TST_INFO_OID: [u8; 11] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x10, 0x01, 0x04]; // 1.2.840.113549.1.9.16.1.4
+const CONTENT_TYPE_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x03]; // 1.2.840.113549.1.9.3
+const MESSAGE_DIGEST_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x04]; // 1.2.840.113549.1.9.4
+const RSA_ENCRYPTION_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01]; // 1.2.840.113549.1.1.1
+const SHA256_WITH_RSA_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b]; // 1.2.840.113549.1.1.11
+
+/// RFC 3161 token over the checksum of a changeset, stored next to the
+/// checksum in the file.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TrustedTimestamp {
+    pub tsa_url: String,
+    pub gen_time: DateTime<Utc>,
+    pub serial_number: String, // Hex, as the TSA numbers its tokens
+    pub token: String,         // Base64 of the DER TimeStampToken
+}
+
+/// The TSA configured in the settings, if the school opted in.
+pub async fn configured_tsa(db: &Database) -> Result<Option<String>> {
+    Ok(db
+        .get_setting(TSA_URL_SETTING)
+        .await?
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty()))
+}
+
+/// DER of the TSA certificate configured in the settings.
+pub async fn configured_certificate(db: &Database) -> Result<Option<Vec<u8>>> {
+    db.get_setting(TSA_CERTIFICATE_SETTING)
+        .await?
+        .filter(|pem| !pem.trim().is_empty())
+        .map(|pem| parse_pem(&pem))
+        .transpose()
+}
+
+/// `None` or an empty URL turns trusted timestamps off again. Without a
+/// certificate, tokens of imported changesets are not trusted.
+pub async fn set_tsa(db: &Database, tsa_url: Option<&str>, certificate_pem: Option<&str>) -> Result<()> {
+    let tsa_url = tsa_url.map(str::trim).unwrap_or_default();
+    if !tsa_url.is_empty() && !tsa_url.starts_with("http://") {
+        return Err(anyhow::anyhow!("Only http:// time stamping authorities are supported, not {}", tsa_url));
+    }
+    let certificate_pem = certificate_pem.map(str::trim).unwrap_or_default();
+    if !certificate_pem.is_empty() {
+        parse_certificate(&parse_pem(certificate_pem)?)?;
+    }
+    db.set_setting(TSA_URL_SETTING, tsa_url).await?;
+    db.set_setting(TSA_CERTIFICATE_SETTING, certificate_pem).await
+}
+
+/// Asks the TSA at `tsa_url` to timestamp the checksum of a changeset file
+/// and returns the file with the token added as `trusted_timestamp`. Only
+/// the SHA-256 of the `data` section is sent, none of its content.
+pub async fn attach_to_changeset(tsa_url: &str, changeset: &[u8]) -> Result<Vec<u8>> {
+    let mut parsed: serde_json::Value = serde_json::from_slice(changeset).context("Invalid changeset file format")?;
+    let data = parsed.get("data").context("Missing data section in changeset file")?;
+    let digest = Sha256::digest(data.to_string().as_bytes());
+
+    let timestamp = request(tsa_url, &digest).await?;
+    parsed["trusted_timestamp"] = serde_json::to_value(&timestamp)?;
+    Ok(parsed.to_string().into_bytes())
+}
+
+/// Checks the token of a changeset against the checksum calculated on
+/// import and its signature against the TSA's `certificate` (DER). The
+/// time has to lie within the validity of the certificate.
+pub fn verify(timestamp: &serde_json::Value, checksum: &str, certificate: &[u8]) -> Result<TrustedTimestamp> {
+    let timestamp: TrustedTimestamp =
+        serde_json::from_value(timestamp.clone()).map_err(|e| anyhow::anyhow!("Invalid trusted timestamp: {}", e))?;
+    let token = BASE64_STANDARD
+        .decode(&timestamp.token)
+        .context("Invalid trusted timestamp token encoding")?;
+    let info = parse_token(&token)?;
+    if hex(info.hashed_message) != checksum {
+        return Err(anyhow::anyhow!("Trusted timestamp does not belong to this changeset"));
+    }
+    if info.gen_time != timestamp.gen_time {
+        return Err(anyhow::anyhow!("Trusted timestamp time differs from its token"));
+    }
+    let certificate = parse_certificate(certificate)?;
+    check_signature(&token, &certificate)?;
+    if info.gen_time < certificate.not_before || info.gen_time > certificate.not_after {
+        return Err(anyhow::anyhow!("Trusted timestamp lies outside the validity of the TSA certificate"));
+    }
+    Ok(timestamp)
+}
+
+async fn request(tsa_url: &str, digest: &[u8]) -> Result<TrustedTimestamp> {
+    let nonce = rand::random::<u64>();
+    let body = timestamp_request(digest, nonce);
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, post(tsa_url, &body))
+        .await
+        .with_context(|| format!("Time stamping authority {} did not answer in time", tsa_url))??;
+
+    let token = token_of_response(&response)?;
+    let info = parse_token(token)?;
+    if info.hashed_message != digest {
+        return Err(anyhow::anyhow!("Time stamping authority signed a different checksum"));
+    }
+    if info.nonce.map(strip_leading_zeros) != Some(strip_leading_zeros(&nonce.to_be_bytes())) {
+        return Err(anyhow::anyhow!("Time stamping authority answered with a token for another request"));
+    }
+    Ok(TrustedTimestamp {
+        tsa_url: tsa_url.to_string(),
+        gen_time: info.gen_time,
+        serial_number: hex(info.serial_number),
+        token: BASE64_STANDARD.encode(token),
+    })
+}
+
+/// Sends `body` as `application/timestamp-query`. Plain HTTP only: the
+/// token is signed, so transport security adds nothing, and most public
+/// TSAs answer on http.
+async fn post(tsa_url: &str, body: &[u8]) -> Result<Vec<u8>> {
+    let rest = tsa_url
+        .strip_prefix("http://")
+        .with_context(|| format!("Only http:// time stamping authorities are supported, not {}", tsa_url))?;
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let path = if path.is_empty() { "/" } else { path };
+    let address = if authority.contains(':') { authority.to_string() } else { format!("{}:80", authority) };
+
+    let mut stream = tokio::net::TcpStream::connect(&address)
+        .await
+        .with_context(|| format!("Failed to connect to {}", tsa_url))?;
+    let head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/timestamp-query\r\n\
+         Accept: application/timestamp-reply\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        authority,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+
+    let mut response = Vec::new();
+    stream.take(MAX_RESPONSE_BYTES).read_to_end(&mut response).await?;
+    http_body(&response)
+}
+
+/// The body of an HTTP response with status 200, chunked or not.
+fn http_body(response: &[u8]) -> Result<Vec<u8>> {
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("Invalid answer from the time stamping authority")?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let body = &response[end + 4..];
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(anyhow::anyhow!("Time stamping authority answered {}", status));
+    }
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    if !chunked {
+        return Ok(body.to_vec());
+    }
+
+    let mut decoded = Vec::new();
+    let mut rest = body;
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n").context("Truncated chunked answer")?;
+        let size = std::str::from_utf8(&rest[..line_end])?.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).context("Invalid chunk size")?;
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        // The size comes from the server; a huge one must not overflow
+        let end = size
+            .checked_add(2)
+            .filter(|end| *end <= rest.len())
+            .context("Truncated chunked answer")?;
+        decoded.extend_from_slice(&rest[..size]);
+        rest = &rest[end..];
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn strip_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len().saturating_sub(1));
+    &bytes[start..]
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(content.len() as u8);
+    } else {
+        let length = content.len().to_be_bytes();
+        let length = strip_leading_zeros(&length);
+        out.push(0x80 | length.len() as u8);
+        out.extend_from_slice(length);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn der_unsigned(value: &[u8]) -> Vec<u8> {
+    let value = strip_leading_zeros(value);
+    if value[0] & 0x80 != 0 {
+        der(0x02, &[&[0u8][..], value].concat())
+    } else {
+        der(0x02, value)
+    }
+}
+
+fn message_imprint(digest: &[u8]) -> Vec<u8> {
+    let algorithm = der(0x30, &[der(0x06, &SHA256_OID), vec![0x05, 0x00]].concat());
+    der(0x30, &[algorithm, der(0x04, digest)].concat())
+}
+
+/// TimeStampReq of RFC 3161 with the TSA's certificate requested, so the
+/// token can be checked without looking the certificate up.
+fn timestamp_request(digest: &[u8], nonce: u64) -> Vec<u8> {
+    der(
+        0x30,
+        &[der_unsigned(&[1]), message_imprint(digest), der_unsigned(&nonce.to_be_bytes()), der(0x01, &[0xff])].concat(),
+    )
+}
+
+/// Splits the first DER element off `input`: tag, content and what follows.
+fn read(input: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let invalid = || anyhow::anyhow!("Invalid timestamp token");
+    let (&tag, rest) = input.split_first().ok_or_else(invalid)?;
+    let (&first, rest) = rest.split_first().ok_or_else(invalid)?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return Err(invalid());
+        }
+        let length = rest[..count].iter().fold(0usize, |length, b| (length << 8) | *b as usize);
+        (length, &rest[count..])
+    };
+    if rest.len() < length {
+        return Err(invalid());
+    }
+    Ok((tag, &rest[..length], &rest[length..]))
+}
+
+fn expect(input: &[u8], tag: u8) -> Result<(&[u8], &[u8])> {
+    let (found, content, rest) = read(input)?;
+    if found != tag {
+        return Err(anyhow::anyhow!("Invalid timestamp token: expected tag {:#04x}, found {:#04x}", tag, found));
+    }
+    Ok((content, rest))
+}
+
+/// The TimeStampToken of a granted TimeStampResp.
+fn token_of_response(response: &[u8]) -> Result<&[u8]> {
+    let (body, _) = expect(response, 0x30)?;
+    let (status_info, rest) = expect(body, 0x30)?;
+    let (status, _) = expect(status_info, 0x02)?;
+    match status {
+        [0] | [1] => {}
+        [2] => return Err(anyhow::anyhow!("Time stamping authority rejected the request")),
+        [3] => return Err(anyhow::anyhow!("Time stamping authority asks to try again later")),
+        _ => return Err(anyhow::anyhow!("Time stamping authority answered with status {:?}", status)),
+    }
+    let (_, _, after) = read(rest).context("Time stamping authority sent no token")?;
+    Ok(&rest[..rest.len() - after.len()])
+}
+
+/// The parts of a token (a CMS SignedData) the signature covers.
+struct SignedData<'a> {
+    tst_info: &'a [u8],     // DER of the TSTInfo, as signed
+    signer_infos: &'a [u8], // Content of the SET
+}
+
+fn signed_data(token: &[u8]) -> Result<SignedData<'_>> {
+    let (content_info, _) = expect(token, 0x30)?;
+    let (content_type, rest) = expect(content_info, 0x06)?;
+    if content_type != SIGNED_DATA_OID {
+        return Err(anyhow::anyhow!("Timestamp token is not signed data"));
+    }
+    let (signed_data, _) = expect(expect(rest, 0xa0)?.0, 0x30)?;
+    let (_, rest) = expect(signed_data, 0x02)?; // Version
+    let (_, rest) = expect(rest, 0x31)?; // Digest algorithms
+    let (encapsulated, mut rest) = expect(rest, 0x30)?;
+    let (content_type, content) = expect(encapsulated, 0x06)?;
+    if content_type != TST_INFO_OID {
+        return Err(anyhow::anyhow!("Timestamp token holds no timestamp"));
+    }
+    let (tst_info, _) = expect(expect(content, 0xa0)?.0, 0x04)?;
+
+    // Certificates and CRLs come before the signer infos
+    while matches!(rest.first(), Some(0xa0) | Some(0xa1)) {
+        rest = read(rest)?.2;
+    }
+    let (signer_infos, _) = expect(rest, 0x31)?;
+    Ok(SignedData { tst_info, signer_infos })
+}
+
+struct Certificate {
+    public_key: RsaPublicKey,
+    not_before: DateTime<Utc>,
+    not_after: DateTime<Utc>,
+}
+
+/// The DER of the first CERTIFICATE block of a PEM file.
+fn parse_pem(pem: &str) -> Result<Vec<u8>> {
+    let body = pem
+        .split("-----BEGIN CERTIFICATE-----")
+        .nth(1)
+        .and_then(|rest| rest.split("-----END CERTIFICATE-----").next())
+        .context("The TSA certificate has to be a PEM certificate")?;
+    let body: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64_STANDARD.decode(body).context("Invalid TSA certificate encoding")
+}
+
+/// The key and validity of an X.509 certificate with an RSA key.
+fn parse_certificate(der: &[u8]) -> Result<Certificate> {
+    let invalid = |e: anyhow::Error| e.context("Invalid TSA certificate");
+    let (certificate, _) = expect(der, 0x30).map_err(invalid)?;
+    let (tbs, _) = expect(certificate, 0x30).map_err(invalid)?;
+    let mut rest = tbs;
+    if rest.first() == Some(&0xa0) {
+        rest = read(rest).map_err(invalid)?.2; // Version
+    }
+    let (_, rest) = expect(rest, 0x02).map_err(invalid)?; // Serial number
+    let (_, rest) = expect(rest, 0x30).map_err(invalid)?; // Signature algorithm
+    let (_, rest) = expect(rest, 0x30).map_err(invalid)?; // Issuer
+    let (validity, rest) = expect(rest, 0x30).map_err(invalid)?;
+    let (_, rest) = expect(rest, 0x30).map_err(invalid)?; // Subject
+    let (_, _, after) = read(rest).map_err(invalid)?;
+    let public_key = RsaPublicKey::from_public_key_der(&rest[..rest.len() - after.len()])
+        .map_err(|_| anyhow::anyhow!("Only TSA certificates with an RSA key are supported"))?;
+    let (not_before, rest) = read_time(validity).map_err(invalid)?;
+    let (not_after, _) = read_time(rest).map_err(invalid)?;
+    Ok(Certificate { public_key, not_before, not_after })
+}
+
+/// A UTCTime or GeneralizedTime, and what follows it.
+fn read_time(input: &[u8]) -> Result<(DateTime<Utc>, &[u8])> {
+    let (tag, content, rest) = read(input)?;
+    let time = match tag {
+        0x17 => {
+            let value = std::str::from_utf8(content).context("Invalid certificate time")?;
+            // Two-digit years from 50 on are of the last century, see RFC 5280
+            let year = value.get(..2).and_then(|year| year.parse::<u32>().ok()).unwrap_or(0);
+            let century = if year < 50 { "20" } else { "19" };
+            parse_generalized_time(format!("{}{}", century, value).as_bytes())?
+        }
+        0x18 => parse_generalized_time(content)?,
+        _ => return Err(anyhow::anyhow!("Invalid certificate time")),
+    };
+    Ok((time, rest))
+}
+
+/// Checks that the first signer of the token signed the TSTInfo with the
+/// key of `certificate`: the signed attributes hold the digest of the
+/// TSTInfo, the signature covers the signed attributes.
+fn check_signature(token: &[u8], certificate: &Certificate) -> Result<()> {
+    let signed = signed_data(token)?;
+    let (signer_info, _) = expect(signed.signer_infos, 0x30).context("Timestamp token is not signed")?;
+    let (_, rest) = expect(signer_info, 0x02)?; // Version
+    let (_, _, rest) = read(rest)?; // Signer identifier
+    let (digest_algorithm, rest) = expect(rest, 0x30)?;
+    if expect(digest_algorithm, 0x06)?.0 != SHA256_OID {
+        return Err(anyhow::anyhow!("Timestamp token is not signed over SHA-256"));
+    }
+    let (attributes, after) = expect(rest, 0xa0).context("Timestamp token has no signed attributes")?;
+    let signed_attributes = &rest[..rest.len() - after.len()];
+    let (signature_algorithm, rest) = expect(after, 0x30)?;
+    let algorithm = expect(signature_algorithm, 0x06)?.0;
+    if algorithm != RSA_ENCRYPTION_OID && algorithm != SHA256_WITH_RSA_OID {
+        return Err(anyhow::anyhow!("Timestamp token is not signed with RSA"));
+    }
+    let (signature, _) = expect(rest, 0x04)?;
+
+    let mut content_type = None;
+    let mut message_digest = None;
+    let mut rest = attributes;
+    while !rest.is_empty() {
+        let (attribute, after) = expect(rest, 0x30)?;
+        let (oid, values) = expect(attribute, 0x06)?;
+        let (value, _) = expect(values, 0x31)?;
+        if oid == CONTENT_TYPE_OID {
+            content_type = Some(expect(value, 0x06)?.0);
+        } else if oid == MESSAGE_DIGEST_OID {
+            message_digest = Some(expect(value, 0x04)?.0);
+        }
+        rest = after;
+    }
+    let digest = Sha256::digest(signed.tst_info);
+    if content_type != Some(&TST_INFO_OID[..]) || message_digest != Some(&digest[..]) {
+        return Err(anyhow::anyhow!("Timestamp token was changed after it was signed"));
+    }
+
+    // Signed is the DER of the attributes as a SET, not with their implicit tag
+    let mut signed_bytes = signed_attributes.to_vec();
+    signed_bytes[0] = 0x31;
+    certificate
+        .public_key
+        .verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(&signed_bytes), signature)
+        .map_err(|_| anyhow::anyhow!("Timestamp token was not signed by the configured time stamping authority"))
+}
+
+struct TstInfo<'a> {
+    hashed_message: &'a [u8],
+    serial_number: &'a [u8],
+    gen_time: DateTime<Utc>,
+    nonce: Option<&'a [u8]>,
+}
+
+/// Reads the TSTInfo signed inside a token (a CMS SignedData).
+fn parse_token(token: &[u8]) -> Result<TstInfo<'_>> {
+    let (tst_info, _) = expect(signed_data(token)?.tst_info, 0x30)?;
+
+    let (_, rest) = expect(tst_info, 0x02)?; // Version
+    let (_, rest) = expect(rest, 0x06)?; // Policy
+    let (imprint, rest) = expect(rest, 0x30)?;
+    let (algorithm, hashed) = expect(imprint, 0x30)?;
+    if expect(algorithm, 0x06)?.0 != SHA256_OID {
+        return Err(anyhow::anyhow!("Timestamp token does not use SHA-256"));
+    }
+    let (hashed_message, _) = expect(hashed, 0x04)?;
+    let (serial_number, rest) = expect(rest, 0x02)?;
+    let (gen_time, mut rest) = expect(rest, 0x18)?;
+
+    // Accuracy, ordering, nonce, tsa and extensions may follow; only the nonce matters
+    let mut nonce = None;
+    while !rest.is_empty() {
+        let (tag, content, after) = read(rest)?;
+        if tag == 0x02 {
+            nonce = Some(content);
+        }
+        rest = after;
+    }
+
+    Ok(TstInfo {
+        hashed_message,
+        serial_number,
+        gen_time: parse_generalized_time(gen_time)?,
+        nonce,
+    })
+}
+
+/// `YYYYMMDDHHMMSS[.fff]Z`; fractions of a second are dropped.
+fn parse_generalized_time(value: &[u8]) -> Result<DateTime<Utc>> {
+    let value = std::str::from_utf8(value).context("Invalid timestamp time")?;
+    if !value.ends_with('Z') || value.len() < 15 || !value.is_char_boundary(14) {
+        return Err(anyhow::anyhow!("Invalid timestamp time {}", value));
+    }
+    let time = NaiveDateTime::parse_from_str(&value[..14], "%Y%m%d%H%M%S")
+        .with_context(|| format!("Invalid timestamp time {}", value))?;
+    Ok(time.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs8::EncodePublicKey;
+    use rsa::RsaPrivateKey;
+
+    fn test_key() -> RsaPrivateKey {
+        RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap()
+    }
+
+    /// A certificate of `key` as far as `parse_certificate` reads it.
+    fn certificate(key: &RsaPrivateKey, not_before: &str, not_after: &str) -> Vec<u8> {
+        let name = der(0x30, &[]);
+        let algorithm = der(0x30, &[der(0x06, &SHA256_WITH_RSA_OID), vec![0x05, 0x00]].concat());
+        let validity = der(0x30, &[der(0x17, not_before.as_bytes()), der(0x18, not_after.as_bytes())].concat());
+        let spki = key.to_public_key().to_public_key_der().unwrap().as_bytes().to_vec();
+        let tbs = der(
+            0x30,
+            &[der(0xa0, &der_unsigned(&[2])), der_unsigned(&[7]), algorithm.clone(), name.clone(), validity, name, spki]
+                .concat(),
+        );
+        der(0x30, &[tbs, algorithm, der(0x03, &[0])].concat())
+    }
+
+    /// What a TSA answers, signed with `key`.
+    fn granted_response(digest: &[u8], nonce: &[u8], gen_time: &str, key: &RsaPrivateKey) -> Vec<u8> {
+        let tst_info = der(
+            0x30,
+            &[
+                der_unsigned(&[1]),
+                der(0x06, &[0x2a, 0x03, 0x04]),
+                message_imprint(digest),
+                der_unsigned(&[0x12, 0x34]),
+                der(0x18, gen_time.as_bytes()),
+                der(0x02, nonce),
+            ]
+            .concat(),
+        );
+        let encapsulated = der(0x30, &[der(0x06, &TST_INFO_OID), der(0xa0, &der(0x04, &tst_info))].concat());
+        let attributes = [
+            der(0x30, &[der(0x06, &CONTENT_TYPE_OID), der(0x31, &der(0x06, &TST_INFO_OID))].concat()),
+            der(0x30, &[der(0x06, &MESSAGE_DIGEST_OID), der(0x31, &der(0x04, &Sha256::digest(&tst_info)))].concat()),
+        ]
+        .concat();
+        let signature = key
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(der(0x31, &attributes)))
+            .unwrap();
+        let signer_info = der(
+            0x30,
+            &[
+                der_unsigned(&[1]),
+                der(0x30, &[der(0x30, &[]), der_unsigned(&[7])].concat()),
+                der(0x30, &[der(0x06, &SHA256_OID), vec![0x05, 0x00]].concat()),
+                der(0xa0, &attributes),
+                der(0x30, &[der(0x06, &RSA_ENCRYPTION_OID), vec![0x05, 0x00]].concat()),
+                der(0x04, &signature),
+            ]
+            .concat(),
+        );
+        let signed_data = der(0x30, &[der_unsigned(&[3]), der(0x31, &[]), encapsulated, der(0x31, &signer_info)].concat());
+        let token = der(0x30, &[der(0x06, &SIGNED_DATA_OID), der(0xa0, &signed_data)].concat());
+        der(0x30, &[der(0x30, &der_unsigned(&[0])), token].concat())
+    }
+
+    #[test]
+    fn test_chunked_answers_with_bad_sizes_are_rejected() {
+        let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let answer = |body: &[u8]| [head.as_slice(), body].concat();
+        assert_eq!(http_body(&answer(b"3\r\nabc\r\n0\r\n\r\n")).unwrap(), b"abc");
+        assert!(http_body(&answer(b"ffffffffffffffff\r\nabc\r\n")).is_err());
+        assert!(http_body(&answer(b"5\r\nabc\r\n")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_changeset_timestamp_is_requested_and_verified() {
+        let key = test_key();
+        let tsa_certificate = certificate(&key, "240101000000Z", "20300101000000Z");
+        let server_key = key.clone();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tsa_url = format!("http://{}/tsa", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            // Read until the whole DER body has arrived
+            let body = loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                    if expect(&request[end + 4..], 0x30).is_ok() {
+                        break request[end + 4..].to_vec();
+                    }
+                }
+            };
+            assert!(request.starts_with(b"POST /tsa HTTP/1.1\r\n"));
+            let (fields, _) = expect(&body, 0x30).unwrap();
+            let (_, rest) = expect(fields, 0x02).unwrap();
+            let (imprint, rest) = expect(rest, 0x30).unwrap();
+            let (nonce, _) = expect(rest, 0x02).unwrap();
+            let (digest, _) = expect(expect(imprint, 0x30).unwrap().1, 0x04).unwrap();
+
+            let response = granted_response(digest, nonce, "20250301101500.25Z", &server_key);
+            // Chunked, as some TSAs answer
+            let mut reply = b"HTTP/1.1 200 OK\r\nContent-Type: application/timestamp-reply\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+            for chunk in response.chunks(100) {
+                reply.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                reply.extend_from_slice(chunk);
+                reply.extend_from_slice(b"\r\n");
+            }
+            reply.extend_from_slice(b"0\r\n\r\n");
+            stream.write_all(&reply).await.unwrap();
+        });
+
+        let data = serde_json::json!({ "format": "changeset_file_v1", "changes": { "observations": [] } });
+        let checksum = crate::database::changeset_checksum(&data);
+        let changeset = serde_json::json!({ "checksum": checksum, "data": data }).to_string();
+        let stamped = attach_to_changeset(&tsa_url, changeset.as_bytes()).await.unwrap();
+        server.await.unwrap();
+
+        let parsed: serde_json::Value = serde_json::from_slice(&stamped).unwrap();
+        assert_eq!(parsed["checksum"], checksum);
+        let timestamp = verify(&parsed["trusted_timestamp"], &checksum, &tsa_certificate).unwrap();
+        assert_eq!(timestamp.gen_time.to_rfc3339(), "2025-03-01T10:15:00+00:00");
+        assert_eq!(timestamp.serial_number, "1234");
+
+        // A token moved to another file does not verify
+        assert!(verify(&parsed["trusted_timestamp"], &"0".repeat(64), &tsa_certificate).is_err());
+        let mut edited = parsed["trusted_timestamp"].clone();
+        edited["gen_time"] = serde_json::json!("2025-01-01T00:00:00Z");
+        assert!(verify(&edited, &checksum, &tsa_certificate).is_err());
+
+        // Nor does one of another TSA, or one from before the certificate
+        let other_tsa = certificate(&test_key(), "240101000000Z", "20300101000000Z");
+        let error = verify(&parsed["trusted_timestamp"], &checksum, &other_tsa).unwrap_err();
+        assert!(error.to_string().contains("not signed by the configured"), "{}", error);
+        let later_certificate = certificate(&key, "250401000000Z", "20300101000000Z");
+        assert!(verify(&parsed["trusted_timestamp"], &checksum, &later_certificate).is_err());
+
+        // A TSTInfo altered after signing
+        let mut token = BASE64_STANDARD.decode(timestamp.token.as_bytes()).unwrap();
+        let serial = token.windows(4).position(|w| w == [0x02, 0x02, 0x12, 0x34]).unwrap();
+        token[serial + 2] = 0x13;
+        let mut altered = parsed["trusted_timestamp"].clone();
+        altered["token"] = serde_json::json!(BASE64_STANDARD.encode(&token));
+        assert!(verify(&altered, &checksum, &tsa_certificate).is_err());
+
+        assert!(attach_to_changeset("https://tsa.example", changeset.as_bytes()).await.is_err());
+        let rejected = der(0x30, &der(0x30, &der_unsigned(&[2])));
+        assert!(token_of_response(&rejected).is_err());
+    }
+}
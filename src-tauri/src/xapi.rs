@@ -0,0 +1,131 @@
+use crate::crypto::CryptoManager;
+use crate::database::Database;
+use crate::Observation;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+const XAPI_EXPORT_SETTING: &str = "xapi_export_enabled";
+
+/// Identifiers are our own URNs; no registry vocabulary is claimed.
+const HOME_PAGE: &str = "urn:schuelerbeobachtung";
+const VERB_OBSERVED: &str = "urn:schuelerbeobachtung:verb:observed";
+
+pub async fn is_enabled(db: &Database) -> Result<bool> {
+    Ok(db.get_setting(XAPI_EXPORT_SETTING).await?.as_deref() == Some("true"))
+}
+
+/// Turning the export on is a decision of the school, so only an admin may.
+pub async fn set_enabled(db: &Database, enabled: bool, admin_id: i64) -> Result<()> {
+    db.require_admin(admin_id).await?;
+    db.set_setting(XAPI_EXPORT_SETTING, if enabled { "true" } else { "false" })
+        .await
+}
+
+fn account(crypto: &CryptoManager, kind: &str, id: i64) -> Result<Value> {
+    Ok(json!({
+        "objectType": "Agent",
+        "account": { "homePage": HOME_PAGE, "name": crypto.pseudonymous_id(kind, id)? },
+    }))
+}
+
+/// One actor/verb/object statement per observation: the student as actor,
+/// the category as activity and the author as instructor. Names and the
+/// observation text are never included, ids are keyed pseudonyms.
+pub async fn build_statements(
+    db: &Database,
+    crypto: &CryptoManager,
+    class_id: Option<i64>,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<Value>> {
+    if !is_enabled(db).await? {
+        return Err(anyhow::anyhow!(
+            "The learning analytics export is disabled; an admin has to enable it first"
+        ));
+    }
+
+    let classes: HashMap<i64, i64> = db.get_students().await?.into_iter().map(|s| (s.id, s.class_id)).collect();
+    let observations = match since {
+        Some(since) => db.get_observations_since(since).await?,
+        None => db.search_observations(None, None, None).await?,
+    };
+
+    let mut statements = Vec::new();
+    for observation in observations.into_iter().filter(Observation::leaves_device) {
+        let Some(&student_class) = classes.get(&observation.student_id) else {
+            continue;
+        };
+        if class_id.is_some_and(|class_id| class_id != student_class) {
+            continue;
+        }
+
+        statements.push(json!({
+            "id": uuid::Uuid::new_v4().to_string(),
+            "actor": account(crypto, "student", observation.student_id)?,
+            "verb": {
+                "id": VERB_OBSERVED,
+                "display": { "de-DE": "beobachtet", "en-US": "observed" },
+            },
+            "object": {
+                "objectType": "Activity",
+                "id": format!("{}:category:{}", HOME_PAGE, urlencode(&observation.category)),
+                "definition": { "name": { "de-DE": observation.category } },
+            },
+            "context": {
+                "instructor": account(crypto, "user", observation.author_id)?,
+                "contextActivities": {
+                    "grouping": [{
+                        "objectType": "Activity",
+                        "id": format!("{}:class:{}", HOME_PAGE, crypto.pseudonymous_id("class", student_class)?),
+                    }],
+                },
+            },
+            "timestamp": observation.created_at.to_rfc3339(),
+        }));
+    }
+    Ok(statements)
+}
+
+/// Category names go into activity ids, which must stay valid IRIs.
+fn urlencode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_statements_require_setting_and_hide_names() {
+        let (db, crypto, _temp_dir) = crate::fixtures::test_db_with_crypto().await;
+        let class = db.create_class("5a".to_string(), "2023/24".to_string()).await.unwrap();
+        let other = db.create_class("5b".to_string(), "2023/24".to_string()).await.unwrap();
+        let max = db.create_student(class.id, "Max".to_string(), "Mustermann".to_string(), None).await.unwrap();
+        let anna = db.create_student(other.id, "Anna".to_string(), "Schmidt".to_string(), None).await.unwrap();
+        db.create_observation(max.id, 1, "Sozial".to_string(), "Max hilft Anna".to_string(), vec![]).await.unwrap();
+        db.create_observation(anna.id, 1, "Mathe & Physik".to_string(), "Gut".to_string(), vec![]).await.unwrap();
+
+        assert!(build_statements(&db, &crypto, None, None).await.is_err());
+        db.set_setting(XAPI_EXPORT_SETTING, "true").await.unwrap();
+
+        let statements = build_statements(&db, &crypto, Some(class.id), None).await.unwrap();
+        assert_eq!(statements.len(), 1);
+        let serialized = serde_json::to_string(&statements).unwrap();
+        assert!(!serialized.contains("Max"));
+        assert!(!serialized.contains("hilft"));
+        assert_eq!(
+            statements[0]["actor"]["account"]["name"],
+            crypto.pseudonymous_id("student", max.id).unwrap()
+        );
+
+        let all = build_statements(&db, &crypto, None, None).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert!(serde_json::to_string(&all).unwrap().contains("Mathe%20%26%20Physik"));
+    }
+}
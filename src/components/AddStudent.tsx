@@ -39,8 +39,11 @@ export const AddStudent: React.FC = () => {
   };
 
   const handleDeleteStudent = async (studentId: number, forceDelete: boolean = false) => {
+    // Permanent deletions have to be documented with a reason
+    const justification = forceDelete ? window.prompt('Begründung für das endgültige Löschen:') ?? '' : undefined;
+    if (forceDelete && !justification?.trim()) return;
     try {
-      await deleteStudent(studentId, forceDelete);
+      await deleteStudent(studentId, forceDelete, justification);
       await loadStudents();
       setDeleteConfirm(null);
       setShowForceDelete(false);
@@ -54,8 +57,10 @@ export const AddStudent: React.FC = () => {
   };
 
   const handleDeleteClass = async (classId: number, forceDelete: boolean = false) => {
+    const justification = forceDelete ? window.prompt('Begründung für das endgültige Löschen:') ?? '' : undefined;
+    if (forceDelete && !justification?.trim()) return;
     try {
-      await deleteClass(classId, forceDelete);
+      await deleteClass(classId, forceDelete, justification);
       await Promise.all([loadClasses(), loadStudents()]);
       setDeleteConfirm(null);
       setShowForceDelete(false);
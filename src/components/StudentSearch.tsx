@@ -105,9 +105,12 @@ export const StudentSearch: React.FC = () => {
 
   const handleDeleteConfirm = async (forceDelete: boolean = false) => {
     if (!deleteConfirm) return;
+    // Permanent deletions have to be documented with a reason
+    const justification = forceDelete ? window.prompt('Begründung für das endgültige Löschen:') ?? '' : undefined;
+    if (forceDelete && !justification?.trim()) return;
 
     try {
-      await deleteObservation(deleteConfirm.observationId, forceDelete);
+      await deleteObservation(deleteConfirm.observationId, forceDelete, justification);
       setDeleteConfirm(null);
       setError(null);
     } catch (error) {
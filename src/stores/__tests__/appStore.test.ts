@@ -438,6 +438,52 @@ describe('AppStore', () => {
       expect(result.current.error).toBe(null);
     });
 
+    it('should update observation in place', async () => {
+      const { result } = renderHook(() => useAppStore());
+
+      act(() => {
+        result.current.observations = [
+          {
+            id: 1,
+            student_id: 1,
+            author_id: 1,
+            category: 'Sozial',
+            text: 'Test',
+            tags: [],
+            created_at: '2024-01-01T00:00:00Z',
+            updated_at: '2024-01-01T00:00:00Z',
+            source_device_id: 'test-device-1',
+          },
+        ];
+      });
+
+      mockInvoke.mockResolvedValue({
+        id: 1,
+        student_id: 1,
+        author_id: 1,
+        category: 'Fachlich',
+        text: 'Test',
+        tags: '["korrigiert"]',
+        created_at: '2024-01-01T00:00:00Z',
+        updated_at: '2024-01-02T00:00:00Z',
+        source_device_id: 'test-device-1',
+      });
+
+      await act(async () => {
+        await result.current.updateObservation(1, { category: 'Fachlich', tags: ['korrigiert'] });
+      });
+
+      expect(mockInvoke).toHaveBeenCalledWith('update_observation', {
+        observationId: 1,
+        text: null,
+        category: 'Fachlich',
+        tags: ['korrigiert'],
+      });
+      expect(result.current.observations[0].category).toBe('Fachlich');
+      expect(result.current.observations[0].tags).toEqual(['korrigiert']);
+      expect(result.current.error).toBe(null);
+    });
+
     it('should get single observation successfully', async () => {
       const mockObservation = {
         id: 1,
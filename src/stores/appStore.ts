@@ -7,6 +7,23 @@ export interface Student {
   first_name: string;
   last_name: string;
   status: string;
+  last_observation_at?: string | null;
+  left_at?: string | null; // Last school day, YYYY-MM-DD
+  date_of_birth?: string | null; // YYYY-MM-DD
+  notes?: string | null;
+  external_id?: string | null; // Id in the school administration software
+}
+
+export interface StudentDetails {
+  date_of_birth?: string;
+  notes?: string;
+  external_id?: string;
+}
+
+export interface StudentListOptions {
+  sort?: 'name' | 'last_observation';
+  group_by_class?: boolean;
+  status?: string;
 }
 
 export interface Class {
@@ -15,6 +32,88 @@ export interface Class {
   school_year: string;
 }
 
+// `layout` is JSON; seats and groups refer to students by student_id / student_ids
+export interface ClassLayout {
+  class_id: number;
+  layout: string;
+  updated_at: string;
+}
+
+export interface ClassAgreement {
+  id: number;
+  class_id: number;
+  kind: 'photo' | 'audio' | 'video' | 'publication';
+  collected_on: string;
+  valid_until?: string;
+  reference?: string;
+  notes?: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface AnonymizationTerm {
+  id: number;
+  term: string;
+  student_id?: number;
+  note?: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface DictionaryEntry {
+  term: string;
+  student_id?: number;
+  term_id?: number; // Not set for student names
+  note?: string;
+}
+
+export interface Job {
+  id: number;
+  job_type: string;
+  params: string;
+  status: 'running' | 'completed' | 'failed' | 'cancelled' | 'interrupted';
+  progress: number;
+  operation_id?: string;
+  result_path?: string;
+  error?: string;
+  created_at: string;
+  finished_at?: string;
+}
+
+export interface QuarantinedImport {
+  id: number;
+  kind: 'changeset' | 'scanned_notes' | 'clipboard_observations';
+  file_path: string;
+  operation_id: string;
+  source_device_id?: string;
+  new_observations: number;
+  updated_observations: number;
+  unchanged_observations: number;
+  duplicate_observations: number;
+  invalid_observations: number;
+  problems: string;
+  created_at: string;
+}
+
+export interface ScannedNote {
+  file_path: string;
+  text: string;
+  date?: string;
+  proposed_student_id?: number;
+  candidate_student_ids: number[];
+}
+
+export interface ClipboardRow {
+  line: number;
+  name: string;
+  category: string;
+  category_given: string;
+  category_known: boolean;
+  text: string;
+  proposed_student_id?: number;
+  candidate_student_ids: number[];
+}
+
 export interface Observation {
   id: number;
   student_id: number;
@@ -25,6 +124,208 @@ export interface Observation {
   created_at: string;
   updated_at: string;
   source_device_id: string;
+  session_id?: number;
+  subject?: string;
+  language?: string; // ISO 639-1 code, or 'und' when undetermined
+  reported_by_user_id?: number; // Colleague the author heard it from
+  reported_by?: string; // Same for someone without an account
+  pinned?: boolean; // Goes into the substitute brief
+}
+
+export interface Attachment {
+  id: number;
+  observation_id: number;
+  filename: string;
+  content_type: string;
+  file_hash: string;
+  size_bytes: number;
+  storage: 'database' | 'external' | 'remote';
+  created_at: string;
+}
+
+export interface StudentGoal {
+  id: number;
+  student_id: number;
+  text: string;
+  status: 'active' | 'achieved' | 'dropped';
+  created_at: string;
+  updated_at: string;
+}
+
+export type LanguageDisplay = 'none' | 'label' | 'group';
+
+export interface Subject {
+  id: number;
+  name: string;
+  is_active: boolean;
+  sort_order: number;
+  created_at: string;
+}
+
+export interface QuickPhrase {
+  id: number;
+  category: string;
+  text: string;
+  usage_count: number;
+  last_used_at?: string;
+  created_at: string;
+}
+
+export interface ObservationMetric {
+  metric: string;
+  value: number;
+  unit?: string;
+}
+
+export interface MetricPoint extends ObservationMetric {
+  observation_id: number;
+  observed_at: string;
+}
+
+export interface ArchiveFile {
+  name: string;
+  sha256: string;
+  size_bytes: number;
+}
+
+export interface ArchiveManifest {
+  format_version: number;
+  app_version: string;
+  student_id: number;
+  student_reference: string;
+  student_name: string;
+  class_name: string;
+  created_at: string;
+  observations: number;
+  report_format: string;
+  files: ArchiveFile[];
+}
+
+export interface AccessRecord {
+  timestamp: string;
+  kind: 'read' | 'write' | 'export';
+  action: string;
+  object_type: string;
+  object_id: number;
+  object_reference?: string;
+  user_id: number;
+  user_name?: string;
+  description: string;
+  clock_note?: string;
+}
+
+export interface AccessHistory {
+  student_id: number;
+  from?: string;
+  to?: string; // Exclusive
+  generated_at: string;
+  records: AccessRecord[]; // Oldest first
+}
+
+export interface ArchiveBundle {
+  directory: string;
+  manifest: ArchiveManifest;
+  erasure_request?: { id: number; student_id: number; status: string; execute_at?: string };
+  post_export?: HookRun;
+}
+
+export interface Competency {
+  id: number;
+  area: string;
+  name: string;
+  description?: string;
+  is_active: boolean;
+  sort_order: number;
+  created_at: string;
+}
+
+export interface CompetencyCoverage {
+  competency_id: number;
+  area: string;
+  name: string;
+  observations: number;
+  last_observed_at?: string;
+}
+
+export interface RubricLevel {
+  level: number;
+  description: string;
+}
+
+export interface Rubric {
+  id: number;
+  name: string;
+  description?: string;
+  is_active: boolean;
+  created_at: string;
+  updated_at: string;
+  criteria: {
+    id: number;
+    name: string;
+    levels: RubricLevel[];
+  }[];
+}
+
+export interface RubricScore {
+  criterion_id: number;
+  level: number;
+}
+
+export interface RubricAggregate {
+  rubric_id: number;
+  rubric_name: string;
+  criterion_id: number;
+  criterion_name: string;
+  scores: number;
+  average_level: number;
+  lowest_level: number;
+  highest_level: number;
+}
+
+export interface ObservationSession {
+  id: number;
+  class_id: number;
+  title?: string;
+  subject?: string;
+  started_at: string;
+  ended_at?: string;
+  observation_count: number;
+}
+
+export interface CategoryShare {
+  observations: number | null; // null when too few students were observed
+  percent: number | null;
+}
+
+export interface ClassCategoryStatistics {
+  class_id: number;
+  class_name: string;
+  school_year: string;
+  students: number;
+  observations: number | null; // null when it would tell the only hidden category
+  by_category: Record<string, CategoryShare>;
+}
+
+export interface ClassStatisticsComparison {
+  categories: string[];
+  classes: ClassCategoryStatistics[];
+  min_students_per_cell: number;
+}
+
+export interface RetentionDeadline {
+  category: 'observations' | 'attachments' | 'audit';
+  action: 'delete' | 'anonymize';
+  records: number;
+  due_at?: string; // Not set while the student is active
+  basis: string;
+}
+
+export interface RetentionStatus {
+  student_id: number;
+  student_status: string;
+  countdown_started_at?: string;
+  erasure_request?: { id: number; execute_at?: string };
+  deadlines: RetentionDeadline[];
 }
 
 export interface SyncStatus {
@@ -38,6 +339,49 @@ export interface DeviceConfig {
   device_name?: string;
 }
 
+// Limits a GDPR export, e.g. to one incident; dates as YYYY-MM-DD, both days included
+export interface ExportScope {
+  from?: string;
+  to?: string;
+  categories?: string[];
+}
+
+// A subsystem that can be switched off per profile, see `set_feature_flag`
+export interface FeatureFlag {
+  name: string;
+  description: string;
+  enabled: boolean;
+  default_enabled: boolean;
+  commands: string[];
+}
+
+// A school's own program run after an export, see `set_export_hook`
+export interface HookRun {
+  program: string;
+  exit_code?: number;
+  success: boolean;
+  error?: string;
+}
+
+export interface ExportResult {
+  message: string;
+  file_path: string;
+  removable_media: boolean;
+  post_export?: HookRun;
+}
+
+export interface ExportProtection {
+  passphrase?: string;
+  recipient_key?: string;
+}
+
+// In maintenance mode the database could not be opened; only diagnostics work
+export interface StartupStatus {
+  maintenance_mode: boolean;
+  error: string | null;
+  database_path: string;
+}
+
 export interface ActivePin {
   pin: string;
   expires_at: string;
@@ -57,8 +401,10 @@ interface AppState {
   deviceConfig: DeviceConfig | null;
   currentPin: ActivePin | null;
   databasePath: string | null;
+  startupStatus: StartupStatus | null;
   
   // Actions
+  getStartupStatus: () => Promise<StartupStatus>;
   initializeApp: () => Promise<void>;
   loadStudents: () => Promise<void>;
   loadClasses: () => Promise<void>;
@@ -73,17 +419,22 @@ interface AppState {
   searchObservations: (query?: string, student_id?: number, category?: string) => Promise<void>;
   getSyncStatus: () => Promise<void>;
   // eslint-disable-next-line no-unused-vars
-  exportStudentData: (student_id: number, format: string) => Promise<string>;
+  exportStudentData: (student_id: number, format: string, scope?: ExportScope) => Promise<string>;
   // eslint-disable-next-line no-unused-vars
   createClass: (name: string, school_year: string) => Promise<void>;
   // eslint-disable-next-line no-unused-vars
-  createStudent: (class_id: number, first_name: string, last_name: string, status?: string) => Promise<void>;
+  createStudent: (class_id: number, first_name: string, last_name: string, status?: string, details?: StudentDetails) => Promise<void>;
   // eslint-disable-next-line no-unused-vars
-  deleteStudent: (student_id: number, force_delete?: boolean) => Promise<void>;
+  // An empty string clears date of birth, notes or external id
+  updateStudent: (student_id: number, changes: { first_name?: string; last_name?: string; status?: string; class_id?: number } & StudentDetails) => Promise<void>;
   // eslint-disable-next-line no-unused-vars
-  deleteClass: (class_id: number, force_delete?: boolean) => Promise<void>;
+  deleteStudent: (student_id: number, force_delete?: boolean, justification?: string) => Promise<void>;
   // eslint-disable-next-line no-unused-vars
-  deleteObservation: (observation_id: number, force_delete?: boolean) => Promise<void>;
+  deleteClass: (class_id: number, force_delete?: boolean, justification?: string) => Promise<void>;
+  // eslint-disable-next-line no-unused-vars
+  updateObservation: (observation_id: number, changes: { text?: string; category?: string; tags?: string[] }) => Promise<void>;
+  // eslint-disable-next-line no-unused-vars
+  deleteObservation: (observation_id: number, force_delete?: boolean, justification?: string) => Promise<void>;
   // eslint-disable-next-line no-unused-vars
   getObservation: (observation_id: number) => Promise<Observation | null>;
   // eslint-disable-next-line no-unused-vars
@@ -96,9 +447,9 @@ interface AppState {
   // eslint-disable-next-line no-unused-vars
   importChangeset: (changesetData: string) => Promise<void>;
   // eslint-disable-next-line no-unused-vars
-  exportChangesetToFile: (filePath: string, daysBack?: number) => Promise<string>;
+  exportChangesetToFile: (filePath: string, daysBack?: number, protection?: ExportProtection) => Promise<string>;
   // eslint-disable-next-line no-unused-vars
-  importChangesetFromFile: (filePath: string) => Promise<string>;
+  importChangesetFromFile: (filePath: string, passphrase?: string) => Promise<string>;
   // eslint-disable-next-line no-unused-vars
   exportAllData: (daysBack?: number) => Promise<string>;
   // eslint-disable-next-line no-unused-vars
@@ -136,8 +487,18 @@ export const useAppStore = create<AppState>((set, get) => ({
   deviceConfig: null,
   currentPin: null,
   databasePath: null,
+  startupStatus: null,
 
   // Actions
+  getStartupStatus: async () => {
+    const startupStatus = await invoke('get_startup_status') as StartupStatus;
+    set({ startupStatus });
+    if (startupStatus.maintenance_mode) {
+      set({ error: `Database could not be opened: ${startupStatus.error}` });
+    }
+    return startupStatus;
+  },
+
   initializeApp: async () => {
     const { loadStudents, loadClasses, getSyncStatus, getDeviceConfig, getDatabasePath } = get();
     set({ loading: true, error: null });
@@ -248,13 +609,14 @@ export const useAppStore = create<AppState>((set, get) => ({
     }
   },
 
-  exportStudentData: async (student_id, format) => {
+  exportStudentData: async (student_id, format, scope) => {
     set({ loading: true, error: null });
     
     try {
       const exportData = await invoke('export_student_data', {
         studentId: student_id,
         format,
+        ...scope,
       }) as string;
       
       set({ loading: false, error: null });
@@ -282,10 +644,18 @@ export const useAppStore = create<AppState>((set, get) => ({
   },
 
   // Create a student
-  createStudent: async (class_id: number, first_name: string, last_name: string, status: string = 'active') => {
+  createStudent: async (class_id: number, first_name: string, last_name: string, status: string = 'active', details?: StudentDetails) => {
     set({ loading: true, error: null });
     try {
-      const newStudent = await invoke('create_student', { classId: class_id, firstName: first_name, lastName: last_name, status });
+      const newStudent = await invoke('create_student', {
+        classId: class_id,
+        firstName: first_name,
+        lastName: last_name,
+        status,
+        dateOfBirth: details?.date_of_birth ?? null,
+        notes: details?.notes ?? null,
+        externalId: details?.external_id ?? null,
+      });
       const { students } = get();
       set({ students: [newStudent as any, ...students], loading: false });
     } catch (err) {
@@ -294,11 +664,37 @@ export const useAppStore = create<AppState>((set, get) => ({
     }
   },
 
+  // Correct the record of a student
+  updateStudent: async (student_id, changes) => {
+    set({ loading: true, error: null });
+    try {
+      const student = await invoke('update_student', {
+        studentId: student_id,
+        firstName: changes.first_name ?? null,
+        lastName: changes.last_name ?? null,
+        status: changes.status ?? null,
+        classId: changes.class_id ?? null,
+        dateOfBirth: changes.date_of_birth ?? null,
+        notes: changes.notes ?? null,
+        externalId: changes.external_id ?? null,
+      }) as Student;
+      const { students } = get();
+      set({
+        students: students.map(s => s.id === student_id ? student : s),
+        loading: false,
+        error: null
+      });
+    } catch (err) {
+      set({ error: `Failed to update student: ${err}`, loading: false });
+      throw err;
+    }
+  },
+
   // Delete a student
-  deleteStudent: async (student_id: number, force_delete: boolean = false) => {
+  deleteStudent: async (student_id: number, force_delete: boolean = false, justification?: string) => {
     set({ loading: true, error: null });
     try {
-      await invoke('delete_student', { studentId: student_id, forceDelete: force_delete });
+      await invoke('delete_student', { studentId: student_id, forceDelete: force_delete, justification });
       // Refresh students list after deletion
       await get().loadStudents();
       set({ loading: false });
@@ -309,10 +705,10 @@ export const useAppStore = create<AppState>((set, get) => ({
   },
 
   // Delete a class
-  deleteClass: async (class_id: number, force_delete: boolean = false) => {
+  deleteClass: async (class_id: number, force_delete: boolean = false, justification?: string) => {
     set({ loading: true, error: null });
     try {
-      await invoke('delete_class', { classId: class_id, forceDelete: force_delete });
+      await invoke('delete_class', { classId: class_id, forceDelete: force_delete, justification });
       // Refresh classes and students lists after deletion
       await Promise.all([
         get().loadClasses(),
@@ -325,11 +721,40 @@ export const useAppStore = create<AppState>((set, get) => ({
     }
   },
 
+  // Change text, category or tags of an observation
+  updateObservation: async (observation_id, changes) => {
+    set({ loading: true, error: null });
+    try {
+      const rawObservation = await invoke('update_observation', {
+        observationId: observation_id,
+        text: changes.text ?? null,
+        category: changes.category ?? null,
+        tags: changes.tags ?? null,
+      }) as any;
+
+      const observation: Observation = {
+        ...rawObservation,
+        tags: typeof rawObservation.tags === 'string' ?
+          (rawObservation.tags.trim() ? JSON.parse(rawObservation.tags) : []) :
+          (rawObservation.tags || [])
+      };
+      const { observations } = get();
+      set({
+        observations: observations.map(obs => obs.id === observation_id ? observation : obs),
+        loading: false,
+        error: null
+      });
+    } catch (err) {
+      set({ error: `Failed to update observation: ${err}`, loading: false });
+      throw err;
+    }
+  },
+
   // Delete an observation
-  deleteObservation: async (observation_id: number, force_delete: boolean = false) => {
+  deleteObservation: async (observation_id: number, force_delete: boolean = false, justification?: string) => {
     set({ loading: true, error: null });
     try {
-      await invoke('delete_observation', { observationId: observation_id, forceDelete: force_delete });
+      await invoke('delete_observation', { observationId: observation_id, forceDelete: force_delete, justification });
       
       // Remove the observation from local state immediately for better UX
       const { observations } = get();
@@ -409,15 +834,19 @@ export const useAppStore = create<AppState>((set, get) => ({
     }
   },
 
-  exportChangesetToFile: async (filePath: string, daysBack?: number): Promise<string> => {
+  exportChangesetToFile: async (filePath: string, daysBack?: number, protection?: ExportProtection): Promise<string> => {
     set({ loading: true, error: null });
     try {
       const result = await invoke('export_changeset_to_file', { 
         filePath, 
-        daysBack: daysBack || 30 
-      }) as string;
+        daysBack: daysBack || 30,
+        protection
+      }) as ExportResult;
       set({ loading: false, error: null });
-      return result;
+      // A stick pulled without ejecting can still lose the file
+      return result.removable_media
+        ? `${result.message}\nBitte den Datenträger vor dem Abziehen sicher auswerfen.`
+        : result.message;
     } catch (err) {
       set({ 
         error: `Failed to export changeset to file: ${err}`,
@@ -427,10 +856,10 @@ export const useAppStore = create<AppState>((set, get) => ({
     }
   },
 
-  importChangesetFromFile: async (filePath: string): Promise<string> => {
+  importChangesetFromFile: async (filePath: string, passphrase?: string): Promise<string> => {
     set({ loading: true, error: null });
     try {
-      const result = await invoke('import_changeset_from_file', { filePath }) as string;
+      const result = await invoke('import_changeset_from_file', { filePath, passphrase }) as string;
       // Refresh all data after import
       await Promise.all([
         get().searchObservations(),